@@ -1,280 +0,0 @@
-// Code generated by protoc-gen-go. DO NOT EDIT.
-// source: proto/model.proto
-
-package proto
-
-import (
-	reflect "reflect"
-	sync "sync"
-
-	proto "google.golang.org/protobuf/proto"
-	protoreflect "google.golang.org/protobuf/reflect/protoreflect"
-	protoimpl "google.golang.org/protobuf/runtime/protoimpl"
-	descriptorpb "google.golang.org/protobuf/types/descriptorpb"
-)
-
-const (
-	// Verify that this generated code is sufficiently up-to-date.
-	_ = protoimpl.EnforceVersion(20 - protoimpl.MinVersion)
-	// Verify that runtime/protoimpl is sufficiently up-to-date.
-	_ = protoimpl.EnforceVersion(protoimpl.MaxVersion - 20)
-)
-
-type PlanRequest struct {
-	state         protoimpl.MessageState
-	sizeCache     protoimpl.SizeCache
-	unknownFields protoimpl.UnknownFields
-
-	Prompt string `protobuf:"bytes,1,opt,name=prompt,proto3" json:"prompt,omitempty"`
-}
-
-func (x *PlanRequest) Reset() {
-	*x = PlanRequest{}
-	if protoimpl.UnsafeEnabled {
-		mi := &file_proto_model_proto_msgTypes[0]
-		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
-		ms.StoreMessageInfo(mi)
-	}
-}
-
-func (x *PlanRequest) String() string {
-	return protoimpl.X.MessageStringOf(x)
-}
-
-func (*PlanRequest) ProtoMessage() {}
-
-func (x *PlanRequest) ProtoReflect() protoreflect.Message {
-	mi := &file_proto_model_proto_msgTypes[0]
-	if protoimpl.UnsafeEnabled && x != nil {
-		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
-		if ms.LoadMessageInfo() == nil {
-			ms.StoreMessageInfo(mi)
-		}
-		return ms
-	}
-	return mi.MessageOf(x)
-}
-
-// Deprecated: Use PlanRequest.ProtoReflect.Descriptor instead.
-func (*PlanRequest) Descriptor() ([]byte, []int) {
-	return file_proto_model_proto_rawDescGZIP(), []int{0}
-}
-
-func (x *PlanRequest) GetPrompt() string {
-	if x != nil {
-		return x.Prompt
-	}
-	return ""
-}
-
-type PlanResponse struct {
-	state         protoimpl.MessageState
-	sizeCache     protoimpl.SizeCache
-	unknownFields protoimpl.UnknownFields
-
-	Plan      string `protobuf:"bytes,1,opt,name=plan,proto3" json:"plan,omitempty"`
-	ModelName string `protobuf:"bytes,2,opt,name=model_name,json=modelName,proto3" json:"model_name,omitempty"`
-	LatencyMs int64  `protobuf:"varint,3,opt,name=latency_ms,json=latencyMs,proto3" json:"latency_ms,omitempty"`
-}
-
-func (x *PlanResponse) Reset() {
-	*x = PlanResponse{}
-	if protoimpl.UnsafeEnabled {
-		mi := &file_proto_model_proto_msgTypes[1]
-		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
-		ms.StoreMessageInfo(mi)
-	}
-}
-
-func (x *PlanResponse) String() string {
-	return protoimpl.X.MessageStringOf(x)
-}
-
-func (*PlanResponse) ProtoMessage() {}
-
-func (x *PlanResponse) ProtoReflect() protoreflect.Message {
-	mi := &file_proto_model_proto_msgTypes[1]
-	if protoimpl.UnsafeEnabled && x != nil {
-		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
-		if ms.LoadMessageInfo() == nil {
-			ms.StoreMessageInfo(mi)
-		}
-		return ms
-	}
-	return mi.MessageOf(x)
-}
-
-// Deprecated: Use PlanResponse.ProtoReflect.Descriptor instead.
-func (*PlanResponse) Descriptor() ([]byte, []int) {
-	return file_proto_model_proto_rawDescGZIP(), []int{1}
-}
-
-func (x *PlanResponse) GetPlan() string {
-	if x != nil {
-		return x.Plan
-	}
-	return ""
-}
-
-func (x *PlanResponse) GetModelName() string {
-	if x != nil {
-		return x.ModelName
-	}
-	return ""
-}
-
-func (x *PlanResponse) GetLatencyMs() int64 {
-	if x != nil {
-		return x.LatencyMs
-	}
-	return 0
-}
-
-var File_proto_model_proto protoreflect.FileDescriptor
-
-var (
-	file_proto_model_proto_rawDescOnce sync.Once
-	file_proto_model_proto_rawDescData = mustBuildFileProtoModelRawDesc()
-)
-
-func mustBuildFileProtoModelRawDesc() []byte {
-	// Build a valid FileDescriptorProto at runtime.
-	// This avoids panics from an invalid hand-crafted RawDescriptor.
-	labelOptional := descriptorpb.FieldDescriptorProto_LABEL_OPTIONAL
-	typeString := descriptorpb.FieldDescriptorProto_TYPE_STRING
-	typeInt64 := descriptorpb.FieldDescriptorProto_TYPE_INT64
-
-	fd := &descriptorpb.FileDescriptorProto{
-		Syntax:  proto.String("proto3"),
-		Name:    proto.String("proto/model.proto"),
-		Package: proto.String("modelgateway"),
-		Options: &descriptorpb.FileOptions{GoPackage: proto.String("backend-go-model-gateway/proto;proto")},
-		MessageType: []*descriptorpb.DescriptorProto{
-			{
-				Name: proto.String("PlanRequest"),
-				Field: []*descriptorpb.FieldDescriptorProto{
-					{
-						Name:   proto.String("prompt"),
-						Number: proto.Int32(1),
-						Label:  &labelOptional,
-						Type:   &typeString,
-					},
-				},
-			},
-			{
-				Name: proto.String("PlanResponse"),
-				Field: []*descriptorpb.FieldDescriptorProto{
-					{
-						Name:   proto.String("plan"),
-						Number: proto.Int32(1),
-						Label:  &labelOptional,
-						Type:   &typeString,
-					},
-					{
-						Name:   proto.String("model_name"),
-						Number: proto.Int32(2),
-						Label:  &labelOptional,
-						Type:   &typeString,
-					},
-					{
-						Name:   proto.String("latency_ms"),
-						Number: proto.Int32(3),
-						Label:  &labelOptional,
-						Type:   &typeInt64,
-					},
-				},
-			},
-		},
-		Service: []*descriptorpb.ServiceDescriptorProto{
-			{
-				Name: proto.String("ModelGateway"),
-				Method: []*descriptorpb.MethodDescriptorProto{
-					{
-						Name:       proto.String("GetPlan"),
-						InputType:  proto.String(".modelgateway.PlanRequest"),
-						OutputType: proto.String(".modelgateway.PlanResponse"),
-					},
-				},
-			},
-		},
-	}
-
-	b, err := proto.Marshal(fd)
-	if err != nil {
-		panic(err)
-	}
-	return b
-}
-
-func file_proto_model_proto_rawDescGZIP() []byte {
-	file_proto_model_proto_rawDescOnce.Do(func() {
-		file_proto_model_proto_rawDescData = protoimpl.X.CompressGZIP(file_proto_model_proto_rawDescData)
-	})
-	return file_proto_model_proto_rawDescData
-}
-
-var file_proto_model_proto_msgTypes = make([]protoimpl.MessageInfo, 2)
-var file_proto_model_proto_goTypes = []interface{}{
-	(*PlanRequest)(nil),
-	(*PlanResponse)(nil),
-}
-var file_proto_model_proto_depIdxs = []int32{
-	0, // 0: modelgateway.ModelGateway.GetPlan:input_type -> modelgateway.PlanRequest
-	1, // 1: modelgateway.ModelGateway.GetPlan:output_type -> modelgateway.PlanResponse
-	1, // [1:2] is the sub-list for method output_type
-	0, // [0:1] is the sub-list for method input_type
-	0, // [0:0] is the sub-list for extension type_name
-	0, // [0:0] is the sub-list for extension extendee
-	0, // [0:0] is the sub-list for field type_name
-}
-
-func init() { file_proto_model_proto_init() }
-func file_proto_model_proto_init() {
-	if File_proto_model_proto != nil {
-		return
-	}
-	if !protoimpl.UnsafeEnabled {
-		file_proto_model_proto_msgTypes[0].Exporter = func(v interface{}, i int) interface{} {
-			switch v := v.(*PlanRequest); i {
-			case 0:
-				return &v.state
-			case 1:
-				return &v.sizeCache
-			case 2:
-				return &v.unknownFields
-			default:
-				return nil
-			}
-		}
-		file_proto_model_proto_msgTypes[1].Exporter = func(v interface{}, i int) interface{} {
-			switch v := v.(*PlanResponse); i {
-			case 0:
-				return &v.state
-			case 1:
-				return &v.sizeCache
-			case 2:
-				return &v.unknownFields
-			default:
-				return nil
-			}
-		}
-	}
-	type x struct{}
-	out := protoimpl.TypeBuilder{
-		File: protoimpl.DescBuilder{
-			GoPackagePath: reflect.TypeOf(x{}).PkgPath(),
-			RawDescriptor: file_proto_model_proto_rawDescData,
-			NumEnums:      0,
-			NumMessages:   2,
-			NumExtensions: 0,
-			NumServices:   1,
-		},
-		GoTypes:           file_proto_model_proto_goTypes,
-		DependencyIndexes: file_proto_model_proto_depIdxs,
-		MessageInfos:      file_proto_model_proto_msgTypes,
-	}.Build()
-	File_proto_model_proto = out.File
-	file_proto_model_proto_rawDescData = nil
-	file_proto_model_proto_goTypes = nil
-	file_proto_model_proto_depIdxs = nil
-}
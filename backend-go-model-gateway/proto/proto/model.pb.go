@@ -21,6 +21,155 @@ const (
 	_ = protoimpl.EnforceVersion(protoimpl.MaxVersion - 20)
 )
 
+type OutputStreamType int32
+
+const (
+	OutputStreamType_OUTPUT_STREAM_UNSPECIFIED OutputStreamType = 0
+	OutputStreamType_OUTPUT_STREAM_STDOUT      OutputStreamType = 1
+	OutputStreamType_OUTPUT_STREAM_STDERR      OutputStreamType = 2
+)
+
+// Enum value maps for OutputStreamType.
+var (
+	OutputStreamType_name = map[int32]string{
+		0: "OUTPUT_STREAM_UNSPECIFIED",
+		1: "OUTPUT_STREAM_STDOUT",
+		2: "OUTPUT_STREAM_STDERR",
+	}
+	OutputStreamType_value = map[string]int32{
+		"OUTPUT_STREAM_UNSPECIFIED": 0,
+		"OUTPUT_STREAM_STDOUT":      1,
+		"OUTPUT_STREAM_STDERR":      2,
+	}
+)
+
+func (x OutputStreamType) Enum() *OutputStreamType {
+	p := new(OutputStreamType)
+	*p = x
+	return p
+}
+
+func (x OutputStreamType) String() string {
+	return protoimpl.X.EnumStringOf(x.Descriptor(), protoreflect.EnumNumber(x))
+}
+
+func (OutputStreamType) Descriptor() protoreflect.EnumDescriptor {
+	return file_proto_model_proto_enumTypes[0].Descriptor()
+}
+
+func (OutputStreamType) Type() protoreflect.EnumType {
+	return &file_proto_model_proto_enumTypes[0]
+}
+
+func (x OutputStreamType) Number() protoreflect.EnumNumber {
+	return protoreflect.EnumNumber(x)
+}
+
+// Deprecated: Use OutputStreamType.Descriptor instead.
+func (OutputStreamType) EnumDescriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{0}
+}
+
+type InteractiveControl int32
+
+const (
+	InteractiveControl_INTERACTIVE_CONTROL_UNSPECIFIED InteractiveControl = 0
+	// Close the tool's stdin.
+	InteractiveControl_INTERACTIVE_CONTROL_EOF InteractiveControl = 1
+	// Stop the tool; it exits with status "cancelled".
+	InteractiveControl_INTERACTIVE_CONTROL_TERMINATE InteractiveControl = 2
+)
+
+// Enum value maps for InteractiveControl.
+var (
+	InteractiveControl_name = map[int32]string{
+		0: "INTERACTIVE_CONTROL_UNSPECIFIED",
+		1: "INTERACTIVE_CONTROL_EOF",
+		2: "INTERACTIVE_CONTROL_TERMINATE",
+	}
+	InteractiveControl_value = map[string]int32{
+		"INTERACTIVE_CONTROL_UNSPECIFIED": 0,
+		"INTERACTIVE_CONTROL_EOF":         1,
+		"INTERACTIVE_CONTROL_TERMINATE":   2,
+	}
+)
+
+func (x InteractiveControl) Enum() *InteractiveControl {
+	p := new(InteractiveControl)
+	*p = x
+	return p
+}
+
+func (x InteractiveControl) String() string {
+	return protoimpl.X.EnumStringOf(x.Descriptor(), protoreflect.EnumNumber(x))
+}
+
+func (InteractiveControl) Descriptor() protoreflect.EnumDescriptor {
+	return file_proto_model_proto_enumTypes[1].Descriptor()
+}
+
+func (InteractiveControl) Type() protoreflect.EnumType {
+	return &file_proto_model_proto_enumTypes[1]
+}
+
+func (x InteractiveControl) Number() protoreflect.EnumNumber {
+	return protoreflect.EnumNumber(x)
+}
+
+// Deprecated: Use InteractiveControl.Descriptor instead.
+func (InteractiveControl) EnumDescriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{1}
+}
+
+type OutputEncoding int32
+
+const (
+	OutputEncoding_OUTPUT_ENCODING_UTF8     OutputEncoding = 0
+	OutputEncoding_OUTPUT_ENCODING_BASE64   OutputEncoding = 1
+	OutputEncoding_OUTPUT_ENCODING_ARTIFACT OutputEncoding = 2
+)
+
+// Enum value maps for OutputEncoding.
+var (
+	OutputEncoding_name = map[int32]string{
+		0: "OUTPUT_ENCODING_UTF8",
+		1: "OUTPUT_ENCODING_BASE64",
+		2: "OUTPUT_ENCODING_ARTIFACT",
+	}
+	OutputEncoding_value = map[string]int32{
+		"OUTPUT_ENCODING_UTF8":     0,
+		"OUTPUT_ENCODING_BASE64":   1,
+		"OUTPUT_ENCODING_ARTIFACT": 2,
+	}
+)
+
+func (x OutputEncoding) Enum() *OutputEncoding {
+	p := new(OutputEncoding)
+	*p = x
+	return p
+}
+
+func (x OutputEncoding) String() string {
+	return protoimpl.X.EnumStringOf(x.Descriptor(), protoreflect.EnumNumber(x))
+}
+
+func (OutputEncoding) Descriptor() protoreflect.EnumDescriptor {
+	return file_proto_model_proto_enumTypes[2].Descriptor()
+}
+
+func (OutputEncoding) Type() protoreflect.EnumType {
+	return &file_proto_model_proto_enumTypes[2]
+}
+
+func (x OutputEncoding) Number() protoreflect.EnumNumber {
+	return protoreflect.EnumNumber(x)
+}
+
+// Deprecated: Use OutputEncoding.Descriptor instead.
+func (OutputEncoding) EnumDescriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{2}
+}
+
 // Resource represents a structured, optional multi-modal input to the model.
 //
 // This is intentionally minimal and "agnostic": planners can attach references
@@ -385,8 +534,43 @@ type ToolRequest struct {
 	CpuLimitMhz          int32  `protobuf:"varint,4,opt,name=cpu_limit_mhz,json=cpuLimitMhz,proto3" json:"cpu_limit_mhz,omitempty"`                         // Max CPU resource limit
 	MemoryLimitMb        int32  `protobuf:"varint,5,opt,name=memory_limit_mb,json=memoryLimitMb,proto3" json:"memory_limit_mb,omitempty"`                   // Max memory allocation
 	TimeoutSeconds       int32  `protobuf:"varint,6,opt,name=timeout_seconds,json=timeoutSeconds,proto3" json:"timeout_seconds,omitempty"`                  // Max runtime before termination
-	unknownFields        protoimpl.UnknownFields
-	sizeCache            protoimpl.SizeCache
+	// Per-execution wall-clock limit in milliseconds. Takes precedence over
+	// timeout_seconds; the sandbox default applies when neither is set. On
+	// expiry the tool is killed and status is "timed_out" with partial output.
+	TimeoutMs *uint64 `protobuf:"varint,7,opt,name=timeout_ms,json=timeoutMs,proto3,oneof" json:"timeout_ms,omitempty"`
+	// Keep the execution's scratch directory for a while after the run instead
+	// of deleting it; the response then names it in workspace_id.
+	RetainWorkspace bool `protobuf:"varint,8,opt,name=retain_workspace,json=retainWorkspace,proto3" json:"retain_workspace,omitempty"`
+	// Workspace files to collect into the artifact store after the run, as
+	// globs relative to the workspace; added to those the manifest declares.
+	Artifacts []string `protobuf:"bytes,9,rep,name=artifacts,proto3" json:"artifacts,omitempty"`
+	// Written to the tool's stdin; without it stdin is empty.
+	Stdin []byte `protobuf:"bytes,10,opt,name=stdin,proto3,oneof" json:"stdin,omitempty"`
+	// Files placed in the workspace before the run, keyed by path relative to
+	// it.
+	InputFiles map[string][]byte `protobuf:"bytes,11,rep,name=input_files,json=inputFiles,proto3" json:"input_files,omitempty" protobuf_key:"bytes,1,opt,name=key,proto3" protobuf_val:"bytes,2,opt,name=value,proto3"`
+	// Changes to the tool's retry policy for this call.
+	Retry *RetryOverride `protobuf:"bytes,12,opt,name=retry,proto3,oneof" json:"retry,omitempty"`
+	// "interactive" (the default), "batch" or "background". Higher classes get
+	// free execution slots first and, with preemption enabled, may cancel
+	// long-running lower ones.
+	Priority *string `protobuf:"bytes,13,opt,name=priority,proto3,oneof" json:"priority,omitempty"`
+	// Check the request and return what it would run, and under which limits,
+	// network and environment, instead of running it: status "planned" with
+	// the plan as JSON in stdout. Only ExecuteTool accepts it.
+	DryRun bool `protobuf:"varint,14,opt,name=dry_run,json=dryRun,proto3" json:"dry_run,omitempty"`
+	// Record what the execution takes from outside (its clock, random seed and
+	// http_request answers) and return it in ToolResponse.trace_json.
+	RecordTrace bool `protobuf:"varint,15,opt,name=record_trace,json=recordTrace,proto3" json:"record_trace,omitempty"`
+	// A trace_json from an earlier recording, served to the execution in place
+	// of the outside world. Not together with record_trace.
+	ReplayTraceJson *string `protobuf:"bytes,16,opt,name=replay_trace_json,json=replayTraceJson,proto3,oneof" json:"replay_trace_json,omitempty"`
+	// Run the tool on a pseudo-terminal of this size instead of pipes; its
+	// stderr then comes out with its stdout. Native and container tools only;
+	// only RunInteractive accepts it.
+	Pty           *TerminalSize `protobuf:"bytes,17,opt,name=pty,proto3,oneof" json:"pty,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
 }
 
 func (x *ToolRequest) Reset() {
@@ -461,29 +645,108 @@ func (x *ToolRequest) GetTimeoutSeconds() int32 {
 	return 0
 }
 
-type ToolResponse struct {
-	state         protoimpl.MessageState `protogen:"open.v1"`
-	Status        string                 `protobuf:"bytes,1,opt,name=status,proto3" json:"status,omitempty"`
-	Stdout        string                 `protobuf:"bytes,2,opt,name=stdout,proto3" json:"stdout,omitempty"`
-	Stderr        string                 `protobuf:"bytes,3,opt,name=stderr,proto3" json:"stderr,omitempty"`
-	unknownFields protoimpl.UnknownFields
-	sizeCache     protoimpl.SizeCache
+func (x *ToolRequest) GetTimeoutMs() uint64 {
+	if x != nil && x.TimeoutMs != nil {
+		return *x.TimeoutMs
+	}
+	return 0
 }
 
-func (x *ToolResponse) Reset() {
-	*x = ToolResponse{}
+func (x *ToolRequest) GetRetainWorkspace() bool {
+	if x != nil {
+		return x.RetainWorkspace
+	}
+	return false
+}
+
+func (x *ToolRequest) GetArtifacts() []string {
+	if x != nil {
+		return x.Artifacts
+	}
+	return nil
+}
+
+func (x *ToolRequest) GetStdin() []byte {
+	if x != nil {
+		return x.Stdin
+	}
+	return nil
+}
+
+func (x *ToolRequest) GetInputFiles() map[string][]byte {
+	if x != nil {
+		return x.InputFiles
+	}
+	return nil
+}
+
+func (x *ToolRequest) GetRetry() *RetryOverride {
+	if x != nil {
+		return x.Retry
+	}
+	return nil
+}
+
+func (x *ToolRequest) GetPriority() string {
+	if x != nil && x.Priority != nil {
+		return *x.Priority
+	}
+	return ""
+}
+
+func (x *ToolRequest) GetDryRun() bool {
+	if x != nil {
+		return x.DryRun
+	}
+	return false
+}
+
+func (x *ToolRequest) GetRecordTrace() bool {
+	if x != nil {
+		return x.RecordTrace
+	}
+	return false
+}
+
+func (x *ToolRequest) GetReplayTraceJson() string {
+	if x != nil && x.ReplayTraceJson != nil {
+		return *x.ReplayTraceJson
+	}
+	return ""
+}
+
+func (x *ToolRequest) GetPty() *TerminalSize {
+	if x != nil {
+		return x.Pty
+	}
+	return nil
+}
+
+// Unset fields keep the tool's policy (or the default one). Attempts are
+// capped by the sandbox.
+type RetryOverride struct {
+	state            protoimpl.MessageState `protogen:"open.v1"`
+	MaxAttempts      *uint32                `protobuf:"varint,1,opt,name=max_attempts,json=maxAttempts,proto3,oneof" json:"max_attempts,omitempty"`
+	InitialBackoffMs *uint64                `protobuf:"varint,2,opt,name=initial_backoff_ms,json=initialBackoffMs,proto3,oneof" json:"initial_backoff_ms,omitempty"`
+	MaxBackoffMs     *uint64                `protobuf:"varint,3,opt,name=max_backoff_ms,json=maxBackoffMs,proto3,oneof" json:"max_backoff_ms,omitempty"`
+	unknownFields    protoimpl.UnknownFields
+	sizeCache        protoimpl.SizeCache
+}
+
+func (x *RetryOverride) Reset() {
+	*x = RetryOverride{}
 	mi := &file_proto_model_proto_msgTypes[7]
 	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
 	ms.StoreMessageInfo(mi)
 }
 
-func (x *ToolResponse) String() string {
+func (x *RetryOverride) String() string {
 	return protoimpl.X.MessageStringOf(x)
 }
 
-func (*ToolResponse) ProtoMessage() {}
+func (*RetryOverride) ProtoMessage() {}
 
-func (x *ToolResponse) ProtoReflect() protoreflect.Message {
+func (x *RetryOverride) ProtoReflect() protoreflect.Message {
 	mi := &file_proto_model_proto_msgTypes[7]
 	if x != nil {
 		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
@@ -495,134 +758,1366 @@ func (x *ToolResponse) ProtoReflect() protoreflect.Message {
 	return mi.MessageOf(x)
 }
 
-// Deprecated: Use ToolResponse.ProtoReflect.Descriptor instead.
-func (*ToolResponse) Descriptor() ([]byte, []int) {
+// Deprecated: Use RetryOverride.ProtoReflect.Descriptor instead.
+func (*RetryOverride) Descriptor() ([]byte, []int) {
 	return file_proto_model_proto_rawDescGZIP(), []int{7}
 }
 
-func (x *ToolResponse) GetStatus() string {
+func (x *RetryOverride) GetMaxAttempts() uint32 {
+	if x != nil && x.MaxAttempts != nil {
+		return *x.MaxAttempts
+	}
+	return 0
+}
+
+func (x *RetryOverride) GetInitialBackoffMs() uint64 {
+	if x != nil && x.InitialBackoffMs != nil {
+		return *x.InitialBackoffMs
+	}
+	return 0
+}
+
+func (x *RetryOverride) GetMaxBackoffMs() uint64 {
+	if x != nil && x.MaxBackoffMs != nil {
+		return *x.MaxBackoffMs
+	}
+	return 0
+}
+
+type ToolOutputChunk struct {
+	state  protoimpl.MessageState `protogen:"open.v1"`
+	Stream OutputStreamType       `protobuf:"varint,1,opt,name=stream,proto3,enum=modelgateway.OutputStreamType" json:"stream,omitempty"`
+	Data   []byte                 `protobuf:"bytes,2,opt,name=data,proto3" json:"data,omitempty"`
+	// Monotonic across both streams of one execution, starting at 0.
+	Sequence      uint64 `protobuf:"varint,3,opt,name=sequence,proto3" json:"sequence,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *ToolOutputChunk) Reset() {
+	*x = ToolOutputChunk{}
+	mi := &file_proto_model_proto_msgTypes[8]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *ToolOutputChunk) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*ToolOutputChunk) ProtoMessage() {}
+
+func (x *ToolOutputChunk) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[8]
 	if x != nil {
-		return x.Status
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
 	}
-	return ""
+	return mi.MessageOf(x)
 }
 
-func (x *ToolResponse) GetStdout() string {
+// Deprecated: Use ToolOutputChunk.ProtoReflect.Descriptor instead.
+func (*ToolOutputChunk) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{8}
+}
+
+func (x *ToolOutputChunk) GetStream() OutputStreamType {
 	if x != nil {
-		return x.Stdout
+		return x.Stream
 	}
-	return ""
+	return OutputStreamType_OUTPUT_STREAM_UNSPECIFIED
 }
 
-func (x *ToolResponse) GetStderr() string {
+func (x *ToolOutputChunk) GetData() []byte {
 	if x != nil {
-		return x.Stderr
+		return x.Data
 	}
-	return ""
+	return nil
 }
 
-var File_proto_model_proto protoreflect.FileDescriptor
+func (x *ToolOutputChunk) GetSequence() uint64 {
+	if x != nil {
+		return x.Sequence
+	}
+	return 0
+}
 
-const file_proto_model_proto_rawDesc = "" +
-	"\n" +
-	"\x11proto/model.proto\x12\fmodelgateway\"0\n" +
-	"\bResource\x12\x12\n" +
-	"\x04type\x18\x01 \x01(\tR\x04type\x12\x10\n" +
-	"\x03uri\x18\x02 \x01(\tR\x03uri\"[\n" +
-	"\vPlanRequest\x12\x16\n" +
-	"\x06prompt\x18\x01 \x01(\tR\x06prompt\x124\n" +
-	"\tresources\x18\x02 \x03(\v2\x16.modelgateway.ResourceR\tresources\"`\n" +
-	"\fPlanResponse\x12\x12\n" +
-	"\x04plan\x18\x01 \x01(\tR\x04plan\x12\x1d\n" +
-	"\n" +
-	"model_name\x18\x02 \x01(\tR\tmodelName\x12\x1d\n" +
-	"\n" +
-	"latency_ms\x18\x03 \x01(\x03R\tlatencyMs\"g\n" +
-	"\x11RAGContextRequest\x12\x14\n" +
-	"\x05query\x18\x01 \x01(\tR\x05query\x12\x13\n" +
-	"\x05top_k\x18\x02 \x01(\x05R\x04topK\x12'\n" +
-	"\x0fknowledge_bases\x18\x03 \x03(\tR\x0eknowledgeBases\"\x89\x01\n" +
-	"\bRAGMatch\x12\x0e\n" +
-	"\x02id\x18\x01 \x01(\tR\x02id\x12\x12\n" +
-	"\x04text\x18\x02 \x01(\tR\x04text\x12\x1a\n" +
-	"\bdistance\x18\x03 \x01(\x01R\bdistance\x12%\n" +
-	"\x0eknowledge_base\x18\x04 \x01(\tR\rknowledgeBase\x12\x16\n" +
-	"\x06source\x18\x05 \x01(\tR\x06source\"F\n" +
-	"\x12RAGContextResponse\x120\n" +
-	"\amatches\x18\x01 \x03(\v2\x16.modelgateway.RAGMatchR\amatches\"\xf1\x01\n" +
-	"\vToolRequest\x12\x1b\n" +
-	"\ttool_name\x18\x01 \x01(\tR\btoolName\x12\x1b\n" +
-	"\targs_json\x18\x02 \x01(\tR\bargsJson\x123\n" +
-	"\x15execution_environment\x18\x03 \x01(\tR\x14executionEnvironment\x12\"\n" +
-	"\rcpu_limit_mhz\x18\x04 \x01(\x05R\vcpuLimitMhz\x12&\n" +
-	"\x0fmemory_limit_mb\x18\x05 \x01(\x05R\rmemoryLimitMb\x12'\n" +
-	"\x0ftimeout_seconds\x18\x06 \x01(\x05R\x0etimeoutSeconds\"V\n" +
-	"\fToolResponse\x12\x16\n" +
-	"\x06status\x18\x01 \x01(\tR\x06status\x12\x16\n" +
-	"\x06stdout\x18\x02 \x01(\tR\x06stdout\x12\x16\n" +
-	"\x06stderr\x18\x03 \x01(\tR\x06stderr2\xa4\x01\n" +
-	"\fModelGateway\x12@\n" +
-	"\aGetPlan\x12\x19.modelgateway.PlanRequest\x1a\x1a.modelgateway.PlanResponse\x12R\n" +
-	"\rGetRAGContext\x12\x1f.modelgateway.RAGContextRequest\x1a .modelgateway.RAGContextResponse2S\n" +
-	"\vToolService\x12D\n" +
-	"\vExecuteTool\x12\x19.modelgateway.ToolRequest\x1a\x1a.modelgateway.ToolResponseB&Z$backend-go-model-gateway/proto;protob\x06proto3"
+type ToolStreamMessage struct {
+	state protoimpl.MessageState `protogen:"open.v1"`
+	// Types that are valid to be assigned to Payload:
+	//
+	//	*ToolStreamMessage_Chunk
+	//	*ToolStreamMessage_Final
+	Payload       isToolStreamMessage_Payload `protobuf_oneof:"payload"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
 
-var (
-	file_proto_model_proto_rawDescOnce sync.Once
-	file_proto_model_proto_rawDescData []byte
-)
+func (x *ToolStreamMessage) Reset() {
+	*x = ToolStreamMessage{}
+	mi := &file_proto_model_proto_msgTypes[9]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
 
-func file_proto_model_proto_rawDescGZIP() []byte {
-	file_proto_model_proto_rawDescOnce.Do(func() {
-		file_proto_model_proto_rawDescData = protoimpl.X.CompressGZIP(unsafe.Slice(unsafe.StringData(file_proto_model_proto_rawDesc), len(file_proto_model_proto_rawDesc)))
-	})
-	return file_proto_model_proto_rawDescData
+func (x *ToolStreamMessage) String() string {
+	return protoimpl.X.MessageStringOf(x)
 }
 
-var file_proto_model_proto_msgTypes = make([]protoimpl.MessageInfo, 8)
-var file_proto_model_proto_goTypes = []any{
-	(*Resource)(nil),           // 0: modelgateway.Resource
-	(*PlanRequest)(nil),        // 1: modelgateway.PlanRequest
-	(*PlanResponse)(nil),       // 2: modelgateway.PlanResponse
-	(*RAGContextRequest)(nil),  // 3: modelgateway.RAGContextRequest
-	(*RAGMatch)(nil),           // 4: modelgateway.RAGMatch
-	(*RAGContextResponse)(nil), // 5: modelgateway.RAGContextResponse
-	(*ToolRequest)(nil),        // 6: modelgateway.ToolRequest
-	(*ToolResponse)(nil),       // 7: modelgateway.ToolResponse
+func (*ToolStreamMessage) ProtoMessage() {}
+
+func (x *ToolStreamMessage) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[9]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
 }
-var file_proto_model_proto_depIdxs = []int32{
-	0, // 0: modelgateway.PlanRequest.resources:type_name -> modelgateway.Resource
-	4, // 1: modelgateway.RAGContextResponse.matches:type_name -> modelgateway.RAGMatch
-	1, // 2: modelgateway.ModelGateway.GetPlan:input_type -> modelgateway.PlanRequest
-	3, // 3: modelgateway.ModelGateway.GetRAGContext:input_type -> modelgateway.RAGContextRequest
-	6, // 4: modelgateway.ToolService.ExecuteTool:input_type -> modelgateway.ToolRequest
-	2, // 5: modelgateway.ModelGateway.GetPlan:output_type -> modelgateway.PlanResponse
-	5, // 6: modelgateway.ModelGateway.GetRAGContext:output_type -> modelgateway.RAGContextResponse
-	7, // 7: modelgateway.ToolService.ExecuteTool:output_type -> modelgateway.ToolResponse
-	5, // [5:8] is the sub-list for method output_type
-	2, // [2:5] is the sub-list for method input_type
-	2, // [2:2] is the sub-list for extension type_name
-	2, // [2:2] is the sub-list for extension extendee
-	0, // [0:2] is the sub-list for field type_name
+
+// Deprecated: Use ToolStreamMessage.ProtoReflect.Descriptor instead.
+func (*ToolStreamMessage) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{9}
 }
 
-func init() { file_proto_model_proto_init() }
-func file_proto_model_proto_init() {
-	if File_proto_model_proto != nil {
-		return
+func (x *ToolStreamMessage) GetPayload() isToolStreamMessage_Payload {
+	if x != nil {
+		return x.Payload
+	}
+	return nil
+}
+
+func (x *ToolStreamMessage) GetChunk() *ToolOutputChunk {
+	if x != nil {
+		if x, ok := x.Payload.(*ToolStreamMessage_Chunk); ok {
+			return x.Chunk
+		}
+	}
+	return nil
+}
+
+func (x *ToolStreamMessage) GetFinal() *ToolResponse {
+	if x != nil {
+		if x, ok := x.Payload.(*ToolStreamMessage_Final); ok {
+			return x.Final
+		}
+	}
+	return nil
+}
+
+type isToolStreamMessage_Payload interface {
+	isToolStreamMessage_Payload()
+}
+
+type ToolStreamMessage_Chunk struct {
+	Chunk *ToolOutputChunk `protobuf:"bytes,1,opt,name=chunk,proto3,oneof"`
+}
+
+type ToolStreamMessage_Final struct {
+	Final *ToolResponse `protobuf:"bytes,2,opt,name=final,proto3,oneof"`
+}
+
+func (*ToolStreamMessage_Chunk) isToolStreamMessage_Payload() {}
+
+func (*ToolStreamMessage_Final) isToolStreamMessage_Payload() {}
+
+type TerminalSize struct {
+	state         protoimpl.MessageState `protogen:"open.v1"`
+	Cols          uint32                 `protobuf:"varint,1,opt,name=cols,proto3" json:"cols,omitempty"`
+	Rows          uint32                 `protobuf:"varint,2,opt,name=rows,proto3" json:"rows,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *TerminalSize) Reset() {
+	*x = TerminalSize{}
+	mi := &file_proto_model_proto_msgTypes[10]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *TerminalSize) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*TerminalSize) ProtoMessage() {}
+
+func (x *TerminalSize) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[10]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use TerminalSize.ProtoReflect.Descriptor instead.
+func (*TerminalSize) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{10}
+}
+
+func (x *TerminalSize) GetCols() uint32 {
+	if x != nil {
+		return x.Cols
+	}
+	return 0
+}
+
+func (x *TerminalSize) GetRows() uint32 {
+	if x != nil {
+		return x.Rows
+	}
+	return 0
+}
+
+type InteractiveClientMessage struct {
+	state protoimpl.MessageState `protogen:"open.v1"`
+	// Types that are valid to be assigned to Payload:
+	//
+	//	*InteractiveClientMessage_Start
+	//	*InteractiveClientMessage_Stdin
+	//	*InteractiveClientMessage_Control
+	//	*InteractiveClientMessage_Resize
+	Payload       isInteractiveClientMessage_Payload `protobuf_oneof:"payload"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *InteractiveClientMessage) Reset() {
+	*x = InteractiveClientMessage{}
+	mi := &file_proto_model_proto_msgTypes[11]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *InteractiveClientMessage) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*InteractiveClientMessage) ProtoMessage() {}
+
+func (x *InteractiveClientMessage) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[11]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use InteractiveClientMessage.ProtoReflect.Descriptor instead.
+func (*InteractiveClientMessage) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{11}
+}
+
+func (x *InteractiveClientMessage) GetPayload() isInteractiveClientMessage_Payload {
+	if x != nil {
+		return x.Payload
+	}
+	return nil
+}
+
+func (x *InteractiveClientMessage) GetStart() *ToolRequest {
+	if x != nil {
+		if x, ok := x.Payload.(*InteractiveClientMessage_Start); ok {
+			return x.Start
+		}
+	}
+	return nil
+}
+
+func (x *InteractiveClientMessage) GetStdin() []byte {
+	if x != nil {
+		if x, ok := x.Payload.(*InteractiveClientMessage_Stdin); ok {
+			return x.Stdin
+		}
+	}
+	return nil
+}
+
+func (x *InteractiveClientMessage) GetControl() InteractiveControl {
+	if x != nil {
+		if x, ok := x.Payload.(*InteractiveClientMessage_Control); ok {
+			return x.Control
+		}
+	}
+	return InteractiveControl_INTERACTIVE_CONTROL_UNSPECIFIED
+}
+
+func (x *InteractiveClientMessage) GetResize() *TerminalSize {
+	if x != nil {
+		if x, ok := x.Payload.(*InteractiveClientMessage_Resize); ok {
+			return x.Resize
+		}
+	}
+	return nil
+}
+
+type isInteractiveClientMessage_Payload interface {
+	isInteractiveClientMessage_Payload()
+}
+
+type InteractiveClientMessage_Start struct {
+	Start *ToolRequest `protobuf:"bytes,1,opt,name=start,proto3,oneof"`
+}
+
+type InteractiveClientMessage_Stdin struct {
+	Stdin []byte `protobuf:"bytes,2,opt,name=stdin,proto3,oneof"`
+}
+
+type InteractiveClientMessage_Control struct {
+	Control InteractiveControl `protobuf:"varint,3,opt,name=control,proto3,enum=modelgateway.InteractiveControl,oneof"`
+}
+
+type InteractiveClientMessage_Resize struct {
+	// Resize the tool's terminal, when it runs on one; otherwise it has no
+	// effect.
+	Resize *TerminalSize `protobuf:"bytes,4,opt,name=resize,proto3,oneof"`
+}
+
+func (*InteractiveClientMessage_Start) isInteractiveClientMessage_Payload() {}
+
+func (*InteractiveClientMessage_Stdin) isInteractiveClientMessage_Payload() {}
+
+func (*InteractiveClientMessage_Control) isInteractiveClientMessage_Payload() {}
+
+func (*InteractiveClientMessage_Resize) isInteractiveClientMessage_Payload() {}
+
+type InteractiveStarted struct {
+	state         protoimpl.MessageState `protogen:"open.v1"`
+	ToolName      string                 `protobuf:"bytes,1,opt,name=tool_name,json=toolName,proto3" json:"tool_name,omitempty"`
+	SessionId     string                 `protobuf:"bytes,2,opt,name=session_id,json=sessionId,proto3" json:"session_id,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *InteractiveStarted) Reset() {
+	*x = InteractiveStarted{}
+	mi := &file_proto_model_proto_msgTypes[12]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *InteractiveStarted) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*InteractiveStarted) ProtoMessage() {}
+
+func (x *InteractiveStarted) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[12]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use InteractiveStarted.ProtoReflect.Descriptor instead.
+func (*InteractiveStarted) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{12}
+}
+
+func (x *InteractiveStarted) GetToolName() string {
+	if x != nil {
+		return x.ToolName
+	}
+	return ""
+}
+
+func (x *InteractiveStarted) GetSessionId() string {
+	if x != nil {
+		return x.SessionId
+	}
+	return ""
+}
+
+type InteractiveServerMessage struct {
+	state protoimpl.MessageState `protogen:"open.v1"`
+	// Types that are valid to be assigned to Payload:
+	//
+	//	*InteractiveServerMessage_Started
+	//	*InteractiveServerMessage_Chunk
+	//	*InteractiveServerMessage_Exited
+	Payload       isInteractiveServerMessage_Payload `protobuf_oneof:"payload"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *InteractiveServerMessage) Reset() {
+	*x = InteractiveServerMessage{}
+	mi := &file_proto_model_proto_msgTypes[13]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *InteractiveServerMessage) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*InteractiveServerMessage) ProtoMessage() {}
+
+func (x *InteractiveServerMessage) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[13]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use InteractiveServerMessage.ProtoReflect.Descriptor instead.
+func (*InteractiveServerMessage) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{13}
+}
+
+func (x *InteractiveServerMessage) GetPayload() isInteractiveServerMessage_Payload {
+	if x != nil {
+		return x.Payload
+	}
+	return nil
+}
+
+func (x *InteractiveServerMessage) GetStarted() *InteractiveStarted {
+	if x != nil {
+		if x, ok := x.Payload.(*InteractiveServerMessage_Started); ok {
+			return x.Started
+		}
+	}
+	return nil
+}
+
+func (x *InteractiveServerMessage) GetChunk() *ToolOutputChunk {
+	if x != nil {
+		if x, ok := x.Payload.(*InteractiveServerMessage_Chunk); ok {
+			return x.Chunk
+		}
+	}
+	return nil
+}
+
+func (x *InteractiveServerMessage) GetExited() *ToolResponse {
+	if x != nil {
+		if x, ok := x.Payload.(*InteractiveServerMessage_Exited); ok {
+			return x.Exited
+		}
+	}
+	return nil
+}
+
+type isInteractiveServerMessage_Payload interface {
+	isInteractiveServerMessage_Payload()
+}
+
+type InteractiveServerMessage_Started struct {
+	Started *InteractiveStarted `protobuf:"bytes,1,opt,name=started,proto3,oneof"`
+}
+
+type InteractiveServerMessage_Chunk struct {
+	Chunk *ToolOutputChunk `protobuf:"bytes,2,opt,name=chunk,proto3,oneof"`
+}
+
+type InteractiveServerMessage_Exited struct {
+	Exited *ToolResponse `protobuf:"bytes,3,opt,name=exited,proto3,oneof"`
+}
+
+func (*InteractiveServerMessage_Started) isInteractiveServerMessage_Payload() {}
+
+func (*InteractiveServerMessage_Chunk) isInteractiveServerMessage_Payload() {}
+
+func (*InteractiveServerMessage_Exited) isInteractiveServerMessage_Payload() {}
+
+type ToolResponse struct {
+	state protoimpl.MessageState `protogen:"open.v1"`
+	// "ok", or a failure class such as "non_zero_exit", "killed", "not_found",
+	// "spawn_error", "unknown_tool", "policy_violation"; "planned" answers a
+	// dry_run.
+	Status string `protobuf:"bytes,1,opt,name=status,proto3" json:"status,omitempty"`
+	Stdout string `protobuf:"bytes,2,opt,name=stdout,proto3" json:"stdout,omitempty"`
+	Stderr string `protobuf:"bytes,3,opt,name=stderr,proto3" json:"stderr,omitempty"`
+	// Exit code of the tool process, when there was one and it exited normally.
+	ExitCode *int32 `protobuf:"varint,4,opt,name=exit_code,json=exitCode,proto3,oneof" json:"exit_code,omitempty"`
+	// Set when the request asked to retain the workspace and it was kept.
+	WorkspaceId *string `protobuf:"bytes,5,opt,name=workspace_id,json=workspaceId,proto3,oneof" json:"workspace_id,omitempty"`
+	// Files collected from the workspace; fetch them from
+	// GET /api/v1/artifacts/{artifact_id}.
+	Artifacts []*Artifact `protobuf:"bytes,6,rep,name=artifacts,proto3" json:"artifacts,omitempty"`
+	// Set when output went past the sandbox's limit and only its beginning
+	// was kept; the byte counts are what the tool wrote in all.
+	Truncated   bool   `protobuf:"varint,7,opt,name=truncated,proto3" json:"truncated,omitempty"`
+	StdoutBytes uint64 `protobuf:"varint,8,opt,name=stdout_bytes,json=stdoutBytes,proto3" json:"stdout_bytes,omitempty"`
+	StderrBytes uint64 `protobuf:"varint,9,opt,name=stderr_bytes,json=stderrBytes,proto3" json:"stderr_bytes,omitempty"`
+	// Runs it took, when a retry policy applied.
+	Attempts *uint32 `protobuf:"varint,10,opt,name=attempts,proto3,oneof" json:"attempts,omitempty"`
+	// The recorded trace, as JSON, when the request set record_trace.
+	TraceJson *string `protobuf:"bytes,11,opt,name=trace_json,json=traceJson,proto3,oneof" json:"trace_json,omitempty"`
+	// What the execution used, once it has run.
+	Usage *ResourceUsage `protobuf:"bytes,12,opt,name=usage,proto3,oneof" json:"usage,omitempty"`
+	// How stdout and stderr are carried: as they are, in base64 when they
+	// are not UTF-8, or as the id of the artifact holding them.
+	StdoutEncoding OutputEncoding `protobuf:"varint,13,opt,name=stdout_encoding,json=stdoutEncoding,proto3,enum=modelgateway.OutputEncoding" json:"stdout_encoding,omitempty"`
+	StderrEncoding OutputEncoding `protobuf:"varint,14,opt,name=stderr_encoding,json=stderrEncoding,proto3,enum=modelgateway.OutputEncoding" json:"stderr_encoding,omitempty"`
+	// What ran and under which policy, as JSON, once it has run: the tool,
+	// its backend, digests of its program or image, limits and variable
+	// names.
+	EnvironmentJson *string `protobuf:"bytes,15,opt,name=environment_json,json=environmentJson,proto3,oneof" json:"environment_json,omitempty"`
+	// What the code scan found in submitted code: why a "policy_violation"
+	// did not run, or what flagged code ran with.
+	PolicyViolations []*PolicyViolation `protobuf:"bytes,16,rep,name=policy_violations,json=policyViolations,proto3" json:"policy_violations,omitempty"`
+	// What the output filters took out of stdout and stderr.
+	Redactions []*Redaction `protobuf:"bytes,17,rep,name=redactions,proto3" json:"redactions,omitempty"`
+	// Set when the execution was quarantined: its artifacts are held until
+	// an operator approves them.
+	Quarantine    *Quarantine `protobuf:"bytes,18,opt,name=quarantine,proto3,oneof" json:"quarantine,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *ToolResponse) Reset() {
+	*x = ToolResponse{}
+	mi := &file_proto_model_proto_msgTypes[14]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *ToolResponse) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*ToolResponse) ProtoMessage() {}
+
+func (x *ToolResponse) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[14]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use ToolResponse.ProtoReflect.Descriptor instead.
+func (*ToolResponse) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{14}
+}
+
+func (x *ToolResponse) GetStatus() string {
+	if x != nil {
+		return x.Status
+	}
+	return ""
+}
+
+func (x *ToolResponse) GetStdout() string {
+	if x != nil {
+		return x.Stdout
+	}
+	return ""
+}
+
+func (x *ToolResponse) GetStderr() string {
+	if x != nil {
+		return x.Stderr
+	}
+	return ""
+}
+
+func (x *ToolResponse) GetExitCode() int32 {
+	if x != nil && x.ExitCode != nil {
+		return *x.ExitCode
+	}
+	return 0
+}
+
+func (x *ToolResponse) GetWorkspaceId() string {
+	if x != nil && x.WorkspaceId != nil {
+		return *x.WorkspaceId
+	}
+	return ""
+}
+
+func (x *ToolResponse) GetArtifacts() []*Artifact {
+	if x != nil {
+		return x.Artifacts
+	}
+	return nil
+}
+
+func (x *ToolResponse) GetTruncated() bool {
+	if x != nil {
+		return x.Truncated
+	}
+	return false
+}
+
+func (x *ToolResponse) GetStdoutBytes() uint64 {
+	if x != nil {
+		return x.StdoutBytes
+	}
+	return 0
+}
+
+func (x *ToolResponse) GetStderrBytes() uint64 {
+	if x != nil {
+		return x.StderrBytes
+	}
+	return 0
+}
+
+func (x *ToolResponse) GetAttempts() uint32 {
+	if x != nil && x.Attempts != nil {
+		return *x.Attempts
+	}
+	return 0
+}
+
+func (x *ToolResponse) GetTraceJson() string {
+	if x != nil && x.TraceJson != nil {
+		return *x.TraceJson
+	}
+	return ""
+}
+
+func (x *ToolResponse) GetUsage() *ResourceUsage {
+	if x != nil {
+		return x.Usage
+	}
+	return nil
+}
+
+func (x *ToolResponse) GetStdoutEncoding() OutputEncoding {
+	if x != nil {
+		return x.StdoutEncoding
+	}
+	return OutputEncoding_OUTPUT_ENCODING_UTF8
+}
+
+func (x *ToolResponse) GetStderrEncoding() OutputEncoding {
+	if x != nil {
+		return x.StderrEncoding
+	}
+	return OutputEncoding_OUTPUT_ENCODING_UTF8
+}
+
+func (x *ToolResponse) GetEnvironmentJson() string {
+	if x != nil && x.EnvironmentJson != nil {
+		return *x.EnvironmentJson
+	}
+	return ""
+}
+
+func (x *ToolResponse) GetPolicyViolations() []*PolicyViolation {
+	if x != nil {
+		return x.PolicyViolations
+	}
+	return nil
+}
+
+func (x *ToolResponse) GetRedactions() []*Redaction {
+	if x != nil {
+		return x.Redactions
+	}
+	return nil
+}
+
+func (x *ToolResponse) GetQuarantine() *Quarantine {
+	if x != nil {
+		return x.Quarantine
+	}
+	return nil
+}
+
+type Quarantine struct {
+	state protoimpl.MessageState `protogen:"open.v1"`
+	// The execution id, as the admin API names the entry.
+	Id string `protobuf:"bytes,1,opt,name=id,proto3" json:"id,omitempty"`
+	// Why, as text: "anonymous caller", "flagged code (rule)", "wall_ms 12000
+	// over 10000" and the like.
+	Reasons       []string `protobuf:"bytes,2,rep,name=reasons,proto3" json:"reasons,omitempty"`
+	ArtifactsHeld uint32   `protobuf:"varint,3,opt,name=artifacts_held,json=artifactsHeld,proto3" json:"artifacts_held,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *Quarantine) Reset() {
+	*x = Quarantine{}
+	mi := &file_proto_model_proto_msgTypes[15]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *Quarantine) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*Quarantine) ProtoMessage() {}
+
+func (x *Quarantine) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[15]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use Quarantine.ProtoReflect.Descriptor instead.
+func (*Quarantine) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{15}
+}
+
+func (x *Quarantine) GetId() string {
+	if x != nil {
+		return x.Id
+	}
+	return ""
+}
+
+func (x *Quarantine) GetReasons() []string {
+	if x != nil {
+		return x.Reasons
+	}
+	return nil
+}
+
+func (x *Quarantine) GetArtifactsHeld() uint32 {
+	if x != nil {
+		return x.ArtifactsHeld
+	}
+	return 0
+}
+
+type Redaction struct {
+	state  protoimpl.MessageState `protogen:"open.v1"`
+	Stream OutputStreamType       `protobuf:"varint,1,opt,name=stream,proto3,enum=modelgateway.OutputStreamType" json:"stream,omitempty"`
+	// "secret", "credential", a PII kind, "patterns[<index>]" or
+	// "max_line_length".
+	Filter string `protobuf:"bytes,2,opt,name=filter,proto3" json:"filter,omitempty"`
+	// Matches replaced, or lines cut.
+	Count         uint32 `protobuf:"varint,3,opt,name=count,proto3" json:"count,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *Redaction) Reset() {
+	*x = Redaction{}
+	mi := &file_proto_model_proto_msgTypes[16]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *Redaction) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*Redaction) ProtoMessage() {}
+
+func (x *Redaction) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[16]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use Redaction.ProtoReflect.Descriptor instead.
+func (*Redaction) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{16}
+}
+
+func (x *Redaction) GetStream() OutputStreamType {
+	if x != nil {
+		return x.Stream
+	}
+	return OutputStreamType_OUTPUT_STREAM_UNSPECIFIED
+}
+
+func (x *Redaction) GetFilter() string {
+	if x != nil {
+		return x.Filter
+	}
+	return ""
+}
+
+func (x *Redaction) GetCount() uint32 {
+	if x != nil {
+		return x.Count
+	}
+	return 0
+}
+
+type PolicyViolation struct {
+	state protoimpl.MessageState `protogen:"open.v1"`
+	// The rule's id, or "blocked_import".
+	Rule    string `protobuf:"bytes,1,opt,name=rule,proto3" json:"rule,omitempty"`
+	Message string `protobuf:"bytes,2,opt,name=message,proto3" json:"message,omitempty"`
+	// "reject" or "flag".
+	Action        string `protobuf:"bytes,3,opt,name=action,proto3" json:"action,omitempty"`
+	Line          uint32 `protobuf:"varint,4,opt,name=line,proto3" json:"line,omitempty"`
+	Column        uint32 `protobuf:"varint,5,opt,name=column,proto3" json:"column,omitempty"`
+	Snippet       string `protobuf:"bytes,6,opt,name=snippet,proto3" json:"snippet,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *PolicyViolation) Reset() {
+	*x = PolicyViolation{}
+	mi := &file_proto_model_proto_msgTypes[17]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *PolicyViolation) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*PolicyViolation) ProtoMessage() {}
+
+func (x *PolicyViolation) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[17]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use PolicyViolation.ProtoReflect.Descriptor instead.
+func (*PolicyViolation) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{17}
+}
+
+func (x *PolicyViolation) GetRule() string {
+	if x != nil {
+		return x.Rule
+	}
+	return ""
+}
+
+func (x *PolicyViolation) GetMessage() string {
+	if x != nil {
+		return x.Message
+	}
+	return ""
+}
+
+func (x *PolicyViolation) GetAction() string {
+	if x != nil {
+		return x.Action
+	}
+	return ""
+}
+
+func (x *PolicyViolation) GetLine() uint32 {
+	if x != nil {
+		return x.Line
+	}
+	return 0
+}
+
+func (x *PolicyViolation) GetColumn() uint32 {
+	if x != nil {
+		return x.Column
+	}
+	return 0
+}
+
+func (x *PolicyViolation) GetSnippet() string {
+	if x != nil {
+		return x.Snippet
+	}
+	return ""
+}
+
+// The CPU, memory and storage figures are measured on the processes the
+// execution spawned, and unset when it spawned none.
+type ResourceUsage struct {
+	state protoimpl.MessageState `protogen:"open.v1"`
+	// Time the execution held its slot, all attempts included.
+	WallMs       uint64  `protobuf:"varint,1,opt,name=wall_ms,json=wallMs,proto3" json:"wall_ms,omitempty"`
+	UserCpuMs    *uint64 `protobuf:"varint,2,opt,name=user_cpu_ms,json=userCpuMs,proto3,oneof" json:"user_cpu_ms,omitempty"`
+	SysCpuMs     *uint64 `protobuf:"varint,3,opt,name=sys_cpu_ms,json=sysCpuMs,proto3,oneof" json:"sys_cpu_ms,omitempty"`
+	PeakRssBytes *uint64 `protobuf:"varint,4,opt,name=peak_rss_bytes,json=peakRssBytes,proto3,oneof" json:"peak_rss_bytes,omitempty"`
+	// Written to storage.
+	BytesWritten *uint64 `protobuf:"varint,5,opt,name=bytes_written,json=bytesWritten,proto3,oneof" json:"bytes_written,omitempty"`
+	// Written to stdout and stderr, whether kept or not.
+	OutputBytes *uint64 `protobuf:"varint,6,opt,name=output_bytes,json=outputBytes,proto3,oneof" json:"output_bytes,omitempty"`
+	// What it cost at the sandbox's rates, when they price anything.
+	Cost          *float64 `protobuf:"fixed64,7,opt,name=cost,proto3,oneof" json:"cost,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *ResourceUsage) Reset() {
+	*x = ResourceUsage{}
+	mi := &file_proto_model_proto_msgTypes[18]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *ResourceUsage) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*ResourceUsage) ProtoMessage() {}
+
+func (x *ResourceUsage) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[18]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use ResourceUsage.ProtoReflect.Descriptor instead.
+func (*ResourceUsage) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{18}
+}
+
+func (x *ResourceUsage) GetWallMs() uint64 {
+	if x != nil {
+		return x.WallMs
+	}
+	return 0
+}
+
+func (x *ResourceUsage) GetUserCpuMs() uint64 {
+	if x != nil && x.UserCpuMs != nil {
+		return *x.UserCpuMs
+	}
+	return 0
+}
+
+func (x *ResourceUsage) GetSysCpuMs() uint64 {
+	if x != nil && x.SysCpuMs != nil {
+		return *x.SysCpuMs
+	}
+	return 0
+}
+
+func (x *ResourceUsage) GetPeakRssBytes() uint64 {
+	if x != nil && x.PeakRssBytes != nil {
+		return *x.PeakRssBytes
+	}
+	return 0
+}
+
+func (x *ResourceUsage) GetBytesWritten() uint64 {
+	if x != nil && x.BytesWritten != nil {
+		return *x.BytesWritten
+	}
+	return 0
+}
+
+func (x *ResourceUsage) GetOutputBytes() uint64 {
+	if x != nil && x.OutputBytes != nil {
+		return *x.OutputBytes
+	}
+	return 0
+}
+
+func (x *ResourceUsage) GetCost() float64 {
+	if x != nil && x.Cost != nil {
+		return *x.Cost
+	}
+	return 0
+}
+
+type Artifact struct {
+	state protoimpl.MessageState `protogen:"open.v1"`
+	// SHA-256 of the contents.
+	ArtifactId string `protobuf:"bytes,1,opt,name=artifact_id,json=artifactId,proto3" json:"artifact_id,omitempty"`
+	// Path relative to the workspace.
+	Path          string `protobuf:"bytes,2,opt,name=path,proto3" json:"path,omitempty"`
+	SizeBytes     uint64 `protobuf:"varint,3,opt,name=size_bytes,json=sizeBytes,proto3" json:"size_bytes,omitempty"`
+	unknownFields protoimpl.UnknownFields
+	sizeCache     protoimpl.SizeCache
+}
+
+func (x *Artifact) Reset() {
+	*x = Artifact{}
+	mi := &file_proto_model_proto_msgTypes[19]
+	ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+	ms.StoreMessageInfo(mi)
+}
+
+func (x *Artifact) String() string {
+	return protoimpl.X.MessageStringOf(x)
+}
+
+func (*Artifact) ProtoMessage() {}
+
+func (x *Artifact) ProtoReflect() protoreflect.Message {
+	mi := &file_proto_model_proto_msgTypes[19]
+	if x != nil {
+		ms := protoimpl.X.MessageStateOf(protoimpl.Pointer(x))
+		if ms.LoadMessageInfo() == nil {
+			ms.StoreMessageInfo(mi)
+		}
+		return ms
+	}
+	return mi.MessageOf(x)
+}
+
+// Deprecated: Use Artifact.ProtoReflect.Descriptor instead.
+func (*Artifact) Descriptor() ([]byte, []int) {
+	return file_proto_model_proto_rawDescGZIP(), []int{19}
+}
+
+func (x *Artifact) GetArtifactId() string {
+	if x != nil {
+		return x.ArtifactId
+	}
+	return ""
+}
+
+func (x *Artifact) GetPath() string {
+	if x != nil {
+		return x.Path
+	}
+	return ""
+}
+
+func (x *Artifact) GetSizeBytes() uint64 {
+	if x != nil {
+		return x.SizeBytes
+	}
+	return 0
+}
+
+var File_proto_model_proto protoreflect.FileDescriptor
+
+const file_proto_model_proto_rawDesc = "" +
+	"\n" +
+	"\x11proto/model.proto\x12\fmodelgateway\"0\n" +
+	"\bResource\x12\x12\n" +
+	"\x04type\x18\x01 \x01(\tR\x04type\x12\x10\n" +
+	"\x03uri\x18\x02 \x01(\tR\x03uri\"[\n" +
+	"\vPlanRequest\x12\x16\n" +
+	"\x06prompt\x18\x01 \x01(\tR\x06prompt\x124\n" +
+	"\tresources\x18\x02 \x03(\v2\x16.modelgateway.ResourceR\tresources\"`\n" +
+	"\fPlanResponse\x12\x12\n" +
+	"\x04plan\x18\x01 \x01(\tR\x04plan\x12\x1d\n" +
+	"\n" +
+	"model_name\x18\x02 \x01(\tR\tmodelName\x12\x1d\n" +
+	"\n" +
+	"latency_ms\x18\x03 \x01(\x03R\tlatencyMs\"g\n" +
+	"\x11RAGContextRequest\x12\x14\n" +
+	"\x05query\x18\x01 \x01(\tR\x05query\x12\x13\n" +
+	"\x05top_k\x18\x02 \x01(\x05R\x04topK\x12'\n" +
+	"\x0fknowledge_bases\x18\x03 \x03(\tR\x0eknowledgeBases\"\x89\x01\n" +
+	"\bRAGMatch\x12\x0e\n" +
+	"\x02id\x18\x01 \x01(\tR\x02id\x12\x12\n" +
+	"\x04text\x18\x02 \x01(\tR\x04text\x12\x1a\n" +
+	"\bdistance\x18\x03 \x01(\x01R\bdistance\x12%\n" +
+	"\x0eknowledge_base\x18\x04 \x01(\tR\rknowledgeBase\x12\x16\n" +
+	"\x06source\x18\x05 \x01(\tR\x06source\"F\n" +
+	"\x12RAGContextResponse\x120\n" +
+	"\amatches\x18\x01 \x03(\v2\x16.modelgateway.RAGMatchR\amatches\"\xcb\x06\n" +
+	"\vToolRequest\x12\x1b\n" +
+	"\ttool_name\x18\x01 \x01(\tR\btoolName\x12\x1b\n" +
+	"\targs_json\x18\x02 \x01(\tR\bargsJson\x123\n" +
+	"\x15execution_environment\x18\x03 \x01(\tR\x14executionEnvironment\x12\"\n" +
+	"\rcpu_limit_mhz\x18\x04 \x01(\x05R\vcpuLimitMhz\x12&\n" +
+	"\x0fmemory_limit_mb\x18\x05 \x01(\x05R\rmemoryLimitMb\x12'\n" +
+	"\x0ftimeout_seconds\x18\x06 \x01(\x05R\x0etimeoutSeconds\x12\"\n" +
+	"\n" +
+	"timeout_ms\x18\a \x01(\x04H\x00R\ttimeoutMs\x88\x01\x01\x12)\n" +
+	"\x10retain_workspace\x18\b \x01(\bR\x0fretainWorkspace\x12\x1c\n" +
+	"\tartifacts\x18\t \x03(\tR\tartifacts\x12\x19\n" +
+	"\x05stdin\x18\n" +
+	" \x01(\fH\x01R\x05stdin\x88\x01\x01\x12J\n" +
+	"\vinput_files\x18\v \x03(\v2).modelgateway.ToolRequest.InputFilesEntryR\n" +
+	"inputFiles\x126\n" +
+	"\x05retry\x18\f \x01(\v2\x1b.modelgateway.RetryOverrideH\x02R\x05retry\x88\x01\x01\x12\x1f\n" +
+	"\bpriority\x18\r \x01(\tH\x03R\bpriority\x88\x01\x01\x12\x17\n" +
+	"\adry_run\x18\x0e \x01(\bR\x06dryRun\x12!\n" +
+	"\frecord_trace\x18\x0f \x01(\bR\vrecordTrace\x12/\n" +
+	"\x11replay_trace_json\x18\x10 \x01(\tH\x04R\x0freplayTraceJson\x88\x01\x01\x121\n" +
+	"\x03pty\x18\x11 \x01(\v2\x1a.modelgateway.TerminalSizeH\x05R\x03pty\x88\x01\x01\x1a=\n" +
+	"\x0fInputFilesEntry\x12\x10\n" +
+	"\x03key\x18\x01 \x01(\tR\x03key\x12\x14\n" +
+	"\x05value\x18\x02 \x01(\fR\x05value:\x028\x01B\r\n" +
+	"\v_timeout_msB\b\n" +
+	"\x06_stdinB\b\n" +
+	"\x06_retryB\v\n" +
+	"\t_priorityB\x14\n" +
+	"\x12_replay_trace_jsonB\x06\n" +
+	"\x04_pty\"\xd0\x01\n" +
+	"\rRetryOverride\x12&\n" +
+	"\fmax_attempts\x18\x01 \x01(\rH\x00R\vmaxAttempts\x88\x01\x01\x121\n" +
+	"\x12initial_backoff_ms\x18\x02 \x01(\x04H\x01R\x10initialBackoffMs\x88\x01\x01\x12)\n" +
+	"\x0emax_backoff_ms\x18\x03 \x01(\x04H\x02R\fmaxBackoffMs\x88\x01\x01B\x0f\n" +
+	"\r_max_attemptsB\x15\n" +
+	"\x13_initial_backoff_msB\x11\n" +
+	"\x0f_max_backoff_ms\"y\n" +
+	"\x0fToolOutputChunk\x126\n" +
+	"\x06stream\x18\x01 \x01(\x0e2\x1e.modelgateway.OutputStreamTypeR\x06stream\x12\x12\n" +
+	"\x04data\x18\x02 \x01(\fR\x04data\x12\x1a\n" +
+	"\bsequence\x18\x03 \x01(\x04R\bsequence\"\x89\x01\n" +
+	"\x11ToolStreamMessage\x125\n" +
+	"\x05chunk\x18\x01 \x01(\v2\x1d.modelgateway.ToolOutputChunkH\x00R\x05chunk\x122\n" +
+	"\x05final\x18\x02 \x01(\v2\x1a.modelgateway.ToolResponseH\x00R\x05finalB\t\n" +
+	"\apayload\"6\n" +
+	"\fTerminalSize\x12\x12\n" +
+	"\x04cols\x18\x01 \x01(\rR\x04cols\x12\x12\n" +
+	"\x04rows\x18\x02 \x01(\rR\x04rows\"\xe4\x01\n" +
+	"\x18InteractiveClientMessage\x121\n" +
+	"\x05start\x18\x01 \x01(\v2\x19.modelgateway.ToolRequestH\x00R\x05start\x12\x16\n" +
+	"\x05stdin\x18\x02 \x01(\fH\x00R\x05stdin\x12<\n" +
+	"\acontrol\x18\x03 \x01(\x0e2 .modelgateway.InteractiveControlH\x00R\acontrol\x124\n" +
+	"\x06resize\x18\x04 \x01(\v2\x1a.modelgateway.TerminalSizeH\x00R\x06resizeB\t\n" +
+	"\apayload\"P\n" +
+	"\x12InteractiveStarted\x12\x1b\n" +
+	"\ttool_name\x18\x01 \x01(\tR\btoolName\x12\x1d\n" +
+	"\n" +
+	"session_id\x18\x02 \x01(\tR\tsessionId\"\xd0\x01\n" +
+	"\x18InteractiveServerMessage\x12<\n" +
+	"\astarted\x18\x01 \x01(\v2 .modelgateway.InteractiveStartedH\x00R\astarted\x125\n" +
+	"\x05chunk\x18\x02 \x01(\v2\x1d.modelgateway.ToolOutputChunkH\x00R\x05chunk\x124\n" +
+	"\x06exited\x18\x03 \x01(\v2\x1a.modelgateway.ToolResponseH\x00R\x06exitedB\t\n" +
+	"\apayload\"\xa2\a\n" +
+	"\fToolResponse\x12\x16\n" +
+	"\x06status\x18\x01 \x01(\tR\x06status\x12\x16\n" +
+	"\x06stdout\x18\x02 \x01(\tR\x06stdout\x12\x16\n" +
+	"\x06stderr\x18\x03 \x01(\tR\x06stderr\x12 \n" +
+	"\texit_code\x18\x04 \x01(\x05H\x00R\bexitCode\x88\x01\x01\x12&\n" +
+	"\fworkspace_id\x18\x05 \x01(\tH\x01R\vworkspaceId\x88\x01\x01\x124\n" +
+	"\tartifacts\x18\x06 \x03(\v2\x16.modelgateway.ArtifactR\tartifacts\x12\x1c\n" +
+	"\ttruncated\x18\a \x01(\bR\ttruncated\x12!\n" +
+	"\fstdout_bytes\x18\b \x01(\x04R\vstdoutBytes\x12!\n" +
+	"\fstderr_bytes\x18\t \x01(\x04R\vstderrBytes\x12\x1f\n" +
+	"\battempts\x18\n" +
+	" \x01(\rH\x02R\battempts\x88\x01\x01\x12\"\n" +
+	"\n" +
+	"trace_json\x18\v \x01(\tH\x03R\ttraceJson\x88\x01\x01\x126\n" +
+	"\x05usage\x18\f \x01(\v2\x1b.modelgateway.ResourceUsageH\x04R\x05usage\x88\x01\x01\x12E\n" +
+	"\x0fstdout_encoding\x18\r \x01(\x0e2\x1c.modelgateway.OutputEncodingR\x0estdoutEncoding\x12E\n" +
+	"\x0fstderr_encoding\x18\x0e \x01(\x0e2\x1c.modelgateway.OutputEncodingR\x0estderrEncoding\x12.\n" +
+	"\x10environment_json\x18\x0f \x01(\tH\x05R\x0fenvironmentJson\x88\x01\x01\x12J\n" +
+	"\x11policy_violations\x18\x10 \x03(\v2\x1d.modelgateway.PolicyViolationR\x10policyViolations\x127\n" +
+	"\n" +
+	"redactions\x18\x11 \x03(\v2\x17.modelgateway.RedactionR\n" +
+	"redactions\x12=\n" +
+	"\n" +
+	"quarantine\x18\x12 \x01(\v2\x18.modelgateway.QuarantineH\x06R\n" +
+	"quarantine\x88\x01\x01B\f\n" +
+	"\n" +
+	"_exit_codeB\x0f\n" +
+	"\r_workspace_idB\v\n" +
+	"\t_attemptsB\r\n" +
+	"\v_trace_jsonB\b\n" +
+	"\x06_usageB\x13\n" +
+	"\x11_environment_jsonB\r\n" +
+	"\v_quarantine\"]\n" +
+	"\n" +
+	"Quarantine\x12\x0e\n" +
+	"\x02id\x18\x01 \x01(\tR\x02id\x12\x18\n" +
+	"\areasons\x18\x02 \x03(\tR\areasons\x12%\n" +
+	"\x0eartifacts_held\x18\x03 \x01(\rR\rartifactsHeld\"q\n" +
+	"\tRedaction\x126\n" +
+	"\x06stream\x18\x01 \x01(\x0e2\x1e.modelgateway.OutputStreamTypeR\x06stream\x12\x16\n" +
+	"\x06filter\x18\x02 \x01(\tR\x06filter\x12\x14\n" +
+	"\x05count\x18\x03 \x01(\rR\x05count\"\x9d\x01\n" +
+	"\x0fPolicyViolation\x12\x12\n" +
+	"\x04rule\x18\x01 \x01(\tR\x04rule\x12\x18\n" +
+	"\amessage\x18\x02 \x01(\tR\amessage\x12\x16\n" +
+	"\x06action\x18\x03 \x01(\tR\x06action\x12\x12\n" +
+	"\x04line\x18\x04 \x01(\rR\x04line\x12\x16\n" +
+	"\x06column\x18\x05 \x01(\rR\x06column\x12\x18\n" +
+	"\asnippet\x18\x06 \x01(\tR\asnippet\"\xe4\x02\n" +
+	"\rResourceUsage\x12\x17\n" +
+	"\awall_ms\x18\x01 \x01(\x04R\x06wallMs\x12#\n" +
+	"\vuser_cpu_ms\x18\x02 \x01(\x04H\x00R\tuserCpuMs\x88\x01\x01\x12!\n" +
+	"\n" +
+	"sys_cpu_ms\x18\x03 \x01(\x04H\x01R\bsysCpuMs\x88\x01\x01\x12)\n" +
+	"\x0epeak_rss_bytes\x18\x04 \x01(\x04H\x02R\fpeakRssBytes\x88\x01\x01\x12(\n" +
+	"\rbytes_written\x18\x05 \x01(\x04H\x03R\fbytesWritten\x88\x01\x01\x12&\n" +
+	"\foutput_bytes\x18\x06 \x01(\x04H\x04R\voutputBytes\x88\x01\x01\x12\x17\n" +
+	"\x04cost\x18\a \x01(\x01H\x05R\x04cost\x88\x01\x01B\x0e\n" +
+	"\f_user_cpu_msB\r\n" +
+	"\v_sys_cpu_msB\x11\n" +
+	"\x0f_peak_rss_bytesB\x10\n" +
+	"\x0e_bytes_writtenB\x0f\n" +
+	"\r_output_bytesB\a\n" +
+	"\x05_cost\"^\n" +
+	"\bArtifact\x12\x1f\n" +
+	"\vartifact_id\x18\x01 \x01(\tR\n" +
+	"artifactId\x12\x12\n" +
+	"\x04path\x18\x02 \x01(\tR\x04path\x12\x1d\n" +
+	"\n" +
+	"size_bytes\x18\x03 \x01(\x04R\tsizeBytes*e\n" +
+	"\x10OutputStreamType\x12\x1d\n" +
+	"\x19OUTPUT_STREAM_UNSPECIFIED\x10\x00\x12\x18\n" +
+	"\x14OUTPUT_STREAM_STDOUT\x10\x01\x12\x18\n" +
+	"\x14OUTPUT_STREAM_STDERR\x10\x02*y\n" +
+	"\x12InteractiveControl\x12#\n" +
+	"\x1fINTERACTIVE_CONTROL_UNSPECIFIED\x10\x00\x12\x1b\n" +
+	"\x17INTERACTIVE_CONTROL_EOF\x10\x01\x12!\n" +
+	"\x1dINTERACTIVE_CONTROL_TERMINATE\x10\x02*d\n" +
+	"\x0eOutputEncoding\x12\x18\n" +
+	"\x14OUTPUT_ENCODING_UTF8\x10\x00\x12\x1a\n" +
+	"\x16OUTPUT_ENCODING_BASE64\x10\x01\x12\x1c\n" +
+	"\x18OUTPUT_ENCODING_ARTIFACT\x10\x022\xa4\x01\n" +
+	"\fModelGateway\x12@\n" +
+	"\aGetPlan\x12\x19.modelgateway.PlanRequest\x1a\x1a.modelgateway.PlanResponse\x12R\n" +
+	"\rGetRAGContext\x12\x1f.modelgateway.RAGContextRequest\x1a .modelgateway.RAGContextResponse2\x8c\x02\n" +
+	"\vToolService\x12D\n" +
+	"\vExecuteTool\x12\x19.modelgateway.ToolRequest\x1a\x1a.modelgateway.ToolResponse\x12Q\n" +
+	"\x11ExecuteToolStream\x12\x19.modelgateway.ToolRequest\x1a\x1f.modelgateway.ToolStreamMessage0\x01\x12d\n" +
+	"\x0eRunInteractive\x12&.modelgateway.InteractiveClientMessage\x1a&.modelgateway.InteractiveServerMessage(\x010\x01B&Z$backend-go-model-gateway/proto;protob\x06proto3"
+
+var (
+	file_proto_model_proto_rawDescOnce sync.Once
+	file_proto_model_proto_rawDescData []byte
+)
+
+func file_proto_model_proto_rawDescGZIP() []byte {
+	file_proto_model_proto_rawDescOnce.Do(func() {
+		file_proto_model_proto_rawDescData = protoimpl.X.CompressGZIP(unsafe.Slice(unsafe.StringData(file_proto_model_proto_rawDesc), len(file_proto_model_proto_rawDesc)))
+	})
+	return file_proto_model_proto_rawDescData
+}
+
+var file_proto_model_proto_enumTypes = make([]protoimpl.EnumInfo, 3)
+var file_proto_model_proto_msgTypes = make([]protoimpl.MessageInfo, 21)
+var file_proto_model_proto_goTypes = []any{
+	(OutputStreamType)(0),            // 0: modelgateway.OutputStreamType
+	(InteractiveControl)(0),          // 1: modelgateway.InteractiveControl
+	(OutputEncoding)(0),              // 2: modelgateway.OutputEncoding
+	(*Resource)(nil),                 // 3: modelgateway.Resource
+	(*PlanRequest)(nil),              // 4: modelgateway.PlanRequest
+	(*PlanResponse)(nil),             // 5: modelgateway.PlanResponse
+	(*RAGContextRequest)(nil),        // 6: modelgateway.RAGContextRequest
+	(*RAGMatch)(nil),                 // 7: modelgateway.RAGMatch
+	(*RAGContextResponse)(nil),       // 8: modelgateway.RAGContextResponse
+	(*ToolRequest)(nil),              // 9: modelgateway.ToolRequest
+	(*RetryOverride)(nil),            // 10: modelgateway.RetryOverride
+	(*ToolOutputChunk)(nil),          // 11: modelgateway.ToolOutputChunk
+	(*ToolStreamMessage)(nil),        // 12: modelgateway.ToolStreamMessage
+	(*TerminalSize)(nil),             // 13: modelgateway.TerminalSize
+	(*InteractiveClientMessage)(nil), // 14: modelgateway.InteractiveClientMessage
+	(*InteractiveStarted)(nil),       // 15: modelgateway.InteractiveStarted
+	(*InteractiveServerMessage)(nil), // 16: modelgateway.InteractiveServerMessage
+	(*ToolResponse)(nil),             // 17: modelgateway.ToolResponse
+	(*Quarantine)(nil),               // 18: modelgateway.Quarantine
+	(*Redaction)(nil),                // 19: modelgateway.Redaction
+	(*PolicyViolation)(nil),          // 20: modelgateway.PolicyViolation
+	(*ResourceUsage)(nil),            // 21: modelgateway.ResourceUsage
+	(*Artifact)(nil),                 // 22: modelgateway.Artifact
+	nil,                              // 23: modelgateway.ToolRequest.InputFilesEntry
+}
+var file_proto_model_proto_depIdxs = []int32{
+	3,  // 0: modelgateway.PlanRequest.resources:type_name -> modelgateway.Resource
+	7,  // 1: modelgateway.RAGContextResponse.matches:type_name -> modelgateway.RAGMatch
+	23, // 2: modelgateway.ToolRequest.input_files:type_name -> modelgateway.ToolRequest.InputFilesEntry
+	10, // 3: modelgateway.ToolRequest.retry:type_name -> modelgateway.RetryOverride
+	13, // 4: modelgateway.ToolRequest.pty:type_name -> modelgateway.TerminalSize
+	0,  // 5: modelgateway.ToolOutputChunk.stream:type_name -> modelgateway.OutputStreamType
+	11, // 6: modelgateway.ToolStreamMessage.chunk:type_name -> modelgateway.ToolOutputChunk
+	17, // 7: modelgateway.ToolStreamMessage.final:type_name -> modelgateway.ToolResponse
+	9,  // 8: modelgateway.InteractiveClientMessage.start:type_name -> modelgateway.ToolRequest
+	1,  // 9: modelgateway.InteractiveClientMessage.control:type_name -> modelgateway.InteractiveControl
+	13, // 10: modelgateway.InteractiveClientMessage.resize:type_name -> modelgateway.TerminalSize
+	15, // 11: modelgateway.InteractiveServerMessage.started:type_name -> modelgateway.InteractiveStarted
+	11, // 12: modelgateway.InteractiveServerMessage.chunk:type_name -> modelgateway.ToolOutputChunk
+	17, // 13: modelgateway.InteractiveServerMessage.exited:type_name -> modelgateway.ToolResponse
+	22, // 14: modelgateway.ToolResponse.artifacts:type_name -> modelgateway.Artifact
+	21, // 15: modelgateway.ToolResponse.usage:type_name -> modelgateway.ResourceUsage
+	2,  // 16: modelgateway.ToolResponse.stdout_encoding:type_name -> modelgateway.OutputEncoding
+	2,  // 17: modelgateway.ToolResponse.stderr_encoding:type_name -> modelgateway.OutputEncoding
+	20, // 18: modelgateway.ToolResponse.policy_violations:type_name -> modelgateway.PolicyViolation
+	19, // 19: modelgateway.ToolResponse.redactions:type_name -> modelgateway.Redaction
+	18, // 20: modelgateway.ToolResponse.quarantine:type_name -> modelgateway.Quarantine
+	0,  // 21: modelgateway.Redaction.stream:type_name -> modelgateway.OutputStreamType
+	4,  // 22: modelgateway.ModelGateway.GetPlan:input_type -> modelgateway.PlanRequest
+	6,  // 23: modelgateway.ModelGateway.GetRAGContext:input_type -> modelgateway.RAGContextRequest
+	9,  // 24: modelgateway.ToolService.ExecuteTool:input_type -> modelgateway.ToolRequest
+	9,  // 25: modelgateway.ToolService.ExecuteToolStream:input_type -> modelgateway.ToolRequest
+	14, // 26: modelgateway.ToolService.RunInteractive:input_type -> modelgateway.InteractiveClientMessage
+	5,  // 27: modelgateway.ModelGateway.GetPlan:output_type -> modelgateway.PlanResponse
+	8,  // 28: modelgateway.ModelGateway.GetRAGContext:output_type -> modelgateway.RAGContextResponse
+	17, // 29: modelgateway.ToolService.ExecuteTool:output_type -> modelgateway.ToolResponse
+	12, // 30: modelgateway.ToolService.ExecuteToolStream:output_type -> modelgateway.ToolStreamMessage
+	16, // 31: modelgateway.ToolService.RunInteractive:output_type -> modelgateway.InteractiveServerMessage
+	27, // [27:32] is the sub-list for method output_type
+	22, // [22:27] is the sub-list for method input_type
+	22, // [22:22] is the sub-list for extension type_name
+	22, // [22:22] is the sub-list for extension extendee
+	0,  // [0:22] is the sub-list for field type_name
+}
+
+func init() { file_proto_model_proto_init() }
+func file_proto_model_proto_init() {
+	if File_proto_model_proto != nil {
+		return
+	}
+	file_proto_model_proto_msgTypes[6].OneofWrappers = []any{}
+	file_proto_model_proto_msgTypes[7].OneofWrappers = []any{}
+	file_proto_model_proto_msgTypes[9].OneofWrappers = []any{
+		(*ToolStreamMessage_Chunk)(nil),
+		(*ToolStreamMessage_Final)(nil),
+	}
+	file_proto_model_proto_msgTypes[11].OneofWrappers = []any{
+		(*InteractiveClientMessage_Start)(nil),
+		(*InteractiveClientMessage_Stdin)(nil),
+		(*InteractiveClientMessage_Control)(nil),
+		(*InteractiveClientMessage_Resize)(nil),
+	}
+	file_proto_model_proto_msgTypes[13].OneofWrappers = []any{
+		(*InteractiveServerMessage_Started)(nil),
+		(*InteractiveServerMessage_Chunk)(nil),
+		(*InteractiveServerMessage_Exited)(nil),
 	}
+	file_proto_model_proto_msgTypes[14].OneofWrappers = []any{}
+	file_proto_model_proto_msgTypes[18].OneofWrappers = []any{}
 	type x struct{}
 	out := protoimpl.TypeBuilder{
 		File: protoimpl.DescBuilder{
 			GoPackagePath: reflect.TypeOf(x{}).PkgPath(),
 			RawDescriptor: unsafe.Slice(unsafe.StringData(file_proto_model_proto_rawDesc), len(file_proto_model_proto_rawDesc)),
-			NumEnums:      0,
-			NumMessages:   8,
+			NumEnums:      3,
+			NumMessages:   21,
 			NumExtensions: 0,
 			NumServices:   2,
 		},
 		GoTypes:           file_proto_model_proto_goTypes,
 		DependencyIndexes: file_proto_model_proto_depIdxs,
+		EnumInfos:         file_proto_model_proto_enumTypes,
 		MessageInfos:      file_proto_model_proto_msgTypes,
 	}.Build()
 	File_proto_model_proto = out.File
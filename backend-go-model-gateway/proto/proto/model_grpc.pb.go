@@ -159,7 +159,9 @@ var ModelGateway_ServiceDesc = grpc.ServiceDesc{
 }
 
 const (
-	ToolService_ExecuteTool_FullMethodName = "/modelgateway.ToolService/ExecuteTool"
+	ToolService_ExecuteTool_FullMethodName       = "/modelgateway.ToolService/ExecuteTool"
+	ToolService_ExecuteToolStream_FullMethodName = "/modelgateway.ToolService/ExecuteToolStream"
+	ToolService_RunInteractive_FullMethodName    = "/modelgateway.ToolService/RunInteractive"
 )
 
 // ToolServiceClient is the client API for ToolService service.
@@ -170,6 +172,14 @@ const (
 // over low-latency gRPC.
 type ToolServiceClient interface {
 	ExecuteTool(ctx context.Context, in *ToolRequest, opts ...grpc.CallOption) (*ToolResponse, error)
+	// Like ExecuteTool, but streams output while the tool runs. Every message
+	// but the last carries a chunk; the last carries the final ToolResponse.
+	ExecuteToolStream(ctx context.Context, in *ToolRequest, opts ...grpc.CallOption) (grpc.ServerStreamingClient[ToolStreamMessage], error)
+	// An interactive session. The first client message starts the tool; later
+	// ones feed its stdin or control it, and half-closing the call closes stdin.
+	// The server answers with `started`, output chunks as they arrive, and one
+	// final `exited`, then ends the call. Cancelling the call kills the tool.
+	RunInteractive(ctx context.Context, opts ...grpc.CallOption) (grpc.BidiStreamingClient[InteractiveClientMessage, InteractiveServerMessage], error)
 }
 
 type toolServiceClient struct {
@@ -190,6 +200,38 @@ func (c *toolServiceClient) ExecuteTool(ctx context.Context, in *ToolRequest, op
 	return out, nil
 }
 
+func (c *toolServiceClient) ExecuteToolStream(ctx context.Context, in *ToolRequest, opts ...grpc.CallOption) (grpc.ServerStreamingClient[ToolStreamMessage], error) {
+	cOpts := append([]grpc.CallOption{grpc.StaticMethod()}, opts...)
+	stream, err := c.cc.NewStream(ctx, &ToolService_ServiceDesc.Streams[0], ToolService_ExecuteToolStream_FullMethodName, cOpts...)
+	if err != nil {
+		return nil, err
+	}
+	x := &grpc.GenericClientStream[ToolRequest, ToolStreamMessage]{ClientStream: stream}
+	if err := x.ClientStream.SendMsg(in); err != nil {
+		return nil, err
+	}
+	if err := x.ClientStream.CloseSend(); err != nil {
+		return nil, err
+	}
+	return x, nil
+}
+
+// This type alias is provided for backwards compatibility with existing code that references the prior non-generic stream type by name.
+type ToolService_ExecuteToolStreamClient = grpc.ServerStreamingClient[ToolStreamMessage]
+
+func (c *toolServiceClient) RunInteractive(ctx context.Context, opts ...grpc.CallOption) (grpc.BidiStreamingClient[InteractiveClientMessage, InteractiveServerMessage], error) {
+	cOpts := append([]grpc.CallOption{grpc.StaticMethod()}, opts...)
+	stream, err := c.cc.NewStream(ctx, &ToolService_ServiceDesc.Streams[1], ToolService_RunInteractive_FullMethodName, cOpts...)
+	if err != nil {
+		return nil, err
+	}
+	x := &grpc.GenericClientStream[InteractiveClientMessage, InteractiveServerMessage]{ClientStream: stream}
+	return x, nil
+}
+
+// This type alias is provided for backwards compatibility with existing code that references the prior non-generic stream type by name.
+type ToolService_RunInteractiveClient = grpc.BidiStreamingClient[InteractiveClientMessage, InteractiveServerMessage]
+
 // ToolServiceServer is the server API for ToolService service.
 // All implementations must embed UnimplementedToolServiceServer
 // for forward compatibility.
@@ -198,6 +240,14 @@ func (c *toolServiceClient) ExecuteTool(ctx context.Context, in *ToolRequest, op
 // over low-latency gRPC.
 type ToolServiceServer interface {
 	ExecuteTool(context.Context, *ToolRequest) (*ToolResponse, error)
+	// Like ExecuteTool, but streams output while the tool runs. Every message
+	// but the last carries a chunk; the last carries the final ToolResponse.
+	ExecuteToolStream(*ToolRequest, grpc.ServerStreamingServer[ToolStreamMessage]) error
+	// An interactive session. The first client message starts the tool; later
+	// ones feed its stdin or control it, and half-closing the call closes stdin.
+	// The server answers with `started`, output chunks as they arrive, and one
+	// final `exited`, then ends the call. Cancelling the call kills the tool.
+	RunInteractive(grpc.BidiStreamingServer[InteractiveClientMessage, InteractiveServerMessage]) error
 	mustEmbedUnimplementedToolServiceServer()
 }
 
@@ -211,6 +261,12 @@ type UnimplementedToolServiceServer struct{}
 func (UnimplementedToolServiceServer) ExecuteTool(context.Context, *ToolRequest) (*ToolResponse, error) {
 	return nil, status.Error(codes.Unimplemented, "method ExecuteTool not implemented")
 }
+func (UnimplementedToolServiceServer) ExecuteToolStream(*ToolRequest, grpc.ServerStreamingServer[ToolStreamMessage]) error {
+	return status.Error(codes.Unimplemented, "method ExecuteToolStream not implemented")
+}
+func (UnimplementedToolServiceServer) RunInteractive(grpc.BidiStreamingServer[InteractiveClientMessage, InteractiveServerMessage]) error {
+	return status.Error(codes.Unimplemented, "method RunInteractive not implemented")
+}
 func (UnimplementedToolServiceServer) mustEmbedUnimplementedToolServiceServer() {}
 func (UnimplementedToolServiceServer) testEmbeddedByValue()                     {}
 
@@ -250,6 +306,24 @@ func _ToolService_ExecuteTool_Handler(srv interface{}, ctx context.Context, dec
 	return interceptor(ctx, in, info, handler)
 }
 
+func _ToolService_ExecuteToolStream_Handler(srv interface{}, stream grpc.ServerStream) error {
+	m := new(ToolRequest)
+	if err := stream.RecvMsg(m); err != nil {
+		return err
+	}
+	return srv.(ToolServiceServer).ExecuteToolStream(m, &grpc.GenericServerStream[ToolRequest, ToolStreamMessage]{ServerStream: stream})
+}
+
+// This type alias is provided for backwards compatibility with existing code that references the prior non-generic stream type by name.
+type ToolService_ExecuteToolStreamServer = grpc.ServerStreamingServer[ToolStreamMessage]
+
+func _ToolService_RunInteractive_Handler(srv interface{}, stream grpc.ServerStream) error {
+	return srv.(ToolServiceServer).RunInteractive(&grpc.GenericServerStream[InteractiveClientMessage, InteractiveServerMessage]{ServerStream: stream})
+}
+
+// This type alias is provided for backwards compatibility with existing code that references the prior non-generic stream type by name.
+type ToolService_RunInteractiveServer = grpc.BidiStreamingServer[InteractiveClientMessage, InteractiveServerMessage]
+
 // ToolService_ServiceDesc is the grpc.ServiceDesc for ToolService service.
 // It's only intended for direct use with grpc.RegisterService,
 // and not to be introspected or modified (even as a copy)
@@ -262,6 +336,18 @@ var ToolService_ServiceDesc = grpc.ServiceDesc{
 			Handler:    _ToolService_ExecuteTool_Handler,
 		},
 	},
-	Streams:  []grpc.StreamDesc{},
+	Streams: []grpc.StreamDesc{
+		{
+			StreamName:    "ExecuteToolStream",
+			Handler:       _ToolService_ExecuteToolStream_Handler,
+			ServerStreams: true,
+		},
+		{
+			StreamName:    "RunInteractive",
+			Handler:       _ToolService_RunInteractive_Handler,
+			ServerStreams: true,
+			ClientStreams: true,
+		},
+	},
 	Metadata: "proto/model.proto",
 }
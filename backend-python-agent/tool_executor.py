@@ -23,14 +23,18 @@ def execute_tool(tool_name: str, args: dict) -> Dict[str, Any]:
 
     payload = {"tool_name": tool_name, "args": args}
 
+    # Required once the sandbox has API keys configured.
+    api_key = os.environ.get("RUST_SANDBOX_API_KEY")
+    headers = {"Authorization": f"Bearer {api_key}"} if api_key else {}
+
     primary_url = _build_url(rust_sandbox_url, "/execute-tool")
     fallback_url = _build_url(rust_sandbox_url, "/api/v1/execute_tool")
 
     try:
-        resp = requests.post(primary_url, json=payload, timeout=timeout_seconds)
+        resp = requests.post(primary_url, json=payload, headers=headers, timeout=timeout_seconds)
         if resp.status_code == 404:
             # Backwards-compatible fallback for older Rust Sandbox route.
-            resp = requests.post(fallback_url, json=payload, timeout=timeout_seconds)
+            resp = requests.post(fallback_url, json=payload, headers=headers, timeout=timeout_seconds)
 
         resp.raise_for_status()
         return resp.json()
@@ -1,10 +1,16 @@
+use std::path::PathBuf;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-	// Rebuild if the proto changes.
+	// Rebuild if the protos change.
 	println!("cargo:rerun-if-changed=../backend-go-model-gateway/proto/model.proto");
 
+	// The descriptors back gRPC server reflection; tonic-health and
+	// tonic-reflection bring their own.
+	let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
 	tonic_build::configure()
 		.build_server(true)
-		.build_client(false)
+		.build_client(true)
+		.file_descriptor_set_path(out_dir.join("modelgateway_descriptor.bin"))
 		.compile_protos(
 			&["../backend-go-model-gateway/proto/model.proto"],
 			&["../backend-go-model-gateway/proto"],
@@ -12,4 +18,3 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 	Ok(())
 }
-
@@ -0,0 +1,14 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	println!("cargo:rerun-if-changed=../../backend-go-model-gateway/proto/model.proto");
+
+	// Only the client side; the sandbox builds its own server stubs.
+	tonic_build::configure()
+		.build_server(false)
+		.build_client(true)
+		.compile_protos(
+			&["../../backend-go-model-gateway/proto/model.proto"],
+			&["../../backend-go-model-gateway/proto"],
+		)?;
+
+	Ok(())
+}
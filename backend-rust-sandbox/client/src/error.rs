@@ -0,0 +1,55 @@
+use std::fmt;
+
+/// Why a call to the sandbox failed.
+#[derive(Debug)]
+pub enum Error {
+	/// The sandbox could not be reached, or the connection broke.
+	Transport(String),
+	/// The sandbox refused the request, with the `code` and `message` of its
+	/// error body when it sent one.
+	Api {
+		status: u16,
+		code: Option<String>,
+		message: String,
+	},
+	/// An answer that does not parse as what the endpoint returns.
+	Decode(String),
+	/// A gRPC call failed. Boxed, being much larger than the other variants.
+	Grpc(Box<tonic::Status>),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Transport(e) => write!(f, "transport error: {e}"),
+			Self::Api { status, code: Some(code), message } => write!(f, "{status} {code}: {message}"),
+			Self::Api { status, code: None, message } => write!(f, "{status}: {message}"),
+			Self::Decode(e) => write!(f, "unexpected answer: {e}"),
+			Self::Grpc(status) => write!(f, "{}: {}", status.code(), status.message()),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+	fn from(e: reqwest::Error) -> Self {
+		if e.is_decode() {
+			Self::Decode(e.to_string())
+		} else {
+			Self::Transport(e.to_string())
+		}
+	}
+}
+
+impl From<tonic::Status> for Error {
+	fn from(status: tonic::Status) -> Self {
+		Self::Grpc(Box::new(status))
+	}
+}
+
+impl From<tonic::transport::Error> for Error {
+	fn from(e: tonic::transport::Error) -> Self {
+		Self::Transport(e.to_string())
+	}
+}
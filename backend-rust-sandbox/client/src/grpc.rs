@@ -0,0 +1,88 @@
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::transport::Channel;
+use tonic::{Request, Streaming};
+
+use crate::error::Error;
+use crate::proto::tool_service_client::ToolServiceClient;
+use crate::proto::{self, ToolRequest, ToolResponse, ToolStreamMessage};
+use crate::wire::{RetryOverride, ToolExecutionRequest};
+
+/// The sandbox's gRPC `ToolService`. Cheap to clone; clones share the
+/// channel.
+#[derive(Debug, Clone)]
+pub struct GrpcClient {
+	inner: ToolServiceClient<Channel>,
+	api_key: Option<MetadataValue<Ascii>>,
+}
+
+impl GrpcClient {
+	/// Connect to the sandbox's gRPC port at `url`, e.g.
+	/// `http://localhost:50053`.
+	pub async fn connect(url: impl Into<String>) -> Result<Self, Error> {
+		Ok(Self {
+			inner: ToolServiceClient::connect(url.into()).await?,
+			api_key: None,
+		})
+	}
+
+	/// Send `key` as a bearer token: an API key or a JWT.
+	pub fn with_api_key(mut self, key: &str) -> Result<Self, Error> {
+		let value = format!("Bearer {key}")
+			.parse()
+			.map_err(|_| Error::Transport("the API key is not a valid metadata value".to_string()))?;
+		self.api_key = Some(value);
+		Ok(self)
+	}
+
+	fn request<T>(&self, message: T) -> Request<T> {
+		let mut request = Request::new(message);
+		if let Some(key) = &self.api_key {
+			request.metadata_mut().insert("authorization", key.clone());
+		}
+		request
+	}
+
+	/// Run a tool and wait for it; see [`ToolRequest`] for what can be set,
+	/// or build it from a REST request with `ToolRequest::from`.
+	pub async fn execute(&self, request: ToolRequest) -> Result<ToolResponse, Error> {
+		let response = self.inner.clone().execute_tool(self.request(request)).await?;
+		Ok(response.into_inner())
+	}
+
+	/// Run a tool and follow its output: chunks, then the final response.
+	/// Dropping the stream cancels the run.
+	pub async fn execute_stream(&self, request: ToolRequest) -> Result<Streaming<ToolStreamMessage>, Error> {
+		let response = self.inner.clone().execute_tool_stream(self.request(request)).await?;
+		Ok(response.into_inner())
+	}
+}
+
+impl From<&ToolExecutionRequest> for ToolRequest {
+	fn from(req: &ToolExecutionRequest) -> Self {
+		Self {
+			tool_name: req.tool_name.clone(),
+			args_json: req.args.to_string(),
+			timeout_ms: req.timeout_ms,
+			retain_workspace: req.retain_workspace,
+			artifacts: req.artifacts.clone(),
+			stdin: req.stdin.as_ref().map(|data| data.0.clone()),
+			input_files: req.input_files.iter().map(|(path, data)| (path.clone(), data.0.clone())).collect(),
+			retry: req.retry.as_ref().map(proto::RetryOverride::from),
+			priority: req.priority.map(|priority| priority.to_string()),
+			dry_run: req.dry_run,
+			record_trace: req.record_trace,
+			replay_trace_json: req.replay_trace.as_ref().and_then(|trace| serde_json::to_string(trace).ok()),
+			..Default::default()
+		}
+	}
+}
+
+impl From<&RetryOverride> for proto::RetryOverride {
+	fn from(retry: &RetryOverride) -> Self {
+		Self {
+			max_attempts: retry.max_attempts,
+			initial_backoff_ms: retry.initial_backoff_ms,
+			max_backoff_ms: retry.max_backoff_ms,
+		}
+	}
+}
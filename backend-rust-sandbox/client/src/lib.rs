@@ -0,0 +1,17 @@
+mod error;
+mod grpc;
+mod rest;
+pub mod wire;
+
+#[allow(clippy::enum_variant_names, clippy::large_enum_variant)]
+pub mod proto {
+	tonic::include_proto!("modelgateway");
+}
+
+pub use error::Error;
+pub use grpc::GrpcClient;
+pub use rest::{CancelOutcome, EventStream, SandboxClient};
+pub use wire::{
+	Artifact, Base64, Encoding, ExecutionTrace, HttpExchange, JobApproval, JobState, JobView, Priority, RetryOverride,
+	ScalingSignal, StreamEvent, ToolExecutionRequest, ToolExecutionResponse, ToolInfo,
+};
@@ -0,0 +1,197 @@
+use futures::Stream;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::pin::Pin;
+
+use crate::error::Error;
+use crate::wire::{JobView, ScalingSignal, StreamEvent, ToolExecutionRequest, ToolExecutionResponse, ToolInfo};
+
+/// The events of a streamed execution, ending with [`StreamEvent::Exited`].
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<StreamEvent, Error>> + Send>>;
+
+/// Outcome of [`SandboxClient::cancel_job`].
+#[derive(Debug)]
+pub enum CancelOutcome {
+	Cancelled(JobView),
+	/// The job had finished before it could be cancelled; it is as it ended.
+	AlreadyFinished(JobView),
+}
+
+/// The sandbox's REST API at one base URL. Cheap to clone; clones share
+/// their connection pool.
+#[derive(Debug, Clone)]
+pub struct SandboxClient {
+	http: reqwest::Client,
+	url: String,
+	api_key: Option<String>,
+}
+
+impl SandboxClient {
+	/// A client for the sandbox at `url`, e.g. `http://localhost:8001`.
+	pub fn new(url: impl Into<String>) -> Self {
+		Self {
+			http: reqwest::Client::new(),
+			url: url.into().trim_end_matches('/').to_string(),
+			api_key: None,
+		}
+	}
+
+	/// Send `key` as a bearer token: an API key or a JWT.
+	pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+		self.api_key = Some(key.into());
+		self
+	}
+
+	/// Make requests with `http`, for its timeouts, proxy or TLS settings.
+	pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+		self.http = http;
+		self
+	}
+
+	fn request(&self, method: Method, path: &str) -> RequestBuilder {
+		let request = self.http.request(method, format!("{}{path}", self.url));
+		match &self.api_key {
+			Some(key) => request.bearer_auth(key),
+			None => request,
+		}
+	}
+
+	/// Every tool, with its versions.
+	pub async fn list_tools(&self) -> Result<Vec<ToolInfo>, Error> {
+		#[derive(Deserialize)]
+		struct Tools {
+			tools: Vec<ToolInfo>,
+		}
+		let tools: Tools = json_of(self.request(Method::GET, "/api/v1/tools").send().await?).await?;
+		Ok(tools.tools)
+	}
+
+	/// One tool, with its changelog and versions.
+	pub async fn get_tool(&self, name: &str) -> Result<Value, Error> {
+		json_of(self.request(Method::GET, &format!("/api/v1/tools/{name}")).send().await?).await
+	}
+
+	/// Run a tool and wait for it. Refused runs (bad args, rate limits, full
+	/// queues) come back as responses too, with their status saying why.
+	pub async fn execute(&self, request: &ToolExecutionRequest) -> Result<ToolExecutionResponse, Error> {
+		let response = self
+			.request(Method::POST, "/api/v1/execute_tool")
+			.header("accept", "application/json")
+			.json(request)
+			.send()
+			.await?;
+		let status = response.status();
+		let body = response.bytes().await?;
+		match serde_json::from_slice(&body) {
+			Ok(answer) => Ok(answer),
+			Err(_) => Err(api_error(status, &body)),
+		}
+	}
+
+	/// Run a tool and follow its output as it is produced. Dropping the
+	/// stream cancels the run.
+	pub async fn execute_stream(&self, request: &ToolExecutionRequest) -> Result<EventStream, Error> {
+		let response = self
+			.request(Method::POST, "/api/v1/execute_tool/stream")
+			.header("accept", "application/x-ndjson")
+			.json(request)
+			.send()
+			.await?;
+		let mut response = checked(response).await?;
+		Ok(Box::pin(async_stream::stream! {
+			let mut pending = Vec::new();
+			loop {
+				let bytes = match response.chunk().await {
+					Ok(Some(bytes)) => bytes,
+					Ok(None) => break,
+					Err(e) => {
+						yield Err(Error::from(e));
+						break;
+					}
+				};
+				pending.extend_from_slice(&bytes);
+				while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+					let line: Vec<u8> = pending.drain(..=end).collect();
+					if line.iter().all(u8::is_ascii_whitespace) {
+						continue;
+					}
+					yield serde_json::from_slice::<StreamEvent>(&line).map_err(|e| Error::Decode(e.to_string()));
+				}
+			}
+		}))
+	}
+
+	/// Queue a tool run; poll [`get_job`](Self::get_job) for its outcome.
+	pub async fn submit_job(&self, request: &ToolExecutionRequest) -> Result<JobView, Error> {
+		json_of(self.request(Method::POST, "/api/v1/jobs").json(request).send().await?).await
+	}
+
+	pub async fn get_job(&self, job_id: &str) -> Result<JobView, Error> {
+		json_of(self.request(Method::GET, &format!("/api/v1/jobs/{job_id}")).send().await?).await
+	}
+
+	pub async fn cancel_job(&self, job_id: &str) -> Result<CancelOutcome, Error> {
+		let response = self.request(Method::DELETE, &format!("/api/v1/jobs/{job_id}")).send().await?;
+		if response.status() == StatusCode::CONFLICT {
+			return Ok(CancelOutcome::AlreadyFinished(response.json().await?));
+		}
+		Ok(CancelOutcome::Cancelled(json_of(response).await?))
+	}
+
+	/// Let a job that is pending approval run; it is queued from then on.
+	pub async fn approve_job(&self, job_id: &str) -> Result<JobView, Error> {
+		json_of(self.request(Method::POST, &format!("/api/v1/jobs/{job_id}/approve")).send().await?).await
+	}
+
+	/// Turn down a job that is pending approval, saying why if `reason` is set.
+	pub async fn reject_job(&self, job_id: &str, reason: Option<&str>) -> Result<JobView, Error> {
+		let body = json!({ "reason": reason });
+		let path = format!("/api/v1/jobs/{job_id}/reject");
+		json_of(self.request(Method::POST, &path).json(&body).send().await?).await
+	}
+
+	/// How much work the replica answering has, as autoscalers see it.
+	pub async fn scaling_signal(&self) -> Result<ScalingSignal, Error> {
+		json_of(self.request(Method::GET, "/api/v1/scaling").send().await?).await
+	}
+
+	/// The contents of a collected artifact.
+	pub async fn artifact(&self, artifact_id: &str) -> Result<Vec<u8>, Error> {
+		let response = self.request(Method::GET, &format!("/api/v1/artifacts/{artifact_id}")).send().await?;
+		Ok(checked(response).await?.bytes().await?.to_vec())
+	}
+}
+
+/// `response` if it succeeded, else the error it carries.
+async fn checked(response: Response) -> Result<Response, Error> {
+	let status = response.status();
+	if status.is_success() {
+		return Ok(response);
+	}
+	let body = response.bytes().await?;
+	Err(api_error(status, &body))
+}
+
+async fn json_of<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
+	Ok(checked(response).await?.json().await?)
+}
+
+/// The error a failed request's `body` describes: the sandbox's error
+/// envelope, or a refused execution's response.
+fn api_error(status: StatusCode, body: &[u8]) -> Error {
+	let body: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+	let code = body["code"].as_str().or_else(|| body["status"].as_str()).map(str::to_string);
+	let message = body["message"]
+		.as_str()
+		.or_else(|| body["result"]["error"].as_str())
+		.or_else(|| status.canonical_reason())
+		.unwrap_or_default()
+		.to_string();
+	Error::Api {
+		status: status.as_u16(),
+		code,
+		message,
+	}
+}
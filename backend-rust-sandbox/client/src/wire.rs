@@ -0,0 +1,386 @@
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// The body of `POST /api/v1/execute_tool`, its streaming variant and
+/// `POST /api/v1/jobs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolExecutionRequest {
+	pub tool_name: String,
+	pub args: Value,
+	/// Wall-clock limit for the execution; the server default applies when
+	/// omitted, and the server maximum caps it.
+	#[serde(default)]
+	pub timeout_ms: Option<u64>,
+	/// Keep the execution's workspace for a while instead of deleting it
+	/// when the run ends; the response then names it in `workspace_id`.
+	#[serde(default)]
+	pub retain_workspace: bool,
+	/// Workspace files to collect as artifacts (globs), on top of those the
+	/// manifest declares.
+	#[serde(default)]
+	pub artifacts: Vec<String>,
+	/// Bytes for the tool's stdin, which is empty without them.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub stdin: Option<Base64>,
+	/// Files written into the workspace before the run, by path relative to
+	/// it.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub input_files: BTreeMap<String, Base64>,
+	/// Send `args` to this running session (see `POST /api/v1/sessions`)
+	/// instead of starting the tool.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub session_id: Option<String>,
+	/// Changes to the tool's retry policy for this call, e.g.
+	/// `{"max_attempts": 1}` to run it once.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub retry: Option<RetryOverride>,
+	/// Where to `POST` a signed notice once the job finishes; only
+	/// accepted by `POST /api/v1/jobs`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub callback_url: Option<String>,
+	/// Run at this priority instead of the route's own (interactive for
+	/// single calls); a request can only lower it.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub priority: Option<Priority>,
+	/// Check the request and answer with what it would run, and how, instead
+	/// of running it; only accepted by `POST /api/v1/execute_tool`.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub dry_run: bool,
+	/// Record what the execution takes from outside (its clock, seed and
+	/// `http_request` answers) and return it as `result.trace`.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub record_trace: bool,
+	/// Serve the execution those effects from a trace recorded earlier
+	/// instead of the outside world.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub replay_trace: Option<ExecutionTrace>,
+}
+
+impl ToolExecutionRequest {
+	/// A call to `tool_name` with `args` and the server's defaults for
+	/// everything else.
+	pub fn new(tool_name: impl Into<String>, args: Value) -> Self {
+		Self {
+			tool_name: tool_name.into(),
+			args,
+			timeout_ms: None,
+			retain_workspace: false,
+			artifacts: Vec::new(),
+			stdin: None,
+			input_files: BTreeMap::new(),
+			session_id: None,
+			retry: None,
+			callback_url: None,
+			priority: None,
+			dry_run: false,
+			record_trace: false,
+			replay_trace: None,
+		}
+	}
+}
+
+/// What an execution came back with; `status` is `ok` or a failure class,
+/// and `result` holds the output, exit code and artifacts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolExecutionResponse {
+	pub status: String,
+	pub tool_name: String,
+	pub result: Value,
+}
+
+/// A file collected from an execution's workspace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+	/// SHA-256 of the contents; fetch it from `GET /api/v1/artifacts/{id}`.
+	pub artifact_id: String,
+	/// Path relative to the workspace.
+	pub path: String,
+	pub size_bytes: u64,
+}
+
+/// Binary request data, carried in JSON as a standard base64 string.
+#[derive(Debug, Clone, Default)]
+pub struct Base64(pub Vec<u8>);
+
+impl Serialize for Base64 {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(&self.0))
+	}
+}
+
+impl<'de> Deserialize<'de> for Base64 {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let encoded = String::deserialize(deserializer)?;
+		base64::engine::general_purpose::STANDARD
+			.decode(encoded.trim())
+			.map(Base64)
+			.map_err(|e| serde::de::Error::custom(format!("invalid base64: {e}")))
+	}
+}
+
+/// How a piece of tool output is carried in a JSON string. Output that is
+/// not valid UTF-8 is sent as base64 rather than mangled; results and chunks
+/// leave the encoding out when it is plain text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+	#[default]
+	Utf8,
+	Base64,
+	/// Diverted into the artifact store: the string is the artifact's id.
+	Artifact,
+}
+
+impl Encoding {
+	pub fn is_utf8(&self) -> bool {
+		*self == Self::Utf8
+	}
+
+	/// The bytes `text` carries, or, for an artifact, its id's.
+	pub fn decode(self, text: &str) -> Result<Vec<u8>, base64::DecodeError> {
+		match self {
+			Self::Base64 => base64::engine::general_purpose::STANDARD.decode(text),
+			Self::Utf8 | Self::Artifact => Ok(text.as_bytes().to_vec()),
+		}
+	}
+}
+
+/// A request's `retry`: the attempts and waits of the tool's policy (or the
+/// default policy, for tools without one) changed for one call. Which
+/// failures are transient stays the manifest's call.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryOverride {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_attempts: Option<u32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub initial_backoff_ms: Option<u64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_backoff_ms: Option<u64>,
+}
+
+/// Which waiting executions get a free slot first. Executions of one class
+/// take slots in the order they ask for them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+	/// Schedules and async jobs.
+	Background,
+	/// Batches and pipelines.
+	Batch,
+	/// A caller waiting on the response, e.g. an agent's tool call.
+	#[default]
+	Interactive,
+}
+
+impl Priority {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Background => "background",
+			Self::Batch => "batch",
+			Self::Interactive => "interactive",
+		}
+	}
+}
+
+impl fmt::Display for Priority {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl FromStr for Priority {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"background" => Ok(Self::Background),
+			"batch" => Ok(Self::Batch),
+			"interactive" => Ok(Self::Interactive),
+			_ => Err(format!("unknown priority {s:?} (interactive, batch or background)")),
+		}
+	}
+}
+
+/// What one execution took from outside: the time it started at, the seed
+/// of its randomness, and what each `http_request` call returned, in order.
+/// Recording one and handing it back makes the execution reproducible.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExecutionTrace {
+	/// The wall-clock time the execution starts at, in ms since the epoch.
+	pub time_ms: u64,
+	pub seed: u64,
+	#[serde(default)]
+	pub http: Vec<HttpExchange>,
+}
+
+/// One `http_request` call and the tool's answer to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpExchange {
+	pub method: String,
+	pub url: String,
+	/// SHA-256 of the request body, when it had one. Request headers are not
+	/// recorded: they carry credentials.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub body_sha256: Option<String>,
+	pub status: String,
+	#[serde(default)]
+	pub stdout: String,
+	#[serde(default)]
+	pub stderr: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+	Queued,
+	Running,
+	/// The tool ran to the end; its own outcome is in `response.status`.
+	Completed,
+	Cancelled,
+	/// Stopped to make room for a higher-priority execution; the partial
+	/// result is in `response`.
+	Preempted,
+	/// Waits for an approver before it is queued; see `approval`.
+	#[serde(rename = "pending_approval")]
+	PendingApproval,
+	/// An approver turned it down; it never ran.
+	Rejected,
+	/// Nobody approved it in time; it never ran.
+	Expired,
+}
+
+impl JobState {
+	pub fn is_finished(self) -> bool {
+		matches!(
+			self,
+			Self::Completed | Self::Cancelled | Self::Preempted | Self::Rejected | Self::Expired
+		)
+	}
+
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Queued => "queued",
+			Self::Running => "running",
+			Self::Completed => "completed",
+			Self::Cancelled => "cancelled",
+			Self::Preempted => "preempted",
+			Self::PendingApproval => "pending_approval",
+			Self::Rejected => "rejected",
+			Self::Expired => "expired",
+		}
+	}
+}
+
+/// Snapshot of a job as returned by the jobs API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobView {
+	pub job_id: String,
+	pub tool_name: String,
+	pub state: JobState,
+	pub created_at_ms: u64,
+	pub started_at_ms: Option<u64>,
+	pub finished_at_ms: Option<u64>,
+	pub response: Option<ToolExecutionResponse>,
+	/// The execution this job replays.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub replay_of: Option<String>,
+	/// Set on jobs of tools that run only once someone approves them.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub approval: Option<JobApproval>,
+	/// The submitter's subject; `None` when authentication is disabled.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub requested_by: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tenant: Option<String>,
+}
+
+/// Who asked for a job that needs approval, and who decided on it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobApproval {
+	/// The submitter's subject; `None` when authentication is disabled.
+	#[serde(default)]
+	pub requested_by: Option<String>,
+	/// When the job expires unless it is approved first.
+	pub expires_at_ms: u64,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub decided_by: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub decided_at_ms: Option<u64>,
+	/// Why it was rejected, if the approver said.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub reason: Option<String>,
+}
+
+/// One entry of `GET /api/v1/tools`; what else the manifest says (backend,
+/// args schema, limits, network, retry, versions) is kept in `details`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInfo {
+	pub name: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub version: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub deprecated: Option<String>,
+	pub description: String,
+	#[serde(flatten)]
+	pub details: serde_json::Map<String, Value>,
+}
+
+/// `GET /api/v1/scaling`: how much work one replica has, for an external
+/// autoscaler (KEDA's `metrics-api` scaler, or an HPA on an external metric)
+/// to add replicas by queued work rather than by CPU.
+///
+/// The contract: every figure but `shared_queue_depth` is the answering
+/// replica's own, so a scaler that polls through a Service samples one
+/// replica per poll, and summing across pods takes the Prometheus gauges
+/// instead. Scale out while `pending_queue_depth` per replica is above the
+/// backlog you accept, or `avg_wait_ms` above the wait you accept; scale in
+/// once both stay at 0 and `utilization` is well under 1. A replica that is
+/// `draining` takes no new work and should not be counted as capacity.
+/// Fields are only ever added.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScalingSignal {
+	/// Executions admitted but not yet running: queued requests and jobs,
+	/// those waiting for GPUs included. Never above `max_queued`; more are
+	/// turned away with `429`.
+	pub pending_queue_depth: u64,
+	/// Mean time executions waited for a slot over the last `window_secs`,
+	/// those still waiting counted with their wait so far; 0 without any.
+	pub avg_wait_ms: u64,
+	pub running: u64,
+	pub max_concurrent: u64,
+	pub max_queued: u64,
+	/// `(running + pending_queue_depth) / max_concurrent`: above 1, there is
+	/// more work than slots.
+	pub utilization: f64,
+	pub draining: bool,
+	pub window_secs: u64,
+	/// Executions waiting in the work queue for any worker, when there is
+	/// one; the same on every replica, so not to be summed.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub shared_queue_depth: Option<u64>,
+}
+
+/// One event of `POST /api/v1/execute_tool/stream`, as the NDJSON framing
+/// carries it: `{"event": ..., "data": ...}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "lowercase")]
+pub enum StreamEvent {
+	Started { tool_name: String },
+	/// Output as the tool writes it; `seq` is monotonic across both streams.
+	Chunk {
+		seq: u64,
+		stream: String,
+		data: String,
+		#[serde(default, skip_serializing_if = "Encoding::is_utf8")]
+		encoding: Encoding,
+	},
+	/// The same body `POST /api/v1/execute_tool` would have returned.
+	Exited(ToolExecutionResponse),
+}
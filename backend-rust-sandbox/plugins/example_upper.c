@@ -0,0 +1,60 @@
+/*
+ * An example plugin with one tool, `upper`, that upper-cases its `text`
+ * arg (naively: the value is not unescaped). Build it with:
+ *
+ *   cc -shared -fPIC -O2 -o plugins/libexample_upper.so plugins/example_upper.c
+ */
+#include <ctype.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "pagi_plugin.h"
+
+static char *run_upper(const char *args_json, uint64_t timeout_ms) {
+	(void)timeout_ms;
+	const char *key = strstr(args_json, "\"text\":\"");
+	const char *start = key ? key + strlen("\"text\":\"") : "";
+	const char *end = key ? strchr(start, '"') : start;
+	size_t len = end ? (size_t)(end - start) : 0;
+
+	char *result = malloc(len + 64);
+	if (result == NULL) {
+		return NULL;
+	}
+	int head = sprintf(result, "{\"status\":\"ok\",\"stdout\":\"");
+	for (size_t i = 0; i < len; i++) {
+		result[head + i] = (char)toupper((unsigned char)start[i]);
+	}
+	strcpy(result + head + len, "\"}");
+	return result;
+}
+
+static void free_result(char *result) {
+	free(result);
+}
+
+static const pagi_tool_v1 tools[] = {
+	{
+		.name = "upper",
+		.description = "Upper-case a string (example plugin).",
+		.args_schema_json = "{\"type\":\"object\",\"properties\":{\"text\":{\"type\":\"string\"}},\"required\":[\"text\"]}",
+		.run = run_upper,
+	},
+};
+
+static const pagi_plugin_v1 plugin = {
+	.abi_version = PAGI_PLUGIN_ABI_V1,
+	.name = "example-upper",
+	.version = "1.0.0",
+	.tool_count = sizeof(tools) / sizeof(tools[0]),
+	.tools = tools,
+	.free_result = free_result,
+};
+
+const void *pagi_plugin_init(uint32_t min_abi, uint32_t max_abi) {
+	if (min_abi > PAGI_PLUGIN_ABI_V1 || max_abi < PAGI_PLUGIN_ABI_V1) {
+		return NULL;
+	}
+	return &plugin;
+}
@@ -0,0 +1,56 @@
+/*
+ * The C ABI of sandbox tool plugins.
+ *
+ * A plugin is a shared library in `executor.plugin_dir` exporting
+ * `pagi_plugin_init`. Each library is loaded into a host process of its
+ * own, started by the sandbox with an empty environment; the host calls
+ * `pagi_plugin_init` once with the range of ABI versions it speaks, and the
+ * plugin returns a descriptor for one of them, or NULL if it speaks none.
+ * The descriptor and everything it points to must stay valid until the
+ * process exits. Anything the plugin writes to stdout or stderr ends up in
+ * the sandbox's stderr.
+ *
+ * `run` may be called from several threads at once. It gets the args as a
+ * JSON object that passed the tool's schema, and the execution's timeout,
+ * which it should honour: the sandbox answers a late call with `timed_out`
+ * but cannot stop it. It returns a NUL-terminated JSON object that the
+ * sandbox passes to `free_result`:
+ *
+ *   {"status": "ok", "stdout": "...", "stderr": "...", "exit_code": null}
+ *
+ * Only `status` is required; anything but "ok" is a failure. NULL is
+ * reported as `plugin_error`.
+ *
+ * A crash in the plugin - a segfault, an abort, a Rust panic reaching the
+ * `extern "C"` boundary - ends only its host: the calls in flight fail with
+ * `plugin_crashed`, and the next call starts a new host, which must declare
+ * the same tools.
+ */
+#ifndef PAGI_PLUGIN_H
+#define PAGI_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define PAGI_PLUGIN_ABI_V1 1
+
+typedef struct {
+	const char *name;             /* [A-Za-z0-9_-]+, unique across the sandbox */
+	const char *description;
+	const char *args_schema_json; /* a JSON Schema object; NULL accepts any object */
+	char *(*run)(const char *args_json, uint64_t timeout_ms);
+} pagi_tool_v1;
+
+typedef struct {
+	uint32_t abi_version;         /* PAGI_PLUGIN_ABI_V1 */
+	const char *name;
+	const char *version;
+	size_t tool_count;
+	const pagi_tool_v1 *tools;
+	void (*free_result)(char *result);
+} pagi_plugin_v1;
+
+/* Return a descriptor for an ABI version in [min_abi, max_abi], or NULL. */
+const void *pagi_plugin_init(uint32_t min_abi, uint32_t max_abi);
+
+#endif
@@ -0,0 +1,509 @@
+use futures::TryStreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path as StorePath;
+use object_store::{GetResult, ObjectStore, PutPayload};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncReadExt;
+use tracing::{info, warn};
+
+use crate::audit::Caller;
+
+pub use pagi_sandbox_client::wire::Artifact;
+
+const DEFAULT_DIR: &str = "sandbox_artifacts";
+const DEFAULT_S3_PREFIX: &str = "artifacts";
+const DEFAULT_TTL_SECS: u64 = 24 * 3600;
+const DEFAULT_MAX_FILE_BYTES: u64 = 100 * 1024 * 1024;
+const DEFAULT_MAX_TOTAL_BYTES: u64 = 512 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 100;
+/// Expired artifacts are looked for at most this often.
+const GC_INTERVAL: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactBackend {
+	/// A directory on the sandbox host.
+	Local,
+	/// An S3 (or S3-compatible) bucket; credentials come from the usual
+	/// `AWS_*` variables.
+	S3,
+}
+
+impl std::str::FromStr for ArtifactBackend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"local" => Ok(Self::Local),
+			"s3" => Ok(Self::S3),
+			_ => Err(format!("expected local or s3 (got {s:?})")),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Config {
+	pub bucket: Option<String>,
+	pub region: Option<String>,
+	/// For S3-compatible stores such as MinIO; AWS when unset.
+	pub endpoint: Option<String>,
+	/// Key prefix artifacts are stored under.
+	pub prefix: String,
+}
+
+impl Default for S3Config {
+	fn default() -> Self {
+		Self {
+			bucket: None,
+			region: None,
+			endpoint: None,
+			prefix: DEFAULT_S3_PREFIX.to_string(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArtifactsConfig {
+	pub backend: ArtifactBackend,
+	/// `local` backend: where artifacts are stored.
+	pub dir: PathBuf,
+	pub s3: S3Config,
+	/// How long an artifact is kept after it was last produced.
+	pub ttl_secs: u64,
+	/// Larger files are skipped.
+	pub max_file_bytes: u64,
+	/// Per execution; files past the limit are skipped.
+	pub max_total_bytes: u64,
+	pub max_files: usize,
+	/// Stdout or stderr that is not text, and larger than this, is stored
+	/// as an artifact instead of returned in base64; unset keeps it inline.
+	pub binary_output_bytes: Option<u64>,
+}
+
+impl Default for ArtifactsConfig {
+	fn default() -> Self {
+		Self {
+			backend: ArtifactBackend::Local,
+			dir: DEFAULT_DIR.into(),
+			s3: S3Config::default(),
+			ttl_secs: DEFAULT_TTL_SECS,
+			max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+			max_total_bytes: DEFAULT_MAX_TOTAL_BYTES,
+			max_files: DEFAULT_MAX_FILES,
+			binary_output_bytes: None,
+		}
+	}
+}
+
+impl ArtifactsConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if self.backend == ArtifactBackend::S3 && self.s3.bucket.is_none() {
+			errors.push("executor.artifacts.s3.bucket is required by the s3 backend".to_string());
+		}
+		if self.ttl_secs == 0 {
+			errors.push("executor.artifacts.ttl_secs must be greater than 0".to_string());
+		}
+		errors
+	}
+}
+
+/// What a collection produced: the stored artifacts, plus a note for each
+/// matching file that was left out.
+#[derive(Debug, Default)]
+pub struct Collected {
+	pub artifacts: Vec<Artifact>,
+	pub skipped: Vec<String>,
+}
+
+/// Whom an artifact was stored for: the subject and tenant of the caller of
+/// the execution that produced it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactOwner {
+	pub subject: Option<String>,
+	pub tenant: Option<String>,
+}
+
+impl ArtifactOwner {
+	pub fn of(caller: &Caller) -> Self {
+		Self {
+			subject: caller.subject.clone(),
+			tenant: caller.tenant.clone(),
+		}
+	}
+}
+
+/// Whether `pattern` is a glob that stays within the workspace.
+pub fn is_valid_artifact_pattern(pattern: &str) -> bool {
+	let path = Path::new(pattern);
+	!pattern.is_empty()
+		&& glob::Pattern::new(pattern).is_ok()
+		&& path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Content-addressed storage for the files tools produce.
+///
+/// Identical contents share one object; storing it again restarts its TTL.
+/// Each caller it is stored for is recorded beside it, under `owners/`, with
+/// the same TTL. Objects older than the TTL are deleted in the background.
+pub struct ArtifactStore {
+	store: Arc<dyn ObjectStore>,
+	prefix: Option<String>,
+	ttl: Duration,
+	max_file_bytes: u64,
+	max_total_bytes: u64,
+	max_files: usize,
+	binary_output_bytes: Option<u64>,
+	last_gc: Mutex<Instant>,
+}
+
+impl ArtifactStore {
+	pub fn new(config: &ArtifactsConfig) -> Result<Self, String> {
+		let (store, prefix): (Arc<dyn ObjectStore>, _) = match config.backend {
+			ArtifactBackend::Local => {
+				std::fs::create_dir_all(&config.dir).map_err(|e| format!("{}: {e}", config.dir.display()))?;
+				let store = LocalFileSystem::new_with_prefix(&config.dir)
+					.map_err(|e| format!("{}: {e}", config.dir.display()))?;
+				info!(backend = "local", dir = %config.dir.display(), message = "Artifact store ready");
+				(Arc::new(store), None)
+			}
+			ArtifactBackend::S3 => {
+				let s3 = &config.s3;
+				let bucket = s3.bucket.as_deref().expect("validated: s3 backend has a bucket");
+				let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+				if let Some(region) = &s3.region {
+					builder = builder.with_region(region);
+				}
+				if let Some(endpoint) = &s3.endpoint {
+					builder = builder
+						.with_endpoint(endpoint)
+						.with_allow_http(endpoint.starts_with("http://"));
+				}
+				let store = builder.build().map_err(|e| format!("executor.artifacts.s3: {e}"))?;
+				info!(backend = "s3", bucket = bucket, prefix = %s3.prefix, message = "Artifact store ready");
+				let prefix = Some(s3.prefix.trim_matches('/').to_string()).filter(|p| !p.is_empty());
+				(Arc::new(store), prefix)
+			}
+		};
+		Ok(Self {
+			store,
+			prefix,
+			ttl: Duration::from_secs(config.ttl_secs),
+			max_file_bytes: config.max_file_bytes,
+			max_total_bytes: config.max_total_bytes,
+			max_files: config.max_files,
+			binary_output_bytes: config.binary_output_bytes,
+			// Due right away, so leftovers of a previous run go soon.
+			last_gc: Mutex::new(Instant::now() - GC_INTERVAL),
+		})
+	}
+
+	/// Store the regular files in `work_dir` matching `patterns` for
+	/// `caller`. Symlinks and anything resolving outside the workspace are
+	/// never followed.
+	pub async fn collect(&self, work_dir: &Path, patterns: &[&str], caller: &Caller) -> Collected {
+		self.maybe_gc();
+		let mut collected = Collected::default();
+		let Ok(root) = work_dir.canonicalize() else {
+			return collected;
+		};
+		let mut total = 0u64;
+		for path in matching_files(&root, patterns, &mut collected.skipped) {
+			let relative = path.strip_prefix(&root).unwrap_or(&path).to_string_lossy().to_string();
+			if collected.artifacts.len() == self.max_files {
+				collected.skipped.push(format!("{relative}: more than {} artifacts", self.max_files));
+				continue;
+			}
+			let size = match tokio::fs::metadata(&path).await {
+				Ok(meta) => meta.len(),
+				Err(e) => {
+					collected.skipped.push(format!("{relative}: {e}"));
+					continue;
+				}
+			};
+			if size > self.max_file_bytes {
+				collected.skipped.push(format!("{relative}: larger than {} bytes", self.max_file_bytes));
+				continue;
+			}
+			if total + size > self.max_total_bytes {
+				collected.skipped.push(format!("{relative}: artifacts exceed {} bytes in total", self.max_total_bytes));
+				continue;
+			}
+			match self.store_file(&path, caller).await {
+				Ok(artifact_id) => {
+					total += size;
+					collected.artifacts.push(Artifact {
+						artifact_id,
+						path: relative,
+						size_bytes: size,
+					});
+				}
+				Err(e) => {
+					warn!(path = %relative, error = %e, message = "Failed to store artifact");
+					collected.skipped.push(format!("{relative}: could not be stored"));
+				}
+			}
+		}
+		collected
+	}
+
+	async fn store_file(&self, path: &Path, caller: &Caller) -> Result<String, String> {
+		// O_NOFOLLOW: the file must not have become a symlink since it was
+		// matched.
+		let mut file = tokio::fs::OpenOptions::new()
+			.read(true)
+			.custom_flags(libc::O_NOFOLLOW)
+			.open(path)
+			.await
+			.map_err(|e| e.to_string())?;
+		let mut contents = Vec::new();
+		file.read_to_end(&mut contents).await.map_err(|e| e.to_string())?;
+		self.store_bytes(contents, caller).await
+	}
+
+	/// Whether binary output of `len` bytes is stored rather than returned.
+	pub fn diverts_output(&self, len: usize) -> bool {
+		self.binary_output_bytes
+			.is_some_and(|threshold| len as u64 > threshold && len as u64 <= self.max_file_bytes)
+	}
+
+	/// Store `contents` for `caller`; the artifact id.
+	pub async fn store_bytes(&self, contents: Vec<u8>, caller: &Caller) -> Result<String, String> {
+		let id = self.put(contents).await?;
+		self.record_owner(&id, &ArtifactOwner::of(caller)).await?;
+		Ok(id)
+	}
+
+	async fn put(&self, contents: Vec<u8>) -> Result<String, String> {
+		self.maybe_gc();
+		let id = hex_digest(&contents);
+		self.store
+			.put(&self.location(&id), PutPayload::from(contents))
+			.await
+			.map_err(|e| e.to_string())?;
+		Ok(id)
+	}
+
+	/// Note that `artifact_id` was stored for `owner`, or that it was again.
+	/// Nothing is noted for anonymous callers.
+	async fn record_owner(&self, artifact_id: &str, owner: &ArtifactOwner) -> Result<(), String> {
+		if owner.subject.is_none() && owner.tenant.is_none() {
+			return Ok(());
+		}
+		let marker = serde_json::to_vec(owner).map_err(|e| e.to_string())?;
+		let location = self.owners_location(artifact_id).child(hex_digest(&marker));
+		self.store
+			.put(&location, PutPayload::from(marker))
+			.await
+			.map_err(|e| e.to_string())
+			.map(drop)
+	}
+
+	/// Everyone the artifact was stored for within its TTL.
+	pub async fn owners(&self, artifact_id: &str) -> Result<Vec<ArtifactOwner>, String> {
+		if !is_artifact_id(artifact_id) {
+			return Ok(Vec::new());
+		}
+		let markers: Vec<_> = self
+			.store
+			.list(Some(&self.owners_location(artifact_id)))
+			.try_filter(|meta| std::future::ready(!self.expired(meta.last_modified.into())))
+			.try_collect()
+			.await
+			.map_err(|e| e.to_string())?;
+		let mut owners = Vec::with_capacity(markers.len());
+		for marker in markers {
+			let contents = match self.store.get(&marker.location).await {
+				Ok(result) => result.bytes().await.map_err(|e| e.to_string())?,
+				Err(object_store::Error::NotFound { .. }) => continue,
+				Err(e) => return Err(e.to_string()),
+			};
+			match serde_json::from_slice(&contents) {
+				Ok(owner) => owners.push(owner),
+				Err(e) => warn!(marker = %marker.location, error = %e, message = "Ignoring unreadable artifact owner"),
+			}
+		}
+		Ok(owners)
+	}
+
+	/// The artifact's contents, unless it does not exist or has expired.
+	pub async fn get(&self, artifact_id: &str) -> Result<Option<GetResult>, String> {
+		if !is_artifact_id(artifact_id) {
+			return Ok(None);
+		}
+		match self.store.get(&self.location(artifact_id)).await {
+			Ok(result) if self.expired(result.meta.last_modified.into()) => Ok(None),
+			Ok(result) => Ok(Some(result)),
+			Err(object_store::Error::NotFound { .. }) => Ok(None),
+			Err(e) => Err(e.to_string()),
+		}
+	}
+
+	/// Store the artifact `artifact_id` of `from` here too, for the same
+	/// owners.
+	pub async fn copy_from(&self, from: &ArtifactStore, artifact_id: &str) -> Result<(), String> {
+		let contents = from
+			.get(artifact_id)
+			.await?
+			.ok_or_else(|| format!("artifact {artifact_id} has expired"))?
+			.bytes()
+			.await
+			.map_err(|e| e.to_string())?;
+		self.put(contents.to_vec()).await?;
+		for owner in from.owners(artifact_id).await? {
+			self.record_owner(artifact_id, &owner).await?;
+		}
+		Ok(())
+	}
+
+	pub async fn delete(&self, artifact_id: &str) -> Result<(), String> {
+		match self.store.delete(&self.location(artifact_id)).await {
+			Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+			Err(e) => Err(e.to_string()),
+		}
+	}
+
+	/// `ab/abcd...`: a level of fan-out keeps local directories small.
+	fn location(&self, artifact_id: &str) -> StorePath {
+		let key = format!("{}/{artifact_id}", &artifact_id[..2]);
+		match &self.prefix {
+			Some(prefix) => StorePath::from(format!("{prefix}/{key}")),
+			None => StorePath::from(key),
+		}
+	}
+
+	/// `owners/abcd...`, beside the fan-out directories.
+	fn owners_location(&self, artifact_id: &str) -> StorePath {
+		let key = format!("owners/{artifact_id}");
+		match &self.prefix {
+			Some(prefix) => StorePath::from(format!("{prefix}/{key}")),
+			None => StorePath::from(key),
+		}
+	}
+
+	fn expired(&self, modified: SystemTime) -> bool {
+		SystemTime::now().duration_since(modified).unwrap_or_default() >= self.ttl
+	}
+
+	fn maybe_gc(&self) {
+		{
+			let mut last = self.last_gc.lock().unwrap();
+			if last.elapsed() < GC_INTERVAL {
+				return;
+			}
+			*last = Instant::now();
+		}
+		let store = self.store.clone();
+		let prefix = self.prefix.clone().map(StorePath::from);
+		let ttl = self.ttl;
+		tokio::spawn(async move {
+			let cutoff = SystemTime::now() - ttl;
+			let expired = store
+				.list(prefix.as_ref())
+				.try_filter(|meta| std::future::ready(SystemTime::from(meta.last_modified) < cutoff))
+				.map_ok(|meta| meta.location)
+				.try_collect::<Vec<_>>()
+				.await;
+			match expired {
+				Ok(locations) => {
+					for location in locations {
+						if let Err(e) = store.delete(&location).await {
+							warn!(artifact = %location, error = %e, message = "Failed to delete expired artifact");
+						}
+					}
+				}
+				Err(e) => warn!(error = %e, message = "Failed to list artifacts for expiry"),
+			}
+		});
+	}
+}
+
+/// Regular files under `root` matching any of `patterns`, each once; what
+/// matched but cannot be collected is noted in `skipped`.
+fn is_artifact_id(artifact_id: &str) -> bool {
+	artifact_id.len() == 64 && artifact_id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn hex_digest(contents: &[u8]) -> String {
+	Sha256::digest(contents).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn matching_files(root: &Path, patterns: &[&str], skipped: &mut Vec<String>) -> Vec<PathBuf> {
+	let escaped_root = glob::Pattern::escape(&root.to_string_lossy());
+	let mut files = Vec::new();
+	let mut seen = HashSet::new();
+	for pattern in patterns {
+		let Ok(paths) = glob::glob(&format!("{escaped_root}/{pattern}")) else {
+			continue;
+		};
+		for path in paths.flatten() {
+			if !seen.insert(path.clone()) {
+				continue;
+			}
+			// No symlinks, at the leaf or in between: the tool controls the
+			// workspace, and a link could point anywhere on the host.
+			let is_file = std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_file());
+			let inside = path.canonicalize().is_ok_and(|real| real == path && real.starts_with(root));
+			if !is_file || !inside {
+				if !std::fs::metadata(&path).is_ok_and(|m| m.is_dir()) {
+					let relative = path.strip_prefix(root).unwrap_or(&path);
+					skipped.push(format!("{}: not a regular file in the workspace", relative.display()));
+				}
+				continue;
+			}
+			files.push(path);
+		}
+	}
+	files
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn caller(subject: &str, tenant: Option<&str>) -> Caller {
+		Caller {
+			subject: Some(subject.to_string()),
+			tenant: tenant.map(str::to_string),
+			..Caller::new(None, None, "http")
+		}
+	}
+
+	#[tokio::test]
+	async fn owners_are_recorded_for_each_caller_and_copied() {
+		let dir = std::env::temp_dir().join(format!("artifacts-{}", uuid::Uuid::new_v4()));
+		let store = |name: &str| {
+			ArtifactStore::new(&ArtifactsConfig {
+				dir: dir.join(name),
+				..Default::default()
+			})
+			.unwrap()
+		};
+		let (artifacts, other) = (store("artifacts"), store("other"));
+
+		let anonymous = artifacts.store_bytes(b"anyone".to_vec(), &Caller::new(None, None, "http")).await;
+		assert!(artifacts.owners(&anonymous.unwrap()).await.unwrap().is_empty());
+
+		let alice = caller("alice", Some("acme"));
+		let id = artifacts.store_bytes(b"report".to_vec(), &alice).await.unwrap();
+		assert_eq!(artifacts.store_bytes(b"report".to_vec(), &alice).await.unwrap(), id);
+		artifacts.store_bytes(b"report".to_vec(), &caller("bob", None)).await.unwrap();
+		let mut owners = artifacts.owners(&id).await.unwrap();
+		owners.sort_by(|a, b| a.subject.cmp(&b.subject));
+		assert_eq!(owners, vec![ArtifactOwner::of(&alice), ArtifactOwner::of(&caller("bob", None))]);
+
+		assert!(other.owners(&id).await.unwrap().is_empty());
+		other.copy_from(&artifacts, &id).await.unwrap();
+		assert_eq!(other.owners(&id).await.unwrap().len(), 2);
+		assert!(artifacts.owners("not-an-id").await.unwrap().is_empty());
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::auth::Principal;
+use crate::clock::now_ms;
+use crate::logging::SyslogSocket;
+use crate::request_id;
+
+const DEFAULT_RECENT: usize = 1000;
+const MAX_QUERY_LIMIT: usize = 1000;
+/// syslog facility local0, severity informational.
+const SYSLOG_PRIORITY: u8 = 16 * 8 + 6;
+const SYSLOG_TAG: &str = "pagi-sandbox-audit";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+	/// JSON-lines file entries are appended to.
+	pub file: Option<PathBuf>,
+	/// Local socket path (`/dev/log`) or UDP `host:port` of a syslog daemon.
+	pub syslog: Option<String>,
+	/// Entries kept in memory for `GET /api/v1/admin/audit`.
+	pub recent: usize,
+}
+
+impl Default for AuditConfig {
+	fn default() -> Self {
+		Self {
+			file: None,
+			syslog: None,
+			recent: DEFAULT_RECENT,
+		}
+	}
+}
+
+/// Who is asking for an execution, as the audit log records it.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+	/// `None` when authentication is disabled.
+	pub subject: Option<String>,
+	pub tenant: Option<String>,
+	/// Peer address of the connection the request arrived on.
+	pub client_ip: Option<IpAddr>,
+	/// `http`, `sse`, `job` or `grpc`.
+	pub transport: &'static str,
+	/// The `X-Request-Id` of the request that asked.
+	pub request_id: Option<String>,
+}
+
+impl Caller {
+	pub fn new(principal: Option<&Principal>, client_ip: Option<IpAddr>, transport: &'static str) -> Self {
+		Self {
+			subject: principal.map(|p| p.subject.clone()),
+			tenant: principal.and_then(|p| p.tenant.clone()),
+			client_ip,
+			transport,
+			request_id: request_id::current(),
+		}
+	}
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+	pub timestamp_ms: u64,
+	pub subject: Option<String>,
+	pub tenant: Option<String>,
+	pub client_ip: Option<IpAddr>,
+	pub transport: &'static str,
+	pub request_id: Option<String>,
+	pub tool_name: String,
+	/// SHA-256 of the args as compact JSON with sorted keys, so identical
+	/// arguments can be correlated without logging their contents.
+	pub args_sha256: String,
+	/// The execution's status, or `forbidden` / `invalid_args` for requests
+	/// rejected before anything ran.
+	pub status: String,
+}
+
+/// Filters for `GET /api/v1/admin/audit`.
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+	pub subject: Option<String>,
+	pub tenant: Option<String>,
+	pub tool_name: Option<String>,
+	pub status: Option<String>,
+	/// Only entries at or after this time.
+	pub since_ms: Option<u64>,
+	pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+	fn matches(&self, entry: &AuditEntry) -> bool {
+		let eq = |want: &Option<String>, have: Option<&str>| want.as_deref().is_none_or(|w| have == Some(w));
+		eq(&self.subject, entry.subject.as_deref())
+			&& eq(&self.tenant, entry.tenant.as_deref())
+			&& eq(&self.tool_name, Some(&entry.tool_name))
+			&& eq(&self.status, Some(&entry.status))
+			&& self.since_ms.is_none_or(|t| entry.timestamp_ms >= t)
+	}
+}
+
+enum Sink {
+	File { path: PathBuf, file: Mutex<File> },
+	Syslog(SyslogSocket),
+}
+
+impl Sink {
+	fn open_file(path: PathBuf) -> Result<Self, String> {
+		let file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&path)
+			.map_err(|e| format!("{}: {e}", path.display()))?;
+		Ok(Self::File {
+			path,
+			file: Mutex::new(file),
+		})
+	}
+
+	fn open_syslog(target: &str) -> Result<Self, String> {
+		SyslogSocket::connect(target).map(Self::Syslog)
+	}
+
+	fn write(&self, line: &str) -> std::io::Result<()> {
+		match self {
+			Self::File { file, .. } => {
+				let mut file = file.lock().unwrap();
+				file.write_all(format!("{line}\n").as_bytes())
+			}
+			Self::Syslog(socket) => socket.send(SYSLOG_PRIORITY, SYSLOG_TAG, line),
+		}
+	}
+
+	fn describe(&self) -> String {
+		match self {
+			Self::File { path, .. } => format!("file:{}", path.display()),
+			Self::Syslog(_) => "syslog".to_string(),
+		}
+	}
+}
+
+/// Append-only record of tool executions, kept apart from the application
+/// logs.
+///
+/// Entries go to every configured sink (`audit.file`, `audit.syslog`) as one
+/// JSON object per line; the most recent `audit.recent` are also kept in
+/// memory for the admin API.
+pub struct AuditLog {
+	sinks: Vec<Sink>,
+	recent: Mutex<VecDeque<AuditEntry>>,
+	capacity: usize,
+}
+
+impl AuditLog {
+	pub fn open(config: &AuditConfig) -> Result<Self, String> {
+		let mut sinks = Vec::new();
+		if let Some(path) = &config.file {
+			sinks.push(Sink::open_file(path.clone())?);
+		}
+		if let Some(target) = &config.syslog {
+			sinks.push(Sink::open_syslog(target)?);
+		}
+		let capacity = config.recent;
+
+		let described: Vec<String> = sinks.iter().map(Sink::describe).collect();
+		info!(sinks = ?described, recent = capacity, message = "Audit log ready");
+		Ok(Self {
+			sinks,
+			recent: Mutex::new(VecDeque::with_capacity(capacity)),
+			capacity,
+		})
+	}
+
+	/// Record one execution or rejected request. Sink failures are logged and
+	/// never fail the request.
+	pub fn record(&self, caller: &Caller, tool_name: &str, args: &Value, status: &str) {
+		let entry = AuditEntry {
+			timestamp_ms: now_ms(),
+			subject: caller.subject.clone(),
+			tenant: caller.tenant.clone(),
+			client_ip: caller.client_ip,
+			transport: caller.transport,
+			request_id: caller.request_id.clone(),
+			tool_name: tool_name.to_string(),
+			args_sha256: args_sha256(args),
+			status: status.to_string(),
+		};
+
+		let line = serde_json::to_string(&entry).expect("audit entries serialize");
+		for sink in &self.sinks {
+			if let Err(e) = sink.write(&line) {
+				warn!(sink = %sink.describe(), error = %e, message = "Failed to write audit entry");
+			}
+		}
+
+		let mut recent = self.recent.lock().unwrap();
+		if recent.len() == self.capacity {
+			recent.pop_front();
+		}
+		if self.capacity > 0 {
+			recent.push_back(entry);
+		}
+	}
+
+	/// Push file entries to disk; called on shutdown.
+	pub fn flush(&self) {
+		for sink in &self.sinks {
+			if let Sink::File { path, file } = sink {
+				if let Err(e) = file.lock().unwrap().sync_data() {
+					warn!(path = %path.display(), error = %e, message = "Failed to sync audit file");
+				}
+			}
+		}
+	}
+
+	/// Matching in-memory entries, newest first.
+	pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+		let limit = query.limit.unwrap_or(100).min(MAX_QUERY_LIMIT);
+		self.recent
+			.lock()
+			.unwrap()
+			.iter()
+			.rev()
+			.filter(|e| query.matches(e))
+			.take(limit)
+			.cloned()
+			.collect()
+	}
+}
+
+pub fn args_sha256(args: &Value) -> String {
+	// serde_json keeps object keys sorted, so equal args hash equally.
+	let digest = Sha256::digest(args.to_string().as_bytes());
+	digest.iter().map(|b| format!("{b:02x}")).collect()
+}
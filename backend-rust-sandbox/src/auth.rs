@@ -0,0 +1,383 @@
+use axum::{
+	extract::{Request, State},
+	http::{header, HeaderMap},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tonic::metadata::MetadataMap;
+use tracing::{debug, info, warn};
+
+use crate::auth_jwt::{JwtConfig, JwtValidator};
+use crate::error::SandboxError;
+use crate::tls::PeerIdentity;
+
+const DEFAULT_RELOAD_SECS: u64 = 10;
+
+/// Who made an authenticated request. Handlers can read it from the request
+/// extensions.
+#[derive(Debug, Clone)]
+pub struct Principal {
+	/// The JWT `sub`, the key's label from the key file, `env-<n>` for the
+	/// static keys in `auth.api_keys`, or `cert:` and the client
+	/// certificate's identity.
+	pub subject: String,
+	/// JWT `tenant` claim, or the subject's entry in `auth.tenants`; scopes
+	/// logs, history and quotas.
+	pub tenant: Option<String>,
+	/// Tools this principal may execute (JWT `tools` claim); `None` = all.
+	pub allowed_tools: Option<HashSet<String>>,
+	/// JWT `roles` claim, checked against the RBAC policy.
+	pub roles: Vec<String>,
+}
+
+impl Principal {
+	fn api_key(subject: String) -> Self {
+		Self {
+			subject,
+			tenant: None,
+			allowed_tools: None,
+			roles: Vec::new(),
+		}
+	}
+
+	/// A caller known only by its client certificate; RBAC bindings give it
+	/// roles. The `cert:` prefix keeps a certificate from passing for the API
+	/// key or JWT subject its SAN happens to spell.
+	fn client_cert(identity: &PeerIdentity) -> Self {
+		Self::api_key(format!("cert:{}", identity.0))
+	}
+
+	pub fn may_execute(&self, tool_name: &str) -> bool {
+		self.allowed_tools
+			.as_ref()
+			.is_none_or(|tools| tools.contains(tool_name))
+	}
+}
+
+/// `tenant` field value for log lines.
+pub fn tenant_of(principal: Option<&Principal>) -> &str {
+	principal.and_then(|p| p.tenant.as_deref()).unwrap_or("none")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+	/// Static keys (`SANDBOX_API_KEYS`, comma-separated).
+	pub api_keys: Vec<String>,
+	/// File with one key per line (`key` or `label:key`, `#` comments).
+	pub api_keys_file: Option<PathBuf>,
+	/// How often the keys file is checked for changes.
+	pub api_keys_reload_secs: u64,
+	pub jwt: JwtConfig,
+	/// Tenant of each subject: API key labels, `cert:<san>` for client
+	/// certificates and the `sub` of JWTs without a `tenant` claim.
+	pub tenants: BTreeMap<String, String>,
+}
+
+impl Default for AuthConfig {
+	fn default() -> Self {
+		Self {
+			api_keys: Vec::new(),
+			api_keys_file: None,
+			api_keys_reload_secs: DEFAULT_RELOAD_SECS,
+			jwt: JwtConfig::default(),
+			tenants: BTreeMap::new(),
+		}
+	}
+}
+
+/// Why a request was rejected.
+#[derive(Debug, Clone)]
+pub enum AuthError {
+	Missing,
+	/// Carries the reason for the logs; clients only see "invalid credentials".
+	Invalid(String),
+}
+
+impl AuthError {
+	fn message(&self) -> &'static str {
+		match self {
+			Self::Missing => "missing credentials: send `Authorization: Bearer <token>` or `X-Api-Key`",
+			Self::Invalid(_) => "invalid credentials",
+		}
+	}
+}
+
+impl fmt::Display for AuthError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Missing => f.write_str("missing credentials"),
+			Self::Invalid(reason) => f.write_str(reason),
+		}
+	}
+}
+
+/// Validates API keys against the configured key store, and bearer JWTs
+/// against the configured signing keys.
+///
+/// With neither keys nor JWT settings configured, authentication is disabled
+/// and every request is let through (a warning is logged at startup).
+pub struct Authenticator {
+	config: AuthConfig,
+	jwt: Option<Arc<JwtValidator>>,
+	/// key -> label
+	keys: RwLock<HashMap<String, String>>,
+	file_mtime: RwLock<Option<SystemTime>>,
+}
+
+impl Authenticator {
+	pub fn new(config: AuthConfig) -> Self {
+		let jwt = config
+			.jwt
+			.enabled()
+			.then(|| Arc::new(JwtValidator::new(config.jwt.clone())));
+		let auth = Self {
+			config,
+			jwt,
+			keys: RwLock::new(HashMap::new()),
+			file_mtime: RwLock::new(None),
+		};
+		auth.reload();
+		if !auth.enabled() {
+			warn!(message = "No API keys or JWT settings configured; authentication is disabled");
+		}
+		auth
+	}
+
+	pub fn enabled(&self) -> bool {
+		!self.config.api_keys.is_empty() || self.config.api_keys_file.is_some() || self.jwt.is_some()
+	}
+
+	/// See [`JwtValidator::jwks_key_count`].
+	pub fn jwks_key_count(&self) -> Option<usize> {
+		self.jwt.as_ref().and_then(|jwt| jwt.jwks_key_count())
+	}
+
+	/// Start polling the keys file for changes and refreshing the JWKS.
+	pub fn spawn_refreshers(self: &Arc<Self>) {
+		if let Some(jwt) = &self.jwt {
+			jwt.spawn_jwks_refresher();
+		}
+		if self.config.api_keys_file.is_none() {
+			return;
+		}
+		let auth = self.clone();
+		tokio::spawn(async move {
+			let mut tick = tokio::time::interval(Duration::from_secs(auth.config.api_keys_reload_secs));
+			tick.tick().await;
+			loop {
+				tick.tick().await;
+				let mtime = auth.keys_file_mtime();
+				if mtime != *auth.file_mtime.read().unwrap() {
+					auth.reload();
+				}
+			}
+		});
+	}
+
+	fn keys_file_mtime(&self) -> Option<SystemTime> {
+		let path = self.config.api_keys_file.as_ref()?;
+		std::fs::metadata(path).and_then(|m| m.modified()).ok()
+	}
+
+	/// Rebuild the key set from the static keys and the keys file. A file that
+	/// cannot be read keeps the previous keys in place.
+	fn reload(&self) {
+		let mut keys: HashMap<String, String> = self
+			.config
+			.api_keys
+			.iter()
+			.enumerate()
+			.map(|(i, key)| (key.clone(), format!("env-{i}")))
+			.collect();
+
+		if let Some(path) = &self.config.api_keys_file {
+			let mtime = self.keys_file_mtime();
+			match std::fs::read_to_string(path) {
+				Ok(text) => {
+					for (n, line) in text.lines().enumerate() {
+						let line = line.trim();
+						if line.is_empty() || line.starts_with('#') {
+							continue;
+						}
+						let (label, key) = match line.split_once(':') {
+							Some((label, key)) => (label.trim().to_string(), key.trim()),
+							None => (format!("{}:{}", path.display(), n + 1), line),
+						};
+						keys.insert(key.to_string(), label);
+					}
+				}
+				Err(e) => {
+					warn!(path = %path.display(), error = %e, message = "Failed to read API keys file; keeping previous keys");
+					return;
+				}
+			}
+			*self.file_mtime.write().unwrap() = mtime;
+		}
+
+		info!(keys = keys.len(), message = "Loaded API keys");
+		*self.keys.write().unwrap() = keys;
+	}
+
+	/// Check a presented key. Every stored key is compared in constant time so
+	/// response timing reveals nothing about near misses.
+	fn check_key(&self, presented: &str) -> Result<Principal, AuthError> {
+		let keys = self.keys.read().unwrap();
+		let mut matched = None;
+		for (key, label) in keys.iter() {
+			if constant_time_eq(key.as_bytes(), presented.as_bytes()) {
+				matched = Some(label.clone());
+			}
+		}
+		matched
+			.map(Principal::api_key)
+			.ok_or_else(|| AuthError::Invalid("unknown API key".to_string()))
+	}
+
+	/// Identify the caller, and give it its tenant from `auth.tenants` unless
+	/// its token named one.
+	fn authenticate(
+		&self,
+		bearer: Option<&str>,
+		api_key: Option<&str>,
+		peer: Option<&PeerIdentity>,
+	) -> Result<Option<Principal>, AuthError> {
+		let mut principal = self.identify(bearer, api_key, peer)?;
+		if let Some(principal) = principal.as_mut().filter(|p| p.tenant.is_none()) {
+			principal.tenant = self.config.tenants.get(&principal.subject).cloned();
+		}
+		Ok(principal)
+	}
+
+	/// A token or key takes precedence over the connection's client
+	/// certificate, which identifies the caller when neither is sent (even with
+	/// authentication otherwise disabled).
+	fn identify(
+		&self,
+		bearer: Option<&str>,
+		api_key: Option<&str>,
+		peer: Option<&PeerIdentity>,
+	) -> Result<Option<Principal>, AuthError> {
+		let presented = bearer.or(api_key);
+		if !self.enabled() || presented.is_none() {
+			if let Some(peer) = peer {
+				return Ok(Some(Principal::client_cert(peer)));
+			}
+		}
+		if !self.enabled() {
+			return Ok(None);
+		}
+		let presented = presented.ok_or(AuthError::Missing)?;
+		// Anything shaped like a JWT is checked as one first; an API key that
+		// happens to contain two dots still works through the fallback.
+		if let (Some(jwt), Some(token)) = (&self.jwt, bearer) {
+			if token.split('.').count() == 3 {
+				return match jwt.validate(token) {
+					Ok(principal) => Ok(Some(principal)),
+					Err(reason) => self
+						.check_key(presented)
+						.map(Some)
+						.map_err(|_| AuthError::Invalid(reason)),
+				};
+			}
+		}
+		self.check_key(presented).map(Some)
+	}
+
+	pub fn authenticate_http(
+		&self,
+		headers: &HeaderMap,
+		peer: Option<&PeerIdentity>,
+	) -> Result<Option<Principal>, AuthError> {
+		let bearer = headers
+			.get(header::AUTHORIZATION)
+			.and_then(|v| v.to_str().ok())
+			.and_then(bearer_token);
+		let api_key = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+		self.authenticate(bearer, api_key, peer)
+	}
+
+	pub fn authenticate_grpc(
+		&self,
+		metadata: &MetadataMap,
+		peer: Option<&PeerIdentity>,
+	) -> Result<Option<Principal>, AuthError> {
+		let bearer = metadata
+			.get("authorization")
+			.and_then(|v| v.to_str().ok())
+			.and_then(bearer_token);
+		let api_key = metadata.get("x-api-key").and_then(|v| v.to_str().ok());
+		self.authenticate(bearer, api_key, peer)
+	}
+}
+
+fn bearer_token(value: &str) -> Option<&str> {
+	let (scheme, token) = value.split_once(' ')?;
+	scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	if a.len() != b.len() {
+		return false;
+	}
+	a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// axum middleware rejecting requests without a valid key with `401`.
+pub async fn require_api_key(
+	State(auth): State<Arc<Authenticator>>,
+	mut request: Request,
+	next: Next,
+) -> Response {
+	match auth.authenticate_http(request.headers(), request.extensions().get::<PeerIdentity>()) {
+		Ok(principal) => {
+			if let Some(principal) = principal {
+				debug!(subject = %principal.subject, path = %request.uri().path(), message = "Authenticated request");
+				request.extensions_mut().insert(principal);
+			}
+			next.run(request).await
+		}
+		Err(e) => {
+			warn!(path = %request.uri().path(), reason = %e, message = "Rejected unauthenticated request");
+			([(header::WWW_AUTHENTICATE, "Bearer")], SandboxError::Unauthorized(e.message().to_string())).into_response()
+		}
+	}
+}
+
+/// tonic interceptor applying the same check to every gRPC call.
+#[derive(Clone)]
+pub struct GrpcAuthInterceptor {
+	auth: Arc<Authenticator>,
+}
+
+impl GrpcAuthInterceptor {
+	pub fn new(auth: Arc<Authenticator>) -> Self {
+		Self { auth }
+	}
+}
+
+impl tonic::service::Interceptor for GrpcAuthInterceptor {
+	fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+		match self
+			.auth
+			.authenticate_grpc(request.metadata(), request.extensions().get::<PeerIdentity>())
+		{
+			Ok(principal) => {
+				if let Some(principal) = principal {
+					request.extensions_mut().insert(principal);
+				}
+				Ok(request)
+			}
+			Err(e) => {
+				warn!(reason = %e, message = "Rejected unauthenticated gRPC call");
+				Err(SandboxError::Unauthorized(e.message().to_string()).into())
+			}
+		}
+	}
+}
@@ -0,0 +1,272 @@
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::auth::Principal;
+
+const DEFAULT_JWKS_REFRESH_SECS: u64 = 300;
+/// Unknown `kid`s trigger an early JWKS refresh, but not more often than this.
+const MIN_JWKS_REFETCH: Duration = Duration::from_secs(10);
+/// Entry in the `tools` claim that grants every tool.
+const ALL_TOOLS: &str = "*";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+	/// Shared secret for HS256 tokens.
+	pub hs256_secret: Option<String>,
+	/// PEM public key for RS256 tokens without a `kid`.
+	pub rs256_public_key_file: Option<PathBuf>,
+	/// JWKS endpoint with RS256 keys, selected by `kid`.
+	pub jwks_url: Option<String>,
+	pub jwks_refresh_secs: u64,
+	pub issuer: Option<String>,
+	pub audience: Option<String>,
+}
+
+impl Default for JwtConfig {
+	fn default() -> Self {
+		Self {
+			hs256_secret: None,
+			rs256_public_key_file: None,
+			jwks_url: None,
+			jwks_refresh_secs: DEFAULT_JWKS_REFRESH_SECS,
+			issuer: None,
+			audience: None,
+		}
+	}
+}
+
+impl JwtConfig {
+	pub fn enabled(&self) -> bool {
+		self.hs256_secret.is_some() || self.rs256_public_key_file.is_some() || self.jwks_url.is_some()
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+	/// Required: it is who the caller is to rate limits, tenants and the
+	/// audit log.
+	sub: String,
+	/// Tool names the token may execute; `"*"` grants all. Absent = all.
+	#[serde(default)]
+	tools: Option<Vec<String>>,
+	#[serde(default)]
+	tenant: Option<String>,
+	#[serde(default)]
+	roles: Vec<String>,
+}
+
+/// Verifies JWTs and turns their claims into a [`Principal`].
+pub struct JwtValidator {
+	config: JwtConfig,
+	hs256: Option<DecodingKey>,
+	rs256_pem: Option<DecodingKey>,
+	/// kid -> key, from the JWKS endpoint.
+	jwks: RwLock<HashMap<String, DecodingKey>>,
+	last_jwks_fetch: RwLock<Option<Instant>>,
+	http: reqwest::Client,
+}
+
+impl JwtValidator {
+	pub fn new(config: JwtConfig) -> Self {
+		let hs256 = config
+			.hs256_secret
+			.as_ref()
+			.map(|s| DecodingKey::from_secret(s.as_bytes()));
+		let rs256_pem = config.rs256_public_key_file.as_ref().and_then(|path| {
+			let pem = std::fs::read(path)
+				.map_err(|e| warn!(path = %path.display(), error = %e, message = "Failed to read RS256 public key"))
+				.ok()?;
+			DecodingKey::from_rsa_pem(&pem)
+				.map_err(|e| warn!(error = %e, message = "Ignoring invalid RS256 public key"))
+				.ok()
+		});
+		Self {
+			config,
+			hs256,
+			rs256_pem,
+			jwks: RwLock::new(HashMap::new()),
+			last_jwks_fetch: RwLock::new(None),
+			http: reqwest::Client::new(),
+		}
+	}
+
+	/// Fetch the JWKS now and then every `jwks_refresh_secs`.
+	pub fn spawn_jwks_refresher(self: &Arc<Self>) {
+		if self.config.jwks_url.is_none() {
+			return;
+		}
+		let validator = self.clone();
+		tokio::spawn(async move {
+			let mut tick = tokio::time::interval(Duration::from_secs(validator.config.jwks_refresh_secs));
+			loop {
+				tick.tick().await;
+				validator.refresh_jwks().await;
+			}
+		});
+	}
+
+	async fn refresh_jwks(&self) {
+		let Some(url) = &self.config.jwks_url else {
+			return;
+		};
+		*self.last_jwks_fetch.write().unwrap() = Some(Instant::now());
+
+		let set: JwkSet = match self.http.get(url).send().await.and_then(|r| r.error_for_status()) {
+			Ok(resp) => match resp.json().await {
+				Ok(set) => set,
+				Err(e) => {
+					warn!(url = %url, error = %e, message = "Invalid JWKS document; keeping previous keys");
+					return;
+				}
+			},
+			Err(e) => {
+				warn!(url = %url, error = %e, message = "Failed to fetch JWKS; keeping previous keys");
+				return;
+			}
+		};
+
+		let keys: HashMap<String, DecodingKey> = set
+			.keys
+			.iter()
+			.filter_map(|jwk| {
+				let kid = jwk.common.key_id.clone()?;
+				DecodingKey::from_jwk(jwk).ok().map(|key| (kid, key))
+			})
+			.collect();
+		info!(url = %url, keys = keys.len(), message = "Loaded JWKS");
+		*self.jwks.write().unwrap() = keys;
+	}
+
+	/// Refetch the JWKS in the background after seeing an unknown `kid`, in case
+	/// the issuer rotated keys since the last refresh.
+	fn refetch_soon(self: &Arc<Self>) {
+		let due = self
+			.last_jwks_fetch
+			.read()
+			.unwrap()
+			.is_none_or(|t| t.elapsed() >= MIN_JWKS_REFETCH);
+		if due && self.config.jwks_url.is_some() {
+			let validator = self.clone();
+			tokio::spawn(async move { validator.refresh_jwks().await });
+		}
+	}
+
+	/// Keys currently loaded from the JWKS endpoint; `None` without one.
+	pub fn jwks_key_count(&self) -> Option<usize> {
+		self.config
+			.jwks_url
+			.as_ref()
+			.map(|_| self.jwks.read().unwrap().len())
+	}
+
+	/// Verify `token` and return the principal it describes.
+	pub fn validate(self: &Arc<Self>, token: &str) -> Result<Principal, String> {
+		let header = decode_header(token).map_err(|e| format!("malformed token: {e}"))?;
+		let key = match header.alg {
+			Algorithm::HS256 => self.hs256.clone(),
+			Algorithm::RS256 => match &header.kid {
+				Some(kid) => {
+					let key = self.jwks.read().unwrap().get(kid).cloned();
+					if key.is_none() {
+						self.refetch_soon();
+					}
+					key
+				}
+				None => self.rs256_pem.clone(),
+			},
+			other => return Err(format!("unsupported token algorithm {other:?}")),
+		}
+		.ok_or_else(|| format!("no key configured for {:?} token", header.alg))?;
+
+		let mut validation = Validation::new(header.alg);
+		let mut required = vec!["exp", "sub"];
+		match &self.config.issuer {
+			Some(iss) => {
+				validation.set_issuer(&[iss]);
+				required.push("iss");
+			}
+			None => validation.iss = None,
+		}
+		match &self.config.audience {
+			Some(aud) => {
+				validation.set_audience(&[aud]);
+				required.push("aud");
+			}
+			None => validation.validate_aud = false,
+		}
+		validation.set_required_spec_claims(&required);
+
+		let claims = decode::<Claims>(token, &key, &validation)
+			.map_err(|e| format!("invalid token: {e}"))?
+			.claims;
+		if claims.sub.is_empty() {
+			return Err("invalid token: empty sub claim".to_string());
+		}
+		let allowed_tools = claims
+			.tools
+			.filter(|tools| !tools.iter().any(|t| t == ALL_TOOLS))
+			.map(|tools| tools.into_iter().collect::<HashSet<_>>());
+		Ok(Principal {
+			subject: claims.sub,
+			tenant: claims.tenant,
+			allowed_tools,
+			roles: claims.roles,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use jsonwebtoken::{encode, EncodingKey, Header};
+	use serde_json::{json, Value};
+
+	const SECRET: &str = "test-secret";
+
+	fn validator(issuer: Option<&str>) -> Arc<JwtValidator> {
+		Arc::new(JwtValidator::new(JwtConfig {
+			hs256_secret: Some(SECRET.to_string()),
+			issuer: issuer.map(str::to_string),
+			..Default::default()
+		}))
+	}
+
+	fn token(mut claims: Value) -> String {
+		claims["exp"] = json!(jsonwebtoken::get_current_timestamp() + 600);
+		encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET.as_bytes())).unwrap()
+	}
+
+	#[test]
+	fn subject_comes_from_the_sub_claim() {
+		let claims = json!({"sub": "ci", "tenant": "acme", "tools": ["python"], "roles": ["operator"]});
+		let principal = validator(None).validate(&token(claims)).unwrap();
+		assert_eq!(principal.subject, "ci");
+		assert_eq!(principal.tenant.as_deref(), Some("acme"));
+		assert_eq!(principal.allowed_tools, Some(HashSet::from(["python".to_string()])));
+		assert_eq!(principal.roles, vec!["operator"]);
+		assert_eq!(validator(None).validate(&token(json!({"sub": "ci", "tools": ["*"]}))).unwrap().allowed_tools, None);
+	}
+
+	#[test]
+	fn tokens_without_a_subject_are_refused() {
+		let validator = validator(None);
+		assert!(validator.validate(&token(json!({"tenant": "acme"}))).is_err());
+		assert!(validator.validate(&token(json!({"sub": ""}))).is_err());
+		assert!(validator.validate(&token(json!({"sub": null}))).is_err());
+	}
+
+	#[test]
+	fn a_configured_issuer_is_required() {
+		let validator = validator(Some("https://issuer.example"));
+		assert!(validator.validate(&token(json!({"sub": "ci", "iss": "https://issuer.example"}))).is_ok());
+		assert!(validator.validate(&token(json!({"sub": "ci", "iss": "https://other.example"}))).is_err());
+		assert!(validator.validate(&token(json!({"sub": "ci"}))).is_err());
+	}
+}
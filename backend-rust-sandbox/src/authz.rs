@@ -0,0 +1,361 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+use crate::auth::Principal;
+use crate::policy::{PolicyConfig, PolicyEngine, PolicyInput};
+use crate::registry::base_name;
+
+/// What one role may do.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RolePolicy {
+	/// Tool name patterns this role may execute (`*` and `?` wildcards).
+	#[serde(default)]
+	pub allow: Vec<String>,
+	/// Patterns this role may never execute, whatever other roles allow.
+	#[serde(default)]
+	pub deny: Vec<String>,
+	/// Grants access to the admin API.
+	#[serde(default)]
+	pub admin: bool,
+	/// Tool name patterns whose jobs this role may approve or reject, for
+	/// tools marked `requires_approval`. Admin roles may decide on every
+	/// tool's.
+	#[serde(default)]
+	pub approve: Vec<String>,
+}
+
+/// Role-based access policy, loaded from `SANDBOX_RBAC_POLICY_FILE` or set
+/// through the admin API.
+///
+/// ```toml
+/// default_roles = ["read-only"]
+///
+/// [roles.read-only]
+/// allow = ["search_*", "weather_tool"]
+/// deny = ["shell"]
+///
+/// [roles.operator]
+/// allow = ["*"]
+/// admin = true
+///
+/// [roles.release-manager]
+/// approve = ["deploy_*"]
+///
+/// [bindings]
+/// alice = ["operator"]
+/// "cert:spiffe://pagi/ci" = ["operator"]
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RbacPolicy {
+	/// Roles for callers that have none from their token or a binding,
+	/// including anonymous callers when authentication is disabled.
+	#[serde(default)]
+	pub default_roles: Vec<String>,
+	#[serde(default)]
+	pub roles: BTreeMap<String, RolePolicy>,
+	/// Principal subject -> roles, for callers (e.g. API keys) without a
+	/// `roles` claim. Client certificates are bound as `cert:<san>`.
+	#[serde(default)]
+	pub bindings: BTreeMap<String, Vec<String>>,
+}
+
+impl RbacPolicy {
+	fn validate(&self) -> Result<(), String> {
+		let referenced = self
+			.default_roles
+			.iter()
+			.chain(self.bindings.values().flatten());
+		let unknown: BTreeSet<&String> = referenced.filter(|r| !self.roles.contains_key(*r)).collect();
+		if unknown.is_empty() {
+			Ok(())
+		} else {
+			Err(format!("undefined roles referenced: {unknown:?}"))
+		}
+	}
+
+	fn roles_of<'a>(&'a self, principal: Option<&'a Principal>) -> Vec<&'a str> {
+		let mut roles: Vec<&str> = principal
+			.map(|p| {
+				p.roles
+					.iter()
+					.map(String::as_str)
+					.chain(self.bindings.get(&p.subject).into_iter().flatten().map(String::as_str))
+					.collect()
+			})
+			.unwrap_or_default();
+		if roles.is_empty() {
+			roles = self.default_roles.iter().map(String::as_str).collect();
+		}
+		roles
+	}
+
+	fn may_execute(&self, roles: &[&str], tool_name: &str) -> bool {
+		let policies: Vec<&RolePolicy> = roles.iter().filter_map(|r| self.roles.get(*r)).collect();
+		let denied = policies
+			.iter()
+			.any(|p| p.deny.iter().any(|pat| glob_match(pat, tool_name)));
+		let allowed = policies
+			.iter()
+			.any(|p| p.allow.iter().any(|pat| glob_match(pat, tool_name)));
+		allowed && !denied
+	}
+
+	fn may_approve(&self, roles: &[&str], tool_name: &str) -> bool {
+		roles
+			.iter()
+			.filter_map(|r| self.roles.get(*r))
+			.any(|p| p.admin || p.approve.iter().any(|pat| glob_match(pat, tool_name)))
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RbacConfig {
+	/// TOML or YAML policy; without one RBAC is off.
+	pub policy_file: Option<PathBuf>,
+}
+
+/// Shared authorization checks for the HTTP and gRPC front ends.
+pub struct Authorizer {
+	/// `None` until a policy is configured; then only the token's own `tools`
+	/// claim restricts execution.
+	policy: RwLock<Option<RbacPolicy>>,
+	/// Execution policy checked after RBAC, when one is configured.
+	engine: RwLock<Option<Arc<PolicyEngine>>>,
+}
+
+impl Authorizer {
+	pub fn new(config: &RbacConfig, policy_config: &PolicyConfig) -> Result<Self, String> {
+		let policy = match &config.policy_file {
+			Some(path) => {
+				let policy = load_policy(path)?;
+				info!(path = %path.display(), roles = policy.roles.len(), message = "Loaded RBAC policy");
+				Some(policy)
+			}
+			None => None,
+		};
+		Ok(Self {
+			policy: RwLock::new(policy),
+			engine: RwLock::new(PolicyEngine::load(policy_config)?.map(Arc::new)),
+		})
+	}
+
+	pub fn policy(&self) -> Option<RbacPolicy> {
+		self.policy.read().unwrap().clone()
+	}
+
+	/// Replace the active policy. Not written back to the policy file.
+	pub fn set_policy(&self, policy: RbacPolicy) -> Result<(), String> {
+		policy.validate()?;
+		info!(roles = policy.roles.len(), message = "RBAC policy replaced");
+		*self.policy.write().unwrap() = Some(policy);
+		Ok(())
+	}
+
+	/// Check that the caller may run `tool_name`: the token's `tools` claim
+	/// must cover it, and so must the caller's roles once RBAC is configured.
+	/// Every version of a tool is covered by its name.
+	pub fn authorize_tool(&self, principal: Option<&Principal>, tool_name: &str) -> Result<(), String> {
+		let tool_name = base_name(tool_name);
+		let who = principal.map(|p| p.subject.as_str()).unwrap_or("anonymous");
+		if let Some(p) = principal {
+			if !p.may_execute(tool_name) {
+				return Err(format!("{who} is not allowed to execute {tool_name:?}"));
+			}
+		}
+
+		let policy = self.policy.read().unwrap();
+		let Some(policy) = policy.as_ref() else {
+			return Ok(());
+		};
+		let roles = policy.roles_of(principal);
+		if policy.may_execute(&roles, tool_name) {
+			Ok(())
+		} else {
+			warn!(subject = who, roles = ?roles, tool_name = tool_name, message = "RBAC denied tool execution");
+			Err(format!("roles {roles:?} of {who} do not allow executing {tool_name:?}"))
+		}
+	}
+
+	pub fn engine(&self) -> Option<Arc<PolicyEngine>> {
+		self.engine.read().unwrap().clone()
+	}
+
+	/// Replace the execution policy, dropping the decisions of the old one.
+	pub fn set_engine(&self, engine: Option<PolicyEngine>) {
+		*self.engine.write().unwrap() = engine.map(Arc::new);
+	}
+
+	/// `authorize_tool`, then the execution policy, which also sees the
+	/// request's args and the transport it came in on.
+	pub fn authorize_execution(
+		&self,
+		principal: Option<&Principal>,
+		transport: &str,
+		tool_name: &str,
+		args: &Value,
+	) -> Result<(), String> {
+		self.authorize_tool(principal, tool_name)?;
+		self.check_policy(principal, transport, tool_name, args)
+	}
+
+	/// The execution policy's half of [`Self::authorize_execution`].
+	pub fn check_policy(
+		&self,
+		principal: Option<&Principal>,
+		transport: &str,
+		tool_name: &str,
+		args: &Value,
+	) -> Result<(), String> {
+		match self.engine() {
+			Some(engine) => engine.check(&self.policy_input(principal, transport, tool_name, args)),
+			None => Ok(()),
+		}
+	}
+
+	/// What the execution policy is given for a request.
+	pub fn policy_input(
+		&self,
+		principal: Option<&Principal>,
+		transport: &str,
+		tool_name: &str,
+		args: &Value,
+	) -> PolicyInput {
+		let roles = match self.policy.read().unwrap().as_ref() {
+			Some(policy) => policy.roles_of(principal).into_iter().map(str::to_string).collect(),
+			None => principal.map(|p| p.roles.clone()).unwrap_or_default(),
+		};
+		PolicyInput::new(principal, roles, transport, tool_name, args)
+	}
+
+	/// Check that the caller may use the admin API. Without a policy there are
+	/// no admin roles, so only unauthenticated deployments are let through.
+	pub fn authorize_admin(&self, principal: Option<&Principal>) -> Result<(), String> {
+		let who = principal.map(|p| p.subject.as_str()).unwrap_or("anonymous");
+		let policy = self.policy.read().unwrap();
+		let admin = match policy.as_ref() {
+			Some(policy) => policy
+				.roles_of(principal)
+				.iter()
+				.any(|r| policy.roles.get(*r).is_some_and(|p| p.admin)),
+			None => principal.is_none(),
+		};
+		if admin {
+			Ok(())
+		} else {
+			Err(format!("{who} has no admin role"))
+		}
+	}
+
+	/// Check that the caller may approve or reject jobs of `tool_name`: an
+	/// admin role, or one whose `approve` patterns cover it. As with
+	/// [`Self::authorize_admin`], only unauthenticated deployments get by
+	/// without a policy.
+	pub fn authorize_approval(&self, principal: Option<&Principal>, tool_name: &str) -> Result<(), String> {
+		let tool_name = base_name(tool_name);
+		let who = principal.map(|p| p.subject.as_str()).unwrap_or("anonymous");
+		let policy = self.policy.read().unwrap();
+		let approver = match policy.as_ref() {
+			Some(policy) => policy.may_approve(&policy.roles_of(principal), tool_name),
+			None => principal.is_none(),
+		};
+		if approver {
+			Ok(())
+		} else {
+			warn!(subject = who, tool_name = tool_name, message = "Job approval denied");
+			Err(format!("{who} may not approve {tool_name:?} jobs"))
+		}
+	}
+}
+
+/// Read and validate a TOML or YAML policy file.
+pub fn load_policy(path: &Path) -> Result<RbacPolicy, String> {
+	let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+	let policy: RbacPolicy = match path.extension().and_then(|e| e.to_str()) {
+		Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?,
+		_ => toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?,
+	};
+	policy.validate().map_err(|e| format!("{}: {e}", path.display()))?;
+	Ok(policy)
+}
+
+/// Shell-style matching with `*` (any run) and `?` (any one character).
+fn glob_match(pattern: &str, name: &str) -> bool {
+	let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+	let (mut pi, mut ni) = (0, 0);
+	let mut backtrack: Option<(usize, usize)> = None;
+	while ni < n.len() {
+		match p.get(pi) {
+			Some('*') => {
+				backtrack = Some((pi, ni));
+				pi += 1;
+			}
+			Some(c) if *c == '?' || *c == n[ni] => {
+				pi += 1;
+				ni += 1;
+			}
+			_ => match backtrack {
+				Some((star, matched)) => {
+					pi = star + 1;
+					ni = matched + 1;
+					backtrack = Some((star, matched + 1));
+				}
+				None => return false,
+			},
+		}
+	}
+	p[pi..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn glob_match_wildcards() {
+		assert!(glob_match("*", "python_exec"));
+		assert!(glob_match("python_*", "python_exec"));
+		assert!(glob_match("*_exec", "python_exec"));
+		assert!(glob_match("py*on*ex?c", "python_exec"));
+		assert!(glob_match("http_?et", "http_get"));
+		assert!(!glob_match("http_?et", "http_et"));
+		assert!(!glob_match("http_?", "http_get"));
+		assert!(glob_match("a*b*c", "aXbYbZc"));
+		assert!(!glob_match("a*b*c", "aXbYbZ"));
+	}
+
+	#[test]
+	fn glob_match_is_anchored() {
+		assert!(glob_match("shell", "shell"));
+		assert!(!glob_match("shell", "my_shell"));
+		assert!(!glob_match("shell", "shell_exec"));
+		assert!(!glob_match("hell", "shell"));
+		assert!(!glob_match("shell*", "my_shell"));
+		assert!(!glob_match("*shell", "shell_exec"));
+	}
+
+	#[test]
+	fn glob_match_empty_patterns() {
+		assert!(glob_match("", ""));
+		assert!(glob_match("*", ""));
+		assert!(glob_match("**", ""));
+		assert!(!glob_match("", "shell"));
+		assert!(!glob_match("?", ""));
+	}
+
+	#[test]
+	fn glob_match_does_not_cross_tool_name_prefixes() {
+		// A role allowed `python` is not allowed `python_admin`, and one
+		// allowed `python_*` is not allowed `python` itself.
+		assert!(!glob_match("python", "python_admin"));
+		assert!(!glob_match("python_*", "python"));
+		assert!(!glob_match("python_*", "pythonista"));
+		assert!(glob_match("python*", "pythonista"));
+	}
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::tool::{execute_tool_request, ToolExecutionRequest, ToolExecutionResponse};
+use crate::tool_executor::{ExecutionContext, Executor};
+
+const DEFAULT_MAX_ITEMS: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchConfig {
+	/// Invocations one `execute_batch` request, or steps one
+	/// `execute_pipeline` request, may hold.
+	pub max_items: usize,
+}
+
+impl Default for BatchConfig {
+	fn default() -> Self {
+		Self {
+			max_items: DEFAULT_MAX_ITEMS,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+	/// Every item at once, as far as the execution slots allow.
+	#[default]
+	Parallel,
+	/// One item after another, whatever their outcome.
+	Sequential,
+	/// One item after another until one does not finish `ok`; the rest are
+	/// skipped.
+	FailFast,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+	#[serde(default)]
+	pub mode: BatchMode,
+	pub requests: Vec<ToolExecutionRequest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchItem {
+	#[serde(flatten)]
+	pub response: ToolExecutionResponse,
+	/// From the start of the batch's turn for this item to its result,
+	/// including time spent queued for a slot.
+	pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+	pub mode: BatchMode,
+	/// One per request, in request order.
+	pub results: Vec<BatchItem>,
+	pub succeeded: usize,
+	pub failed: usize,
+	pub skipped: usize,
+	pub elapsed_ms: u64,
+}
+
+/// Run a batch of tool requests.
+///
+/// `admit` does for each item what the single-item endpoints do (authorize,
+/// validate, rate limit, reserve a queue slot); a rejection becomes that
+/// item's result instead of failing the batch. Sequential items are admitted
+/// when their turn comes, so a batch can be longer than the queue.
+pub async fn execute_batch<F, A>(executor: Arc<Executor>, batch: BatchRequest, admit: F) -> BatchResponse
+where
+	F: Fn(&ToolExecutionRequest) -> A,
+	A: Future<Output = Result<ExecutionContext, ToolExecutionResponse>>,
+{
+	let started = Instant::now();
+	let mode = batch.mode;
+	let results = match mode {
+		BatchMode::Parallel => {
+			let (admit, executor) = (&admit, &executor);
+			let runs = batch.requests.into_iter().map(|req| async move {
+				let admitted = admit(&req).await;
+				run_item(executor.clone(), req, admitted).await
+			});
+			futures::future::join_all(runs).await
+		}
+		BatchMode::Sequential | BatchMode::FailFast => {
+			let mut results = Vec::with_capacity(batch.requests.len());
+			let mut failed = false;
+			for req in batch.requests {
+				if failed {
+					results.push(skipped(req));
+					continue;
+				}
+				let admitted = admit(&req).await;
+				let item = run_item(executor.clone(), req, admitted).await;
+				failed = mode == BatchMode::FailFast && item.response.status != "ok";
+				results.push(item);
+			}
+			results
+		}
+	};
+
+	let count = |status: &str| results.iter().filter(|item| item.response.status == status).count();
+	let (succeeded, skipped) = (count("ok"), count("skipped"));
+	BatchResponse {
+		mode,
+		succeeded,
+		failed: results.len() - succeeded - skipped,
+		skipped,
+		results,
+		elapsed_ms: started.elapsed().as_millis() as u64,
+	}
+}
+
+async fn run_item(
+	executor: Arc<Executor>,
+	req: ToolExecutionRequest,
+	admitted: Result<ExecutionContext, ToolExecutionResponse>,
+) -> BatchItem {
+	let started = Instant::now();
+	let response = match admitted {
+		Ok(ctx) => execute_tool_request(executor, req, ctx).await,
+		Err(rejection) => rejection,
+	};
+	BatchItem {
+		response,
+		elapsed_ms: started.elapsed().as_millis() as u64,
+	}
+}
+
+fn skipped(req: ToolExecutionRequest) -> BatchItem {
+	BatchItem {
+		response: ToolExecutionResponse {
+			status: "skipped".to_string(),
+			tool_name: req.tool_name,
+			result: json!({ "error": "not run: an earlier request in the fail_fast batch failed" }),
+		},
+		elapsed_ms: 0,
+	}
+}
@@ -0,0 +1,448 @@
+use base64::Engine;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tonic::metadata::MetadataValue;
+use tonic::transport::Channel;
+
+use crate::tool::ToolExecutionResponse;
+use crate::tool_service::proto::tool_service_client::ToolServiceClient;
+use crate::tool_service::proto::ToolRequest;
+
+/// The first argument that makes the binary a load generator instead of a
+/// server.
+pub const BENCH_ARG: &str = "bench";
+
+const USAGE: &str = "\
+usage: backend-rust-sandbox bench [options]
+
+Drives ExecuteTool against a running sandbox, one step per payload size and
+concurrency, and reports latency percentiles and throughput for each.
+
+  --url URL               REST base URL (default http://localhost:8001)
+  --grpc URL              call ExecuteTool over gRPC at URL instead
+  --api-key KEY           sent as X-Api-Key (default $SANDBOX_BENCH_API_KEY)
+  --tool NAME             tool to run (default python)
+  --args JSON             its args (default: a program reading stdin)
+  --payload-bytes LIST    stdin sizes, comma-separated (default 0,1024,65536)
+  --concurrency LIST      callers in flight, comma-separated (default 1,4,16,64)
+  --duration SECS         measured time per step (default 10)
+  --warmup SECS           unmeasured time before each step (default 1)
+  --max-error-rate RATE   a step failing more is not sustainable (default 0.01)
+  --max-p99-ms MS         a step slower at p99 is not sustainable
+  --min-throughput RPS    exit 1 when a payload size sustains less
+  --json                  print the report as JSON";
+
+const DEFAULT_TOOL: &str = "python";
+const DEFAULT_CODE: &str = "import sys; sys.stdout.write(str(len(sys.stdin.buffer.read())))";
+
+/// The arguments after [`BENCH_ARG`], if the binary was started with it.
+pub fn arguments() -> Option<Vec<String>> {
+	let mut args = std::env::args().skip(1);
+	if args.next()? != BENCH_ARG {
+		return None;
+	}
+	Some(args.collect())
+}
+
+enum Target {
+	Http(String),
+	Grpc(String),
+}
+
+struct Options {
+	target: Target,
+	api_key: Option<String>,
+	tool: String,
+	args: Value,
+	payload_bytes: Vec<usize>,
+	concurrency: Vec<usize>,
+	duration: Duration,
+	warmup: Duration,
+	max_error_rate: f64,
+	max_p99: Option<Duration>,
+	min_throughput: Option<f64>,
+	json: bool,
+}
+
+impl Options {
+	fn parse(args: Vec<String>) -> Result<Self, String> {
+		let mut options = Self {
+			target: Target::Http("http://localhost:8001".to_string()),
+			api_key: std::env::var("SANDBOX_BENCH_API_KEY").ok().filter(|v| !v.is_empty()),
+			tool: DEFAULT_TOOL.to_string(),
+			args: json!({ "code": DEFAULT_CODE }),
+			payload_bytes: vec![0, 1024, 65536],
+			concurrency: vec![1, 4, 16, 64],
+			duration: Duration::from_secs(10),
+			warmup: Duration::from_secs(1),
+			max_error_rate: 0.01,
+			max_p99: None,
+			min_throughput: None,
+			json: false,
+		};
+		let mut args = args.into_iter();
+		while let Some(flag) = args.next() {
+			let mut value = || args.next().ok_or_else(|| format!("{flag} needs a value"));
+			match flag.as_str() {
+				"--url" => options.target = Target::Http(value()?.trim_end_matches('/').to_string()),
+				"--grpc" => options.target = Target::Grpc(value()?),
+				"--api-key" => options.api_key = Some(value()?),
+				"--tool" => options.tool = value()?,
+				"--args" => {
+					options.args = serde_json::from_str(&value()?).map_err(|e| format!("--args: {e}"))?
+				}
+				"--payload-bytes" => options.payload_bytes = list(&flag, &value()?)?,
+				"--concurrency" => options.concurrency = list(&flag, &value()?)?,
+				"--duration" => options.duration = Duration::from_secs(number(&flag, &value()?)?),
+				"--warmup" => options.warmup = Duration::from_secs(number(&flag, &value()?)?),
+				"--max-error-rate" => options.max_error_rate = number(&flag, &value()?)?,
+				"--max-p99-ms" => options.max_p99 = Some(Duration::from_millis(number(&flag, &value()?)?)),
+				"--min-throughput" => options.min_throughput = Some(number(&flag, &value()?)?),
+				"--json" => options.json = true,
+				"-h" | "--help" => return Err(String::new()),
+				_ => return Err(format!("unknown option {flag}")),
+			}
+		}
+		if options.concurrency.contains(&0) {
+			return Err("--concurrency must be positive".to_string());
+		}
+		if options.duration.is_zero() {
+			return Err("--duration must be positive".to_string());
+		}
+		Ok(options)
+	}
+}
+
+fn number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+	value.trim().parse().map_err(|_| format!("{flag}: {value:?} is not a number"))
+}
+
+fn list<T: FromStr>(flag: &str, value: &str) -> Result<Vec<T>, String> {
+	let items = value
+		.split(',')
+		.filter(|item| !item.trim().is_empty())
+		.map(|item| number(flag, item))
+		.collect::<Result<Vec<T>, _>>()?;
+	if items.is_empty() {
+		return Err(format!("{flag} needs at least one value"));
+	}
+	Ok(items)
+}
+
+/// One way of calling ExecuteTool, cloned into every caller.
+#[derive(Clone)]
+enum Client {
+	Http {
+		http: reqwest::Client,
+		url: String,
+		api_key: Option<String>,
+	},
+	Grpc {
+		client: ToolServiceClient<Channel>,
+		api_key: Option<MetadataValue<tonic::metadata::Ascii>>,
+	},
+}
+
+impl Client {
+	async fn connect(options: &Options) -> Result<Self, String> {
+		match &options.target {
+			Target::Http(url) => Ok(Self::Http {
+				http: reqwest::Client::new(),
+				url: format!("{url}/api/v1/execute_tool"),
+				api_key: options.api_key.clone(),
+			}),
+			Target::Grpc(url) => {
+				let client = ToolServiceClient::connect(url.clone())
+					.await
+					.map_err(|e| format!("cannot reach {url}: {e}"))?;
+				let api_key = match &options.api_key {
+					Some(key) => Some(key.parse().map_err(|_| "--api-key is not a valid header value".to_string())?),
+					None => None,
+				};
+				Ok(Self::Grpc { client, api_key })
+			}
+		}
+	}
+
+	/// One execution, which must come back `ok`.
+	async fn execute(&self, tool: &str, args: &Value, stdin: &[u8]) -> Result<(), String> {
+		match self {
+			Self::Http { http, url, api_key } => {
+				let mut body = json!({ "tool_name": tool, "args": args });
+				if !stdin.is_empty() {
+					body["stdin"] = json!(base64::engine::general_purpose::STANDARD.encode(stdin));
+				}
+				let mut request = http.post(url).header("accept", "application/json").json(&body);
+				if let Some(key) = api_key {
+					request = request.header("x-api-key", key);
+				}
+				let response = request.send().await.map_err(|e| e.to_string())?;
+				let code = response.status();
+				match response.json::<ToolExecutionResponse>().await {
+					Ok(response) if response.status == "ok" => Ok(()),
+					Ok(response) => Err(response.status),
+					Err(_) => Err(code.to_string()),
+				}
+			}
+			Self::Grpc { client, api_key } => {
+				let mut request = tonic::Request::new(ToolRequest {
+					tool_name: tool.to_string(),
+					args_json: args.to_string(),
+					stdin: (!stdin.is_empty()).then(|| stdin.to_vec()),
+					..Default::default()
+				});
+				if let Some(key) = api_key {
+					request.metadata_mut().insert("x-api-key", key.clone());
+				}
+				let response = client.clone().execute_tool(request).await.map_err(|e| e.code().to_string())?;
+				match response.into_inner().status.as_str() {
+					"ok" => Ok(()),
+					status => Err(status.to_string()),
+				}
+			}
+		}
+	}
+}
+
+/// What one step measured.
+#[derive(Serialize)]
+struct Step {
+	payload_bytes: usize,
+	concurrency: usize,
+	requests: usize,
+	errors: usize,
+	/// The most common error, to tell a broken setup from an overloaded one.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	common_error: Option<String>,
+	throughput: f64,
+	p50_ms: f64,
+	p95_ms: f64,
+	p99_ms: f64,
+	max_ms: f64,
+	sustainable: bool,
+}
+
+/// The best sustainable step of one payload size.
+#[derive(Serialize)]
+struct Sustained {
+	payload_bytes: usize,
+	throughput: f64,
+	concurrency: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+	tool: String,
+	steps: Vec<Step>,
+	max_sustainable: &'a [Sustained],
+}
+
+/// Run the benchmark and print its report; returns the exit status.
+pub async fn run(args: Vec<String>) -> i32 {
+	let options = match Options::parse(args) {
+		Ok(options) => options,
+		Err(e) => {
+			if !e.is_empty() {
+				eprintln!("bench: {e}\n");
+			}
+			eprintln!("{USAGE}");
+			return 2;
+		}
+	};
+	let client = match Client::connect(&options).await {
+		Ok(client) => client,
+		Err(e) => {
+			eprintln!("bench: {e}");
+			return 1;
+		}
+	};
+
+	let mut steps = Vec::new();
+	for &payload_bytes in &options.payload_bytes {
+		let stdin = vec![b'x'; payload_bytes];
+		for &concurrency in &options.concurrency {
+			if !options.warmup.is_zero() {
+				drive(&client, &options, &stdin, concurrency, options.warmup).await;
+			}
+			let (latencies, errors, elapsed) = drive(&client, &options, &stdin, concurrency, options.duration).await;
+			let step = measure(&options, payload_bytes, concurrency, latencies, errors, elapsed);
+			if !options.json {
+				print_step(&step, steps.is_empty());
+			}
+			steps.push(step);
+		}
+	}
+
+	let max_sustainable: Vec<Sustained> = options
+		.payload_bytes
+		.iter()
+		.map(|&payload_bytes| {
+			let best = steps
+				.iter()
+				.filter(|step| step.payload_bytes == payload_bytes && step.sustainable)
+				.max_by(|a, b| a.throughput.total_cmp(&b.throughput));
+			Sustained {
+				payload_bytes,
+				throughput: best.map_or(0.0, |step| step.throughput),
+				concurrency: best.map(|step| step.concurrency),
+			}
+		})
+		.collect();
+	let below: Vec<&Sustained> = match options.min_throughput {
+		Some(min) => max_sustainable.iter().filter(|s| s.throughput < min).collect(),
+		None => max_sustainable.iter().filter(|s| s.concurrency.is_none()).collect(),
+	};
+
+	if options.json {
+		let report = Report {
+			tool: options.tool.clone(),
+			steps,
+			max_sustainable: &max_sustainable,
+		};
+		println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+	} else {
+		println!();
+		for sustained in &max_sustainable {
+			match sustained.concurrency {
+				Some(concurrency) => println!(
+					"max sustainable throughput at {} B: {:.1} req/s (concurrency {concurrency})",
+					sustained.payload_bytes, sustained.throughput
+				),
+				None => println!("max sustainable throughput at {} B: none of the steps held", sustained.payload_bytes),
+			}
+		}
+	}
+	if below.is_empty() {
+		0
+	} else {
+		for sustained in below {
+			eprintln!(
+				"bench: {} B sustained {:.1} req/s, below the required throughput",
+				sustained.payload_bytes, sustained.throughput
+			);
+		}
+		1
+	}
+}
+
+/// Keep `concurrency` executions in flight for `duration`; the latency of
+/// each that succeeded, the errors of those that did not, and the time it
+/// took for all to finish.
+async fn drive(
+	client: &Client,
+	options: &Options,
+	stdin: &[u8],
+	concurrency: usize,
+	duration: Duration,
+) -> (Vec<Duration>, Vec<String>, Duration) {
+	let started = Instant::now();
+	let until = started + duration;
+	let callers: Vec<_> = (0..concurrency)
+		.map(|_| {
+			let client = client.clone();
+			let tool = options.tool.clone();
+			let args = options.args.clone();
+			let stdin = stdin.to_vec();
+			tokio::spawn(async move {
+				let mut latencies = Vec::new();
+				let mut errors = Vec::new();
+				while Instant::now() < until {
+					let sent = Instant::now();
+					match client.execute(&tool, &args, &stdin).await {
+						Ok(()) => latencies.push(sent.elapsed()),
+						Err(e) => errors.push(e),
+					}
+				}
+				(latencies, errors)
+			})
+		})
+		.collect();
+	let mut latencies = Vec::new();
+	let mut errors = Vec::new();
+	for caller in callers {
+		if let Ok((mut caller_latencies, mut caller_errors)) = caller.await {
+			latencies.append(&mut caller_latencies);
+			errors.append(&mut caller_errors);
+		}
+	}
+	(latencies, errors, started.elapsed())
+}
+
+fn measure(
+	options: &Options,
+	payload_bytes: usize,
+	concurrency: usize,
+	mut latencies: Vec<Duration>,
+	errors: Vec<String>,
+	elapsed: Duration,
+) -> Step {
+	latencies.sort_unstable();
+	let requests = latencies.len() + errors.len();
+	let error_rate = if requests == 0 { 1.0 } else { errors.len() as f64 / requests as f64 };
+	let p99 = percentile(&latencies, 99.0);
+	let sustainable = !latencies.is_empty()
+		&& error_rate <= options.max_error_rate
+		&& options.max_p99.is_none_or(|max| p99 <= max);
+	Step {
+		payload_bytes,
+		concurrency,
+		requests,
+		errors: errors.len(),
+		common_error: most_common(errors),
+		throughput: latencies.len() as f64 / elapsed.as_secs_f64(),
+		p50_ms: millis(percentile(&latencies, 50.0)),
+		p95_ms: millis(percentile(&latencies, 95.0)),
+		p99_ms: millis(p99),
+		max_ms: millis(latencies.last().copied().unwrap_or_default()),
+		sustainable,
+	}
+}
+
+/// Nearest-rank percentile of sorted `latencies`.
+fn percentile(latencies: &[Duration], pct: f64) -> Duration {
+	if latencies.is_empty() {
+		return Duration::ZERO;
+	}
+	let rank = (pct / 100.0 * latencies.len() as f64).ceil() as usize;
+	latencies[rank.clamp(1, latencies.len()) - 1]
+}
+
+fn most_common(mut errors: Vec<String>) -> Option<String> {
+	errors.sort_unstable();
+	let mut best: Option<(&String, usize)> = None;
+	for group in errors.chunk_by(|a, b| a == b) {
+		if best.is_none_or(|(_, count)| group.len() > count) {
+			best = Some((&group[0], group.len()));
+		}
+	}
+	best.map(|(error, _)| error.clone())
+}
+
+fn millis(duration: Duration) -> f64 {
+	duration.as_secs_f64() * 1000.0
+}
+
+fn print_step(step: &Step, header: bool) {
+	if header {
+		println!(
+			"{:>9} {:>11} {:>9} {:>7} {:>10} {:>9} {:>9} {:>9} {:>9}  sustainable",
+			"payload_B", "concurrency", "requests", "errors", "req/s", "p50_ms", "p95_ms", "p99_ms", "max_ms"
+		);
+	}
+	println!(
+		"{:>9} {:>11} {:>9} {:>7} {:>10.1} {:>9.1} {:>9.1} {:>9.1} {:>9.1}  {}{}",
+		step.payload_bytes,
+		step.concurrency,
+		step.requests,
+		step.errors,
+		step.throughput,
+		step.p50_ms,
+		step.p95_ms,
+		step.p99_ms,
+		step.max_ms,
+		if step.sustainable { "yes" } else { "no" },
+		step.common_error.as_ref().map(|e| format!(" ({e})")).unwrap_or_default(),
+	);
+}
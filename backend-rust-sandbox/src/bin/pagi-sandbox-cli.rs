@@ -0,0 +1,342 @@
+use futures::StreamExt;
+use pagi_sandbox_client::{
+	Artifact, Base64, CancelOutcome, JobView, Priority, SandboxClient, StreamEvent, ToolExecutionRequest,
+	ToolExecutionResponse,
+};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "\
+usage: pagi-sandbox-cli [--url URL] [--api-key KEY] COMMAND
+
+commands:
+  tools                       list the tools
+  tool NAME                   describe a tool and its versions
+  exec TOOL [RUN OPTIONS]     run a tool and print its response
+      --stream                print its output as it is produced instead
+  submit TOOL [RUN OPTIONS]   start a job and print it
+      --follow                wait for it to finish
+  job ID [--follow]           show a job; --follow waits for it to finish
+  cancel ID                   cancel a job
+  artifact ID [-o FILE]       download an artifact, to stdout without -o
+
+run options:
+  --args JSON                 the tool's args (default {})
+  --args-file FILE            read them from FILE, or stdin for -
+  --stdin-file FILE           bytes for the tool's stdin
+  --input-file PATH=FILE      a file for the workspace (repeatable)
+  --timeout-ms MS             wall-clock limit
+  --priority CLASS            interactive, batch or background
+  --collect GLOB              workspace files to keep as artifacts (repeatable)
+  --artifacts-dir DIR         download the collected artifacts into DIR
+
+URL defaults to $RUST_SANDBOX_URL or http://localhost:8001, and KEY, sent as a
+bearer token, to $RUST_SANDBOX_API_KEY. Exits 1 when the tool's status is not
+ok or the request fails, 2 on a usage error.";
+
+const DEFAULT_URL: &str = "http://localhost:8001";
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+#[tokio::main]
+async fn main() -> ExitCode {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let (client, command) = match Client::from_args(args) {
+		Ok(parsed) => parsed,
+		Err(e) => return usage_error(&e),
+	};
+	let result = match command.first().map(String::as_str) {
+		Some("tools") => client.list_tools().await,
+		Some("tool") => match command.get(1) {
+			Some(name) => client.describe_tool(name).await,
+			None => return usage_error("tool needs a NAME"),
+		},
+		Some("exec") => match Run::parse(&command[1..], "--stream") {
+			Ok(run) => client.execute(run).await,
+			Err(e) => return usage_error(&e),
+		},
+		Some("submit") => match Run::parse(&command[1..], "--follow") {
+			Ok(run) => client.submit(run).await,
+			Err(e) => return usage_error(&e),
+		},
+		Some("job") => match command.get(1) {
+			Some(id) => client.job(id, command[2..].iter().any(|arg| arg == "--follow")).await,
+			None => return usage_error("job needs an ID"),
+		},
+		Some("cancel") => match command.get(1) {
+			Some(id) => client.cancel(id).await,
+			None => return usage_error("cancel needs an ID"),
+		},
+		Some("artifact") => match command.get(1) {
+			Some(id) => {
+				let output = match &command[2..] {
+					[] => None,
+					[flag, file] if flag == "-o" || flag == "--output" => Some(PathBuf::from(file)),
+					_ => return usage_error("artifact takes an ID and optionally -o FILE"),
+				};
+				client.artifact(id, output.as_deref()).await
+			}
+			None => return usage_error("artifact needs an ID"),
+		},
+		Some("-h" | "--help") | None => return usage_error(""),
+		Some(other) => return usage_error(&format!("unknown command {other}")),
+	};
+	match result {
+		Ok(true) => ExitCode::SUCCESS,
+		Ok(false) => ExitCode::FAILURE,
+		Err(e) => {
+			eprintln!("pagi-sandbox-cli: {e}");
+			ExitCode::FAILURE
+		}
+	}
+}
+
+fn usage_error(message: &str) -> ExitCode {
+	if !message.is_empty() {
+		eprintln!("pagi-sandbox-cli: {message}\n");
+	}
+	eprintln!("{USAGE}");
+	ExitCode::from(2)
+}
+
+/// What `exec` and `submit` send, with what to do once it is answered.
+struct Run {
+	request: ToolExecutionRequest,
+	/// `--stream` for `exec`, `--follow` for `submit`.
+	switch: bool,
+	artifacts_dir: Option<PathBuf>,
+}
+
+impl Run {
+	fn parse(args: &[String], switch_flag: &str) -> Result<Self, String> {
+		let (tool_name, options) = match args.split_first() {
+			Some((tool, options)) if !tool.starts_with('-') => (tool.clone(), options),
+			_ => return Err("needs a TOOL".to_string()),
+		};
+		let mut request = ToolExecutionRequest::new(tool_name, Value::Object(Default::default()));
+		let mut switch = false;
+		let mut artifacts_dir = None;
+		let mut options = options.iter();
+		while let Some(flag) = options.next() {
+			if flag == switch_flag {
+				switch = true;
+				continue;
+			}
+			let value = options.next().ok_or_else(|| format!("{flag} needs a value"))?;
+			match flag.as_str() {
+				"--args" => request.args = serde_json::from_str(value).map_err(|e| format!("--args: {e}"))?,
+				"--args-file" => {
+					let text = String::from_utf8(read_input(value)?).map_err(|e| format!("{value}: {e}"))?;
+					request.args = serde_json::from_str(&text).map_err(|e| format!("{value}: {e}"))?;
+				}
+				"--stdin-file" => request.stdin = Some(Base64(read_input(value)?)),
+				"--input-file" => {
+					let (path, file) = value
+						.split_once('=')
+						.ok_or_else(|| format!("--input-file takes PATH=FILE (got {value:?})"))?;
+					request.input_files.insert(path.to_string(), Base64(read_input(file)?));
+				}
+				"--timeout-ms" => {
+					request.timeout_ms = Some(value.parse().map_err(|_| format!("--timeout-ms: {value:?} is not a number"))?)
+				}
+				"--priority" => request.priority = Some(value.parse::<Priority>()?),
+				"--collect" => request.artifacts.push(value.clone()),
+				"--artifacts-dir" => artifacts_dir = Some(PathBuf::from(value)),
+				_ => return Err(format!("unknown option {flag}")),
+			}
+		}
+		Ok(Self {
+			request,
+			switch,
+			artifacts_dir,
+		})
+	}
+}
+
+/// The contents of `path`, or of stdin for `-`.
+fn read_input(path: &str) -> Result<Vec<u8>, String> {
+	if path == "-" {
+		let mut data = Vec::new();
+		std::io::stdin().read_to_end(&mut data).map_err(|e| format!("stdin: {e}"))?;
+		return Ok(data);
+	}
+	std::fs::read(path).map_err(|e| format!("{path}: {e}"))
+}
+
+struct Client {
+	api: SandboxClient,
+	url: String,
+}
+
+impl Client {
+	/// The client the global options describe, and the command after them.
+	fn from_args(args: Vec<String>) -> Result<(Self, Vec<String>), String> {
+		let env = |key: &str| std::env::var(key).ok().filter(|v| !v.is_empty());
+		let mut url = env("RUST_SANDBOX_URL").unwrap_or_else(|| DEFAULT_URL.to_string());
+		let mut api_key = env("RUST_SANDBOX_API_KEY");
+		let mut args = args.into_iter().peekable();
+		while let Some(flag) = args.next_if(|arg| arg == "--url" || arg == "--api-key") {
+			let value = args.next().ok_or_else(|| format!("{flag} needs a value"))?;
+			match flag.as_str() {
+				"--url" => url = value,
+				_ => api_key = Some(value),
+			}
+		}
+		let http = reqwest::Client::builder()
+			.user_agent(concat!("pagi-sandbox-cli/", env!("CARGO_PKG_VERSION")))
+			.build()
+			.map_err(|e| e.to_string())?;
+		let mut api = SandboxClient::new(url.as_str()).with_http_client(http);
+		if let Some(key) = api_key {
+			api = api.with_api_key(key);
+		}
+		Ok((Self { api, url }, args.collect()))
+	}
+
+	/// `error`, saying which sandbox it came from.
+	fn failed(&self, error: pagi_sandbox_client::Error) -> String {
+		format!("{}: {error}", self.url)
+	}
+
+	async fn list_tools(&self) -> Result<bool, String> {
+		for tool in self.api.list_tools().await.map_err(|e| self.failed(e))? {
+			let version = tool.version.map(|v| format!("@{v}")).unwrap_or_default();
+			println!("{}{version}\t{}", tool.name, tool.description);
+		}
+		Ok(true)
+	}
+
+	async fn describe_tool(&self, name: &str) -> Result<bool, String> {
+		print_json(&self.api.get_tool(name).await.map_err(|e| self.failed(e))?);
+		Ok(true)
+	}
+
+	async fn execute(&self, run: Run) -> Result<bool, String> {
+		let response = if run.switch {
+			self.execute_stream(&run.request).await?
+		} else {
+			let response = self.api.execute(&run.request).await.map_err(|e| self.failed(e))?;
+			print_json(&response);
+			response
+		};
+		self.finish(&response, run.artifacts_dir.as_deref()).await
+	}
+
+	/// Run over the streaming endpoint, writing output as it comes; the
+	/// response is reported on stderr.
+	async fn execute_stream(&self, request: &ToolExecutionRequest) -> Result<ToolExecutionResponse, String> {
+		let mut events = self.api.execute_stream(request).await.map_err(|e| self.failed(e))?;
+		let mut exited = None;
+		while let Some(event) = events.next().await {
+			match event.map_err(|e| self.failed(e))? {
+				StreamEvent::Chunk {
+					stream,
+					data,
+					encoding,
+					..
+				} => {
+					// Binary output arrives as base64; it is written out as the
+					// tool wrote it.
+					let bytes = encoding.decode(&data).map_err(|e| format!("bad {stream} chunk: {e}"))?;
+					let _ = match stream.as_str() {
+						"stderr" => std::io::stderr().write_all(&bytes),
+						_ => std::io::stdout().write_all(&bytes).and_then(|()| std::io::stdout().flush()),
+					};
+				}
+				StreamEvent::Exited(response) => exited = Some(response),
+				StreamEvent::Started { .. } => {}
+			}
+		}
+		let response = exited.ok_or("the stream ended before the tool exited")?;
+		eprintln!("status: {}", response.status);
+		Ok(response)
+	}
+
+	async fn submit(&self, run: Run) -> Result<bool, String> {
+		let job = self.api.submit_job(&run.request).await.map_err(|e| self.failed(e))?;
+		if !run.switch {
+			print_json(&job);
+			return Ok(true);
+		}
+		eprintln!("job {} {}", job.job_id, job.state.as_str());
+		let job = self.follow(job).await?;
+		print_json(&job);
+		match &job.response {
+			Some(response) => self.finish(response, run.artifacts_dir.as_deref()).await,
+			None => Ok(false),
+		}
+	}
+
+	async fn job(&self, id: &str, follow: bool) -> Result<bool, String> {
+		let job = self.api.get_job(id).await.map_err(|e| self.failed(e))?;
+		let job = if follow { self.follow(job).await? } else { job };
+		print_json(&job);
+		Ok(job.response.as_ref().is_none_or(|response| response.status == "ok"))
+	}
+
+	/// Poll `job` until it finishes, noting each change of state on stderr.
+	async fn follow(&self, mut job: JobView) -> Result<JobView, String> {
+		while !job.state.is_finished() {
+			tokio::time::sleep(FOLLOW_INTERVAL).await;
+			let next = self.api.get_job(&job.job_id).await.map_err(|e| self.failed(e))?;
+			if next.state != job.state {
+				eprintln!("job {} {}", next.job_id, next.state.as_str());
+			}
+			job = next;
+		}
+		Ok(job)
+	}
+
+	async fn cancel(&self, id: &str) -> Result<bool, String> {
+		match self.api.cancel_job(id).await.map_err(|e| self.failed(e))? {
+			CancelOutcome::Cancelled(job) => {
+				print_json(&job);
+				Ok(true)
+			}
+			CancelOutcome::AlreadyFinished(job) => {
+				print_json(&job);
+				eprintln!("job {} had already finished", job.job_id);
+				Ok(false)
+			}
+		}
+	}
+
+	async fn artifact(&self, id: &str, output: Option<&Path>) -> Result<bool, String> {
+		let bytes = self.api.artifact(id).await.map_err(|e| self.failed(e))?;
+		match output {
+			Some(path) => std::fs::write(path, &bytes).map_err(|e| format!("{}: {e}", path.display()))?,
+			None => std::io::stdout().write_all(&bytes).map_err(|e| e.to_string())?,
+		}
+		Ok(true)
+	}
+
+	/// Download the response's artifacts into `dir`, if given; whether the
+	/// tool came back `ok`.
+	async fn finish(&self, response: &ToolExecutionResponse, dir: Option<&Path>) -> Result<bool, String> {
+		if let Some(dir) = dir {
+			let artifacts: Vec<Artifact> = match response.result.get("artifacts") {
+				Some(list) => serde_json::from_value(list.clone()).map_err(|e| format!("artifacts: {e}"))?,
+				None => Vec::new(),
+			};
+			for artifact in artifacts {
+				if !Path::new(&artifact.path).components().all(|c| matches!(c, Component::Normal(_))) {
+					return Err(format!("artifact path {:?} leaves {}", artifact.path, dir.display()));
+				}
+				let path = dir.join(&artifact.path);
+				if let Some(parent) = path.parent() {
+					std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+				}
+				let bytes = self.api.artifact(&artifact.artifact_id).await.map_err(|e| self.failed(e))?;
+				std::fs::write(&path, bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+				eprintln!("saved {} ({} bytes)", path.display(), artifact.size_bytes);
+			}
+		}
+		Ok(response.status == "ok")
+	}
+}
+
+fn print_json<T: serde::Serialize>(value: &T) {
+	println!("{}", serde_json::to_string_pretty(value).unwrap_or_default());
+}
@@ -0,0 +1,268 @@
+// Init of the sandbox's Firecracker guests: takes tool executions from the
+// host over vsock, one at a time, cleaning up after each, until the host
+// shuts the VM down. Build it statically (`--target
+// x86_64-unknown-linux-musl`) and install it in the guest image as
+// `/sbin/pagi-vm-agent`.
+
+#[allow(dead_code)]
+#[path = "../vm_protocol.rs"]
+mod vm_protocol;
+
+use pagi_sandbox_client::Base64;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::FromRawFd;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Component, Path};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use vm_protocol::{GuestFrame, HostFrame, DEFAULT_GUEST_PORT};
+
+/// Where the tool runs; the workspace the host sends is written here and
+/// sent back from here.
+const WORK_DIR: &str = "/work";
+/// Not in libc for every target.
+const AF_VSOCK: libc::c_int = 40;
+const CHUNK_BYTES: usize = 16 * 1024;
+
+type Writer = Arc<Mutex<File>>;
+
+fn main() {
+	let init = std::process::id() == 1;
+	if !init {
+		if let Err(e) = serve() {
+			eprintln!("pagi-vm-agent: {e}");
+		}
+		return;
+	}
+	mount_filesystems();
+	// A warm VM may be recycled for another execution; each connection is
+	// one. The host destroys the VM when it wants no more.
+	loop {
+		if let Err(e) = serve() {
+			eprintln!("pagi-vm-agent: {e}");
+			break;
+		}
+		clean_up();
+	}
+	// SAFETY: plain syscalls; init must not exit, so the VM is powered off
+	// instead (`reboot=k` makes Firecracker stop on it).
+	unsafe {
+		libc::sync();
+		libc::reboot(libc::RB_AUTOBOOT);
+	}
+}
+
+/// Leave nothing of the last tool for the next: no processes, and empty
+/// scratch directories.
+fn clean_up() {
+	// SAFETY: plain syscalls. As init, `kill(-1)` reaches every process but
+	// this one.
+	unsafe {
+		libc::kill(-1, libc::SIGKILL);
+		while libc::waitpid(-1, std::ptr::null_mut(), 0) > 0 {}
+	}
+	for dir in ["/tmp", WORK_DIR] {
+		let Ok(entries) = std::fs::read_dir(dir) else {
+			continue;
+		};
+		for entry in entries.flatten() {
+			let path = entry.path();
+			let removed = match entry.file_type() {
+				Ok(kind) if kind.is_dir() => std::fs::remove_dir_all(&path),
+				_ => std::fs::remove_file(&path),
+			};
+			if let Err(e) = removed {
+				eprintln!("pagi-vm-agent: removing {}: {e}", path.display());
+			}
+		}
+	}
+}
+
+/// What a tool expects to find mounted, on a read-only root.
+fn mount_filesystems() {
+	for (source, target, fstype) in [
+		("proc", "/proc", "proc"),
+		("sysfs", "/sys", "sysfs"),
+		("devtmpfs", "/dev", "devtmpfs"),
+		("tmpfs", "/tmp", "tmpfs"),
+		("tmpfs", WORK_DIR, "tmpfs"),
+	] {
+		let (source, target, fstype) = (cstring(source), cstring(target), cstring(fstype));
+		// SAFETY: NUL-terminated strings that outlive the call.
+		let rc = unsafe { libc::mount(source.as_ptr(), target.as_ptr(), fstype.as_ptr(), 0, std::ptr::null()) };
+		// The kernel may have mounted /dev already.
+		if rc != 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EBUSY) {
+			eprintln!("pagi-vm-agent: mounting {target:?}: {}", io::Error::last_os_error());
+		}
+	}
+}
+
+fn cstring(s: &str) -> CString {
+	CString::new(s).expect("no NUL bytes")
+}
+
+/// Wait for the host, then run the tool it sends.
+fn serve() -> Result<(), String> {
+	let port = match std::env::var("PAGI_VM_AGENT_PORT") {
+		Ok(port) => port.parse().map_err(|_| format!("PAGI_VM_AGENT_PORT is not a port: {port}"))?,
+		Err(_) => DEFAULT_GUEST_PORT,
+	};
+	let conn = accept(port).map_err(|e| format!("vsock port {port}: {e}"))?;
+	let reader = BufReader::new(conn.try_clone().map_err(|e| e.to_string())?);
+	let writer: Writer = Arc::new(Mutex::new(conn));
+	match run(reader, &writer) {
+		Ok(exit) => send(&writer, &exit),
+		Err(message) => send(&writer, &GuestFrame::Error { message }),
+	}
+	.map_err(|e| format!("answering the host: {e}"))
+}
+
+/// The first connection to vsock `port`.
+fn accept(port: u32) -> io::Result<File> {
+	// SAFETY: plain syscalls on a zeroed address that outlives them.
+	unsafe {
+		let socket = libc::socket(AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+		if socket < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let mut addr: libc::sockaddr_vm = std::mem::zeroed();
+		addr.svm_family = AF_VSOCK as libc::sa_family_t;
+		addr.svm_port = port;
+		addr.svm_cid = libc::VMADDR_CID_ANY;
+		let bound = libc::bind(
+			socket,
+			&addr as *const libc::sockaddr_vm as *const libc::sockaddr,
+			std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+		);
+		if bound != 0 || libc::listen(socket, 1) != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let conn = libc::accept4(socket, std::ptr::null_mut(), std::ptr::null_mut(), libc::SOCK_CLOEXEC);
+		if conn < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		libc::close(socket);
+		Ok(File::from_raw_fd(conn))
+	}
+}
+
+/// Run what the start frame asks for, feeding it stdin and streaming its
+/// output as it goes; the exit frame to end with.
+fn run(mut reader: impl BufRead + Send + 'static, writer: &Writer) -> Result<GuestFrame, String> {
+	let (argv, env, files) = match read_frame(&mut reader).map_err(|e| format!("bad start frame: {e}"))? {
+		Some(HostFrame::Start { argv, env, files }) => (argv, env, files),
+		_ => return Err("the host did not start with a start frame".to_string()),
+	};
+	for (relative, data) in files {
+		let path = work_path(&relative).ok_or_else(|| format!("{relative:?} is not a path inside {WORK_DIR}"))?;
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+		}
+		std::fs::write(&path, data.0).map_err(|e| format!("{}: {e}", path.display()))?;
+	}
+	let (program, args) = argv.split_first().ok_or("the start frame has no argv")?;
+	let mut child = Command::new(program)
+		.args(args)
+		.env_clear()
+		.envs(env)
+		.current_dir(WORK_DIR)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| format!("failed to spawn {program}: {e}"))?;
+
+	// Output is pumped while stdin is still being fed, so neither blocks
+	// the other.
+	let pumps = [
+		pump(child.stdout.take().expect("piped"), writer.clone(), |data| GuestFrame::Stdout { data }),
+		pump(child.stderr.take().expect("piped"), writer.clone(), |data| GuestFrame::Stderr { data }),
+	];
+	let mut stdin = child.stdin.take();
+	// Stdin is fed until the host ends it, or the connection does; the tool
+	// may well exit first.
+	thread::spawn(move || {
+		while let Ok(Some(HostFrame::Stdin { data })) = read_frame(&mut reader) {
+			// A tool that will not read its stdin gets it dropped.
+			if stdin.as_mut().is_some_and(|pipe| pipe.write_all(&data.0).is_err()) {
+				stdin = None;
+			}
+		}
+	});
+	let status = child.wait().map_err(|e| format!("waiting for {program}: {e}"))?;
+	for pump in pumps {
+		let _ = pump.join();
+	}
+	Ok(GuestFrame::Exit {
+		exit_code: status.code(),
+		signal: status.signal(),
+		files: collect(Path::new(WORK_DIR)).map_err(|e| format!("reading {WORK_DIR}: {e}"))?,
+	})
+}
+
+/// `relative` under the work directory, if it stays there.
+fn work_path(relative: &str) -> Option<std::path::PathBuf> {
+	let path = Path::new(relative);
+	let inside = !relative.is_empty() && path.components().all(|part| matches!(part, Component::Normal(_)));
+	inside.then(|| Path::new(WORK_DIR).join(path))
+}
+
+/// Send what `pipe` produces to the host as frames `frame` makes.
+fn pump(
+	mut pipe: impl Read + Send + 'static,
+	writer: Writer,
+	frame: fn(Base64) -> GuestFrame,
+) -> thread::JoinHandle<()> {
+	thread::spawn(move || {
+		let mut buf = vec![0; CHUNK_BYTES];
+		loop {
+			match pipe.read(&mut buf) {
+				Ok(0) | Err(_) => break,
+				Ok(n) => {
+					if send(&writer, &frame(Base64(buf[..n].to_vec()))).is_err() {
+						break;
+					}
+				}
+			}
+		}
+	})
+}
+
+/// Every regular file under `root`, by path relative to it.
+fn collect(root: &Path) -> io::Result<BTreeMap<String, Base64>> {
+	let mut files = BTreeMap::new();
+	let mut dirs = vec![root.to_path_buf()];
+	while let Some(dir) = dirs.pop() {
+		for entry in std::fs::read_dir(&dir)? {
+			let entry = entry?;
+			let kind = entry.file_type()?;
+			if kind.is_dir() {
+				dirs.push(entry.path());
+			} else if kind.is_file() {
+				let path = entry.path();
+				let relative = path.strip_prefix(root).expect("entries are under the root");
+				files.insert(relative.to_string_lossy().to_string(), Base64(std::fs::read(&path)?));
+			}
+		}
+	}
+	Ok(files)
+}
+
+fn read_frame(reader: &mut impl BufRead) -> io::Result<Option<HostFrame>> {
+	let mut line = Vec::new();
+	if reader.read_until(b'\n', &mut line)? == 0 {
+		return Ok(None);
+	}
+	serde_json::from_slice(&line).map(Some).map_err(io::Error::other)
+}
+
+fn send(writer: &Writer, frame: &GuestFrame) -> io::Result<()> {
+	let mut line = serde_json::to_vec(frame).map_err(io::Error::other)?;
+	line.push(b'\n');
+	writer.lock().unwrap().write_all(&line)
+}
@@ -0,0 +1,248 @@
+use axum::body::{to_bytes, Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::info;
+
+use crate::error::SandboxError;
+use crate::redact::{redact_path, REDACTED};
+use crate::tool_http::DEFAULT_REDACT_HEADERS;
+
+const DEFAULT_SAMPLE_RATE: f64 = 1.0;
+const DEFAULT_MAX_BYTES: usize = 16 * 1024;
+/// Args that hold credentials by their name.
+const SECRET_ARGS: &[&str] = &["password", "token", "secret", "api_key"];
+/// Where a tool request sits in a body: a request of its own, one of a
+/// batch's `requests` or one of a pipeline's `steps`.
+const REQUEST_PATHS: &[&str] = &["", "requests.*.", "steps.*."];
+
+fn default_redact_headers() -> Vec<String> {
+	DEFAULT_REDACT_HEADERS.iter().map(|h| h.to_string()).collect()
+}
+
+/// Credential args, `http_request` credential headers and the contents of
+/// input files, wherever a request sits.
+fn default_redact_paths() -> Vec<String> {
+	let mut paths = Vec::new();
+	for request in REQUEST_PATHS {
+		paths.extend(SECRET_ARGS.iter().map(|arg| format!("{request}args.{arg}")));
+		paths.extend(DEFAULT_REDACT_HEADERS.iter().map(|h| format!("{request}args.headers.{h}")));
+		paths.push(format!("{request}input_files.*"));
+	}
+	paths
+}
+
+fn default_skip_paths() -> Vec<String> {
+	["/health", "/healthz", "/readyz", "/metrics"].map(String::from).to_vec()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BodyLogConfig {
+	/// Log the bodies of REST requests and their responses. Meant for
+	/// debugging: bodies hold tool args and output.
+	pub enabled: bool,
+	/// Fraction of requests logged, from 0 to 1.
+	pub sample_rate: f64,
+	/// Bytes of each body logged; the rest is cut off.
+	pub max_bytes: usize,
+	/// Headers whose values are replaced, matched without regard to case.
+	pub redact_headers: Vec<String>,
+	/// Fields of JSON bodies whose values are replaced, as dotted paths
+	/// (`args.password`); `*` stands for any one key or array index. Keys
+	/// match without regard to case.
+	pub redact_paths: Vec<String>,
+	/// Request paths never logged.
+	pub skip_paths: Vec<String>,
+}
+
+impl Default for BodyLogConfig {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			sample_rate: DEFAULT_SAMPLE_RATE,
+			max_bytes: DEFAULT_MAX_BYTES,
+			redact_headers: default_redact_headers(),
+			redact_paths: default_redact_paths(),
+			skip_paths: default_skip_paths(),
+		}
+	}
+}
+
+impl BodyLogConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if !(0.0..=1.0).contains(&self.sample_rate) {
+			errors.push(format!("body_log.sample_rate must be between 0 and 1 (got {})", self.sample_rate));
+		}
+		if self.max_bytes == 0 {
+			errors.push("body_log.max_bytes must be positive".to_string());
+		}
+		for path in &self.redact_paths {
+			if path.is_empty() || path.split('.').any(str::is_empty) {
+				errors.push(format!("body_log.redact_paths: {path:?} is not a dotted path"));
+			}
+		}
+		errors
+	}
+}
+
+/// Logs request and response bodies, redacted, for a sample of requests.
+pub struct BodyLogger {
+	config: BodyLogConfig,
+	/// Largest request body read, as the server limits them.
+	max_request_bytes: usize,
+	redact_paths: Vec<Vec<String>>,
+}
+
+impl BodyLogger {
+	pub fn new(config: BodyLogConfig, max_request_bytes: usize) -> Arc<Self> {
+		let redact_paths = config
+			.redact_paths
+			.iter()
+			.map(|path| path.split('.').map(str::to_ascii_lowercase).collect())
+			.collect();
+		Arc::new(Self {
+			config,
+			max_request_bytes,
+			redact_paths,
+		})
+	}
+
+	fn sampled(&self, path: &str) -> bool {
+		if !self.config.enabled || self.config.skip_paths.iter().any(|p| p == path) {
+			return false;
+		}
+		self.config.sample_rate >= 1.0 || rand::thread_rng().gen_bool(self.config.sample_rate)
+	}
+
+	fn headers(&self, headers: &HeaderMap) -> Value {
+		let mut logged = Map::new();
+		for (name, value) in headers {
+			let redact = self.config.redact_headers.iter().any(|h| name.as_str().eq_ignore_ascii_case(h));
+			let value = match redact {
+				true => REDACTED.to_string(),
+				false => String::from_utf8_lossy(value.as_bytes()).to_string(),
+			};
+			logged.insert(name.to_string(), json!(value));
+		}
+		Value::Object(logged)
+	}
+
+	/// A body as it goes in the log: JSON with the configured fields
+	/// redacted, cut to `max_bytes`; anything else only by type and size.
+	fn body(&self, headers: &HeaderMap, bytes: &[u8]) -> Value {
+		if bytes.is_empty() {
+			return Value::Null;
+		}
+		let json_body = content_type(headers).is_some_and(|t| t.starts_with("application/json"));
+		let parsed = json_body.then(|| serde_json::from_slice::<Value>(bytes).ok()).flatten();
+		let Some(mut value) = parsed else {
+			return json!({
+				"omitted": format!("{} bytes of {}", bytes.len(), content_type(headers).unwrap_or("unknown type")),
+			});
+		};
+		for path in &self.redact_paths {
+			redact_path(&mut value, path);
+		}
+		let text = value.to_string();
+		if text.len() <= self.config.max_bytes {
+			return value;
+		}
+		let mut end = self.config.max_bytes;
+		while !text.is_char_boundary(end) {
+			end -= 1;
+		}
+		json!({ "truncated": format!("{}...", &text[..end]), "bytes": text.len() })
+	}
+}
+
+fn content_type(headers: &HeaderMap) -> Option<&str> {
+	headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok())
+}
+
+/// Streams (SSE, gRPC, WebSocket upgrades) are passed through unread.
+fn streams(headers: &HeaderMap) -> bool {
+	headers.contains_key(header::UPGRADE)
+		|| content_type(headers).is_some_and(|t| t.starts_with("text/event-stream") || t.starts_with("application/grpc"))
+}
+
+/// axum middleware logging a sample of requests with their bodies; see
+/// [`BodyLogConfig`].
+pub async fn log_bodies(State(logger): State<Arc<BodyLogger>>, request: Request, next: Next) -> Response {
+	if !logger.sampled(request.uri().path()) || streams(request.headers()) {
+		return next.run(request).await;
+	}
+	let started = Instant::now();
+	let (parts, body) = request.into_parts();
+	let request_bytes = match to_bytes(body, logger.max_request_bytes).await {
+		Ok(bytes) => bytes,
+		Err(_) => return SandboxError::PayloadTooLarge("length limit exceeded".to_string()).into_response(),
+	};
+	let logged_request = logger.body(&parts.headers, &request_bytes);
+	let request_headers = logger.headers(&parts.headers);
+	let method = parts.method.to_string();
+	let uri = parts.uri.to_string();
+	let response = next.run(Request::from_parts(parts, Body::from(request_bytes))).await;
+
+	let (parts, body) = response.into_parts();
+	let (body, logged_response) = if streams(&parts.headers) {
+		(body, json!({ "omitted": "stream" }))
+	} else {
+		match to_bytes(body, usize::MAX).await {
+			Ok(bytes) => {
+				let logged = logger.body(&parts.headers, &bytes);
+				(Body::from(bytes), logged)
+			}
+			Err(e) => (Body::from(Bytes::new()), json!({ "error": e.to_string() })),
+		}
+	};
+	info!(
+		method = method,
+		uri = uri,
+		status = parts.status.as_u16(),
+		elapsed_ms = started.elapsed().as_millis() as u64,
+		request_headers = %request_headers,
+		request_body = %logged_request,
+		response_headers = %logger.headers(&parts.headers),
+		response_body = %logged_response,
+		message = "HTTP exchange"
+	);
+	Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn default_paths_reach_batches_pipelines_and_input_files() {
+		let logger = BodyLogger::new(BodyLogConfig::default(), 1024);
+		let mut headers = HeaderMap::new();
+		headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+		let body = json!({
+			"requests": [{
+				"tool_name": "http_request",
+				"args": { "url": "https://example.com", "headers": { "X-Api-Key": "key", "Accept": "text/plain" } },
+			}],
+			"steps": [{
+				"id": "fit",
+				"tool_name": "python",
+				"args": { "Token": "token" },
+				"input_files": { "data.csv": "c2VjcmV0" },
+			}],
+		});
+		let logged = logger.body(&headers, body.to_string().as_bytes());
+		assert_eq!(logged["requests"][0]["args"]["headers"]["X-Api-Key"], REDACTED);
+		assert_eq!(logged["requests"][0]["args"]["headers"]["Accept"], "text/plain");
+		assert_eq!(logged["requests"][0]["args"]["url"], "https://example.com");
+		assert_eq!(logged["steps"][0]["args"]["Token"], REDACTED);
+		assert_eq!(logged["steps"][0]["input_files"]["data.csv"], REDACTED);
+	}
+}
@@ -0,0 +1,212 @@
+use lru::LruCache;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::audit::{args_sha256, Caller};
+use crate::quotas::tenant_of;
+use crate::registry::ToolManifest;
+use crate::tool_executor::ToolResult;
+use crate::workspace::ToolInput;
+
+const DEFAULT_CAPACITY: usize = 1000;
+const DEFAULT_TTL_SECS: u64 = 300;
+const DEFAULT_MAX_ENTRY_BYTES: usize = 1024 * 1024;
+const REDIS_KEY_PREFIX: &str = "pagi-sandbox:cache:";
+/// A lookup slower than this counts as a miss rather than holding up the run.
+const REDIS_TIMEOUT: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+	/// Results kept by the in-memory LRU; 0 disables caching unless Redis is
+	/// configured.
+	pub capacity: usize,
+	/// TTL of tools that do not set `cache_ttl_secs`.
+	pub default_ttl_secs: u64,
+	/// Shared cache for every replica instead of the in-memory one.
+	pub redis_url: Option<String>,
+	/// Results with more stdout plus stderr than this are not cached.
+	pub max_entry_bytes: usize,
+}
+
+impl Default for CacheConfig {
+	fn default() -> Self {
+		Self {
+			capacity: DEFAULT_CAPACITY,
+			default_ttl_secs: DEFAULT_TTL_SECS,
+			redis_url: None,
+			max_entry_bytes: DEFAULT_MAX_ENTRY_BYTES,
+		}
+	}
+}
+
+impl CacheConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if let Some(url) = &self.redis_url {
+			if !url.starts_with("redis://") && !url.starts_with("rediss://") {
+				errors.push("cache.redis_url must be a redis:// or rediss:// URL".to_string());
+			}
+		}
+		errors
+	}
+}
+
+/// Whether a `Cache-Control` request header asks for a fresh run.
+pub fn bypass_requested(cache_control: Option<&str>) -> bool {
+	cache_control.is_some_and(|value| {
+		value
+			.split(',')
+			.any(|d| matches!(d.trim().to_ascii_lowercase().as_str(), "no-cache" | "no-store"))
+	})
+}
+
+/// Identifies one cached result: the tool and a hash of its normalized args.
+pub struct CacheKey {
+	key: String,
+	ttl: Duration,
+}
+
+impl CacheKey {
+	/// How long the result stays cached.
+	pub fn ttl(&self) -> Duration {
+		self.ttl
+	}
+}
+
+enum Backend {
+	Memory(Mutex<LruCache<String, (Instant, ToolResult)>>),
+	Redis(redis::aio::ConnectionManager),
+	Disabled,
+}
+
+/// Results of `idempotent` tools, reused while their TTL lasts.
+///
+/// Only `ok` results are stored; lookup failures against Redis are logged and
+/// treated as misses, so the cache can never fail an execution.
+pub struct ResultCache {
+	backend: Backend,
+	default_ttl: Duration,
+	max_entry_bytes: usize,
+}
+
+impl ResultCache {
+	pub async fn connect(config: &CacheConfig) -> Result<Self, String> {
+		let backend = match (&config.redis_url, NonZeroUsize::new(config.capacity)) {
+			(Some(url), _) => {
+				let client = redis::Client::open(url.as_str()).map_err(|e| format!("cache.redis_url: {e}"))?;
+				let connection = client
+					.get_connection_manager()
+					.await
+					.map_err(|e| format!("cache.redis_url: {e}"))?;
+				info!(backend = "redis", message = "Result cache ready");
+				Backend::Redis(connection)
+			}
+			(None, Some(capacity)) => {
+				info!(backend = "memory", capacity = capacity.get(), message = "Result cache ready");
+				Backend::Memory(Mutex::new(LruCache::new(capacity)))
+			}
+			(None, None) => Backend::Disabled,
+		};
+		Ok(Self {
+			backend,
+			default_ttl: Duration::from_secs(config.default_ttl_secs),
+			max_entry_bytes: config.max_entry_bytes,
+		})
+	}
+
+	/// The key for `caller` running `tool` with `args` and `input`, if the
+	/// tool's results can be cached at all. Results are kept per tenant (or
+	/// subject, for callers without one), since they may depend on the
+	/// tenant's secrets and never go to another.
+	pub fn key(&self, caller: &Caller, tool: &ToolManifest, args: &Value, input: &ToolInput) -> Option<CacheKey> {
+		if !tool.idempotent || matches!(self.backend, Backend::Disabled) {
+			return None;
+		}
+		let ttl = tool.cache_ttl_secs.map(Duration::from_secs).unwrap_or(self.default_ttl);
+		if ttl.is_zero() {
+			return None;
+		}
+		// Escaped so that a tenant name cannot run into the tool's part.
+		let owner = tenant_of(caller).unwrap_or_default().replace('%', "%25").replace(':', "%3A");
+		let mut key = format!("{owner}:{}:{}", tool.id(), args_sha256(args));
+		if let Some(input) = input.sha256() {
+			key = format!("{key}:{input}");
+		}
+		Some(CacheKey { key, ttl })
+	}
+
+	pub async fn get(&self, key: &CacheKey) -> Option<ToolResult> {
+		match &self.backend {
+			Backend::Memory(entries) => {
+				let mut entries = entries.lock().unwrap();
+				match entries.get(&key.key) {
+					Some((expires, result)) if *expires > Instant::now() => Some(result.clone()),
+					Some(_) => {
+						entries.pop(&key.key);
+						None
+					}
+					None => None,
+				}
+			}
+			Backend::Redis(connection) => {
+				let mut connection = connection.clone();
+				let lookup = connection.get::<_, Option<String>>(format!("{REDIS_KEY_PREFIX}{}", key.key));
+				match tokio::time::timeout(REDIS_TIMEOUT, lookup).await {
+					Ok(Ok(value)) => value.and_then(|v| serde_json::from_str(&v).ok()),
+					Ok(Err(e)) => {
+						warn!(error = %e, message = "Result cache lookup failed");
+						None
+					}
+					Err(_) => {
+						warn!(message = "Result cache lookup timed out");
+						None
+					}
+				}
+			}
+			Backend::Disabled => None,
+		}
+	}
+
+	/// Store `result` if it is worth reusing. Redis writes happen in the
+	/// background.
+	pub fn put(&self, key: CacheKey, result: &ToolResult) {
+		if result.status != "ok" || result.stdout.len() + result.stderr.len() > self.max_entry_bytes {
+			return;
+		}
+		match &self.backend {
+			Backend::Memory(entries) => {
+				entries
+					.lock()
+					.unwrap()
+					.put(key.key, (Instant::now() + key.ttl, result.clone()));
+			}
+			Backend::Redis(connection) => {
+				let mut connection = connection.clone();
+				let value = serde_json::to_string(result).expect("tool results serialize");
+				tokio::spawn(async move {
+					let stored = connection
+						.set_ex::<_, _, ()>(format!("{REDIS_KEY_PREFIX}{}", key.key), value, key.ttl.as_secs().max(1))
+						.await;
+					if let Err(e) = stored {
+						warn!(error = %e, message = "Result cache store failed");
+					}
+				});
+			}
+			Backend::Disabled => {}
+		}
+	}
+
+	/// Forget every in-memory result, e.g. because the tool definitions
+	/// changed. Redis entries run out on their TTL.
+	pub fn clear(&self) {
+		if let Backend::Memory(entries) = &self.backend {
+			entries.lock().unwrap().clear();
+		}
+	}
+}
@@ -0,0 +1,434 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::registry::ToolLimits;
+use crate::usage::ProcessUsage;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+/// Per-execution cgroups are named `<prefix><n>` under the parent.
+const EXECUTION_PREFIX: &str = "exec-";
+/// Child of the sandbox's own cgroup that the sandbox process moves into.
+const SUPERVISOR_CGROUP: &str = "supervisor";
+const CPU_MAX_PERIOD_US: u64 = 100_000;
+/// How often CPU usage is compared against the CPU time limit.
+const CPU_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long to wait for killed processes to leave a cgroup before removing it.
+const EMPTY_WAIT: Duration = Duration::from_secs(1);
+
+const DEFAULT_CPU_WEIGHT: u32 = 100;
+const DEFAULT_MEMORY_MAX_MB: u64 = 512;
+const DEFAULT_PIDS_MAX: u64 = 128;
+
+/// Whether an isolation mechanism that needs kernel support is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IsolationMode {
+	Off,
+	/// Use it when the host supports it; otherwise warn and run tools without.
+	Auto,
+	/// Refuse to start without it.
+	Required,
+}
+
+impl FromStr for IsolationMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"off" => Ok(Self::Off),
+			"auto" => Ok(Self::Auto),
+			"required" => Ok(Self::Required),
+			_ => Err("expected off, auto or required".to_string()),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CgroupConfig {
+	pub mode: IsolationMode,
+	/// Delegated cgroup to create execution cgroups in. Defaults to the
+	/// sandbox's own cgroup, which it then leaves for a `supervisor` child.
+	pub parent: Option<PathBuf>,
+	/// Relative CPU share (`cpu.weight`, 1-10000) for tools without their own.
+	pub cpu_weight: u32,
+	pub memory_max_mb: u64,
+	pub pids_max: u64,
+	/// CPU time a tool may use before it is killed as `cpu_exceeded`.
+	pub cpu_time_limit_ms: Option<u64>,
+}
+
+impl Default for CgroupConfig {
+	fn default() -> Self {
+		Self {
+			mode: IsolationMode::Auto,
+			parent: None,
+			cpu_weight: DEFAULT_CPU_WEIGHT,
+			memory_max_mb: DEFAULT_MEMORY_MAX_MB,
+			pids_max: DEFAULT_PIDS_MAX,
+			cpu_time_limit_ms: None,
+		}
+	}
+}
+
+/// Limits for one execution, after manifest overrides.
+#[derive(Debug, Clone)]
+pub struct CgroupLimits {
+	pub cpu_weight: u32,
+	/// `cpu.max` quota in CPUs; unset leaves CPU bandwidth unlimited.
+	pub cpus: Option<f64>,
+	pub memory_max_mb: u64,
+	pub pids_max: u64,
+	pub cpu_time_limit: Option<Duration>,
+}
+
+/// Where and how to confine one subprocess; the cgroup itself is created when
+/// the process is spawned.
+#[derive(Clone)]
+pub struct CgroupSpec {
+	cgroups: Arc<Cgroups>,
+	pub limits: CgroupLimits,
+	/// CPU time of the processes run under this spec so far, `None` until one
+	/// has exited.
+	cpu_used: Arc<Mutex<Option<Duration>>>,
+}
+
+impl fmt::Debug for CgroupSpec {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("CgroupSpec")
+			.field("parent", &self.cgroups.parent)
+			.field("limits", &self.limits)
+			.finish()
+	}
+}
+
+impl CgroupSpec {
+	pub fn create(&self) -> io::Result<Cgroup> {
+		self.cgroups.create(&self.limits)
+	}
+
+	/// Add what an exited process's cgroup used to the total.
+	pub fn record_cpu_usage(&self, cgroup: &Cgroup) {
+		if let Some(usage) = cgroup.cpu_usage() {
+			let mut used = self.cpu_used.lock().unwrap();
+			*used = Some(used.unwrap_or_default() + usage);
+		}
+	}
+
+	/// CPU time of every process run under this spec, if any was measured.
+	pub fn cpu_used(&self) -> Option<Duration> {
+		*self.cpu_used.lock().unwrap()
+	}
+}
+
+/// Creates a transient cgroup v2 per subprocess execution.
+pub struct Cgroups {
+	parent: PathBuf,
+	config: CgroupConfig,
+	next: AtomicU64,
+}
+
+impl Cgroups {
+	/// Set up the parent cgroup according to `config.mode`. `Ok(None)` means
+	/// tools run without cgroups.
+	pub fn init(config: &CgroupConfig) -> Result<Option<Arc<Self>>, String> {
+		if config.mode == IsolationMode::Off {
+			return Ok(None);
+		}
+		match Self::setup(config) {
+			Ok(cgroups) => {
+				info!(parent = %cgroups.parent.display(), message = "Running subprocess tools in cgroups");
+				Ok(Some(Arc::new(cgroups)))
+			}
+			Err(e) if config.mode == IsolationMode::Auto => {
+				warn!(error = %e, message = "cgroups unavailable; subprocess tools run without resource limits");
+				Ok(None)
+			}
+			Err(e) => Err(format!("cgroup setup failed: {e}")),
+		}
+	}
+
+	fn setup(config: &CgroupConfig) -> Result<Self, String> {
+		let parent = match &config.parent {
+			Some(parent) => parent.clone(),
+			None => {
+				let root = Path::new(CGROUP_ROOT);
+				if !root.join("cgroup.controllers").exists() {
+					return Err(format!("{CGROUP_ROOT} is not a cgroup v2 mount"));
+				}
+				// Only leaf cgroups may hold processes once controllers are
+				// enabled for children, so step aside into one.
+				let own = root.join(own_cgroup()?.trim_start_matches('/'));
+				let supervisor = own.join(SUPERVISOR_CGROUP);
+				fs::create_dir_all(&supervisor).map_err(|e| format!("{}: {e}", supervisor.display()))?;
+				write_file(&supervisor, "cgroup.procs", &std::process::id().to_string())?;
+				own
+			}
+		};
+
+		let available = fs::read_to_string(parent.join("cgroup.controllers"))
+			.map_err(|e| format!("{}: {e}", parent.display()))?;
+		let missing: Vec<&str> = CONTROLLERS
+			.into_iter()
+			.filter(|c| !available.split_whitespace().any(|a| a == *c))
+			.collect();
+		if !missing.is_empty() {
+			return Err(format!(
+				"controllers {} are not available in {}",
+				missing.join(", "),
+				parent.display()
+			));
+		}
+		let enable: Vec<String> = CONTROLLERS.iter().map(|c| format!("+{c}")).collect();
+		write_file(&parent, "cgroup.subtree_control", &enable.join(" "))?;
+
+		remove_stale(&parent);
+		Ok(Self {
+			parent,
+			config: config.clone(),
+			next: AtomicU64::new(0),
+		})
+	}
+
+	/// Limits for a tool: its manifest's, else the configured defaults.
+	pub fn spec(self: &Arc<Self>, tool: &ToolLimits) -> CgroupSpec {
+		let config = &self.config;
+		CgroupSpec {
+			cgroups: self.clone(),
+			limits: CgroupLimits {
+				cpu_weight: tool.cpu_weight.unwrap_or(config.cpu_weight),
+				cpus: tool.cpus,
+				memory_max_mb: tool.memory_mb.unwrap_or(config.memory_max_mb),
+				pids_max: tool.pids_limit.unwrap_or(config.pids_max),
+				cpu_time_limit: tool
+					.cpu_time_ms
+					.or(config.cpu_time_limit_ms)
+					.map(Duration::from_millis),
+			},
+			cpu_used: Arc::default(),
+		}
+	}
+
+	fn create(&self, limits: &CgroupLimits) -> io::Result<Cgroup> {
+		let n = self.next.fetch_add(1, Ordering::Relaxed);
+		let path = self.parent.join(format!("{EXECUTION_PREFIX}{n}"));
+		fs::create_dir(&path)?;
+		// From here on, dropping the cgroup removes the directory again.
+		let mut cgroup = Cgroup {
+			path,
+			procs: None,
+			cpu_time_limit: limits.cpu_time_limit,
+		};
+		cgroup.write("cpu.weight", &limits.cpu_weight.to_string())?;
+		if let Some(cpus) = limits.cpus {
+			let quota = (cpus * CPU_MAX_PERIOD_US as f64) as u64;
+			cgroup.write("cpu.max", &format!("{quota} {CPU_MAX_PERIOD_US}"))?;
+		}
+		cgroup.write("memory.max", &(limits.memory_max_mb * 1024 * 1024).to_string())?;
+		// Without swap accounting there is nothing to turn off.
+		let _ = cgroup.write("memory.swap.max", "0");
+		cgroup.write("pids.max", &limits.pids_max.to_string())?;
+		cgroup.procs = Some(OpenOptions::new().write(true).open(cgroup.path.join("cgroup.procs"))?);
+		Ok(cgroup)
+	}
+}
+
+/// One execution's cgroup. Dropping it kills whatever is left inside and
+/// removes it.
+#[derive(Debug)]
+pub struct Cgroup {
+	path: PathBuf,
+	procs: Option<File>,
+	pub cpu_time_limit: Option<Duration>,
+}
+
+impl Cgroup {
+	/// Descriptor the child writes itself into between fork and exec.
+	pub fn procs_fd(&self) -> RawFd {
+		self.procs.as_ref().expect("procs is opened on create").as_raw_fd()
+	}
+
+	/// Resolves once the processes in the cgroup have used up the CPU time
+	/// limit; never if there is none.
+	pub async fn cpu_exhausted(&self) {
+		let Some(limit) = self.cpu_time_limit else {
+			return std::future::pending().await;
+		};
+		let mut tick = tokio::time::interval(CPU_POLL_INTERVAL);
+		loop {
+			tick.tick().await;
+			if self.cpu_usage().is_some_and(|used| used >= limit) {
+				return;
+			}
+		}
+	}
+
+	fn cpu_usage(&self) -> Option<Duration> {
+		let usec = self.stat("cpu.stat", "usage_usec")?;
+		Some(Duration::from_micros(usec))
+	}
+
+	/// Take what every process in the cgroup used over what the leader's
+	/// `rusage` says: the CPU times from `cpu.stat`, and `memory.peak`
+	/// (Linux 5.19+) where it is higher.
+	pub fn refine_usage(&self, usage: &mut ProcessUsage) {
+		if let Some(usec) = self.stat("cpu.stat", "user_usec") {
+			usage.user_cpu = Duration::from_micros(usec);
+		}
+		if let Some(usec) = self.stat("cpu.stat", "system_usec") {
+			usage.sys_cpu = Duration::from_micros(usec);
+		}
+		let peak = fs::read_to_string(self.path.join("memory.peak"))
+			.ok()
+			.and_then(|v| v.trim().parse::<u64>().ok());
+		if let Some(peak) = peak {
+			usage.peak_rss_bytes = usage.peak_rss_bytes.max(peak);
+		}
+	}
+
+	/// Whether the kernel OOM-killed any process in the cgroup.
+	pub fn oom_killed(&self) -> bool {
+		self.stat("memory.events", "oom_kill").is_some_and(|n| n > 0)
+	}
+
+	/// SIGKILL every process in the cgroup, including ones that left the
+	/// process group.
+	pub fn kill(&self) {
+		if self.write("cgroup.kill", "1").is_ok() {
+			return;
+		}
+		// cgroup.kill is Linux 5.14+; signal the members one by one instead.
+		for pid in self.pids() {
+			// SAFETY: kill has no memory-safety preconditions.
+			unsafe { libc::kill(pid, libc::SIGKILL) };
+		}
+	}
+
+	/// Wait briefly for killed processes to be reaped so the cgroup can be
+	/// removed.
+	pub async fn wait_empty(&self) {
+		let _ = tokio::time::timeout(EMPTY_WAIT, async {
+			while self.populated() {
+				tokio::time::sleep(Duration::from_millis(10)).await;
+			}
+		})
+		.await;
+	}
+
+	fn populated(&self) -> bool {
+		populated(&self.path)
+	}
+
+	fn pids(&self) -> Vec<libc::pid_t> {
+		fs::read_to_string(self.path.join("cgroup.procs"))
+			.unwrap_or_default()
+			.lines()
+			.filter_map(|l| l.trim().parse().ok())
+			.collect()
+	}
+
+	fn stat(&self, file: &str, key: &str) -> Option<u64> {
+		stat(&self.path, file, key)
+	}
+
+	fn write(&self, file: &str, value: &str) -> io::Result<()> {
+		fs::write(self.path.join(file), value)
+	}
+}
+
+impl Drop for Cgroup {
+	fn drop(&mut self) {
+		self.procs = None;
+		if !self.populated() {
+			remove(&self.path);
+			return;
+		}
+		// Dropped mid-execution: the killed processes take a moment to exit,
+		// and the directory cannot be removed until they have.
+		self.kill();
+		let path = std::mem::take(&mut self.path);
+		std::thread::spawn(move || {
+			let deadline = std::time::Instant::now() + EMPTY_WAIT;
+			while populated(&path) && std::time::Instant::now() < deadline {
+				std::thread::sleep(Duration::from_millis(10));
+			}
+			remove(&path);
+		});
+	}
+}
+
+/// A `key value` line from a flat-keyed cgroup file.
+fn stat(cgroup: &Path, file: &str, key: &str) -> Option<u64> {
+	let text = fs::read_to_string(cgroup.join(file)).ok()?;
+	text.lines().find_map(|line| {
+		let (k, v) = line.split_once(' ')?;
+		(k == key).then(|| v.trim().parse().ok()).flatten()
+	})
+}
+
+/// Unreadable counts as populated, so nothing is removed on a guess.
+fn populated(cgroup: &Path) -> bool {
+	stat(cgroup, "cgroup.events", "populated").is_none_or(|n| n > 0)
+}
+
+fn remove(path: &Path) {
+	if let Err(e) = fs::remove_dir(path) {
+		warn!(cgroup = %path.display(), error = %e, message = "Failed to remove cgroup");
+	}
+}
+
+/// Move the calling process into the cgroup whose `cgroup.procs` is open as
+/// `fd`. Runs in the child between fork and exec, so it only makes a syscall.
+pub fn join(fd: RawFd) -> io::Result<()> {
+	// SAFETY: writes one byte from a static buffer to an open descriptor.
+	let written = unsafe { libc::write(fd, b"0".as_ptr().cast(), 1) };
+	if written == 1 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+/// The sandbox's own cgroup v2 path, from `/proc/self/cgroup`.
+fn own_cgroup() -> Result<String, String> {
+	let text = fs::read_to_string("/proc/self/cgroup").map_err(|e| format!("/proc/self/cgroup: {e}"))?;
+	text.lines()
+		.find_map(|line| line.strip_prefix("0::"))
+		.map(str::to_string)
+		.ok_or_else(|| "the sandbox is not in a cgroup v2 hierarchy".to_string())
+}
+
+fn write_file(dir: &Path, file: &str, value: &str) -> Result<(), String> {
+	let path = dir.join(file);
+	File::options()
+		.write(true)
+		.open(&path)
+		.and_then(|mut f| f.write_all(value.as_bytes()))
+		.map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Remove execution cgroups left behind by a previous run of the sandbox.
+fn remove_stale(parent: &Path) {
+	let Ok(entries) = fs::read_dir(parent) else {
+		return;
+	};
+	for entry in entries.flatten() {
+		if entry.file_name().to_string_lossy().starts_with(EXECUTION_PREFIX) {
+			drop(Cgroup {
+				path: entry.path(),
+				procs: None,
+				cpu_time_limit: None,
+			});
+		}
+	}
+}
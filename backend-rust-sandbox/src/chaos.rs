@@ -0,0 +1,176 @@
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::Status;
+use tracing::warn;
+
+use crate::request_id;
+
+/// Messages a dropped gRPC stream may get through before it is cut.
+const MAX_MESSAGES_BEFORE_DROP: u64 = 16;
+
+fn default_error_statuses() -> Vec<u16> {
+	vec![500, 502, 503]
+}
+
+/// Faults injected on purpose, for testing how clients cope with a
+/// misbehaving sandbox. Off unless `enabled`; never turn it on in
+/// production. Each rate is a fraction from 0 to 1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChaosConfig {
+	pub enabled: bool,
+	/// Requests and gRPC calls held back before they are served.
+	pub latency_rate: f64,
+	/// How long those are held back, picked evenly in between.
+	pub latency_min_ms: u64,
+	pub latency_max_ms: u64,
+	/// Requests answered with one of `error_statuses` instead, and gRPC calls
+	/// failed with the matching code.
+	pub error_rate: f64,
+	pub error_statuses: Vec<u16>,
+	/// gRPC output streams cut off with `UNAVAILABLE` before their final
+	/// message.
+	pub stream_drop_rate: f64,
+	/// Execution attempts that fail with `internal_error`, as if the executor
+	/// had crashed, before the tool runs.
+	pub crash_rate: f64,
+}
+
+impl Default for ChaosConfig {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			latency_rate: 0.0,
+			latency_min_ms: 0,
+			latency_max_ms: 0,
+			error_rate: 0.0,
+			error_statuses: default_error_statuses(),
+			stream_drop_rate: 0.0,
+			crash_rate: 0.0,
+		}
+	}
+}
+
+impl ChaosConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		for (name, rate) in [
+			("latency_rate", self.latency_rate),
+			("error_rate", self.error_rate),
+			("stream_drop_rate", self.stream_drop_rate),
+			("crash_rate", self.crash_rate),
+		] {
+			if !(0.0..=1.0).contains(&rate) {
+				errors.push(format!("chaos.{name} must be between 0 and 1 (got {rate})"));
+			}
+		}
+		if self.latency_min_ms > self.latency_max_ms {
+			errors.push("chaos.latency_min_ms must not exceed chaos.latency_max_ms".to_string());
+		}
+		if self.error_rate > 0.0 && self.error_statuses.is_empty() {
+			errors.push("chaos.error_statuses must not be empty when chaos.error_rate is set".to_string());
+		}
+		for status in &self.error_statuses {
+			if !(400..=599).contains(status) {
+				errors.push(format!("chaos.error_statuses: {status} is not an HTTP error status"));
+			}
+		}
+		errors
+	}
+}
+
+/// Decides, call by call, which faults to inject.
+pub struct Chaos {
+	config: ChaosConfig,
+}
+
+impl Chaos {
+	pub fn new(config: ChaosConfig) -> Arc<Self> {
+		if config.enabled {
+			warn!(chaos = ?config, message = "Fault injection is on; this instance misbehaves on purpose");
+		}
+		Arc::new(Self { config })
+	}
+
+	fn roll(&self, rate: f64) -> bool {
+		self.config.enabled && rate > 0.0 && rand::thread_rng().gen_bool(rate)
+	}
+
+	/// Hold the caller back, if this call is one of those picked.
+	async fn delay(&self) {
+		if !self.roll(self.config.latency_rate) {
+			return;
+		}
+		let ms = rand::thread_rng().gen_range(self.config.latency_min_ms..=self.config.latency_max_ms);
+		tokio::time::sleep(Duration::from_millis(ms)).await;
+	}
+
+	/// The error status to answer with, if this request is to fail.
+	fn error_status(&self) -> Option<StatusCode> {
+		if !self.roll(self.config.error_rate) {
+			return None;
+		}
+		let status = self.config.error_statuses.choose(&mut rand::thread_rng())?;
+		StatusCode::from_u16(*status).ok()
+	}
+
+	/// Injected latency and failures for a gRPC call, before it is served.
+	pub async fn before_call(&self) -> Result<(), Status> {
+		self.delay().await;
+		let Some(status) = self.error_status() else {
+			return Ok(());
+		};
+		let message = format!("chaos: injected {}", status.as_u16());
+		Err(match status {
+			StatusCode::SERVICE_UNAVAILABLE | StatusCode::BAD_GATEWAY => Status::unavailable(message),
+			StatusCode::GATEWAY_TIMEOUT => Status::deadline_exceeded(message),
+			StatusCode::TOO_MANY_REQUESTS => Status::resource_exhausted(message),
+			_ => Status::internal(message),
+		})
+	}
+
+	/// For a gRPC output stream to be dropped, how many messages it gets
+	/// through first.
+	pub fn stream_cut(&self) -> Option<u64> {
+		self.roll(self.config.stream_drop_rate)
+			.then(|| rand::thread_rng().gen_range(0..=MAX_MESSAGES_BEFORE_DROP))
+	}
+
+	/// Whether this execution attempt is to crash.
+	pub fn crash(&self) -> bool {
+		self.roll(self.config.crash_rate)
+	}
+}
+
+/// The status a dropped stream ends with.
+pub fn stream_dropped() -> Status {
+	Status::unavailable("chaos: stream dropped")
+}
+
+/// axum middleware injecting latency and error responses into the API's
+/// requests; see [`ChaosConfig`]. The probes and `/metrics` are left alone,
+/// so orchestrators and scrapers keep seeing the instance as it is.
+pub async fn inject_http(State(chaos): State<Arc<Chaos>>, request: Request, next: Next) -> Response {
+	if !chaos.config.enabled {
+		return next.run(request).await;
+	}
+	chaos.delay().await;
+	if let Some(status) = chaos.error_status() {
+		let body = json!({
+			"code": "chaos",
+			"message": format!("chaos: injected {}", status.as_u16()),
+			"details": null,
+			"request_id": request_id::current(),
+		});
+		return (status, axum::Json(body)).into_response();
+	}
+	next.run(request).await
+}
@@ -0,0 +1,624 @@
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::clock::now_ms;
+use crate::error::SandboxError;
+use crate::metrics::METRICS;
+use crate::registry::base_name;
+use crate::scheduler::Capacity;
+use crate::session_registry::{self, FORWARDED_HEADER};
+use crate::tool::ToolExecutionRequest;
+use crate::tool_executor::{ExecutionBackend, Executor};
+
+pub const DEFAULT_KEY_PREFIX: &str = "pagi-sandbox/nodes";
+const DEFAULT_HEARTBEAT_MS: u64 = 5_000;
+const DEFAULT_NODE_TTL_MS: u64 = 15_000;
+/// The shortest session TTL Consul accepts.
+const CONSUL_MIN_TTL_MS: u64 = 10_000;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Polls of peers and calls to etcd or Consul are given up on after this
+/// long; forwarded requests take as long as they run.
+const POLL_TIMEOUT: Duration = Duration::from_secs(5);
+/// Names the node that ran a forwarded request, on its response.
+const SERVED_BY_HEADER: &str = "x-sandbox-served-by";
+
+/// How nodes find each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Discovery {
+	/// Poll each of `peers` for what it advertises.
+	#[default]
+	Static,
+	/// Keep every node under `key_prefix` in etcd, through its v3 JSON
+	/// gateway, on a lease that runs out with the node.
+	Etcd,
+	/// Keep every node under `key_prefix` in Consul's KV store, held by a
+	/// session that runs out with the node.
+	Consul,
+}
+
+impl fmt::Display for Discovery {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Static => "static",
+			Self::Etcd => "etcd",
+			Self::Consul => "consul",
+		})
+	}
+}
+
+impl FromStr for Discovery {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"static" => Ok(Self::Static),
+			"etcd" => Ok(Self::Etcd),
+			"consul" => Ok(Self::Consul),
+			_ => Err(format!("unknown cluster discovery {s:?} (static, etcd or consul)")),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClusterConfig {
+	/// Base URL at which the other nodes reach this one, e.g.
+	/// `http://10.0.0.5:8080`; unset, the node joins no cluster.
+	pub advertise_url: Option<String>,
+	/// Names the node to its peers; its advertised URL by default.
+	pub node_id: Option<String>,
+	/// Peers in the same region are preferred when forwarding.
+	pub region: Option<String>,
+	/// Backends this node runs tools with; every one when empty. A node
+	/// without Docker, say, leaves out `container`.
+	pub backends: Vec<ExecutionBackend>,
+	pub discovery: Discovery,
+	/// Base URLs of the other nodes, for `static` discovery.
+	pub peers: Vec<String>,
+	/// For `static` discovery, the bearer token the peers are polled with:
+	/// one of their API keys.
+	pub api_key: Option<String>,
+	/// etcd's or the Consul agent's base URL, e.g. `http://127.0.0.1:2379`.
+	pub registry_url: Option<String>,
+	/// An etcd auth token or a Consul ACL token.
+	pub registry_token: Option<String>,
+	/// Where the nodes are kept in etcd or Consul.
+	pub key_prefix: String,
+	/// How often the node advertises itself and looks at its peers.
+	pub heartbeat_ms: u64,
+	/// A peer not heard from for this long is left out.
+	pub node_ttl_ms: u64,
+	/// Send `execute_tool` requests this node cannot serve to a peer that
+	/// can, and relay its answer.
+	pub forward: bool,
+}
+
+impl Default for ClusterConfig {
+	fn default() -> Self {
+		Self {
+			advertise_url: None,
+			node_id: None,
+			region: None,
+			backends: Vec::new(),
+			discovery: Discovery::default(),
+			peers: Vec::new(),
+			api_key: None,
+			registry_url: None,
+			registry_token: None,
+			key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+			heartbeat_ms: DEFAULT_HEARTBEAT_MS,
+			node_ttl_ms: DEFAULT_NODE_TTL_MS,
+			forward: false,
+		}
+	}
+}
+
+impl ClusterConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		let http = |url: &str| url.starts_with("http://") || url.starts_with("https://");
+		let Some(advertise_url) = &self.advertise_url else {
+			if self.forward {
+				errors.push("cluster.forward needs cluster.advertise_url".to_string());
+			}
+			return errors;
+		};
+		if !http(advertise_url) {
+			errors.push("cluster.advertise_url must be an http:// or https:// URL".to_string());
+		}
+		if self.node_id.as_deref() == Some("") {
+			errors.push("cluster.node_id must not be empty".to_string());
+		}
+		if self.heartbeat_ms == 0 {
+			errors.push("cluster.heartbeat_ms must be positive".to_string());
+		}
+		if self.node_ttl_ms <= self.heartbeat_ms {
+			errors.push("cluster.node_ttl_ms must be longer than cluster.heartbeat_ms".to_string());
+		}
+		match (self.discovery, &self.registry_url) {
+			(Discovery::Static, _) => {
+				if self.peers.is_empty() {
+					errors.push("cluster.peers must list the other nodes with static discovery".to_string());
+				}
+				if self.peers.iter().any(|peer| !http(peer)) {
+					errors.push("cluster.peers must be http:// or https:// URLs".to_string());
+				}
+			}
+			(discovery, None) => errors.push(format!("cluster.registry_url is required with {discovery} discovery")),
+			(_, Some(url)) if !http(url) => {
+				errors.push("cluster.registry_url must be an http:// or https:// URL".to_string());
+			}
+			_ => {}
+		}
+		if self.discovery != Discovery::Static && self.key_prefix.trim_matches('/').is_empty() {
+			errors.push("cluster.key_prefix must not be empty".to_string());
+		}
+		if self.discovery == Discovery::Consul && self.node_ttl_ms < CONSUL_MIN_TTL_MS {
+			errors.push(format!("cluster.node_ttl_ms must be at least {CONSUL_MIN_TTL_MS} with Consul"));
+		}
+		errors
+	}
+}
+
+/// What a node advertises to its peers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeInfo {
+	pub node_id: String,
+	pub url: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub region: Option<String>,
+	pub backends: Vec<ExecutionBackend>,
+	/// Names of the tools it has loaded.
+	pub tools: Vec<String>,
+	pub gpus: usize,
+	pub gpus_free: usize,
+	pub capacity: Capacity,
+	pub draining: bool,
+	/// When it was last heard from, by this node's clock.
+	pub updated_at_ms: u64,
+}
+
+/// What a request needs of the node that runs it.
+struct Need<'a> {
+	tool_name: &'a str,
+	/// Unknown when this node does not have the tool.
+	backend: Option<ExecutionBackend>,
+	gpus: u32,
+}
+
+impl NodeInfo {
+	fn serves(&self, need: &Need) -> bool {
+		!self.draining
+			&& self.capacity.free() > 0
+			&& self.tools.iter().any(|tool| tool == base_name(need.tool_name))
+			&& need.backend.is_none_or(|backend| self.backends.contains(&backend))
+			&& self.gpus >= need.gpus as usize
+	}
+}
+
+/// This node as `GET /api/v1/admin/cluster` shows it, with the peers it
+/// knows.
+#[derive(Debug, Serialize)]
+pub struct ClusterView {
+	pub node: NodeInfo,
+	pub discovery: Discovery,
+	pub forward: bool,
+	pub peers: Vec<NodeInfo>,
+}
+
+/// Membership in a cluster of sandbox nodes, and forwarding between them.
+///
+/// Every heartbeat, the node advertises its backends, tools, GPUs and free
+/// capacity and takes in its peers': by polling each static peer's
+/// `/api/v1/cluster/node`, or through etcd or Consul, where an entry
+/// disappears soon after its node stops renewing it. A request the node
+/// cannot serve goes to the peer best placed to, and that peer answers it
+/// as its own.
+pub struct Cluster {
+	config: ClusterConfig,
+	node_id: String,
+	url: String,
+	backends: Vec<ExecutionBackend>,
+	executor: Arc<Executor>,
+	http: reqwest::Client,
+	/// By node id, never this node.
+	peers: RwLock<BTreeMap<String, NodeInfo>>,
+}
+
+impl Cluster {
+	/// Join the cluster `config` describes, if it names an advertised URL,
+	/// and keep up with it until `shutdown`.
+	pub fn start(
+		config: &ClusterConfig,
+		executor: Arc<Executor>,
+		shutdown: CancellationToken,
+	) -> Result<Option<Arc<Self>>, String> {
+		let Some(url) = &config.advertise_url else {
+			return Ok(None);
+		};
+		let url = url.trim_end_matches('/').to_string();
+		let http = reqwest::Client::builder()
+			.connect_timeout(CONNECT_TIMEOUT)
+			.redirect(reqwest::redirect::Policy::none())
+			.build()
+			.map_err(|e| format!("cluster client: {e}"))?;
+		let backends = match config.backends.is_empty() {
+			true => ExecutionBackend::ALL.to_vec(),
+			false => config.backends.clone(),
+		};
+		let mut config = config.clone();
+		config.key_prefix = config.key_prefix.trim_matches('/').to_string();
+		let cluster = Arc::new(Self {
+			node_id: config.node_id.clone().unwrap_or_else(|| url.clone()),
+			url,
+			backends,
+			executor,
+			http,
+			peers: RwLock::new(BTreeMap::new()),
+			config,
+		});
+		info!(
+			node_id = %cluster.node_id,
+			discovery = %cluster.config.discovery,
+			forward = cluster.config.forward,
+			message = "Joining the sandbox cluster"
+		);
+		tokio::spawn(cluster.clone().heartbeat(shutdown));
+		Ok(Some(cluster))
+	}
+
+	/// What this node advertises now.
+	pub fn node(&self) -> NodeInfo {
+		let tools = self.executor.registry().tools().map(|tool| tool.name.clone()).collect();
+		let (gpus, gpus_free) = self.executor.gpus();
+		NodeInfo {
+			node_id: self.node_id.clone(),
+			url: self.url.clone(),
+			region: self.config.region.clone(),
+			backends: self.backends.clone(),
+			tools,
+			gpus,
+			gpus_free,
+			capacity: self.executor.capacity(),
+			draining: self.executor.draining(),
+			updated_at_ms: now_ms(),
+		}
+	}
+
+	pub fn view(&self) -> ClusterView {
+		ClusterView {
+			node: self.node(),
+			discovery: self.config.discovery,
+			forward: self.config.forward,
+			peers: self.peers.read().unwrap().values().cloned().collect(),
+		}
+	}
+
+	/// Send `req` to a peer that can serve it if this node cannot; `None`
+	/// leaves it to be answered here, as are requests a peer sent on and
+	/// those no reachable peer can serve. `headers` are the request's.
+	pub async fn forward(&self, req: &ToolExecutionRequest, headers: &HeaderMap) -> Option<Response> {
+		if !self.config.forward || req.session_id.is_some() || headers.contains_key(FORWARDED_HEADER) {
+			return None;
+		}
+		let (reason, need) = self.unservable(&req.tool_name)?;
+		let Some(peer) = self.pick(&need) else {
+			METRICS.cluster_forwards.with_label_values(&[reason, "no_peer"]).inc();
+			return None;
+		};
+		info!(
+			tool_name = req.tool_name,
+			reason = reason,
+			node_id = %peer.node_id,
+			message = "Forwarding tool request to a peer that can serve it"
+		);
+		let body = serde_json::to_value(req).expect("a tool request serializes");
+		let target = format!("{}/api/v1/execute_tool", peer.url);
+		match session_registry::relay(&self.http, &target, Method::POST, headers, Some(body), &self.url).await {
+			Ok(mut response) => {
+				METRICS.cluster_forwards.with_label_values(&[reason, "forwarded"]).inc();
+				if let Ok(value) = HeaderValue::from_str(&peer.node_id) {
+					response.headers_mut().insert(HeaderName::from_static(SERVED_BY_HEADER), value);
+				}
+				Some(response)
+			}
+			// The request never got there, so it can still run here.
+			Err(e) if e.is_connect() => {
+				METRICS.cluster_forwards.with_label_values(&[reason, "unreachable"]).inc();
+				warn!(
+					node_id = %peer.node_id,
+					error = %e,
+					message = "Unable to reach cluster peer; serving the request here"
+				);
+				self.peers.write().unwrap().remove(&peer.node_id);
+				None
+			}
+			Err(e) => {
+				METRICS.cluster_forwards.with_label_values(&[reason, "failed"]).inc();
+				warn!(
+					node_id = %peer.node_id,
+					error = %e,
+					message = "Cluster peer failed to answer a forwarded request"
+				);
+				let message = "the node the request was forwarded to did not answer".to_string();
+				Some(SandboxError::BadGateway(message).into_response())
+			}
+		}
+	}
+
+	/// Why this node cannot serve a request for `tool_name`, and what a peer
+	/// would need to.
+	fn unservable<'a>(&self, tool_name: &'a str) -> Option<(&'static str, Need<'a>)> {
+		let registry = self.executor.registry();
+		let Some(tool) = registry.get(tool_name) else {
+			let need = Need {
+				tool_name,
+				backend: None,
+				gpus: 0,
+			};
+			return Some(("unknown_tool", need));
+		};
+		let need = Need {
+			tool_name,
+			backend: Some(tool.backend),
+			gpus: tool.limits.gpu.unwrap_or(0),
+		};
+		let reason = if !self.backends.contains(&tool.backend) {
+			"backend"
+		} else if need.gpus as usize > self.executor.gpus().0 {
+			"gpu"
+		} else if self.executor.draining() {
+			"draining"
+		} else if self.executor.capacity().free() == 0 {
+			"capacity"
+		} else {
+			return None;
+		};
+		Some((reason, need))
+	}
+
+	/// The peer best placed to serve `need`: one in this node's region
+	/// first, then one with the GPUs free, then the one with the most room.
+	fn pick(&self, need: &Need) -> Option<NodeInfo> {
+		let peers = self.peers.read().unwrap();
+		peers
+			.values()
+			.filter(|peer| peer.serves(need))
+			.max_by_key(|peer| {
+				let same_region = self.config.region.is_some() && peer.region == self.config.region;
+				(same_region, peer.gpus_free >= need.gpus as usize, peer.capacity.free())
+			})
+			.cloned()
+	}
+
+	async fn heartbeat(self: Arc<Self>, shutdown: CancellationToken) {
+		let mut tick = tokio::time::interval(Duration::from_millis(self.config.heartbeat_ms));
+		tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+		// The etcd lease or Consul session the entry is held by.
+		let mut lease: Option<String> = None;
+		loop {
+			tokio::select! {
+				_ = tick.tick() => {}
+				_ = shutdown.cancelled() => break,
+			}
+			let discovery = self.config.discovery;
+			if let Err(e) = self.advertise(&mut lease).await {
+				warn!(discovery = %discovery, error = %e, message = "Unable to advertise this node to the cluster");
+			}
+			match self.discover().await {
+				Ok(nodes) => self.update(nodes),
+				Err(e) => warn!(discovery = %discovery, error = %e, message = "Unable to list cluster nodes"),
+			}
+		}
+		if let Some(lease) = lease {
+			self.withdraw(&lease).await;
+		}
+	}
+
+	/// Take in what the peers advertise, and leave out those not heard
+	/// from in time.
+	fn update(&self, nodes: Vec<NodeInfo>) {
+		let now_ms = now_ms();
+		let mut peers = self.peers.write().unwrap();
+		for mut node in nodes.into_iter().filter(|node| node.node_id != self.node_id) {
+			node.updated_at_ms = now_ms;
+			if !peers.contains_key(&node.node_id) {
+				info!(node_id = %node.node_id, url = %node.url, message = "Cluster peer joined");
+			}
+			peers.insert(node.node_id.clone(), node);
+		}
+		let cutoff_ms = now_ms.saturating_sub(self.config.node_ttl_ms);
+		peers.retain(|node_id, node| {
+			let live = node.updated_at_ms >= cutoff_ms;
+			if !live {
+				info!(node_id = %node_id, message = "Cluster peer left");
+			}
+			live
+		});
+		METRICS.cluster_peers.set(peers.len() as i64);
+	}
+
+	/// Write this node's entry, renewing what holds it; static peers poll
+	/// the node instead.
+	async fn advertise(&self, lease: &mut Option<String>) -> Result<(), String> {
+		let value = serde_json::to_string(&self.node()).expect("a node serializes");
+		let ttl_secs = self.config.node_ttl_ms.div_ceil(1000);
+		match self.config.discovery {
+			Discovery::Static => Ok(()),
+			Discovery::Etcd => {
+				if let Some(id) = lease.as_deref() {
+					let kept = self.etcd("/v3/lease/keepalive", json!({ "ID": id })).await?;
+					// A lease that ran out comes back without a TTL.
+					if kept["result"]["TTL"].as_str().is_none_or(|ttl| ttl == "0") {
+						*lease = None;
+					}
+				}
+				if lease.is_none() {
+					let granted = self.etcd("/v3/lease/grant", json!({ "TTL": ttl_secs })).await?;
+					let id = granted["ID"].as_str().ok_or("etcd granted no lease")?;
+					*lease = Some(id.to_string());
+				}
+				let put = json!({
+					"key": STANDARD.encode(self.key()),
+					"value": STANDARD.encode(value),
+					"lease": lease.as_deref(),
+				});
+				self.etcd("/v3/kv/put", put).await.map(drop)
+			}
+			Discovery::Consul => {
+				if let Some(id) = lease.as_deref() {
+					let renewed = self.consul(Method::PUT, &format!("/v1/session/renew/{id}"), None).await?;
+					if renewed.is_none() {
+						*lease = None;
+					}
+				}
+				if lease.is_none() {
+					let session = json!({
+						"Name": format!("pagi-sandbox {}", self.node_id),
+						"TTL": format!("{ttl_secs}s"),
+						"Behavior": "delete",
+						"LockDelay": "0s",
+					});
+					let created = self.consul(Method::PUT, "/v1/session/create", Some(session.to_string())).await?;
+					let id = created.as_ref().and_then(|c| c["ID"].as_str()).ok_or("Consul created no session")?;
+					*lease = Some(id.to_string());
+				}
+				let path = format!("/v1/kv/{}?acquire={}", self.key(), lease.as_deref().unwrap_or_default());
+				match self.consul(Method::PUT, &path, Some(value)).await? {
+					Some(Value::Bool(true)) => Ok(()),
+					_ => Err("Consul refused the node's entry".to_string()),
+				}
+			}
+		}
+	}
+
+	/// What every node advertises, this one included.
+	async fn discover(&self) -> Result<Vec<NodeInfo>, String> {
+		let entries: Vec<String> = match self.config.discovery {
+			Discovery::Static => {
+				let polls = self
+					.config
+					.peers
+					.iter()
+					.map(|peer| peer.trim_end_matches('/'))
+					.filter(|peer| *peer != self.url)
+					.map(|peer| self.poll(peer));
+				// Peers that do not answer are left to age out.
+				return Ok(futures::future::join_all(polls).await.into_iter().flatten().collect());
+			}
+			Discovery::Etcd => {
+				let prefix = format!("{}/", self.config.key_prefix);
+				let mut range_end = prefix.clone().into_bytes();
+				*range_end.last_mut().expect("the prefix ends with a slash") += 1;
+				let range = json!({ "key": STANDARD.encode(&prefix), "range_end": STANDARD.encode(range_end) });
+				let listed = self.etcd("/v3/kv/range", range).await?;
+				let values = listed["kvs"].as_array().cloned().unwrap_or_default();
+				values
+					.iter()
+					.filter_map(|kv| STANDARD.decode(kv["value"].as_str()?).ok())
+					.filter_map(|value| String::from_utf8(value).ok())
+					.collect()
+			}
+			Discovery::Consul => {
+				let path = format!("/v1/kv/{}/?recurse=true", self.config.key_prefix);
+				let listed = self.consul(Method::GET, &path, None).await?.unwrap_or_default();
+				let values = listed.as_array().cloned().unwrap_or_default();
+				values
+					.iter()
+					.filter_map(|kv| STANDARD.decode(kv["Value"].as_str()?).ok())
+					.filter_map(|value| String::from_utf8(value).ok())
+					.collect()
+			}
+		};
+		Ok(entries.iter().filter_map(|entry| serde_json::from_str(entry).ok()).collect())
+	}
+
+	async fn poll(&self, peer: &str) -> Option<NodeInfo> {
+		let mut request = self.http.get(format!("{peer}/api/v1/cluster/node")).timeout(POLL_TIMEOUT);
+		if let Some(key) = &self.config.api_key {
+			request = request.bearer_auth(key);
+		}
+		let reply = request.send().await.ok()?.error_for_status().ok()?;
+		reply.json().await.ok()
+	}
+
+	/// Remove this node's entry on the way out, rather than have it linger
+	/// until it runs out.
+	async fn withdraw(&self, lease: &str) {
+		let withdrawn = match self.config.discovery {
+			Discovery::Static => Ok(()),
+			Discovery::Etcd => self.etcd("/v3/lease/revoke", json!({ "ID": lease })).await.map(drop),
+			Discovery::Consul => {
+				let path = format!("/v1/session/destroy/{lease}");
+				self.consul(Method::PUT, &path, None).await.map(drop)
+			}
+		};
+		match withdrawn {
+			Ok(()) => info!(node_id = %self.node_id, message = "Left the sandbox cluster"),
+			Err(e) => warn!(error = %e, message = "Unable to remove this node from the cluster registry"),
+		}
+	}
+
+	/// This node's key in etcd or Consul.
+	fn key(&self) -> String {
+		let name: String = self
+			.node_id
+			.chars()
+			.map(|c| match c.is_ascii_alphanumeric() || "-_.".contains(c) {
+				true => c,
+				false => '_',
+			})
+			.collect();
+		format!("{}/{name}", self.config.key_prefix)
+	}
+
+	async fn etcd(&self, path: &str, body: Value) -> Result<Value, String> {
+		let url = format!("{}{path}", self.registry_url());
+		let mut request = self.http.post(url).timeout(POLL_TIMEOUT).json(&body);
+		if let Some(token) = &self.config.registry_token {
+			request = request.header("authorization", token);
+		}
+		let reply = request.send().await.map_err(|e| e.to_string())?;
+		let status = reply.status();
+		let body: Value = reply.json().await.map_err(|e| e.to_string())?;
+		match status.is_success() {
+			true => Ok(body),
+			false => Err(format!("etcd answered {status}: {}", body["message"].as_str().unwrap_or_default())),
+		}
+	}
+
+	/// Consul's answer, or `None` for `404`, which it answers for missing
+	/// keys and sessions.
+	async fn consul(&self, method: Method, path: &str, body: Option<String>) -> Result<Option<Value>, String> {
+		let url = format!("{}{path}", self.registry_url());
+		let mut request = self.http.request(method, url).timeout(POLL_TIMEOUT);
+		if let Some(token) = &self.config.registry_token {
+			request = request.header("x-consul-token", token);
+		}
+		if let Some(body) = body {
+			request = request.body(body);
+		}
+		let reply = request.send().await.map_err(|e| e.to_string())?;
+		match reply.status() {
+			StatusCode::NOT_FOUND => Ok(None),
+			status if status.is_success() => reply.json().await.map(Some).map_err(|e| e.to_string()),
+			status => Err(format!("Consul answered {status}: {}", reply.text().await.unwrap_or_default().trim())),
+		}
+	}
+
+	fn registry_url(&self) -> &str {
+		self.config.registry_url.as_deref().unwrap_or_default().trim_end_matches('/')
+	}
+}
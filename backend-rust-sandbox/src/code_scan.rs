@@ -0,0 +1,491 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// Longest piece of code quoted in a finding.
+const MAX_SNIPPET_CHARS: usize = 120;
+
+/// What becomes of a request whose code a rule matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanAction {
+	/// The code is not run; the result has status `policy_violation`.
+	#[default]
+	Reject,
+	/// The code runs, and the finding comes back with its result.
+	Flag,
+}
+
+impl FromStr for ScanAction {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"reject" => Ok(Self::Reject),
+			"flag" => Ok(Self::Flag),
+			_ => Err(format!("expected reject or flag (got {s:?})")),
+		}
+	}
+}
+
+impl ScanAction {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Reject => "reject",
+			Self::Flag => "flag",
+		}
+	}
+}
+
+/// One check of submitted code, as a regular expression or as a pattern in
+/// the style of semgrep's: `...` stands for anything (across lines too),
+/// `$NAME` for an identifier or dotted name, and space between tokens for
+/// any space, none included. So `subprocess.$F(..., shell=True)` finds
+/// `subprocess.run(["ls"], shell = True)`. Matching is on the text, not on
+/// a syntax tree, so comments and strings are searched too.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanRule {
+	/// Names the rule in findings.
+	pub id: String,
+	/// A regular expression; either this or `pattern`.
+	pub regex: Option<String>,
+	pub pattern: Option<String>,
+	/// Languages the rule applies to (`python`, `javascript`, `rust`, `go`,
+	/// `java`); every language when empty.
+	pub languages: Vec<String>,
+	pub action: ScanAction,
+	/// Why the match is a problem, for the caller.
+	pub message: String,
+}
+
+/// Modules submitted code may not import.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlockedImports {
+	/// Python modules; `os` blocks `os.path` too.
+	pub python: Vec<String>,
+	/// JavaScript modules, with or without `node:`; `fs` blocks `fs/promises`
+	/// too.
+	pub javascript: Vec<String>,
+	pub action: ScanAction,
+}
+
+/// Checks run on the `code` of `execute_code`, `python` and `javascript`
+/// requests before anything runs. None by default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CodeScanConfig {
+	pub rules: Vec<ScanRule>,
+	pub blocked_imports: BlockedImports,
+}
+
+impl CodeScanConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		for (i, rule) in self.rules.iter().enumerate() {
+			let at = format!("executor.code_scan.rules[{i}]");
+			if rule.id.is_empty() {
+				errors.push(format!("{at}: id must not be empty"));
+			}
+			if let Err(e) = compile(rule) {
+				errors.push(format!("{at} ({:?}): {e}", rule.id));
+			}
+		}
+		errors
+	}
+}
+
+/// A match of one rule in submitted code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyViolation {
+	/// The rule's id, or `blocked_import`.
+	pub rule: String,
+	pub message: String,
+	pub action: ScanAction,
+	/// 1-based, where the match starts.
+	pub line: usize,
+	pub column: usize,
+	/// The matched code, shortened to its first line.
+	pub snippet: String,
+}
+
+impl fmt::Display for PolicyViolation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} at line {}:{}: {}", self.rule, self.line, self.column, self.message)
+	}
+}
+
+struct CompiledRule {
+	id: String,
+	regex: Regex,
+	languages: Vec<String>,
+	action: ScanAction,
+	message: String,
+}
+
+/// The configured checks, compiled once.
+pub struct CodeScanner {
+	rules: Vec<CompiledRule>,
+	imports: BlockedImports,
+	python_import: Regex,
+	javascript_import: Regex,
+}
+
+impl CodeScanner {
+	pub fn new(config: &CodeScanConfig) -> Result<Self, String> {
+		let rules = config
+			.rules
+			.iter()
+			.map(|rule| {
+				Ok(CompiledRule {
+					id: rule.id.clone(),
+					regex: compile(rule).map_err(|e| format!("code scan rule {:?}: {e}", rule.id))?,
+					languages: rule.languages.clone(),
+					action: rule.action,
+					message: rule.message.clone(),
+				})
+			})
+			.collect::<Result<_, String>>()?;
+		Ok(Self {
+			rules,
+			imports: config.blocked_imports.clone(),
+			python_import: Regex::new(
+				r#"(?m)^[ \t]*(?:from[ \t]+([\w.]+)[ \t]+import\b|import[ \t]+([\w., \t]+))|\b(?:__import__|import_module)\(\s*["']([\w.]+)["']"#,
+			)
+			.expect("valid regex"),
+			javascript_import: Regex::new(
+				r#"\brequire\(\s*["'`]([^"'`]+)["'`]\s*\)|\bimport\s*\(\s*["'`]([^"'`]+)["'`]|\bimport\s+(?:[\w*{}\s,$]+\s+from\s+)?["']([^"']+)["']|\bfrom\s+["']([^"']+)["']"#,
+			)
+			.expect("valid regex"),
+		})
+	}
+
+	/// Nothing is checked.
+	pub fn is_empty(&self) -> bool {
+		self.rules.is_empty() && self.imports.python.is_empty() && self.imports.javascript.is_empty()
+	}
+
+	/// Findings in the code a request to `tool_name` would run, in the order
+	/// they appear; none for tools that run no submitted code.
+	pub fn scan_request(&self, tool_name: &str, args: &Value) -> Vec<PolicyViolation> {
+		if self.is_empty() {
+			return Vec::new();
+		}
+		let language = match tool_name {
+			"execute_code" => args.get("language").and_then(Value::as_str).unwrap_or("python"),
+			"python" => "python",
+			"javascript" => "javascript",
+			_ => return Vec::new(),
+		};
+		match args.get("code").and_then(Value::as_str) {
+			Some(code) => self.scan(language, code),
+			None => Vec::new(),
+		}
+	}
+
+	pub fn scan(&self, language: &str, code: &str) -> Vec<PolicyViolation> {
+		let mut found = Vec::new();
+		for rule in &self.rules {
+			if !rule.languages.is_empty() && !rule.languages.iter().any(|l| l == language) {
+				continue;
+			}
+			for m in rule.regex.find_iter(code) {
+				found.push(violation(code, m.start(), m.as_str(), &rule.id, &rule.message, rule.action));
+			}
+		}
+		match language {
+			"python" => self.scan_python_imports(code, &mut found),
+			"javascript" => self.scan_javascript_imports(code, &mut found),
+			_ => {}
+		}
+		found.sort_by_key(|v| (v.line, v.column));
+		found
+	}
+
+	fn scan_python_imports(&self, code: &str, found: &mut Vec<PolicyViolation>) {
+		if self.imports.python.is_empty() {
+			return;
+		}
+		for caps in self.python_import.captures_iter(code) {
+			let whole = caps.get(0).expect("the whole match");
+			let modules: Vec<&str> = match (caps.get(1), caps.get(2), caps.get(3)) {
+				(Some(from), _, _) => vec![from.as_str()],
+				// `import a.b as c, d`
+				(_, Some(list), _) => list
+					.as_str()
+					.split(',')
+					.filter_map(|item| item.split_whitespace().next())
+					.collect(),
+				(_, _, Some(dynamic)) => vec![dynamic.as_str()],
+				_ => continue,
+			};
+			for module in modules {
+				let blocked = self.imports.python.iter().find(|b| {
+					module == b.as_str() || module.strip_prefix(b.as_str()).is_some_and(|rest| rest.starts_with('.'))
+				});
+				if let Some(blocked) = blocked {
+					found.push(violation(
+						code,
+						whole.start(),
+						whole.as_str(),
+						"blocked_import",
+						&format!("importing {module:?} is not allowed ({blocked:?} is blocked)"),
+						self.imports.action,
+					));
+				}
+			}
+		}
+	}
+
+	fn scan_javascript_imports(&self, code: &str, found: &mut Vec<PolicyViolation>) {
+		if self.imports.javascript.is_empty() {
+			return;
+		}
+		for caps in self.javascript_import.captures_iter(code) {
+			let whole = caps.get(0).expect("the whole match");
+			let Some(specifier) = (1..=4).find_map(|i| caps.get(i)).map(|m| m.as_str()) else {
+				continue;
+			};
+			let module = specifier.strip_prefix("node:").unwrap_or(specifier);
+			let blocked = self.imports.javascript.iter().find(|b| {
+				let b = b.strip_prefix("node:").unwrap_or(b);
+				module == b || module.strip_prefix(b).is_some_and(|rest| rest.starts_with('/'))
+			});
+			if let Some(blocked) = blocked {
+				found.push(violation(
+					code,
+					whole.start(),
+					whole.as_str(),
+					"blocked_import",
+					&format!("importing {specifier:?} is not allowed ({blocked:?} is blocked)"),
+					self.imports.action,
+				));
+			}
+		}
+	}
+}
+
+/// Whether any of `violations` keeps the code from running.
+pub fn rejects(violations: &[PolicyViolation]) -> bool {
+	violations.iter().any(|v| v.action == ScanAction::Reject)
+}
+
+fn violation(code: &str, start: usize, matched: &str, rule: &str, message: &str, action: ScanAction) -> PolicyViolation {
+	let before = &code[..start];
+	let line = before.matches('\n').count() + 1;
+	let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+	let first = matched.lines().next().unwrap_or_default().trim();
+	PolicyViolation {
+		rule: rule.to_string(),
+		message: message.to_string(),
+		action,
+		line,
+		column,
+		snippet: first.chars().take(MAX_SNIPPET_CHARS).collect(),
+	}
+}
+
+fn compile(rule: &ScanRule) -> Result<Regex, String> {
+	let source = match (&rule.regex, &rule.pattern) {
+		(Some(regex), None) => regex.clone(),
+		(None, Some(pattern)) => pattern_regex(pattern)?,
+		_ => return Err("set exactly one of regex and pattern".to_string()),
+	};
+	Regex::new(&source).map_err(|e| e.to_string())
+}
+
+/// The regular expression a semgrep-style `pattern` stands for.
+fn pattern_regex(pattern: &str) -> Result<String, String> {
+	let mut tokens: Vec<(bool, String)> = Vec::new();
+	let mut rest = pattern;
+	loop {
+		rest = rest.trim_start();
+		let Some(c) = rest.chars().next() else {
+			break;
+		};
+		let (regex, word, len) = if rest.starts_with("...") {
+			("(?s:.*?)".to_string(), false, 3)
+		} else if c == '$' && rest[1..].starts_with(|c: char| c.is_ascii_uppercase()) {
+			let len = 1 + rest[1..]
+				.find(|c: char| !(c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'))
+				.unwrap_or(rest.len() - 1);
+			(r"[A-Za-z_$][\w$]*(?:\s*\.\s*[A-Za-z_$][\w$]*)*".to_string(), true, len)
+		} else if c.is_alphanumeric() || c == '_' {
+			let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+			(format!(r"\b{}\b", regex::escape(&rest[..len])), true, len)
+		} else {
+			(regex::escape(&rest[..c.len_utf8()]), false, c.len_utf8())
+		};
+		tokens.push((word, regex));
+		rest = &rest[len..];
+	}
+	if tokens.is_empty() {
+		return Err("the pattern is empty".to_string());
+	}
+	let mut source = String::new();
+	let mut last_word = None;
+	for (word, regex) in tokens {
+		// Two words need space between them; anything else may have none.
+		match last_word {
+			Some(true) if word => source.push_str(r"\s+"),
+			Some(_) => source.push_str(r"\s*"),
+			None => {}
+		}
+		source.push_str(&regex);
+		last_word = Some(word);
+	}
+	Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rule(id: &str, regex: Option<&str>, pattern: Option<&str>) -> ScanRule {
+		ScanRule {
+			id: id.to_string(),
+			regex: regex.map(str::to_string),
+			pattern: pattern.map(str::to_string),
+			action: ScanAction::Flag,
+			..Default::default()
+		}
+	}
+
+	fn scanner(rules: Vec<ScanRule>, python: &[&str], javascript: &[&str]) -> CodeScanner {
+		CodeScanner::new(&CodeScanConfig {
+			rules,
+			blocked_imports: BlockedImports {
+				python: python.iter().map(|m| m.to_string()).collect(),
+				javascript: javascript.iter().map(|m| m.to_string()).collect(),
+				action: ScanAction::Reject,
+			},
+		})
+		.unwrap()
+	}
+
+	/// Each finding as `rule@line:column`.
+	fn found(violations: &[PolicyViolation]) -> Vec<String> {
+		violations.iter().map(|v| format!("{}@{}:{}", v.rule, v.line, v.column)).collect()
+	}
+
+	#[test]
+	fn overlapping_matches() {
+		let scanner = scanner(
+			vec![
+				rule("system", Some(r"os\.system"), None),
+				rule("shell", None, Some("os.$F(...)")),
+				rule("aa", Some("aa"), None),
+			],
+			&["os"],
+			&[],
+		);
+		let code = "import os\nos.system('ls')\naaaa\n";
+		let violations = scanner.scan("python", code);
+		// Rules see the same code independently; one rule's matches do not
+		// overlap each other; findings come in the order they appear.
+		assert_eq!(
+			found(&violations),
+			["blocked_import@1:1", "system@2:1", "shell@2:1", "aa@3:1", "aa@3:3"]
+		);
+		assert_eq!(violations[2].snippet, "os.system('ls')");
+		assert!(rejects(&violations));
+		assert!(!rejects(&violations[1..]));
+	}
+
+	#[test]
+	fn patterns_span_space_and_lines() {
+		let scanner = scanner(vec![rule("shell", None, Some("subprocess.$F(..., shell=True)"))], &[], &[]);
+		let code = "subprocess.run(\n    [\"ls\"],\n    shell = True)\nsubprocess.run([\"ls\"], shell=False)";
+		let violations = scanner.scan("python", code);
+		assert_eq!(found(&violations), ["shell@1:1"]);
+		// The snippet is the first line of the match.
+		assert_eq!(violations[0].snippet, "subprocess.run(");
+		assert!(scanner.scan("python", "mysubprocess.run(shell=True)").is_empty());
+	}
+
+	#[test]
+	fn multibyte_code() {
+		let scanner = scanner(vec![rule("eval", None, Some("eval(...)"))], &["os"], &["fs"]);
+		let violations = scanner.scan("python", "données = «x»; résultat = eval(données)\n# ✓ import os");
+		// Columns count characters, not bytes.
+		assert_eq!(found(&violations), ["eval@1:27"]);
+		assert_eq!(violations[0].snippet, "eval(données)");
+		let violations = scanner.scan("javascript", "const π = 3.14;\nconst 文件 = require('node:fs/promises');");
+		assert_eq!(found(&violations), ["blocked_import@2:12"]);
+		let long = format!("eval({})", "é".repeat(200));
+		let snippet = &scanner.scan("python", &long)[0].snippet;
+		assert_eq!(snippet.chars().count(), MAX_SNIPPET_CHARS);
+		assert!(snippet.starts_with("eval(éé"));
+	}
+
+	#[test]
+	fn blocked_python_imports() {
+		let scanner = scanner(Vec::new(), &["os", "ctypes"], &[]);
+		for code in [
+			"import os",
+			"import os.path",
+			"import sys, os as o",
+			"  from os import path",
+			"from os.path import join",
+			"__import__('ctypes')",
+			"importlib.import_module(\"os.path\")",
+		] {
+			assert_eq!(found(&scanner.scan("python", code)).len(), 1, "{code}");
+		}
+		for code in ["import osx", "from oslo import x", "import sys", "x = 'import os'", "print(os)"] {
+			assert!(scanner.scan("python", code).is_empty(), "{code}");
+		}
+	}
+
+	#[test]
+	fn blocked_javascript_imports() {
+		let scanner = scanner(Vec::new(), &[], &["fs", "node:child_process"]);
+		for code in [
+			"const fs = require('fs')",
+			"require(\"node:fs\")",
+			"import { readFile } from 'fs/promises'",
+			"import * as cp from \"child_process\"",
+			"import 'fs'",
+			"await import(`node:child_process`)",
+		] {
+			assert_eq!(found(&scanner.scan("javascript", code)).len(), 1, "{code}");
+		}
+		for code in ["require('fsevents')", "import x from 'path'", "const fs = 1"] {
+			assert!(scanner.scan("javascript", code).is_empty(), "{code}");
+		}
+	}
+
+	#[test]
+	fn clean_code_has_no_findings() {
+		let scanner = scanner(vec![rule("eval", None, Some("eval(...)"))], &["os"], &["fs"]);
+		assert!(scanner.scan("python", "").is_empty());
+		assert!(scanner.scan("python", "import json\nprint(json.dumps({'é': 1}))").is_empty());
+		assert!(scanner.scan("python", "import json\nevaluate(x)").is_empty());
+		assert!(scanner.scan("rust", "fn main() { let os = 1; }").is_empty());
+		// Only the tools that run submitted code are scanned.
+		let args = serde_json::json!({ "code": "eval(1)" });
+		assert!(scanner.scan_request("http_get", &args).is_empty());
+		assert_eq!(scanner.scan_request("python", &args).len(), 1);
+		let empty = CodeScanner::new(&CodeScanConfig::default()).unwrap();
+		assert!(empty.is_empty());
+		assert!(empty.scan_request("python", &args).is_empty());
+	}
+
+	#[test]
+	fn rules_are_validated() {
+		let config = CodeScanConfig {
+			rules: vec![
+				rule("", Some("x"), None),
+				rule("both", Some("x"), Some("x")),
+				rule("neither", None, None),
+				rule("bad", Some("("), None),
+				rule("blank", None, Some("   ")),
+			],
+			..Default::default()
+		};
+		assert_eq!(config.validate().len(), 5);
+	}
+}
@@ -0,0 +1,78 @@
+use axum::http::Extensions;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use tonic::codec::CompressionEncoding;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+use tower_http::decompression::RequestDecompressionLayer;
+
+/// Marks a response whose body is sent as it is produced, such as a
+/// streamed execution, so that compression does not hold it back to fill
+/// its buffers.
+#[derive(Debug, Clone, Copy)]
+pub struct Unbuffered;
+
+/// Compress REST responses of at least `min_bytes` (or of unknown length)
+/// with gzip, zstd or brotli, whichever `Accept-Encoding` prefers. gRPC,
+/// images, event streams and [`Unbuffered`] responses are sent as they are.
+pub fn response_layer(min_bytes: u16) -> CompressionLayer<impl Predicate> {
+	let predicate = SizeAbove::new(min_bytes)
+		.and(NotForContentType::GRPC)
+		.and(NotForContentType::IMAGES)
+		.and(NotForContentType::SSE)
+		.and(|_: axum::http::StatusCode, _: axum::http::Version, _: &axum::http::HeaderMap, extensions: &Extensions| {
+			extensions.get::<Unbuffered>().is_none()
+		});
+	CompressionLayer::new().compress_when(predicate)
+}
+
+/// Inflate request bodies sent with a gzip, zstd or brotli
+/// `Content-Encoding` before anything reads them; unknown encodings are
+/// refused with `415`. Body limits apply to the inflated size.
+pub fn request_layer() -> RequestDecompressionLayer {
+	RequestDecompressionLayer::new()
+}
+
+/// How gRPC messages are compressed on the way out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GrpcCompression {
+	#[default]
+	None,
+	Gzip,
+	Zstd,
+}
+
+impl GrpcCompression {
+	pub fn encoding(self) -> Option<CompressionEncoding> {
+		match self {
+			Self::None => None,
+			Self::Gzip => Some(CompressionEncoding::Gzip),
+			Self::Zstd => Some(CompressionEncoding::Zstd),
+		}
+	}
+}
+
+impl fmt::Display for GrpcCompression {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::None => "none",
+			Self::Gzip => "gzip",
+			Self::Zstd => "zstd",
+		})
+	}
+}
+
+impl FromStr for GrpcCompression {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"none" => Ok(Self::None),
+			"gzip" => Ok(Self::Gzip),
+			"zstd" => Ok(Self::Zstd),
+			_ => Err(format!("unknown gRPC compression {s:?} (none, gzip or zstd)")),
+		}
+	}
+}
@@ -0,0 +1,960 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::Level;
+
+use crate::audit::AuditConfig;
+use crate::auth::AuthConfig;
+use crate::batch::BatchConfig;
+use crate::body_log::BodyLogConfig;
+use crate::authz::RbacConfig;
+use crate::cache::CacheConfig;
+use crate::chaos::ChaosConfig;
+use crate::cluster::ClusterConfig;
+use crate::events::EventsConfig;
+use crate::executor_firecracker::DEFAULT_CLASS;
+use crate::egress::NetworkPolicy;
+use crate::history::{redact_url, HistoryConfig};
+use crate::idempotency::IdempotencyConfig;
+use crate::jobs::JobsConfig;
+use crate::logging::{is_syslog_target, LoggingConfig};
+use crate::mcp::McpConfig;
+use crate::policy::PolicyConfig;
+use crate::quotas::QuotaConfig;
+use crate::ratelimit::{BucketConfig, RateLimitConfig};
+use crate::schedules::SchedulesConfig;
+use crate::sessions::SessionsConfig;
+use crate::shared_limits::SharedLimitsConfig;
+use crate::tls::TlsConfig;
+use crate::secrets::is_valid_env_pattern;
+use crate::tool_executor::ExecutorConfig;
+use crate::webhooks::WebhooksConfig;
+use crate::work_queue::QueueConfig;
+
+/// Names the optional settings file; the environment overrides what it sets.
+pub const CONFIG_FILE_ENV: &str = "PAGI_SANDBOX_CONFIG";
+const DEFAULT_PORT: u16 = 8001;
+const DEFAULT_GRPC_PORT: u16 = 50053;
+const DEFAULT_LOG_LEVEL: &str = "info";
+const DEFAULT_DRAIN_SECS: u64 = 30;
+const DEFAULT_MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+const DEFAULT_COMPRESSION_MIN_BYTES: u16 = 1024;
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+	pub http_port: u16,
+	pub grpc_port: u16,
+	/// Serve gRPC on `http_port` next to the REST API, told apart by content
+	/// type; `grpc_port` is then unused.
+	pub single_port: bool,
+	/// Baseline log level; `RUST_LOG` directives are added on top.
+	pub log_level: String,
+	/// How long shutdown waits for in-flight work before aborting it.
+	pub shutdown_drain_secs: u64,
+	/// Serve Swagger UI for `/api/v1/openapi.json` at `/api/v1/docs`, from
+	/// assets built into the binary.
+	pub swagger_ui: bool,
+	/// Serve gRPC server reflection, so clients like grpcurl need no protos.
+	pub grpc_reflection: bool,
+	/// Largest request body the REST API reads, and largest gRPC message
+	/// the tool service decodes; anything bigger is rejected with 413 or
+	/// `OUT_OF_RANGE`.
+	pub max_body_bytes: usize,
+	/// Compress REST responses as `Accept-Encoding` allows, and inflate
+	/// request bodies sent with a `Content-Encoding`.
+	pub compression: bool,
+	/// Responses smaller than this are sent uncompressed.
+	pub compression_min_bytes: u16,
+	/// Compress tool service responses, with gzip or zstd, for clients that
+	/// accept either; compressed requests are accepted either way.
+	pub grpc_compression: bool,
+}
+
+impl Default for ServerConfig {
+	fn default() -> Self {
+		Self {
+			http_port: DEFAULT_PORT,
+			grpc_port: DEFAULT_GRPC_PORT,
+			single_port: false,
+			log_level: DEFAULT_LOG_LEVEL.to_string(),
+			shutdown_drain_secs: DEFAULT_DRAIN_SECS,
+			swagger_ui: false,
+			grpc_reflection: true,
+			max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+			compression: true,
+			compression_min_bytes: DEFAULT_COMPRESSION_MIN_BYTES,
+			grpc_compression: true,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+	/// OTLP/gRPC collector, with scheme (`http://jaeger:4317`). Spans are
+	/// only exported when set.
+	pub otlp_endpoint: Option<String>,
+}
+
+/// Everything the sandbox reads at startup.
+///
+/// Built from the defaults, then the TOML or YAML file named by
+/// `PAGI_SANDBOX_CONFIG`, then the environment variables documented in the
+/// README, and validated as a whole before anything is started.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+	pub server: ServerConfig,
+	pub tls: TlsConfig,
+	pub executor: ExecutorConfig,
+	pub auth: AuthConfig,
+	pub rbac: RbacConfig,
+	pub policy: PolicyConfig,
+	pub rate_limit: RateLimitConfig,
+	pub quotas: QuotaConfig,
+	pub shared_limits: SharedLimitsConfig,
+	pub jobs: JobsConfig,
+	pub webhooks: WebhooksConfig,
+	pub events: EventsConfig,
+	pub batch: BatchConfig,
+	pub sessions: SessionsConfig,
+	pub cluster: ClusterConfig,
+	pub schedules: SchedulesConfig,
+	pub mcp: McpConfig,
+	pub history: HistoryConfig,
+	pub cache: CacheConfig,
+	pub idempotency: IdempotencyConfig,
+	pub queue: QueueConfig,
+	pub audit: AuditConfig,
+	pub telemetry: TelemetryConfig,
+	pub logging: LoggingConfig,
+	pub body_log: BodyLogConfig,
+	pub chaos: ChaosConfig,
+	/// The file the settings were read from, if any.
+	#[serde(skip)]
+	pub source: Option<PathBuf>,
+}
+
+/// Every problem found while loading the settings, so one restart fixes them
+/// all.
+#[derive(Debug)]
+pub struct ConfigError {
+	pub errors: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} configuration error(s): {}", self.errors.len(), self.errors.join("; "))
+	}
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Settings {
+	pub fn load() -> Result<Self, ConfigError> {
+		let mut settings = match env_value(CONFIG_FILE_ENV) {
+			Some(path) => Self::from_file(Path::new(&path)).map_err(|e| ConfigError { errors: vec![e] })?,
+			None => Self::default(),
+		};
+		let mut errors = settings.apply_env();
+		errors.extend(settings.validate());
+		if errors.is_empty() {
+			Ok(settings)
+		} else {
+			Err(ConfigError { errors })
+		}
+	}
+
+	fn from_file(path: &Path) -> Result<Self, String> {
+		let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+		let mut settings: Self = match path.extension().and_then(|e| e.to_str()) {
+			Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?,
+			_ => toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?,
+		};
+		settings.source = Some(path.to_path_buf());
+		Ok(settings)
+	}
+
+	/// Override settings with the environment variables that are set and
+	/// non-empty. Returns the values that failed to parse.
+	fn apply_env(&mut self) -> Vec<String> {
+		let mut env = EnvOverlay::default();
+
+		let server = &mut self.server;
+		env.parse("RUST_SANDBOX_PORT", &mut server.http_port);
+		env.parse("RUST_SANDBOX_GRPC_PORT", &mut server.grpc_port);
+		env.parse("SANDBOX_SINGLE_PORT", &mut server.single_port);
+		env.parse("LOG_LEVEL", &mut server.log_level);
+		env.parse("SANDBOX_SHUTDOWN_DRAIN_SECS", &mut server.shutdown_drain_secs);
+		env.parse("SANDBOX_SWAGGER_UI", &mut server.swagger_ui);
+		env.parse("SANDBOX_GRPC_REFLECTION", &mut server.grpc_reflection);
+		env.parse("SANDBOX_MAX_BODY_BYTES", &mut server.max_body_bytes);
+		env.parse("SANDBOX_COMPRESSION", &mut server.compression);
+		env.parse("SANDBOX_COMPRESSION_MIN_BYTES", &mut server.compression_min_bytes);
+		env.parse("SANDBOX_GRPC_COMPRESSION", &mut server.grpc_compression);
+
+		let tls = &mut self.tls;
+		env.optional("SANDBOX_TLS_CERT", &mut tls.cert_path);
+		env.optional("SANDBOX_TLS_KEY", &mut tls.key_path);
+		env.optional("SANDBOX_TLS_CLIENT_CA", &mut tls.client_ca_path);
+		env.parse("SANDBOX_TLS_REQUIRE_CLIENT_CERT", &mut tls.require_client_cert);
+
+		let executor = &mut self.executor;
+		env.parse("SANDBOX_TOOLS_MANIFEST_DIR", &mut executor.manifest_dir);
+		env.optional("SANDBOX_PLUGIN_DIR", &mut executor.plugin_dir);
+		env.optional("SANDBOX_COMPONENT_DIR", &mut executor.component_dir);
+		env.parse("SANDBOX_REQUIRE_SIGNED_TOOLS", &mut executor.signing.require_signed_tools);
+		if let Some(paths) = env_value("SANDBOX_MINISIGN_PUBLIC_KEYS") {
+			executor.signing.minisign_public_keys = split_list(&paths).into_iter().map(PathBuf::from).collect();
+		}
+		if let Some(paths) = env_value("SANDBOX_COSIGN_PUBLIC_KEYS") {
+			executor.signing.cosign_public_keys = split_list(&paths).into_iter().map(PathBuf::from).collect();
+		}
+		env.parse("SANDBOX_DEFAULT_TIMEOUT_MS", &mut executor.default_timeout_ms);
+		env.parse("SANDBOX_MAX_TIMEOUT_MS", &mut executor.max_timeout_ms);
+		env.parse("SANDBOX_DEADLINE_OVERHEAD_MS", &mut executor.deadline_overhead_ms);
+		env.parse("MAX_CONCURRENT_EXECUTIONS", &mut executor.max_concurrent_executions);
+		env.parse("SANDBOX_MAX_QUEUED_EXECUTIONS", &mut executor.max_queued_executions);
+		env.parse("SANDBOX_PREEMPTION", &mut executor.preemption.enabled);
+		env.parse("SANDBOX_PREEMPTION_MIN_RUNTIME_MS", &mut executor.preemption.min_runtime_ms);
+		env.parse("SANDBOX_MAX_OUTPUT_BYTES", &mut executor.max_output_bytes);
+		env.parse("SANDBOX_MAX_ATTEMPTS", &mut executor.max_attempts);
+		env.parse("SANDBOX_WASM_FUEL", &mut executor.wasm.fuel);
+		env.parse("SANDBOX_WASM_MAX_MEMORY_MB", &mut executor.wasm.max_memory_mb);
+		let container = &mut executor.container;
+		env.parse("SANDBOX_CONTAINER_RUNTIME", &mut container.runtime);
+		env.parse("SANDBOX_CONTAINER_DEFAULT_IMAGE", &mut container.default_image);
+		env.parse("SANDBOX_CONTAINER_CPUS", &mut container.cpus);
+		env.parse("SANDBOX_CONTAINER_MEMORY_MB", &mut container.memory_mb);
+		env.parse("SANDBOX_CONTAINER_PIDS_LIMIT", &mut container.pids_limit);
+		env.parse("SANDBOX_CONTAINER_NETWORK", &mut container.network);
+		env.parse("SANDBOX_CONTAINER_POOL_SIZE", &mut container.pool_size);
+		env.parse("SANDBOX_CONTAINER_WARM_DIR", &mut container.warm_dir);
+		let warm_pool = &mut executor.warm_pool;
+		env.parse("SANDBOX_WARM_POOL_TTL_SECS", &mut warm_pool.ttl_secs);
+		env.parse("SANDBOX_WARM_POOL_TAINT", &mut warm_pool.taint);
+		env.parse("SANDBOX_WARM_POOL_MAX_USES", &mut warm_pool.max_uses);
+		let firecracker = &mut executor.firecracker;
+		env.parse("SANDBOX_FIRECRACKER_BINARY", &mut firecracker.binary);
+		env.parse("SANDBOX_FIRECRACKER_STATE_DIR", &mut firecracker.state_dir);
+		env.parse("SANDBOX_FIRECRACKER_BOOT_ARGS", &mut firecracker.boot_args);
+		env.parse("SANDBOX_FIRECRACKER_BOOT_TIMEOUT_MS", &mut firecracker.boot_timeout_ms);
+		env.parse("SANDBOX_FIRECRACKER_GUEST_PORT", &mut firecracker.guest_port);
+		env.parse("SANDBOX_FIRECRACKER_MAX_WORKSPACE_BYTES", &mut firecracker.max_workspace_bytes);
+		// The default class can be set up without a config file.
+		if env_value("SANDBOX_FIRECRACKER_KERNEL").is_some() || env_value("SANDBOX_FIRECRACKER_ROOTFS").is_some() {
+			let class = firecracker.classes.entry(DEFAULT_CLASS.to_string()).or_default();
+			env.parse("SANDBOX_FIRECRACKER_KERNEL", &mut class.kernel);
+			env.parse("SANDBOX_FIRECRACKER_ROOTFS", &mut class.rootfs);
+			env.parse("SANDBOX_FIRECRACKER_VCPUS", &mut class.vcpus);
+			env.parse("SANDBOX_FIRECRACKER_MEMORY_MB", &mut class.memory_mb);
+			env.parse("SANDBOX_FIRECRACKER_POOL_SIZE", &mut class.pool_size);
+		}
+		env.parse("SANDBOX_PYTHON_INTERPRETER", &mut executor.python.interpreter);
+		env.optional("SANDBOX_PYTHON_VERSION", &mut executor.python.version);
+		let javascript = &mut executor.javascript;
+		env.optional("SANDBOX_JAVASCRIPT_ENGINE", &mut javascript.engine);
+		env.parse("SANDBOX_JAVASCRIPT_HEAP_MB", &mut javascript.heap_mb);
+		env.parse("SANDBOX_JAVASCRIPT_FUEL", &mut javascript.fuel);
+		env.optional("SANDBOX_JAVASCRIPT_MODULES_DIR", &mut javascript.modules_dir);
+		if let Some(names) = env_value("SANDBOX_JAVASCRIPT_ALLOWED_MODULES") {
+			javascript.allowed_modules = split_list(&names);
+		}
+		let blocked = &mut executor.code_scan.blocked_imports;
+		if let Some(names) = env_value("SANDBOX_CODE_SCAN_BLOCKED_PYTHON_IMPORTS") {
+			blocked.python = split_list(&names);
+		}
+		if let Some(names) = env_value("SANDBOX_CODE_SCAN_BLOCKED_JAVASCRIPT_IMPORTS") {
+			blocked.javascript = split_list(&names);
+		}
+		env.parse("SANDBOX_CODE_SCAN_IMPORT_ACTION", &mut blocked.action);
+		let filters = &mut executor.output_filters.default;
+		env.parse("SANDBOX_OUTPUT_FILTER_SECRETS", &mut filters.secrets);
+		if let Some(kinds) = env_value("SANDBOX_OUTPUT_FILTER_PII") {
+			match split_list(&kinds).iter().map(|kind| kind.parse()).collect::<Result<Vec<_>, _>>() {
+				Ok(kinds) => filters.pii = kinds,
+				Err(e) => env.errors.push(format!("SANDBOX_OUTPUT_FILTER_PII={kinds:?}: {e}")),
+			}
+		}
+		env.optional("SANDBOX_OUTPUT_FILTER_MAX_LINE_LENGTH", &mut filters.max_line_length);
+		let quarantine = &mut executor.quarantine;
+		env.parse("SANDBOX_QUARANTINE_ENABLED", &mut quarantine.enabled);
+		env.parse("SANDBOX_QUARANTINE_UNKNOWN_CALLERS", &mut quarantine.unknown_callers);
+		if let Some(subjects) = env_value("SANDBOX_QUARANTINE_TRUSTED_SUBJECTS") {
+			quarantine.trusted_subjects = split_list(&subjects);
+		}
+		env.parse("SANDBOX_QUARANTINE_FLAGGED_CODE", &mut quarantine.flagged_code);
+		env.optional("SANDBOX_QUARANTINE_MAX_WALL_MS", &mut quarantine.max_wall_ms);
+		env.optional("SANDBOX_QUARANTINE_MAX_CPU_MS", &mut quarantine.max_cpu_ms);
+		env.optional("SANDBOX_QUARANTINE_MAX_PEAK_RSS_MB", &mut quarantine.max_peak_rss_mb);
+		env.optional("SANDBOX_QUARANTINE_MAX_OUTPUT_BYTES", &mut quarantine.max_output_bytes);
+		env.parse("SANDBOX_QUARANTINE_DIR", &mut quarantine.dir);
+		let shell = &mut executor.shell;
+		if let Some(names) = env_value("SANDBOX_SHELL_ALLOWED_COMMANDS") {
+			shell.allowed_commands = split_list(&names);
+		}
+		env.parse("SANDBOX_SHELL_ALLOW_PIPELINES", &mut shell.allow_pipelines);
+		env.parse("SANDBOX_SHELL_ALLOW_REDIRECTS", &mut shell.allow_redirects);
+		let http = &mut executor.http;
+		if let Some(hosts) = env_value("SANDBOX_HTTP_ALLOWED_HOSTS") {
+			http.network = match hosts.as_str() {
+				"*" => NetworkPolicy::Full,
+				_ => NetworkPolicy::Allowlist(split_list(&hosts)),
+			};
+		}
+		env.parse("SANDBOX_HTTP_ALLOW_PRIVATE_NETWORKS", &mut http.allow_private_networks);
+		env.parse("SANDBOX_HTTP_MAX_RESPONSE_BYTES", &mut http.max_response_bytes);
+		env.parse("SANDBOX_HTTP_MAX_REDIRECTS", &mut http.max_redirects);
+		let gateway = &mut executor.gateway;
+		env.optional("SANDBOX_GATEWAY_URL", &mut gateway.url);
+		env.parse("SANDBOX_GATEWAY_CONNECTIONS", &mut gateway.connections);
+		env.parse("SANDBOX_GATEWAY_TIMEOUT_MS", &mut gateway.timeout_ms);
+		env.parse("SANDBOX_GATEWAY_MAX_ATTEMPTS", &mut gateway.max_attempts);
+		env.parse("SANDBOX_GATEWAY_FORWARD_AUTH", &mut gateway.forward_auth);
+		env.parse("SANDBOX_GATEWAY_COMPRESSION", &mut gateway.compression);
+		env.optional("SANDBOX_GATEWAY_TLS_CA", &mut gateway.tls.ca_path);
+		env.optional("SANDBOX_GATEWAY_TLS_CERT", &mut gateway.tls.cert_path);
+		env.optional("SANDBOX_GATEWAY_TLS_KEY", &mut gateway.tls.key_path);
+		env.optional("SANDBOX_GATEWAY_TLS_SERVER_NAME", &mut gateway.tls.server_name);
+		let cgroup = &mut executor.cgroup;
+		env.parse("SANDBOX_CGROUP_MODE", &mut cgroup.mode);
+		env.optional("SANDBOX_CGROUP_PARENT", &mut cgroup.parent);
+		env.parse("SANDBOX_CGROUP_CPU_WEIGHT", &mut cgroup.cpu_weight);
+		env.parse("SANDBOX_CGROUP_MEMORY_MAX_MB", &mut cgroup.memory_max_mb);
+		env.parse("SANDBOX_CGROUP_PIDS_MAX", &mut cgroup.pids_max);
+		env.optional("SANDBOX_CGROUP_CPU_TIME_LIMIT_MS", &mut cgroup.cpu_time_limit_ms);
+		let gpu = &mut executor.gpu;
+		env.parse("SANDBOX_GPU_MODE", &mut gpu.mode);
+		env.parse("SANDBOX_GPU_LIBRARY", &mut gpu.library);
+		if let Some(devices) = env_value("SANDBOX_GPU_DEVICES") {
+			match split_list(&devices).iter().map(|s| s.parse()).collect::<Result<Vec<u32>, _>>() {
+				Ok(parsed) => gpu.devices = parsed,
+				Err(e) => env.errors.push(format!("SANDBOX_GPU_DEVICES={devices:?}: {e}")),
+			}
+		}
+		env.parse("SANDBOX_GPU_SAMPLE_INTERVAL_MS", &mut gpu.sample_interval_ms);
+		let workspace = &mut executor.workspace;
+		env.parse("SANDBOX_WORKSPACE_ROOT", &mut workspace.root);
+		env.parse("SANDBOX_JAIL_MODE", &mut workspace.jail);
+		env.parse("SANDBOX_PID_NAMESPACE_MODE", &mut workspace.pid_namespace);
+		env.parse("SANDBOX_LANDLOCK_MODE", &mut workspace.landlock);
+		if let Some(paths) = env_value("SANDBOX_LANDLOCK_READ_PATHS") {
+			workspace.landlock_read_paths = split_list(&paths).into_iter().map(PathBuf::from).collect();
+		}
+		env.parse("SANDBOX_WORKSPACE_RETAIN_SECS", &mut workspace.retain_secs);
+		env.optional("SANDBOX_WORKSPACE_DISK_QUOTA_MB", &mut workspace.disk_quota_mb);
+		env.parse("SANDBOX_XFS_QUOTA_MODE", &mut workspace.xfs_quota);
+		env.parse("SANDBOX_WORKSPACE_DISK_CHECK_INTERVAL_MS", &mut workspace.disk_check_interval_ms);
+		if let Some(names) = env_value("SANDBOX_ENV_ALLOW") {
+			executor.env_allow = split_list(&names);
+		}
+		let secrets = &mut executor.secrets;
+		env.parse("SANDBOX_SECRETS_PROVIDER", &mut secrets.provider);
+		env.parse("SANDBOX_SECRETS_ENV_PREFIX", &mut secrets.env_prefix);
+		env.parse("SANDBOX_SECRETS_DIR", &mut secrets.dir);
+		env.optional("VAULT_ADDR", &mut secrets.vault.addr);
+		env.optional("VAULT_TOKEN", &mut secrets.vault.token);
+		env.optional("SANDBOX_VAULT_TOKEN_FILE", &mut secrets.vault.token_file);
+		env.parse("SANDBOX_VAULT_MOUNT", &mut secrets.vault.mount);
+		env.parse("SANDBOX_VAULT_CACHE_SECS", &mut secrets.vault.cache_secs);
+		let artifacts = &mut executor.artifacts;
+		env.parse("SANDBOX_ARTIFACTS_BACKEND", &mut artifacts.backend);
+		env.parse("SANDBOX_ARTIFACTS_DIR", &mut artifacts.dir);
+		env.optional("SANDBOX_ARTIFACTS_S3_BUCKET", &mut artifacts.s3.bucket);
+		env.optional("SANDBOX_ARTIFACTS_S3_REGION", &mut artifacts.s3.region);
+		env.optional("SANDBOX_ARTIFACTS_S3_ENDPOINT", &mut artifacts.s3.endpoint);
+		env.parse("SANDBOX_ARTIFACTS_S3_PREFIX", &mut artifacts.s3.prefix);
+		env.parse("SANDBOX_ARTIFACTS_TTL_SECS", &mut artifacts.ttl_secs);
+		env.parse("SANDBOX_ARTIFACTS_MAX_FILE_BYTES", &mut artifacts.max_file_bytes);
+		env.parse("SANDBOX_ARTIFACTS_MAX_TOTAL_BYTES", &mut artifacts.max_total_bytes);
+		env.parse("SANDBOX_ARTIFACTS_MAX_FILES", &mut artifacts.max_files);
+		env.optional("SANDBOX_ARTIFACTS_BINARY_OUTPUT_BYTES", &mut artifacts.binary_output_bytes);
+
+		let auth = &mut self.auth;
+		if let Some(keys) = env_value("SANDBOX_API_KEYS") {
+			auth.api_keys = split_list(&keys);
+		}
+		env.optional("SANDBOX_API_KEYS_FILE", &mut auth.api_keys_file);
+		env.parse("SANDBOX_API_KEYS_RELOAD_SECS", &mut auth.api_keys_reload_secs);
+		let jwt = &mut auth.jwt;
+		env.optional("SANDBOX_JWT_HS256_SECRET", &mut jwt.hs256_secret);
+		env.optional("SANDBOX_JWT_RS256_PUBLIC_KEY_FILE", &mut jwt.rs256_public_key_file);
+		env.optional("SANDBOX_JWT_JWKS_URL", &mut jwt.jwks_url);
+		env.parse("SANDBOX_JWT_JWKS_REFRESH_SECS", &mut jwt.jwks_refresh_secs);
+		env.optional("SANDBOX_JWT_ISSUER", &mut jwt.issuer);
+		env.optional("SANDBOX_JWT_AUDIENCE", &mut jwt.audience);
+
+		env.optional("SANDBOX_RBAC_POLICY_FILE", &mut self.rbac.policy_file);
+		let policy = &mut self.policy;
+		env.optional("SANDBOX_POLICY_FILE", &mut policy.policy_file);
+		env.optional("SANDBOX_POLICY_ENGINE", &mut policy.engine);
+		env.parse("SANDBOX_POLICY_REGO_RULE", &mut policy.rego_rule);
+		env.parse("SANDBOX_POLICY_CACHE_CAPACITY", &mut policy.cache_capacity);
+		env.parse("SANDBOX_POLICY_CACHE_TTL_SECS", &mut policy.cache_ttl_secs);
+		// The default bucket can be set from the environment; per-tool limits
+		// only from the file.
+		let capacity = env.read("SANDBOX_RATE_LIMIT_CAPACITY");
+		let refill_per_sec = env.read("SANDBOX_RATE_LIMIT_REFILL_PER_SEC");
+		if capacity.is_some() || refill_per_sec.is_some() {
+			let current = self.rate_limit.default;
+			match (
+				capacity.or(current.map(|b| b.capacity)),
+				refill_per_sec.or(current.map(|b| b.refill_per_sec)),
+			) {
+				(Some(capacity), Some(refill_per_sec)) => {
+					self.rate_limit.default = Some(BucketConfig {
+						capacity,
+						refill_per_sec,
+					})
+				}
+				_ => env.errors.push(
+					"SANDBOX_RATE_LIMIT_CAPACITY and SANDBOX_RATE_LIMIT_REFILL_PER_SEC must be set together".to_string(),
+				),
+			}
+		}
+		// Likewise the default quotas; per-tenant ones only from the file.
+		let quota = &mut self.quotas.default;
+		env.optional("SANDBOX_QUOTA_EXECUTIONS_PER_DAY", &mut quota.executions_per_day);
+		env.optional("SANDBOX_QUOTA_CPU_SECONDS_PER_DAY", &mut quota.cpu_seconds_per_day);
+		env.optional("SANDBOX_QUOTA_STORAGE_BYTES", &mut quota.storage_bytes);
+		env.optional("SANDBOX_QUOTA_MONTHLY_BUDGET", &mut quota.monthly_budget);
+		env.optional("SANDBOX_QUOTA_MONTHLY_BUDGET_SOFT", &mut quota.monthly_budget_soft);
+		let rates = &mut self.quotas.rates;
+		env.parse("SANDBOX_COST_CURRENCY", &mut rates.currency);
+		env.parse("SANDBOX_COST_CPU_SECOND", &mut rates.cpu_second);
+		env.parse("SANDBOX_COST_GB_SECOND", &mut rates.gb_second);
+		env.parse("SANDBOX_COST_GPU_MINUTE", &mut rates.gpu_minute);
+		let shared_limits = &mut self.shared_limits;
+		env.optional("SANDBOX_SHARED_LIMITS_REDIS_URL", &mut shared_limits.redis_url);
+		env.parse("SANDBOX_SHARED_LIMITS_KEY_PREFIX", &mut shared_limits.key_prefix);
+		env.parse("SANDBOX_SHARED_LIMITS_TIMEOUT_MS", &mut shared_limits.timeout_ms);
+		env.parse("SANDBOX_SHARED_LIMITS_RETRY_MS", &mut shared_limits.retry_ms);
+		env.parse("SANDBOX_JOB_RETENTION_SECS", &mut self.jobs.retention_secs);
+		let approval = &mut self.jobs.approval;
+		env.parse("SANDBOX_JOB_APPROVAL_TTL_SECS", &mut approval.ttl_secs);
+		env.optional("SANDBOX_JOB_APPROVAL_WEBHOOK_URL", &mut approval.webhook_url);
+		env.optional("SANDBOX_JOB_APPROVAL_SLACK_WEBHOOK_URL", &mut approval.slack_webhook_url);
+		env.parse("SANDBOX_JOB_APPROVAL_ALLOW_SELF", &mut approval.allow_self_approval);
+		let webhooks = &mut self.webhooks;
+		env.optional("SANDBOX_WEBHOOK_SECRET", &mut webhooks.secret);
+		if let Some(hosts) = env_value("SANDBOX_WEBHOOK_ALLOWED_HOSTS") {
+			webhooks.allowed_hosts = split_list(&hosts);
+		}
+		env.parse("SANDBOX_WEBHOOK_ALLOW_PRIVATE_NETWORKS", &mut webhooks.allow_private_networks);
+		env.optional("SANDBOX_WEBHOOK_BASE_URL", &mut webhooks.base_url);
+		env.parse("SANDBOX_WEBHOOK_MAX_ATTEMPTS", &mut webhooks.max_attempts);
+		env.parse("SANDBOX_WEBHOOK_TIMEOUT_MS", &mut webhooks.timeout_ms);
+		env.optional("SANDBOX_WEBHOOK_DEAD_LETTER_FILE", &mut webhooks.dead_letter_file);
+		let events = &mut self.events;
+		env.optional("SANDBOX_EVENTS_NATS_URL", &mut events.nats_url);
+		env.optional("SANDBOX_EVENTS_REDIS_URL", &mut events.redis_url);
+		env.optional("SANDBOX_EVENTS_KAFKA_REST_URL", &mut events.kafka_rest_url);
+		env.parse("SANDBOX_EVENTS_TOPIC", &mut events.topic);
+		env.parse("SANDBOX_EVENTS_OUTPUT", &mut events.output);
+		env.parse("SANDBOX_BATCH_MAX_ITEMS", &mut self.batch.max_items);
+		env.parse("SANDBOX_SESSION_IDLE_TIMEOUT_SECS", &mut self.sessions.idle_timeout_secs);
+		env.parse("SANDBOX_SESSION_MAX_LIFETIME_SECS", &mut self.sessions.max_lifetime_secs);
+		env.parse("SANDBOX_SESSION_MAX_PER_CLIENT", &mut self.sessions.max_per_client);
+		env.parse("SANDBOX_MAX_SESSIONS", &mut self.sessions.max_sessions);
+		env.optional("SANDBOX_SESSION_REGISTRY_URL", &mut self.sessions.registry_url);
+		env.parse("SANDBOX_SESSION_REGISTRY_KEY_PREFIX", &mut self.sessions.registry_key_prefix);
+		env.optional("SANDBOX_SESSION_ADVERTISE_URL", &mut self.sessions.advertise_url);
+		env.parse("SANDBOX_SESSION_ROUTING", &mut self.sessions.routing);
+		env.optional("SANDBOX_SESSION_EVICT_AFTER_SECS", &mut self.sessions.evict_after_secs);
+		env.parse("SANDBOX_SESSION_SNAPSHOT_DIR", &mut self.sessions.snapshot_dir);
+		env.parse("SANDBOX_CRIU", &mut self.sessions.criu);
+		let cluster = &mut self.cluster;
+		env.optional("SANDBOX_CLUSTER_ADVERTISE_URL", &mut cluster.advertise_url);
+		env.optional("SANDBOX_CLUSTER_NODE_ID", &mut cluster.node_id);
+		env.optional("SANDBOX_CLUSTER_REGION", &mut cluster.region);
+		if let Some(backends) = env_value("SANDBOX_CLUSTER_BACKENDS") {
+			match split_list(&backends).iter().map(|backend| backend.parse()).collect::<Result<Vec<_>, _>>() {
+				Ok(backends) => cluster.backends = backends,
+				Err(e) => env.errors.push(format!("SANDBOX_CLUSTER_BACKENDS={backends:?}: {e}")),
+			}
+		}
+		env.parse("SANDBOX_CLUSTER_DISCOVERY", &mut cluster.discovery);
+		if let Some(peers) = env_value("SANDBOX_CLUSTER_PEERS") {
+			cluster.peers = split_list(&peers);
+		}
+		env.optional("SANDBOX_CLUSTER_API_KEY", &mut cluster.api_key);
+		env.optional("SANDBOX_CLUSTER_REGISTRY_URL", &mut cluster.registry_url);
+		env.optional("SANDBOX_CLUSTER_REGISTRY_TOKEN", &mut cluster.registry_token);
+		env.parse("SANDBOX_CLUSTER_KEY_PREFIX", &mut cluster.key_prefix);
+		env.parse("SANDBOX_CLUSTER_HEARTBEAT_MS", &mut cluster.heartbeat_ms);
+		env.parse("SANDBOX_CLUSTER_NODE_TTL_MS", &mut cluster.node_ttl_ms);
+		env.parse("SANDBOX_CLUSTER_FORWARD", &mut cluster.forward);
+		env.parse("SANDBOX_MAX_SCHEDULES", &mut self.schedules.max_schedules);
+		if let Some(origins) = env_value("SANDBOX_MCP_ALLOWED_ORIGINS") {
+			self.mcp.allowed_origins = split_list(&origins);
+		}
+		env.optional("SANDBOX_HISTORY_DATABASE_URL", &mut self.history.database_url);
+		env.parse("SANDBOX_HISTORY_OUTPUT_LIMIT_BYTES", &mut self.history.output_limit_bytes);
+		env.parse("SANDBOX_HISTORY_RETENTION_SECS", &mut self.history.retention_secs);
+		env.parse("SANDBOX_HISTORY_MAX_CONNECTIONS", &mut self.history.max_connections);
+		env.parse("SANDBOX_HISTORY_RECORD_REQUESTS", &mut self.history.record_requests);
+		env.optional("SANDBOX_HISTORY_INSTANCE_ID", &mut self.history.instance_id);
+		env.parse("SANDBOX_CACHE_CAPACITY", &mut self.cache.capacity);
+		env.parse("SANDBOX_CACHE_DEFAULT_TTL_SECS", &mut self.cache.default_ttl_secs);
+		env.optional("SANDBOX_CACHE_REDIS_URL", &mut self.cache.redis_url);
+		env.parse("SANDBOX_CACHE_MAX_ENTRY_BYTES", &mut self.cache.max_entry_bytes);
+
+		let idempotency = &mut self.idempotency;
+		env.parse("SANDBOX_IDEMPOTENCY", &mut idempotency.enabled);
+		env.parse("SANDBOX_IDEMPOTENCY_TTL_SECS", &mut idempotency.ttl_secs);
+		env.parse("SANDBOX_IDEMPOTENCY_CAPACITY", &mut idempotency.capacity);
+		env.optional("SANDBOX_IDEMPOTENCY_REDIS_URL", &mut idempotency.redis_url);
+		env.parse("SANDBOX_IDEMPOTENCY_MAX_ENTRY_BYTES", &mut idempotency.max_entry_bytes);
+		let queue = &mut self.queue;
+		env.optional("SANDBOX_QUEUE_REDIS_URL", &mut queue.redis_url);
+		env.parse("SANDBOX_QUEUE_DISPATCH", &mut queue.dispatch);
+		env.parse("SANDBOX_QUEUE_WORKER", &mut queue.worker);
+		env.parse("SANDBOX_QUEUE_KEY_PREFIX", &mut queue.key_prefix);
+		env.parse("SANDBOX_QUEUE_VISIBILITY_TIMEOUT_MS", &mut queue.visibility_timeout_ms);
+		env.parse("SANDBOX_QUEUE_HEARTBEAT_MS", &mut queue.heartbeat_ms);
+		env.parse("SANDBOX_QUEUE_MAX_DELIVERIES", &mut queue.max_deliveries);
+		env.parse("SANDBOX_QUEUE_MAX_WAIT_MS", &mut queue.max_wait_ms);
+		env.optional("SANDBOX_AUDIT_FILE", &mut self.audit.file);
+		env.optional("SANDBOX_AUDIT_SYSLOG", &mut self.audit.syslog);
+		env.parse("SANDBOX_AUDIT_RECENT", &mut self.audit.recent);
+		env.optional("OTEL_EXPORTER_OTLP_ENDPOINT", &mut self.telemetry.otlp_endpoint);
+		let logging = &mut self.logging;
+		env.parse("SANDBOX_LOG_CONSOLE", &mut logging.console);
+		env.optional("SANDBOX_LOG_CONSOLE_LEVEL", &mut logging.console_level);
+		env.parse("SANDBOX_LOG_FORMAT", &mut logging.format);
+		env.optional("SANDBOX_LOG_FILE", &mut logging.file);
+		env.optional("SANDBOX_LOG_FILE_LEVEL", &mut logging.file_level);
+		env.parse("SANDBOX_LOG_FILE_MAX_BYTES", &mut logging.file_max_bytes);
+		env.parse("SANDBOX_LOG_FILE_KEEP", &mut logging.file_keep);
+		env.optional("SANDBOX_LOG_SYSLOG", &mut logging.syslog);
+		env.optional("SANDBOX_LOG_SYSLOG_LEVEL", &mut logging.syslog_level);
+		env.parse("SANDBOX_LOG_OTLP", &mut logging.otlp);
+		env.optional("SANDBOX_LOG_OTLP_LEVEL", &mut logging.otlp_level);
+		if let Some(paths) = env_value("SANDBOX_LOG_REDACT_PATHS") {
+			logging.redact_paths = split_list(&paths);
+		}
+		env.parse("SANDBOX_LOG_REDACT_SECRETS", &mut logging.redact_secrets);
+		let body_log = &mut self.body_log;
+		env.parse("SANDBOX_BODY_LOG", &mut body_log.enabled);
+		env.parse("SANDBOX_BODY_LOG_SAMPLE_RATE", &mut body_log.sample_rate);
+		env.parse("SANDBOX_BODY_LOG_MAX_BYTES", &mut body_log.max_bytes);
+		if let Some(names) = env_value("SANDBOX_BODY_LOG_REDACT_HEADERS") {
+			body_log.redact_headers = split_list(&names);
+		}
+		if let Some(paths) = env_value("SANDBOX_BODY_LOG_REDACT_PATHS") {
+			body_log.redact_paths = split_list(&paths);
+		}
+		let chaos = &mut self.chaos;
+		env.parse("SANDBOX_CHAOS", &mut chaos.enabled);
+		env.parse("SANDBOX_CHAOS_LATENCY_RATE", &mut chaos.latency_rate);
+		env.parse("SANDBOX_CHAOS_LATENCY_MIN_MS", &mut chaos.latency_min_ms);
+		env.parse("SANDBOX_CHAOS_LATENCY_MAX_MS", &mut chaos.latency_max_ms);
+		env.parse("SANDBOX_CHAOS_ERROR_RATE", &mut chaos.error_rate);
+		if let Some(statuses) = env_value("SANDBOX_CHAOS_ERROR_STATUSES") {
+			match split_list(&statuses).iter().map(|s| s.parse()).collect::<Result<Vec<u16>, _>>() {
+				Ok(parsed) => chaos.error_statuses = parsed,
+				Err(e) => env.errors.push(format!("SANDBOX_CHAOS_ERROR_STATUSES={statuses:?}: {e}")),
+			}
+		}
+		env.parse("SANDBOX_CHAOS_STREAM_DROP_RATE", &mut chaos.stream_drop_rate);
+		env.parse("SANDBOX_CHAOS_CRASH_RATE", &mut chaos.crash_rate);
+
+		env.errors
+	}
+
+	/// Check the settings as a whole; each message names the offending key.
+	fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		let mut check = |ok: bool, message: String| {
+			if !ok {
+				errors.push(message);
+			}
+		};
+
+		let server = &self.server;
+		check(server.http_port != 0, "server.http_port must not be 0".to_string());
+		check(server.grpc_port != 0, "server.grpc_port must not be 0".to_string());
+		check(server.max_body_bytes > 0, "server.max_body_bytes must be positive".to_string());
+		check(
+			server.single_port || server.http_port != server.grpc_port,
+			format!("server.http_port and server.grpc_port are both {}", server.http_port),
+		);
+		check(
+			server.log_level.parse::<Level>().is_ok(),
+			format!(
+				"server.log_level must be one of trace, debug, info, warn, error (got {:?})",
+				server.log_level
+			),
+		);
+
+		let tls = &self.tls;
+		check(
+			tls.cert_path.is_some() == tls.key_path.is_some(),
+			"tls.cert_path and tls.key_path must be set together".to_string(),
+		);
+		for (key, path) in [
+			("tls.cert_path", &tls.cert_path),
+			("tls.key_path", &tls.key_path),
+			("tls.client_ca_path", &tls.client_ca_path),
+		] {
+			if let Some(path) = path {
+				check(path.is_file(), format!("{key} {} does not exist", path.display()));
+			}
+		}
+		check(
+			tls.client_ca_path.is_none() || tls.enabled(),
+			"tls.client_ca_path needs tls.cert_path and tls.key_path".to_string(),
+		);
+		check(
+			!tls.require_client_cert || tls.client_ca_path.is_some(),
+			"tls.require_client_cert needs tls.client_ca_path".to_string(),
+		);
+
+		let executor = &self.executor;
+		check(
+			!executor.manifest_dir.exists() || executor.manifest_dir.is_dir(),
+			format!("executor.manifest_dir {} is not a directory", executor.manifest_dir.display()),
+		);
+		check(executor.default_timeout_ms > 0, "executor.default_timeout_ms must be positive".to_string());
+		check(
+			executor.max_timeout_ms >= executor.default_timeout_ms,
+			format!(
+				"executor.max_timeout_ms ({}) is below executor.default_timeout_ms ({})",
+				executor.max_timeout_ms, executor.default_timeout_ms
+			),
+		);
+		check(executor.max_output_bytes > 0, "executor.max_output_bytes must be positive".to_string());
+		check(executor.max_attempts > 0, "executor.max_attempts must be at least 1".to_string());
+		check(
+			executor.max_concurrent_executions > 0,
+			"executor.max_concurrent_executions must be positive".to_string(),
+		);
+		check(executor.wasm.fuel > 0, "executor.wasm.fuel must be positive".to_string());
+		check(executor.wasm.max_memory_mb > 0, "executor.wasm.max_memory_mb must be positive".to_string());
+		let container = &executor.container;
+		check(!container.runtime.is_empty(), "executor.container.runtime must not be empty".to_string());
+		check(
+			!container.default_image.is_empty(),
+			"executor.container.default_image must not be empty".to_string(),
+		);
+		check(
+			container.cpus.parse::<f64>().is_ok_and(|cpus| cpus > 0.0),
+			format!("executor.container.cpus must be a positive number (got {:?})", container.cpus),
+		);
+		check(container.memory_mb > 0, "executor.container.memory_mb must be positive".to_string());
+		check(container.pids_limit > 0, "executor.container.pids_limit must be positive".to_string());
+		check(!container.network.is_empty(), "executor.container.network must not be empty".to_string());
+		let cgroup = &executor.cgroup;
+		check(
+			(1..=10_000).contains(&cgroup.cpu_weight),
+			format!("executor.cgroup.cpu_weight must be between 1 and 10000 (got {})", cgroup.cpu_weight),
+		);
+		check(cgroup.memory_max_mb > 0, "executor.cgroup.memory_max_mb must be positive".to_string());
+		check(cgroup.pids_max > 0, "executor.cgroup.pids_max must be positive".to_string());
+		check(
+			cgroup.cpu_time_limit_ms != Some(0),
+			"executor.cgroup.cpu_time_limit_ms must be positive".to_string(),
+		);
+		let workspace = &executor.workspace;
+		check(
+			!workspace.root.exists() || workspace.root.is_dir(),
+			format!("executor.workspace.root {} is not a directory", workspace.root.display()),
+		);
+		// Expiry is judged by age, so it must not catch executions still running.
+		check(
+			workspace.retain_secs.saturating_mul(1000) >= executor.max_timeout_ms,
+			format!(
+				"executor.workspace.retain_secs ({}) is shorter than executor.max_timeout_ms ({})",
+				workspace.retain_secs, executor.max_timeout_ms
+			),
+		);
+		check(
+			workspace.disk_quota_mb != Some(0),
+			"executor.workspace.disk_quota_mb must be positive".to_string(),
+		);
+		check(
+			workspace.disk_check_interval_ms > 0,
+			"executor.workspace.disk_check_interval_ms must be positive".to_string(),
+		);
+		for path in &workspace.landlock_read_paths {
+			check(
+				path.is_absolute(),
+				format!("executor.workspace.landlock_read_paths: {} is not absolute", path.display()),
+			);
+		}
+		if let Some(parent) = &cgroup.parent {
+			check(
+				parent.is_dir(),
+				format!("executor.cgroup.parent {} is not a directory", parent.display()),
+			);
+		}
+		for pattern in &executor.env_allow {
+			check(
+				is_valid_env_pattern(pattern),
+				format!("executor.env_allow: {pattern:?} is not a variable name or PREFIX* pattern"),
+			);
+		}
+
+		let auth = &self.auth;
+		if let Some(path) = &auth.api_keys_file {
+			check(path.is_file(), format!("auth.api_keys_file {} does not exist", path.display()));
+			check(auth.api_keys_reload_secs > 0, "auth.api_keys_reload_secs must be positive".to_string());
+		}
+		let jwt = &auth.jwt;
+		check(
+			jwt.hs256_secret.as_ref().is_none_or(|s| !s.is_empty()),
+			"auth.jwt.hs256_secret must not be empty".to_string(),
+		);
+		if let Some(path) = &jwt.rs256_public_key_file {
+			check(
+				path.is_file(),
+				format!("auth.jwt.rs256_public_key_file {} does not exist", path.display()),
+			);
+		}
+		if let Some(url) = &jwt.jwks_url {
+			check(
+				url.starts_with("http://") || url.starts_with("https://"),
+				format!("auth.jwt.jwks_url must be an http(s) URL (got {url:?})"),
+			);
+			check(jwt.jwks_refresh_secs > 0, "auth.jwt.jwks_refresh_secs must be positive".to_string());
+		}
+
+		if let Some(path) = &self.rbac.policy_file {
+			check(path.is_file(), format!("rbac.policy_file {} does not exist", path.display()));
+		}
+		if let Some(target) = &self.audit.syslog {
+			check(
+				is_syslog_target(target),
+				format!("audit.syslog must be a socket path or host:port (got {target:?})"),
+			);
+		}
+		check(self.batch.max_items > 0, "batch.max_items must be positive".to_string());
+		let sessions = &self.sessions;
+		check(sessions.idle_timeout_secs > 0, "sessions.idle_timeout_secs must be positive".to_string());
+		check(sessions.max_lifetime_secs > 0, "sessions.max_lifetime_secs must be positive".to_string());
+		check(
+			sessions.max_per_client <= sessions.max_sessions,
+			format!(
+				"sessions.max_per_client ({}) must not exceed sessions.max_sessions ({})",
+				sessions.max_per_client, sessions.max_sessions
+			),
+		);
+		if let Some(evict_after_secs) = sessions.evict_after_secs {
+			check(
+				evict_after_secs > 0 && evict_after_secs < sessions.idle_timeout_secs,
+				format!(
+					"sessions.evict_after_secs ({evict_after_secs}) must be positive and below sessions.idle_timeout_secs ({})",
+					sessions.idle_timeout_secs
+				),
+			);
+		}
+		if let Some(url) = &sessions.registry_url {
+			check(
+				url.starts_with("redis://") || url.starts_with("rediss://"),
+				"sessions.registry_url must be a redis:// or rediss:// URL".to_string(),
+			);
+			check(
+				!sessions.registry_key_prefix.is_empty(),
+				"sessions.registry_key_prefix must not be empty".to_string(),
+			);
+			match &sessions.advertise_url {
+				Some(advertise_url) => check(
+					(advertise_url.starts_with("http://") || advertise_url.starts_with("https://"))
+						&& axum::http::HeaderValue::from_str(advertise_url).is_ok(),
+					format!("sessions.advertise_url must be an http:// or https:// URL (got {advertise_url:?})"),
+				),
+				None => check(
+					false,
+					"sessions.registry_url needs sessions.advertise_url, the URL other instances reach this one at"
+						.to_string(),
+				),
+			}
+		}
+		if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+			check(
+				endpoint.contains("://"),
+				format!("telemetry.otlp_endpoint needs a scheme, e.g. http://{endpoint}"),
+			);
+		}
+		let logging = &self.logging;
+		for (sink, level) in [
+			("console", &logging.console_level),
+			("file", &logging.file_level),
+			("syslog", &logging.syslog_level),
+			("otlp", &logging.otlp_level),
+		] {
+			if let Some(level) = level {
+				check(
+					level.parse::<Level>().is_ok(),
+					format!("logging.{sink}_level must be one of trace, debug, info, warn, error (got {level:?})"),
+				);
+			}
+		}
+		check(
+			logging.file_max_bytes == 0 || logging.file_keep > 0,
+			"logging.file_keep must be positive when logging.file_max_bytes is set".to_string(),
+		);
+		if let Some(target) = &logging.syslog {
+			check(
+				is_syslog_target(target),
+				format!("logging.syslog must be a socket path or host:port (got {target:?})"),
+			);
+		}
+		check(
+			!logging.otlp || self.telemetry.otlp_endpoint.is_some(),
+			"logging.otlp needs telemetry.otlp_endpoint".to_string(),
+		);
+		for path in &logging.redact_paths {
+			check(
+				!path.is_empty() && !path.split('.').any(str::is_empty),
+				format!("logging.redact_paths: {path:?} is not a dotted path"),
+			);
+		}
+		for pattern in &logging.redact_patterns {
+			if let Err(e) = regex::Regex::new(pattern) {
+				check(false, format!("logging.redact_patterns: {pattern:?} is not a regular expression: {e}"));
+			}
+		}
+
+		errors.extend(self.executor.firecracker.validate());
+		errors.extend(self.executor.warm_pool.validate());
+		errors.extend(self.executor.gpu.validate());
+		errors.extend(self.executor.python.validate());
+		errors.extend(self.executor.javascript.validate());
+		errors.extend(self.executor.shell.validate());
+		errors.extend(self.executor.sql.validate());
+		errors.extend(self.executor.http.validate());
+		errors.extend(self.executor.gateway.validate());
+		errors.extend(self.executor.code_scan.validate());
+		errors.extend(self.executor.output_filters.validate());
+		errors.extend(self.executor.quarantine.validate());
+		errors.extend(self.executor.signing.validate());
+		errors.extend(self.executor.secrets.validate());
+		errors.extend(self.executor.artifacts.validate());
+		errors.extend(self.policy.validate());
+		errors.extend(self.rate_limit.validate());
+		errors.extend(self.quotas.validate());
+		errors.extend(self.shared_limits.validate());
+		errors.extend(self.cluster.validate());
+		errors.extend(self.schedules.validate());
+		errors.extend(self.webhooks.validate());
+		errors.extend(self.jobs.validate());
+		if self.jobs.approval.webhook_url.is_some() && self.webhooks.secret.is_none() {
+			errors.push("jobs.approval.webhook_url needs webhooks.secret to sign its payloads".to_string());
+		}
+		errors.extend(self.events.validate());
+		errors.extend(self.history.validate());
+		errors.extend(self.cache.validate());
+		errors.extend(self.idempotency.validate());
+		errors.extend(self.queue.validate());
+		errors.extend(self.body_log.validate());
+		errors.extend(self.chaos.validate());
+		errors
+	}
+
+	/// The settings with API keys, signing secrets, the Vault and cluster
+	/// registry tokens and database and broker passwords masked, for the admin
+	/// API.
+	pub fn redacted(&self) -> Self {
+		let mut settings = self.clone();
+		for key in &mut settings.auth.api_keys {
+			*key = REDACTED.to_string();
+		}
+		if let Some(secret) = &mut settings.auth.jwt.hs256_secret {
+			*secret = REDACTED.to_string();
+		}
+		if let Some(secret) = &mut settings.webhooks.secret {
+			*secret = REDACTED.to_string();
+		}
+		if let Some(token) = &mut settings.executor.secrets.vault.token {
+			*token = REDACTED.to_string();
+		}
+		for secret in [&mut settings.cluster.api_key, &mut settings.cluster.registry_token].into_iter().flatten() {
+			*secret = REDACTED.to_string();
+		}
+		if let Some(url) = &mut settings.history.database_url {
+			*url = redact_url(url);
+		}
+		if let Some(url) = &mut settings.cache.redis_url {
+			*url = redact_url(url);
+		}
+		if let Some(url) = &mut settings.idempotency.redis_url {
+			*url = redact_url(url);
+		}
+		if let Some(url) = &mut settings.queue.redis_url {
+			*url = redact_url(url);
+		}
+		if let Some(url) = &mut settings.sessions.registry_url {
+			*url = redact_url(url);
+		}
+		if let Some(url) = &mut settings.shared_limits.redis_url {
+			*url = redact_url(url);
+		}
+		if let Some(url) = &mut settings.cluster.registry_url {
+			*url = redact_url(url);
+		}
+		if let Some(url) = &mut settings.executor.gateway.url {
+			*url = redact_url(url);
+		}
+		settings.events.redact();
+		settings.executor.sql.redact();
+		settings
+	}
+}
+
+/// A comma-separated environment value, without blanks.
+fn split_list(value: &str) -> Vec<String> {
+	value
+		.split(',')
+		.map(str::trim)
+		.filter(|item| !item.is_empty())
+		.map(str::to_string)
+		.collect()
+}
+
+fn env_value(key: &str) -> Option<String> {
+	env::var(key).ok().filter(|v| !v.is_empty())
+}
+
+#[derive(Default)]
+struct EnvOverlay {
+	errors: Vec<String>,
+}
+
+impl EnvOverlay {
+	fn parse<T>(&mut self, key: &str, target: &mut T)
+	where
+		T: FromStr,
+		T::Err: fmt::Display,
+	{
+		if let Some(value) = self.read(key) {
+			*target = value;
+		}
+	}
+
+	fn optional<T>(&mut self, key: &str, target: &mut Option<T>)
+	where
+		T: FromStr,
+		T::Err: fmt::Display,
+	{
+		if let Some(value) = self.read(key) {
+			*target = Some(value);
+		}
+	}
+
+	fn read<T>(&mut self, key: &str) -> Option<T>
+	where
+		T: FromStr,
+		T::Err: fmt::Display,
+	{
+		let value = env_value(key)?;
+		value
+			.parse()
+			.map_err(|e| self.errors.push(format!("{key}={value:?}: {e}")))
+			.ok()
+	}
+}
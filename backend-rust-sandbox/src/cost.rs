@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::registry::ToolLimits;
+
+const BYTES_PER_GB: f64 = (1u64 << 30) as f64;
+
+/// Prices of compute, in `currency`, for budgets and for attributing spend
+/// to tenants. Everything is free by default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CostRates {
+	/// Only shown with amounts; nothing is converted.
+	pub currency: String,
+	/// Per second of CPU time.
+	pub cpu_second: f64,
+	/// Per GiB of memory held for a second of wall time: the tool's
+	/// `limits.memory_mb` when it has one, else its peak RSS.
+	pub gb_second: f64,
+	/// Per GPU held for a minute of wall time.
+	pub gpu_minute: f64,
+}
+
+impl Default for CostRates {
+	fn default() -> Self {
+		Self {
+			currency: "USD".to_string(),
+			cpu_second: 0.0,
+			gb_second: 0.0,
+			gpu_minute: 0.0,
+		}
+	}
+}
+
+impl CostRates {
+	/// Nothing is priced.
+	pub fn is_free(&self) -> bool {
+		self.cpu_second == 0.0 && self.gb_second == 0.0 && self.gpu_minute == 0.0
+	}
+
+	pub fn validate(&self) -> Vec<String> {
+		let rates = [
+			("cpu_second", self.cpu_second),
+			("gb_second", self.gb_second),
+			("gpu_minute", self.gpu_minute),
+		];
+		rates
+			.into_iter()
+			.filter(|(_, rate)| !rate.is_finite() || *rate < 0.0)
+			.map(|(name, _)| format!("quotas.rates.{name} must be a non-negative number"))
+			.collect()
+	}
+
+	pub fn price(&self, used: &Consumption) -> f64 {
+		let wall = used.wall.as_secs_f64();
+		used.cpu.as_secs_f64() * self.cpu_second
+			+ used.memory_bytes as f64 / BYTES_PER_GB * wall * self.gb_second
+			+ used.gpus as f64 * wall / 60.0 * self.gpu_minute
+	}
+
+	/// The most one attempt of a tool with `limits` could cost if it ran for
+	/// all of `timeout`: its CPU quota (one CPU without one), memory limit
+	/// (free without one) and GPUs for the whole time.
+	pub fn estimate(&self, limits: &ToolLimits, timeout: Duration) -> f64 {
+		self.price(&Consumption {
+			cpu: timeout.mul_f64(limits.cpus.unwrap_or(1.0)),
+			memory_bytes: limits.memory_mb.unwrap_or(0) << 20,
+			gpus: limits.gpu.unwrap_or(0),
+			wall: timeout,
+		})
+	}
+}
+
+/// What an execution is charged for.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Consumption {
+	pub cpu: Duration,
+	/// Memory held over `wall`.
+	pub memory_bytes: u64,
+	/// GPUs held over `wall`.
+	pub gpus: u32,
+	pub wall: Duration,
+}
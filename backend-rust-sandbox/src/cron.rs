@@ -0,0 +1,266 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+use std::fmt;
+
+/// How far ahead fire times are looked for. 28 years is a full cycle of
+/// weekdays over leap days, so an expression that matches nothing within it
+/// (`0 0 30 2 *`) never does.
+const HORIZON_DAYS: i64 = 28 * 366;
+
+const MONTH_NAMES: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A cron expression, evaluated in UTC: the five fields
+/// `minute hour day-of-month month day-of-week`, each `*`, a value, a range
+/// `a-b`, a step `*/n` or `a-b/n`, or a comma-separated list of those.
+/// Months and weekdays may be named (`jan`, `mon`), and Sunday is 0 or 7.
+/// `@yearly`, `@monthly`, `@weekly`, `@daily` and `@hourly` stand for the
+/// usual expressions.
+///
+/// As in Vixie cron, when both the day of month and the day of week are
+/// restricted, a day matching either one fires.
+#[derive(Debug, Clone)]
+pub struct Cron {
+	source: String,
+	/// Bit `n` set when value `n` of the field matches.
+	minutes: u64,
+	hours: u64,
+	days: u64,
+	months: u64,
+	weekdays: u64,
+	/// Day of month and day of week were both given without a leading `*`.
+	either_day: bool,
+}
+
+impl Cron {
+	pub fn parse(expression: &str) -> Result<Self, String> {
+		let fields = match expression.trim() {
+			"@yearly" | "@annually" => "0 0 1 1 *",
+			"@monthly" => "0 0 1 * *",
+			"@weekly" => "0 0 * * 0",
+			"@daily" | "@midnight" => "0 0 * * *",
+			"@hourly" => "0 * * * *",
+			other => other,
+		};
+		let fields: Vec<&str> = fields.split_whitespace().collect();
+		let [minute, hour, day, month, weekday] = fields[..] else {
+			return Err(format!(
+				"{expression:?} has {} fields; expected minute, hour, day of month, month and day of week",
+				fields.len()
+			));
+		};
+		let mut weekdays = parse_field(weekday, "day of week", 0, 7, WEEKDAY_NAMES)?;
+		// 7 is another name for Sunday.
+		if weekdays & (1 << 7) != 0 {
+			weekdays = (weekdays | 1) & !(1 << 7);
+		}
+		let cron = Self {
+			source: expression.trim().to_string(),
+			minutes: parse_field(minute, "minute", 0, 59, &[])?,
+			hours: parse_field(hour, "hour", 0, 23, &[])?,
+			days: parse_field(day, "day of month", 1, 31, &[])?,
+			months: parse_field(month, "month", 1, 12, MONTH_NAMES)?,
+			weekdays,
+			either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+		};
+		let now = Utc::now();
+		if cron.next_after(now).is_none() {
+			return Err(format!("{expression:?} never fires"));
+		}
+		Ok(cron)
+	}
+
+	/// The first fire time strictly after `after`, to the minute.
+	pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+		let start = after.naive_utc().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+		let end = start + Duration::days(HORIZON_DAYS);
+		let mut t = start;
+		while t < end {
+			let date = t.date();
+			if !has(self.months, date.month()) {
+				let (year, month) = if date.month() == 12 {
+					(date.year() + 1, 1)
+				} else {
+					(date.year(), date.month() + 1)
+				};
+				t = midnight(NaiveDate::from_ymd_opt(year, month, 1)?);
+			} else if !self.day_matches(date) {
+				t = midnight(date.succ_opt()?);
+			} else if !has(self.hours, t.hour()) {
+				t = date.and_time(NaiveTime::from_hms_opt(t.hour(), 0, 0)?) + Duration::hours(1);
+			} else if !has(self.minutes, t.minute()) {
+				t += Duration::minutes(1);
+			} else {
+				return Some(t.and_utc());
+			}
+		}
+		None
+	}
+
+	fn day_matches(&self, date: NaiveDate) -> bool {
+		let day = has(self.days, date.day());
+		let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+		if self.either_day {
+			day || weekday
+		} else {
+			day && weekday
+		}
+	}
+}
+
+impl fmt::Display for Cron {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.source)
+	}
+}
+
+fn has(bits: u64, value: u32) -> bool {
+	bits & (1 << value) != 0
+}
+
+fn midnight(date: NaiveDate) -> NaiveDateTime {
+	date.and_time(NaiveTime::MIN)
+}
+
+/// The values matched by one field, as a bit set. `names` are the values
+/// from `min` on.
+fn parse_field(text: &str, field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+	let value = |s: &str| -> Result<u32, String> {
+		let lower = s.to_ascii_lowercase();
+		let parsed = match names.iter().position(|name| *name == lower) {
+			Some(i) => Some(min + i as u32),
+			None => s.parse().ok(),
+		};
+		parsed
+			.filter(|v| (min..=max).contains(v))
+			.ok_or_else(|| format!("{field}: {s:?} is not between {min} and {max}"))
+	};
+	let mut bits = 0;
+	for part in text.split(',') {
+		let (range, step) = match part.split_once('/') {
+			Some((range, step)) => {
+				let step: u32 = step
+					.parse()
+					.ok()
+					.filter(|s| *s > 0)
+					.ok_or_else(|| format!("{field}: invalid step {step:?}"))?;
+				(range, step)
+			}
+			None => (part, 1),
+		};
+		let (low, high) = if range == "*" {
+			(min, max)
+		} else if let Some((low, high)) = range.split_once('-') {
+			(value(low)?, value(high)?)
+		} else {
+			let start = value(range)?;
+			// `5/15` runs from 5 to the end of the field.
+			(start, if part.contains('/') { max } else { start })
+		};
+		if low > high {
+			return Err(format!("{field}: range {range:?} runs backwards"));
+		}
+		for v in (low..=high).step_by(step as usize) {
+			bits |= 1 << v;
+		}
+	}
+	Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn at(text: &str) -> DateTime<Utc> {
+		DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+	}
+
+	/// The fire time of `expression` after `after`, both in RFC 3339.
+	fn next(expression: &str, after: &str) -> String {
+		let next = Cron::parse(expression).unwrap().next_after(at(after)).unwrap();
+		next.to_rfc3339()
+	}
+
+	#[test]
+	fn steps() {
+		assert_eq!(next("*/15 * * * *", "2024-01-01T10:07:00Z"), "2024-01-01T10:15:00+00:00");
+		assert_eq!(next("*/15 * * * *", "2024-01-01T10:45:00Z"), "2024-01-01T11:00:00+00:00");
+		// Strictly after, to the minute.
+		assert_eq!(next("*/15 * * * *", "2024-01-01T10:15:30Z"), "2024-01-01T10:30:00+00:00");
+		assert_eq!(next("5/15 * * * *", "2024-01-01T10:00:00Z"), "2024-01-01T10:05:00+00:00");
+		assert_eq!(next("5/15 * * * *", "2024-01-01T10:50:00Z"), "2024-01-01T11:05:00+00:00");
+		assert_eq!(next("0 8-18/5 * * *", "2024-01-01T13:00:00Z"), "2024-01-01T18:00:00+00:00");
+		assert_eq!(next("0 8-18/5 * * *", "2024-01-01T18:00:00Z"), "2024-01-02T08:00:00+00:00");
+	}
+
+	#[test]
+	fn named_weekday_ranges() {
+		// 2024-01-05 is a Friday.
+		assert_eq!(next("0 9 * * mon-fri", "2024-01-05T09:00:00Z"), "2024-01-08T09:00:00+00:00");
+		assert_eq!(next("0 9 * * MON-FRI", "2024-01-05T08:59:00Z"), "2024-01-05T09:00:00+00:00");
+		assert_eq!(next("0 9 * * sat,sun", "2024-01-01T00:00:00Z"), "2024-01-06T09:00:00+00:00");
+		assert_eq!(next("0 0 1 jan-mar *", "2024-03-02T00:00:00Z"), "2025-01-01T00:00:00+00:00");
+	}
+
+	#[test]
+	fn sunday_is_zero_or_seven() {
+		// 2023-12-31 is a Sunday.
+		for weekday in ["0", "7", "sun", "SUN"] {
+			let expression = format!("0 0 * * {weekday}");
+			assert_eq!(next(&expression, "2023-12-30T12:00:00Z"), "2023-12-31T00:00:00+00:00", "{weekday}");
+		}
+		assert_eq!(next("0 0 * * 6-7", "2023-12-30T12:00:00Z"), "2023-12-31T00:00:00+00:00");
+		assert_eq!(next("0 0 * * 5-7", "2023-12-31T01:00:00Z"), "2024-01-05T00:00:00+00:00");
+		assert_eq!(next("@weekly", "2023-12-30T12:00:00Z"), "2023-12-31T00:00:00+00:00");
+	}
+
+	#[test]
+	fn day_of_month_or_day_of_week() {
+		// Both restricted: the 13th or any Friday, whichever comes first.
+		assert_eq!(next("0 0 13 * fri", "2024-01-01T00:00:00Z"), "2024-01-05T00:00:00+00:00");
+		assert_eq!(next("0 0 13 * fri", "2024-01-12T00:00:00Z"), "2024-01-13T00:00:00+00:00");
+		// Either one a `*`: only the other counts.
+		assert_eq!(next("0 0 13 * *", "2024-01-01T00:00:00Z"), "2024-01-13T00:00:00+00:00");
+		assert_eq!(next("0 0 * * fri", "2024-01-05T00:00:00Z"), "2024-01-12T00:00:00+00:00");
+		// A field starting with `*` counts as unrestricted for the choice, as
+		// in Vixie cron, so a stepped day of month must match as well.
+		assert_eq!(next("0 0 */2 * fri", "2024-01-05T00:00:00Z"), "2024-01-19T00:00:00+00:00");
+	}
+
+	#[test]
+	fn impossible_dates_never_fire() {
+		for expression in ["0 0 30 2 *", "0 0 31 4 *", "0 0 31 2,4,6,9,11 *"] {
+			let error = Cron::parse(expression).unwrap_err();
+			assert!(error.contains("never fires"), "{expression}: {error}");
+		}
+		// Leap days are years apart, but they come.
+		assert_eq!(next("0 0 29 2 *", "2024-03-01T00:00:00Z"), "2028-02-29T00:00:00+00:00");
+	}
+
+	#[test]
+	fn december_rolls_over_into_the_next_year() {
+		assert_eq!(next("0 0 1 * *", "2024-12-15T00:00:00Z"), "2025-01-01T00:00:00+00:00");
+		assert_eq!(next("30 23 31 12 *", "2024-12-31T23:30:00Z"), "2025-12-31T23:30:00+00:00");
+		assert_eq!(next("0 0 * 1 *", "2024-02-01T00:00:00Z"), "2025-01-01T00:00:00+00:00");
+		assert_eq!(next("@yearly", "2024-12-31T23:59:00Z"), "2025-01-01T00:00:00+00:00");
+		assert_eq!(next("59 23 * * *", "2024-12-31T23:59:00Z"), "2025-01-01T23:59:00+00:00");
+	}
+
+	#[test]
+	fn invalid_expressions() {
+		for expression in [
+			"* * * *",
+			"* * * * * *",
+			"60 * * * *",
+			"* 24 * * *",
+			"* * 0 * *",
+			"* * * 13 *",
+			"* * * * 8",
+			"30-10 * * * *",
+			"*/0 * * * *",
+			"*/x * * * *",
+			"* * * foo *",
+		] {
+			assert!(Cron::parse(expression).is_err(), "{expression}");
+		}
+	}
+}
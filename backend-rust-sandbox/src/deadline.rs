@@ -0,0 +1,77 @@
+use axum::http::HeaderMap;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tonic::metadata::MetadataMap;
+
+use crate::error::SandboxError;
+
+/// The caller's remaining budget for an HTTP request, in milliseconds.
+pub const DEADLINE_HEADER: &str = "x-deadline-ms";
+
+/// When an execution must be over for its caller to still get the answer:
+/// the `X-Deadline-Ms` budget less `overhead`, counted from now. `Ok(None)`
+/// without the header; a value that is not a number of milliseconds is a
+/// malformed request.
+pub fn from_headers(headers: &HeaderMap, overhead: Duration) -> Result<Option<Instant>, SandboxError> {
+	let Some(value) = headers.get(DEADLINE_HEADER) else {
+		return Ok(None);
+	};
+	let ms = value
+		.to_str()
+		.ok()
+		.and_then(|v| v.trim().parse::<u64>().ok())
+		.ok_or_else(|| SandboxError::InvalidRequest(format!("{DEADLINE_HEADER} must be a number of milliseconds")))?;
+	Ok(Some(deadline_in(Duration::from_millis(ms), overhead)))
+}
+
+/// The same for a gRPC call, from the `grpc-timeout` its client sent. A
+/// value that does not parse is ignored, as tonic does.
+pub fn from_metadata(metadata: &MetadataMap, overhead: Duration) -> Option<Instant> {
+	let timeout = metadata.get("grpc-timeout")?.to_str().ok()?;
+	parse_grpc_timeout(timeout).map(|budget| deadline_in(budget, overhead))
+}
+
+fn deadline_in(budget: Duration, overhead: Duration) -> Instant {
+	Instant::now() + budget.saturating_sub(overhead)
+}
+
+/// A `grpc-timeout` value: up to eight digits and a unit, `H`, `M`, `S`,
+/// `m`, `u` or `n`.
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+	if value.len() < 2 || value.len() > 9 {
+		return None;
+	}
+	let (digits, unit) = value.split_at(value.len() - 1);
+	let n: u64 = digits.parse().ok()?;
+	Some(match unit {
+		"H" => Duration::from_secs(n * 3600),
+		"M" => Duration::from_secs(n * 60),
+		"S" => Duration::from_secs(n),
+		"m" => Duration::from_millis(n),
+		"u" => Duration::from_micros(n),
+		"n" => Duration::from_nanos(n),
+		_ => return None,
+	})
+}
+
+/// Cancels an execution when its caller's deadline passes, unless it was
+/// dropped first.
+pub struct DeadlineGuard(JoinHandle<()>);
+
+impl DeadlineGuard {
+	pub fn arm(deadline: Instant, cancel: CancellationToken) -> Self {
+		Self(tokio::spawn(async move {
+			tokio::select! {
+				_ = tokio::time::sleep_until(deadline.into()) => cancel.cancel(),
+				_ = cancel.cancelled() => {}
+			}
+		}))
+	}
+}
+
+impl Drop for DeadlineGuard {
+	fn drop(&mut self) {
+		self.0.abort();
+	}
+}
@@ -0,0 +1,315 @@
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::cgroup::IsolationMode;
+use crate::namespace::check;
+
+const XFS_SUPER_MAGIC: i64 = 0x5846_5342;
+/// `SYS_quotactl_fd`, which has the same number on every architecture.
+const SYS_QUOTACTL_FD: libc::c_long = 443;
+const Q_XGETQUOTA: libc::c_uint = 0x5803;
+const Q_XSETQLIM: libc::c_uint = 0x5804;
+const PRJQUOTA: libc::c_uint = 2;
+/// `_IOR('X', 31, struct fsxattr)` and `_IOW('X', 32, struct fsxattr)`.
+const FS_IOC_FSGETXATTR: libc::c_ulong = 0x801c_581f;
+const FS_IOC_FSSETXATTR: libc::c_ulong = 0x401c_5820;
+/// New files and directories take the directory's project.
+const FS_XFLAG_PROJINHERIT: u32 = 0x200;
+const FS_DQUOT_VERSION: i8 = 1;
+const FS_PROJ_QUOTA: i8 = 2;
+const FS_DQ_BHARD: u16 = 1 << 3;
+/// XFS counts quota in 512-byte basic blocks, as `st_blocks` does.
+const BASIC_BLOCK: u64 = 512;
+/// Workspaces get project ids from this range, clear of the low ones an
+/// administrator is likely to have in `/etc/projid`.
+const FIRST_PROJECT: u32 = 1 << 20;
+const PROJECTS: u32 = 1 << 20;
+/// XFS refuses the write that would go over, so a workspace it has filled
+/// may stop this far short of the limit.
+const XFS_SLACK: u64 = 64 * 1024;
+
+/// `struct fsxattr`.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Default)]
+struct FsXattr {
+	xflags: u32,
+	extsize: u32,
+	nextents: u32,
+	projid: u32,
+	cowextsize: u32,
+	pad: [u8; 8],
+}
+
+/// `struct fs_disk_quota`. The sandbox sets and reads block counts only.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Default)]
+struct FsDiskQuota {
+	version: i8,
+	flags: i8,
+	fieldmask: u16,
+	id: u32,
+	blk_hardlimit: u64,
+	blk_softlimit: u64,
+	ino_hardlimit: u64,
+	ino_softlimit: u64,
+	bcount: u64,
+	icount: u64,
+	itimer: i32,
+	btimer: i32,
+	iwarns: u16,
+	bwarns: u16,
+	itimer_hi: i8,
+	btimer_hi: i8,
+	rtbtimer_hi: i8,
+	padding2: i8,
+	rtb_hardlimit: u64,
+	rtb_softlimit: u64,
+	rtbcount: u64,
+	rtbtimer: i32,
+	rtbwarns: u16,
+	padding3: i16,
+	padding4: [u8; 8],
+}
+
+/// XFS project quotas on the workspace root's filesystem: one project per
+/// workspace, with a hard block limit the kernel enforces.
+#[derive(Debug)]
+struct XfsProjects {
+	/// Any file on the filesystem names it to `quotactl_fd`.
+	root: File,
+	next: AtomicU32,
+}
+
+impl XfsProjects {
+	fn open(root: &Path) -> Result<Self, String> {
+		let file = File::open(root).map_err(|e| format!("{}: {e}", root.display()))?;
+		// SAFETY: fstatfs(2) on an open fd, into a zeroed struct that
+		// outlives the call.
+		let magic = unsafe {
+			let mut stat: libc::statfs = std::mem::zeroed();
+			check(libc::fstatfs(file.as_raw_fd(), &mut stat)).map_err(|e| e.to_string())?;
+			stat.f_type as i64
+		};
+		if magic != XFS_SUPER_MAGIC {
+			return Err(format!("{} is not on XFS", root.display()));
+		}
+		let projects = Self {
+			root: file,
+			next: AtomicU32::new(std::process::id() % PROJECTS),
+		};
+		// A filesystem mounted without `prjquota`, or a sandbox without
+		// CAP_SYS_ADMIN, fails here rather than on the first execution.
+		let probe = root.join(".quota-probe");
+		std::fs::create_dir_all(&probe).map_err(|e| format!("{}: {e}", probe.display()))?;
+		let probed = projects
+			.assign(&probe, BASIC_BLOCK)
+			.and_then(|id| projects.usage(id).and_then(|_| projects.set_limit(id, 0)));
+		let _ = std::fs::remove_dir(&probe);
+		probed.map_err(|e| format!("project quotas unavailable: {e}"))?;
+		Ok(projects)
+	}
+
+	/// Put `dir` in a fresh project limited to `limit` bytes; its id.
+	fn assign(&self, dir: &Path, limit: u64) -> io::Result<u32> {
+		let id = FIRST_PROJECT + self.next.fetch_add(1, Ordering::Relaxed) % PROJECTS;
+		self.set_limit(id, limit)?;
+		let dir = File::open(dir)?;
+		let mut attr = FsXattr::default();
+		// SAFETY: ioctls on an open fd with a struct that outlives them.
+		unsafe {
+			check(libc::ioctl(dir.as_raw_fd(), FS_IOC_FSGETXATTR as _, &mut attr))?;
+			attr.projid = id;
+			attr.xflags |= FS_XFLAG_PROJINHERIT;
+			check(libc::ioctl(dir.as_raw_fd(), FS_IOC_FSSETXATTR as _, &attr))?;
+		}
+		Ok(id)
+	}
+
+	/// Limit project `id` to `limit` bytes; 0 lifts the limit.
+	fn set_limit(&self, id: u32, limit: u64) -> io::Result<()> {
+		let mut quota = FsDiskQuota {
+			version: FS_DQUOT_VERSION,
+			flags: FS_PROJ_QUOTA,
+			fieldmask: FS_DQ_BHARD,
+			id,
+			blk_hardlimit: limit.div_ceil(BASIC_BLOCK),
+			..Default::default()
+		};
+		self.quotactl(Q_XSETQLIM, id, &mut quota)
+	}
+
+	/// Bytes the files of project `id` take up.
+	fn usage(&self, id: u32) -> io::Result<u64> {
+		let mut quota = FsDiskQuota::default();
+		match self.quotactl(Q_XGETQUOTA, id, &mut quota) {
+			Ok(()) => Ok(quota.bcount * BASIC_BLOCK),
+			// No quota record: nothing written yet.
+			Err(e) if e.raw_os_error() == Some(libc::ENOENT) => Ok(0),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn quotactl(&self, cmd: libc::c_uint, id: u32, quota: &mut FsDiskQuota) -> io::Result<()> {
+		// SAFETY: quotactl_fd(2) on an open fd, with a struct of the type
+		// the command takes that outlives the call.
+		let rc = unsafe {
+			libc::syscall(
+				SYS_QUOTACTL_FD,
+				self.root.as_raw_fd(),
+				(cmd << 8) | PRJQUOTA,
+				id,
+				quota as *mut FsDiskQuota,
+			)
+		};
+		check(rc as libc::c_int)
+	}
+}
+
+/// Caps on the disk space of workspaces: XFS project quotas where the root
+/// supports them, and otherwise a periodic count of what each workspace
+/// holds.
+pub struct DiskQuotas {
+	xfs: Option<Arc<XfsProjects>>,
+	interval: Duration,
+}
+
+impl DiskQuotas {
+	/// Project quotas on `root` according to `mode`, checked every
+	/// `interval` either way.
+	pub fn init(root: &Path, mode: IsolationMode, interval: Duration) -> Result<Self, String> {
+		let xfs = match mode {
+			IsolationMode::Off => None,
+			mode => match XfsProjects::open(root) {
+				Ok(xfs) => Some(Arc::new(xfs)),
+				Err(e) if mode == IsolationMode::Auto => {
+					warn!(error = %e, message = "XFS project quotas unavailable; workspace disk use is checked periodically");
+					None
+				}
+				Err(e) => return Err(format!("XFS project quotas unavailable: {e}")),
+			},
+		};
+		Ok(Self { xfs, interval })
+	}
+
+	/// Whether the kernel enforces the quotas.
+	pub fn enforced(&self) -> bool {
+		self.xfs.is_some()
+	}
+
+	/// Cap the (empty) directory `dir` at `limit_mb`.
+	pub fn apply(&self, dir: &Path, limit_mb: u64) -> io::Result<DiskQuota> {
+		let limit = limit_mb.saturating_mul(1024 * 1024);
+		let project = match &self.xfs {
+			Some(xfs) => Some((xfs.clone(), xfs.assign(dir, limit)?)),
+			None => None,
+		};
+		Ok(DiskQuota {
+			dir: dir.to_path_buf(),
+			limit,
+			project,
+			interval: self.interval,
+			exceeded: Arc::new(AtomicBool::new(false)),
+		})
+	}
+}
+
+/// The disk space one workspace may take up.
+#[derive(Debug, Clone)]
+pub struct DiskQuota {
+	dir: PathBuf,
+	limit: u64,
+	/// The XFS project holding the workspace, when the kernel enforces the
+	/// limit.
+	project: Option<(Arc<XfsProjects>, u32)>,
+	interval: Duration,
+	/// Set by [`watch`](Self::watch) once the workspace went over.
+	exceeded: Arc<AtomicBool>,
+}
+
+impl DiskQuota {
+	pub fn limit_mb(&self) -> u64 {
+		self.limit / (1024 * 1024)
+	}
+
+	/// Bytes the workspace takes up.
+	async fn usage(&self) -> io::Result<u64> {
+		match &self.project {
+			Some((xfs, id)) => xfs.usage(*id),
+			None => {
+				let dir = self.dir.clone();
+				tokio::task::spawn_blocking(move || dir_usage(&dir)).await.map_err(io::Error::other)?
+			}
+		}
+	}
+
+	/// Cancel the execution, through `cancel`, once the workspace reaches its
+	/// limit. Under XFS the kernel has refused the writes past it by then;
+	/// otherwise the tool is stopped within an interval of going over.
+	pub async fn watch(self, cancel: CancellationToken) {
+		loop {
+			tokio::select! {
+				_ = tokio::time::sleep(self.interval) => {}
+				_ = cancel.cancelled() => return,
+			}
+			match self.usage().await {
+				Ok(used) if self.full(used) => {
+					self.exceeded.store(true, Ordering::Relaxed);
+					cancel.cancel();
+					return;
+				}
+				Ok(_) => {}
+				Err(e) => {
+					warn!(workspace = %self.dir.display(), error = %e, message = "Failed to measure workspace disk use");
+					return;
+				}
+			}
+		}
+	}
+
+	/// Whether the workspace went over its limit, now or while it was
+	/// watched.
+	pub async fn exceeded(&self) -> bool {
+		self.exceeded.load(Ordering::Relaxed) || self.usage().await.is_ok_and(|used| self.full(used))
+	}
+
+	fn full(&self, used: u64) -> bool {
+		match self.project {
+			Some(_) => used >= self.limit.saturating_sub(XFS_SLACK),
+			None => used >= self.limit,
+		}
+	}
+}
+
+/// Bytes allocated to the files under `dir`. Files removed while it counts
+/// are skipped.
+fn dir_usage(dir: &Path) -> io::Result<u64> {
+	let mut used = 0;
+	let mut dirs = vec![dir.to_path_buf()];
+	while let Some(dir) = dirs.pop() {
+		let entries = match std::fs::read_dir(&dir) {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e),
+		};
+		for entry in entries.flatten() {
+			let Ok(meta) = entry.path().symlink_metadata() else {
+				continue;
+			};
+			used += meta.blocks() * BASIC_BLOCK;
+			if meta.is_dir() {
+				dirs.push(entry.path());
+			}
+		}
+	}
+	Ok(used)
+}
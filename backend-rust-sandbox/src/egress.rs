@@ -0,0 +1,577 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::debug;
+
+use crate::namespace::check;
+
+/// Where allowlisted tools find the egress proxy, inside their own network
+/// namespace.
+const PROXY_PORT: u16 = 3128;
+/// Longest request head the proxy reads before giving up on a client.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Which descriptors the child sent, as bits of the message's data byte.
+const SENT_SNMP6: u8 = 1;
+const SENT_LISTENER: u8 = 2;
+
+/// A tool manifest's `network` setting.
+///
+/// ```toml
+/// network = "none"                                  # no network at all
+/// network = { allowlist = ["api.github.com", "*.githubusercontent.com"] }
+/// network = "full"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkPolicy {
+	None,
+	/// Host names (or IP literals) the tool may connect to; `*.example.com`
+	/// matches every subdomain of `example.com`, but not `example.com` itself.
+	Allowlist(Vec<String>),
+	Full,
+}
+
+impl NetworkPolicy {
+	/// Check the allowlist entries and normalise them to lowercase.
+	pub fn validate(&mut self) -> Result<(), String> {
+		let NetworkPolicy::Allowlist(domains) = self else {
+			return Ok(());
+		};
+		if domains.is_empty() {
+			return Err("network.allowlist must not be empty; use network = \"none\" instead".to_string());
+		}
+		for domain in domains.iter_mut() {
+			*domain = domain.trim_end_matches('.').to_ascii_lowercase();
+			let name = domain.strip_prefix("*.").unwrap_or(domain);
+			let valid = domain.parse::<IpAddr>().is_ok()
+				|| (!name.is_empty()
+					&& name.split('.').all(|label| {
+						!label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+					}));
+			if !valid {
+				return Err(format!("network.allowlist: {domain:?} is not a host name"));
+			}
+		}
+		Ok(())
+	}
+
+	/// Whether the tool may connect to `host`.
+	pub fn allows(&self, host: &str) -> bool {
+		match self {
+			NetworkPolicy::None => false,
+			NetworkPolicy::Full => true,
+			NetworkPolicy::Allowlist(domains) => {
+				let host = host.trim_end_matches('.').to_ascii_lowercase();
+				domains.iter().any(|domain| match domain.strip_prefix("*.") {
+					Some(parent) => host
+						.strip_suffix(parent)
+						.is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+					None => *domain == host,
+				})
+			}
+		}
+	}
+}
+
+/// Cuts a native subprocess off from the network: it runs in a network
+/// namespace of its own with only a loopback interface. Under an allowlist,
+/// an HTTP proxy listening on that loopback forwards permitted hosts, and
+/// `HTTP_PROXY`/`HTTPS_PROXY` point the tool at it.
+#[derive(Debug, Clone)]
+pub struct NetworkJail {
+	policy: Arc<NetworkPolicy>,
+}
+
+impl NetworkJail {
+	/// `None` when the policy allows everything.
+	pub fn new(policy: &NetworkPolicy) -> Option<Self> {
+		(*policy != NetworkPolicy::Full).then(|| Self {
+			policy: Arc::new(policy.clone()),
+		})
+	}
+
+	fn proxied(&self) -> bool {
+		matches!(*self.policy, NetworkPolicy::Allowlist(_))
+	}
+
+	/// Environment pointing proxy-aware clients at the egress proxy.
+	pub fn proxy_env(&self) -> Vec<(String, String)> {
+		if !self.proxied() {
+			return Vec::new();
+		}
+		let url = format!("http://127.0.0.1:{PROXY_PORT}");
+		["HTTP_PROXY", "HTTPS_PROXY", "http_proxy", "https_proxy", "ALL_PROXY"]
+			.into_iter()
+			.map(|key| (key.to_string(), url.clone()))
+			.chain(["NO_PROXY", "no_proxy"].map(|key| (key.to_string(), "localhost,127.0.0.1".to_string())))
+			.collect()
+	}
+
+	/// Socket pair over which the child hands its namespace's descriptors to
+	/// the sandbox. The first end stays with the sandbox.
+	pub fn channel() -> io::Result<(UnixStream, UnixStream)> {
+		UnixStream::pair()
+	}
+
+	/// Set up the child's side. Runs between fork and exec, once it is in a
+	/// network namespace of its own (`CLONE_NEWNET`), so it only makes
+	/// syscalls. The namespace's counters and the proxy listener are sent
+	/// over `channel` for `NetworkMonitor::receive`.
+	pub fn enter(&self, channel: RawFd) -> io::Result<()> {
+		// SAFETY: plain syscalls on stack buffers and NUL-terminated strings.
+		unsafe {
+			loopback_up()?;
+			let snmp = libc::open(c"/proc/self/net/snmp".as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+			check(snmp)?;
+			let mut fds = [snmp, -1, -1];
+			let mut count = 1;
+			let mut sent = 0;
+			let snmp6 = libc::open(c"/proc/self/net/snmp6".as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+			if snmp6 >= 0 {
+				fds[count] = snmp6;
+				count += 1;
+				sent |= SENT_SNMP6;
+			}
+			if self.proxied() {
+				fds[count] = listen_loopback(PROXY_PORT)?;
+				count += 1;
+				sent |= SENT_LISTENER;
+			}
+			send_fds(channel, sent, &fds[..count])
+		}
+	}
+}
+
+/// Watches a jailed subprocess's network namespace from the sandbox side:
+/// runs its egress proxy and reads the namespace's counters afterwards.
+pub struct NetworkMonitor {
+	snmp: File,
+	snmp6: Option<File>,
+	proxy: Option<JoinHandle<()>>,
+	/// Destinations the proxy refused.
+	denied: Arc<Mutex<Vec<String>>>,
+}
+
+impl NetworkMonitor {
+	/// Take the descriptors `NetworkJail::enter` sent. Called right after
+	/// spawn, by which point the child has sent them.
+	pub fn receive(jail: &NetworkJail, channel: &UnixStream) -> io::Result<Self> {
+		let (sent, mut fds) = recv_fds(channel.as_raw_fd())?;
+		let mut fds = fds.drain(..);
+		let mut next = || fds.next().ok_or_else(|| io::Error::other("network namespace descriptors missing"));
+		let snmp = File::from(next()?);
+		let snmp6 = (sent & SENT_SNMP6 != 0).then(&mut next).transpose()?.map(File::from);
+		let denied = Arc::new(Mutex::new(Vec::new()));
+		let mut proxy = None;
+		if sent & SENT_LISTENER != 0 {
+			let listener = std::net::TcpListener::from(next()?);
+			listener.set_nonblocking(true)?;
+			let listener = TcpListener::from_std(listener)?;
+			proxy = Some(tokio::spawn(serve_proxy(listener, jail.policy.clone(), denied.clone())));
+		}
+		Ok(Self {
+			snmp,
+			snmp6,
+			proxy,
+			denied,
+		})
+	}
+
+	/// Stop the proxy and report what the tool was refused: each host the
+	/// proxy denied, plus one entry if it tried to reach the network directly.
+	pub fn finish(mut self) -> Vec<String> {
+		if let Some(proxy) = self.proxy.take() {
+			proxy.abort();
+		}
+		let mut denied = std::mem::take(&mut *self.denied.lock().unwrap());
+		// Without a route out of the namespace, every direct connection or
+		// DNS query fails its route lookup, or lands on a closed loopback port.
+		let mut blocked = read_counters(&mut self.snmp, &[("Ip", "OutNoRoutes"), ("Udp", "NoPorts")]);
+		if let Some(snmp6) = &mut self.snmp6 {
+			blocked += read_counters(snmp6, &[("", "Ip6OutNoRoutes"), ("", "Udp6NoPorts")]);
+		}
+		if blocked > 0 {
+			denied.push("direct connection".to_string());
+		}
+		denied
+	}
+}
+
+impl Drop for NetworkMonitor {
+	fn drop(&mut self) {
+		if let Some(proxy) = &self.proxy {
+			proxy.abort();
+		}
+	}
+}
+
+/// Sum the named counters. `/proc/net/snmp` has a header line of names and a
+/// line of values per protocol (`Ip: ... OutNoRoutes ...`); `snmp6` has one
+/// `Name value` line per counter, read here with an empty protocol.
+fn read_counters(file: &mut File, wanted: &[(&str, &str)]) -> u64 {
+	let mut text = String::new();
+	if file.read_to_string(&mut text).is_err() {
+		return 0;
+	}
+	let mut total = 0;
+	let lines: Vec<&str> = text.lines().collect();
+	for (protocol, counter) in wanted {
+		if protocol.is_empty() {
+			total += lines
+				.iter()
+				.filter_map(|line| line.split_once(char::is_whitespace))
+				.filter(|(name, _)| name == counter)
+				.filter_map(|(_, value)| value.trim().parse::<u64>().ok())
+				.sum::<u64>();
+			continue;
+		}
+		let prefix = format!("{protocol}:");
+		let mut rows = lines.iter().filter(|line| line.starts_with(&prefix));
+		if let (Some(names), Some(values)) = (rows.next(), rows.next()) {
+			if let Some((_, value)) = names
+				.split_whitespace()
+				.zip(values.split_whitespace())
+				.find(|(name, _)| name == counter)
+			{
+				total += value.parse::<u64>().unwrap_or(0);
+			}
+		}
+	}
+	total
+}
+
+async fn serve_proxy(listener: TcpListener, policy: Arc<NetworkPolicy>, denied: Arc<Mutex<Vec<String>>>) {
+	// Dropped with the task, which aborts every connection still open.
+	let mut connections = JoinSet::new();
+	loop {
+		tokio::select! {
+			accepted = listener.accept() => match accepted {
+				Ok((client, _)) => {
+					connections.spawn(proxy_connection(client, policy.clone(), denied.clone()));
+				}
+				Err(e) => {
+					debug!(error = %e, message = "Egress proxy accept failed");
+					tokio::time::sleep(Duration::from_millis(10)).await;
+				}
+			},
+			Some(_) = connections.join_next() => {}
+		}
+	}
+}
+
+/// Serve one proxy client: a `CONNECT host:port` tunnel, or a plain HTTP
+/// request in absolute form. Anything the policy does not allow gets `403`.
+async fn proxy_connection(mut client: TcpStream, policy: Arc<NetworkPolicy>, denied: Arc<Mutex<Vec<String>>>) {
+	let head = match tokio::time::timeout(HEAD_TIMEOUT, read_head(&mut client)).await {
+		Ok(Ok(Some(head))) => head,
+		Ok(Ok(None)) | Ok(Err(_)) | Err(_) => return,
+	};
+	let Some(Target { host, port, origin_line }) = parse_target(&head) else {
+		respond(&mut client, "400 Bad Request", "unsupported proxy request").await;
+		return;
+	};
+	if !policy.allows(&host) {
+		let host = if host.contains(':') { format!("[{host}]") } else { host };
+		denied.lock().unwrap().push(format!("{host}:{port}"));
+		respond(&mut client, "403 Forbidden", "blocked by the sandbox egress policy").await;
+		return;
+	}
+	let mut upstream = match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host.as_str(), port))).await {
+		Ok(Ok(upstream)) => upstream,
+		Ok(Err(_)) | Err(_) => {
+			respond(&mut client, "502 Bad Gateway", "could not reach upstream").await;
+			return;
+		}
+	};
+	let forwarded = match origin_line {
+		None => client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").await,
+		Some(line) => {
+			let rest = head.iter().position(|&b| b == b'\r').map_or(&head[..], |i| &head[i..]);
+			let mut request = line.into_bytes();
+			request.extend_from_slice(rest);
+			upstream.write_all(&request).await
+		}
+	};
+	if forwarded.is_ok() {
+		let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+	}
+}
+
+/// Read up to the end of the request head. Bytes the client sent after it
+/// (a request body) are kept and forwarded with it.
+async fn read_head(client: &mut TcpStream) -> io::Result<Option<Vec<u8>>> {
+	let mut head = Vec::with_capacity(1024);
+	loop {
+		if head.windows(4).any(|w| w == b"\r\n\r\n") {
+			return Ok(Some(head));
+		}
+		if head.len() >= MAX_HEAD_BYTES || client.read_buf(&mut head).await? == 0 {
+			return Ok(None);
+		}
+	}
+}
+
+/// Where a proxy request goes.
+#[derive(Debug, PartialEq)]
+struct Target {
+	/// Without the brackets of an IPv6 literal, as allowlists spell it.
+	host: String,
+	port: u16,
+	/// For plain HTTP, the request line rewritten to origin form
+	/// (`GET /path HTTP/1.1`), which servers handle more reliably than the
+	/// absolute form sent to proxies; `None` for a `CONNECT` tunnel.
+	origin_line: Option<String>,
+}
+
+fn parse_target(head: &[u8]) -> Option<Target> {
+	let line = head.split(|&b| b == b'\r').next()?;
+	let line = std::str::from_utf8(line).ok()?;
+	let mut parts = line.split(' ');
+	let (method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+	let (authority, default_port, origin_line) = if method.eq_ignore_ascii_case("CONNECT") {
+		(target, None, None)
+	} else {
+		let rest = target.get(..7).filter(|s| s.eq_ignore_ascii_case("http://")).map(|_| &target[7..])?;
+		let path_start = rest.find(['/', '?']).unwrap_or(rest.len());
+		let path = match &rest[path_start..] {
+			"" => "/".to_string(),
+			path if path.starts_with('?') => format!("/{path}"),
+			path => path.to_string(),
+		};
+		(&rest[..path_start], Some(80), Some(format!("{method} {path} {version}")))
+	};
+	let (host, port) = match authority.rsplit_once(':') {
+		Some((host, port)) if !port.contains(']') => (host, port.parse().ok()?),
+		_ => (authority, default_port?),
+	};
+	let host = match host.strip_prefix('[') {
+		Some(literal) => literal.strip_suffix(']').filter(|literal| literal.contains(':'))?,
+		None => host,
+	};
+	if host.is_empty() {
+		return None;
+	}
+	Some(Target {
+		host: host.to_string(),
+		port,
+		origin_line,
+	})
+}
+
+async fn respond(client: &mut TcpStream, status: &str, body: &str) {
+	let response = format!(
+		"HTTP/1.1 {status}\r\ncontent-type: text/plain\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}\n",
+		body.len() + 1
+	);
+	let _ = client.write_all(response.as_bytes()).await;
+}
+
+/// Bring up `lo`, which starts out down in a new network namespace.
+unsafe fn loopback_up() -> io::Result<()> {
+	let sock = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+	check(sock)?;
+	let mut req: libc::ifreq = std::mem::zeroed();
+	for (dst, src) in req.ifr_name.iter_mut().zip(b"lo") {
+		*dst = *src as libc::c_char;
+	}
+	req.ifr_ifru.ifru_flags = (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+	let rc = libc::ioctl(sock, libc::SIOCSIFFLAGS, &req as *const libc::ifreq);
+	libc::close(sock);
+	check(rc)
+}
+
+unsafe fn listen_loopback(port: u16) -> io::Result<RawFd> {
+	let fd = libc::socket(libc::AF_INET, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+	check(fd)?;
+	let addr = libc::sockaddr_in {
+		sin_family: libc::AF_INET as libc::sa_family_t,
+		sin_port: port.to_be(),
+		sin_addr: libc::in_addr {
+			s_addr: u32::from(Ipv4Addr::LOCALHOST).to_be(),
+		},
+		sin_zero: [0; 8],
+	};
+	check(libc::bind(
+		fd,
+		&addr as *const libc::sockaddr_in as *const libc::sockaddr,
+		std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+	))?;
+	check(libc::listen(fd, 128))?;
+	Ok(fd)
+}
+
+/// Room for the `SCM_RIGHTS` message carrying up to three descriptors.
+#[repr(C, align(8))]
+struct ControlBuffer([u8; 64]);
+
+unsafe fn send_fds(channel: RawFd, sent: u8, fds: &[RawFd]) -> io::Result<()> {
+	let mut data = [sent];
+	let mut iov = libc::iovec {
+		iov_base: data.as_mut_ptr().cast(),
+		iov_len: data.len(),
+	};
+	let mut control = ControlBuffer([0; 64]);
+	let fds_len = std::mem::size_of_val(fds) as libc::c_uint;
+	let mut msg: libc::msghdr = std::mem::zeroed();
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.0.as_mut_ptr().cast();
+	msg.msg_controllen = libc::CMSG_SPACE(fds_len) as _;
+	let cmsg = libc::CMSG_FIRSTHDR(&msg);
+	(*cmsg).cmsg_level = libc::SOL_SOCKET;
+	(*cmsg).cmsg_type = libc::SCM_RIGHTS;
+	(*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+	std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+	if libc::sendmsg(channel, &msg, 0) < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+fn recv_fds(channel: RawFd) -> io::Result<(u8, Vec<OwnedFd>)> {
+	let mut data = [0u8];
+	let mut iov = libc::iovec {
+		iov_base: data.as_mut_ptr().cast(),
+		iov_len: data.len(),
+	};
+	let mut control = ControlBuffer([0; 64]);
+	let mut fds = Vec::new();
+	// SAFETY: recvmsg into stack buffers sized above; the kernel reports how
+	// much of the control buffer it filled, and every descriptor it passed is
+	// owned from here on.
+	unsafe {
+		let mut msg: libc::msghdr = std::mem::zeroed();
+		msg.msg_iov = &mut iov;
+		msg.msg_iovlen = 1;
+		msg.msg_control = control.0.as_mut_ptr().cast();
+		msg.msg_controllen = control.0.len() as _;
+		if libc::recvmsg(channel, &mut msg, libc::MSG_DONTWAIT | libc::MSG_CMSG_CLOEXEC) < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let cmsg = libc::CMSG_FIRSTHDR(&msg);
+		if !cmsg.is_null() && (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+			let len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+			let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+			for i in 0..len / std::mem::size_of::<RawFd>() {
+				fds.push(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+			}
+		}
+	}
+	Ok((data[0], fds))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn allowlist(domains: &[&str]) -> NetworkPolicy {
+		let mut policy = NetworkPolicy::Allowlist(domains.iter().map(|d| d.to_string()).collect());
+		policy.validate().unwrap();
+		policy
+	}
+
+	fn target(line: &str) -> Option<(String, u16, Option<String>)> {
+		let head = format!("{line}\r\nHost: example\r\n\r\n");
+		parse_target(head.as_bytes()).map(|t| (t.host, t.port, t.origin_line))
+	}
+
+	#[test]
+	fn parse_target_reads_host_and_port() {
+		assert_eq!(target("CONNECT api.github.com:443 HTTP/1.1"), Some(("api.github.com".into(), 443, None)));
+		assert_eq!(
+			target("GET http://example.com:8080/a?b=c HTTP/1.1"),
+			Some(("example.com".into(), 8080, Some("GET /a?b=c HTTP/1.1".into())))
+		);
+		assert_eq!(
+			target("GET HTTP://example.com HTTP/1.1"),
+			Some(("example.com".into(), 80, Some("GET / HTTP/1.1".into())))
+		);
+		assert_eq!(
+			target("GET http://example.com?q HTTP/1.1"),
+			Some(("example.com".into(), 80, Some("GET /?q HTTP/1.1".into())))
+		);
+		// A tunnel names its port; only plain HTTP has a default.
+		assert_eq!(target("CONNECT api.github.com HTTP/1.1"), None);
+		assert_eq!(target("GET https://example.com/ HTTP/1.1"), None);
+		assert_eq!(target("GET /relative HTTP/1.1"), None);
+		assert_eq!(target("CONNECT :443 HTTP/1.1"), None);
+		assert_eq!(target("CONNECT"), None);
+	}
+
+	#[test]
+	fn parse_target_reads_ports_in_range_only() {
+		assert_eq!(target("CONNECT example.com:1 HTTP/1.1").map(|t| t.1), Some(1));
+		assert_eq!(target("CONNECT example.com:65535 HTTP/1.1").map(|t| t.1), Some(65535));
+		assert_eq!(target("CONNECT example.com:65536 HTTP/1.1"), None);
+		assert_eq!(target("CONNECT example.com:-1 HTTP/1.1"), None);
+		assert_eq!(target("CONNECT example.com: HTTP/1.1"), None);
+		assert_eq!(target("CONNECT example.com:https HTTP/1.1"), None);
+	}
+
+	#[test]
+	fn parse_target_unbrackets_ipv6() {
+		assert_eq!(target("CONNECT [2001:db8::1]:443 HTTP/1.1"), Some(("2001:db8::1".into(), 443, None)));
+		assert_eq!(
+			target("GET http://[::1]/ HTTP/1.1"),
+			Some(("::1".into(), 80, Some("GET / HTTP/1.1".into())))
+		);
+		assert_eq!(target("GET http://[::1]:8080/ HTTP/1.1").map(|t| t.1), Some(8080));
+		assert_eq!(target("CONNECT [2001:db8::1] HTTP/1.1"), None);
+		assert_eq!(target("CONNECT [2001:db8::1:443 HTTP/1.1"), None);
+		assert_eq!(target("CONNECT [example.com]:443 HTTP/1.1"), None);
+		assert_eq!(target("CONNECT []:443 HTTP/1.1"), None);
+	}
+
+	#[test]
+	fn allowlist_matches_hosts_exactly() {
+		let policy = allowlist(&["API.GitHub.com.", "10.0.0.1", "2001:db8::1"]);
+		assert!(policy.allows("api.github.com"));
+		assert!(policy.allows("Api.GitHub.Com."));
+		assert!(policy.allows("10.0.0.1"));
+		assert!(policy.allows("2001:db8::1"));
+		assert!(!policy.allows("github.com"));
+		assert!(!policy.allows("evil-api.github.com"));
+		assert!(!policy.allows("api.github.com.evil.com"));
+		assert!(!policy.allows("10.0.0.10"));
+	}
+
+	#[test]
+	fn allowlist_wildcards_match_subdomains_only() {
+		let policy = allowlist(&["*.githubusercontent.com"]);
+		assert!(policy.allows("raw.githubusercontent.com"));
+		assert!(policy.allows("a.b.githubusercontent.com"));
+		assert!(!policy.allows("githubusercontent.com"));
+		assert!(!policy.allows("evilgithubusercontent.com"));
+		assert!(!policy.allows(".githubusercontent.com"));
+		assert!(!policy.allows("githubusercontent.com.evil.com"));
+	}
+
+	#[test]
+	fn proxied_ipv6_targets_match_the_allowlist() {
+		let policy = allowlist(&["2001:db8::1"]);
+		let host = target("CONNECT [2001:db8::1]:443 HTTP/1.1").unwrap().0;
+		assert!(policy.allows(&host));
+	}
+
+	#[test]
+	fn validate_refuses_non_host_names() {
+		for bad in ["", "*.", "exa mple.com", "example..com", "*.*.example.com", "http://example.com"] {
+			let mut policy = NetworkPolicy::Allowlist(vec![bad.to_string()]);
+			assert!(policy.validate().is_err(), "{bad:?}");
+		}
+		assert!(NetworkPolicy::Allowlist(vec![]).validate().is_err());
+		assert!(!NetworkPolicy::None.allows("example.com"));
+		assert!(NetworkPolicy::Full.allows("example.com"));
+	}
+}
@@ -0,0 +1,126 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::subprocess::{OutputChunk, StreamKind};
+
+pub use pagi_sandbox_client::wire::Encoding;
+
+/// Bytes a UTF-8 character has past its first.
+const MAX_CHAR_TAIL: usize = 3;
+
+/// How a result's `stdout` and `stderr` are carried; results only have it
+/// when either is not plain UTF-8.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputEncoding {
+	#[serde(default)]
+	pub stdout: Encoding,
+	#[serde(default)]
+	pub stderr: Encoding,
+}
+
+impl OutputEncoding {
+	/// `None` when both streams are text.
+	pub fn of(stdout: Encoding, stderr: Encoding) -> Option<Self> {
+		(!stdout.is_utf8() || !stderr.is_utf8()).then_some(Self { stdout, stderr })
+	}
+
+	pub fn stdout(encoding: Option<Self>) -> Encoding {
+		encoding.unwrap_or_default().stdout
+	}
+
+	pub fn stderr(encoding: Option<Self>) -> Encoding {
+		encoding.unwrap_or_default().stderr
+	}
+}
+
+/// Captured output as a string: as it is when it is UTF-8, in base64
+/// otherwise. A capture `cut` at the output limit may end partway through a
+/// character, which is dropped rather than making the whole of it binary.
+pub fn encode(bytes: Vec<u8>, cut: bool) -> (String, Encoding) {
+	match String::from_utf8(bytes) {
+		Ok(text) => (text, Encoding::Utf8),
+		Err(e) => {
+			let error = e.utf8_error();
+			let mut bytes = e.into_bytes();
+			if cut && error.error_len().is_none() {
+				bytes.truncate(error.valid_up_to());
+				return (String::from_utf8(bytes).expect("valid up to the cut"), Encoding::Utf8);
+			}
+			(STANDARD.encode(&bytes), Encoding::Base64)
+		}
+	}
+}
+
+/// The bytes of output `text` in `encoding`.
+pub fn decode(text: &str, encoding: Encoding) -> Vec<u8> {
+	encoding.decode(text).unwrap_or_default()
+}
+
+/// Add `note` to output `text` in `encoding`, starting it on a line of its
+/// own.
+pub fn push_note(text: &mut String, encoding: Encoding, note: &str) {
+	match encoding {
+		Encoding::Base64 => {
+			let mut bytes = decode(text, encoding);
+			if bytes.last().is_some_and(|&b| b != b'\n') {
+				bytes.push(b'\n');
+			}
+			bytes.extend_from_slice(note.as_bytes());
+			*text = STANDARD.encode(bytes);
+		}
+		Encoding::Utf8 | Encoding::Artifact => {
+			if !text.is_empty() && !text.ends_with('\n') {
+				text.push('\n');
+			}
+			text.push_str(note);
+		}
+	}
+}
+
+/// Output `text` in `encoding`, with `prefix` before it on a line of its
+/// own, unless that is empty.
+pub fn prefixed(prefix: &str, text: &str, encoding: Encoding) -> String {
+	let separator = if prefix.is_empty() { "" } else { "\n" };
+	match encoding {
+		Encoding::Base64 => {
+			let mut bytes = format!("{prefix}{separator}").into_bytes();
+			bytes.extend(decode(text, encoding));
+			STANDARD.encode(bytes)
+		}
+		Encoding::Utf8 | Encoding::Artifact => format!("{prefix}{separator}{text}"),
+	}
+}
+
+/// Turns the chunks of live output into the data of frames. A character
+/// split between chunks waits for the chunk that completes it, so that text
+/// stays text; chunks that are not UTF-8 go out in base64.
+#[derive(Debug, Default)]
+pub struct ChunkEncoder {
+	stdout: Vec<u8>,
+	stderr: Vec<u8>,
+}
+
+impl ChunkEncoder {
+	/// `chunk`'s data and encoding; `None` while all there is to send is part
+	/// of a character.
+	pub fn encode(&mut self, chunk: &OutputChunk) -> Option<(String, Encoding)> {
+		let held = match chunk.stream {
+			StreamKind::Stdout => &mut self.stdout,
+			StreamKind::Stderr => &mut self.stderr,
+		};
+		let mut bytes = std::mem::take(held);
+		bytes.extend_from_slice(&chunk.data);
+		match std::str::from_utf8(&bytes) {
+			Ok(_) => {}
+			Err(e) if e.error_len().is_none() && bytes.len() - e.valid_up_to() <= MAX_CHAR_TAIL => {
+				*held = bytes.split_off(e.valid_up_to());
+			}
+			Err(_) => return Some((STANDARD.encode(&bytes), Encoding::Base64)),
+		}
+		if bytes.is_empty() {
+			return None;
+		}
+		Some((String::from_utf8(bytes).expect("checked above"), Encoding::Utf8))
+	}
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::tool_executor::ExecutionBackend;
+
+/// The sandbox's own version, which is the version of its built-ins.
+pub const SANDBOX_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What one execution ran and under which policy, returned with its result
+/// and kept with its history entry, so that a result can be traced back to
+/// what produced it and run again the same way. Like a plan, it names
+/// variables but never holds their values, and it leaves out host paths.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionEnvironment {
+	/// The definition that ran, pinned as `name@version`.
+	pub tool: String,
+	pub backend: ExecutionBackend,
+	pub sandbox_version: String,
+	/// What the backend ran, by role: the `sha256:` digest of a native
+	/// tool's `program`, a WASM `module` or a `component`; a container's
+	/// `image`, its `image_id` and the digest of its `script`; a VM's
+	/// `vm_class` and the digests of its `kernel` and `rootfs`. A digest
+	/// that could not be taken is left out.
+	pub runtime: BTreeMap<String, String>,
+	pub timeout_ms: u64,
+	/// Limits as the backend applied them, in the form a plan gives them.
+	pub limits: Value,
+	pub network: Value,
+	pub seccomp: bool,
+	pub landlock: bool,
+	/// Host variables the tool inherited, by name.
+	pub env: Vec<String>,
+	/// Variables set from secrets, by name.
+	pub secrets: Vec<String>,
+}
+
+/// Digests of the files tools run from. A file is hashed again only once its
+/// size or modification time changes, so a large VM image is read once.
+#[derive(Debug, Default)]
+pub struct FileDigests(Mutex<HashMap<PathBuf, (u64, SystemTime, String)>>);
+
+impl FileDigests {
+	/// `sha256:<hex>` of the file at `path`.
+	pub async fn digest(&self, path: &Path) -> io::Result<String> {
+		let meta = tokio::fs::metadata(path).await?;
+		let (len, modified) = (meta.len(), meta.modified()?);
+		if let Some((l, m, digest)) = self.0.lock().unwrap().get(path) {
+			if (*l, *m) == (len, modified) {
+				return Ok(digest.clone());
+			}
+		}
+		let owned = path.to_path_buf();
+		let digest = tokio::task::spawn_blocking(move || hash_file(&owned))
+			.await
+			.map_err(io::Error::other)??;
+		self.0
+			.lock()
+			.unwrap()
+			.insert(path.to_path_buf(), (len, modified, digest.clone()));
+		Ok(digest)
+	}
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+	let mut file = std::fs::File::open(path)?;
+	let mut hasher = Sha256::new();
+	io::copy(&mut file, &mut hasher)?;
+	let hex: String = hasher.finalize().iter().map(|b| format!("{b:02x}")).collect();
+	Ok(format!("sha256:{hex}"))
+}
+
+/// The file `program` runs from when it is a command's first word: itself
+/// when it is a path, otherwise the first match on the sandbox's `PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+	if program.contains('/') {
+		return Some(PathBuf::from(program));
+	}
+	let path = std::env::var_os("PATH")?;
+	std::env::split_paths(&path)
+		.map(|dir| dir.join(program))
+		.find(|candidate| candidate.is_file())
+}
@@ -0,0 +1,306 @@
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+use crate::registry::ArgError;
+use crate::request_id;
+
+/// `ErrorInfo.domain` of the sandbox's gRPC errors.
+const ERROR_DOMAIN: &str = "pagi-sandbox";
+
+/// A request the sandbox could not or would not serve, as every HTTP endpoint
+/// and gRPC method reports it.
+///
+/// Over HTTP it is the body `{code, message, details, request_id}` with the
+/// variant's status; over gRPC the variant's code, with `code` as the
+/// `ErrorInfo` reason and the request id as `RequestInfo`. A tool that ran and
+/// failed is not one: its outcome is a tool response.
+#[derive(Debug)]
+pub enum SandboxError {
+	/// The request is malformed: unparsable JSON, an unexpected part or field.
+	InvalidRequest(String),
+	/// Args (or other fields of a tool request) that fail validation, with
+	/// one entry per problem.
+	InvalidArgs { message: String, errors: Vec<ArgError> },
+	/// Well-formed, but not something that can be done: an invalid pipeline,
+	/// policy or schedule.
+	Invalid(String),
+	/// Valid, but not supported by the tool it names.
+	Unsupported(String),
+	Unauthorized(String),
+	Forbidden(String),
+	NotFound(String),
+	/// The feature the endpoint serves is turned off.
+	Disabled(String),
+	Conflict(String),
+	PayloadTooLarge(String),
+	/// The request accepts no format the endpoint can answer in.
+	NotAcceptable(String),
+	/// Turned away for now; `code` says by what (`rate_limited`,
+	/// `quota_exceeded`, `budget_exceeded`, `queue_full`, `session_limit` or
+	/// `draining`) and
+	/// `subject` whose limit it was, if it is someone's.
+	Limited {
+		code: &'static str,
+		message: String,
+		retry_after_secs: Option<u64>,
+		subject: Option<String>,
+	},
+	/// Another instance or service the request went to could not be reached.
+	BadGateway(String),
+	/// A backing store is down.
+	Unavailable(String),
+	Internal(String),
+	/// An error with more to say, as `details`.
+	Detailed(Box<SandboxError>, Value),
+}
+
+impl SandboxError {
+	pub fn invalid_args(tool_name: &str, errors: Vec<ArgError>) -> Self {
+		let message = format!(
+			"invalid {tool_name} request: {}",
+			errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>().join("; ")
+		);
+		Self::InvalidArgs { message, errors }
+	}
+
+	/// `self`, with `details` in the envelope.
+	pub fn with_details(self, details: Value) -> Self {
+		Self::Detailed(Box::new(self), details)
+	}
+
+	/// The machine-readable half of the envelope.
+	pub fn code(&self) -> &'static str {
+		match self {
+			Self::InvalidRequest(_) => "invalid_request",
+			Self::InvalidArgs { .. } => "invalid_args",
+			Self::Invalid(_) => "invalid",
+			Self::Unsupported(_) => "unsupported",
+			Self::Unauthorized(_) => "unauthorized",
+			Self::Forbidden(_) => "forbidden",
+			Self::NotFound(_) => "not_found",
+			Self::Disabled(_) => "disabled",
+			Self::Conflict(_) => "conflict",
+			Self::PayloadTooLarge(_) => "payload_too_large",
+			Self::NotAcceptable(_) => "not_acceptable",
+			Self::Limited { code, .. } => code,
+			Self::BadGateway(_) => "bad_gateway",
+			Self::Unavailable(_) => "unavailable",
+			Self::Internal(_) => "internal",
+			Self::Detailed(error, _) => error.code(),
+		}
+	}
+
+	pub fn message(&self) -> &str {
+		match self {
+			Self::InvalidRequest(m)
+			| Self::Invalid(m)
+			| Self::Unsupported(m)
+			| Self::Unauthorized(m)
+			| Self::Forbidden(m)
+			| Self::NotFound(m)
+			| Self::Disabled(m)
+			| Self::Conflict(m)
+			| Self::PayloadTooLarge(m)
+			| Self::NotAcceptable(m)
+			| Self::BadGateway(m)
+			| Self::Unavailable(m)
+			| Self::Internal(m) => m,
+			Self::InvalidArgs { message, .. } | Self::Limited { message, .. } => message,
+			Self::Detailed(error, _) => error.message(),
+		}
+	}
+
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+			Self::InvalidArgs { .. } | Self::Invalid(_) | Self::Unsupported(_) => StatusCode::UNPROCESSABLE_ENTITY,
+			Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+			Self::Forbidden(_) => StatusCode::FORBIDDEN,
+			Self::NotFound(_) | Self::Disabled(_) => StatusCode::NOT_FOUND,
+			Self::Conflict(_) => StatusCode::CONFLICT,
+			Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+			Self::NotAcceptable(_) => StatusCode::NOT_ACCEPTABLE,
+			Self::Limited { code: "draining", .. } => StatusCode::SERVICE_UNAVAILABLE,
+			Self::Limited { .. } => StatusCode::TOO_MANY_REQUESTS,
+			Self::BadGateway(_) => StatusCode::BAD_GATEWAY,
+			Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+			Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+			Self::Detailed(error, _) => error.status_code(),
+		}
+	}
+
+	fn grpc_code(&self) -> Code {
+		match self {
+			Self::InvalidRequest(_) | Self::InvalidArgs { .. } | Self::Invalid(_) | Self::NotAcceptable(_) => {
+				Code::InvalidArgument
+			}
+			Self::Unsupported(_) | Self::Conflict(_) => Code::FailedPrecondition,
+			Self::Unauthorized(_) => Code::Unauthenticated,
+			Self::Forbidden(_) => Code::PermissionDenied,
+			Self::NotFound(_) => Code::NotFound,
+			Self::Disabled(_) => Code::Unimplemented,
+			Self::PayloadTooLarge(_) => Code::OutOfRange,
+			Self::Limited { code: "draining", .. } => Code::Unavailable,
+			Self::Limited { .. } => Code::ResourceExhausted,
+			Self::BadGateway(_) | Self::Unavailable(_) => Code::Unavailable,
+			Self::Internal(_) => Code::Internal,
+			Self::Detailed(error, _) => error.grpc_code(),
+		}
+	}
+
+	fn details(&self) -> Value {
+		match self {
+			Self::InvalidArgs { errors, .. } => json!({ "errors": errors }),
+			Self::Limited {
+				retry_after_secs: Some(secs),
+				..
+			} => json!({ "retry_after_secs": secs }),
+			Self::Detailed(error, details) => match (error.details(), details) {
+				(Value::Object(mut inner), Value::Object(outer)) => {
+					inner.extend(outer.clone());
+					Value::Object(inner)
+				}
+				_ => details.clone(),
+			},
+			_ => Value::Null,
+		}
+	}
+
+	fn retry_after_secs(&self) -> Option<u64> {
+		match self {
+			Self::Limited { retry_after_secs, .. } => *retry_after_secs,
+			Self::Detailed(error, _) => error.retry_after_secs(),
+			_ => None,
+		}
+	}
+
+	/// The gRPC status for the request `request_id`.
+	fn into_status(self, request_id: Option<&str>) -> Status {
+		// The envelope's details go in `ErrorInfo.metadata`, which holds strings.
+		let metadata: HashMap<String, String> = match self.details() {
+			Value::Object(fields) => fields
+				.into_iter()
+				.map(|(key, value)| match value {
+					Value::String(s) => (key, s),
+					other => (key, other.to_string()),
+				})
+				.collect(),
+			_ => HashMap::new(),
+		};
+		let mut details = ErrorDetails::new();
+		details.set_error_info(self.code(), ERROR_DOMAIN, metadata);
+		if let Some(request_id) = request_id {
+			details.set_request_info(request_id, "");
+		}
+		let mut inner = &self;
+		while let Self::Detailed(error, _) = inner {
+			inner = error;
+		}
+		match inner {
+			Self::InvalidArgs { errors, .. } => {
+				for e in errors {
+					details.add_bad_request_violation(e.path.clone(), e.message.clone());
+				}
+			}
+			Self::Limited {
+				retry_after_secs,
+				subject,
+				message,
+				..
+			} => {
+				details.set_retry_info(retry_after_secs.map(Duration::from_secs));
+				if let Some(subject) = subject {
+					details.add_quota_failure_violation(subject.clone(), message.clone());
+				}
+			}
+			_ => {}
+		}
+		Status::with_error_details(self.grpc_code(), self.message(), details)
+	}
+}
+
+impl std::fmt::Display for SandboxError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: {}", self.code(), self.message())
+	}
+}
+
+impl std::error::Error for SandboxError {}
+
+impl IntoResponse for SandboxError {
+	fn into_response(self) -> Response {
+		let body = json!({
+			"code": self.code(),
+			"message": self.message(),
+			"details": self.details(),
+			"request_id": request_id::current(),
+		});
+		let mut response = (self.status_code(), Json(body)).into_response();
+		if let Some(secs) = self.retry_after_secs() {
+			response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+		}
+		response
+	}
+}
+
+impl From<SandboxError> for Status {
+	fn from(error: SandboxError) -> Self {
+		error.into_status(request_id::current().as_deref())
+	}
+}
+
+impl From<JsonRejection> for SandboxError {
+	fn from(rejection: JsonRejection) -> Self {
+		match rejection.status() {
+			StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge(rejection.body_text()),
+			_ => Self::InvalidRequest(rejection.body_text()),
+		}
+	}
+}
+
+impl From<QueryRejection> for SandboxError {
+	fn from(rejection: QueryRejection) -> Self {
+		Self::InvalidRequest(rejection.body_text())
+	}
+}
+
+/// An extractor whose rejection is a [`SandboxError`], as in
+/// `Api(Json(body)): Api<Json<Body>>`.
+pub struct Api<E>(pub E);
+
+#[axum::async_trait]
+impl<S, E> FromRequest<S> for Api<E>
+where
+	S: Send + Sync,
+	E: FromRequest<S>,
+	E::Rejection: Into<SandboxError>,
+{
+	type Rejection = SandboxError;
+
+	async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+		E::from_request(request, state).await.map(Self).map_err(Into::into)
+	}
+}
+
+#[axum::async_trait]
+impl<S, E> FromRequestParts<S> for Api<E>
+where
+	S: Send + Sync,
+	E: FromRequestParts<S>,
+	E::Rejection: Into<SandboxError>,
+{
+	type Rejection = SandboxError;
+
+	async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+		E::from_request_parts(parts, state).await.map(Self).map_err(Into::into)
+	}
+}
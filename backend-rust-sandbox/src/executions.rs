@@ -0,0 +1,175 @@
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::registry::ToolLimits;
+use crate::scheduler::Priority;
+use crate::tool_executor::ExecutionContext;
+
+/// Process groups an execution has spawned and not yet reaped, by leader
+/// PID. Clones share one list.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessList(Arc<Mutex<BTreeSet<u32>>>);
+
+impl ProcessList {
+	/// List `pid` until the returned guard is dropped.
+	pub fn add(&self, pid: u32) -> ListedProcess {
+		self.0.lock().unwrap().insert(pid);
+		ListedProcess {
+			list: self.clone(),
+			pid,
+		}
+	}
+
+	pub fn pids(&self) -> Vec<u32> {
+		self.0.lock().unwrap().iter().copied().collect()
+	}
+}
+
+/// Returned by [`ProcessList::add`].
+pub struct ListedProcess {
+	list: ProcessList,
+	pid: u32,
+}
+
+impl Drop for ListedProcess {
+	fn drop(&mut self) {
+		self.list.0.lock().unwrap().remove(&self.pid);
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionState {
+	/// Admitted and waiting for a slot.
+	Queued,
+	Running,
+}
+
+/// One execution as `GET /api/v1/admin/executions` lists it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionView {
+	/// The execution's workspace id; a session's is its session id.
+	pub execution_id: String,
+	pub tool_name: String,
+	pub backend: &'static str,
+	pub state: ExecutionState,
+	pub priority: Priority,
+	pub subject: Option<String>,
+	pub tenant: Option<String>,
+	pub transport: &'static str,
+	pub request_id: Option<String>,
+	pub job_id: Option<String>,
+	pub admitted_at_ms: u64,
+	pub started_at_ms: Option<u64>,
+	pub timeout_ms: u64,
+	pub max_output_bytes: usize,
+	/// The tool's manifest limits.
+	pub limits: ToolLimits,
+	/// Leader PIDs of the process groups it is running; empty for backends
+	/// that run in-process, such as WASM.
+	pub pids: Vec<u32>,
+}
+
+struct Entry {
+	view: ExecutionView,
+	processes: ProcessList,
+	cancel: CancellationToken,
+}
+
+/// The executions this instance is running or has queued, for the admin API.
+#[derive(Default)]
+pub struct Executions {
+	entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl Executions {
+	/// List the execution `ctx` describes until the returned guard is
+	/// dropped.
+	pub fn track(&self, tool_name: &str, backend: &'static str, limits: &ToolLimits, ctx: &ExecutionContext) -> Tracked {
+		let view = ExecutionView {
+			execution_id: ctx.workspace_id.clone(),
+			tool_name: tool_name.to_string(),
+			backend,
+			state: ExecutionState::Queued,
+			priority: ctx.priority,
+			subject: ctx.caller.subject.clone(),
+			tenant: ctx.caller.tenant.clone(),
+			transport: ctx.caller.transport,
+			request_id: ctx.caller.request_id.clone(),
+			job_id: ctx.job_id.clone(),
+			admitted_at_ms: ctx.created_at_ms,
+			started_at_ms: None,
+			timeout_ms: ctx.timeout.as_millis() as u64,
+			max_output_bytes: ctx.max_output_bytes,
+			limits: limits.clone(),
+			pids: Vec::new(),
+		};
+		let entry = Entry {
+			view,
+			processes: ctx.processes.clone(),
+			cancel: ctx.cancel.clone(),
+		};
+		self.entries.lock().unwrap().insert(ctx.workspace_id.clone(), entry);
+		Tracked {
+			entries: self.entries.clone(),
+			id: ctx.workspace_id.clone(),
+		}
+	}
+
+	/// Every execution, the longest-admitted first.
+	pub fn list(&self) -> Vec<ExecutionView> {
+		let mut views: Vec<_> = self
+			.entries
+			.lock()
+			.unwrap()
+			.values()
+			.map(|entry| ExecutionView {
+				pids: entry.processes.pids(),
+				..entry.view.clone()
+			})
+			.collect();
+		views.sort_by_key(|v| v.admitted_at_ms);
+		views
+	}
+
+	/// Cancel an execution, which then ends with status `cancelled`; `false`
+	/// if there is none by that id.
+	pub fn kill(&self, execution_id: &str) -> bool {
+		let entries = self.entries.lock().unwrap();
+		let Some(entry) = entries.get(execution_id) else {
+			return false;
+		};
+		info!(
+			execution_id = execution_id,
+			tool_name = entry.view.tool_name,
+			message = "Killing execution on an admin's request"
+		);
+		entry.cancel.cancel();
+		true
+	}
+}
+
+/// Returned by [`Executions::track`].
+pub struct Tracked {
+	entries: Arc<Mutex<HashMap<String, Entry>>>,
+	id: String,
+}
+
+impl Tracked {
+	/// The execution got its slot.
+	pub fn started(&self, started_at_ms: u64) {
+		if let Some(entry) = self.entries.lock().unwrap().get_mut(&self.id) {
+			entry.view.state = ExecutionState::Running;
+			entry.view.started_at_ms = Some(started_at_ms);
+		}
+	}
+}
+
+impl Drop for Tracked {
+	fn drop(&mut self) {
+		self.entries.lock().unwrap().remove(&self.id);
+	}
+}
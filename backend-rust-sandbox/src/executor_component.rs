@@ -0,0 +1,290 @@
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, info_span, warn, Instrument};
+use wasmtime::component::{Component, HasSelf, Linker, ResourceTable};
+use wasmtime::{Store, StoreLimits, StoreLimitsBuilder, Trap};
+use wasmtime_wasi::{FsPerms, I32Exit, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+
+use crate::encoding::{self, OutputEncoding};
+use crate::executor_wasm::{bound_store, trap_status, wasi_builder, CappedPipe, WasmRuntime};
+use crate::registry::ToolManifest;
+use crate::secrets::ToolEnv;
+use crate::signing::ToolVerifier;
+use crate::subprocess::{OutputChunk, OutputSink, StreamKind, Truncation};
+use crate::tool_executor::{ExecutionContext, ToolResult};
+
+wasmtime::component::bindgen!({
+	path: "wit/tool.wit",
+	world: "tool-component",
+	imports: { default: async },
+	exports: { default: async },
+});
+
+use exports::pagi::tool::tool::Outcome;
+
+/// How long a component may take to describe its tools when loaded.
+const DESCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Core and component instances one component may create; each of its
+/// modules counts.
+const MAX_INSTANCES: usize = 64;
+/// Output kept of a `describe` call, which should print nothing.
+const DESCRIBE_OUTPUT_BYTES: usize = 64 * 1024;
+
+struct ComponentState {
+	wasi: WasiCtx,
+	table: ResourceTable,
+	limits: StoreLimits,
+	/// Set by the epoch callback when it interrupts a cancelled call.
+	cancelled: bool,
+	stdout: CappedPipe,
+	output: Option<OutputSink>,
+}
+
+impl WasiView for ComponentState {
+	fn ctx(&mut self) -> WasiCtxView<'_> {
+		WasiCtxView {
+			ctx: &mut self.wasi,
+			table: &mut self.table,
+		}
+	}
+}
+
+impl pagi::tool::output::Host for ComponentState {
+	async fn write(&mut self, bytes: Vec<u8>) {
+		self.stdout.push(&bytes);
+		if let Some(tx) = &self.output {
+			let chunk = OutputChunk {
+				stream: StreamKind::Stdout,
+				data: bytes,
+			};
+			if tx.send(chunk).await.is_err() {
+				self.output = None;
+			}
+		}
+	}
+}
+
+/// Runs tools implemented as WebAssembly components of the `pagi:tool`
+/// world (`wit/tool.wit`), on the engine WASM modules run on.
+pub struct ComponentRuntime {
+	wasm: Arc<WasmRuntime>,
+	linker: Linker<ComponentState>,
+}
+
+impl ComponentRuntime {
+	pub fn new(wasm: Arc<WasmRuntime>) -> wasmtime::Result<Self> {
+		let mut linker = Linker::new(wasm.engine());
+		wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
+		ToolComponent::add_to_linker::<_, HasSelf<ComponentState>>(&mut linker, |s| s)?;
+		Ok(Self { wasm, linker })
+	}
+
+	/// Compile every `*.wasm` component in `dir` and ask each for its tools.
+	/// A component whose signature `verifier` rejects, that fails to compile,
+	/// link or describe itself, or that declares a malformed tool, is an
+	/// error; the rest are still checked so
+	/// every problem is reported at once.
+	///
+	/// Blocks the calling thread, which must be on the multi-threaded
+	/// runtime, until they are all loaded.
+	pub fn load(&self, dir: &Path, verifier: &ToolVerifier) -> Result<Vec<ToolManifest>, Vec<String>> {
+		let handle = tokio::runtime::Handle::current();
+		tokio::task::block_in_place(|| handle.block_on(self.load_dir(dir, verifier)))
+	}
+
+	async fn load_dir(&self, dir: &Path, verifier: &ToolVerifier) -> Result<Vec<ToolManifest>, Vec<String>> {
+		let entries = match std::fs::read_dir(dir) {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(e) => return Err(vec![format!("{}: {e}", dir.display())]),
+		};
+		let mut paths: Vec<PathBuf> = entries
+			.filter_map(|e| e.ok().map(|e| e.path()))
+			.filter(|path| path.extension().and_then(|e| e.to_str()) == Some("wasm"))
+			.collect();
+		paths.sort();
+
+		let mut tools = Vec::new();
+		let mut errors = Vec::new();
+		for path in paths {
+			match self.describe(&path, verifier).await {
+				Ok(described) => {
+					info!(path = %path.display(), tools = described.len(), message = "Loaded tool component");
+					tools.extend(described);
+				}
+				Err(e) => errors.push(format!("{}: {e}", path.display())),
+			}
+		}
+		if errors.is_empty() {
+			Ok(tools)
+		} else {
+			Err(errors)
+		}
+	}
+
+	/// The tools the component at `path` declares, each carrying the
+	/// compiled component.
+	async fn describe(&self, path: &Path, verifier: &ToolVerifier) -> Result<Vec<ToolManifest>, String> {
+		let binary = std::fs::read(path).map_err(|e| e.to_string())?;
+		verifier.verify(path, &binary)?;
+		let component = Component::from_binary(self.wasm.engine(), &binary).map_err(|e| format!("{e:#}"))?;
+		let pipe = CappedPipe::new(DESCRIBE_OUTPUT_BYTES);
+		let mut wasi = WasiCtxBuilder::new();
+		wasi.stdout(pipe.clone()).stderr(pipe.clone());
+		let mut store = self.store(wasi.build(), pipe, None, self.wasm.default_max_memory_mb());
+		bound_store(
+			&mut store,
+			self.wasm.default_fuel(),
+			DESCRIBE_TIMEOUT,
+			CancellationToken::new(),
+			|s| s.cancelled = true,
+		)
+		.map_err(|e| format!("{e:#}"))?;
+		let infos = async {
+			let instance = ToolComponent::instantiate_async(&mut store, &component, &self.linker).await?;
+			instance.pagi_tool_tool().call_describe(&mut store).await
+		}
+		.await
+		.map_err(|e| format!("describe failed: {e:#}"))?;
+
+		let mut tools: Vec<ToolManifest> = Vec::with_capacity(infos.len());
+		for info in infos {
+			let schema = match info.args_schema.trim() {
+				"" => None,
+				text => Some(
+					serde_json::from_str::<Value>(text)
+						.map_err(|e| format!("tool {:?}: args-schema: {e}", info.name))?,
+				),
+			};
+			let manifest = ToolManifest::component(&info.name, &info.description, schema, path, component.clone())?;
+			if tools.iter().any(|t| t.name == manifest.name) {
+				return Err(format!("tool {:?} is declared twice", manifest.name));
+			}
+			tools.push(manifest);
+		}
+		Ok(tools)
+	}
+
+	fn store(
+		&self,
+		wasi: WasiCtx,
+		stdout: CappedPipe,
+		output: Option<OutputSink>,
+		max_memory_mb: usize,
+	) -> Store<ComponentState> {
+		let limits = StoreLimitsBuilder::new()
+			.memory_size(max_memory_mb * 1024 * 1024)
+			.instances(MAX_INSTANCES)
+			.build();
+		let mut store = Store::new(
+			self.wasm.engine(),
+			ComponentState {
+				wasi,
+				table: ResourceTable::new(),
+				limits,
+				cancelled: false,
+				stdout,
+				output,
+			},
+		);
+		store.limiter(|s| &mut s.limits);
+		store
+	}
+
+	/// Run `tool` in a fresh instance of its component.
+	///
+	/// The component sees the WASI environment of a WASM module tool:
+	/// `PAGI_TOOL_NAME` and `env`, the request's stdin, and `work_dir` as
+	/// `/work`, with no network. What it writes, through `output` or to
+	/// stdout and stderr, streams to the caller as it is written.
+	pub async fn run(
+		&self,
+		tool: &ToolManifest,
+		args: &Value,
+		work_dir: &Path,
+		env: &ToolEnv,
+		ctx: &ExecutionContext,
+	) -> ToolResult {
+		let Some(component) = &tool.component else {
+			return failure("wasm_error", format!("{} has no component", tool.name));
+		};
+		let spawn_span = info_span!("sandbox.spawn", backend = "component", component = %tool.command[0]);
+		match self.try_run(tool, component, args, work_dir, env, ctx).instrument(spawn_span).await {
+			Ok(result) => result,
+			Err(e) => failure("wasm_error", format!("{e:#}")),
+		}
+	}
+
+	async fn try_run(
+		&self,
+		tool: &ToolManifest,
+		component: &Component,
+		args: &Value,
+		work_dir: &Path,
+		env: &ToolEnv,
+		ctx: &ExecutionContext,
+	) -> wasmtime::Result<ToolResult> {
+		let stdout = CappedPipe::streaming(ctx.max_output_bytes, StreamKind::Stdout, ctx.output.clone());
+		let stderr = CappedPipe::streaming(ctx.max_output_bytes, StreamKind::Stderr, ctx.output.clone());
+		let mut wasi = wasi_builder(&tool.name, std::slice::from_ref(&tool.name), env, ctx, &stdout, &stderr);
+		wasi.preopened_dir(work_dir, "/work", FsPerms::ReadWrite)?;
+		let mut store = self.store(wasi.build(), stdout.clone(), ctx.output.clone(), self.wasm.max_memory_mb(tool));
+		bound_store(&mut store, self.wasm.fuel(tool), ctx.timeout, ctx.cancel.clone(), |s| s.cancelled = true)?;
+
+		let outcome = async {
+			let instance = ToolComponent::instantiate_async(&mut store, component, &self.linker).await?;
+			instance
+				.pagi_tool_tool()
+				.call_run(&mut store, &tool.name, &args.to_string())
+				.await
+		}
+		.await;
+
+		let (mut stderr_text, stderr_encoding) = stderr.contents();
+		let (status, exit_code) = match outcome {
+			Ok(Ok(Outcome { status, exit_code })) => (status, exit_code),
+			Ok(Err(message)) => {
+				encoding::push_note(&mut stderr_text, stderr_encoding, &message);
+				("tool_error".to_string(), None)
+			}
+			Err(e) => {
+				if let Some(exit) = e.downcast_ref::<I32Exit>() {
+					let status = if exit.0 == 0 { "ok" } else { "non_zero_exit" };
+					(status.to_string(), Some(exit.0))
+				} else if let Some(trap) = e.downcast_ref::<Trap>() {
+					warn!(tool_name = %tool.name, trap = %trap, message = "WASM component trapped");
+					(trap_status(trap, store.data().cancelled).to_string(), None)
+				} else {
+					return Err(e);
+				}
+			}
+		};
+		let (stdout_bytes, stderr_bytes) = (stdout.total(), stderr.total());
+		let limit = ctx.max_output_bytes as u64;
+		let truncated = (stdout_bytes > limit || stderr_bytes > limit).then_some(Truncation {
+			stdout_bytes,
+			stderr_bytes,
+		});
+		let (stdout_text, stdout_encoding) = stdout.contents();
+		Ok(ToolResult {
+			status,
+			stdout: stdout_text,
+			stderr: stderr_text,
+			encoding: OutputEncoding::of(stdout_encoding, stderr_encoding),
+			exit_code,
+			truncated,
+			..Default::default()
+		})
+	}
+}
+
+fn failure(status: &str, message: String) -> ToolResult {
+	ToolResult {
+		status: status.to_string(),
+		stderr: message,
+		..Default::default()
+	}
+}
@@ -0,0 +1,552 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::egress::NetworkPolicy;
+use crate::registry::ToolManifest;
+use crate::secrets::ToolEnv;
+use crate::subprocess::{self, CommandSpec, ProcessOutput};
+use crate::tool_executor::{ExecutionContext, ToolResult};
+use crate::warm_pool::{Launcher, WarmPool, WarmPoolConfig};
+use crate::workspace::{copy_tree, empty_dir};
+
+const DEFAULT_RUNTIME: &str = "docker";
+const DEFAULT_IMAGE: &str = "debian:bookworm-slim";
+const DEFAULT_CPUS: &str = "1.0";
+const DEFAULT_MEMORY_MB: u64 = 512;
+const DEFAULT_PIDS_LIMIT: u64 = 128;
+const DEFAULT_NETWORK: &str = "none";
+const DEFAULT_WARM_DIR: &str = "sandbox_warm";
+
+/// Mount points inside the container.
+const TOOL_MOUNT: &str = "/opt/pagi/tool";
+const WORK_MOUNT: &str = "/work";
+
+/// Exit codes the docker CLI uses for its own failures (not the tool's).
+const EXIT_DAEMON_ERROR: i32 = 125;
+const EXIT_CANNOT_INVOKE: i32 = 126;
+const EXIT_COMMAND_NOT_FOUND: i32 = 127;
+const EXIT_SIGKILL: i32 = 137;
+
+const RUNTIME_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+/// Starting a warm container may have to pull its image first.
+const WARM_START_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContainerConfig {
+	/// CLI used to launch containers (`docker` or a compatible one like `podman`).
+	pub runtime: String,
+	/// Image for tools whose manifest does not name one.
+	pub default_image: String,
+	pub cpus: String,
+	pub memory_mb: u64,
+	pub pids_limit: u64,
+	/// Value for `--network`; `none` keeps containers offline.
+	pub network: String,
+	/// Containers kept started for each image and limits of the registered
+	/// tools, so that executions skip the start; 0 starts one per execution.
+	/// Tools with a `script`, or that run the image's entrypoint, always get
+	/// a fresh one.
+	pub pool_size: usize,
+	/// Host directories warm containers mount at `/work`, one each.
+	pub warm_dir: PathBuf,
+}
+
+impl Default for ContainerConfig {
+	fn default() -> Self {
+		Self {
+			runtime: DEFAULT_RUNTIME.to_string(),
+			default_image: DEFAULT_IMAGE.to_string(),
+			cpus: DEFAULT_CPUS.to_string(),
+			memory_mb: DEFAULT_MEMORY_MB,
+			pids_limit: DEFAULT_PIDS_LIMIT,
+			network: DEFAULT_NETWORK.to_string(),
+			pool_size: 0,
+			warm_dir: DEFAULT_WARM_DIR.into(),
+		}
+	}
+}
+
+/// The `docker run` settings of one tool's containers.
+#[derive(Debug, Serialize)]
+pub struct ContainerLimits<'a> {
+	pub image: &'a str,
+	pub cpus: String,
+	pub memory_mb: u64,
+	pub pids_limit: u64,
+	pub network: &'a str,
+}
+
+impl ContainerLimits<'_> {
+	fn setup(&self) -> Setup {
+		Setup {
+			image: self.image.to_string(),
+			cpus: self.cpus.clone(),
+			memory_mb: self.memory_mb,
+			pids_limit: self.pids_limit,
+			network: self.network.to_string(),
+		}
+	}
+}
+
+/// Runs each tool invocation in an ephemeral, locked-down container, started
+/// for it or taken from the warm pool.
+pub struct ContainerExecutor {
+	config: ContainerConfig,
+	pool: Arc<WarmPool<Starter>>,
+}
+
+impl ContainerExecutor {
+	pub fn new(config: ContainerConfig, warm: WarmPoolConfig) -> Self {
+		let starter = Starter { config: config.clone() };
+		Self {
+			config,
+			pool: WarmPool::new("container", starter, warm),
+		}
+	}
+
+	/// Whether `tool` runs in a warm container. Tools with GPUs do not: a
+	/// container gets its devices when it starts.
+	fn pooled(&self, tool: &ToolManifest) -> bool {
+		self.config.pool_size > 0 && tool.script.is_none() && !tool.command.is_empty() && tool.limits.gpu.is_none()
+	}
+
+	/// Start the warm containers `tools` run in.
+	pub fn prewarm<'a>(&self, tools: impl IntoIterator<Item = &'a ToolManifest>) {
+		for tool in tools.into_iter().filter(|tool| self.pooled(tool)) {
+			self.pool.refill(&self.limits(tool).setup());
+		}
+	}
+
+	/// How `tool`'s containers are started, after the manifest and the
+	/// configured defaults.
+	pub fn limits<'a>(&'a self, tool: &'a ToolManifest) -> ContainerLimits<'a> {
+		ContainerLimits {
+			image: tool.image.as_deref().unwrap_or(&self.config.default_image),
+			cpus: tool
+				.limits
+				.cpus
+				.map(|c| c.to_string())
+				.unwrap_or_else(|| self.config.cpus.clone()),
+			memory_mb: tool.limits.memory_mb.unwrap_or(self.config.memory_mb),
+			pids_limit: tool.limits.pids_limit.unwrap_or(self.config.pids_limit),
+			// Allowlists are rejected for containers when the manifest loads.
+			network: match &tool.network {
+				Some(NetworkPolicy::None) => "none",
+				Some(NetworkPolicy::Full) if self.config.network == "none" => "bridge",
+				_ => &self.config.network,
+			},
+		}
+	}
+
+	/// Run `tool` in a fresh container.
+	///
+	/// When the manifest has a `script` it is bind-mounted read-only and used
+	/// as the entrypoint; otherwise the image's own entrypoint runs the
+	/// manifest's `command`. Either way the JSON args come last. `work_dir` is mounted read-write at `/work` and is the only
+	/// writable location besides a small `/tmp` tmpfs. `env` is passed in by
+	/// name through the client's environment, so secret values never appear
+	/// on its command line. On timeout the CLI client is killed and the
+	/// container force-removed.
+	///
+	/// Tools that run in warm containers go through [`run_warm`](Self::run_warm)
+	/// instead.
+	pub async fn run(
+		&self,
+		tool: &ToolManifest,
+		args: &Value,
+		work_dir: &Path,
+		env: &ToolEnv,
+		ctx: &ExecutionContext,
+	) -> ToolResult {
+		let work_dir = match work_dir.canonicalize() {
+			Ok(p) => p,
+			Err(e) => {
+				return ToolResult {
+					status: "io_error".to_string(),
+					stderr: format!("failed to resolve run dir: {e}"),
+					..Default::default()
+				}
+			}
+		};
+		if self.pooled(tool) {
+			return self.run_warm(tool, args, &work_dir, env, ctx).await;
+		}
+		let tool_name = tool.name.as_str();
+		let ContainerLimits {
+			image,
+			cpus,
+			memory_mb,
+			pids_limit,
+			network,
+		} = self.limits(tool);
+		let container_name = format!(
+			"pagi-{tool_name}-{}",
+			work_dir
+				.file_name()
+				.map(|n| n.to_string_lossy().to_string())
+				.unwrap_or_default()
+		);
+
+		let spec = CommandSpec::new(&self.config.runtime)
+			.arg("run")
+			.arg("--rm")
+			.arg("--name")
+			.arg(&container_name);
+		let mut spec = confined(spec, &cpus, memory_mb, pids_limit, network)
+			.arg("--env")
+			.arg(format!("PAGI_TOOL_NAME={tool_name}"))
+			.arg("--volume")
+			.arg(format!("{}:{WORK_MOUNT}:rw", work_dir.display()))
+			.arg("--workdir")
+			.arg(WORK_MOUNT);
+		for (key, value) in &env.vars {
+			spec = spec.arg("--env").arg(key).env(key, value);
+		}
+		for (key, secret) in &env.secrets {
+			spec = spec.arg("--env").arg(key).secret(key, secret.clone());
+		}
+		if !env.gpus.is_empty() {
+			spec = spec.arg("--gpus").arg(format!("\"device={}\"", env.gpus.join(",")));
+		}
+
+		let stdin = ctx.stdin();
+		let terminal = ctx.terminal(tool);
+		if terminal.is_some() {
+			spec = spec.arg("--tty");
+		}
+		if stdin.is_some() || terminal.is_some() {
+			spec = spec.arg("--interactive");
+		}
+
+		if let Some(script) = &tool.script {
+			let script = match script.canonicalize() {
+				Ok(p) => p,
+				Err(e) => {
+					return ToolResult {
+						status: "io_error".to_string(),
+						stderr: format!("failed to resolve tool path: {e}"),
+						..Default::default()
+					}
+				}
+			};
+			spec = spec
+				.arg("--volume")
+				.arg(format!("{}:{TOOL_MOUNT}:ro", script.display()))
+				.arg("--entrypoint")
+				.arg(TOOL_MOUNT);
+		}
+		let mut spec = spec.arg(image);
+		for arg in &tool.command {
+			spec = spec.arg(arg);
+		}
+		let spec = spec
+			.arg(args.to_string())
+			.stdin(stdin)
+			.terminal(terminal)
+			.timeout(ctx.timeout)
+			.output(ctx.output.clone())
+			.max_output(ctx.max_output_bytes)
+			.cancel(ctx.cancel.clone())
+			.processes(ctx.processes.clone());
+
+		let result = self.result(subprocess::run(&spec).await);
+		if !ended_cleanly(&result) {
+			force_remove(&self.config.runtime, &container_name).await;
+		}
+		result
+	}
+
+	/// Run `tool` with `docker exec` in a warm container of its setup.
+	///
+	/// Warm containers are started with the same limits and hardening as
+	/// fresh ones, but idle on `sleep` with a directory of their own mounted
+	/// at `/work`: the workspace is copied in before the tool runs and back
+	/// out after it. Symlinks are not copied either way.
+	async fn run_warm(
+		&self,
+		tool: &ToolManifest,
+		args: &Value,
+		work_dir: &Path,
+		env: &ToolEnv,
+		ctx: &ExecutionContext,
+	) -> ToolResult {
+		let mut lease = match self.pool.take(&self.limits(tool).setup()).await {
+			Ok(lease) => lease,
+			Err(e) => return failure("container_error", format!("failed to start a container: {e}")),
+		};
+		let container = lease.instance().clone();
+		let (from, to) = (work_dir.to_path_buf(), container.dir.clone());
+		if let Err(e) = blocking(move || copy_tree(&from, &to)).await {
+			return failure("io_error", format!("failed to copy the workspace into the container: {e}"));
+		}
+
+		let mut spec = CommandSpec::new(&self.config.runtime)
+			.arg("exec")
+			.arg("--workdir")
+			.arg(WORK_MOUNT)
+			.arg("--env")
+			.arg(format!("PAGI_TOOL_NAME={}", tool.name));
+		for (key, value) in &env.vars {
+			spec = spec.arg("--env").arg(key).env(key, value);
+		}
+		for (key, secret) in &env.secrets {
+			spec = spec.arg("--env").arg(key).secret(key, secret.clone());
+		}
+		let stdin = ctx.stdin();
+		let terminal = ctx.terminal(tool);
+		if terminal.is_some() {
+			spec = spec.arg("--tty");
+		}
+		if stdin.is_some() || terminal.is_some() {
+			spec = spec.arg("--interactive");
+		}
+		let mut spec = spec.arg(&container.name);
+		for arg in &tool.command {
+			spec = spec.arg(arg);
+		}
+		let spec = spec
+			.arg(args.to_string())
+			.stdin(stdin)
+			.terminal(terminal)
+			.timeout(ctx.timeout)
+			.output(ctx.output.clone())
+			.max_output(ctx.max_output_bytes)
+			.cancel(ctx.cancel.clone())
+			.processes(ctx.processes.clone());
+		let mut result = self.result(subprocess::run(&spec).await);
+
+		let (from, to) = (container.dir.clone(), work_dir.to_path_buf());
+		let copied = blocking(move || empty_dir(&to).and_then(|()| copy_tree(&from, &to))).await;
+		if let Err(e) = &copied {
+			result.status = "io_error".to_string();
+			result.note(&format!("failed to copy the workspace out of the container: {e}\n"));
+		}
+		lease.release(copied.is_ok() && ended_cleanly(&result));
+		result
+	}
+
+	/// The result of a `docker run` or `docker exec` client.
+	fn result(&self, out: std::io::Result<ProcessOutput>) -> ToolResult {
+		match out {
+			Ok(out) => {
+				let status = match out.exit_code {
+					Some(EXIT_DAEMON_ERROR) => "container_error",
+					Some(EXIT_CANNOT_INVOKE) => "permission_denied",
+					Some(EXIT_COMMAND_NOT_FOUND) => "not_found",
+					Some(EXIT_SIGKILL) => "killed",
+					_ => out.status(),
+				};
+				ToolResult {
+					status: status.to_string(),
+					stdout: out.stdout,
+					stderr: out.stderr,
+					encoding: out.encoding,
+					exit_code: out.exit_code,
+					truncated: out.truncated,
+					..Default::default()
+				}
+			}
+			Err(e) => ToolResult {
+				status: subprocess::spawn_error_status(&e).to_string(),
+				stderr: format!("failed to spawn {}: {e}", self.config.runtime),
+				..Default::default()
+			},
+		}
+	}
+
+	/// Ask the runtime's daemon for its version, for readiness probes.
+	pub async fn check_runtime(&self) -> Result<String, String> {
+		let spec = CommandSpec::new(&self.config.runtime)
+			.arg("version")
+			.arg("--format")
+			.arg("{{.Server.Version}}")
+			.timeout(RUNTIME_CHECK_TIMEOUT);
+		match subprocess::run(&spec).await {
+			Ok(out) if out.success() => Ok(format!("{} {}", self.config.runtime, out.stdout.trim())),
+			Ok(out) if out.timed_out => Err(format!("{} did not answer in time", self.config.runtime)),
+			Ok(out) => Err(out.stderr.trim().to_string()),
+			Err(e) => Err(format!("failed to spawn {}: {e}", self.config.runtime)),
+		}
+	}
+
+	/// The id of the local image `image` names, which is what a container
+	/// started from it now runs.
+	pub async fn image_id(&self, image: &str) -> Result<String, String> {
+		let spec = CommandSpec::new(&self.config.runtime)
+			.arg("image")
+			.arg("inspect")
+			.arg("--format")
+			.arg("{{.Id}}")
+			.arg(image)
+			.timeout(RUNTIME_CHECK_TIMEOUT);
+		match subprocess::run(&spec).await {
+			Ok(out) if out.success() => Ok(out.stdout.trim().to_string()),
+			Ok(out) => Err(out.stderr.trim().to_string()),
+			Err(e) => Err(format!("failed to spawn {}: {e}", self.config.runtime)),
+		}
+	}
+}
+
+/// Whether the tool exited on its own, leaving nothing running in the
+/// container.
+fn ended_cleanly(result: &ToolResult) -> bool {
+	result.status == "ok" || result.status == "non_zero_exit"
+}
+
+fn failure(status: &str, stderr: String) -> ToolResult {
+	ToolResult {
+		status: status.to_string(),
+		stderr,
+		..Default::default()
+	}
+}
+
+async fn blocking(work: impl FnOnce() -> std::io::Result<()> + Send + 'static) -> std::io::Result<()> {
+	tokio::task::spawn_blocking(work).await.map_err(std::io::Error::other)?
+}
+
+/// `spec` with the limits and hardening every tool container gets.
+fn confined(spec: CommandSpec, cpus: &str, memory_mb: u64, pids_limit: u64, network: &str) -> CommandSpec {
+	spec.arg("--cpus")
+		.arg(cpus)
+		.arg("--memory")
+		.arg(format!("{memory_mb}m"))
+		.arg("--pids-limit")
+		.arg(pids_limit.to_string())
+		.arg("--network")
+		.arg(network)
+		.arg("--read-only")
+		.arg("--tmpfs")
+		.arg("/tmp:rw,size=64m")
+		.arg("--cap-drop")
+		.arg("ALL")
+		.arg("--security-opt")
+		.arg("no-new-privileges")
+}
+
+/// Best-effort removal of a container that `--rm` may not have reaped.
+async fn force_remove(runtime: &str, container_name: &str) {
+	let spec = CommandSpec::new(runtime).arg("rm").arg("--force").arg(container_name);
+	if let Err(e) = subprocess::run(&spec).await {
+		warn!(container = container_name, error = %e, message = "Failed to remove container");
+	}
+}
+
+/// The image and limits a warm container is started with; tools only get
+/// warm containers started for their own.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Setup {
+	image: String,
+	cpus: String,
+	memory_mb: u64,
+	pids_limit: u64,
+	network: String,
+}
+
+impl fmt::Display for Setup {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.image)
+	}
+}
+
+/// A started container idling until an execution `docker exec`s into it.
+#[derive(Debug, Clone)]
+struct WarmContainer {
+	name: String,
+	/// Mounted at `/work`.
+	dir: PathBuf,
+}
+
+/// Starts the containers of the warm pool.
+struct Starter {
+	config: ContainerConfig,
+}
+
+#[tonic::async_trait]
+impl Launcher for Starter {
+	type Class = Setup;
+	type Instance = WarmContainer;
+
+	fn pool_size(&self, _: &Setup) -> usize {
+		self.config.pool_size
+	}
+
+	async fn launch(&self, setup: &Setup) -> Result<WarmContainer, String> {
+		let id = uuid::Uuid::new_v4().to_string();
+		let dir = self.config.warm_dir.join(&id);
+		tokio::fs::create_dir_all(&dir)
+			.await
+			.map_err(|e| format!("{}: {e}", dir.display()))?;
+		let dir = dir.canonicalize().map_err(|e| format!("{}: {e}", dir.display()))?;
+		let container = WarmContainer {
+			name: format!("pagi-warm-{id}"),
+			dir,
+		};
+		let spec = CommandSpec::new(&self.config.runtime)
+			.arg("run")
+			.arg("--detach")
+			.arg("--name")
+			.arg(&container.name);
+		let spec = confined(spec, &setup.cpus, setup.memory_mb, setup.pids_limit, &setup.network)
+			.arg("--volume")
+			.arg(format!("{}:{WORK_MOUNT}:rw", container.dir.display()))
+			.arg("--workdir")
+			.arg(WORK_MOUNT)
+			.arg("--entrypoint")
+			.arg("sleep")
+			.arg(&setup.image)
+			.arg("infinity")
+			.timeout(WARM_START_TIMEOUT);
+		let started = match subprocess::run(&spec).await {
+			Ok(out) if out.success() => Ok(()),
+			Ok(out) => Err(out.stderr.trim().to_string()),
+			Err(e) => Err(format!("failed to spawn {}: {e}", self.config.runtime)),
+		};
+		match started {
+			Ok(()) => Ok(container),
+			Err(e) => {
+				self.destroy(container).await;
+				Err(e)
+			}
+		}
+	}
+
+	/// A dead container shows when an execution fails to `exec` into it.
+	fn alive(&self, _: &mut WarmContainer) -> bool {
+		true
+	}
+
+	/// Empty `/work`, and restart the container: that kills whatever the
+	/// tool left running and remounts `/tmp`. The rest of its filesystem is
+	/// read-only.
+	async fn reset(&self, container: &mut WarmContainer) -> Result<(), String> {
+		let dir = container.dir.clone();
+		blocking(move || empty_dir(&dir))
+			.await
+			.map_err(|e| format!("{}: {e}", container.dir.display()))?;
+		let spec = CommandSpec::new(&self.config.runtime)
+			.arg("restart")
+			.arg("--time")
+			.arg("0")
+			.arg(&container.name)
+			.timeout(WARM_START_TIMEOUT);
+		match subprocess::run(&spec).await {
+			Ok(out) if out.success() => Ok(()),
+			Ok(out) => Err(out.stderr.trim().to_string()),
+			Err(e) => Err(format!("failed to spawn {}: {e}", self.config.runtime)),
+		}
+	}
+
+	async fn destroy(&self, container: WarmContainer) {
+		force_remove(&self.config.runtime, &container.name).await;
+		if let Err(e) = tokio::fs::remove_dir_all(&container.dir).await {
+			warn!(dir = %container.dir.display(), error = %e, message = "Failed to remove warm container directory");
+		}
+	}
+}
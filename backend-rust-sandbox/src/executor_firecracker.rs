@@ -0,0 +1,673 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::UnixStream;
+use tokio::process::{Child, Command};
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::encoding::{self, OutputEncoding};
+use crate::registry::ToolManifest;
+use crate::secrets::ToolEnv;
+use crate::subprocess::{self, CommandSpec, OutputChunk, StdinSource, StreamKind, Truncation};
+use crate::tool::Base64;
+use crate::tool_executor::{ExecutionContext, ToolResult};
+use crate::vm_protocol::{GuestFrame, HostFrame, DEFAULT_GUEST_PORT};
+use crate::warm_pool::{Launcher, WarmPool, WarmPoolConfig};
+use crate::workspace::is_valid_input_path;
+
+const DEFAULT_BINARY: &str = "firecracker";
+const DEFAULT_STATE_DIR: &str = "sandbox_vms";
+const DEFAULT_BOOT_ARGS: &str = "console=ttyS0 reboot=k panic=1 pci=off quiet init=/sbin/pagi-vm-agent";
+const DEFAULT_BOOT_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_MAX_WORKSPACE_BYTES: u64 = 64 * 1024 * 1024;
+const DEFAULT_VCPUS: u32 = 1;
+const DEFAULT_MEMORY_MB: u64 = 256;
+/// Firecracker's own limit.
+const MAX_VCPUS: u32 = 32;
+
+/// The class of tools whose manifest names none.
+pub const DEFAULT_CLASS: &str = "default";
+
+/// Every VM has a vsock device of its own, so they can all use one CID.
+const GUEST_CID: u32 = 3;
+/// How often a booting guest's agent is knocked on.
+const CONNECT_INTERVAL: Duration = Duration::from_millis(50);
+/// Bytes of a VM's serial console quoted when it fails.
+const CONSOLE_TAIL_BYTES: u64 = 2048;
+const VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FirecrackerConfig {
+	/// The `firecracker` binary.
+	pub binary: String,
+	/// Holds each VM's config, vsock socket and console log while it runs.
+	pub state_dir: PathBuf,
+	/// Kernel command line for every class; it must start `pagi-vm-agent`
+	/// as init.
+	pub boot_args: String,
+	/// How long a VM has to boot and have its agent answer.
+	pub boot_timeout_ms: u64,
+	/// vsock port the agent listens on.
+	pub guest_port: u32,
+	/// Cap on the workspace sent into a VM and on the one it sends back.
+	pub max_workspace_bytes: u64,
+	/// Kernel and root filesystem images by class, which a manifest picks
+	/// with `vm_class`.
+	pub classes: BTreeMap<String, VmClass>,
+}
+
+impl Default for FirecrackerConfig {
+	fn default() -> Self {
+		Self {
+			binary: DEFAULT_BINARY.to_string(),
+			state_dir: DEFAULT_STATE_DIR.into(),
+			boot_args: DEFAULT_BOOT_ARGS.to_string(),
+			boot_timeout_ms: DEFAULT_BOOT_TIMEOUT_MS,
+			guest_port: DEFAULT_GUEST_PORT,
+			max_workspace_bytes: DEFAULT_MAX_WORKSPACE_BYTES,
+			classes: BTreeMap::new(),
+		}
+	}
+}
+
+impl FirecrackerConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if self.binary.is_empty() {
+			errors.push("executor.firecracker.binary must not be empty".to_string());
+		}
+		if self.boot_timeout_ms == 0 {
+			errors.push("executor.firecracker.boot_timeout_ms must be positive".to_string());
+		}
+		if self.guest_port == 0 {
+			errors.push("executor.firecracker.guest_port must be positive".to_string());
+		}
+		for (name, class) in &self.classes {
+			let key = format!("executor.firecracker.classes.{name}");
+			if class.kernel.as_os_str().is_empty() || class.rootfs.as_os_str().is_empty() {
+				errors.push(format!("{key} needs a kernel and a rootfs"));
+			}
+			if !(1..=MAX_VCPUS).contains(&class.vcpus) {
+				errors.push(format!("{key}.vcpus must be between 1 and {MAX_VCPUS} (got {})", class.vcpus));
+			}
+			if class.memory_mb == 0 {
+				errors.push(format!("{key}.memory_mb must be positive"));
+			}
+		}
+		errors
+	}
+}
+
+/// What the VMs of one class boot, and how big they are.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VmClass {
+	/// Uncompressed kernel (`vmlinux`).
+	pub kernel: PathBuf,
+	/// ext4 image holding `pagi-vm-agent`, attached read-only.
+	pub rootfs: PathBuf,
+	pub vcpus: u32,
+	pub memory_mb: u64,
+	/// VMs booted ahead of time and kept waiting, so that executions skip
+	/// the boot; `executor.warm_pool` says what becomes of them after. 0
+	/// boots one per execution.
+	pub pool_size: usize,
+}
+
+impl Default for VmClass {
+	fn default() -> Self {
+		Self {
+			kernel: PathBuf::new(),
+			rootfs: PathBuf::new(),
+			vcpus: DEFAULT_VCPUS,
+			memory_mb: DEFAULT_MEMORY_MB,
+			pool_size: 0,
+		}
+	}
+}
+
+/// Runs each tool invocation in a Firecracker microVM, booted for it or
+/// taken from the warm pool, and shut down or reset after it as the taint
+/// policy says.
+pub struct FirecrackerExecutor {
+	pool: Arc<WarmPool<Booter>>,
+}
+
+impl FirecrackerExecutor {
+	pub fn new(config: FirecrackerConfig, warm: WarmPoolConfig) -> Self {
+		Self {
+			pool: WarmPool::new("firecracker", Booter { config }, warm),
+		}
+	}
+
+	fn config(&self) -> &FirecrackerConfig {
+		&self.pool.launcher().config
+	}
+
+	/// The class `tool` boots, with its name.
+	fn class<'a>(&'a self, tool: &'a ToolManifest) -> (&'a str, Option<&'a VmClass>) {
+		let name = tool.vm_class.as_deref().unwrap_or(DEFAULT_CLASS);
+		(name, self.config().classes.get(name))
+	}
+
+	/// The VM `tool` runs in, for dry runs.
+	pub fn limits(&self, tool: &ToolManifest) -> Value {
+		match self.class(tool) {
+			(name, Some(class)) => json!({
+				"vm_class": name,
+				"vcpus": class.vcpus,
+				"memory_mb": class.memory_mb,
+			}),
+			(name, None) => json!({ "vm_class": name }),
+		}
+	}
+
+	/// The class `tool` boots, with the kernel and root filesystem it boots
+	/// from when it is configured.
+	pub fn images<'a>(&'a self, tool: &'a ToolManifest) -> (&'a str, Option<(&'a Path, &'a Path)>) {
+		let (name, class) = self.class(tool);
+		(name, class.map(|class| (class.kernel.as_path(), class.rootfs.as_path())))
+	}
+
+	/// Boot the warm pools of every class that has one.
+	pub fn prewarm(&self) {
+		for name in self.config().classes.keys() {
+			self.pool.refill(&name.to_string());
+		}
+	}
+
+	/// Run `tool` in a VM of its class.
+	///
+	/// The workspace is copied into the guest's `/work`, where the agent runs
+	/// the manifest's `command` with the JSON args appended, with `env` as
+	/// its whole environment. Secrets travel over vsock only; the VM has no
+	/// network device. Output is streamed back as it is produced, and
+	/// whatever the tool leaves in `/work` is copied back into the workspace
+	/// when it exits. On timeout or cancellation the VM is killed.
+	pub async fn run(
+		&self,
+		tool: &ToolManifest,
+		args: &Value,
+		work_dir: &Path,
+		env: &ToolEnv,
+		ctx: &ExecutionContext,
+	) -> ToolResult {
+		let (class_name, class) = self.class(tool);
+		if class.is_none() {
+			return failure(
+				"vm_error",
+				format!("no executor.firecracker.classes.{class_name} for this tool to boot"),
+			);
+		};
+		let max_bytes = self.config().max_workspace_bytes;
+		let files = match read_workspace(work_dir.to_path_buf(), max_bytes).await {
+			Ok(files) => files,
+			Err(e) => return failure("io_error", format!("failed to read the workspace: {e}")),
+		};
+		let mut argv = tool.command.clone();
+		argv.push(args.to_string());
+		let mut vars: BTreeMap<String, String> = env.vars.iter().cloned().collect();
+		vars.extend(env.secrets.iter().map(|(key, secret)| (key.clone(), secret.expose().to_string())));
+		vars.insert("PAGI_TOOL_NAME".to_string(), tool.name.clone());
+		let start = HostFrame::Start {
+			argv,
+			env: vars,
+			files,
+		};
+
+		let captured = Mutex::new(Captured::default());
+		let mut lease = None;
+		let run = async {
+			let vm = lease.insert(self.pool.take(&class_name.to_string()).await?);
+			vm.instance().execute(start, ctx.stdin(), work_dir, max_bytes, ctx, &captured).await
+		};
+		let ending = tokio::select! {
+			ending = run => match ending {
+				Ok((exit_code, signal)) => Ending::Exited { exit_code, signal },
+				Err(e) => Ending::Failed(e),
+			},
+			_ = tokio::time::sleep(ctx.timeout) => Ending::TimedOut,
+			_ = ctx.cancel.cancelled() => Ending::Cancelled,
+		};
+		if let Some(lease) = lease {
+			lease.release(matches!(ending, Ending::Exited { exit_code: Some(_), .. }));
+		}
+
+		let captured = captured.into_inner().unwrap();
+		let limit = ctx.max_output_bytes as u64;
+		let truncated = (captured.stdout_bytes > limit || captured.stderr_bytes > limit).then_some(Truncation {
+			stdout_bytes: captured.stdout_bytes,
+			stderr_bytes: captured.stderr_bytes,
+		});
+		let (stdout, stdout_encoding) = encoding::encode(captured.stdout, captured.stdout_bytes > limit);
+		let (mut stderr, stderr_encoding) = encoding::encode(captured.stderr, captured.stderr_bytes > limit);
+		let (status, exit_code) = match ending {
+			Ending::Exited { exit_code, signal } => {
+				let status = match (exit_code, signal) {
+					(Some(0), _) => "ok",
+					(Some(_), _) => "non_zero_exit",
+					(None, Some(_)) => "killed",
+					(None, None) => "unknown_exit",
+				};
+				(status, exit_code)
+			}
+			Ending::TimedOut => ("timed_out", None),
+			Ending::Cancelled => ("cancelled", None),
+			Ending::Failed(e) => {
+				warn!(tool_name = %tool.name, vm_class = class_name, error = %e, message = "Firecracker execution failed");
+				encoding::push_note(&mut stderr, stderr_encoding, &format!("{e}\n"));
+				("vm_error", None)
+			}
+		};
+		ToolResult {
+			status: status.to_string(),
+			stdout,
+			stderr,
+			encoding: OutputEncoding::of(stdout_encoding, stderr_encoding),
+			exit_code,
+			truncated,
+			..Default::default()
+		}
+	}
+
+	/// Whether VMs can be run here: `/dev/kvm` is usable and the binary
+	/// answers. For readiness probes.
+	pub async fn check(&self) -> Result<Value, String> {
+		std::fs::OpenOptions::new()
+			.read(true)
+			.write(true)
+			.open("/dev/kvm")
+			.map_err(|e| format!("/dev/kvm: {e}"))?;
+		let spec = CommandSpec::new(&self.config().binary)
+			.arg("--version")
+			.timeout(VERSION_CHECK_TIMEOUT);
+		let version = match subprocess::run(&spec).await {
+			Ok(out) if out.success() => out.stdout.lines().next().unwrap_or_default().to_string(),
+			Ok(out) if out.timed_out => return Err(format!("{} did not answer in time", self.config().binary)),
+			Ok(out) => return Err(out.stderr.trim().to_string()),
+			Err(e) => return Err(format!("failed to spawn {}: {e}", self.config().binary)),
+		};
+		Ok(json!({ "version": version, "warm": self.pool.ready() }))
+	}
+}
+
+fn failure(status: &str, stderr: String) -> ToolResult {
+	ToolResult {
+		status: status.to_string(),
+		stderr,
+		..Default::default()
+	}
+}
+
+enum Ending {
+	Exited { exit_code: Option<i32>, signal: Option<i32> },
+	TimedOut,
+	Cancelled,
+	Failed(String),
+}
+
+/// What was kept of the tool's output, and how much of it there was.
+#[derive(Default)]
+struct Captured {
+	stdout: Vec<u8>,
+	stderr: Vec<u8>,
+	stdout_bytes: u64,
+	stderr_bytes: u64,
+}
+
+/// Boots the VMs of the warm pool, whose classes are class names.
+struct Booter {
+	config: FirecrackerConfig,
+}
+
+impl Booter {
+	fn class(&self, name: &str) -> Result<&VmClass, String> {
+		self.config
+			.classes
+			.get(name)
+			.ok_or_else(|| format!("no executor.firecracker.classes.{name}"))
+	}
+}
+
+#[tonic::async_trait]
+impl Launcher for Booter {
+	type Class = String;
+	type Instance = Vm;
+
+	fn pool_size(&self, class: &String) -> usize {
+		self.class(class).map_or(0, |class| class.pool_size)
+	}
+
+	async fn launch(&self, class: &String) -> Result<Vm, String> {
+		Vm::boot(&self.config, self.class(class)?).await
+	}
+
+	fn alive(&self, vm: &mut Vm) -> bool {
+		matches!(vm.process.try_wait(), Ok(None))
+	}
+
+	/// The agent cleans the guest up after every tool (killing what it left
+	/// running and emptying `/work` and `/tmp`) and waits for the next
+	/// connection; a VM that takes it is as good as new.
+	async fn reset(&self, vm: &mut Vm) -> Result<(), String> {
+		let deadline = Instant::now() + Duration::from_millis(self.config.boot_timeout_ms);
+		vm.connect(self.config.guest_port, deadline).await
+	}
+
+	async fn destroy(&self, vm: Vm) {
+		drop(vm);
+	}
+}
+
+/// A running microVM whose agent has answered. Dropping it kills the VM and
+/// deletes its state directory.
+struct Vm {
+	process: Child,
+	/// Taken by an execution; a recycled VM connects again.
+	stream: Option<UnixStream>,
+	vsock: PathBuf,
+	dir: VmDir,
+}
+
+impl Vm {
+	async fn boot(config: &FirecrackerConfig, class: &VmClass) -> Result<Self, String> {
+		let id = uuid::Uuid::new_v4().to_string();
+		let dir = VmDir(config.state_dir.join(&id));
+		std::fs::create_dir_all(&dir.0).map_err(|e| format!("{}: {e}", dir.0.display()))?;
+		let vsock = dir.0.join("vsock.sock");
+		let vm_config = json!({
+			"boot-source": {
+				"kernel_image_path": class.kernel,
+				"boot_args": config.boot_args,
+			},
+			"drives": [{
+				"drive_id": "rootfs",
+				"path_on_host": class.rootfs,
+				"is_root_device": true,
+				"is_read_only": true,
+			}],
+			"machine-config": {
+				"vcpu_count": class.vcpus,
+				"mem_size_mib": class.memory_mb,
+			},
+			"vsock": {
+				"guest_cid": GUEST_CID,
+				"uds_path": vsock,
+			},
+		});
+		let config_path = dir.0.join("config.json");
+		std::fs::write(&config_path, vm_config.to_string()).map_err(|e| format!("{}: {e}", config_path.display()))?;
+		let console = std::fs::File::create(dir.0.join("console.log")).map_err(|e| format!("console log: {e}"))?;
+		let console_err = console.try_clone().map_err(|e| format!("console log: {e}"))?;
+
+		let mut cmd = Command::new(&config.binary);
+		cmd.arg("--no-api")
+			.arg("--config-file")
+			.arg(&config_path)
+			.arg("--id")
+			.arg(&id)
+			.stdin(Stdio::null())
+			.stdout(console)
+			.stderr(console_err)
+			.kill_on_drop(true);
+		// SAFETY: prctl with integer arguments only; a VM must not outlive
+		// the sandbox that would have shut it down.
+		unsafe {
+			cmd.pre_exec(|| {
+				if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) != 0 {
+					return Err(io::Error::last_os_error());
+				}
+				Ok(())
+			});
+		}
+		let process = cmd.spawn().map_err(|e| format!("failed to spawn {}: {e}", config.binary))?;
+
+		let mut vm = Self {
+			process,
+			stream: None,
+			vsock,
+			dir,
+		};
+		vm.connect(config.guest_port, Instant::now() + Duration::from_millis(config.boot_timeout_ms))
+			.await?;
+		Ok(vm)
+	}
+
+	/// Wait for the agent to take a connection, until `deadline`.
+	async fn connect(&mut self, port: u32, deadline: Instant) -> Result<(), String> {
+		self.stream = None;
+		loop {
+			if let Ok(Some(status)) = self.process.try_wait() {
+				return Err(format!("firecracker exited with {status}: {}", self.dir.console_tail()));
+			}
+			if let Ok(Some(stream)) = tokio::time::timeout_at(deadline, connect(&self.vsock, port)).await {
+				self.stream = Some(stream);
+				return Ok(());
+			}
+			if Instant::now() >= deadline {
+				return Err(format!("the VM's agent did not answer in time: {}", self.dir.console_tail()));
+			}
+			tokio::time::sleep(CONNECT_INTERVAL).await;
+		}
+	}
+
+	/// Hand the agent `start`, feed it `stdin` and collect what comes back
+	/// until the tool exits: its exit code and signal.
+	async fn execute(
+		&mut self,
+		start: HostFrame,
+		stdin: Option<StdinSource>,
+		work_dir: &Path,
+		max_bytes: u64,
+		ctx: &ExecutionContext,
+		captured: &Mutex<Captured>,
+	) -> Result<(Option<i32>, Option<i32>), String> {
+		let stream = self.stream.take().ok_or("the VM's agent is not connected")?;
+		let dir = &self.dir;
+		let (read, mut write) = stream.into_split();
+		send(&mut write, &start).await.map_err(|e| format!("failed to start the tool: {e}"))?;
+		let feed = async move {
+			// A guest that stops reading its stdin is the tool's business.
+			let _ = feed_stdin(&mut write, stdin).await;
+			std::future::pending::<()>().await
+		};
+
+		// Frames carry base64, a third more than the bytes.
+		let max_frame = max_bytes + max_bytes / 2 + 64 * 1024;
+		let mut frames = BufReader::new(read);
+		let limit = ctx.max_output_bytes;
+		let collect = async {
+			loop {
+				let frame = match read_frame(&mut frames, max_frame).await {
+					Ok(Some(frame)) => frame,
+					Ok(None) => return Err(format!("the VM went away before the tool exited: {}", dir.console_tail())),
+					Err(e) => return Err(format!("bad frame from the guest: {e}")),
+				};
+				match frame {
+					GuestFrame::Stdout { data } => output(captured, StreamKind::Stdout, data.0, limit, ctx).await,
+					GuestFrame::Stderr { data } => output(captured, StreamKind::Stderr, data.0, limit, ctx).await,
+					GuestFrame::Exit {
+						exit_code,
+						signal,
+						files,
+					} => {
+						write_workspace(work_dir, files, max_bytes)
+							.await
+							.map_err(|e| format!("failed to copy the workspace back: {e}"))?;
+						return Ok((exit_code, signal));
+					}
+					GuestFrame::Error { message } => return Err(format!("the guest agent failed: {message}")),
+				}
+			}
+		};
+		tokio::select! {
+			result = collect => result,
+			_ = feed => unreachable!("feeding stdin never ends"),
+		}
+	}
+}
+
+/// A VM's state directory, deleted with it.
+struct VmDir(PathBuf);
+
+impl VmDir {
+	/// The end of the VM's serial console, where the kernel says why it
+	/// stopped.
+	fn console_tail(&self) -> String {
+		let Ok(mut console) = std::fs::File::open(self.0.join("console.log")) else {
+			return "no console output".to_string();
+		};
+		let len = console.metadata().map(|m| m.len()).unwrap_or_default();
+		let mut tail = Vec::new();
+		let _ = console.seek(SeekFrom::Start(len.saturating_sub(CONSOLE_TAIL_BYTES)));
+		let _ = console.read_to_end(&mut tail);
+		let tail = String::from_utf8_lossy(&tail);
+		match tail.trim() {
+			"" => "no console output".to_string(),
+			tail => format!("console: {tail}"),
+		}
+	}
+}
+
+impl Drop for VmDir {
+	fn drop(&mut self) {
+		if let Err(e) = std::fs::remove_dir_all(&self.0) {
+			if e.kind() != io::ErrorKind::NotFound {
+				warn!(dir = %self.0.display(), error = %e, message = "Failed to remove VM state directory");
+			}
+		}
+	}
+}
+
+/// Open a connection to the guest's `port` through Firecracker's vsock
+/// socket; `None` while nothing listens there yet.
+async fn connect(uds: &Path, port: u32) -> Option<UnixStream> {
+	let mut stream = UnixStream::connect(uds).await.ok()?;
+	stream.write_all(format!("CONNECT {port}\n").as_bytes()).await.ok()?;
+	// Firecracker answers `OK <host port>` once the guest accepts, or hangs
+	// up. Read it a byte at a time so nothing past it is consumed.
+	let mut answer = Vec::new();
+	loop {
+		let byte = stream.read_u8().await.ok()?;
+		if byte == b'\n' {
+			break;
+		}
+		answer.push(byte);
+	}
+	answer.starts_with(b"OK ").then_some(stream)
+}
+
+async fn send(write: &mut OwnedWriteHalf, frame: &HostFrame) -> io::Result<()> {
+	let mut line = serde_json::to_vec(frame).map_err(io::Error::other)?;
+	line.push(b'\n');
+	write.write_all(&line).await
+}
+
+async fn feed_stdin(write: &mut OwnedWriteHalf, stdin: Option<StdinSource>) -> io::Result<()> {
+	match stdin {
+		Some(StdinSource::Bytes(data)) => {
+			send(write, &HostFrame::Stdin { data: Base64(data.to_vec()) }).await?;
+		}
+		Some(StdinSource::Stream(stream)) => {
+			if let Some(mut chunks) = stream.take() {
+				while let Some(chunk) = chunks.recv().await {
+					send(write, &HostFrame::Stdin { data: Base64(chunk) }).await?;
+				}
+			}
+		}
+		None => {}
+	}
+	send(write, &HostFrame::StdinEof).await
+}
+
+/// The next frame, or `None` at the end of the connection. The guest runs
+/// untrusted code, so a frame may be anything up to `max` bytes.
+async fn read_frame(reader: &mut (impl AsyncBufRead + Unpin), max: u64) -> io::Result<Option<GuestFrame>> {
+	let mut line = Vec::new();
+	let read = reader.take(max).read_until(b'\n', &mut line).await?;
+	if read == 0 {
+		return Ok(None);
+	}
+	if line.last() != Some(&b'\n') {
+		let problem = if read as u64 >= max { "is too large" } else { "is cut off" };
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("a frame {problem}")));
+	}
+	serde_json::from_slice(&line).map(Some).map_err(io::Error::other)
+}
+
+async fn output(captured: &Mutex<Captured>, stream: StreamKind, data: Vec<u8>, limit: usize, ctx: &ExecutionContext) {
+	{
+		let captured = &mut *captured.lock().unwrap();
+		let (kept, total) = match stream {
+			StreamKind::Stdout => (&mut captured.stdout, &mut captured.stdout_bytes),
+			StreamKind::Stderr => (&mut captured.stderr, &mut captured.stderr_bytes),
+		};
+		let keep = data.len().min(limit.saturating_sub(kept.len()));
+		kept.extend_from_slice(&data[..keep]);
+		*total += data.len() as u64;
+	}
+	if let Some(sink) = &ctx.output {
+		let _ = sink.send(OutputChunk { stream, data }).await;
+	}
+}
+
+/// Every regular file under `root`, by path relative to it.
+async fn read_workspace(root: PathBuf, max_bytes: u64) -> io::Result<BTreeMap<String, Base64>> {
+	tokio::task::spawn_blocking(move || {
+		let mut files = BTreeMap::new();
+		let mut total = 0;
+		let mut dirs = vec![root.clone()];
+		while let Some(dir) = dirs.pop() {
+			for entry in std::fs::read_dir(&dir)? {
+				let entry = entry?;
+				let kind = entry.file_type()?;
+				if kind.is_dir() {
+					dirs.push(entry.path());
+				} else if kind.is_file() {
+					let data = std::fs::read(entry.path())?;
+					total += data.len() as u64;
+					if total > max_bytes {
+						return Err(io::Error::other(format!("it is over {max_bytes} bytes")));
+					}
+					let path = entry.path();
+					let relative = path.strip_prefix(&root).expect("entries are under the root");
+					files.insert(relative.to_string_lossy().to_string(), Base64(data));
+				}
+			}
+		}
+		Ok(files)
+	})
+	.await
+	.map_err(io::Error::other)?
+}
+
+/// Put the files the guest sent back into the workspace, which holds only
+/// what the sandbox itself wrote there.
+async fn write_workspace(root: &Path, files: BTreeMap<String, Base64>, max_bytes: u64) -> io::Result<()> {
+	let total: u64 = files.values().map(|data| data.0.len() as u64).sum();
+	if total > max_bytes {
+		return Err(io::Error::other(format!("it is over {max_bytes} bytes")));
+	}
+	for (relative, data) in files {
+		if !is_valid_input_path(&relative) {
+			return Err(io::Error::other(format!("{relative:?} is not a path inside the workspace")));
+		}
+		let path = root.join(&relative);
+		if let Some(parent) = path.parent() {
+			tokio::fs::create_dir_all(parent).await?;
+		}
+		tokio::fs::write(&path, data.0)
+			.await
+			.map_err(|e| io::Error::new(e.kind(), format!("{relative}: {e}")))?;
+	}
+	Ok(())
+}
@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tracing::info;
+use wasmtime::Module;
+use wasmtime_wasi::FsPerms;
+
+use crate::egress::NetworkPolicy;
+use crate::executor_wasm::{Guest, WasmRuntime};
+use crate::secrets::ToolEnv;
+use crate::tool_executor::{CodeException, ExecutionContext, ToolExecutionResult, ToolLanguage};
+use crate::workspace::Workspace;
+
+const DEFAULT_HEAP_MB: usize = 64;
+const DEFAULT_FUEL: u64 = 10_000_000_000;
+/// Linear memory the engine gets on top of the JS heap, for its own data
+/// and stack.
+const ENGINE_OVERHEAD_MB: usize = 32;
+const SCRIPT: &str = "main.mjs";
+const RUNNER_FILE: &str = ".pagi_runner.mjs";
+/// Where the runner leaves the uncaught exception, in the workspace.
+const EXCEPTION_FILE: &str = ".pagi_exception.json";
+/// Set by the first statement of `main.mjs`, so a `SyntaxError` thrown
+/// while it runs is not taken for one in its source.
+const STARTED_MARK: &str = "globalThis[Symbol.for(\"pagi.started\")] = true; ";
+
+/// Takes `eval` and the `Function` constructors away, since code built from
+/// strings would escape the import check, then imports `main.mjs` and, if it
+/// throws or rejects, writes the exception to `EXCEPTION_FILE`, prints it
+/// with its stack and exits 1.
+const RUNNER: &str = r#"import * as std from "std";
+const disabled = (name) => function () {
+	throw new EvalError(`${name} is disabled by the sandbox`);
+};
+globalThis.eval = disabled("eval");
+const NoFunction = disabled("Function");
+NoFunction.prototype = Function.prototype;
+for (const f of [function () {}, async function () {}, function* () {}, async function* () {}]) {
+	Object.defineProperty(Object.getPrototypeOf(f), "constructor", { value: NoFunction });
+}
+globalThis.Function = NoFunction;
+scriptArgs[0] = "main.mjs";
+import("./main.mjs").catch((e) => {
+	const error = e instanceof Error ? e : { name: typeof e, message: String(e), stack: "" };
+	const stack = String(error.stack ?? "");
+	const at = /main\.mjs:(\d+)/.exec(stack);
+	const text = `${error.name}: ${error.message}\n${stack}`;
+	const f = std.open("/work/.pagi_exception.json", "w");
+	f.puts(JSON.stringify({
+		type: String(error.name),
+		message: String(error.message),
+		line: at ? Number(at[1]) : null,
+		syntax_error: error instanceof SyntaxError && !globalThis[Symbol.for("pagi.started")],
+		traceback: text,
+	}));
+	f.close();
+	std.err.puts(text);
+	std.exit(1);
+});
+"#;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JavaScriptConfig {
+	/// WASI build of the QuickJS interpreter, `qjs` (e.g. quickjs-ng's
+	/// `qjs-wasi.wasm`). The `javascript` tool is unavailable without one.
+	pub engine: Option<PathBuf>,
+	/// Heap the engine lets a program allocate before throwing.
+	pub heap_mb: usize,
+	/// Instruction budget, counted in engine instructions.
+	pub fuel: u64,
+	/// Vetted modules, mounted read-only as `/modules`.
+	pub modules_dir: Option<PathBuf>,
+	/// Bare module names code may import, e.g. the engine's `std` and `os`.
+	/// With either, code can write a module and load it or evaluate a
+	/// string (`std.evalScript`) past the import check.
+	pub allowed_modules: Vec<String>,
+}
+
+impl Default for JavaScriptConfig {
+	fn default() -> Self {
+		Self {
+			engine: None,
+			heap_mb: DEFAULT_HEAP_MB,
+			fuel: DEFAULT_FUEL,
+			modules_dir: None,
+			allowed_modules: Vec::new(),
+		}
+	}
+}
+
+impl JavaScriptConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if self.heap_mb == 0 {
+			errors.push("executor.javascript.heap_mb must be positive".to_string());
+		}
+		if self.fuel == 0 {
+			errors.push("executor.javascript.fuel must be positive".to_string());
+		}
+		if let Some(dir) = &self.modules_dir {
+			if !dir.is_dir() {
+				errors.push(format!("executor.javascript.modules_dir {} is not a directory", dir.display()));
+			}
+		}
+		for name in &self.allowed_modules {
+			if name.is_empty() || name.starts_with('.') || name.starts_with('/') {
+				errors.push(format!(
+					"executor.javascript.allowed_modules holds bare module names (got {name:?})"
+				));
+			}
+		}
+		errors
+	}
+}
+
+/// Runs JavaScript modules in the configured engine on the embedded WASM
+/// runtime, so no JavaScript runtime is needed on the host. The engine only
+/// sees the workspace as `/work` and the modules directory as `/modules`,
+/// and has no sockets.
+pub struct JavaScriptRuntime {
+	config: JavaScriptConfig,
+	wasm: Arc<WasmRuntime>,
+	/// Compiled once at startup.
+	engine: Option<Module>,
+}
+
+impl JavaScriptRuntime {
+	/// Fails when the engine does not compile.
+	pub fn new(config: JavaScriptConfig, wasm: Arc<WasmRuntime>) -> Result<Self, String> {
+		let engine = match &config.engine {
+			Some(path) => {
+				let module = wasm.load(path).map_err(|e| format!("{}: {e:#}", path.display()))?;
+				info!(engine = %path.display(), heap_mb = config.heap_mb, message = "JavaScript engine loaded");
+				Some(module)
+			}
+			None => None,
+		};
+		Ok(Self { config, wasm, engine })
+	}
+
+	/// Write `code` to `main.mjs` in the workspace and run it as a module
+	/// with `argv` in `scriptArgs`. Its imports, and those of the workspace
+	/// modules it imports, are checked against the allowlist first. An
+	/// uncaught exception is reported in `exception`,
+	/// with status `compile_error` for a syntax error and `runtime_error`
+	/// for any other.
+	pub async fn run(
+		&self,
+		code: &str,
+		argv: &[String],
+		ctx: &ExecutionContext,
+		workspace: &Workspace,
+		env: &ToolEnv,
+	) -> ToolExecutionResult {
+		let failed = |status: &str, stderr: String| ToolExecutionResult {
+			status: status.to_string(),
+			language: ToolLanguage::Javascript,
+			stdout: "".to_string(),
+			stderr,
+			compile_stdout: "".to_string(),
+			compile_stderr: "".to_string(),
+			encoding: None,
+			exit_code: None,
+			truncated: None,
+			exception: None,
+		};
+		let Some(engine) = &self.engine else {
+			return failed(
+				"unsupported_language",
+				"JavaScript is not enabled; set executor.javascript.engine".to_string(),
+			);
+		};
+		let checked = check_module_graph(
+			code,
+			&workspace.path,
+			&self.config.allowed_modules,
+			self.config.modules_dir.is_some(),
+		);
+		if let Err(e) = checked.await {
+			return failed("module_denied", format!("blocked by the sandbox: {e}\n"));
+		}
+		let source = format!("{STARTED_MARK}{code}");
+		for (file, contents) in [(SCRIPT, source.as_str()), (RUNNER_FILE, RUNNER)] {
+			if let Err(e) = fs::write(workspace.path.join(file), contents).await {
+				return failed("io_error", format!("failed to write {file}: {e}"));
+			}
+		}
+
+		let mut preopens = vec![(workspace.path.clone(), "/work", FsPerms::ReadWrite)];
+		if let Some(dir) = &self.config.modules_dir {
+			preopens.push((dir.clone(), "/modules", FsPerms::ReadOnly));
+		}
+		let heap_bytes = self.config.heap_mb * 1024 * 1024;
+		let mut engine_argv = vec![
+			"qjs".to_string(),
+			"--memory-limit".to_string(),
+			heap_bytes.to_string(),
+			"-m".to_string(),
+			format!("/work/{RUNNER_FILE}"),
+		];
+		engine_argv.extend(argv.iter().cloned());
+		let guest = Guest {
+			name: "javascript",
+			module: engine.clone(),
+			argv: engine_argv,
+			preopens,
+			network: &NetworkPolicy::None,
+			max_memory_mb: self.config.heap_mb + ENGINE_OVERHEAD_MB,
+			fuel: self.config.fuel,
+		};
+		let outcome = self.wasm.run_guest(guest, env, ctx).await;
+		let _ = fs::remove_file(workspace.path.join(RUNNER_FILE)).await;
+		let run = match outcome {
+			Ok(run) => run,
+			Err(e) => return failed("wasm_error", format!("{e:#}")),
+		};
+		let exception_path = workspace.path.join(EXCEPTION_FILE);
+		let exception: Option<CodeException> = fs::read(&exception_path)
+			.await
+			.ok()
+			.and_then(|bytes| serde_json::from_slice(&bytes).ok());
+		let _ = fs::remove_file(&exception_path).await;
+
+		let status = match run.status.as_str() {
+			"non_zero_exit" if exception.as_ref().is_some_and(|e| e.syntax_error) => "compile_error".to_string(),
+			"non_zero_exit" => "runtime_error".to_string(),
+			_ => run.status,
+		};
+		ToolExecutionResult {
+			status,
+			language: ToolLanguage::Javascript,
+			stdout: run.stdout,
+			stderr: run.stderr,
+			compile_stdout: "".to_string(),
+			compile_stderr: "".to_string(),
+			encoding: run.encoding,
+			exit_code: run.exit_code,
+			truncated: run.truncated,
+			exception,
+		}
+	}
+}
+
+/// Check `code` as `main.mjs`, then each workspace module it imports in
+/// turn, since input files can be modules too. A workspace module that does
+/// not exist yet is refused: the code could write it before importing it.
+/// Modules under `/modules` are vetted and not followed.
+async fn check_module_graph(code: &str, workspace: &Path, allowed: &[String], modules: bool) -> Result<(), String> {
+	let mut pending = vec![(PathBuf::from(SCRIPT), code.to_string())];
+	let mut seen = HashSet::from([PathBuf::from(SCRIPT)]);
+	while let Some((file, source)) = pending.pop() {
+		let in_file = |e: String| format!("{}: {e}", file.display());
+		for specifier in imports(&source).map_err(in_file)? {
+			check_specifier(&specifier, allowed, modules).map_err(in_file)?;
+			let Some(target) = workspace_module(&file, &specifier) else {
+				continue;
+			};
+			let target = target.ok_or_else(|| in_file(format!("module {specifier:?} is outside the workspace")))?;
+			if !seen.insert(target.clone()) {
+				continue;
+			}
+			let source = fs::read(workspace.join(&target))
+				.await
+				.map_err(|e| in_file(format!("module {specifier:?}: {e}")))?;
+			pending.push((target, String::from_utf8_lossy(&source).into_owned()));
+		}
+	}
+	Ok(())
+}
+
+/// Where `specifier`, imported by the workspace file `from`, is in the
+/// workspace: `None` for a module elsewhere, `Some(None)` for a path that
+/// leaves the workspace.
+fn workspace_module(from: &Path, specifier: &str) -> Option<Option<PathBuf>> {
+	let path = match specifier.strip_prefix("/work/") {
+		Some(path) => PathBuf::from(path),
+		None if specifier.starts_with("./") || specifier.starts_with("../") => {
+			from.parent().unwrap_or(Path::new("")).join(specifier)
+		}
+		None => return None,
+	};
+	let mut resolved = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::Normal(name) => resolved.push(name),
+			Component::CurDir => {}
+			Component::ParentDir if resolved.pop() => {}
+			_ => return Some(None),
+		}
+	}
+	Some(Some(resolved))
+}
+
+/// Check an imported module against the allowlist: relative paths and
+/// `/work` (the workspace), `/modules` when there is a modules directory,
+/// and the bare names in `allowed`.
+fn check_specifier(specifier: &str, allowed: &[String], modules: bool) -> Result<(), String> {
+	let permitted = specifier.starts_with("./")
+		|| specifier.starts_with("../")
+		|| specifier.starts_with("/work/")
+		|| (modules && specifier.starts_with("/modules/"))
+		|| allowed.iter().any(|name| name == specifier);
+	if !permitted {
+		return Err(format!("module {specifier:?} is not in executor.javascript.allowed_modules"));
+	}
+	Ok(())
+}
+
+/// The specifiers of the modules `code` imports, statically or with
+/// `import()`, or re-exports from. `import()` must be given a string
+/// literal.
+fn imports(code: &str) -> Result<Vec<String>, String> {
+	let code = strip_comments(code);
+	let bytes = code.as_bytes();
+	let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b'$';
+	let mut specifiers = Vec::new();
+	let mut i = 0;
+	while i < bytes.len() {
+		let keyword = ["import", "export"].into_iter().find(|k| bytes[i..].starts_with(k.as_bytes()));
+		let boundary = i == 0 || !(is_ident(bytes[i - 1]) || bytes[i - 1] == b'.');
+		let (Some(keyword), true) = (keyword, boundary) else {
+			i += 1;
+			continue;
+		};
+		let mut j = i + keyword.len();
+		if bytes.get(j).copied().is_some_and(is_ident) {
+			i = j;
+			continue;
+		}
+		j = skip_space(bytes, j);
+		match bytes.get(j) {
+			Some(b'(') if keyword == "import" => {
+				let start = skip_space(bytes, j + 1);
+				let literal = string_literal(&code, start)
+					.filter(|(_, end)| bytes.get(skip_space(bytes, *end)) == Some(&b')'));
+				match literal {
+					Some((specifier, _)) => specifiers.push(specifier),
+					None => return Err("import() must be given a string literal".to_string()),
+				}
+			}
+			Some(b'.') => {}
+			Some(b'"' | b'\'') if keyword == "import" => {
+				if let Some((specifier, _)) = string_literal(&code, j) {
+					specifiers.push(specifier);
+				}
+			}
+			_ => {
+				// `import x from "m"`, `export { x } from "m"`: the clause runs
+				// to its `from "m"` or its end.
+				let end = code[j..].find(';').map_or(code.len(), |e| j + e);
+				let clause = &code[j..end];
+				let mut from = 0;
+				while let Some(at) = clause[from..].find("from") {
+					let at = from + at;
+					let after = skip_space(bytes, j + at + 4);
+					if let Some((specifier, _)) = string_literal(&code, after) {
+						specifiers.push(specifier);
+						break;
+					}
+					from = at + 4;
+				}
+			}
+		}
+		i = j;
+	}
+	Ok(specifiers)
+}
+
+/// `code` with its comments blanked, strings and template literals kept.
+fn strip_comments(code: &str) -> String {
+	let mut out = String::with_capacity(code.len());
+	let mut chars = code.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'"' | '\'' | '`' => {
+				out.push(c);
+				while let Some(s) = chars.next() {
+					out.push(s);
+					if s == '\\' {
+						if let Some(escaped) = chars.next() {
+							out.push(escaped);
+						}
+					} else if s == c {
+						break;
+					}
+				}
+			}
+			'/' if chars.peek() == Some(&'/') => {
+				for s in chars.by_ref() {
+					if s == '\n' {
+						out.push('\n');
+						break;
+					}
+				}
+			}
+			'/' if chars.peek() == Some(&'*') => {
+				chars.next();
+				let mut last = ' ';
+				for s in chars.by_ref() {
+					if last == '*' && s == '/' {
+						break;
+					}
+					last = s;
+				}
+				out.push(' ');
+			}
+			_ => out.push(c),
+		}
+	}
+	out
+}
+
+fn skip_space(bytes: &[u8], mut i: usize) -> usize {
+	while bytes.get(i).is_some_and(|b| b.is_ascii_whitespace()) {
+		i += 1;
+	}
+	i
+}
+
+/// The quoted string starting at `start`, and the index after it.
+fn string_literal(code: &str, start: usize) -> Option<(String, usize)> {
+	let quote = *code.as_bytes().get(start)?;
+	if quote != b'"' && quote != b'\'' {
+		return None;
+	}
+	let rest = &code[start + 1..];
+	let len = rest.find(quote as char)?;
+	Some((rest[..len].to_string(), start + 1 + len + 1))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn imports_finds_every_form() {
+		let code = r#"
+			import x from "std";
+			import { a,
+				b } from './a.js';
+			import * as os from "os"
+			export * from "../b.js";
+			export { c } from "/work/c.js";
+			import "./side.js";
+			const m = await import( "./dyn.js" );
+		"#;
+		assert_eq!(
+			imports(code).unwrap(),
+			["std", "./a.js", "os", "../b.js", "/work/c.js", "./side.js", "./dyn.js"]
+		);
+	}
+
+	#[test]
+	fn imports_skips_comments_and_lookalikes() {
+		let code = r#"
+			// import x from "commented";
+			/* import("commented") */
+			console.log(import.meta.url, reimport("x"), loader.import("y"));
+			export const answer = 42;
+			import y from "kept";
+		"#;
+		assert_eq!(imports(code).unwrap(), ["kept"]);
+	}
+
+	#[test]
+	fn imports_wants_literal_dynamic_specifiers() {
+		assert!(imports("await import(name)").is_err());
+		assert!(imports("await import('./a' + suffix)").is_err());
+		assert!(imports("await import(`./a.js`)").is_err());
+	}
+
+	#[test]
+	fn check_specifier_allows_the_workspace_and_listed_names() {
+		let allowed = ["std".to_string()];
+		for specifier in ["./a.js", "../a.js", "/work/a.js", "std"] {
+			assert!(check_specifier(specifier, &allowed, false).is_ok(), "{specifier}");
+		}
+		for specifier in ["os", "/modules/x.js", "/etc/passwd", "https://example.com/x.js", "std/x"] {
+			assert!(check_specifier(specifier, &allowed, false).is_err(), "{specifier}");
+		}
+		assert!(check_specifier("/modules/x.js", &allowed, true).is_ok());
+	}
+
+	#[test]
+	fn workspace_module_resolves_within_the_workspace() {
+		let from = Path::new(SCRIPT);
+		let nested = Path::new("lib/a.js");
+		assert_eq!(workspace_module(from, "./lib/a.js"), Some(Some(PathBuf::from("lib/a.js"))));
+		assert_eq!(workspace_module(nested, "../b.js"), Some(Some(PathBuf::from("b.js"))));
+		assert_eq!(workspace_module(nested, "./c/../d.js"), Some(Some(PathBuf::from("lib/d.js"))));
+		assert_eq!(workspace_module(from, "/work/a/./b.js"), Some(Some(PathBuf::from("a/b.js"))));
+		assert_eq!(workspace_module(nested, "../../x.js"), Some(None));
+		assert_eq!(workspace_module(from, "/work/../etc/passwd"), Some(None));
+		assert_eq!(workspace_module(from, "std"), None);
+		assert_eq!(workspace_module(from, "/modules/x.js"), None);
+	}
+
+	#[tokio::test]
+	async fn check_module_graph_follows_workspace_imports() {
+		let workspace = std::env::temp_dir().join(format!("js-imports-{}", uuid::Uuid::new_v4()));
+		fs::create_dir_all(workspace.join("lib")).await.unwrap();
+		fs::write(workspace.join("lib/a.js"), "import b from '../b.js'; export default b;").await.unwrap();
+		fs::write(workspace.join("b.js"), "import a from './lib/a.js'; import os from 'os';").await.unwrap();
+		fs::write(workspace.join("c.js"), "import x from '../../outside.js';").await.unwrap();
+		fs::write(workspace.join("d.js"), "import a from './lib/a.js'; export default a;").await.unwrap();
+
+		let check = |code: &'static str| check_module_graph(code, &workspace, &[], false);
+		let denied = check("import a from './lib/a.js';").await.unwrap_err();
+		assert!(denied.starts_with("b.js:") && denied.contains("\"os\""), "{denied}");
+		let outside = check("import c from './c.js';").await.unwrap_err();
+		assert!(outside.contains("outside the workspace"), "{outside}");
+		assert!(check("import m from './missing.js';").await.is_err());
+		assert!(check("export const x = 1;").await.is_ok());
+
+		fs::write(workspace.join("b.js"), "import a from './lib/a.js'; export default 1;").await.unwrap();
+		assert!(check("import d from './d.js';").await.is_ok());
+		let _ = fs::remove_dir_all(&workspace).await;
+	}
+}
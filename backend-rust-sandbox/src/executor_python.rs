@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Command;
+use tokio::fs;
+use tracing::info;
+
+use crate::egress::{NetworkJail, NetworkPolicy};
+use crate::secrets::ToolEnv;
+use crate::subprocess::{self, CommandSpec};
+use crate::tool_executor::{CodeException, ExecutionContext, ToolExecutionResult, ToolLanguage};
+use crate::workspace::Workspace;
+
+const DEFAULT_INTERPRETER: &str = "python3";
+const SCRIPT: &str = "main.py";
+/// Where the runner leaves the uncaught exception, in the workspace.
+const EXCEPTION_FILE: &str = ".pagi_exception.json";
+
+/// Runs `main.py` as `__main__` and, if it raises, writes the exception to
+/// `EXCEPTION_FILE` and prints its traceback without the runner's frames.
+/// `SystemExit` passes through, so `sys.exit(n)` still sets the exit code.
+const RUNNER: &str = r#"
+import json, os, runpy, sys, traceback
+script = os.path.abspath("main.py")
+sys.argv = ["main.py"] + sys.argv[1:]
+sys.path.insert(0, os.getcwd())
+try:
+    runpy.run_path("main.py", run_name="__main__")
+except SystemExit:
+    raise
+except BaseException as e:
+    tb = e.__traceback__
+    while tb is not None and os.path.abspath(tb.tb_frame.f_code.co_filename) != script:
+        tb = tb.tb_next
+    line = e.lineno if isinstance(e, SyntaxError) else None
+    frame = tb
+    while frame is not None:
+        if os.path.abspath(frame.tb_frame.f_code.co_filename) == script:
+            line = frame.tb_lineno
+        frame = frame.tb_next
+    text = "".join(traceback.format_exception(type(e), e, tb))
+    with open(".pagi_exception.json", "w") as f:
+        json.dump({
+            "type": type(e).__name__,
+            "message": str(e),
+            "line": line,
+            "syntax_error": isinstance(e, SyntaxError),
+            "traceback": text,
+        }, f)
+    sys.stderr.write(text)
+    sys.exit(1)
+"#;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PythonConfig {
+	/// Interpreter the `python` tool and `execute_code` run code with.
+	pub interpreter: PathBuf,
+	/// Version the interpreter must be, e.g. `3.12` or `3.12.4`; checked at
+	/// startup, which fails on a mismatch.
+	pub version: Option<String>,
+	/// Where the code may connect to; offline by default.
+	pub network: NetworkPolicy,
+}
+
+impl Default for PythonConfig {
+	fn default() -> Self {
+		Self {
+			interpreter: DEFAULT_INTERPRETER.into(),
+			version: None,
+			network: NetworkPolicy::None,
+		}
+	}
+}
+
+impl PythonConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if let Some(version) = &self.version {
+			let parts: Vec<&str> = version.split('.').collect();
+			if !(2..=3).contains(&parts.len()) || parts.iter().any(|p| p.parse::<u32>().is_err()) {
+				errors.push(format!(
+					"executor.python.version must look like 3.12 or 3.12.4 (got {version:?})"
+				));
+			}
+		}
+		if let Err(e) = self.network.clone().validate() {
+			errors.push(e.replace("network.allowlist", "executor.python.network.allowlist"));
+		}
+		errors
+	}
+}
+
+/// Runs Python code in the execution's workspace under the configured
+/// interpreter, in isolated mode (`-I`: no `PYTHON*` variables, user site
+/// or script directory on the path) and without writing bytecode.
+pub struct PythonRuntime {
+	config: PythonConfig,
+	network: Option<NetworkJail>,
+}
+
+impl PythonRuntime {
+	/// Fails when a pinned version does not match the interpreter's.
+	pub fn new(config: PythonConfig) -> Result<Self, String> {
+		if let Some(pinned) = &config.version {
+			let found = interpreter_version(&config)?;
+			let matches = found == *pinned || found.starts_with(&format!("{pinned}."));
+			if !matches {
+				return Err(format!(
+					"{} is Python {found}, but executor.python.version pins {pinned}",
+					config.interpreter.display()
+				));
+			}
+			info!(interpreter = %config.interpreter.display(), version = found, message = "Python interpreter checked");
+		}
+		let mut network = config.network.clone();
+		network.validate()?;
+		Ok(Self {
+			network: NetworkJail::new(&network),
+			config,
+		})
+	}
+
+	/// Write `code` to `main.py` in the workspace and run it with `argv` as
+	/// its arguments. An uncaught exception is reported in `exception`, with
+	/// status `compile_error` for a syntax error and `runtime_error` for any
+	/// other.
+	pub async fn run(
+		&self,
+		code: &str,
+		argv: &[String],
+		ctx: &ExecutionContext,
+		workspace: &Workspace,
+		env: &ToolEnv,
+	) -> ToolExecutionResult {
+		let failed = |status: &str, stderr: String| ToolExecutionResult {
+			status: status.to_string(),
+			language: ToolLanguage::Python,
+			stdout: "".to_string(),
+			stderr,
+			compile_stdout: "".to_string(),
+			compile_stderr: "".to_string(),
+			encoding: None,
+			exit_code: None,
+			truncated: None,
+			exception: None,
+		};
+		if let Err(e) = fs::write(workspace.path.join(SCRIPT), code).await {
+			return failed("io_error", format!("failed to write {SCRIPT}: {e}"));
+		}
+		let mut spec = CommandSpec::new(self.config.interpreter.to_string_lossy())
+			.arg("-I")
+			.arg("-B")
+			.arg("-c")
+			.arg(RUNNER);
+		for arg in argv {
+			spec = spec.arg(arg);
+		}
+		let spec = spec
+			.stdin(ctx.stdin())
+			.tool_env(env)
+			.env("HOME", workspace.path.to_string_lossy())
+			.env("TMPDIR", workspace.path.to_string_lossy())
+			.env("PYTHONUNBUFFERED", "1")
+			.cwd(&workspace.path)
+			.jail(workspace.jail.clone())
+			.pid_namespace(workspace.pid_namespace)
+			.network(self.network.clone())
+			.timeout(ctx.timeout)
+			.max_output(ctx.max_output_bytes)
+			.cancel(ctx.cancel.clone())
+			.processes(ctx.processes.clone())
+			.usage(ctx.usage.clone())
+			.cgroup(ctx.cgroup.clone());
+		let mut run = match subprocess::run(&spec).await {
+			Ok(run) => run,
+			Err(e) => {
+				return failed(
+					subprocess::spawn_error_status(&e),
+					format!("failed to run {}: {e}", self.config.interpreter.display()),
+				)
+			}
+		};
+		let exception_path = workspace.path.join(EXCEPTION_FILE);
+		let exception: Option<CodeException> = fs::read(&exception_path)
+			.await
+			.ok()
+			.and_then(|bytes| serde_json::from_slice(&bytes).ok());
+		let _ = fs::remove_file(&exception_path).await;
+		if !run.network_denied.is_empty() {
+			run.note(&format!(
+				"blocked by the sandbox: not allowed by executor.python.network: {}\n",
+				run.network_denied.join(", ")
+			));
+		}
+
+		let status = if run.success() {
+			"ok"
+		} else if run.interrupted() {
+			run.status()
+		} else if exception.as_ref().is_some_and(|e| e.syntax_error) {
+			"compile_error"
+		} else {
+			"runtime_error"
+		};
+		ToolExecutionResult {
+			status: status.to_string(),
+			language: ToolLanguage::Python,
+			stdout: run.stdout,
+			stderr: run.stderr,
+			compile_stdout: "".to_string(),
+			compile_stderr: "".to_string(),
+			encoding: run.encoding,
+			exit_code: run.exit_code,
+			truncated: run.truncated,
+			exception,
+		}
+	}
+}
+
+/// `major.minor.patch` of the configured interpreter.
+fn interpreter_version(config: &PythonConfig) -> Result<String, String> {
+	let output = Command::new(&config.interpreter)
+		.args(["-I", "-c", "import sys; print('%d.%d.%d' % sys.version_info[:3])"])
+		.output()
+		.map_err(|e| format!("{}: {e}", config.interpreter.display()))?;
+	if !output.status.success() {
+		return Err(format!(
+			"{} exited with {}: {}",
+			config.interpreter.display(),
+			output.status,
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,499 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWrite;
+use tokio_util::sync::{CancellationToken, PollSender};
+use tracing::{info_span, warn, Instrument};
+use wasmtime::{
+	Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap, UpdateDeadline,
+};
+use wasmtime_wasi::p1::{self, WasiP1Ctx};
+use wasmtime_wasi::cli::{IsTerminal, StdoutStream};
+use wasmtime_wasi::p2::pipe::MemoryInputPipe;
+use wasmtime_wasi::sockets::SocketAddrUse;
+use wasmtime_wasi::{Deterministic, FsPerms, HostWallClock, I32Exit, WasiCtxBuilder};
+
+use crate::egress::NetworkPolicy;
+use crate::encoding::{self, Encoding, OutputEncoding};
+use crate::registry::ToolManifest;
+use crate::secrets::ToolEnv;
+use crate::subprocess::{OutputChunk, OutputSink, StreamKind, Truncation};
+use crate::tool_executor::{ExecutionContext, ToolResult};
+
+const DEFAULT_FUEL: u64 = 1_000_000_000;
+const DEFAULT_MAX_MEMORY_MB: usize = 256;
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Default limits for WASM executions; a manifest's `limits` override them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WasmConfig {
+	/// Instruction budget; the guest traps once it is consumed.
+	pub fuel: u64,
+	/// Maximum linear memory a guest may grow to.
+	pub max_memory_mb: usize,
+}
+
+impl Default for WasmConfig {
+	fn default() -> Self {
+		Self {
+			fuel: DEFAULT_FUEL,
+			max_memory_mb: DEFAULT_MAX_MEMORY_MB,
+		}
+	}
+}
+
+struct WasmState {
+	wasi: WasiP1Ctx,
+	limits: StoreLimits,
+	/// Set by the epoch callback when it interrupts a cancelled guest.
+	cancelled: bool,
+}
+
+/// Shared wasmtime engine plus the epoch ticker that drives timeouts.
+pub struct WasmRuntime {
+	engine: Engine,
+	config: WasmConfig,
+}
+
+impl WasmRuntime {
+	pub fn new(config: WasmConfig) -> wasmtime::Result<Self> {
+		let mut engine_config = Config::new();
+		engine_config.consume_fuel(true).epoch_interruption(true);
+		let engine = Engine::new(&engine_config)?;
+
+		// A plain thread rather than a tokio task: a guest spinning inside
+		// `call_async` occupies its worker, and the ticker must never queue
+		// behind it.
+		let ticker = engine.weak();
+		std::thread::Builder::new()
+			.name("wasm-epoch".to_string())
+			.spawn(move || {
+				while let Some(engine) = ticker.upgrade() {
+					engine.increment_epoch();
+					drop(engine);
+					std::thread::sleep(EPOCH_TICK);
+				}
+			})?;
+
+		Ok(Self { engine, config })
+	}
+
+	pub fn engine(&self) -> &Engine {
+		&self.engine
+	}
+
+	/// The linear memory a guest may grow to unless its manifest says
+	/// otherwise, in MiB.
+	pub fn default_max_memory_mb(&self) -> usize {
+		self.config.max_memory_mb
+	}
+
+	/// The instruction budget of a run unless its manifest says otherwise.
+	pub fn default_fuel(&self) -> u64 {
+		self.config.fuel
+	}
+
+	/// The linear memory `tool`'s guests may grow to, in MiB.
+	pub fn max_memory_mb(&self, tool: &ToolManifest) -> usize {
+		tool.limits
+			.memory_mb
+			.map(|mb| mb as usize)
+			.unwrap_or(self.config.max_memory_mb)
+	}
+
+	/// The instruction budget of one run of `tool`.
+	pub fn fuel(&self, tool: &ToolManifest) -> u64 {
+		tool.limits.fuel.unwrap_or(self.config.fuel)
+	}
+
+	/// Run the WASI (preview1) command module of `tool`.
+	///
+	/// The guest sees the same argv contract as native tools (`argv[1]` is the
+	/// JSON args object), its environment holds only `PAGI_TOOL_NAME` and
+	/// `env`, and its stdin is the request's. Its only filesystem access is
+	/// `work_dir`, preopened read-write as `/work`, plus the manifest's
+	/// `data_dir` read-only as `/data` when set. Sockets follow the manifest's
+	/// `network` policy (none by default), checked address by address at the
+	/// WASI socket layer.
+	/// The context's timeout and cancellation are checked on every epoch
+	/// tick, where the guest also yields to the async runtime.
+	pub async fn run(
+		&self,
+		tool: &ToolManifest,
+		args: &Value,
+		work_dir: &Path,
+		env: &ToolEnv,
+		ctx: &ExecutionContext,
+	) -> ToolResult {
+		match self.try_run(tool, args, work_dir, env, ctx).await {
+			Ok(result) => result,
+			Err(e) => ToolResult {
+				status: "wasm_error".to_string(),
+				stderr: format!("{e:#}"),
+				..Default::default()
+			},
+		}
+	}
+
+	async fn try_run(
+		&self,
+		tool: &ToolManifest,
+		args: &Value,
+		work_dir: &Path,
+		env: &ToolEnv,
+		ctx: &ExecutionContext,
+	) -> wasmtime::Result<ToolResult> {
+		let module_path = Path::new(&tool.command[0]);
+		// Covers compiling the module and instantiating it in a fresh store.
+		let spawn_span = info_span!("sandbox.spawn", backend = "wasm", module = %module_path.display());
+		let module = match spawn_span.in_scope(|| Module::from_file(&self.engine, module_path)) {
+			Ok(m) => m,
+			Err(e) => {
+				return Ok(ToolResult {
+					status: "invalid_module".to_string(),
+					stderr: format!("failed to load {}: {e:#}", module_path.display()),
+					..Default::default()
+				})
+			}
+		};
+		let mut preopens = vec![(work_dir.to_path_buf(), "/work", FsPerms::ReadWrite)];
+		if let Some(data_dir) = &tool.data_dir {
+			preopens.push((data_dir.clone(), "/data", FsPerms::ReadOnly));
+		}
+		let guest = Guest {
+			name: &tool.name,
+			module,
+			argv: vec![tool.name.clone(), args.to_string()],
+			preopens,
+			network: tool.network.as_ref().unwrap_or(&NetworkPolicy::None),
+			max_memory_mb: self.max_memory_mb(tool),
+			fuel: self.fuel(tool),
+		};
+		self.run_guest(guest, env, ctx).instrument(spawn_span).await
+	}
+
+	/// Compile a module for [`WasmRuntime::run_guest`].
+	pub fn load(&self, path: &Path) -> wasmtime::Result<Module> {
+		Module::from_file(&self.engine, path)
+	}
+
+	/// Instantiate `guest` in a fresh store and run its `_start`, under the
+	/// limits and policy described on [`WasmRuntime::run`].
+	pub async fn run_guest(&self, guest: Guest<'_>, env: &ToolEnv, ctx: &ExecutionContext) -> wasmtime::Result<ToolResult> {
+		let tool_name = guest.name;
+		let stdout = CappedPipe::new(ctx.max_output_bytes);
+		let stderr = CappedPipe::new(ctx.max_output_bytes);
+		let mut wasi = wasi_builder(tool_name, &guest.argv, env, ctx, &stdout, &stderr);
+		for (host, guest_path, perms) in &guest.preopens {
+			wasi.preopened_dir(host, guest_path, *perms)?;
+		}
+		let network_denied = Arc::new(Mutex::new(Vec::new()));
+		let network = guest.network;
+		let online = *network != NetworkPolicy::None;
+		wasi.allow_tcp(online).allow_udp(online).allow_ip_name_lookup(online);
+		if let NetworkPolicy::Allowlist(domains) = network {
+			let allowed = Arc::new(resolve_allowlist(domains).await);
+			let denied = network_denied.clone();
+			wasi.socket_addr_check(move |addr, usage| {
+				let permitted = match usage {
+					SocketAddrUse::TcpConnect | SocketAddrUse::UdpSend => allowed.contains(&addr.ip()),
+					_ => true,
+				};
+				if !permitted {
+					denied.lock().unwrap().push(addr.to_string());
+				}
+				Box::pin(std::future::ready(permitted))
+			});
+		}
+
+		let limits = StoreLimitsBuilder::new()
+			.memory_size(guest.max_memory_mb * 1024 * 1024)
+			.instances(1)
+			.build();
+		let mut store = Store::new(
+			&self.engine,
+			WasmState {
+				wasi: wasi.build_p1(),
+				limits,
+				cancelled: false,
+			},
+		);
+		store.limiter(|s| &mut s.limits);
+		bound_store(&mut store, guest.fuel, ctx.timeout, ctx.cancel.clone(), |s| s.cancelled = true)?;
+
+		let mut linker: Linker<WasmState> = Linker::new(&self.engine);
+		p1::add_to_linker_async(&mut linker, |s| &mut s.wasi)?;
+		let instance = linker.instantiate_async(&mut store, &guest.module).await?;
+		let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+		let outcome = start.call_async(&mut store, ()).await;
+
+		let (status, exit_code) = match outcome {
+			Ok(()) => ("ok".to_string(), Some(0)),
+			Err(e) => {
+				if let Some(exit) = e.downcast_ref::<I32Exit>() {
+					let status = if exit.0 == 0 { "ok" } else { "non_zero_exit" };
+					(status.to_string(), Some(exit.0))
+				} else if let Some(trap) = e.downcast_ref::<Trap>() {
+					warn!(tool_name = tool_name, trap = %trap, message = "WASM guest trapped");
+					(trap_status(trap, store.data().cancelled).to_string(), None)
+				} else {
+					return Err(e);
+				}
+			}
+		};
+		let network_denied = std::mem::take(&mut *network_denied.lock().unwrap());
+		let (stdout_bytes, stderr_bytes) = (stdout.total(), stderr.total());
+		let limit = ctx.max_output_bytes as u64;
+		let truncated = (stdout_bytes > limit || stderr_bytes > limit).then_some(Truncation {
+			stdout_bytes,
+			stderr_bytes,
+		});
+		let (stdout, stdout_encoding) = stdout.contents();
+		let (mut stderr, stderr_encoding) = stderr.contents();
+		let status = if !network_denied.is_empty() && exit_code.is_some() {
+			encoding::push_note(
+				&mut stderr,
+				stderr_encoding,
+				&format!(
+					"blocked by the sandbox: not allowed by the tool's network policy: {}\n",
+					network_denied.join(", ")
+				),
+			);
+			"network_denied".to_string()
+		} else {
+			status
+		};
+
+		Ok(ToolResult {
+			status,
+			stdout,
+			stderr,
+			encoding: OutputEncoding::of(stdout_encoding, stderr_encoding),
+			exit_code,
+			truncated,
+			..Default::default()
+		})
+	}
+}
+
+/// The WASI context a guest starts from: `argv`, an environment of
+/// `PAGI_TOOL_NAME` and `env`, the request's stdin, and `stdout` and
+/// `stderr` as its output. Under a trace the guest's clock starts at the
+/// recorded time and its randomness comes from the recorded seed. It has no
+/// files and no sockets until the caller grants them.
+pub(crate) fn wasi_builder(
+	name: &str,
+	argv: &[String],
+	env: &ToolEnv,
+	ctx: &ExecutionContext,
+	stdout: &CappedPipe,
+	stderr: &CappedPipe,
+) -> WasiCtxBuilder {
+	let mut wasi = WasiCtxBuilder::new();
+	if let Some(stdin) = &ctx.input.stdin {
+		wasi.stdin(MemoryInputPipe::new(stdin.to_vec()));
+	}
+	for (key, value) in &env.vars {
+		wasi.env(key, value);
+	}
+	for (key, secret) in &env.secrets {
+		wasi.env(key, secret.expose());
+	}
+	wasi.args(argv)
+		.env("PAGI_TOOL_NAME", name)
+		.stdout(stdout.clone())
+		.stderr(stderr.clone())
+		.allow_tcp(false)
+		.allow_udp(false)
+		.allow_ip_name_lookup(false);
+	if let Some(trace) = &ctx.trace {
+		wasi.wall_clock(TracedClock {
+			start: Duration::from_millis(trace.time_ms()),
+			since: Instant::now(),
+		})
+		.secure_random(Deterministic::new(trace.random_bytes()))
+		.insecure_random(Deterministic::new(trace.random_bytes()))
+		.insecure_random_seed(u128::from(trace.seed()));
+	}
+	wasi
+}
+
+/// Give `store` a budget of `fuel` and interrupt its guest on the first
+/// epoch tick after `timeout` or after `cancel` fires, calling `cancelled`
+/// on the store's data in the latter case.
+pub(crate) fn bound_store<T: Send + 'static>(
+	store: &mut Store<T>,
+	fuel: u64,
+	timeout: Duration,
+	cancel: CancellationToken,
+	cancelled: fn(&mut T),
+) -> wasmtime::Result<()> {
+	store.set_fuel(fuel)?;
+	let deadline = Instant::now() + timeout;
+	store.epoch_deadline_callback(move |mut ctx| {
+		if cancel.is_cancelled() {
+			cancelled(ctx.data_mut());
+			return Ok(UpdateDeadline::Interrupt);
+		}
+		if Instant::now() >= deadline {
+			return Ok(UpdateDeadline::Interrupt);
+		}
+		Ok(UpdateDeadline::Yield(1))
+	});
+	store.set_epoch_deadline(1);
+	Ok(())
+}
+
+/// The status of a run that ended in `trap`.
+pub(crate) fn trap_status(trap: &Trap, cancelled: bool) -> &'static str {
+	match trap {
+		Trap::OutOfFuel => "fuel_exhausted",
+		Trap::Interrupt if cancelled => "cancelled",
+		Trap::Interrupt => "timed_out",
+		_ => "trap",
+	}
+}
+
+/// One WASI command to run: the module, what it is given and how far it
+/// may go.
+pub struct Guest<'a> {
+	/// Names the guest in logs and as `PAGI_TOOL_NAME`.
+	pub name: &'a str,
+	pub module: Module,
+	pub argv: Vec<String>,
+	/// Host directories and where the guest sees them.
+	pub preopens: Vec<(PathBuf, &'static str, FsPerms)>,
+	pub network: &'a NetworkPolicy,
+	pub max_memory_mb: usize,
+	pub fuel: u64,
+}
+
+/// A guest's stdout or stderr. Keeps the first `limit` bytes and counts the
+/// rest; writes past the limit still succeed, so the guest runs on as it
+/// would with a native tool's pipe. A streaming pipe also sends every write
+/// on as it happens, holding the guest back while the receiver is full.
+#[derive(Clone)]
+pub(crate) struct CappedPipe {
+	captured: Arc<Mutex<(Vec<u8>, u64)>>,
+	limit: usize,
+	sink: Option<(StreamKind, PollSender<OutputChunk>)>,
+}
+
+impl CappedPipe {
+	pub(crate) fn new(limit: usize) -> Self {
+		Self {
+			captured: Arc::new(Mutex::new((Vec::new(), 0))),
+			limit,
+			sink: None,
+		}
+	}
+
+	pub(crate) fn streaming(limit: usize, stream: StreamKind, sink: Option<OutputSink>) -> Self {
+		Self {
+			sink: sink.map(|tx| (stream, PollSender::new(tx))),
+			..Self::new(limit)
+		}
+	}
+
+	/// Keep what fits of `buf` and count all of it.
+	pub(crate) fn push(&self, buf: &[u8]) {
+		let mut captured = self.captured.lock().unwrap();
+		let keep = buf.len().min(self.limit.saturating_sub(captured.0.len()));
+		captured.0.extend_from_slice(&buf[..keep]);
+		captured.1 += buf.len() as u64;
+	}
+
+	/// What was kept, as text or, where it is not UTF-8, base64.
+	pub(crate) fn contents(&self) -> (String, Encoding) {
+		let captured = self.captured.lock().unwrap();
+		encoding::encode(captured.0.clone(), captured.1 > captured.0.len() as u64)
+	}
+
+	pub(crate) fn total(&self) -> u64 {
+		self.captured.lock().unwrap().1
+	}
+}
+
+impl IsTerminal for CappedPipe {
+	fn is_terminal(&self) -> bool {
+		false
+	}
+}
+
+impl StdoutStream for CappedPipe {
+	fn async_stream(&self) -> Box<dyn AsyncWrite + Send + Sync> {
+		Box::new(self.clone())
+	}
+}
+
+impl AsyncWrite for CappedPipe {
+	fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+		let this = self.get_mut();
+		if let Some((stream, tx)) = &mut this.sink {
+			match tx.poll_reserve(cx) {
+				Poll::Pending => return Poll::Pending,
+				Poll::Ready(Ok(())) => {
+					let _ = tx.send_item(OutputChunk {
+						stream: *stream,
+						data: buf.to_vec(),
+					});
+				}
+				// The receiver is gone; capture goes on.
+				Poll::Ready(Err(_)) => this.sink = None,
+			}
+		}
+		this.push(buf);
+		Poll::Ready(Ok(buf.len()))
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+}
+
+/// The addresses a WASM guest under an allowlist may connect to: what the
+/// listed names resolve to when the execution starts, plus listed IP
+/// literals. Wildcard entries cannot be resolved up front and apply to native
+/// tools only.
+async fn resolve_allowlist(domains: &[String]) -> HashSet<IpAddr> {
+	let mut allowed = HashSet::new();
+	for domain in domains {
+		if let Ok(ip) = domain.parse::<IpAddr>() {
+			allowed.insert(ip);
+		} else if !domain.starts_with("*.") {
+			match tokio::net::lookup_host((domain.as_str(), 0)).await {
+				Ok(addrs) => allowed.extend(addrs.map(|a| a.ip())),
+				Err(e) => warn!(domain = %domain, error = %e, message = "Could not resolve allowlisted host"),
+			}
+		}
+	}
+	allowed
+}
+
+/// A traced guest's wall clock: the recorded start time, running on from
+/// when the guest was set up.
+struct TracedClock {
+	start: Duration,
+	since: Instant,
+}
+
+impl HostWallClock for TracedClock {
+	fn resolution(&self) -> Duration {
+		Duration::from_nanos(1)
+	}
+
+	fn now(&self) -> Duration {
+		self.start + self.since.elapsed()
+	}
+}
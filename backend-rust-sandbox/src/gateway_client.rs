@@ -0,0 +1,395 @@
+use axum::http::{header, HeaderMap, Uri};
+use hyper_util::rt::TokioIo;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{PrivateKeyDer, ServerName};
+use rustls::RootCertStore;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tonic::codec::CompressionEncoding;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Response, Status};
+use tracing::warn;
+
+use crate::compression::GrpcCompression;
+use crate::request_id;
+use crate::retry::RetryPolicy;
+use crate::secrets::Secret;
+use crate::tls::read_certs;
+use crate::tool_service::proto::model_gateway_client::ModelGatewayClient;
+use crate::tool_service::proto::{PlanRequest, PlanResponse, RagContextRequest, RagContextResponse};
+
+const DEFAULT_CONNECTIONS: usize = 2;
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INITIAL_BACKOFF_MS: u64 = 100;
+const DEFAULT_MAX_BACKOFF_MS: u64 = 2_000;
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+/// The gateway logs calls under this metadata key.
+const TRACE_ID_KEY: &str = "x-trace-id";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GatewayConfig {
+	/// The model gateway's gRPC endpoint, `http://` or `https://`. Tools
+	/// have no gateway client while it is unset.
+	pub url: Option<String>,
+	/// Connections kept open to the gateway; calls take them in turn.
+	pub connections: usize,
+	pub connect_timeout_ms: u64,
+	/// Longest a call may take, retries included. Calls from an execution
+	/// with less time left get only that.
+	pub timeout_ms: u64,
+	/// Attempts per call, the first included. Only `UNAVAILABLE` is retried.
+	pub max_attempts: u32,
+	pub initial_backoff_ms: u64,
+	pub max_backoff_ms: u64,
+	/// Pass the `Authorization` or `X-Api-Key` the execution's caller
+	/// presented on to the gateway.
+	pub forward_auth: bool,
+	/// Encoding of requests to the gateway, which must accept it. Its
+	/// answers may be gzip or zstd either way.
+	pub compression: GrpcCompression,
+	/// The client side of TLS, for `https://` URLs.
+	pub tls: GatewayTlsConfig,
+}
+
+impl Default for GatewayConfig {
+	fn default() -> Self {
+		Self {
+			url: None,
+			connections: DEFAULT_CONNECTIONS,
+			connect_timeout_ms: DEFAULT_CONNECT_TIMEOUT_MS,
+			timeout_ms: DEFAULT_TIMEOUT_MS,
+			max_attempts: DEFAULT_MAX_ATTEMPTS,
+			initial_backoff_ms: DEFAULT_INITIAL_BACKOFF_MS,
+			max_backoff_ms: DEFAULT_MAX_BACKOFF_MS,
+			forward_auth: false,
+			compression: GrpcCompression::None,
+			tls: GatewayTlsConfig::default(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GatewayTlsConfig {
+	/// PEM CA certificates the gateway's certificate is verified against.
+	pub ca_path: Option<PathBuf>,
+	/// PEM client certificate chain and key, for gateways that want mTLS.
+	pub cert_path: Option<PathBuf>,
+	pub key_path: Option<PathBuf>,
+	/// The name the gateway's certificate is checked for, when it is not the
+	/// URL's host.
+	pub server_name: Option<String>,
+}
+
+impl GatewayConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		let Some(url) = &self.url else {
+			return errors;
+		};
+		match url.parse::<Uri>() {
+			Ok(uri) if uri.host().is_some() && matches!(uri.scheme_str(), Some("http" | "https")) => {
+				if uri.scheme_str() == Some("https") && self.tls.ca_path.is_none() {
+					errors.push("executor.gateway.tls.ca_path is required for an https:// url".to_string());
+				}
+			}
+			_ => errors.push(format!("executor.gateway.url must be an http:// or https:// URL (got {url:?})")),
+		}
+		if self.tls.cert_path.is_some() != self.tls.key_path.is_some() {
+			errors.push("executor.gateway.tls.cert_path and key_path must be set together".to_string());
+		}
+		if self.connections == 0 {
+			errors.push("executor.gateway.connections must be at least 1".to_string());
+		}
+		if self.timeout_ms == 0 {
+			errors.push("executor.gateway.timeout_ms must be positive".to_string());
+		}
+		if let Err(e) = self.retry_policy().validate() {
+			errors.push(format!("executor.gateway: {e}"));
+		}
+		errors
+	}
+
+	fn retry_policy(&self) -> RetryPolicy {
+		RetryPolicy {
+			max_attempts: self.max_attempts,
+			initial_backoff_ms: self.initial_backoff_ms,
+			max_backoff_ms: self.max_backoff_ms,
+			retry_on: Vec::new(),
+			..RetryPolicy::default()
+		}
+	}
+}
+
+/// The credentials a caller presented, kept for the gateway calls its
+/// execution makes.
+#[derive(Debug, Clone)]
+pub struct ForwardedAuth {
+	key: &'static str,
+	value: Secret,
+}
+
+impl ForwardedAuth {
+	/// The request's `Authorization`, else its `X-Api-Key`.
+	pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+		[header::AUTHORIZATION.as_str(), "x-api-key"].into_iter().find_map(|key| {
+			let value = headers.get(key)?.to_str().ok()?;
+			Some(Self {
+				key,
+				value: Secret::new(value),
+			})
+		})
+	}
+
+	/// The gRPC counterpart of [`ForwardedAuth::from_headers`].
+	pub fn from_metadata(metadata: &MetadataMap) -> Option<Self> {
+		["authorization", "x-api-key"].into_iter().find_map(|key| {
+			let value = metadata.get(key)?.to_str().ok()?;
+			Some(Self {
+				key,
+				value: Secret::new(value),
+			})
+		})
+	}
+}
+
+/// A pooled, retrying client for the model gateway, shared by every
+/// execution.
+#[derive(Debug)]
+pub struct GatewayClient {
+	channels: Vec<Channel>,
+	next: AtomicUsize,
+	timeout: Duration,
+	retry: RetryPolicy,
+	forward_auth: bool,
+	compression: GrpcCompression,
+}
+
+impl GatewayClient {
+	/// The client `config` describes, or `None` when no URL is set.
+	/// Connections are opened on first use and reopened when they drop.
+	pub fn new(config: &GatewayConfig) -> Result<Option<Self>, String> {
+		let Some(url) = &config.url else {
+			return Ok(None);
+		};
+		let endpoint = Endpoint::from_shared(url.clone())
+			.map_err(|e| format!("executor.gateway.url: {e}"))?
+			.connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+			.tcp_nodelay(true)
+			.http2_keep_alive_interval(KEEPALIVE_INTERVAL)
+			.keep_alive_while_idle(true);
+		let tls = match endpoint.uri().scheme_str() {
+			Some("https") => Some(tls_connector(&config.tls)?),
+			_ => None,
+		};
+		let channels = (0..config.connections.max(1))
+			.map(|_| match &tls {
+				Some(tls) => endpoint.connect_with_connector_lazy(tls.clone()),
+				None => endpoint.connect_lazy(),
+			})
+			.collect();
+		Ok(Some(Self {
+			channels,
+			next: AtomicUsize::new(0),
+			timeout: Duration::from_millis(config.timeout_ms),
+			retry: config.retry_policy(),
+			forward_auth: config.forward_auth,
+			compression: config.compression,
+		}))
+	}
+
+	/// A stub on the next connection in turn.
+	fn stub(&self) -> ModelGatewayClient<Channel> {
+		let i = self.next.fetch_add(1, Ordering::Relaxed) % self.channels.len();
+		let mut stub = ModelGatewayClient::new(self.channels[i].clone())
+			.accept_compressed(CompressionEncoding::Gzip)
+			.accept_compressed(CompressionEncoding::Zstd);
+		if let Some(encoding) = self.compression.encoding() {
+			stub = stub.send_compressed(encoding);
+		}
+		stub
+	}
+
+	/// Calls on behalf of one execution: they end by `deadline`, carry its
+	/// request id, and its caller's credentials when those are forwarded.
+	pub fn scoped<'a>(
+		&'a self,
+		deadline: Instant,
+		request_id: Option<&'a str>,
+		auth: Option<&'a ForwardedAuth>,
+	) -> GatewayCall<'a> {
+		GatewayCall {
+			client: self,
+			deadline,
+			request_id,
+			auth: auth.filter(|_| self.forward_auth),
+		}
+	}
+}
+
+/// An execution's handle on the [`GatewayClient`].
+pub struct GatewayCall<'a> {
+	client: &'a GatewayClient,
+	deadline: Instant,
+	request_id: Option<&'a str>,
+	auth: Option<&'a ForwardedAuth>,
+}
+
+impl GatewayCall<'_> {
+	/// Have the gateway's model answer a prompt.
+	pub async fn plan(&self, message: PlanRequest) -> Result<PlanResponse, Status> {
+		self.call(message, |mut client, request| async move { client.get_plan(request).await })
+			.await
+	}
+
+	/// Look up knowledge-base passages matching a query.
+	pub async fn rag_context(&self, message: RagContextRequest) -> Result<RagContextResponse, Status> {
+		self.call(message, |mut client, request| async move { client.get_rag_context(request).await })
+			.await
+	}
+
+	/// Send `message` until it is answered, fails for good, runs out of
+	/// attempts or the deadline passes. Each attempt tells the gateway how
+	/// long is left, so it stops working on calls nobody waits for.
+	async fn call<T, R, F, Fut>(&self, message: T, send: F) -> Result<R, Status>
+	where
+		T: Clone,
+		F: Fn(ModelGatewayClient<Channel>, Request<T>) -> Fut,
+		Fut: Future<Output = Result<Response<R>, Status>>,
+	{
+		let deadline = self.deadline.min(Instant::now() + self.client.timeout);
+		let mut attempt = 1;
+		loop {
+			let left = deadline.saturating_duration_since(Instant::now());
+			if left.is_zero() {
+				return Err(Status::deadline_exceeded("the deadline passed before the model gateway answered"));
+			}
+			let request = self.request(message.clone(), left);
+			let client = self.client.stub();
+			let status = match tokio::time::timeout(left, send(client, request)).await {
+				Ok(Ok(response)) => return Ok(response.into_inner()),
+				// The channel ends calls past their `grpc-timeout` as cancelled.
+				Ok(Err(status)) if status.code() == Code::Cancelled && Instant::now() >= deadline => {
+					return Err(Status::deadline_exceeded("the model gateway did not answer in time"))
+				}
+				Ok(Err(status)) => status,
+				Err(_) => return Err(Status::deadline_exceeded("the model gateway did not answer in time")),
+			};
+			let backoff = self.client.retry.backoff(attempt);
+			let retry = status.code() == Code::Unavailable
+				&& attempt < self.client.retry.max_attempts
+				&& Instant::now() + backoff < deadline;
+			if !retry {
+				return Err(status);
+			}
+			warn!(
+				attempt,
+				backoff_ms = backoff.as_millis() as u64,
+				error = %status.message(),
+				message = "Model gateway unavailable, retrying"
+			);
+			tokio::time::sleep(backoff).await;
+			attempt += 1;
+		}
+	}
+
+	fn request<T>(&self, message: T, timeout: Duration) -> Request<T> {
+		let mut request = Request::new(message);
+		request.set_timeout(timeout);
+		let metadata = request.metadata_mut();
+		if let Some(id) = self.request_id.and_then(|id| MetadataValue::try_from(id).ok()) {
+			metadata.insert(request_id::HEADER, id.clone());
+			metadata.insert(TRACE_ID_KEY, id);
+		}
+		if let Some(auth) = self.auth {
+			if let Ok(mut value) = MetadataValue::try_from(auth.value.expose()) {
+				value.set_sensitive(true);
+				metadata.insert(auth.key, value);
+			}
+		}
+		request
+	}
+}
+
+/// Connects to `https://` gateways, verifying them against the configured
+/// CAs and presenting the client certificate if there is one.
+#[derive(Clone)]
+struct Tls {
+	connector: TlsConnector,
+	server_name: Option<ServerName<'static>>,
+}
+
+fn tls_connector(config: &GatewayTlsConfig) -> Result<Tls, String> {
+	let Some(ca_path) = &config.ca_path else {
+		return Err("executor.gateway.tls.ca_path is required for an https:// url".to_string());
+	};
+	let mut roots = RootCertStore::empty();
+	for cert in read_certs(ca_path)? {
+		roots.add(cert).map_err(|e| format!("executor.gateway.tls.ca_path {}: {e}", ca_path.display()))?;
+	}
+	let builder = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+		.with_safe_default_protocol_versions()
+		.map_err(|e| e.to_string())?
+		.with_root_certificates(roots);
+	let mut client_config = match (&config.cert_path, &config.key_path) {
+		(Some(cert_path), Some(key_path)) => {
+			let key = PrivateKeyDer::from_pem_file(key_path)
+				.map_err(|e| format!("executor.gateway.tls.key_path {}: {e}", key_path.display()))?;
+			builder
+				.with_client_auth_cert(read_certs(cert_path)?, key)
+				.map_err(|e| format!("executor.gateway.tls.cert_path {}: {e}", cert_path.display()))?
+		}
+		_ => builder.with_no_client_auth(),
+	};
+	client_config.alpn_protocols = vec![b"h2".to_vec()];
+	let server_name = config
+		.server_name
+		.as_deref()
+		.map(|name| {
+			ServerName::try_from(name.to_string())
+				.map_err(|e| format!("executor.gateway.tls.server_name {name:?}: {e}"))
+		})
+		.transpose()?;
+	Ok(Tls {
+		connector: TlsConnector::from(Arc::new(client_config)),
+		server_name,
+	})
+}
+
+impl tower::Service<Uri> for Tls {
+	type Response = TokioIo<tokio_rustls::client::TlsStream<TcpStream>>;
+	type Error = std::io::Error;
+	type Future = std::pin::Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+	fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+		std::task::Poll::Ready(Ok(()))
+	}
+
+	fn call(&mut self, uri: Uri) -> Self::Future {
+		let tls = self.clone();
+		Box::pin(async move {
+			let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, message.to_string());
+			let host = uri.host().ok_or_else(|| invalid("the gateway URL has no host"))?;
+			let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+			let port = uri.port_u16().unwrap_or(443);
+			let server_name = match tls.server_name {
+				Some(name) => name,
+				None => ServerName::try_from(host.clone()).map_err(|_| invalid("the gateway host is not a valid name"))?,
+			};
+			let tcp = TcpStream::connect((host.as_str(), port)).await?;
+			tcp.set_nodelay(true)?;
+			let stream = tls.connector.connect(server_name, tcp).await?;
+			Ok(TokioIo::new(stream))
+		})
+	}
+}
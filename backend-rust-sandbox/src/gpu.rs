@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::ffi::{c_char, c_uint, c_void, CStr, CString};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+use crate::cgroup::IsolationMode;
+use crate::metrics::METRICS;
+
+const DEFAULT_LIBRARY: &str = "libnvidia-ml.so.1";
+const DEFAULT_SAMPLE_INTERVAL_MS: u64 = 5000;
+
+type NvmlReturn = libc::c_int;
+const NVML_SUCCESS: NvmlReturn = 0;
+/// `NVML_DEVICE_UUID_V2_BUFFER_SIZE`.
+const UUID_BUFFER: usize = 96;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpuConfig {
+	/// Whether tools get GPUs handed out; `auto` runs without when there is
+	/// no NVML, which leaves tools that request one unable to run.
+	pub mode: IsolationMode,
+	/// The NVML library, loaded at startup.
+	pub library: String,
+	/// NVML indices of the devices to hand out; every device when empty.
+	pub devices: Vec<u32>,
+	/// How often device utilization is sampled for the metrics.
+	pub sample_interval_ms: u64,
+}
+
+impl Default for GpuConfig {
+	fn default() -> Self {
+		Self {
+			mode: IsolationMode::Off,
+			library: DEFAULT_LIBRARY.to_string(),
+			devices: Vec::new(),
+			sample_interval_ms: DEFAULT_SAMPLE_INTERVAL_MS,
+		}
+	}
+}
+
+impl GpuConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if self.sample_interval_ms == 0 {
+			errors.push("executor.gpu.sample_interval_ms must be positive".to_string());
+		}
+		let unique: BTreeSet<_> = self.devices.iter().collect();
+		if unique.len() != self.devices.len() {
+			errors.push("executor.gpu.devices lists a device twice".to_string());
+		}
+		errors
+	}
+}
+
+/// `nvmlDevice_t`. NVML's calls are thread-safe, so handles may be used from
+/// any thread.
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+struct NvmlDevice(*mut c_void);
+
+// SAFETY: see above; a handle stays valid until NVML is shut down, which the
+// sandbox never does.
+unsafe impl Send for NvmlDevice {}
+unsafe impl Sync for NvmlDevice {}
+
+/// `nvmlUtilization_t`: percentages over the driver's last sample period.
+/// NVML fills in all of it; the sandbox reads what it exports.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Default)]
+struct Utilization {
+	gpu: c_uint,
+	memory: c_uint,
+}
+
+/// `nvmlMemory_t`, in bytes.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Default)]
+struct Memory {
+	total: u64,
+	free: u64,
+	used: u64,
+}
+
+/// The NVML entry points the sandbox uses, resolved from the library at
+/// runtime so that hosts without the driver need nothing installed.
+struct Nvml {
+	init: unsafe extern "C" fn() -> NvmlReturn,
+	device_count: unsafe extern "C" fn(*mut c_uint) -> NvmlReturn,
+	device_by_index: unsafe extern "C" fn(c_uint, *mut NvmlDevice) -> NvmlReturn,
+	uuid: unsafe extern "C" fn(NvmlDevice, *mut c_char, c_uint) -> NvmlReturn,
+	utilization: unsafe extern "C" fn(NvmlDevice, *mut Utilization) -> NvmlReturn,
+	memory: unsafe extern "C" fn(NvmlDevice, *mut Memory) -> NvmlReturn,
+	error_string: unsafe extern "C" fn(NvmlReturn) -> *const c_char,
+}
+
+impl Nvml {
+	fn load(library: &str) -> Result<Self, String> {
+		let name = CString::new(library).map_err(|_| format!("{library:?} is not a library name"))?;
+		// SAFETY: NUL-terminated names that outlive the calls. The library is
+		// never closed, so the resolved functions stay valid; their types
+		// are NVML's declarations.
+		unsafe {
+			let lib = libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+			if lib.is_null() {
+				let error = libc::dlerror();
+				return Err(match error.is_null() {
+					true => format!("cannot load {library}"),
+					false => CStr::from_ptr(error).to_string_lossy().to_string(),
+				});
+			}
+			Ok(Self {
+				init: symbol(lib, "nvmlInit_v2")?,
+				device_count: symbol(lib, "nvmlDeviceGetCount_v2")?,
+				device_by_index: symbol(lib, "nvmlDeviceGetHandleByIndex_v2")?,
+				uuid: symbol(lib, "nvmlDeviceGetUUID")?,
+				utilization: symbol(lib, "nvmlDeviceGetUtilizationRates")?,
+				memory: symbol(lib, "nvmlDeviceGetMemoryInfo")?,
+				error_string: symbol(lib, "nvmlErrorString")?,
+			})
+		}
+	}
+
+	fn check(&self, call: &str, rc: NvmlReturn) -> Result<(), String> {
+		if rc == NVML_SUCCESS {
+			return Ok(());
+		}
+		// SAFETY: NVML returns a static string for any code.
+		let message = unsafe { CStr::from_ptr((self.error_string)(rc)) };
+		Err(format!("{call}: {}", message.to_string_lossy()))
+	}
+}
+
+/// `name` from `lib`, as a function of type `F`.
+unsafe fn symbol<F: Copy>(lib: *mut c_void, name: &str) -> Result<F, String> {
+	let cname = CString::new(name).expect("symbol names have no NUL bytes");
+	let function = libc::dlsym(lib, cname.as_ptr());
+	if function.is_null() {
+		return Err(format!("the NVML library has no {name}"));
+	}
+	Ok(std::mem::transmute_copy(&function))
+}
+
+struct Device {
+	/// NVML's index, which labels the metrics.
+	index: String,
+	/// What `CUDA_VISIBLE_DEVICES` names it by; CUDA may number devices
+	/// differently from NVML, but UUIDs are the same to both.
+	uuid: String,
+	handle: NvmlDevice,
+}
+
+/// The host's GPUs, handed to executions whose tool asks for them.
+///
+/// An execution waits until as many devices as its tool asks for are free,
+/// in the order executions asked, and holds them for its whole run; tools
+/// see only the devices they hold.
+pub struct Gpus {
+	nvml: Nvml,
+	devices: Vec<Device>,
+	/// Positions in `devices` nobody holds.
+	free: Mutex<BTreeSet<usize>>,
+	/// One permit per free device; waiting on it is waiting in line.
+	permits: Arc<Semaphore>,
+}
+
+impl Gpus {
+	/// Load NVML and find the devices according to `config.mode`. `Ok(None)`
+	/// means no GPUs are handed out.
+	pub fn init(config: &GpuConfig) -> Result<Option<Arc<Self>>, String> {
+		if config.mode == IsolationMode::Off {
+			return Ok(None);
+		}
+		match Self::setup(config) {
+			Ok(gpus) => {
+				let uuids: Vec<&str> = gpus.devices.iter().map(|d| d.uuid.as_str()).collect();
+				info!(devices = ?uuids, message = "Scheduling GPUs for the tools that request them");
+				let gpus = Arc::new(gpus);
+				tokio::spawn(sample(gpus.clone(), Duration::from_millis(config.sample_interval_ms)));
+				Ok(Some(gpus))
+			}
+			Err(e) if config.mode == IsolationMode::Auto => {
+				warn!(error = %e, message = "GPUs unavailable; tools that request one cannot run");
+				Ok(None)
+			}
+			Err(e) => Err(format!("GPU setup failed: {e}")),
+		}
+	}
+
+	fn setup(config: &GpuConfig) -> Result<Self, String> {
+		let nvml = Nvml::load(&config.library)?;
+		// SAFETY: NVML calls with out-pointers to locals that outlive them.
+		let devices = unsafe {
+			nvml.check("nvmlInit", (nvml.init)())?;
+			let mut count: c_uint = 0;
+			nvml.check("nvmlDeviceGetCount", (nvml.device_count)(&mut count))?;
+			let indices: Vec<u32> = match config.devices.is_empty() {
+				true => (0..count).collect(),
+				false => config.devices.clone(),
+			};
+			let mut devices = Vec::new();
+			for index in indices {
+				if index >= count {
+					return Err(format!("device {index} is configured, but NVML finds {count} devices"));
+				}
+				let mut handle = NvmlDevice(std::ptr::null_mut());
+				nvml.check("nvmlDeviceGetHandleByIndex", (nvml.device_by_index)(index, &mut handle))?;
+				let mut uuid = [0 as c_char; UUID_BUFFER];
+				nvml.check("nvmlDeviceGetUUID", (nvml.uuid)(handle, uuid.as_mut_ptr(), UUID_BUFFER as c_uint))?;
+				devices.push(Device {
+					index: index.to_string(),
+					uuid: CStr::from_ptr(uuid.as_ptr()).to_string_lossy().to_string(),
+					handle,
+				});
+			}
+			devices
+		};
+		if devices.is_empty() {
+			return Err("NVML finds no devices".to_string());
+		}
+		Ok(Self {
+			nvml,
+			free: Mutex::new((0..devices.len()).collect()),
+			permits: Arc::new(Semaphore::new(devices.len())),
+			devices,
+		})
+	}
+
+	/// Devices there are to hand out.
+	pub fn count(&self) -> usize {
+		self.devices.len()
+	}
+
+	/// Devices nobody holds.
+	pub fn free(&self) -> usize {
+		self.free.lock().unwrap().len()
+	}
+
+	/// `count` devices, once they are free.
+	pub async fn acquire(self: &Arc<Self>, count: u32) -> GpuLease {
+		let permit = match count {
+			0 => None,
+			count => {
+				METRICS.gpu_waiting.inc();
+				let permit = self.permits.clone().acquire_many_owned(count).await;
+				METRICS.gpu_waiting.dec();
+				Some(permit.expect("the semaphore is never closed"))
+			}
+		};
+		let held: Vec<usize> = {
+			let mut free = self.free.lock().unwrap();
+			(0..count).filter_map(|_| free.pop_first()).collect()
+		};
+		METRICS.gpus_in_use.add(held.len() as i64);
+		GpuLease {
+			gpus: self.clone(),
+			held,
+			_permit: permit,
+		}
+	}
+}
+
+/// Devices held by one execution, given back when dropped.
+pub struct GpuLease {
+	gpus: Arc<Gpus>,
+	held: Vec<usize>,
+	_permit: Option<OwnedSemaphorePermit>,
+}
+
+impl GpuLease {
+	/// The devices' UUIDs, as `CUDA_VISIBLE_DEVICES` takes them; none for a
+	/// tool that asked for no GPU.
+	pub fn devices(&self) -> Vec<String> {
+		self.held.iter().map(|&i| self.gpus.devices[i].uuid.clone()).collect()
+	}
+}
+
+impl Drop for GpuLease {
+	fn drop(&mut self) {
+		// The devices are back before the permits are.
+		self.gpus.free.lock().unwrap().extend(&self.held);
+		METRICS.gpus_in_use.sub(self.held.len() as i64);
+	}
+}
+
+/// Export each device's utilization and memory use, for as long as the
+/// sandbox runs.
+async fn sample(gpus: Arc<Gpus>, interval: Duration) {
+	let mut tick = tokio::time::interval(interval);
+	loop {
+		tick.tick().await;
+		for device in &gpus.devices {
+			let mut utilization = Utilization::default();
+			let mut memory = Memory::default();
+			// SAFETY: NVML calls with out-pointers to locals that outlive them.
+			let sampled = unsafe {
+				gpus.nvml
+					.check("nvmlDeviceGetUtilizationRates", (gpus.nvml.utilization)(device.handle, &mut utilization))
+					.and_then(|()| {
+						gpus.nvml
+							.check("nvmlDeviceGetMemoryInfo", (gpus.nvml.memory)(device.handle, &mut memory))
+					})
+			};
+			match sampled {
+				Ok(()) => {
+					let labels = [device.index.as_str()];
+					METRICS.gpu_utilization.with_label_values(&labels).set(utilization.gpu as f64 / 100.0);
+					METRICS.gpu_memory_used_bytes.with_label_values(&labels).set(memory.used as f64);
+				}
+				Err(e) => warn!(device = device.uuid, error = %e, message = "Failed to sample a GPU"),
+			}
+		}
+	}
+}
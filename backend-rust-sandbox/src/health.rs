@@ -0,0 +1,181 @@
+use axum::http::StatusCode;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+use crate::auth::Authenticator;
+use crate::tool_executor::Executor;
+
+/// Probes may come every few seconds; the container runtime and Firecracker
+/// are asked at most this often.
+const RUNTIME_CHECK_TTL: Duration = Duration::from_secs(10);
+
+/// How often the gRPC health statuses are brought up to date.
+const GRPC_HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+	Ok,
+	Fail,
+	/// Not applicable to this deployment (e.g. no container tools).
+	Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+	pub status: CheckStatus,
+	pub detail: Value,
+}
+
+impl Check {
+	fn new(status: CheckStatus, detail: Value) -> Self {
+		Self { status, detail }
+	}
+}
+
+/// Answers `GET /readyz`: whether this instance should receive traffic.
+pub struct Readiness {
+	executor: Arc<Executor>,
+	auth: Arc<Authenticator>,
+	shutdown: CancellationToken,
+	runtime_check: Mutex<Option<(Instant, Check)>>,
+	firecracker_check: Mutex<Option<(Instant, Check)>>,
+}
+
+impl Readiness {
+	pub fn new(executor: Arc<Executor>, auth: Arc<Authenticator>, shutdown: CancellationToken) -> Self {
+		Self {
+			executor,
+			auth,
+			shutdown,
+			runtime_check: Mutex::new(None),
+			firecracker_check: Mutex::new(None),
+		}
+	}
+
+	/// Run every check; ready only if none failed.
+	pub async fn check(&self) -> (StatusCode, Value) {
+		let mut checks = BTreeMap::new();
+
+		let in_flight = self.executor.in_flight();
+		let queued = self.executor.queued();
+		checks.insert(
+			"executor",
+			if self.shutdown.is_cancelled() || self.executor.draining() {
+				Check::new(
+					CheckStatus::Fail,
+					json!({ "draining": true, "in_flight": in_flight, "queued": queued }),
+				)
+			} else {
+				Check::new(CheckStatus::Ok, json!({ "in_flight": in_flight, "queued": queued }))
+			},
+		);
+
+		let registry = self.executor.registry();
+		checks.insert(
+			"registry",
+			Check::new(CheckStatus::Ok, json!({ "tools": registry.len() })),
+		);
+
+		checks.insert("container_runtime", self.container_runtime().await);
+		checks.insert("firecracker", self.firecracker().await);
+
+		checks.insert(
+			"jwks",
+			match self.auth.jwks_key_count() {
+				None => Check::new(CheckStatus::Skipped, json!("no JWKS URL configured")),
+				Some(0) => Check::new(CheckStatus::Fail, json!("no signing keys loaded yet")),
+				Some(keys) => Check::new(CheckStatus::Ok, json!({ "keys": keys })),
+			},
+		);
+
+		let ready = checks.values().all(|c| c.status != CheckStatus::Fail);
+		let code = if ready {
+			StatusCode::OK
+		} else {
+			StatusCode::SERVICE_UNAVAILABLE
+		};
+		let status = if ready { "ready" } else { "not_ready" };
+		(code, json!({ "status": status, "checks": checks }))
+	}
+
+	/// Whether every check passes, for probes that need no details.
+	pub async fn is_ready(&self) -> bool {
+		self.check().await.0 == StatusCode::OK
+	}
+
+	async fn container_runtime(&self) -> Check {
+		if let Some((at, check)) = &*self.runtime_check.lock().unwrap() {
+			if at.elapsed() < RUNTIME_CHECK_TTL {
+				return check.clone();
+			}
+		}
+		let check = match self.executor.check_container_runtime().await {
+			None => Check::new(CheckStatus::Skipped, json!("no container tools registered")),
+			Some(Ok(version)) => Check::new(CheckStatus::Ok, json!({ "version": version })),
+			Some(Err(e)) => Check::new(CheckStatus::Fail, json!({ "error": e })),
+		};
+		*self.runtime_check.lock().unwrap() = Some((Instant::now(), check.clone()));
+		check
+	}
+
+	async fn firecracker(&self) -> Check {
+		if let Some((at, check)) = &*self.firecracker_check.lock().unwrap() {
+			if at.elapsed() < RUNTIME_CHECK_TTL {
+				return check.clone();
+			}
+		}
+		let check = match self.executor.check_firecracker().await {
+			None => Check::new(CheckStatus::Skipped, json!("no firecracker tools registered")),
+			Some(Ok(detail)) => Check::new(CheckStatus::Ok, detail),
+			Some(Err(e)) => Check::new(CheckStatus::Fail, json!({ "error": e })),
+		};
+		*self.firecracker_check.lock().unwrap() = Some((Instant::now(), check.clone()));
+		check
+	}
+}
+
+/// Keep `reporter`'s `grpc.health.v1.Health` statuses for the empty service
+/// name and each of `services` in step with `readiness`: `SERVING` while the
+/// instance is ready, `NOT_SERVING` while a check fails or shutdown is
+/// draining. On shutdown the statuses are cleared, which ends `Watch` streams
+/// so they do not hold up the server's drain.
+pub fn report_grpc_health(
+	readiness: Arc<Readiness>,
+	mut reporter: HealthReporter,
+	services: Vec<&'static str>,
+	shutdown: CancellationToken,
+) {
+	let names: Vec<&str> = std::iter::once("").chain(services).collect();
+	tokio::spawn(async move {
+		let mut last = None;
+		loop {
+			let status = if readiness.is_ready().await {
+				ServingStatus::Serving
+			} else {
+				ServingStatus::NotServing
+			};
+			// Watchers hear of every update, so only changes are made.
+			if last != Some(status) {
+				last = Some(status);
+				for name in &names {
+					reporter.set_service_status(name, status).await;
+				}
+			}
+			tokio::select! {
+				_ = tokio::time::sleep(GRPC_HEALTH_INTERVAL) => {}
+				_ = shutdown.cancelled() => break,
+			}
+		}
+		for name in &names {
+			reporter.set_service_status(name, ServingStatus::NotServing).await;
+			reporter.clear_service_status(name).await;
+		}
+	});
+}
@@ -0,0 +1,435 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use lru::LruCache;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::audit::{args_sha256, Caller};
+use crate::error::SandboxError;
+use crate::metrics::METRICS;
+use crate::workspace::ToolInput;
+
+const DEFAULT_TTL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_CAPACITY: usize = 10_000;
+const DEFAULT_MAX_ENTRY_BYTES: usize = 1024 * 1024;
+const REDIS_KEY_PREFIX: &str = "pagi-sandbox:idempotency:";
+const REDIS_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest `Idempotency-Key` accepted.
+const MAX_KEY_LEN: usize = 255;
+/// How long past the longest execution another instance's claim is
+/// honoured, in case the instance died without releasing it.
+const CLAIM_GRACE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdempotencyConfig {
+	/// Honour `Idempotency-Key` on execution requests.
+	pub enabled: bool,
+	/// How long a key's result is kept for retries.
+	pub ttl_secs: u64,
+	/// Keys remembered by the in-memory store.
+	pub capacity: usize,
+	/// Shared store for every replica instead of the in-memory one, so a
+	/// retry that reaches another instance is answered too.
+	pub redis_url: Option<String>,
+	/// Responses bigger than this are not kept; a retry of one is refused
+	/// instead of running the tool again.
+	pub max_entry_bytes: usize,
+}
+
+impl Default for IdempotencyConfig {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			ttl_secs: DEFAULT_TTL_SECS,
+			capacity: DEFAULT_CAPACITY,
+			redis_url: None,
+			max_entry_bytes: DEFAULT_MAX_ENTRY_BYTES,
+		}
+	}
+}
+
+impl IdempotencyConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if let Some(url) = &self.redis_url {
+			if !url.starts_with("redis://") && !url.starts_with("rediss://") {
+				errors.push("idempotency.redis_url must be a redis:// or rediss:// URL".to_string());
+			}
+		}
+		if self.enabled && self.ttl_secs == 0 {
+			errors.push("idempotency.ttl_secs must be positive".to_string());
+		}
+		if self.enabled && self.redis_url.is_none() && self.capacity == 0 {
+			errors.push("idempotency.capacity must be positive without a redis_url".to_string());
+		}
+		errors
+	}
+}
+
+/// The request's `Idempotency-Key`, if it sent a usable one.
+pub fn key_from(value: Option<&[u8]>) -> Result<Option<String>, SandboxError> {
+	let Some(value) = value else {
+		return Ok(None);
+	};
+	match std::str::from_utf8(value) {
+		Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|b| b.is_ascii_graphic()) => {
+			Ok(Some(key.to_string()))
+		}
+		_ => Err(SandboxError::InvalidRequest(format!(
+			"Idempotency-Key must be 1 to {MAX_KEY_LEN} printable ASCII characters"
+		))),
+	}
+}
+
+/// What identifies a request under its key: the tool, its args and its
+/// input. A key sent again with a different request is refused.
+pub fn fingerprint(tool_name: &str, args: &Value, input: &ToolInput) -> String {
+	args_sha256(&json!({ "tool_name": tool_name, "args": args, "input": input.sha256() }))
+}
+
+/// The response a request with a key got, as its transport encoded it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredResponse {
+	pub status: u16,
+	#[serde(with = "base64_bytes")]
+	pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+	fingerprint: String,
+	/// `None` while the request runs, or when its response was too big to
+	/// keep.
+	response: Option<StoredResponse>,
+	done: bool,
+}
+
+/// What became of a request that ran under a key, for the retries waiting
+/// on it in this instance.
+#[derive(Clone)]
+enum Progress {
+	Running,
+	Done(Arc<Entry>),
+	Released,
+}
+
+struct InFlight {
+	fingerprint: String,
+	progress: watch::Receiver<Progress>,
+}
+
+enum Backend {
+	Memory(Mutex<LruCache<String, (Instant, Arc<Entry>)>>),
+	Redis(redis::aio::ConnectionManager),
+	Disabled,
+}
+
+/// What to do with a request that carries a key.
+pub enum Claim {
+	/// First seen: run it, then [`Pending::complete`] it.
+	New(Pending),
+	/// Answered before; send this again.
+	Replay(StoredResponse),
+}
+
+/// Keeps the responses of requests sent with an `Idempotency-Key`, so that a
+/// retry with the same key gets the original response instead of running
+/// the tool again.
+///
+/// Keys are scoped by caller and route. A retry that arrives while the
+/// original still runs on this instance waits for it; one that reaches
+/// another instance sharing the Redis store is told to try again.
+pub struct Idempotency {
+	backend: Backend,
+	in_flight: Mutex<HashMap<String, InFlight>>,
+	ttl: Duration,
+	/// How long an unfinished claim blocks retries in the shared store.
+	claim_ttl: Duration,
+	max_entry_bytes: usize,
+}
+
+impl Idempotency {
+	/// `max_execution` is the longest a request may run, which bounds how
+	/// long a claim in the shared store is honoured.
+	pub async fn connect(config: &IdempotencyConfig, max_execution: Duration) -> Result<Self, String> {
+		let backend = match (config.enabled, &config.redis_url, NonZeroUsize::new(config.capacity)) {
+			(false, _, _) => Backend::Disabled,
+			(true, Some(url), _) => {
+				let client = redis::Client::open(url.as_str()).map_err(|e| format!("idempotency.redis_url: {e}"))?;
+				let connection = client
+					.get_connection_manager()
+					.await
+					.map_err(|e| format!("idempotency.redis_url: {e}"))?;
+				info!(backend = "redis", message = "Idempotency store ready");
+				Backend::Redis(connection)
+			}
+			(true, None, Some(capacity)) => Backend::Memory(Mutex::new(LruCache::new(capacity))),
+			(true, None, None) => Backend::Disabled,
+		};
+		Ok(Self {
+			backend,
+			in_flight: Mutex::new(HashMap::new()),
+			ttl: Duration::from_secs(config.ttl_secs),
+			claim_ttl: (max_execution + CLAIM_GRACE).min(Duration::from_secs(config.ttl_secs.max(1))),
+			max_entry_bytes: config.max_entry_bytes,
+		})
+	}
+
+	pub fn enabled(&self) -> bool {
+		!matches!(self.backend, Backend::Disabled)
+	}
+
+	/// Claim `key` for a request to `route` (`execute`, `job`, `grpc`) by
+	/// `caller` with `fingerprint`, or find the response it already got.
+	/// `None` when the store is disabled and the key is ignored.
+	pub async fn claim(
+		self: &Arc<Self>,
+		route: &str,
+		caller: &Caller,
+		key: &str,
+		fingerprint: String,
+	) -> Result<Option<Claim>, SandboxError> {
+		if !self.enabled() {
+			return Ok(None);
+		}
+		let subject = caller.subject.as_deref().unwrap_or("anonymous");
+		let scoped = format!("{route}:{}:{key}", args_sha256(&json!(subject)));
+		let result = self.claim_scoped(scoped, fingerprint).await;
+		let outcome = match &result {
+			Ok(Claim::New(_)) => "new",
+			Ok(Claim::Replay(_)) => "replayed",
+			Err(SandboxError::Conflict(_)) => "in_progress",
+			Err(SandboxError::Invalid(_)) => "mismatched",
+			Err(_) => "failed",
+		};
+		METRICS.idempotency.with_label_values(&[outcome]).inc();
+		result.map(Some)
+	}
+
+	async fn claim_scoped(self: &Arc<Self>, key: String, fingerprint: String) -> Result<Claim, SandboxError> {
+		loop {
+			// A retry of a request this instance is still running waits for it.
+			let waiting = {
+				let mut in_flight = self.in_flight.lock().unwrap();
+				match in_flight.get(&key) {
+					Some(running) => Ok((running.fingerprint == fingerprint, running.progress.clone())),
+					None => {
+						let (tx, rx) = watch::channel(Progress::Running);
+						let claim = InFlight {
+							fingerprint: fingerprint.clone(),
+							progress: rx,
+						};
+						in_flight.insert(key.clone(), claim);
+						Err(tx)
+					}
+				}
+			};
+			let (same, mut progress) = match waiting {
+				Ok(waiting) => waiting,
+				Err(progress) => return self.claim_stored(key, fingerprint, progress).await,
+			};
+			if !same {
+				return Err(mismatch());
+			}
+			let progress = progress
+				.wait_for(|p| !matches!(p, Progress::Running))
+				.await
+				.map(|p| p.clone())
+				.unwrap_or(Progress::Released);
+			match progress {
+				Progress::Done(entry) => return replay(&entry),
+				// It ended without a response; this retry may run it instead.
+				Progress::Released | Progress::Running => continue,
+			}
+		}
+	}
+
+	/// Claim `key` in the store, the in-flight claim in this instance being
+	/// held through `progress`.
+	async fn claim_stored(
+		self: &Arc<Self>,
+		key: String,
+		fingerprint: String,
+		progress: watch::Sender<Progress>,
+	) -> Result<Claim, SandboxError> {
+		let pending = Pending {
+			store: self.clone(),
+			key,
+			fingerprint,
+			progress: Some(progress),
+		};
+		let running = Entry {
+			fingerprint: pending.fingerprint.clone(),
+			response: None,
+			done: false,
+		};
+		let existing = match &self.backend {
+			Backend::Memory(entries) => {
+				let mut entries = entries.lock().unwrap();
+				match entries.get(&pending.key) {
+					Some((expires, entry)) if *expires > Instant::now() => Some(entry.as_ref().clone()),
+					_ => None,
+				}
+			}
+			Backend::Redis(connection) => {
+				let mut connection = connection.clone();
+				let redis_key = format!("{REDIS_KEY_PREFIX}{}", pending.key);
+				let value = serde_json::to_string(&running).expect("idempotency entries serialize");
+				let options = redis::SetOptions::default()
+					.conditional_set(redis::ExistenceCheck::NX)
+					.with_expiration(redis::SetExpiry::PX(self.claim_ttl.as_millis() as u64));
+				let attempt = async {
+					let claimed: Option<String> = connection.set_options(&redis_key, value, options).await?;
+					if claimed.is_some() {
+						return Ok(None);
+					}
+					connection.get::<_, Option<String>>(&redis_key).await
+				};
+				match tokio::time::timeout(REDIS_TIMEOUT, attempt).await {
+					Ok(Ok(value)) => value.and_then(|v| serde_json::from_str::<Entry>(&v).ok()),
+					Ok(Err(e)) => {
+						warn!(error = %e, message = "Idempotency store lookup failed");
+						return Err(unavailable());
+					}
+					Err(_) => {
+						warn!(message = "Idempotency store lookup timed out");
+						return Err(unavailable());
+					}
+				}
+			}
+			Backend::Disabled => None,
+		};
+		let Some(entry) = existing else {
+			return Ok(Claim::New(pending));
+		};
+		// Someone else's claim or response: this request leaves it alone.
+		let mut pending = pending;
+		pending.release_locally();
+		if entry.fingerprint != pending.fingerprint {
+			return Err(mismatch());
+		}
+		if !entry.done {
+			return Err(SandboxError::Conflict(
+				"a request with this Idempotency-Key is still running; retry later".to_string(),
+			));
+		}
+		replay(&entry)
+	}
+
+	fn store(&self, key: &str, entry: Entry) {
+		match &self.backend {
+			Backend::Memory(entries) => {
+				entries
+					.lock()
+					.unwrap()
+					.put(key.to_string(), (Instant::now() + self.ttl, Arc::new(entry)));
+			}
+			Backend::Redis(connection) => {
+				let mut connection = connection.clone();
+				let redis_key = format!("{REDIS_KEY_PREFIX}{key}");
+				let value = serde_json::to_string(&entry).expect("idempotency entries serialize");
+				let ttl_ms = self.ttl.as_millis() as u64;
+				tokio::spawn(async move {
+					if let Err(e) = connection.pset_ex::<_, _, ()>(redis_key, value, ttl_ms).await {
+						warn!(error = %e, message = "Idempotency store write failed");
+					}
+				});
+			}
+			Backend::Disabled => {}
+		}
+	}
+
+	fn forget(&self, key: &str) {
+		if let Backend::Redis(connection) = &self.backend {
+			let mut connection = connection.clone();
+			let redis_key = format!("{REDIS_KEY_PREFIX}{key}");
+			tokio::spawn(async move {
+				if let Err(e) = connection.del::<_, ()>(redis_key).await {
+					warn!(error = %e, message = "Idempotency store release failed");
+				}
+			});
+		}
+	}
+}
+
+/// A key claimed for a request that is now running. Completing it keeps the
+/// response for retries; dropping it, because the request failed before it
+/// could answer, releases the key so a retry runs it again.
+pub struct Pending {
+	store: Arc<Idempotency>,
+	key: String,
+	fingerprint: String,
+	progress: Option<watch::Sender<Progress>>,
+}
+
+impl Pending {
+	pub fn complete(mut self, status: u16, body: Vec<u8>) {
+		let response = (body.len() <= self.store.max_entry_bytes).then_some(StoredResponse { status, body });
+		let entry = Entry {
+			fingerprint: self.fingerprint.clone(),
+			response,
+			done: true,
+		};
+		self.store.store(&self.key, entry.clone());
+		self.store.in_flight.lock().unwrap().remove(&self.key);
+		if let Some(progress) = self.progress.take() {
+			progress.send_replace(Progress::Done(Arc::new(entry)));
+		}
+	}
+
+	/// Drop the in-flight claim of this instance without touching the store.
+	fn release_locally(&mut self) {
+		if let Some(progress) = self.progress.take() {
+			self.store.in_flight.lock().unwrap().remove(&self.key);
+			progress.send_replace(Progress::Released);
+		}
+	}
+}
+
+impl Drop for Pending {
+	fn drop(&mut self) {
+		if self.progress.is_some() {
+			self.release_locally();
+			self.store.forget(&self.key);
+		}
+	}
+}
+
+fn replay(entry: &Entry) -> Result<Claim, SandboxError> {
+	match &entry.response {
+		Some(response) => Ok(Claim::Replay(response.clone())),
+		None => Err(SandboxError::Conflict(
+			"the request with this Idempotency-Key already ran, but its response was too large to keep".to_string(),
+		)),
+	}
+}
+
+fn mismatch() -> SandboxError {
+	SandboxError::Invalid("this Idempotency-Key was used for a different request".to_string())
+}
+
+fn unavailable() -> SandboxError {
+	SandboxError::Unavailable("the idempotency store is unavailable".to_string())
+}
+
+mod base64_bytes {
+	use super::{Engine, STANDARD};
+	use serde::{Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&STANDARD.encode(bytes))
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+		let text = String::deserialize(deserializer)?;
+		STANDARD.decode(text).map_err(serde::de::Error::custom)
+	}
+}
@@ -0,0 +1,477 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, info_span, Instrument};
+
+use crate::audit::Caller;
+use crate::clock::now_ms;
+use crate::history::History;
+use crate::metrics::METRICS;
+use crate::scheduler::QueueFull;
+use crate::tool::{execute_tool_request_with_context, ToolExecutionRequest, ToolExecutionResponse};
+use crate::tool_executor::{ExecutionContext, Executor};
+use crate::webhooks::Webhooks;
+
+pub use pagi_sandbox_client::wire::{JobApproval, JobState, JobView};
+
+const DEFAULT_RETENTION_SECS: u64 = 3600;
+const DEFAULT_APPROVAL_TTL_SECS: u64 = 3600;
+
+struct Job {
+	view: JobView,
+	cancel: CancellationToken,
+	/// Taken when the callback is sent, so it is sent once.
+	callback_url: Option<String>,
+	/// Of the submitting request, sent on with the callback.
+	request_id: Option<String>,
+	/// What a job pending approval runs once it is approved; taken then.
+	held: Option<Held>,
+}
+
+/// Admits a held job's request to the scheduler, with the context it was
+/// submitted with. Called when the job is approved, so that jobs waiting
+/// for an approver take no place in the queue; it may be called again if
+/// the queue was full.
+pub type Admit = Box<dyn Fn() -> Result<ExecutionContext, QueueFull> + Send + Sync>;
+
+struct Held {
+	executor: Arc<Executor>,
+	req: ToolExecutionRequest,
+	admit: Admit,
+}
+
+/// Outcome of a cancel request.
+pub enum CancelOutcome {
+	Cancelled(JobView),
+	AlreadyFinished(JobView),
+	NotFound,
+}
+
+/// Why a job could not be approved or rejected.
+pub enum ApprovalError {
+	NotFound,
+	/// The job is not pending approval (any more).
+	NotPending(JobState),
+	/// The approver is who submitted the job.
+	OwnJob,
+	/// The scheduler queue is full; the job is still pending.
+	QueueFull(QueueFull),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+	/// How long finished jobs stay queryable.
+	pub retention_secs: u64,
+	pub approval: ApprovalConfig,
+}
+
+impl Default for JobsConfig {
+	fn default() -> Self {
+		Self {
+			retention_secs: DEFAULT_RETENTION_SECS,
+			approval: ApprovalConfig::default(),
+		}
+	}
+}
+
+impl JobsConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if self.approval.ttl_secs == 0 {
+			errors.push("jobs.approval.ttl_secs must be positive".to_string());
+		}
+		let urls = [
+			("webhook_url", &self.approval.webhook_url),
+			("slack_webhook_url", &self.approval.slack_webhook_url),
+		];
+		for (name, url) in urls {
+			if let Some(url) = url.as_ref().filter(|u| !(u.starts_with("http://") || u.starts_with("https://"))) {
+				errors.push(format!("jobs.approval.{name} must be an http(s) URL (got {url:?})"));
+			}
+		}
+		errors
+	}
+}
+
+/// Jobs of tools marked `requires_approval`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApprovalConfig {
+	/// How long such a job waits for an approver before it expires.
+	pub ttl_secs: u64,
+	/// Sent a signed `job.approval_requested` payload for every such job,
+	/// like job callbacks; needs `webhooks.secret`.
+	pub webhook_url: Option<String>,
+	/// A Slack incoming webhook, sent a message for every such job.
+	pub slack_webhook_url: Option<String>,
+	/// Let whoever submitted a job approve it too. Anonymous jobs, without
+	/// authentication, may always be approved by anyone allowed to.
+	pub allow_self_approval: bool,
+}
+
+impl Default for ApprovalConfig {
+	fn default() -> Self {
+		Self {
+			ttl_secs: DEFAULT_APPROVAL_TTL_SECS,
+			webhook_url: None,
+			slack_webhook_url: None,
+			allow_self_approval: false,
+		}
+	}
+}
+
+/// Registry of background tool executions.
+///
+/// Jobs run on their own tasks, so they keep going when the submitting client
+/// disconnects. Finished jobs are kept in memory for the retention period and
+/// then dropped; with a job history, every state change is also persisted and
+/// jobs stay queryable from there, including across restarts. Jobs submitted
+/// with a `callback_url` have it called once they finish.
+///
+/// Jobs of tools that require approval are held, `pending_approval`, until
+/// an approver approves them, which queues them, or rejects them; unless
+/// that happens within `jobs.approval.ttl_secs` they expire. Either way the
+/// callback is called once they finish.
+pub struct JobStore {
+	jobs: Mutex<HashMap<String, Job>>,
+	retention: Duration,
+	approval: ApprovalConfig,
+	history: Option<Arc<History>>,
+	webhooks: Arc<Webhooks>,
+}
+
+impl JobStore {
+	pub fn new(config: &JobsConfig, history: Option<Arc<History>>, webhooks: Arc<Webhooks>) -> Self {
+		Self {
+			jobs: Mutex::new(HashMap::new()),
+			retention: Duration::from_secs(config.retention_secs),
+			approval: config.approval.clone(),
+			history,
+			webhooks,
+		}
+	}
+
+	/// Enqueue `req` and return immediately with the new job. The request's
+	/// `callback_url` must have passed [`Webhooks::check_url`].
+	pub fn submit(self: &Arc<Self>, executor: Arc<Executor>, mut req: ToolExecutionRequest, ctx: ExecutionContext) -> JobView {
+		let job_id = uuid::Uuid::new_v4().to_string();
+		let view = JobView {
+			job_id: job_id.clone(),
+			tool_name: req.tool_name.clone(),
+			state: JobState::Queued,
+			created_at_ms: now_ms(),
+			started_at_ms: None,
+			finished_at_ms: None,
+			response: None,
+			replay_of: ctx.replay_of.clone(),
+			approval: None,
+			requested_by: ctx.caller.subject.clone(),
+			tenant: ctx.caller.tenant.clone(),
+		};
+		let ctx = ctx.with_job(job_id.clone());
+
+		{
+			let mut jobs = self.jobs.lock().unwrap();
+			self.prune(&mut jobs);
+			self.persist(&view);
+			jobs.insert(
+				job_id.clone(),
+				Job {
+					view: view.clone(),
+					cancel: ctx.cancel.clone(),
+					callback_url: req.callback_url.take(),
+					request_id: ctx.caller.request_id.clone(),
+					held: None,
+				},
+			);
+		}
+		METRICS.queued_jobs.inc();
+		self.start(executor, req, ctx, &job_id, &view.tool_name);
+
+		info!(job_id = %job_id, tool_name = %view.tool_name, message = "Job submitted");
+		view
+	}
+
+	/// Hold `req`, of a tool that requires approval, until an approver
+	/// approves it and `admit` queues it, and tell the configured approval
+	/// sinks about it. The request must have passed every check admission
+	/// makes.
+	pub fn hold(
+		self: &Arc<Self>,
+		executor: Arc<Executor>,
+		mut req: ToolExecutionRequest,
+		caller: &Caller,
+		replay_of: Option<String>,
+		admit: Admit,
+	) -> JobView {
+		let job_id = uuid::Uuid::new_v4().to_string();
+		let created_at_ms = now_ms();
+		let view = JobView {
+			job_id: job_id.clone(),
+			tool_name: req.tool_name.clone(),
+			state: JobState::PendingApproval,
+			created_at_ms,
+			started_at_ms: None,
+			finished_at_ms: None,
+			response: None,
+			replay_of,
+			approval: Some(JobApproval {
+				requested_by: caller.subject.clone(),
+				expires_at_ms: created_at_ms + self.approval.ttl_secs * 1000,
+				decided_by: None,
+				decided_at_ms: None,
+				reason: None,
+			}),
+			requested_by: caller.subject.clone(),
+			tenant: caller.tenant.clone(),
+		};
+		{
+			let mut jobs = self.jobs.lock().unwrap();
+			self.prune(&mut jobs);
+			self.persist(&view);
+			jobs.insert(
+				job_id.clone(),
+				Job {
+					view: view.clone(),
+					cancel: CancellationToken::new(),
+					callback_url: req.callback_url.take(),
+					request_id: caller.request_id.clone(),
+					held: Some(Held { executor, req, admit }),
+				},
+			);
+		}
+		METRICS.job_approvals.with_label_values(&[view.tool_name.as_str(), "requested"]).inc();
+		if let Some(url) = &self.approval.webhook_url {
+			self.webhooks.request_approval(url.clone(), &view, caller.request_id.clone());
+		}
+		if let Some(url) = &self.approval.slack_webhook_url {
+			self.webhooks.request_approval_on_slack(url.clone(), &view, caller.request_id.clone());
+		}
+
+		let store = self.clone();
+		let id = job_id.clone();
+		let ttl = Duration::from_secs(self.approval.ttl_secs);
+		tokio::spawn(async move {
+			tokio::time::sleep(ttl).await;
+			store.expire(&id);
+		});
+
+		info!(
+			job_id = %job_id,
+			tool_name = %view.tool_name,
+			subject = caller.subject.as_deref(),
+			message = "Job waits for approval"
+		);
+		view
+	}
+
+	/// Queue a job pending approval. `approver` is `None` when
+	/// authentication is disabled.
+	pub fn approve(self: &Arc<Self>, job_id: &str, approver: Option<&str>) -> Result<JobView, ApprovalError> {
+		let (executor, req, ctx, view) = {
+			let mut jobs = self.jobs.lock().unwrap();
+			let job = jobs.get_mut(job_id).ok_or(ApprovalError::NotFound)?;
+			self.check_decider(job, approver)?;
+			let held = job.held.as_ref().expect("pending jobs are held");
+			let ctx = (held.admit)().map_err(ApprovalError::QueueFull)?;
+			let Held { executor, req, .. } = job.held.take().expect("pending jobs are held");
+			let ctx = ctx
+				.with_job(job_id.to_string())
+				.with_approval(approver.unwrap_or("anonymous").to_string());
+			job.cancel = ctx.cancel.clone();
+			job.view.state = JobState::Queued;
+			self.decided(job, approver, None);
+			(executor, req, ctx, job.view.clone())
+		};
+		METRICS.queued_jobs.inc();
+		METRICS.job_approvals.with_label_values(&[view.tool_name.as_str(), "approved"]).inc();
+		info!(job_id = job_id, tool_name = %view.tool_name, approver = approver, message = "Job approved");
+		self.start(executor, req, ctx, job_id, &view.tool_name);
+		Ok(view)
+	}
+
+	/// Turn down a job pending approval; it finishes without running.
+	pub fn reject(&self, job_id: &str, approver: Option<&str>, reason: Option<String>) -> Result<JobView, ApprovalError> {
+		let mut jobs = self.jobs.lock().unwrap();
+		let job = jobs.get_mut(job_id).ok_or(ApprovalError::NotFound)?;
+		self.check_decider(job, approver)?;
+		job.held = None;
+		job.view.state = JobState::Rejected;
+		job.view.finished_at_ms = Some(now_ms());
+		self.decided(job, approver, reason);
+		self.call_back(job);
+		METRICS.job_approvals.with_label_values(&[job.view.tool_name.as_str(), "rejected"]).inc();
+		info!(job_id = job_id, tool_name = %job.view.tool_name, approver = approver, message = "Job rejected");
+		Ok(job.view.clone())
+	}
+
+	/// Whether `approver` may decide on `job` now.
+	fn check_decider(&self, job: &Job, approver: Option<&str>) -> Result<(), ApprovalError> {
+		if job.view.state != JobState::PendingApproval {
+			return Err(ApprovalError::NotPending(job.view.state));
+		}
+		let requested_by = job.view.approval.as_ref().and_then(|a| a.requested_by.as_deref());
+		if !self.approval.allow_self_approval && approver.is_some() && approver == requested_by {
+			return Err(ApprovalError::OwnJob);
+		}
+		Ok(())
+	}
+
+	/// Record who decided on `job`, and persist it.
+	fn decided(&self, job: &mut Job, approver: Option<&str>, reason: Option<String>) {
+		if let Some(approval) = &mut job.view.approval {
+			approval.decided_by = approver.map(str::to_string);
+			approval.decided_at_ms = Some(now_ms());
+			approval.reason = reason;
+		}
+		self.persist(&job.view);
+	}
+
+	fn expire(&self, job_id: &str) {
+		let mut jobs = self.jobs.lock().unwrap();
+		let Some(job) = jobs.get_mut(job_id).filter(|job| job.view.state == JobState::PendingApproval) else {
+			return;
+		};
+		job.held = None;
+		job.view.state = JobState::Expired;
+		job.view.finished_at_ms = Some(now_ms());
+		self.persist(&job.view);
+		self.call_back(job);
+		METRICS.job_approvals.with_label_values(&[job.view.tool_name.as_str(), "expired"]).inc();
+		info!(job_id = job_id, tool_name = %job.view.tool_name, message = "Job expired unapproved");
+	}
+
+	/// Run a queued job on its own task.
+	fn start(
+		self: &Arc<Self>,
+		executor: Arc<Executor>,
+		req: ToolExecutionRequest,
+		ctx: ExecutionContext,
+		job_id: &str,
+		tool_name: &str,
+	) {
+		// The job span continues the submitting request's trace; `queue_wait`
+		// covers the time until the job gets an execution slot.
+		let job_span = info_span!("job", job_id = %job_id, tool_name = %tool_name);
+		let queue_span = info_span!(parent: &job_span, "queue_wait");
+		let store = self.clone();
+		let id = job_id.to_string();
+		tokio::spawn(
+			async move {
+				// A job cancelled while waiting is skipped below; an aborted one
+				// still runs far enough to record its `aborted` result.
+				let _ = ctx.wait_turn().await;
+				let started = store.mark_running(&id);
+				drop(queue_span);
+				if !started {
+					return;
+				}
+				let response = execute_tool_request_with_context(&executor, req, &ctx).await;
+				store.complete(&id, response);
+			}
+			.instrument(job_span),
+		);
+	}
+
+	pub async fn get(&self, job_id: &str) -> Option<JobView> {
+		if let Some(job) = self.jobs.lock().unwrap().get(job_id) {
+			return Some(job.view.clone());
+		}
+		self.stored(job_id).await
+	}
+
+	/// Stop a queued or running job. A running execution is cancelled and its
+	/// partial result is attached to the job once it has wound down.
+	pub async fn cancel(&self, job_id: &str) -> CancelOutcome {
+		if let Some(outcome) = self.cancel_live(job_id) {
+			return outcome;
+		}
+		// Only jobs of this process can still be running.
+		match self.stored(job_id).await {
+			Some(view) => CancelOutcome::AlreadyFinished(view),
+			None => CancelOutcome::NotFound,
+		}
+	}
+
+	fn cancel_live(&self, job_id: &str) -> Option<CancelOutcome> {
+		let mut jobs = self.jobs.lock().unwrap();
+		let job = jobs.get_mut(job_id)?;
+		if job.view.state.is_finished() {
+			return Some(CancelOutcome::AlreadyFinished(job.view.clone()));
+		}
+		let queued = job.view.state == JobState::Queued;
+		if queued {
+			METRICS.queued_jobs.dec();
+		}
+		let held = job.held.take().is_some();
+
+		job.view.state = JobState::Cancelled;
+		job.view.finished_at_ms = Some(now_ms());
+		job.cancel.cancel();
+		self.persist(&job.view);
+		// A running job calls back in `complete`, with the partial result.
+		if queued || held {
+			self.call_back(job);
+		}
+		info!(job_id = job_id, message = "Job cancelled");
+		Some(CancelOutcome::Cancelled(job.view.clone()))
+	}
+
+	/// Returns false when the job was cancelled before it started.
+	fn mark_running(&self, job_id: &str) -> bool {
+		let mut jobs = self.jobs.lock().unwrap();
+		match jobs.get_mut(job_id) {
+			Some(job) if job.view.state == JobState::Queued => {
+				METRICS.queued_jobs.dec();
+				job.view.state = JobState::Running;
+				job.view.started_at_ms = Some(now_ms());
+				self.persist(&job.view);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	fn complete(&self, job_id: &str, response: ToolExecutionResponse) {
+		let mut jobs = self.jobs.lock().unwrap();
+		if let Some(job) = jobs.get_mut(job_id) {
+			if job.view.state == JobState::Running {
+				job.view.state = if response.status == "preempted" {
+					JobState::Preempted
+				} else {
+					JobState::Completed
+				};
+				job.view.finished_at_ms = Some(now_ms());
+			}
+			job.view.response = Some(response);
+			self.persist(&job.view);
+			self.call_back(job);
+		}
+	}
+
+	fn call_back(&self, job: &mut Job) {
+		if let Some(url) = job.callback_url.take() {
+			self.webhooks.notify(url, &job.view, job.request_id.clone());
+		}
+	}
+
+	/// Queue the job's new state for the history. Called with the jobs lock
+	/// held, so the writes reach the database in the order they happened.
+	fn persist(&self, view: &JobView) {
+		if let Some(history) = &self.history {
+			history.record_job(view);
+		}
+	}
+
+	async fn stored(&self, job_id: &str) -> Option<JobView> {
+		self.history.as_ref()?.job(job_id).await
+	}
+
+	fn prune(&self, jobs: &mut HashMap<String, Job>) {
+		let cutoff = now_ms().saturating_sub(self.retention.as_millis() as u64);
+		jobs.retain(|_, job| job.view.finished_at_ms.is_none_or(|t| t >= cutoff));
+	}
+}
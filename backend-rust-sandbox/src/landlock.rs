@@ -0,0 +1,186 @@
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::namespace::check;
+
+/// Read-only paths a confined tool gets by default: the programs, libraries
+/// and configuration an ordinary process expects to find.
+pub const DEFAULT_READ_PATHS: &[&str] = &[
+	"/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/opt", "/proc", "/sys", "/dev",
+];
+
+/// Devices every confined tool may also write to.
+const DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/full", "/dev/random", "/dev/urandom", "/dev/tty"];
+
+const CREATE_RULESET_VERSION: libc::c_uint = 1;
+const RULE_PATH_BENEATH: libc::c_int = 1;
+
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+/// `REMOVE_DIR` through `MAKE_SYM`, the rights ABI 1 adds to these four.
+const ACCESS_ABI_1: u64 = (1 << 13) - 1;
+const ACCESS_REFER: u64 = 1 << 13;
+const ACCESS_TRUNCATE: u64 = 1 << 14;
+const ACCESS_IOCTL_DEV: u64 = 1 << 15;
+
+/// The only rights a rule on a file, rather than a directory, may grant.
+const FILE_ACCESS: u64 = ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE | ACCESS_IOCTL_DEV;
+const READ_ACCESS: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+const DEVICE_ACCESS: u64 = ACCESS_READ_FILE | ACCESS_WRITE_FILE | ACCESS_TRUNCATE | ACCESS_IOCTL_DEV;
+
+/// `struct landlock_ruleset_attr` as ABI 1 defines it; later fields are
+/// left out, which the kernel accepts.
+#[repr(C)]
+struct RulesetAttr {
+	handled_access_fs: u64,
+}
+
+/// `struct landlock_path_beneath_attr`.
+#[repr(C, packed)]
+struct PathBeneathAttr {
+	allowed_access: u64,
+	parent_fd: libc::c_int,
+}
+
+/// The filesystem rights the running kernel's Landlock ABI can restrict, or
+/// why it cannot restrict any.
+pub fn supported_access() -> Result<u64, String> {
+	// SAFETY: the version query takes no attribute struct.
+	let abi = unsafe {
+		libc::syscall(
+			libc::SYS_landlock_create_ruleset,
+			std::ptr::null::<RulesetAttr>(),
+			0usize,
+			CREATE_RULESET_VERSION,
+		)
+	};
+	if abi < 0 {
+		let e = io::Error::last_os_error();
+		return Err(match e.raw_os_error() {
+			Some(libc::ENOSYS) => "the kernel is built without Landlock".to_string(),
+			Some(libc::EOPNOTSUPP) => "Landlock is disabled at boot (see the lsm= parameter)".to_string(),
+			_ => e.to_string(),
+		});
+	}
+	Ok(match abi {
+		1 => ACCESS_ABI_1,
+		2 => ACCESS_ABI_1 | ACCESS_REFER,
+		3 | 4 => ACCESS_ABI_1 | ACCESS_REFER | ACCESS_TRUNCATE,
+		_ => ACCESS_ABI_1 | ACCESS_REFER | ACCESS_TRUNCATE | ACCESS_IOCTL_DEV,
+	})
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+	path: CString,
+	access: u64,
+}
+
+/// A Landlock ruleset for a tool process: everything the kernel can
+/// restrict is denied outside the paths it names.
+#[derive(Debug, Clone)]
+pub struct Landlock {
+	handled: u64,
+	rules: Arc<Vec<Rule>>,
+}
+
+impl Landlock {
+	/// Rules granting read and execute access to `read_paths`, restricting
+	/// the rights in `handled`. Paths that do not exist are left out.
+	pub fn new(handled: u64, read_paths: &[PathBuf]) -> Self {
+		let mut landlock = Self {
+			handled,
+			rules: Arc::new(Vec::new()),
+		};
+		landlock.add(read_paths.iter().map(PathBuf::as_path), READ_ACCESS);
+		landlock.add(DEVICES.iter().map(Path::new), DEVICE_ACCESS);
+		landlock
+	}
+
+	/// These rules, with full access to everything under `dir`.
+	pub fn with_writable(&self, dir: &Path) -> Self {
+		let mut landlock = self.clone();
+		landlock.add([dir], self.handled);
+		landlock
+	}
+
+	/// These rules, with read and execute access to `paths` as well.
+	pub fn with_readable<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) -> Self {
+		let mut landlock = self.clone();
+		landlock.add(paths, READ_ACCESS);
+		landlock
+	}
+
+	fn add<'a>(&mut self, paths: impl IntoIterator<Item = &'a Path>, access: u64) {
+		let rules = Arc::make_mut(&mut self.rules);
+		for path in paths {
+			let Ok(metadata) = std::fs::metadata(path) else {
+				continue;
+			};
+			let access = if metadata.is_dir() { access } else { access & FILE_ACCESS };
+			rules.push(Rule {
+				path: CString::new(path.as_os_str().as_bytes()).expect("paths have no NUL bytes"),
+				access: access & self.handled,
+			});
+		}
+	}
+
+	/// Confine the calling process to the rules. Runs in the child between
+	/// fork and exec, so it only makes syscalls on data prepared beforehand;
+	/// the restriction is inherited across exec and by every descendant.
+	pub fn restrict(&self) -> io::Result<()> {
+		let attr = RulesetAttr {
+			handled_access_fs: self.handled,
+		};
+		// SAFETY: plain syscalls on structs and NUL-terminated strings that
+		// outlive them.
+		unsafe {
+			let ruleset = libc::syscall(
+				libc::SYS_landlock_create_ruleset,
+				&attr as *const RulesetAttr,
+				std::mem::size_of::<RulesetAttr>(),
+				0,
+			) as libc::c_int;
+			check(ruleset)?;
+			let result = self.add_rules(ruleset).and_then(|()| {
+				check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+				check(libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) as libc::c_int)
+			});
+			libc::close(ruleset);
+			result
+		}
+	}
+
+	unsafe fn add_rules(&self, ruleset: libc::c_int) -> io::Result<()> {
+		for rule in self.rules.iter() {
+			let fd = libc::open(rule.path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC);
+			if fd < 0 {
+				let e = io::Error::last_os_error();
+				// Gone since the rules were made.
+				if e.raw_os_error() == Some(libc::ENOENT) {
+					continue;
+				}
+				return Err(e);
+			}
+			let attr = PathBeneathAttr {
+				allowed_access: rule.access,
+				parent_fd: fd,
+			};
+			let rc = libc::syscall(
+				libc::SYS_landlock_add_rule,
+				ruleset,
+				RULE_PATH_BENEATH,
+				&attr as *const PathBeneathAttr,
+				0,
+			);
+			libc::close(fd);
+			check(rc as libc::c_int)?;
+		}
+		Ok(())
+	}
+}
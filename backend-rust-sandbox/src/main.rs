@@ -1,25 +1,181 @@
 use axum::{
-    extract::Json,
-    http::{HeaderMap, StatusCode},
-    routing::{get, post},
+    body::Body,
+    extract::{
+        multipart::MultipartError, ws::WebSocketUpgrade, ConnectInfo, DefaultBodyLimit, Extension, FromRequest, Json,
+        Multipart, Path, Query, Request, State,
+    },
+    http::{header, HeaderMap, HeaderName, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
     Router,
 };
-use serde::Serialize;
-use std::{env, net::SocketAddr};
-use tracing::{info, Level};
-use tracing_subscriber::{prelude::*, Registry};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{net::SocketAddr, sync::Arc, time::{Duration, Instant}};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 
+mod artifacts;
+mod audit;
+mod batch;
+mod bench;
+mod body_log;
+mod auth;
+mod auth_jwt;
+mod authz;
+mod cache;
+mod cgroup;
+mod chaos;
+mod clock;
+mod cluster;
+mod code_scan;
+mod compression;
+mod config;
+mod cost;
+mod cron;
+mod deadline;
+mod disk_quota;
+mod egress;
+mod encoding;
+mod environment;
+mod error;
+mod events;
+mod executions;
+mod executor_container;
+mod executor_firecracker;
+mod executor_javascript;
+mod executor_python;
+mod executor_component;
+mod executor_wasm;
+mod gateway_client;
+mod gpu;
+mod health;
+mod history;
+mod idempotency;
+mod jobs;
+mod landlock;
+mod logging;
+mod mcp;
+mod metrics;
+mod multiplex;
+mod namespace;
+mod negotiate;
+mod openapi;
+mod output_filter;
+mod pipeline;
+mod plugins;
+mod plan;
+mod policy;
+mod pty;
+mod quarantine;
+mod quotas;
+mod ratelimit;
+mod redact;
+mod registry;
+mod reload;
+mod request_id;
+mod retry;
+mod schedules;
+mod scheduler;
+mod seccomp;
+mod secrets;
+mod session_registry;
+mod session_snapshot;
+mod sessions;
+mod shared_limits;
+mod signing;
+mod subprocess;
+mod telemetry;
+mod tls;
 mod tool;
 mod tool_executor;
+mod tool_http;
+mod tool_model;
+mod tool_sdk;
+mod tool_shell;
+mod tool_sql;
+mod tool_weather;
 mod tool_web_search;
 mod tool_service;
-use tool::{execute_mock_tool, ToolExecutionRequest, ToolExecutionResponse};
+mod tool_session;
+mod tool_stream;
+mod trace;
+mod usage;
+mod vm_protocol;
+mod warm_pool;
+mod webhooks;
+mod work_queue;
+mod workspace;
+use audit::{AuditLog, AuditQuery, Caller};
+use batch::{BatchConfig, BatchRequest};
+use auth::{tenant_of, Authenticator, Principal};
+use authz::{Authorizer, RbacPolicy};
+use cache::ResultCache;
+use cluster::Cluster;
+use config::Settings;
+use error::{Api, SandboxError};
+use events::EventBus;
+use gateway_client::ForwardedAuth;
+use health::Readiness;
+use history::{History, HistoryQuery, Replayable, StatsQuery};
+use idempotency::{Claim, Idempotency, Pending};
+use jobs::{Admit, ApprovalError, CancelOutcome, JobState, JobStore, JobView};
+use logging::LogLevels;
+use mcp::McpConfig;
+use negotiate::Format;
+use pipeline::{PipelineRequest, Plan};
+use policy::EvaluateRequest;
+use quarantine::{QuarantineError, QuarantineState};
+use quotas::{Quotas, UsageQuery};
+use ratelimit::{client_key, RateLimiter};
+use registry::ToolRegistry;
+use reload::Reloader;
+use scheduler::{Priority, QueueFull, ScalingSignal};
+use schedules::{ScheduleError, ScheduleSpec, ScheduleUpdate, Schedules};
+use sessions::{SessionError, SessionManager};
+use shared_limits::SharedLimits;
+use signing::ToolVerifier;
+use tool::{
+    budget_exceeded_response, execute_tool_request, plan_tool_request, queue_full_response, quota_exceeded_response,
+    rate_limited_response, request_input, request_tracer, validate_tool_request, Base64, ToolExecutionRequest,
+    ToolExecutionResponse,
+};
+use tool_executor::{ExecutionContext, Executor};
+use webhooks::Webhooks;
+use work_queue::WorkQueue;
 
-const DEFAULT_PORT: u16 = 8001;
-const DEFAULT_GRPC_PORT: u16 = 50053;
+/// After the drain period, how long aborted executions get to clean up.
+const ABORT_GRACE: Duration = Duration::from_secs(5);
 const SERVICE_NAME: &str = "backend-rust-sandbox";
+/// Names a request that must only run once, however often it is retried.
+const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+/// Set on a response that is a stored answer to an earlier request.
+const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
 const VERSION: &str = "1.0.0";
 
+/// Shared state handed to every HTTP handler.
+#[derive(Clone)]
+struct AppState {
+    executor: Arc<Executor>,
+    jobs: Arc<JobStore>,
+    webhooks: Arc<Webhooks>,
+    history: Option<Arc<History>>,
+    authz: Arc<Authorizer>,
+    readiness: Arc<Readiness>,
+    ratelimit: Arc<RateLimiter>,
+    idempotency: Arc<Idempotency>,
+    reloader: Arc<Reloader>,
+    batch: BatchConfig,
+    sessions: Arc<SessionManager>,
+    schedules: Arc<Schedules>,
+    mcp: McpConfig,
+    queue: Option<Arc<WorkQueue>>,
+    cluster: Option<Arc<Cluster>>,
+    log_levels: Arc<LogLevels>,
+}
+
 #[derive(Serialize)]
 struct HealthResponse {
     service: &'static str,
@@ -27,6 +183,8 @@ struct HealthResponse {
     version: &'static str,
 }
 
+/// Liveness: the process is up and serving HTTP. Also served as `/health`
+/// for existing clients.
 async fn health_check() -> (StatusCode, Json<HealthResponse>) {
     (
         StatusCode::OK,
@@ -38,10 +196,103 @@ async fn health_check() -> (StatusCode, Json<HealthResponse>) {
     )
 }
 
+/// Readiness: `503` with per-check details while any check fails, including
+/// once shutdown has begun.
+async fn readiness_check(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let (code, body) = state.readiness.check().await;
+    (code, Json(body))
+}
+
+/// A tool request as JSON, or as `multipart/form-data` so that input data
+/// need not be base64-encoded: the JSON goes in a `request` part, raw stdin in
+/// a `stdin` part, and each input file in an `input_file` part whose filename
+/// is its path in the workspace.
+struct ToolRequestBody(ToolExecutionRequest);
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequest<S> for ToolRequestBody {
+    type Rejection = SandboxError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let multipart = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("multipart/form-data"));
+        if !multipart {
+            let Json(payload) = Json::from_request(req, state).await?;
+            return Ok(Self(payload));
+        }
+
+        let mut form = Multipart::from_request(req, state)
+            .await
+            .map_err(|e| SandboxError::InvalidRequest(e.body_text()))?;
+        let mut payload: Option<ToolExecutionRequest> = None;
+        let mut stdin = None;
+        let mut files = Vec::new();
+        while let Some(field) = form.next_field().await.map_err(multipart_error)? {
+            let name = field.name().unwrap_or_default().to_string();
+            let file_name = field.file_name().map(str::to_string);
+            let data = field.bytes().await.map_err(multipart_error)?;
+            match (name.as_str(), file_name) {
+                ("request", _) => {
+                    let parsed = serde_json::from_slice(&data)
+                        .map_err(|e| SandboxError::InvalidRequest(format!("invalid request part: {e}")))?;
+                    payload = Some(parsed);
+                }
+                ("stdin", _) => stdin = Some(Base64(data.to_vec())),
+                ("input_file", Some(path)) => files.push((path, Base64(data.to_vec()))),
+                ("input_file", None) => {
+                    return Err(SandboxError::InvalidRequest("input_file part without a filename".to_string()))
+                }
+                (other, _) => return Err(SandboxError::InvalidRequest(format!("unexpected part {other:?}"))),
+            }
+        }
+        let mut payload =
+            payload.ok_or_else(|| SandboxError::InvalidRequest("missing request part".to_string()))?;
+        if stdin.is_some() {
+            payload.stdin = stdin;
+        }
+        payload.input_files.extend(files);
+        Ok(Self(payload))
+    }
+}
+
+fn multipart_error(e: MultipartError) -> SandboxError {
+    match e.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => SandboxError::PayloadTooLarge(e.body_text()),
+        _ => SandboxError::InvalidRequest(e.body_text()),
+    }
+}
+
+/// The autoscaling signal; public like `/metrics`, which carries the same
+/// figures.
+async fn handle_get_scaling(State(state): State<AppState>) -> Json<ScalingSignal> {
+    let mut signal = state.executor.scaling();
+    if let Some(queue) = &state.queue {
+        match queue.waiting().await {
+            Ok(waiting) => signal.shared_queue_depth = Some(waiting),
+            Err(e) => warn!(error = %e, message = "Work queue depth query failed"),
+        }
+    }
+    Json(signal)
+}
+
+/// Prometheus metrics, with the autoscaling gauges brought up to date.
+async fn handle_metrics(State(state): State<AppState>) -> Response {
+    let signal = state.executor.scaling();
+    metrics::METRICS.pending_queue_depth.set(signal.pending_queue_depth as i64);
+    metrics::METRICS.avg_wait_ms.set(signal.avg_wait_ms as i64);
+    metrics::handle_metrics().await
+}
+
 async fn handle_execute_tool(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Json(payload): Json<ToolExecutionRequest>,
-) -> (StatusCode, Json<ToolExecutionResponse>) {
+    ToolRequestBody(payload): ToolRequestBody,
+) -> Response {
     let request_id = headers
         .get("x-request-id")
         .and_then(|v| v.to_str().ok())
@@ -51,78 +302,2249 @@ async fn handle_execute_tool(
     info!(
         request_id = request_id,
         method = "POST",
+        tenant = tenant_of(principal.as_deref()),
         tool_name = payload.tool_name,
         message = "Received tool execution request."
     );
 
-    let response = execute_mock_tool(payload).await;
-    (StatusCode::OK, Json(response))
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "http");
+    if payload.dry_run {
+        return plan_tool_call(&state, &caller, principal.as_deref(), &payload);
+    }
+    if let Some(session_id) = payload.session_id.clone() {
+        return call_session(&state, caller, principal.as_deref(), &headers, &session_id, payload).await;
+    }
+    // A peer that takes the request authenticates, admits and runs it itself.
+    if let Some(cluster) = &state.cluster {
+        if let Some(response) = cluster.forward(&payload, &headers).await {
+            return response;
+        }
+    }
+    let deadline = match caller_deadline(&state, &headers) {
+        Ok(deadline) => deadline,
+        Err(response) => return response,
+    };
+    let pending = match claim_idempotency_key(&state, &headers, "execute", &caller, &payload).await {
+        Ok(pending) => pending,
+        Err(response) => return response,
+    };
+    let ctx = match admit_tool_request(&state, caller, principal.as_deref(), &headers, &payload).await {
+        Ok(ctx) => ctx.with_deadline(deadline),
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let response = execute_tool_request(state.executor.clone(), payload, ctx).await;
+    remember_response(pending, StatusCode::OK, &response)
+}
+
+/// When the caller stops waiting for the request, as its `X-Deadline-Ms`
+/// says; a malformed header is answered as the `Err`.
+#[allow(clippy::result_large_err)]
+fn caller_deadline(state: &AppState, headers: &HeaderMap) -> Result<Option<Instant>, Response> {
+    deadline::from_headers(headers, state.executor.deadline_overhead()).map_err(IntoResponse::into_response)
+}
+
+/// Claim the request's `Idempotency-Key` for `caller` on `route`. A retry of
+/// a request that was already answered gets that answer back as the `Err`,
+/// as does a key that cannot be used. Requests turned away before they run
+/// drop the claim, which releases the key for the next attempt.
+async fn claim_idempotency_key(
+    state: &AppState,
+    headers: &HeaderMap,
+    route: &str,
+    caller: &Caller,
+    payload: &ToolExecutionRequest,
+) -> Result<Option<Pending>, Response> {
+    let key = idempotency::key_from(headers.get(IDEMPOTENCY_KEY).map(|v| v.as_bytes()))
+        .map_err(IntoResponse::into_response)?;
+    let Some(key) = key else {
+        return Ok(None);
+    };
+    let fingerprint = idempotency::fingerprint(&payload.tool_name, &payload.args, &request_input(payload));
+    match state.idempotency.claim(route, caller, &key, fingerprint).await {
+        Ok(Some(Claim::New(pending))) => Ok(Some(pending)),
+        Ok(Some(Claim::Replay(stored))) => {
+            let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+            Err((
+                status,
+                [(header::CONTENT_TYPE, "application/json"), (IDEMPOTENT_REPLAYED, "true")],
+                stored.body,
+            )
+                .into_response())
+        }
+        Ok(None) => Ok(None),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+/// Answer with `body`, keeping it for retries under the claimed key.
+fn remember_response(pending: Option<Pending>, status: StatusCode, body: &impl Serialize) -> Response {
+    let body = serde_json::to_vec(body).expect("responses serialize");
+    if let Some(pending) = pending {
+        pending.complete(status.as_u16(), body.clone());
+    }
+    (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+/// A `dry_run`: authorized and validated like any request, then answered
+/// with its plan. It takes no execution slot and counts against no limit.
+fn plan_tool_call(
+    state: &AppState,
+    caller: &Caller,
+    principal: Option<&Principal>,
+    payload: &ToolExecutionRequest,
+) -> Response {
+    if payload.session_id.is_some() {
+        let response = ToolExecutionResponse {
+            status: "invalid_args".to_string(),
+            tool_name: payload.tool_name.clone(),
+            result: json!({ "error": "dry_run does not apply to session calls" }),
+        };
+        return (StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response();
+    }
+    if let Err(rejection) = authorize_tool_request(state, caller, principal, payload) {
+        return rejection.into_response();
+    }
+    (StatusCode::OK, Json(plan_tool_request(&state.executor, caller, payload))).into_response()
+}
+
+/// An `execute_tool` call addressed to a running session: checked like any
+/// request, but it needs no execution slot of its own.
+async fn call_session(
+    state: &AppState,
+    caller: Caller,
+    principal: Option<&Principal>,
+    headers: &HeaderMap,
+    session_id: &str,
+    payload: ToolExecutionRequest,
+) -> Response {
+    if !state.sessions.holds(session_id) {
+        let body = serde_json::to_value(&payload).expect("a tool request serializes");
+        let path = "/api/v1/execute_tool";
+        if let Some(response) = route_session(state, &caller, headers, session_id, Method::POST, path, Some(body)).await {
+            return response;
+        }
+    }
+    let extras = payload.stdin.is_some()
+        || !payload.input_files.is_empty()
+        || !payload.artifacts.is_empty()
+        || payload.retain_workspace;
+    if extras {
+        let response = ToolExecutionResponse {
+            status: "invalid_args".to_string(),
+            tool_name: payload.tool_name,
+            result: json!({
+                "error": "a session call takes only args and timeout_ms; stdin, input files, artifacts and workspace retention belong on the request that creates the session"
+            }),
+        };
+        return (StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response();
+    }
+    if let Err(rejection) = check_tool_request(state, &caller, principal, &payload).await {
+        return rejection.into_response();
+    }
+    let timeout = state.executor.timeout_for(&payload.tool_name, payload.timeout_ms);
+    let tool_name = payload.tool_name.clone();
+    match state.sessions.call(session_id, &caller, payload, timeout).await {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(e) => {
+            let (code, status) = session_error_status(&e);
+            let response = ToolExecutionResponse {
+                status: status.to_string(),
+                tool_name,
+                result: json!({ "error": e.message(), "session_id": session_id }),
+            };
+            (code, Json(response)).into_response()
+        }
+    }
+}
+
+async fn handle_execute_tool_stream(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    ToolRequestBody(payload): ToolRequestBody,
+) -> Response {
+    let request_id = headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("none");
+
+    info!(
+        request_id = request_id,
+        method = "POST",
+        tenant = tenant_of(principal.as_deref()),
+        tool_name = payload.tool_name,
+        message = "Received streaming tool execution request."
+    );
+
+    let format = match Format::choose(&headers, &[Format::EventStream, Format::Ndjson, Format::MessagePack]) {
+        Ok(format) => format,
+        Err(e) => return negotiate::with_vary(e.into_response()),
+    };
+    let deadline = match caller_deadline(&state, &headers) {
+        Ok(deadline) => deadline,
+        Err(response) => return response,
+    };
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "sse");
+    let ctx = match admit_tool_request(&state, caller, principal.as_deref(), &headers, &payload).await {
+        Ok(ctx) => ctx.with_deadline(deadline),
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    tool_stream::execute_tool_stream(state.executor.clone(), payload, ctx, format)
+}
+
+async fn handle_submit_job(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    ToolRequestBody(mut payload): ToolRequestBody,
+) -> Response {
+    let request_id = headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("none");
+
+    info!(
+        request_id = request_id,
+        method = "POST",
+        tenant = tenant_of(principal.as_deref()),
+        tool_name = payload.tool_name,
+        message = "Received job submission."
+    );
+
+    // Checked first, and left out of the idempotency fingerprint.
+    let callback_url = payload.callback_url.take();
+    if let Some(url) = &callback_url {
+        if let Err(e) = state.webhooks.check_url(url) {
+            let response = ToolExecutionResponse {
+                status: "invalid_args".to_string(),
+                tool_name: payload.tool_name.clone(),
+                result: json!({ "error": e }),
+            };
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response();
+        }
+    }
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "job");
+    let pending = match claim_idempotency_key(&state, &headers, "job", &caller, &payload).await {
+        Ok(pending) => pending,
+        Err(response) => return response,
+    };
+    payload.callback_url = callback_url;
+    let background = |ctx: ExecutionContext| ctx.with_priority_at_most(Priority::Background);
+    match submit_job(&state, caller, principal.as_deref(), &headers, payload, None, background).await {
+        Ok(job) => remember_response(pending, StatusCode::ACCEPTED, &job),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Upgrade to a WebSocket carrying one interactive tool session; see
+/// `tool_session::run_session` for the protocol.
+async fn handle_session_ws(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let request_id = headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("none")
+        .to_string();
+
+    info!(
+        request_id = request_id,
+        method = "GET",
+        tenant = tenant_of(principal.as_deref()),
+        message = "Received interactive session request."
+    );
+
+    let principal = principal.map(|Extension(p)| p);
+    let caller = Caller::new(principal.as_ref(), Some(peer.ip()), "websocket");
+    upgrade.on_upgrade(move |socket| async move {
+        let executor = state.executor.clone();
+        let admit = |req: &ToolExecutionRequest| {
+            let req = req.clone();
+            async move {
+                let admitted = admit_tool_request(&state, caller, principal.as_ref(), &headers, &req).await;
+                admitted.map_err(|r| r.response)
+            }
+        };
+        tool_session::run_session(socket, executor, admit).await
+    })
+}
+
+/// Start a stateful session: the body is an `execute_tool` request whose
+/// `args` the tool is started with, admitted like one.
+async fn handle_create_session(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    ToolRequestBody(payload): ToolRequestBody,
+) -> Response {
+    let request_id = headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("none");
+
+    info!(
+        request_id = request_id,
+        method = "POST",
+        tenant = tenant_of(principal.as_deref()),
+        tool_name = payload.tool_name,
+        message = "Received session creation request."
+    );
+
+    if payload.stdin.is_some() {
+        return SandboxError::Invalid(
+            "a session's stdin carries its calls; send input as execute_tool calls with its session_id".to_string(),
+        )
+        .into_response();
+    }
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "session");
+    let ctx = match admit_tool_request(&state, caller, principal.as_deref(), &headers, &payload).await {
+        Ok(ctx) => ctx,
+        Err(rejection) => return rejection.into_response(),
+    };
+    match state.sessions.start(payload, ctx).await {
+        Ok(session) => (StatusCode::CREATED, Json(session)).into_response(),
+        Err(e) => session_error(e),
+    }
+}
+
+/// The caller's sessions.
+async fn handle_list_sessions(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+) -> Response {
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "http");
+    Json(json!({ "sessions": state.sessions.list(&caller) })).into_response()
+}
+
+async fn handle_get_session(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Response {
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "http");
+    let path = format!("/api/v1/sessions/{session_id}");
+    if let Some(response) = route_session(&state, &caller, &headers, &session_id, Method::GET, &path, None).await {
+        return response;
+    }
+    match state.sessions.get(&session_id, &caller) {
+        Ok(session) => Json(session).into_response(),
+        Err(e) => session_error(e),
+    }
+}
+
+/// Tear a session down; the response holds the result of its whole run.
+async fn handle_delete_session(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Response {
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "http");
+    let path = format!("/api/v1/sessions/{session_id}");
+    if let Some(response) = route_session(&state, &caller, &headers, &session_id, Method::DELETE, &path, None).await {
+        return response;
+    }
+    match state.sessions.close(&session_id, &caller).await {
+        Ok((session, response)) => Json(json!({ "session": session, "response": response })).into_response(),
+        Err(e) => session_error(e),
+    }
+}
+
+/// Send a request for a session this instance does not hold to the one that
+/// does, if the session registry knows it; `None` leaves it to be answered
+/// here.
+async fn route_session(
+    state: &AppState,
+    caller: &Caller,
+    headers: &HeaderMap,
+    session_id: &str,
+    method: Method,
+    path: &str,
+    body: Option<Value>,
+) -> Option<Response> {
+    if state.sessions.holds(session_id) {
+        return None;
+    }
+    let registry = state.sessions.registry()?;
+    let url = registry.locate(session_id, &client_key(caller), headers).await?;
+    info!(session_id = session_id, instance = %url, message = "Routing session request to the instance holding it");
+    Some(registry.route(&url, method, path, headers, body).await)
+}
+
+fn session_error_status(e: &SessionError) -> (StatusCode, &'static str) {
+    match e {
+        SessionError::NotFound => (StatusCode::NOT_FOUND, "session_not_found"),
+        SessionError::Unsupported(_) => (StatusCode::UNPROCESSABLE_ENTITY, "unsupported"),
+        SessionError::WrongTool(_) => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_args"),
+        SessionError::LimitReached(_) => (StatusCode::TOO_MANY_REQUESTS, "session_limit"),
+    }
+}
+
+fn session_error(e: SessionError) -> Response {
+    let message = e.message();
+    let error = match e {
+        SessionError::NotFound => SandboxError::NotFound(message),
+        SessionError::Unsupported(_) => SandboxError::Unsupported(message),
+        SessionError::WrongTool(_) => SandboxError::Invalid(message),
+        SessionError::LimitReached(_) => SandboxError::Limited {
+            code: "session_limit",
+            message,
+            retry_after_secs: None,
+            subject: None,
+        },
+    };
+    error.into_response()
+}
+
+/// Several tool requests in one call; each item goes through the same
+/// admission as a single request, and the response holds every item's result.
+async fn handle_execute_batch(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Api(Json(batch)): Api<Json<BatchRequest>>,
+) -> Response {
+    let request_id = headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("none");
+
+    info!(
+        request_id = request_id,
+        method = "POST",
+        tenant = tenant_of(principal.as_deref()),
+        mode = ?batch.mode,
+        items = batch.requests.len(),
+        message = "Received batch execution request."
+    );
+
+    let max_items = state.batch.max_items;
+    if batch.requests.is_empty() || batch.requests.len() > max_items {
+        return SandboxError::Invalid(format!("a batch holds 1 to {max_items} requests")).into_response();
+    }
+    let deadline = match caller_deadline(&state, &headers) {
+        Ok(deadline) => deadline,
+        Err(response) => return response,
+    };
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "batch");
+    let (state, principal, headers) = (&state, principal.as_deref(), &headers);
+    let admit = |req: &ToolExecutionRequest| {
+        let (caller, req) = (caller.clone(), req.clone());
+        async move {
+            let admitted = admit_tool_request(state, caller, principal, headers, &req).await;
+            admitted
+                .map(|ctx| ctx.with_priority_at_most(Priority::Batch).with_deadline(deadline))
+                .map_err(|r| r.response)
+        }
+    };
+    let response = batch::execute_batch(state.executor.clone(), batch, admit).await;
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// A DAG of tool steps, each admitted like a single request once the steps it
+/// depends on have finished and its args have been filled in from theirs.
+async fn handle_execute_pipeline(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Api(Json(pipeline)): Api<Json<PipelineRequest>>,
+) -> Response {
+    let request_id = headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("none");
+
+    info!(
+        request_id = request_id,
+        method = "POST",
+        tenant = tenant_of(principal.as_deref()),
+        steps = pipeline.steps.len(),
+        message = "Received pipeline execution request."
+    );
+
+    let max_items = state.batch.max_items;
+    if pipeline.steps.is_empty() || pipeline.steps.len() > max_items {
+        return SandboxError::Invalid(format!("a pipeline holds 1 to {max_items} steps")).into_response();
+    }
+    let plan = match Plan::new(pipeline) {
+        Ok(plan) => plan,
+        Err(errors) => {
+            return SandboxError::Invalid("invalid pipeline".to_string())
+                .with_details(json!({ "errors": errors }))
+                .into_response()
+        }
+    };
+    let deadline = match caller_deadline(&state, &headers) {
+        Ok(deadline) => deadline,
+        Err(response) => return response,
+    };
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "pipeline");
+    let (state, principal, headers) = (&state, principal.as_deref(), &headers);
+    let admit = |req: &ToolExecutionRequest| {
+        let (caller, req) = (caller.clone(), req.clone());
+        async move {
+            let admitted = admit_tool_request(state, caller, principal, headers, &req).await;
+            admitted
+                .map(|ctx| ctx.with_priority_at_most(Priority::Batch).with_deadline(deadline))
+                .map_err(|r| r.response)
+        }
+    };
+    let response = pipeline::execute_pipeline(state.executor.clone(), plan, admit).await;
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// The MCP streamable HTTP transport: one JSON-RPC message or batch per
+/// `POST`, each answered with a JSON body (`202` when there is nothing to
+/// answer). The server sends no messages of its own, so there is no `GET`
+/// stream.
+async fn handle_mcp(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Response {
+    if let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) {
+        if !state.mcp.allowed_origins.iter().any(|allowed| allowed == origin) {
+            warn!(origin = origin, message = "Refused MCP request from an origin that is not allowed");
+            return SandboxError::Forbidden(format!("origin {origin} is not allowed")).into_response();
+        }
+    }
+    let payload: Value = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(mcp::parse_error(&e))).into_response(),
+    };
+    let deadline = match caller_deadline(&state, &headers) {
+        Ok(deadline) => deadline,
+        Err(response) => return response,
+    };
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "mcp");
+    let (state, principal, headers) = (&state, principal.as_deref(), &headers);
+    let admit = |req: &ToolExecutionRequest| {
+        let (caller, req) = (caller.clone(), req.clone());
+        async move {
+            let admitted = admit_tool_request(state, caller, principal, headers, &req).await;
+            admitted.map(|ctx| ctx.with_deadline(deadline)).map_err(|r| r.response)
+        }
+    };
+    match mcp::handle_payload(&state.executor, payload, &admit).await {
+        Some(answer) => (StatusCode::OK, Json(answer)).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+/// A tool's executions over the last hours, summed up from the job
+/// history. Like the history itself, limited to the caller's own tenant's
+/// unless the caller is an admin.
+async fn handle_get_tool_stats(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(name): Path<String>,
+    Api(Query(mut query)): Api<Query<StatsQuery>>,
+) -> Response {
+    let Some(history) = &state.history else {
+        return SandboxError::Disabled("job history is disabled; set history.database_url".to_string()).into_response();
+    };
+    if state.authz.authorize_admin(principal.as_deref()).is_err() {
+        let principal = principal.as_deref();
+        query.tenant = principal.and_then(|p| p.tenant.clone());
+        if query.tenant.is_none() {
+            query.subject = principal.map(|p| p.subject.clone());
+        }
+    }
+    match history.tool_stats(&name, &query).await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(e) => {
+            warn!(error = %e, message = "Tool stats query failed");
+            SandboxError::Unavailable("job history is unavailable".to_string()).into_response()
+        }
+    }
+}
+
+/// Past executions from the job history, newest first. Admins see every
+/// caller's; everyone else only their own tenant's (or their own, without a
+/// tenant).
+async fn handle_list_jobs(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Api(Query(mut query)): Api<Query<HistoryQuery>>,
+) -> Response {
+    let Some(history) = &state.history else {
+        return SandboxError::Disabled("job history is disabled; set history.database_url".to_string()).into_response();
+    };
+    if state.authz.authorize_admin(principal.as_deref()).is_err() {
+        let principal = principal.as_deref();
+        query.tenant = principal.and_then(|p| p.tenant.clone());
+        if query.tenant.is_none() {
+            query.subject = principal.map(|p| p.subject.clone());
+        }
+    }
+    match history.query(&query).await {
+        Ok(executions) => Json(json!({ "executions": executions })).into_response(),
+        Err(e) => {
+            warn!(error = %e, message = "Job history query failed");
+            SandboxError::Unavailable("job history is unavailable".to_string()).into_response()
+        }
+    }
+}
+
+/// The caller's tenant usage against its quotas. Admins may name any tenant,
+/// or list them all by naming none.
+async fn handle_get_usage(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Api(Query(query)): Api<Query<UsageQuery>>,
+) -> Response {
+    let quotas = state.executor.quotas();
+    let caller = Caller::new(principal.as_deref(), None, "http");
+    let own = quotas::tenant_of(&caller);
+    let tenant = match (query.tenant.as_deref(), own) {
+        (Some(tenant), Some(own)) if tenant == own => Some(tenant),
+        (None, Some(own)) if state.authz.authorize_admin(principal.as_deref()).is_err() => Some(own),
+        (tenant, _) => {
+            if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+                return admin_forbidden(reason);
+            }
+            tenant
+        }
+    };
+    match tenant {
+        Some(tenant) => Json(quotas.usage(tenant).await).into_response(),
+        None => Json(json!({ "tenants": quotas.usage_all().await })).into_response(),
+    }
+}
+
+async fn handle_get_job(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(job_id): Path<String>,
+) -> Response {
+    match visible_job(&state, principal.as_deref(), &job_id).await {
+        Ok(job) => (StatusCode::OK, Json(job)).into_response(),
+        Err(response) => response,
+    }
+}
+
+async fn handle_cancel_job(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(job_id): Path<String>,
+) -> Response {
+    if let Err(response) = visible_job(&state, principal.as_deref(), &job_id).await {
+        return response;
+    }
+    match state.jobs.cancel(&job_id).await {
+        CancelOutcome::Cancelled(job) => (StatusCode::OK, Json(job)).into_response(),
+        CancelOutcome::AlreadyFinished(job) => (StatusCode::CONFLICT, Json(job)).into_response(),
+        CancelOutcome::NotFound => job_not_found(&job_id),
+    }
+}
+
+/// Let a job pending approval run. The caller needs an admin role or one
+/// whose `approve` patterns cover the job's tool, and, unless
+/// `jobs.approval.allow_self_approval` is set, must not be who submitted it.
+async fn handle_approve_job(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(job_id): Path<String>,
+) -> Response {
+    if let Err(response) = check_decision(&state, principal.as_deref(), &job_id).await {
+        return response;
+    }
+    let approver = principal.as_deref().map(|p| p.subject.as_str());
+    match state.jobs.approve(&job_id, approver) {
+        Ok(job) => (StatusCode::OK, Json(job)).into_response(),
+        Err(e) => approval_error(&job_id, e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RejectRequest {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Turn down a job pending approval, with an optional `reason`; the same
+/// callers as for approving may.
+async fn handle_reject_job(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(job_id): Path<String>,
+    body: Option<Json<RejectRequest>>,
+) -> Response {
+    if let Err(response) = check_decision(&state, principal.as_deref(), &job_id).await {
+        return response;
+    }
+    let approver = principal.as_deref().map(|p| p.subject.as_str());
+    let reason = body.and_then(|Json(body)| body.reason);
+    match state.jobs.reject(&job_id, approver, reason) {
+        Ok(job) => (StatusCode::OK, Json(job)).into_response(),
+        Err(e) => approval_error(&job_id, e),
+    }
+}
+
+/// The job, if `principal` submitted it, is of its tenant or is an admin.
+/// Anyone else is told there is no such job.
+async fn visible_job(state: &AppState, principal: Option<&Principal>, job_id: &str) -> Result<JobView, Response> {
+    state
+        .jobs
+        .get(job_id)
+        .await
+        .filter(|job| {
+            owns(principal, job.requested_by.as_ref(), job.tenant.as_ref())
+                || state.authz.authorize_admin(principal).is_ok()
+        })
+        .ok_or_else(|| job_not_found(job_id))
+}
+
+/// Whether the artifact was stored for `principal` or its tenant, as
+/// [`visible_job`] decides for jobs; admins see every artifact.
+async fn visible_artifact(state: &AppState, principal: Option<&Principal>, artifact_id: &str) -> Result<bool, String> {
+    if principal.is_none() || state.authz.authorize_admin(principal).is_ok() {
+        return Ok(true);
+    }
+    let owners = state.executor.artifacts().owners(artifact_id).await?;
+    Ok(owners
+        .iter()
+        .any(|owner| owns(principal, owner.subject.as_ref(), owner.tenant.as_ref())))
+}
+
+/// Check that `principal` may decide on the job, and that it is pending.
+/// Approvers decide on the jobs of every tenant; anyone else who cannot see
+/// the job is told there is no such job rather than that they may not.
+async fn check_decision(state: &AppState, principal: Option<&Principal>, job_id: &str) -> Result<(), Response> {
+    let job = state.jobs.get(job_id).await.ok_or_else(|| job_not_found(job_id))?;
+    if let Err(reason) = state.authz.authorize_approval(principal, &job.tool_name) {
+        let visible = owns(principal, job.requested_by.as_ref(), job.tenant.as_ref())
+            || state.authz.authorize_admin(principal).is_ok();
+        if !visible {
+            return Err(job_not_found(job_id));
+        }
+        return Err(SandboxError::Forbidden(reason).into_response());
+    }
+    if job.state != JobState::PendingApproval {
+        return Err(approval_error(job_id, ApprovalError::NotPending(job.state)));
+    }
+    Ok(())
+}
+
+fn approval_error(job_id: &str, e: ApprovalError) -> Response {
+    let error = match e {
+        ApprovalError::NotFound => return job_not_found(job_id),
+        ApprovalError::NotPending(job_state) => {
+            SandboxError::Conflict(format!("the job is {}, not pending approval", job_state.as_str()))
+        }
+        ApprovalError::OwnJob => SandboxError::Forbidden(
+            "a job cannot be approved or rejected by who submitted it; set jobs.approval.allow_self_approval to allow it"
+                .to_string(),
+        ),
+        // The job stays pending, to be approved again.
+        ApprovalError::QueueFull(e) => SandboxError::Limited {
+            code: e.status(),
+            message: e.message(),
+            retry_after_secs: Some(e.retry_after_secs),
+            subject: None,
+        },
+    };
+    error.with_details(json!({ "job_id": job_id })).into_response()
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ReplayQuery {
+    /// Replay even if host variables the tool inherits have changed since.
+    #[serde(default)]
+    allow_env_changes: bool,
+}
+
+/// Run a recorded execution, named by its ID or its job's, again as a new
+/// job: the same tool version, args, stdin, input files, timeout and retry
+/// policy, without the result cache. Its inherited environment must still
+/// match the recorded one unless `allow_env_changes` is set.
+async fn handle_replay_job(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Api(Query(query)): Api<Query<ReplayQuery>>,
+) -> Response {
+    let Some(history) = state.history.as_ref().filter(|h| h.records_requests()) else {
+        return SandboxError::Disabled(
+            "execution replay is disabled; set history.database_url and history.record_requests".to_string(),
+        )
+        .into_response();
+    };
+    let recorded = match history.replayable(&id).await {
+        Ok(recorded) => recorded,
+        Err(e) => {
+            warn!(error = %e, message = "Job history query failed");
+            return SandboxError::Unavailable("job history is unavailable".to_string()).into_response();
+        }
+    };
+    // As in `GET /api/v1/jobs`, callers only see their own tenant's, and do
+    // not learn of anyone else's.
+    let admin = state.authz.authorize_admin(principal.as_deref()).is_ok();
+    let Some(Replayable { execution, request }) = recorded.filter(|r| {
+        admin || owns(principal.as_deref(), r.execution.subject.as_ref(), r.execution.tenant.as_ref())
+    }) else {
+        return SandboxError::NotFound("no recorded execution or job with this ID".to_string())
+            .with_details(json!({ "id": id }))
+            .into_response();
+    };
+    let Some(request) = request else {
+        return SandboxError::Conflict(format!(
+            "execution {} was recorded without its request",
+            execution.execution_id
+        ))
+        .into_response();
+    };
+    let registry = state.executor.registry();
+    let Some(tool) = registry.get(&request.tool_name).filter(|tool| tool.id() == request.tool_name) else {
+        return SandboxError::Conflict(format!("{} is no longer registered", request.tool_name)).into_response();
+    };
+    let env = state.executor.env_fingerprint(tool);
+    let changed: Vec<&String> = env
+        .keys()
+        .chain(request.env.keys())
+        .filter(|name| env.get(*name) != request.env.get(*name))
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if !changed.is_empty() {
+        if !query.allow_env_changes {
+            return SandboxError::Conflict(format!(
+                "the environment {} inherits has changed since execution {}",
+                request.tool_name, execution.execution_id
+            ))
+            .with_details(json!({ "changed_env": changed }))
+            .into_response();
+        }
+        warn!(
+            execution_id = %execution.execution_id,
+            changed_env = ?changed,
+            message = "Replaying with a changed environment"
+        );
+    }
+
+    let payload = ToolExecutionRequest {
+        tool_name: request.tool_name,
+        args: request.args,
+        timeout_ms: Some(request.timeout_ms),
+        retain_workspace: request.retain_workspace,
+        artifacts: request.artifacts,
+        stdin: request.stdin,
+        input_files: request.input_files,
+        session_id: None,
+        retry: request.retry,
+        callback_url: None,
+        priority: None,
+        dry_run: false,
+        record_trace: false,
+        replay_trace: None,
+    };
+    let caller = Caller::new(principal.as_deref(), Some(peer.ip()), "job");
+    let replay_of = execution.execution_id.clone();
+    let setup = {
+        let replay_of = replay_of.clone();
+        move |ctx: ExecutionContext| {
+            ctx.with_priority_at_most(Priority::Background)
+                .with_cache_bypass(true)
+                .with_replay_of(replay_of.clone())
+        }
+    };
+    let job = match submit_job(&state, caller, principal.as_deref(), &headers, payload, Some(replay_of), setup).await {
+        Ok(job) => job,
+        Err(rejection) => return rejection.into_response(),
+    };
+    info!(
+        execution_id = %execution.execution_id,
+        tool_name = job.tool_name,
+        job_id = job.job_id,
+        message = "Replaying execution"
+    );
+    (StatusCode::ACCEPTED, Json(job)).into_response()
+}
+
+/// Whether what `subject` of `tenant` asked for is something `principal`
+/// may see without being an admin.
+fn owns(principal: Option<&Principal>, subject: Option<&String>, tenant: Option<&String>) -> bool {
+    match principal {
+        Some(Principal { tenant: Some(own), .. }) => tenant == Some(own),
+        Some(principal) => subject == Some(&principal.subject),
+        None => true,
+    }
+}
+
+/// The contents of a collected artifact, by its SHA-256.
+async fn handle_get_artifact(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(artifact_id): Path<String>,
+) -> Response {
+    let object = match visible_artifact(&state, principal.as_deref(), &artifact_id).await {
+        Ok(true) => state.executor.artifacts().get(&artifact_id).await,
+        Ok(false) => Ok(None),
+        Err(e) => Err(e),
+    };
+    match object {
+        Ok(Some(object)) => {
+            let size = object.meta.size;
+            (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                    (header::CONTENT_LENGTH, size.to_string()),
+                    (header::ETAG, format!("\"{artifact_id}\"")),
+                ],
+                Body::from_stream(object.into_stream()),
+            )
+                .into_response()
+        }
+        // Artifacts stored for someone else are not found either.
+        Ok(None) => SandboxError::NotFound("artifact not found".to_string())
+            .with_details(json!({ "artifact_id": artifact_id }))
+            .into_response(),
+        Err(e) => {
+            warn!(artifact_id = %artifact_id, error = %e, message = "Failed to read artifact");
+            SandboxError::Unavailable("artifact store is unavailable".to_string()).into_response()
+        }
+    }
+}
+
+/// Authorize, validate and admit a tool request before it runs. Rejections
+/// are audited here; executions are audited by the executor.
+async fn admit_tool_request(
+    state: &AppState,
+    caller: Caller,
+    principal: Option<&Principal>,
+    headers: &HeaderMap,
+    payload: &ToolExecutionRequest,
+) -> Result<ExecutionContext, Rejection> {
+    check_placement(payload)?;
+    check_tool_request(state, &caller, principal, payload).await?;
+    request_context(&state.executor, caller.clone(), headers, payload).map_err(|e| {
+        let response = queue_full_response(&payload.tool_name, &e);
+        state.executor.audit().record(&caller, &payload.tool_name, &payload.args, &response.status);
+        queue_full_rejection(&e, response)
+    })
+}
+
+/// Refuse fields that only some endpoints take.
+fn check_placement(payload: &ToolExecutionRequest) -> Result<(), Rejection> {
+    let misplaced = if payload.session_id.is_some() {
+        Some("session_id is only accepted by /api/v1/execute_tool")
+    } else if payload.callback_url.is_some() {
+        Some("callback_url is only accepted by /api/v1/jobs")
+    } else if payload.dry_run {
+        Some("dry_run is only accepted by /api/v1/execute_tool")
+    } else {
+        None
+    };
+    match misplaced {
+        Some(error) => Err(Rejection {
+            code: StatusCode::UNPROCESSABLE_ENTITY,
+            retry_after_secs: None,
+            response: ToolExecutionResponse {
+                status: "invalid_args".to_string(),
+                tool_name: payload.tool_name.clone(),
+                result: json!({ "error": error }),
+            },
+        }),
+        None => Ok(()),
+    }
+}
+
+/// A place in the scheduler queue for `payload`, set up as the request asks.
+fn request_context(
+    executor: &Executor,
+    caller: Caller,
+    headers: &HeaderMap,
+    payload: &ToolExecutionRequest,
+) -> Result<ExecutionContext, QueueFull> {
+    let ctx = executor.context(&payload.tool_name, payload.timeout_ms)?;
+    Ok(ctx
+        .with_caller(caller)
+        .with_retained_workspace(payload.retain_workspace)
+        .with_artifacts(payload.artifacts.clone())
+        .with_input(request_input(payload))
+        .with_retry(payload.retry.clone())
+        .with_trace(request_tracer(payload))
+        .with_forwarded_auth(ForwardedAuth::from_headers(headers))
+        .with_priority_at_most(payload.priority.unwrap_or_default())
+        .with_cache_bypass(cache::bypass_requested(
+            headers
+                .get(header::CACHE_CONTROL)
+                .and_then(|v| v.to_str().ok()),
+        )))
+}
+
+fn queue_full_rejection(e: &QueueFull, response: ToolExecutionResponse) -> Rejection {
+    let code = match e.draining {
+        true => StatusCode::SERVICE_UNAVAILABLE,
+        false => StatusCode::TOO_MANY_REQUESTS,
+    };
+    Rejection {
+        code,
+        retry_after_secs: Some(e.retry_after_secs),
+        response,
+    }
+}
+
+/// Submit `payload` as a job, set up by `setup` on top of what the request
+/// asks for. A tool that requires approval is checked as for any job but
+/// held: it is admitted to the queue only once it has been approved.
+async fn submit_job(
+    state: &AppState,
+    caller: Caller,
+    principal: Option<&Principal>,
+    headers: &HeaderMap,
+    mut payload: ToolExecutionRequest,
+    replay_of: Option<String>,
+    setup: impl Fn(ExecutionContext) -> ExecutionContext + Send + Sync + 'static,
+) -> Result<JobView, Rejection> {
+    // Set aside so that admission, which refuses callbacks elsewhere, lets it
+    // through; the caller has checked it.
+    let callback_url = payload.callback_url.take();
+    if !state.executor.requires_approval(&payload.tool_name) {
+        let ctx = admit_tool_request(state, caller, principal, headers, &payload).await?;
+        payload.callback_url = callback_url;
+        return Ok(state.jobs.submit(state.executor.clone(), payload, setup(ctx)));
+    }
+    check_placement(&payload)?;
+    check_tool_request(state, &caller, principal, &payload).await?;
+    let admit: Admit = {
+        let (executor, caller, headers, payload) =
+            (state.executor.clone(), caller.clone(), headers.clone(), payload.clone());
+        Box::new(move || Ok(setup(request_context(&executor, caller.clone(), &headers, &payload)?)))
+    };
+    payload.callback_url = callback_url;
+    Ok(state.jobs.hold(state.executor.clone(), payload, &caller, replay_of, admit))
+}
+
+fn authorize_tool_request(
+    state: &AppState,
+    caller: &Caller,
+    principal: Option<&Principal>,
+    payload: &ToolExecutionRequest,
+) -> Result<(), Rejection> {
+    let reject = |code: StatusCode, response: ToolExecutionResponse| {
+        state.executor.audit().record(caller, &payload.tool_name, &payload.args, &response.status);
+        Rejection {
+            code,
+            retry_after_secs: None,
+            response,
+        }
+    };
+    if let Err(reason) = state
+        .authz
+        .authorize_execution(principal, caller.transport, &payload.tool_name, &payload.args)
+    {
+        return Err(reject(StatusCode::FORBIDDEN, forbidden(payload.tool_name.clone(), reason)));
+    }
+    if let Err(response) = validate_tool_request(&state.executor, payload) {
+        return Err(reject(StatusCode::UNPROCESSABLE_ENTITY, response));
+    }
+    Ok(())
+}
+
+/// Authorize, validate and rate limit a tool request, auditing a rejection.
+async fn check_tool_request(
+    state: &AppState,
+    caller: &Caller,
+    principal: Option<&Principal>,
+    payload: &ToolExecutionRequest,
+) -> Result<(), Rejection> {
+    authorize_tool_request(state, caller, principal, payload)?;
+    let audit = state.executor.audit();
+    let reject = |code: StatusCode, retry_after_secs: Option<u64>, response: ToolExecutionResponse| {
+        audit.record(caller, &payload.tool_name, &payload.args, &response.status);
+        Rejection {
+            code,
+            retry_after_secs,
+            response,
+        }
+    };
+    // Unknown tools are not limited: they run nothing, and their names would
+    // otherwise become bucket keys.
+    if state.executor.registry().get(&payload.tool_name).is_some() {
+        if let Err(e) = state.ratelimit.check(caller, &payload.tool_name).await {
+            let response = rate_limited_response(&payload.tool_name, &e);
+            return Err(reject(StatusCode::TOO_MANY_REQUESTS, Some(e.retry_after_secs), response));
+        }
+        if let Err(e) = state.executor.quotas().check(caller).await {
+            let response = quota_exceeded_response(&payload.tool_name, &e);
+            return Err(reject(StatusCode::TOO_MANY_REQUESTS, e.retry_after_secs, response));
+        }
+        let estimate = state.executor.estimate_cost(&payload.tool_name, payload.timeout_ms);
+        if let Err(e) = state.executor.quotas().check_budget(caller, estimate.unwrap_or_default()).await {
+            let response = budget_exceeded_response(&payload.tool_name, &e);
+            return Err(reject(StatusCode::TOO_MANY_REQUESTS, Some(e.retry_after_secs), response));
+        }
+    }
+    Ok(())
+}
+
+/// A tool request turned away before it ran.
+struct Rejection {
+    code: StatusCode,
+    retry_after_secs: Option<u64>,
+    response: ToolExecutionResponse,
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let mut response = (self.code, Json(self.response)).into_response();
+        if let Some(secs) = self.retry_after_secs {
+            response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+        }
+        response
+    }
+}
+
+/// The body for a caller whose credentials do not cover the requested tool.
+fn forbidden(tool_name: String, reason: String) -> ToolExecutionResponse {
+    ToolExecutionResponse {
+        status: "forbidden".to_string(),
+        tool_name,
+        result: json!({ "error": reason }),
+    }
+}
+
+/// Every schedule with its recent runs. Schedules run unattended, so the
+/// schedules API is for admins.
+async fn handle_list_schedules(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    Json(json!({ "schedules": state.schedules.list() })).into_response()
+}
+
+/// Add a schedule. Its tool and args are checked as for an execution by the
+/// caller, on whose authority every run then happens.
+async fn handle_create_schedule(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Api(Json(spec)): Api<Json<ScheduleSpec>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    if let Err(reason) = state
+        .authz
+        .authorize_execution(principal.as_deref(), "schedule", &spec.tool_name, &spec.args)
+    {
+        return (StatusCode::FORBIDDEN, Json(forbidden(spec.tool_name.clone(), reason))).into_response();
+    }
+    if let Err(response) = validate_tool_request(&state.executor, &spec.request()) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response();
+    }
+    info!(
+        tenant = tenant_of(principal.as_deref()),
+        schedule_id = spec.id,
+        tool_name = spec.tool_name,
+        message = "Received schedule creation request."
+    );
+    match state.schedules.create(spec, principal.map(|Extension(p)| p)) {
+        Ok(schedule) => (StatusCode::CREATED, Json(schedule)).into_response(),
+        Err(e) => schedule_error(e),
+    }
+}
+
+async fn handle_get_schedule(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(schedule_id): Path<String>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    match state.schedules.get(&schedule_id) {
+        Ok(schedule) => Json(schedule).into_response(),
+        Err(e) => schedule_error(e),
+    }
+}
+
+/// Enable or disable a schedule: `{"enabled": false}`.
+async fn handle_update_schedule(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(schedule_id): Path<String>,
+    Api(Json(update)): Api<Json<ScheduleUpdate>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    match state.schedules.set_enabled(&schedule_id, update.enabled) {
+        Ok(schedule) => Json(schedule).into_response(),
+        Err(e) => schedule_error(e),
+    }
+}
+
+async fn handle_delete_schedule(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(schedule_id): Path<String>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    match state.schedules.remove(&schedule_id) {
+        Ok(schedule) => Json(schedule).into_response(),
+        Err(e) => schedule_error(e),
+    }
+}
+
+fn schedule_error(e: ScheduleError) -> Response {
+    let message = e.message();
+    let error = match e {
+        ScheduleError::NotFound => SandboxError::NotFound(message),
+        ScheduleError::Exists => SandboxError::Conflict(message),
+        ScheduleError::Invalid(_) => SandboxError::Invalid(message),
+        ScheduleError::LimitReached(_) => SandboxError::Limited {
+            code: "schedule_limit",
+            message,
+            retry_after_secs: None,
+            subject: None,
+        },
+    };
+    error.into_response()
+}
+
+#[derive(Deserialize)]
+struct QuarantineQuery {
+    state: Option<QuarantineState>,
+}
+
+/// Quarantined executions, oldest first.
+async fn handle_list_quarantine(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Api(Query(query)): Api<Query<QuarantineQuery>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    if let Some(response) = quarantine_disabled(&state) {
+        return response;
+    }
+    Json(json!({ "entries": state.executor.quarantine().list(query.state) })).into_response()
+}
+
+async fn handle_get_quarantined(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    if let Some(response) = quarantine_disabled(&state) {
+        return response;
+    }
+    match state.executor.quarantine().get(&id) {
+        Some(entry) => Json(entry).into_response(),
+        None => quarantine_error(QuarantineError::NotFound),
+    }
+}
+
+/// Release a quarantined execution's artifacts; they can then be fetched
+/// by the ids its entry lists.
+async fn handle_approve_quarantined(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    if let Some(response) = quarantine_disabled(&state) {
+        return response;
+    }
+    let by = principal.as_deref().map(|p| p.subject.as_str());
+    match state.executor.approve_quarantined(&id, by).await {
+        Ok(entry) => Json(entry).into_response(),
+        Err(e) => quarantine_error(e),
+    }
+}
+
+/// Delete a quarantined execution's artifacts.
+async fn handle_reject_quarantined(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    if let Some(response) = quarantine_disabled(&state) {
+        return response;
+    }
+    let by = principal.as_deref().map(|p| p.subject.as_str());
+    match state.executor.quarantine().reject(&id, by).await {
+        Ok(entry) => Json(entry).into_response(),
+        Err(e) => quarantine_error(e),
+    }
+}
+
+fn quarantine_disabled(state: &AppState) -> Option<Response> {
+    (!state.executor.quarantine().enabled()).then(|| {
+        SandboxError::Disabled("quarantine is off; set executor.quarantine.enabled".to_string()).into_response()
+    })
+}
+
+fn quarantine_error(e: QuarantineError) -> Response {
+    let message = e.message();
+    let error = match e {
+        QuarantineError::NotFound => SandboxError::NotFound(message),
+        QuarantineError::Decided(_) => SandboxError::Conflict(message),
+        QuarantineError::Store(_) => SandboxError::Unavailable(message),
+    };
+    error.into_response()
+}
+
+fn job_not_found(job_id: &str) -> Response {
+    SandboxError::NotFound("job not found".to_string())
+        .with_details(json!({ "job_id": job_id }))
+        .into_response()
+}
+
+async fn handle_get_rbac_policy(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    Json(json!({ "policy": state.authz.policy() })).into_response()
+}
+
+async fn handle_put_rbac_policy(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Api(Json(policy)): Api<Json<RbacPolicy>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    match state.authz.set_policy(policy) {
+        Ok(()) => Json(json!({ "policy": state.authz.policy() })).into_response(),
+        Err(e) => SandboxError::Invalid(format!("invalid policy: {e}")).into_response(),
+    }
+}
+
+/// What the execution policy decides for a request, without running it or
+/// using the decision cache.
+async fn handle_evaluate_policy(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Api(Json(request)): Api<Json<EvaluateRequest>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    let Some(engine) = state.authz.engine() else {
+        return SandboxError::Disabled("no execution policy is configured; set policy.policy_file".to_string())
+            .into_response();
+    };
+    let input = request.input();
+    match engine.evaluate(&input) {
+        Ok(decision) => Json(json!({
+            "allow": decision.allow,
+            "reason": decision.reason,
+            "engine": engine.language(),
+            "input": input,
+        }))
+        .into_response(),
+        Err(e) => SandboxError::Invalid(format!("the policy failed to evaluate: {e}")).into_response(),
+    }
+}
+
+/// Recent audit entries, newest first, filtered by the query parameters.
+async fn handle_query_audit(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Api(Query(query)): Api<Query<AuditQuery>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    Json(json!({ "entries": state.executor.audit().query(&query) })).into_response()
+}
+
+/// The effective configuration, with API keys and signing secrets redacted.
+async fn handle_get_config(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    let settings = state.reloader.settings();
+    Json(json!({
+        "source": settings.source,
+        "config": settings.redacted(),
+    }))
+    .into_response()
+}
+
+#[derive(Deserialize)]
+struct LogLevelUpdate {
+    /// `EnvFilter` directives, e.g. `info,tool_executor=debug`.
+    directives: String,
+    /// One sink (`console`, `file`, `syslog`, `otlp`) instead of all.
+    #[serde(default)]
+    sink: Option<String>,
+}
+
+/// Each running log sink's filter directives.
+async fn handle_get_log_level(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    Json(json!({ "sinks": state.log_levels.current() })).into_response()
+}
+
+/// Replace the log filter of one sink or all of them, until the next change
+/// or restart.
+async fn handle_put_log_level(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Api(Json(update)): Api<Json<LogLevelUpdate>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    if let Err(e) = state.log_levels.set(update.sink.as_deref(), &update.directives) {
+        return SandboxError::Invalid(e).into_response();
+    }
+    warn!(
+        subject = principal.as_deref().map(|p| p.subject.as_str()),
+        sink = update.sink.as_deref().unwrap_or("all"),
+        directives = %update.directives,
+        message = "Log level changed"
+    );
+    Json(json!({ "sinks": state.log_levels.current() })).into_response()
+}
+
+/// Put every log sink back at its configured level.
+async fn handle_reset_log_level(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    if let Err(e) = state.log_levels.reset() {
+        return SandboxError::Internal(e).into_response();
+    }
+    Json(json!({ "sinks": state.log_levels.current() })).into_response()
+}
+
+/// What waits in the work queue and the workers serving it.
+async fn handle_get_queue(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    let Some(queue) = &state.queue else {
+        return SandboxError::Disabled("the work queue is disabled; set queue.redis_url".to_string()).into_response();
+    };
+    match queue.status().await {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => {
+            warn!(error = %e, message = "Work queue status query failed");
+            SandboxError::Unavailable("the work queue is unavailable".to_string()).into_response()
+        }
+    }
+}
+
+/// This node and the peers it knows of.
+async fn handle_get_cluster(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    match &state.cluster {
+        Some(cluster) => Json(cluster.view()).into_response(),
+        None => cluster_disabled(),
+    }
+}
+
+/// What this node advertises; polled by peers with static discovery, so it
+/// only needs an API key.
+async fn handle_get_cluster_node(State(state): State<AppState>) -> Response {
+    match &state.cluster {
+        Some(cluster) => Json(cluster.node()).into_response(),
+        None => cluster_disabled(),
+    }
+}
+
+fn cluster_disabled() -> Response {
+    SandboxError::Disabled("clustering is disabled; set cluster.advertise_url".to_string()).into_response()
+}
+
+/// The configuration version in effect and when it was loaded.
+async fn handle_get_reload(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    Json(state.reloader.status()).into_response()
+}
+
+/// Re-read tool manifests and the RBAC policy file; `422` with every error,
+/// and nothing changed, if any of them is invalid.
+async fn handle_reload(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    match state.reloader.reload() {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => SandboxError::Invalid(format!("reload rejected: {e}"))
+            .with_details(json!({
+                "errors": e.errors,
+                "version": state.reloader.status().version,
+            }))
+            .into_response(),
+    }
+}
+
+/// What this instance is running and has queued, with their limits and the
+/// processes they run.
+async fn handle_list_executions(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    Json(json!({
+        "draining": state.executor.draining(),
+        "in_flight": state.executor.in_flight(),
+        "queued": state.executor.queued(),
+        "executions": state.executor.executions(),
+    }))
+    .into_response()
+}
+
+/// Cancel a running or queued execution; it ends with status `cancelled`.
+async fn handle_kill_execution(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(execution_id): Path<String>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    if !state.executor.kill_execution(&execution_id) {
+        return SandboxError::NotFound("no such execution on this instance".to_string()).into_response();
+    }
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({ "execution_id": execution_id, "status": "cancelling" })),
+    )
+        .into_response()
+}
+
+fn drain_status(state: &AppState) -> Json<Value> {
+    Json(json!({
+        "draining": state.executor.draining(),
+        "in_flight": state.executor.in_flight(),
+        "queued": state.executor.queued(),
+    }))
 }
 
-fn init_logging(log_level: &str) {
-    let level = log_level.parse::<Level>().unwrap_or(Level::INFO);
+async fn handle_get_drain(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    drain_status(&state).into_response()
+}
 
-    let subscriber = Registry::default().with(
-        tracing_subscriber::fmt::layer()
-            .json()
-            .with_current_span(false)
-            .with_target(true)
-            .with_level(true)
-            .with_filter(
-                tracing_subscriber::EnvFilter::from_default_env().add_directive(level.into()),
-            ),
+/// Take no new executions, and fail readiness so that the load balancer
+/// stops sending any; running and queued ones carry on.
+async fn handle_start_drain(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    state.executor.set_draining(true);
+    info!(
+        subject = principal.as_deref().map(|p| p.subject.as_str()),
+        in_flight = state.executor.in_flight(),
+        message = "Draining: new executions are turned away"
     );
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("Unable to set global tracing subscriber");
+    drain_status(&state).into_response()
+}
+
+async fn handle_stop_drain(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    state.executor.set_draining(false);
+    info!(
+        subject = principal.as_deref().map(|p| p.subject.as_str()),
+        message = "Draining stopped: new executions are taken again"
+    );
+    drain_status(&state).into_response()
+}
+
+/// Every loaded tool and whether it is turned on.
+async fn handle_list_tool_states(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    let tools: Vec<_> = state
+        .executor
+        .tool_states()
+        .into_iter()
+        .map(|(name, backend, enabled)| json!({ "name": name, "backend": backend, "enabled": enabled }))
+        .collect();
+    Json(json!({ "tools": tools })).into_response()
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToolStateUpdate {
+    enabled: bool,
+}
+
+/// Turn a tool off or on; one that is off looks unknown to every front end
+/// until it is turned on again, reloads included.
+async fn handle_set_tool_state(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(name): Path<String>,
+    Api(Json(update)): Api<Json<ToolStateUpdate>>,
+) -> Response {
+    if let Err(reason) = state.authz.authorize_admin(principal.as_deref()) {
+        return admin_forbidden(reason);
+    }
+    if !state.executor.set_tool_enabled(&name, update.enabled) {
+        return SandboxError::NotFound(format!("no tool named {name:?} is loaded")).into_response();
+    }
+    Json(json!({ "name": name, "enabled": update.enabled })).into_response()
+}
+
+async fn handle_unknown_route(method: Method, uri: axum::http::Uri) -> SandboxError {
+    SandboxError::NotFound(format!("no route for {method} {}", uri.path()))
+}
+
+fn admin_forbidden(reason: String) -> Response {
+    SandboxError::Forbidden(reason).into_response()
+}
+
+/// The OpenAPI document, with the currently registered tools' args schemas.
+async fn handle_openapi(State(state): State<AppState>) -> Json<Value> {
+    Json(openapi::document(&state.executor))
+}
+
+/// List every registered tool with its description, backend, args schema and
+/// limits; a versioned tool as its latest version, with the versions there are.
+async fn list_tools(State(state): State<AppState>) -> Json<Value> {
+    let registry = state.executor.registry();
+    let tools: Vec<_> = registry
+        .tools()
+        .map(|t| {
+            let mut entry = json!(t.summary());
+            if t.version.is_some() {
+                entry["versions"] = json!(registry
+                    .versions(&t.name)
+                    .iter()
+                    .rev()
+                    .filter_map(|v| v.version.as_ref())
+                    .collect::<Vec<_>>());
+            }
+            entry
+        })
+        .collect();
+    Json(json!({ "tools": tools }))
+}
+
+/// One tool as a request naming it would resolve (`name@^1.2` works too),
+/// with the changelog of each of its versions, newest first.
+async fn get_tool(State(state): State<AppState>, Path(name): Path<String>) -> Response {
+    let registry = state.executor.registry();
+    let Some(tool) = registry.get(&name) else {
+        return SandboxError::NotFound(format!("no tool matches {name:?}")).into_response();
+    };
+    let mut body = json!(tool.summary());
+    body["changelog"] = json!(tool.changelog);
+    let versions: Vec<_> = registry
+        .versions(&tool.name)
+        .iter()
+        .rev()
+        .filter(|t| t.version.is_some())
+        .map(|t| json!({ "version": t.version, "deprecated": t.deprecated, "changelog": t.changelog }))
+        .collect();
+    body["versions"] = json!(versions);
+    Json(body).into_response()
+}
+
+/// Reload on every SIGHUP until the process exits.
+fn spawn_reload_on_sighup(reloader: Arc<Reloader>) {
+    let mut hangup = signal(SignalKind::hangup()).expect("Unable to install SIGHUP handler");
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!(message = "SIGHUP received; reloading");
+            // Failures are logged by the reloader.
+            let _ = reloader.reload();
+        }
+    });
+}
+
+/// Step every log sink to debug, then trace, then back to its configured
+/// level on each SIGUSR1.
+fn spawn_log_level_cycle_on_sigusr1(log_levels: Arc<LogLevels>) {
+    let mut user1 = signal(SignalKind::user_defined1()).expect("Unable to install SIGUSR1 handler");
+    tokio::spawn(async move {
+        while user1.recv().await.is_some() {
+            match log_levels.cycle() {
+                Ok(level) => warn!(level, message = "SIGUSR1 received; log level changed"),
+                Err(e) => warn!(error = %e, message = "SIGUSR1 received; log level unchanged"),
+            }
+        }
+    });
+}
+
+/// Resolve on the first SIGTERM or SIGINT.
+async fn shutdown_signal() {
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("Unable to install SIGTERM handler");
+    let name = tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+    };
+    info!(signal = name, message = "Shutdown requested; no longer accepting requests");
+}
+
+/// Wait for a server task unless it has already finished (a finished
+/// `JoinHandle` must not be polled again).
+async fn join_server(task: &mut JoinHandle<()>) {
+    if !task.is_finished() {
+        let _ = task.await;
+    }
 }
 
 #[tokio::main]
 async fn main() {
+    // Before anything reads a credential, so no tool can read it back out.
+    if let Err(e) = namespace::make_undumpable() {
+        panic!("Unable to protect the sandbox's process: {e}");
+    }
+    // A plugin host, started by the sandbox itself to run one plugin library.
+    if let Some(path) = plugins::host_argument() {
+        plugins::host_main(&path);
+    }
+    // A load generator, driving another instance's execute endpoints.
+    if let Some(args) = bench::arguments() {
+        std::process::exit(bench::run(args).await);
+    }
     // Load .env for bare metal if needed
     dotenvy::dotenv().ok();
+    // Serve MCP on stdin/stdout for a local client instead of starting the
+    // HTTP and gRPC servers.
+    let mcp_stdio = std::env::args().skip(1).any(|arg| arg == "--mcp-stdio");
 
-    let port_str = env::var("RUST_SANDBOX_PORT").unwrap_or_else(|_| DEFAULT_PORT.to_string());
-    let grpc_port_str =
-        env::var("RUST_SANDBOX_GRPC_PORT").unwrap_or_else(|_| DEFAULT_GRPC_PORT.to_string());
-    let log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string());
-    let port = port_str.parse::<u16>().unwrap_or(DEFAULT_PORT);
-    let grpc_port = grpc_port_str.parse::<u16>().unwrap_or(DEFAULT_GRPC_PORT);
+    // Every setting is checked before anything starts; a bad value is a
+    // deployment error, reported all at once.
+    let settings = Arc::new(
+        Settings::load().unwrap_or_else(|e| panic!("Unable to load configuration: {e}")),
+    );
+    let port = settings.server.http_port;
+    let single_port = settings.server.single_port;
+    let grpc_port = if single_port { port } else { settings.server.grpc_port };
+    let drain_period = Duration::from_secs(settings.server.shutdown_drain_secs);
 
-    init_logging(&log_level);
+    // Shut down explicitly on exit so buffered spans are exported.
+    let tracer_provider = telemetry::init_tracer(
+        SERVICE_NAME,
+        settings.telemetry.otlp_endpoint.as_deref(),
+    );
+    let logger_provider = settings
+        .telemetry
+        .otlp_endpoint
+        .as_deref()
+        .filter(|_| settings.logging.otlp)
+        .map(|endpoint| telemetry::init_logger(SERVICE_NAME, endpoint));
+    // Logs go to stdout, or to stderr when stdout carries a protocol.
+    let log_levels = logging::init(
+        &settings.logging,
+        &settings.server.log_level,
+        tracer_provider.as_ref().map(|(_, t)| t.clone()),
+        logger_provider.as_ref(),
+        mcp_stdio,
+    )
+    .map(Arc::new)
+    .unwrap_or_else(|e| panic!("Unable to set up logging: {e}"));
+    info!(
+        config_file = settings
+            .source
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "none".to_string()),
+        message = "Loaded configuration"
+    );
 
     // Bind to all interfaces so it works in Docker and bare metal.
     let http_addr = SocketAddr::from(([0, 0, 0, 0], port));
     let grpc_addr = SocketAddr::from(([0, 0, 0, 0], grpc_port));
 
+    if !mcp_stdio {
+        info!(
+            service = SERVICE_NAME,
+            version = VERSION,
+            http_port = port,
+            grpc_port = grpc_port,
+            message = "Starting servers..."
+        );
+    }
+
+    let config = settings.executor.clone();
+    let verifier = Arc::new(
+        ToolVerifier::new(&config.signing).unwrap_or_else(|e| panic!("Unable to load tool signing keys: {e}")),
+    );
+    // Plugins are loaded once, at startup; manifest reloads keep the tools
+    // they brought.
+    if let Some(dir) = &config.plugin_dir {
+        let count = plugins::load(dir, &registry::builtin_names(), &verifier)
+            .await
+            .unwrap_or_else(|e| panic!("Unable to load tool plugins: {e}"));
+        info!(plugin_dir = %dir.display(), tools = count, message = "Loaded tool plugins");
+    }
+    // A broken manifest is a deployment error; refuse to start rather than
+    // silently serve a partial tool set.
+    let registry = ToolRegistry::load(&config.manifest_dir, &verifier)
+        .unwrap_or_else(|e| panic!("Unable to load tool manifests: {e}"));
     info!(
-        service = SERVICE_NAME,
-        version = VERSION,
-        http_port = port,
-        grpc_port = grpc_port,
-        message = "Starting servers..."
+        manifest_dir = %config.manifest_dir.display(),
+        tools = registry.len(),
+        message = "Loaded tool registry"
     );
+    let audit = Arc::new(
+        AuditLog::open(&settings.audit).unwrap_or_else(|e| panic!("Unable to open audit log: {e}")),
+    );
+    let history = History::connect(&settings.history)
+        .await
+        .unwrap_or_else(|e| panic!("Unable to open job history: {e}"));
+    let cache = ResultCache::connect(&settings.cache)
+        .await
+        .unwrap_or_else(|e| panic!("Unable to set up the result cache: {e}"));
+    let idempotency = Arc::new(
+        Idempotency::connect(&settings.idempotency, Duration::from_millis(config.max_timeout_ms))
+            .await
+            .unwrap_or_else(|e| panic!("Unable to set up the idempotency store: {e}")),
+    );
+    let events = EventBus::start(&settings.events).unwrap_or_else(|e| panic!("Unable to set up the event bus: {e}"));
+    let shared_limits = SharedLimits::start(&settings.shared_limits)
+        .unwrap_or_else(|e| panic!("Unable to set up the shared limits store: {e}"));
+    let quotas = Quotas::load(
+        settings.quotas.clone(),
+        Duration::from_secs(config.artifacts.ttl_secs),
+        history.clone(),
+        shared_limits.clone(),
+    )
+    .await
+    .unwrap_or_else(|e| panic!("Unable to load tenant usage: {e}"));
+    let chaos = chaos::Chaos::new(settings.chaos.clone());
+    let executor = Arc::new(
+        Executor::new(
+            config,
+            registry,
+            verifier,
+            audit.clone(),
+            history.clone(),
+            quotas,
+            cache,
+            events.clone(),
+            chaos.clone(),
+        )
+        .expect("Unable to initialise tool executor"),
+    );
+    executor.prewarm();
+    let authz = Arc::new(
+        Authorizer::new(&settings.rbac, &settings.policy)
+            .unwrap_or_else(|e| panic!("Unable to load access policies: {e}")),
+    );
+    let auth = Arc::new(Authenticator::new(settings.auth.clone()));
+    auth.spawn_refreshers();
 
-    let app = Router::new()
-        .route("/health", get(health_check))
+    let ratelimit = Arc::new(RateLimiter::new(settings.rate_limit.clone(), shared_limits.clone()));
+    let reloader = Arc::new(Reloader::new(
+        settings.clone(),
+        executor.clone(),
+        authz.clone(),
+        ratelimit.clone(),
+    ));
+    spawn_reload_on_sighup(reloader.clone());
+    spawn_log_level_cycle_on_sigusr1(log_levels.clone());
+
+    let shutdown = CancellationToken::new();
+    let queue = WorkQueue::connect(&settings.queue)
+        .await
+        .unwrap_or_else(|e| panic!("Unable to connect to the work queue: {e}"));
+    if let Some(queue) = &queue {
+        executor.set_work_queue(queue.clone());
+        queue.spawn_worker(
+            executor.clone(),
+            settings.executor.max_concurrent_executions,
+            shutdown.clone(),
+        );
+    }
+    let sessions = Arc::new(
+        SessionManager::new(executor.clone(), settings.sessions.clone())
+            .await
+            .unwrap_or_else(|e| panic!("Unable to connect to the session registry: {e}")),
+    );
+    sessions.spawn_reaper(shutdown.clone());
+    let schedules = Schedules::start(
+        executor.clone(),
+        authz.clone(),
+        shared_limits,
+        &settings.schedules,
+        shutdown.clone(),
+    )
+    .unwrap_or_else(|e| panic!("Unable to set up schedules: {e}"));
+    let cluster = Cluster::start(&settings.cluster, executor.clone(), shutdown.clone())
+        .unwrap_or_else(|e| panic!("Unable to join the sandbox cluster: {e}"));
+    let readiness = Arc::new(Readiness::new(executor.clone(), auth.clone(), shutdown.clone()));
+    let grpc_readiness = readiness.clone();
+    let webhooks = Arc::new(
+        Webhooks::new(&settings.webhooks).unwrap_or_else(|e| panic!("Unable to set up job callbacks: {e}")),
+    );
+    let state = AppState {
+        executor: executor.clone(),
+        jobs: Arc::new(JobStore::new(&settings.jobs, history.clone(), webhooks.clone())),
+        webhooks: webhooks.clone(),
+        history: history.clone(),
+        authz: authz.clone(),
+        readiness,
+        ratelimit: ratelimit.clone(),
+        idempotency: idempotency.clone(),
+        reloader,
+        batch: settings.batch.clone(),
+        sessions: sessions.clone(),
+        schedules,
+        mcp: settings.mcp.clone(),
+        queue: queue.clone(),
+        cluster,
+        log_levels: log_levels.clone(),
+    };
+
+    if mcp_stdio {
+        // The local client is whoever started the process; RBAC sees it as
+        // anonymous.
+        let stdio_state = state.clone();
+        let admit = move |req: &ToolExecutionRequest| {
+            let (state, req) = (stdio_state.clone(), req.clone());
+            async move {
+                let caller = Caller::new(None, None, "mcp");
+                let admitted = admit_tool_request(&state, caller, None, &HeaderMap::new(), &req).await;
+                admitted.map_err(|r| r.response)
+            }
+        };
+        mcp::serve_stdio(executor.clone(), Arc::new(admit)).await;
+        shutdown.cancel();
+        executor.wait_idle().await;
+        if let Some(queue) = &queue {
+            queue.close().await;
+        }
+        audit.flush();
+        if let Some(history) = &history {
+            history.flush().await;
+        }
+        return;
+    }
+
+    // Execution results come back in the encoding `Accept` asks for; the
+    // streaming endpoint negotiates its own framing.
+    let executions = Router::new()
         // New primary route used by the Python Agent.
         .route("/execute-tool", post(handle_execute_tool))
         // Backwards-compatible route used elsewhere in the stack.
-        .route("/api/v1/execute_tool", post(handle_execute_tool));
+        .route("/api/v1/execute_tool", post(handle_execute_tool))
+        .route("/api/v1/execute_batch", post(handle_execute_batch))
+        .route("/api/v1/execute_pipeline", post(handle_execute_pipeline))
+        .route("/api/v1/jobs", get(handle_list_jobs).post(handle_submit_job))
+        .route(
+            "/api/v1/jobs/:id",
+            get(handle_get_job).delete(handle_cancel_job),
+        )
+        .route("/api/v1/jobs/:id/replay", post(handle_replay_job))
+        .route("/api/v1/jobs/:id/approve", post(handle_approve_job))
+        .route("/api/v1/jobs/:id/reject", post(handle_reject_job))
+        .route_layer(axum::middleware::from_fn(negotiate::negotiate));
+    // Everything except the probes and metrics requires an API key.
+    let protected = Router::new()
+        .merge(executions)
+        .route("/api/v1/execute_tool/stream", post(handle_execute_tool_stream))
+        .route("/api/v1/sessions", get(handle_list_sessions).post(handle_create_session))
+        .route(
+            "/api/v1/sessions/:id",
+            get(handle_get_session).delete(handle_delete_session),
+        )
+        .route("/api/v1/sessions/ws", get(handle_session_ws))
+        .route("/api/v1/schedules", get(handle_list_schedules).post(handle_create_schedule))
+        .route(
+            "/api/v1/schedules/:id",
+            get(handle_get_schedule)
+                .patch(handle_update_schedule)
+                .delete(handle_delete_schedule),
+        )
+        .route("/api/v1/tools", get(list_tools))
+        .route("/api/v1/tools/:name", get(get_tool))
+        .route("/api/v1/tools/:name/stats", get(handle_get_tool_stats))
+        .route("/api/v1/mcp", post(handle_mcp))
+        .route("/api/v1/artifacts/:id", get(handle_get_artifact))
+        .route("/api/v1/usage", get(handle_get_usage))
+        .route(
+            "/api/v1/admin/rbac",
+            get(handle_get_rbac_policy).put(handle_put_rbac_policy),
+        )
+        .route("/api/v1/admin/policy/evaluate", post(handle_evaluate_policy))
+        .route("/api/v1/admin/audit", get(handle_query_audit))
+        .route("/api/v1/admin/config", get(handle_get_config))
+        .route("/api/v1/admin/queue", get(handle_get_queue))
+        .route("/api/v1/admin/cluster", get(handle_get_cluster))
+        .route("/api/v1/cluster/node", get(handle_get_cluster_node))
+        .route(
+            "/api/v1/admin/log_level",
+            get(handle_get_log_level).put(handle_put_log_level).delete(handle_reset_log_level),
+        )
+        .route("/api/v1/admin/executions", get(handle_list_executions))
+        .route("/api/v1/admin/executions/:id", delete(handle_kill_execution))
+        .route("/api/v1/admin/quarantine", get(handle_list_quarantine))
+        .route("/api/v1/admin/quarantine/:id", get(handle_get_quarantined))
+        .route("/api/v1/admin/quarantine/:id/approve", post(handle_approve_quarantined))
+        .route("/api/v1/admin/quarantine/:id/reject", post(handle_reject_quarantined))
+        .route(
+            "/api/v1/admin/drain",
+            get(handle_get_drain).post(handle_start_drain).delete(handle_stop_drain),
+        )
+        .route("/api/v1/admin/tools", get(handle_list_tool_states))
+        .route("/api/v1/admin/tools/:name", put(handle_set_tool_state))
+        .route(
+            "/api/v1/admin/reload",
+            get(handle_get_reload).post(handle_reload),
+        )
+        // Inside authentication, so that only callers who got in see faults.
+        .route_layer(axum::middleware::from_fn_with_state(
+            chaos,
+            chaos::inject_http,
+        ))
+        .route_layer(axum::middleware::from_fn_with_state(
+            auth.clone(),
+            auth::require_api_key,
+        ));
+    // The API description is public, like the probes: it holds nothing a
+    // caller could not learn from the tool list.
+    let mut docs = Router::new().route("/api/v1/openapi.json", get(handle_openapi));
+    if settings.server.swagger_ui {
+        docs = docs
+            .route("/api/v1/docs", get(openapi::handle_swagger_ui_index))
+            .route("/api/v1/docs/*file", get(openapi::handle_swagger_ui));
+    }
+    let mut app = Router::new()
+        .route("/health", get(health_check))
+        .route("/healthz", get(health_check))
+        .route("/readyz", get(readiness_check))
+        .route("/metrics", get(handle_metrics))
+        .route("/api/v1/scaling", get(handle_get_scaling))
+        .merge(docs)
+        .merge(protected)
+        .fallback(handle_unknown_route)
+        .layer(DefaultBodyLimit::max(settings.server.max_body_bytes))
+        .layer(axum::middleware::from_fn_with_state(
+            body_log::BodyLogger::new(settings.body_log.clone(), settings.server.max_body_bytes),
+            body_log::log_bodies,
+        ));
+    // Outside the body logger, which logs bodies as handlers see them.
+    if settings.server.compression {
+        app = app
+            .layer(compression::response_layer(settings.server.compression_min_bytes))
+            .layer(compression::request_layer());
+    }
+    let app = app
+        .layer(axum::middleware::from_fn(metrics::track_http))
+        .layer(axum::middleware::from_fn(telemetry::trace_http))
+        .with_state(state);
 
-    let http_task = tokio::spawn(async move {
+    // Health checks and reflection need no credentials, like `/readyz`.
+    let (health_reporter, grpc_health) = tonic_health::server::health_reporter();
+    health::report_grpc_health(grpc_readiness, health_reporter, vec![tool_service::SERVICE_NAME], shutdown.clone());
+    let reflection = || {
+        tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(tool_service::FILE_DESCRIPTOR_SET)
+            .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+    };
+    let (reflection_v1, reflection_v1alpha) = settings
+        .server
+        .grpc_reflection
+        .then(|| {
+            let v1 = reflection().build_v1();
+            let v1alpha = reflection().build_v1alpha();
+            v1.and_then(|v1| Ok((v1, v1alpha?)))
+                .unwrap_or_else(|e| panic!("Unable to set up gRPC reflection: {e}"))
+        })
+        .unzip();
+    let grpc = tonic::transport::Server::builder()
+        .trace_fn(telemetry::grpc_span)
+        .layer(metrics::GrpcMetricsLayer)
+        .layer(request_id::GrpcRequestIdLayer)
+        .add_service(tool_service::tool_service_server(
+            executor.clone(),
+            auth,
+            authz,
+            ratelimit,
+            settings.server.max_body_bytes,
+            idempotency,
+            settings.server.grpc_compression,
+        ))
+        .add_service(grpc_health)
+        .add_optional_service(reflection_v1)
+        .add_optional_service(reflection_v1alpha);
+
+    // A bad certificate or key is a deployment error like any other.
+    let tls = settings
+        .tls
+        .enabled()
+        .then(|| tls::acceptor(&settings.tls).unwrap_or_else(|e| panic!("Unable to set up TLS: {e}")));
+    let (app, grpc) = if single_port {
+        (multiplex::multiplex(app, multiplex::grpc_router(grpc.into_service())), None)
+    } else {
+        (app, Some(grpc))
+    };
+    let http_shutdown = shutdown.clone();
+    let http_tls = tls.clone();
+    let mut http_task = tokio::spawn(async move {
         let listener = tokio::net::TcpListener::bind(&http_addr).await.unwrap();
-        axum::serve(listener, app).await.unwrap();
+        match http_tls {
+            Some(acceptor) => tls::serve(listener, acceptor, app, http_shutdown).await,
+            // Peer addresses are recorded in the audit log.
+            None => axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(http_shutdown.cancelled_owned())
+            .await
+            .unwrap(),
+        }
     });
 
-    let grpc_task = tokio::spawn(async move {
-        tonic::transport::Server::builder()
-            .add_service(tool_service::tool_service_server())
-            .serve(grpc_addr)
-            .await
-            .unwrap();
+    let grpc_shutdown = shutdown.clone();
+    let mut grpc_task = tokio::spawn(async move {
+        match (grpc, tls) {
+            // tonic's server only speaks plain text here (its TLS support is
+            // not built in); TLS connections are served like the HTTP ones.
+            (Some(grpc), Some(acceptor)) => {
+                let listener = tokio::net::TcpListener::bind(&grpc_addr).await.unwrap();
+                let routes = multiplex::grpc_router(grpc.into_service());
+                tls::serve(listener, acceptor, routes, grpc_shutdown).await
+            }
+            (Some(grpc), None) => grpc
+                .serve_with_shutdown(grpc_addr, grpc_shutdown.cancelled_owned())
+                .await
+                .unwrap(),
+            // Served by the HTTP listener; this task only ends with it.
+            (None, _) => grpc_shutdown.cancelled_owned().await,
+        }
     });
 
-    // Run both servers until one of them exits.
-    let _ = tokio::join!(http_task, grpc_task);
-}
+    // Run both servers until a signal arrives or one of them exits.
+    tokio::select! {
+        _ = shutdown_signal() => {}
+        _ = &mut http_task => warn!(message = "HTTP server exited"),
+        _ = &mut grpc_task => warn!(message = "gRPC server exited"),
+    }
+    shutdown.cancel();
 
+    // Both servers stop accepting and finish the requests they have; jobs keep
+    // running on their own tasks, so wait for the executor too. Sessions would
+    // otherwise idle on until the drain period ran out.
+    let drained = async {
+        tokio::join!(
+            join_server(&mut http_task),
+            join_server(&mut grpc_task),
+            sessions.close_all()
+        );
+        executor.wait_idle().await;
+    };
+    if tokio::time::timeout(drain_period, drained).await.is_err() {
+        warn!(
+            in_flight = executor.in_flight(),
+            drain_secs = drain_period.as_secs(),
+            message = "Drain period elapsed; aborting running executions"
+        );
+        executor.abort_all();
+        let wound_down = async {
+            tokio::join!(join_server(&mut http_task), join_server(&mut grpc_task));
+            executor.wait_idle().await;
+        };
+        if tokio::time::timeout(ABORT_GRACE, wound_down).await.is_err() {
+            warn!(in_flight = executor.in_flight(), message = "Executions still running at exit");
+        }
+    }
+
+    // Jobs that finished during the drain still get their callbacks tried;
+    // ones waiting to be retried go to the dead-letter log.
+    webhooks.close().await;
+    events.close().await;
+    if let Some(queue) = &queue {
+        queue.close().await;
+    }
+    audit.flush();
+    if let Some(history) = &history {
+        history.flush().await;
+    }
+    if let Some((provider, _)) = tracer_provider {
+        if let Err(e) = provider.shutdown() {
+            warn!(error = %e, message = "Failed to flush traces");
+        }
+    }
+    if let Some(provider) = logger_provider {
+        if let Err(e) = provider.shutdown() {
+            warn!(error = %e, message = "Failed to flush logs");
+        }
+    }
+    info!(message = "Shutdown complete");
+}
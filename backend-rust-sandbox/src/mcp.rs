@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::egress::NetworkPolicy;
+use crate::registry::ToolManifest;
+use crate::tool::{execute_tool_request, ToolExecutionRequest, ToolExecutionResponse};
+use crate::tool_executor::{ExecutionContext, Executor};
+
+/// Protocol revisions this server speaks, newest first.
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct McpConfig {
+	/// Browser origins allowed to call the HTTP transport. Requests with an
+	/// `Origin` header not listed here are refused, which keeps web pages
+	/// from reaching a sandbox on localhost through DNS rebinding.
+	pub allowed_origins: Vec<String>,
+}
+
+/// The context a `tools/call` runs in, or the response turning it away.
+type Admitted = Result<ExecutionContext, ToolExecutionResponse>;
+
+/// Admits a `tools/call` like any other tool request.
+pub trait Admit {
+	fn admit(&self, req: &ToolExecutionRequest) -> impl Future<Output = Admitted> + Send;
+}
+
+impl<F, A> Admit for F
+where
+	F: Fn(&ToolExecutionRequest) -> A,
+	A: Future<Output = Admitted> + Send,
+{
+	fn admit(&self, req: &ToolExecutionRequest) -> impl Future<Output = Admitted> + Send {
+		self(req)
+	}
+}
+
+/// Answer a JSON-RPC payload, a single message or a batch. `None` when
+/// nothing needs answering (notifications, or responses from the client).
+pub async fn handle_payload(executor: &Arc<Executor>, payload: Value, admit: &impl Admit) -> Option<Value> {
+	match payload {
+		Value::Array(messages) if messages.is_empty() => Some(error_response(Value::Null, INVALID_REQUEST, "empty batch")),
+		Value::Array(messages) => {
+			let answers = futures::future::join_all(messages.into_iter().map(|m| handle_message(executor, m, admit))).await;
+			let answers: Vec<Value> = answers.into_iter().flatten().collect();
+			(!answers.is_empty()).then_some(Value::Array(answers))
+		}
+		message => handle_message(executor, message, admit).await,
+	}
+}
+
+/// The reply to a body that is not JSON.
+pub fn parse_error(e: &serde_json::Error) -> Value {
+	error_response(Value::Null, PARSE_ERROR, &format!("invalid JSON: {e}"))
+}
+
+async fn handle_message(executor: &Arc<Executor>, message: Value, admit: &impl Admit) -> Option<Value> {
+	let id = message.get("id").cloned();
+	let Some(method) = message.get("method").and_then(Value::as_str) else {
+		// A response to a request of ours; the server sends none.
+		if message.get("result").is_some() || message.get("error").is_some() {
+			return None;
+		}
+		return Some(error_response(id.unwrap_or(Value::Null), INVALID_REQUEST, "not a JSON-RPC request"));
+	};
+	if message.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+		return id.map(|id| error_response(id, INVALID_REQUEST, "jsonrpc must be \"2.0\""));
+	}
+	// Notifications (`notifications/initialized`, `notifications/cancelled`)
+	// need no answer and change nothing here.
+	let id = id?;
+	let params = message.get("params").cloned().unwrap_or_else(|| json!({}));
+	let outcome = match method {
+		"initialize" => Ok(initialize(&params)),
+		"ping" => Ok(json!({})),
+		"tools/list" => Ok(list_tools(executor)),
+		"tools/call" => call_tool(executor, params, admit).await,
+		_ => Err((METHOD_NOT_FOUND, format!("method {method:?} not found"))),
+	};
+	Some(match outcome {
+		Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+		Err((code, message)) => error_response(id, code, &message),
+	})
+}
+
+fn initialize(params: &Value) -> Value {
+	let requested = params.get("protocolVersion").and_then(Value::as_str);
+	let version = requested
+		.filter(|v| PROTOCOL_VERSIONS.contains(v))
+		.unwrap_or(PROTOCOL_VERSIONS[0]);
+	json!({
+		"protocolVersion": version,
+		"capabilities": { "tools": { "listChanged": false } },
+		"serverInfo": { "name": crate::SERVICE_NAME, "version": crate::VERSION },
+	})
+}
+
+fn list_tools(executor: &Executor) -> Value {
+	let registry = executor.registry();
+	let mut tools: Vec<Value> = registry.tools().map(descriptor).collect();
+	tools.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+	json!({ "tools": tools })
+}
+
+/// The MCP tool descriptor for a manifest: its args schema is the input
+/// schema, and its cache and network settings become hints.
+fn descriptor(tool: &ToolManifest) -> Value {
+	let schema = tool
+		.args_schema
+		.clone()
+		.unwrap_or_else(|| json!({ "type": "object" }));
+	let offline = tool.network.as_ref().is_none_or(|n| *n == NetworkPolicy::None);
+	json!({
+		"name": tool.name,
+		"description": tool.description,
+		"inputSchema": schema,
+		"annotations": {
+			"idempotentHint": tool.idempotent,
+			"openWorldHint": !offline,
+		},
+	})
+}
+
+/// Run a tool. Failures of the tool, including a rejected request, are tool
+/// results with `isError`; only a malformed call or an unknown tool is a
+/// protocol error.
+async fn call_tool(executor: &Arc<Executor>, params: Value, admit: &impl Admit) -> Result<Value, (i64, String)> {
+	let Some(name) = params.get("name").and_then(Value::as_str) else {
+		return Err((INVALID_PARAMS, "tools/call needs a tool name".to_string()));
+	};
+	if executor.registry().get(name).is_none() {
+		return Err((INVALID_PARAMS, format!("unknown tool {name:?}")));
+	}
+	let args = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+	let req = ToolExecutionRequest {
+		tool_name: name.to_string(),
+		args,
+		timeout_ms: None,
+		retain_workspace: false,
+		artifacts: Vec::new(),
+		stdin: None,
+		input_files: Default::default(),
+		session_id: None,
+		retry: None,
+		callback_url: None,
+		priority: None,
+		dry_run: false,
+		record_trace: false,
+		replay_trace: None,
+	};
+	let response = match admit.admit(&req).await {
+		Ok(ctx) => execute_tool_request(executor.clone(), req, ctx).await,
+		Err(rejection) => rejection,
+	};
+	let body = json!({ "status": response.status, "result": response.result });
+	Ok(json!({
+		"content": [{ "type": "text", "text": body.to_string() }],
+		"structuredContent": body,
+		"isError": response.status != "ok",
+	}))
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+	json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Serve MCP over stdin/stdout, one JSON-RPC message per line, until stdin
+/// closes. Requests are handled concurrently; answers are written as they
+/// are ready.
+pub async fn serve_stdio<F>(executor: Arc<Executor>, admit: Arc<F>)
+where
+	F: Admit + Send + Sync + 'static,
+{
+	info!(message = "Serving MCP on stdio");
+	let (tx, mut rx) = mpsc::channel::<Value>(64);
+	let writer = tokio::spawn(async move {
+		let mut stdout = tokio::io::stdout();
+		while let Some(answer) = rx.recv().await {
+			let mut line = answer.to_string().into_bytes();
+			line.push(b'\n');
+			if stdout.write_all(&line).await.is_err() || stdout.flush().await.is_err() {
+				return;
+			}
+		}
+	});
+
+	let mut lines = BufReader::new(tokio::io::stdin()).lines();
+	let mut running = JoinSet::new();
+	loop {
+		let line = match lines.next_line().await {
+			Ok(Some(line)) => line,
+			Ok(None) => break,
+			Err(e) => {
+				warn!(error = %e, message = "Failed to read MCP stdin");
+				break;
+			}
+		};
+		if line.trim().is_empty() {
+			continue;
+		}
+		let (executor, admit, tx) = (executor.clone(), admit.clone(), tx.clone());
+		running.spawn(async move {
+			let answer = match serde_json::from_str(&line) {
+				Ok(payload) => handle_payload(&executor, payload, &*admit).await,
+				Err(e) => Some(parse_error(&e)),
+			};
+			if let Some(answer) = answer {
+				let _ = tx.send(answer).await;
+			}
+		});
+	}
+	while running.join_next().await.is_some() {}
+	drop(tx);
+	let _ = writer.await;
+	info!(message = "MCP stdin closed");
+}
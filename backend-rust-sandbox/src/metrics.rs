@@ -0,0 +1,604 @@
+use axum::{
+	extract::{MatchedPath, Request},
+	http::{self, header, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use prometheus::{
+	Counter, Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+	TextEncoder,
+};
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+use crate::tool_executor::ToolResult;
+
+/// Buckets in seconds, from quick built-ins to long container runs.
+const EXECUTION_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+const REQUEST_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+/// stdout plus stderr, from nothing to past the default output limit.
+const OUTPUT_BUCKETS: &[f64] = &[0.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0];
+/// Peak memory of an execution's processes, 1 MiB to 4 GiB.
+const MEMORY_BUCKETS: &[f64] = &[
+	1048576.0, 4194304.0, 16777216.0, 67108864.0, 134217728.0, 268435456.0, 536870912.0, 1073741824.0, 4294967296.0,
+];
+
+/// Every metric the sandbox exports on `GET /metrics`.
+pub struct Metrics {
+	registry: Registry,
+	executions: IntCounterVec,
+	execution_seconds: HistogramVec,
+	execution_failures: IntCounterVec,
+	output_bytes: HistogramVec,
+	cpu_seconds: HistogramVec,
+	peak_memory_bytes: HistogramVec,
+	pub queued_jobs: IntGauge,
+	/// Jobs of tools that require approval, by tool and by what became of
+	/// them: `requested`, `approved`, `rejected` or `expired`.
+	pub job_approvals: IntCounterVec,
+	pub queued_executions: IntGauge,
+	/// Executions admitted but not yet running, GPU waits included; set as
+	/// `GET /api/v1/scaling` reports it, at scrape time.
+	pub pending_queue_depth: IntGauge,
+	/// Mean wait for an execution slot over the last minute, likewise.
+	pub avg_wait_ms: IntGauge,
+	pub live_sessions: IntGauge,
+	/// Sessions whose process is checkpointed to disk until its next call.
+	pub evicted_sessions: IntGauge,
+	/// Session checkpoints by result (`evicted`, `restored`, `failed`).
+	pub session_snapshots: IntCounterVec,
+	pub rejected_executions: IntCounter,
+	/// Rate limit buckets held in memory, one per (client, tool) in use. Not
+	/// labelled by client, which any caller could multiply.
+	pub rate_limit_buckets: IntGauge,
+	pub rate_limited: IntCounterVec,
+	/// Executions cancelled to make room for higher-priority ones, by the
+	/// priority of the cancelled one.
+	pub preemptions: IntCounterVec,
+	/// Requests turned away by a tenant quota, by quota.
+	pub quota_exceeded: IntCounterVec,
+	/// Tenants passing their monthly budget, by threshold (`soft`, `hard`).
+	pub budget_thresholds: IntCounterVec,
+	/// Work queue entries by what became of them.
+	pub queue_messages: IntCounterVec,
+	/// Session requests sent on to the instance holding the session, by
+	/// result (`proxied`, `redirected`, `failed`).
+	pub session_routes: IntCounterVec,
+	/// `execute_tool` requests this node could not serve, by why
+	/// (`unknown_tool`, `backend`, `gpu`, `draining`, `capacity`) and result
+	/// (`forwarded`, `no_peer`, `unreachable`, `failed`).
+	pub cluster_forwards: IntCounterVec,
+	/// Cluster peers heard from within the node TTL.
+	pub cluster_peers: IntGauge,
+	/// Decisions made with local limits because the shared limits store did
+	/// not answer, by kind (`rate_limit`, `quota`, `record`, `claim`).
+	pub shared_limit_fallbacks: IntCounterVec,
+	/// Result cache lookups by tool and outcome (`hit`, `miss`, `bypass`).
+	pub cache_lookups: IntCounterVec,
+	/// Requests with an `Idempotency-Key`, by what was done with them (`new`,
+	/// `replayed`, `in_progress`, `mismatched`, `failed`).
+	pub idempotency: IntCounterVec,
+	/// Attempts started again under a retry policy, by tool.
+	pub retries: IntCounterVec,
+	/// Quarantined executions, by tool and trigger.
+	pub quarantined: IntCounterVec,
+	/// Runs of each schedule by status, skipped ones included.
+	pub schedule_runs: IntCounterVec,
+	/// Job callback attempts by result (`delivered`, `retried`, `failed`).
+	pub webhook_deliveries: IntCounterVec,
+	/// Execution events by sink and result (`published`, `failed`,
+	/// `dropped`).
+	pub events: IntCounterVec,
+	/// Executions of backends with a warm pool, by whether they found an
+	/// instance ready (`hit`) or had one started (`miss`).
+	pub warm_pool_takes: IntCounterVec,
+	/// Warm instances destroyed, by backend and why (`tainted`, `used_up`,
+	/// `expired`, `dead`, `reset_failed`, `surplus`).
+	pub warm_pool_discards: IntCounterVec,
+	/// Busy share of each GPU (0 to 1), by NVML index.
+	pub gpu_utilization: GaugeVec,
+	pub gpu_memory_used_bytes: GaugeVec,
+	/// GPUs held by running executions.
+	pub gpus_in_use: IntGauge,
+	/// Executions waiting for GPUs to free up.
+	pub gpu_waiting: IntGauge,
+	active_executions: IntGauge,
+	child_processes: IntGauge,
+	child_rss_bytes: IntGauge,
+	child_cpu_seconds: Counter,
+	http_requests: IntCounterVec,
+	http_request_seconds: HistogramVec,
+	grpc_requests: IntCounterVec,
+	grpc_request_seconds: HistogramVec,
+	/// Process groups of running tool subprocesses, sampled at scrape time.
+	live_groups: Mutex<HashSet<u32>>,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+	fn new() -> Self {
+		let registry = Registry::new_custom(Some("sandbox".to_string()), None).expect("valid metrics prefix");
+		let counter_vec = |name: &str, help: &str, labels: &[&str]| {
+			let metric = IntCounterVec::new(Opts::new(name, help), labels).expect("valid metric");
+			registry.register(Box::new(metric.clone())).expect("unique metric");
+			metric
+		};
+		let histogram_vec = |name: &str, help: &str, labels: &[&str], buckets: &[f64]| {
+			let opts = HistogramOpts::new(name, help).buckets(buckets.to_vec());
+			let metric = HistogramVec::new(opts, labels).expect("valid metric");
+			registry.register(Box::new(metric.clone())).expect("unique metric");
+			metric
+		};
+		let gauge = |name: &str, help: &str| {
+			let metric = IntGauge::new(name, help).expect("valid metric");
+			registry.register(Box::new(metric.clone())).expect("unique metric");
+			metric
+		};
+		let gauge_vec = |name: &str, help: &str, labels: &[&str]| {
+			let metric = GaugeVec::new(Opts::new(name, help), labels).expect("valid metric");
+			registry.register(Box::new(metric.clone())).expect("unique metric");
+			metric
+		};
+
+		let rejected_executions = IntCounter::new(
+			"executions_rejected_total",
+			"Executions turned away because the queue was full.",
+		)
+		.expect("valid metric");
+		registry
+			.register(Box::new(rejected_executions.clone()))
+			.expect("unique metric");
+
+		let child_cpu_seconds = Counter::new(
+			"child_cpu_seconds_total",
+			"User plus system CPU time of tool subprocesses that have exited.",
+		)
+		.expect("valid metric");
+		registry
+			.register(Box::new(child_cpu_seconds.clone()))
+			.expect("unique metric");
+
+		Self {
+			executions: counter_vec(
+				"tool_executions_total",
+				"Finished tool executions by tool and status.",
+				&["tool", "status"],
+			),
+			execution_seconds: histogram_vec(
+				"tool_execution_duration_seconds",
+				"Wall-clock time of tool executions.",
+				&["tool"],
+				EXECUTION_BUCKETS,
+			),
+			execution_failures: counter_vec(
+				"tool_execution_failures_total",
+				"Failed tool executions by tool and failure class (tool, limit, policy, cancelled, request, sandbox).",
+				&["tool", "class"],
+			),
+			output_bytes: histogram_vec(
+				"tool_output_bytes",
+				"Bytes tool executions wrote to stdout and stderr together.",
+				&["tool"],
+				OUTPUT_BUCKETS,
+			),
+			cpu_seconds: histogram_vec(
+				"tool_cpu_seconds",
+				"User plus system CPU time of the processes of tool executions.",
+				&["tool"],
+				EXECUTION_BUCKETS,
+			),
+			peak_memory_bytes: histogram_vec(
+				"tool_peak_memory_bytes",
+				"Peak resident memory of the processes of tool executions.",
+				&["tool"],
+				MEMORY_BUCKETS,
+			),
+			queued_jobs: gauge("jobs_queued", "Submitted jobs that have not started yet."),
+			job_approvals: counter_vec(
+				"job_approvals_total",
+				"Jobs of tools that require approval, by tool and decision (`requested`, `approved`, `rejected`, `expired`).",
+				&["tool", "decision"],
+			),
+			queued_executions: gauge("executions_queued", "Admitted executions waiting for a free slot."),
+			pending_queue_depth: gauge("pending_queue_depth", "Executions admitted but not yet running, for autoscaling."),
+			avg_wait_ms: gauge("avg_wait_ms", "Mean wait for an execution slot over the last minute, in milliseconds."),
+			live_sessions: gauge("sessions_live", "Stateful tool sessions whose process is running."),
+			evicted_sessions: gauge("sessions_evicted", "Stateful tool sessions checkpointed to disk while idle."),
+			session_snapshots: counter_vec(
+				"session_snapshots_total",
+				"Session processes checkpointed to disk or restored from it, by result.",
+				&["result"],
+			),
+			rejected_executions,
+			rate_limit_buckets: gauge("rate_limit_buckets", "Per-client rate limit buckets held in memory."),
+			rate_limited: counter_vec(
+				"rate_limited_total",
+				"Requests rejected by the per-client rate limit, by tool.",
+				&["tool"],
+			),
+			preemptions: counter_vec(
+				"preemptions_total",
+				"Executions preempted by higher-priority requests, by their priority.",
+				&["priority"],
+			),
+			quota_exceeded: counter_vec(
+				"quota_exceeded_total",
+				"Requests rejected because the caller's tenant used up a quota, by quota.",
+				&["quota"],
+			),
+			budget_thresholds: counter_vec(
+				"budget_thresholds_crossed_total",
+				"Times a tenant's monthly spend passed its soft budget or its budget, by threshold.",
+				&["threshold"],
+			),
+			queue_messages: counter_vec(
+				"queue_messages_total",
+				"Work queue executions by result (dispatched, completed, reclaimed, dead_lettered, timed_out, failed).",
+				&["result"],
+			),
+			session_routes: counter_vec(
+				"session_routes_total",
+				"Session requests sent on to the instance holding the session, by result.",
+				&["result"],
+			),
+			cluster_forwards: counter_vec(
+				"cluster_forwards_total",
+				"Tool requests this node could not serve, by reason and by whether a peer took them.",
+				&["reason", "result"],
+			),
+			cluster_peers: gauge("cluster_peers", "Cluster peers heard from within the node TTL."),
+			shared_limit_fallbacks: counter_vec(
+				"shared_limits_fallbacks_total",
+				"Decisions made locally because the shared limits store did not answer, by kind.",
+				&["kind"],
+			),
+			cache_lookups: counter_vec(
+				"cache_lookups_total",
+				"Result cache lookups of idempotent tools, by tool and result.",
+				&["tool", "result"],
+			),
+			idempotency: counter_vec(
+				"idempotency_requests_total",
+				"Requests carrying an Idempotency-Key, by result.",
+				&["result"],
+			),
+			retries: counter_vec(
+				"execution_retries_total",
+				"Executions run again after a transient failure, by tool.",
+				&["tool"],
+			),
+			quarantined: counter_vec(
+				"executions_quarantined_total",
+				"Executions quarantined, by tool and by what triggered it (`unknown_caller`, `flagged_code`, `resource_use`).",
+				&["tool", "trigger"],
+			),
+			schedule_runs: counter_vec(
+				"schedule_runs_total",
+				"Scheduled tool runs by schedule and status, including skipped and rejected ones.",
+				&["schedule", "status"],
+			),
+			webhook_deliveries: counter_vec(
+				"webhook_deliveries_total",
+				"Job completion callbacks by result: delivered, retried or failed (dead-lettered).",
+				&["result"],
+			),
+			events: counter_vec(
+				"events_total",
+				"Execution lifecycle events by sink and result: published, failed (sink unavailable) or dropped (queue full).",
+				&["sink", "result"],
+			),
+			warm_pool_takes: counter_vec(
+				"warm_pool_takes_total",
+				"Executions given a warm instance (hit) or a freshly started one (miss), by backend.",
+				&["backend", "result"],
+			),
+			warm_pool_discards: counter_vec(
+				"warm_pool_discards_total",
+				"Warm instances destroyed, by backend and reason.",
+				&["backend", "reason"],
+			),
+			gpu_utilization: gauge_vec(
+				"gpu_utilization_ratio",
+				"Share of the last sample period each GPU was busy, by device.",
+				&["device"],
+			),
+			gpu_memory_used_bytes: gauge_vec(
+				"gpu_memory_used_bytes",
+				"Memory in use on each GPU, by device.",
+				&["device"],
+			),
+			gpus_in_use: gauge("gpus_in_use", "GPUs held by running tool executions."),
+			gpu_waiting: gauge("executions_waiting_for_gpu", "Tool executions waiting for GPUs to free up."),
+			active_executions: gauge("active_executions", "Tool executions currently running."),
+			child_processes: gauge("child_processes", "Live processes in tool subprocess groups."),
+			child_rss_bytes: gauge("child_rss_bytes", "Resident memory of live tool subprocesses."),
+			child_cpu_seconds,
+			http_requests: counter_vec(
+				"http_requests_total",
+				"HTTP requests by method, route and status code.",
+				&["method", "route", "status"],
+			),
+			http_request_seconds: histogram_vec(
+				"http_request_duration_seconds",
+				"Time until the HTTP response head was sent.",
+				&["method", "route"],
+				REQUEST_BUCKETS,
+			),
+			grpc_requests: counter_vec(
+				"grpc_requests_total",
+				"gRPC calls by method and the status sent with the response head.",
+				&["method", "code"],
+			),
+			grpc_request_seconds: histogram_vec(
+				"grpc_request_duration_seconds",
+				"Time until the gRPC response head was sent.",
+				&["method"],
+				REQUEST_BUCKETS,
+			),
+			registry,
+			live_groups: Mutex::new(HashSet::new()),
+		}
+	}
+
+	/// Count a tool execution as running until the returned guard is dropped.
+	pub fn start_execution(&self, tool: &str) -> ExecutionTimer {
+		self.active_executions.inc();
+		ExecutionTimer {
+			tool: tool.to_string(),
+			started: Instant::now(),
+		}
+	}
+
+	/// Include the process group led by `pgid` in child resource sampling
+	/// until the returned guard is dropped.
+	pub fn track_process_group(&self, pgid: u32) -> TrackedGroup {
+		self.live_groups.lock().unwrap().insert(pgid);
+		TrackedGroup { pgid }
+	}
+
+	/// Sample child resource usage and encode everything in the Prometheus
+	/// text format.
+	pub fn render(&self) -> String {
+		self.sample_children();
+		let mut out = Vec::new();
+		TextEncoder::new()
+			.encode(&self.registry.gather(), &mut out)
+			.expect("text encoding cannot fail");
+		String::from_utf8(out).expect("text encoding is UTF-8")
+	}
+
+	fn sample_children(&self) {
+		let groups = self.live_groups.lock().unwrap().clone();
+		let (processes, rss_bytes) = if groups.is_empty() {
+			(0, 0)
+		} else {
+			live_group_usage(&groups)
+		};
+		self.child_processes.set(processes);
+		self.child_rss_bytes.set(rss_bytes);
+
+		// RUSAGE_CHILDREN only grows, so feed the counter the difference.
+		let total = reaped_children_cpu_seconds();
+		let delta = total - self.child_cpu_seconds.get();
+		if delta > 0.0 {
+			self.child_cpu_seconds.inc_by(delta);
+		}
+	}
+}
+
+/// Returned by [`Metrics::start_execution`].
+pub struct ExecutionTimer {
+	tool: String,
+	started: Instant,
+}
+
+impl ExecutionTimer {
+	pub fn finish(self, result: &ToolResult) {
+		let tool = [self.tool.as_str()];
+		METRICS
+			.executions
+			.with_label_values(&[self.tool.as_str(), result.status.as_str()])
+			.inc();
+		METRICS
+			.execution_seconds
+			.with_label_values(&tool)
+			.observe(self.started.elapsed().as_secs_f64());
+		if let Some(class) = failure_class(&result.status) {
+			METRICS.execution_failures.with_label_values(&[self.tool.as_str(), class]).inc();
+		}
+		let (stdout_bytes, stderr_bytes) = match &result.truncated {
+			Some(truncation) => (truncation.stdout_bytes, truncation.stderr_bytes),
+			None => (result.stdout.len() as u64, result.stderr.len() as u64),
+		};
+		METRICS
+			.output_bytes
+			.with_label_values(&tool)
+			.observe((stdout_bytes + stderr_bytes) as f64);
+		let usage = result.usage.as_ref();
+		if let Some((user, sys)) = usage.and_then(|u| u.user_cpu_ms.zip(u.sys_cpu_ms)) {
+			METRICS
+				.cpu_seconds
+				.with_label_values(&tool)
+				.observe((user + sys) as f64 / 1000.0);
+		}
+		if let Some(peak) = usage.and_then(|u| u.peak_rss_bytes) {
+			METRICS.peak_memory_bytes.with_label_values(&tool).observe(peak as f64);
+		}
+	}
+}
+
+/// What kind of failure a result status is, for telling tool bugs from
+/// limits and from the sandbox's own trouble; `None` for `ok`.
+pub fn failure_class(status: &str) -> Option<&'static str> {
+	Some(match status {
+		"ok" | "planned" => return None,
+		"non_zero_exit" | "runtime_error" | "compile_error" | "killed" | "unknown_exit" | "trap" => "tool",
+		"timed_out" | "oom_killed" | "cpu_exceeded" | "fuel_exhausted" | "deadline_exceeded"
+		| "disk_quota_exceeded" => "limit",
+		"forbidden" | "rate_limited" | "quota_exceeded" | "budget_exceeded" | "network_denied" | "gateway_denied"
+		| "query_denied" | "permission_denied" => "policy",
+		"cancelled" | "aborted" | "preempted" => "cancelled",
+		"invalid_args" | "unknown_tool" | "unsupported_language" | "trace_mismatch" => "request",
+		_ => "sandbox",
+	})
+}
+
+impl Drop for ExecutionTimer {
+	fn drop(&mut self) {
+		METRICS.active_executions.dec();
+	}
+}
+
+/// Returned by [`Metrics::track_process_group`].
+pub struct TrackedGroup {
+	pgid: u32,
+}
+
+impl Drop for TrackedGroup {
+	fn drop(&mut self) {
+		METRICS.live_groups.lock().unwrap().remove(&self.pgid);
+	}
+}
+
+/// Count and resident memory of every process whose group is in `groups`.
+fn live_group_usage(groups: &HashSet<u32>) -> (i64, i64) {
+	// SAFETY: sysconf has no memory-safety preconditions.
+	let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0);
+	let Ok(entries) = std::fs::read_dir("/proc") else {
+		return (0, 0);
+	};
+	let (mut processes, mut rss_pages) = (0, 0);
+	for entry in entries.flatten() {
+		let name = entry.file_name();
+		if !name.to_string_lossy().bytes().all(|b| b.is_ascii_digit()) {
+			continue;
+		}
+		let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+			continue;
+		};
+		// The command name may contain spaces; the fields after it do not.
+		let Some((_, fields)) = stat.rsplit_once(')') else {
+			continue;
+		};
+		let fields: Vec<&str> = fields.split_whitespace().collect();
+		let pgrp = fields.get(2).and_then(|f| f.parse::<u32>().ok());
+		if pgrp.is_some_and(|g| groups.contains(&g)) {
+			processes += 1;
+			rss_pages += fields.get(21).and_then(|f| f.parse::<i64>().ok()).unwrap_or(0);
+		}
+	}
+	(processes, rss_pages * page_size)
+}
+
+fn reaped_children_cpu_seconds() -> f64 {
+	// SAFETY: getrusage only writes into the struct we pass.
+	let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+	if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } != 0 {
+		return 0.0;
+	}
+	let seconds = |t: libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+	seconds(usage.ru_utime) + seconds(usage.ru_stime)
+}
+
+/// `GET /metrics`.
+pub async fn handle_metrics() -> Response {
+	(
+		StatusCode::OK,
+		[(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+		METRICS.render(),
+	)
+		.into_response()
+}
+
+/// axum middleware recording HTTP request counts and latency, labelled by the
+/// matched route template so path parameters do not explode cardinality.
+pub async fn track_http(request: Request, next: Next) -> Response {
+	let method = request.method().to_string();
+	let route = request
+		.extensions()
+		.get::<MatchedPath>()
+		.map(|p| p.as_str().to_string())
+		.unwrap_or_else(|| "unmatched".to_string());
+	let started = Instant::now();
+	let response = next.run(request).await;
+	METRICS
+		.http_requests
+		.with_label_values(&[method.as_str(), route.as_str(), response.status().as_str()])
+		.inc();
+	METRICS
+		.http_request_seconds
+		.with_label_values(&[method.as_str(), route.as_str()])
+		.observe(started.elapsed().as_secs_f64());
+	response
+}
+
+/// tower layer recording gRPC call counts and latency.
+///
+/// Errors returned before any message is sent travel in the response headers
+/// and are counted under their code; a successful head counts as `0`, so an
+/// error raised in the middle of a stream is not reflected here.
+#[derive(Debug, Clone, Default)]
+pub struct GrpcMetricsLayer;
+
+impl<S> Layer<S> for GrpcMetricsLayer {
+	type Service = GrpcMetrics<S>;
+
+	fn layer(&self, inner: S) -> Self::Service {
+		GrpcMetrics { inner }
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcMetrics<S> {
+	inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for GrpcMetrics<S>
+where
+	S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+	S::Future: Send + 'static,
+{
+	type Response = S::Response;
+	type Error = S::Error;
+	type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+	fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+		self.inner.poll_ready(cx)
+	}
+
+	fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+		let method = request.uri().path().to_string();
+		let started = Instant::now();
+		let future = self.inner.call(request);
+		Box::pin(async move {
+			let result = future.await;
+			let code = match &result {
+				Ok(response) => response
+					.headers()
+					.get("grpc-status")
+					.and_then(|v| v.to_str().ok())
+					.unwrap_or("0")
+					.to_string(),
+				Err(_) => "transport_error".to_string(),
+			};
+			// Unimplemented (12) means the path named no real method; do not
+			// let arbitrary client paths become label values.
+			let method = if code == "12" { "unknown".to_string() } else { method };
+			METRICS
+				.grpc_requests
+				.with_label_values(&[method.as_str(), code.as_str()])
+				.inc();
+			METRICS
+				.grpc_request_seconds
+				.with_label_values(&[method.as_str()])
+				.observe(started.elapsed().as_secs_f64());
+			result
+		})
+	}
+}
@@ -0,0 +1,111 @@
+use axum::body::Body;
+use axum::extract::connect_info::ConnectInfo;
+use axum::extract::Request;
+use axum::http::{self, header::CONTENT_TYPE};
+use axum::response::Response;
+use axum::Router;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::transport::server::TcpConnectInfo;
+use tonic::Status;
+use tower::{BoxError, Service, ServiceExt};
+use tracing::Instrument;
+
+use crate::telemetry;
+
+type BoxFuture = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+/// The tonic services as an axum router, for listeners that tonic's own
+/// server does not run: the shared port, and TLS.
+pub fn grpc_router<G>(grpc: G) -> Router
+where
+	G: Service<http::Request<BoxBody>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+	G::Future: Send + 'static,
+	G::Error: Into<BoxError>,
+{
+	Router::new().fallback_service(Grpc { inner: grpc })
+}
+
+/// REST and gRPC behind one listener: requests whose content type is
+/// `application/grpc` (or `application/grpc+proto` etc.) go to `grpc`,
+/// everything else to `rest`. Both keep their own middleware, so metrics and
+/// spans look the same as on separate ports.
+pub fn multiplex(rest: Router, grpc: Router) -> Router {
+	Router::new().fallback_service(Multiplex { rest, grpc })
+}
+
+/// Does for tonic's routes what its server would: attach the peer address
+/// where `Request::remote_addr` looks for it, and open the call's span.
+#[derive(Clone)]
+struct Grpc<G> {
+	inner: G,
+}
+
+impl<G> Service<Request> for Grpc<G>
+where
+	G: Service<http::Request<BoxBody>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+	G::Future: Send + 'static,
+	G::Error: Into<BoxError>,
+{
+	type Response = Response;
+	type Error = Infallible;
+	type Future = BoxFuture;
+
+	fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+		// Each request is handed to a clone, which is readied then.
+		Poll::Ready(Ok(()))
+	}
+
+	fn call(&mut self, mut request: Request) -> Self::Future {
+		let remote_addr = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|c| c.0);
+		request.extensions_mut().insert(TcpConnectInfo {
+			local_addr: None,
+			remote_addr,
+		});
+		let span = telemetry::grpc_span(&request);
+		let inner = self.inner.clone();
+		Box::pin(
+			async move {
+				let response = match inner.oneshot(request.map(tonic::body::boxed)).await {
+					Ok(response) => response,
+					Err(e) => Status::internal(e.into().to_string()).into_http(),
+				};
+				Ok(response.map(Body::new))
+			}
+			.instrument(span),
+		)
+	}
+}
+
+#[derive(Clone)]
+struct Multiplex {
+	rest: Router,
+	grpc: Router,
+}
+
+fn is_grpc(request: &Request) -> bool {
+	request
+		.headers()
+		.get(CONTENT_TYPE)
+		.and_then(|v| v.to_str().ok())
+		.is_some_and(|v| v.starts_with("application/grpc"))
+}
+
+impl Service<Request> for Multiplex {
+	type Response = Response;
+	type Error = Infallible;
+	type Future = BoxFuture;
+
+	fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+		Poll::Ready(Ok(()))
+	}
+
+	fn call(&mut self, request: Request) -> Self::Future {
+		let target = if is_grpc(&request) { &self.grpc } else { &self.rest };
+		Box::pin(target.clone().oneshot(request))
+	}
+}
@@ -0,0 +1,175 @@
+use std::ffi::CStr;
+use std::io;
+use std::sync::Arc;
+
+/// The Linux namespaces a tool subprocess is moved into before it execs.
+///
+/// Without root, a user namespace mapping the sandbox's own uid and gid is
+/// created alongside, which grants the privileges the other namespaces need.
+#[derive(Debug, Clone)]
+pub struct Unshare {
+	flags: libc::c_int,
+	/// `/proc/self/{uid,gid}_map` contents for the user namespace, when one
+	/// is needed.
+	id_maps: Option<Arc<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl Unshare {
+	/// `flags` is a set of `CLONE_NEW*` flags other than `CLONE_NEWUSER`.
+	pub fn new(flags: libc::c_int) -> Self {
+		// SAFETY: these getters cannot fail.
+		let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+		let id_maps = (uid != 0).then(|| {
+			Arc::new((
+				format!("{uid} {uid} 1").into_bytes(),
+				format!("{gid} {gid} 1").into_bytes(),
+			))
+		});
+		Self { flags, id_maps }
+	}
+
+	/// Enter the new namespaces. Runs in the child between fork and exec, so
+	/// it only makes syscalls on data prepared beforehand.
+	pub fn enter(&self) -> io::Result<()> {
+		let flags = match self.id_maps {
+			Some(_) => self.flags | libc::CLONE_NEWUSER,
+			None => self.flags,
+		};
+		// SAFETY: plain syscalls on NUL-terminated strings and buffers that
+		// outlive them.
+		unsafe {
+			check(libc::unshare(flags))?;
+			if let Some(maps) = &self.id_maps {
+				// The fork kept the sandbox's undumpable flag, which leaves
+				// `/proc/self` to root; the exec that follows resets it anyway.
+				check(libc::prctl(libc::PR_SET_DUMPABLE, 1, 0, 0, 0))?;
+				write_proc(c"/proc/self/setgroups", b"deny")?;
+				write_proc(c"/proc/self/uid_map", &maps.0)?;
+				write_proc(c"/proc/self/gid_map", &maps.1)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Make the exec that follows run in the PID namespace the child unshared
+/// (`CLONE_NEWPID` applies to a process's children only), under an init
+/// that reaps whatever is orphaned there. Runs in the child between fork and
+/// exec.
+///
+/// The child forks that init, which forks the process that returns here to
+/// exec the tool. When the tool exits, init follows, and with it the kernel
+/// kills everything left in the namespace, including descendants that
+/// changed process group or session. The child stays outside to wait for
+/// init, then exits, or dies by the same signal, as the tool did, so that
+/// the sandbox sees the tool's status.
+pub fn enter_pid_namespace() -> io::Result<()> {
+	// SAFETY: only async-signal-safe calls, on buffers that outlive them;
+	// the forked processes never return from here but the tool's.
+	unsafe {
+		let mut status_pipe = [0; 2];
+		check(libc::pipe2(status_pipe.as_mut_ptr(), libc::O_CLOEXEC))?;
+		let [read_end, write_end] = status_pipe;
+		let init = libc::fork();
+		check(init)?;
+		if init > 0 {
+			close_others(read_end, write_end);
+			libc::close(write_end);
+			let mut status = 0;
+			while libc::waitpid(init, &mut status, 0) < 0 && interrupted() {}
+			let mut tool_status: libc::c_int = 0;
+			let size = std::mem::size_of::<libc::c_int>();
+			if libc::read(read_end, (&mut tool_status as *mut libc::c_int).cast(), size) == size as isize {
+				status = tool_status;
+			}
+			exit_as(status);
+		}
+		libc::close(read_end);
+		// Should the child outside be killed on its own, the namespace goes
+		// with it.
+		libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+		let tool = libc::fork();
+		check(tool)?;
+		if tool == 0 {
+			libc::close(write_end);
+			return Ok(());
+		}
+		// The sandbox's exec error pipe must close when the tool execs, so
+		// init holds nothing it was handed but the tool's stdio.
+		close_others(write_end, write_end);
+		loop {
+			let mut status = 0;
+			let pid = libc::waitpid(-1, &mut status, 0);
+			if pid == tool {
+				let size = std::mem::size_of::<libc::c_int>();
+				libc::write(write_end, (&status as *const libc::c_int).cast(), size);
+				libc::_exit(0);
+			}
+			if pid < 0 && !interrupted() {
+				libc::_exit(EXIT_INIT_FAILED);
+			}
+		}
+	}
+}
+
+fn interrupted() -> bool {
+	io::Error::last_os_error().kind() == io::ErrorKind::Interrupted
+}
+
+/// Exit status of an init that lost track of its tool.
+const EXIT_INIT_FAILED: libc::c_int = 127;
+
+/// Close every descriptor above stdio but `keep` and `also`.
+unsafe fn close_others(keep: libc::c_int, also: libc::c_int) {
+	let (low, high) = (keep.min(also) as libc::c_uint, keep.max(also) as libc::c_uint);
+	// Empty ranges fail with EINVAL, which is as good as closing nothing.
+	for (first, last) in [(3, low.wrapping_sub(1)), (low + 1, high.wrapping_sub(1)), (high + 1, libc::c_uint::MAX)] {
+		if first <= last {
+			libc::syscall(libc::SYS_close_range, first, last, 0 as libc::c_uint);
+		}
+	}
+}
+
+/// End this process the way one with wait `status` ended.
+unsafe fn exit_as(status: libc::c_int) -> ! {
+	if libc::WIFSIGNALED(status) {
+		let signal = libc::WTERMSIG(status);
+		// A signal that dumps core would leave the core in the workspace.
+		let no_core = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+		libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+		libc::signal(signal, libc::SIG_DFL);
+		libc::kill(libc::getpid(), signal);
+	}
+	libc::_exit(match libc::WIFEXITED(status) {
+		true => libc::WEXITSTATUS(status),
+		false => EXIT_INIT_FAILED,
+	})
+}
+
+unsafe fn write_proc(path: &CStr, data: &[u8]) -> io::Result<()> {
+	let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+	check(fd)?;
+	let written = libc::write(fd, data.as_ptr().cast(), data.len());
+	libc::close(fd);
+	if written != data.len() as isize {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+/// Keep other processes of the sandbox's uid, tools among them, out of its
+/// `/proc/<pid>` files: `environ` and `cmdline` hold the credentials tools
+/// are never handed, and `mem` everything else. Tools are dumpable again
+/// once they exec.
+pub fn make_undumpable() -> io::Result<()> {
+	// SAFETY: prctl with integer arguments.
+	check(unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) })
+}
+
+pub fn check(rc: libc::c_int) -> io::Result<()> {
+	if rc < 0 {
+		Err(io::Error::last_os_error())
+	} else {
+		Ok(())
+	}
+}
@@ -0,0 +1,257 @@
+use axum::body::{to_bytes, Body};
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::Value;
+
+use crate::error::SandboxError;
+
+/// How an execution endpoint encodes what it sends back, as the caller's
+/// `Accept` header chooses. Every format carries the same envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	Json,
+	/// One JSON document per line; a streamed execution sends one per event.
+	Ndjson,
+	MessagePack,
+	/// Server-Sent Events, for streamed executions only.
+	EventStream,
+}
+
+impl Format {
+	/// The media types that select this format; the first is the one
+	/// answers are labelled with.
+	fn media_types(self) -> &'static [&'static str] {
+		match self {
+			Self::Json => &["application/json"],
+			Self::Ndjson => &["application/x-ndjson", "application/ndjson", "application/jsonl"],
+			Self::MessagePack => &["application/msgpack", "application/x-msgpack", "application/vnd.msgpack"],
+			Self::EventStream => &["text/event-stream"],
+		}
+	}
+
+	pub fn content_type(self) -> HeaderValue {
+		HeaderValue::from_static(self.media_types()[0])
+	}
+
+	/// The format `headers` ask for among `offered`, the first of which is
+	/// the default when they ask for none in particular. The most specific
+	/// media range of the highest quality wins.
+	pub fn choose(headers: &HeaderMap, offered: &[Format]) -> Result<Format, SandboxError> {
+		let accept: Vec<&str> = headers
+			.get_all(header::ACCEPT)
+			.iter()
+			.filter_map(|v| v.to_str().ok())
+			.flat_map(|v| v.split(','))
+			.map(str::trim)
+			.filter(|v| !v.is_empty())
+			.collect();
+		if accept.is_empty() {
+			return Ok(offered[0]);
+		}
+		let mut ranges: Vec<(f32, u8, Format)> = Vec::new();
+		for range in accept {
+			let mut params = range.split(';').map(str::trim);
+			let media = params.next().unwrap_or_default().to_ascii_lowercase();
+			let quality = params
+				.filter_map(|p| p.strip_prefix("q="))
+				.find_map(|q| q.parse::<f32>().ok())
+				.unwrap_or(1.0);
+			if quality <= 0.0 {
+				continue;
+			}
+			let matched = match media.as_str() {
+				"*/*" => Some((0, offered[0])),
+				_ => match media.strip_suffix("/*") {
+					Some(kind) => offered
+						.iter()
+						.find(|f| f.media_types()[0].starts_with(&format!("{kind}/")))
+						.map(|f| (1, *f)),
+					None => offered
+						.iter()
+						.find(|f| f.media_types().contains(&media.as_str()))
+						.map(|f| (2, *f)),
+				},
+			};
+			if let Some((specificity, format)) = matched {
+				ranges.push((quality, specificity, format));
+			}
+		}
+		// Stable, so that ties keep the order the caller listed them in.
+		ranges.sort_by(|a, b| b.0.total_cmp(&a.0).then(b.1.cmp(&a.1)));
+		match ranges.first() {
+			Some((_, _, format)) => Ok(*format),
+			None => {
+				let types: Vec<&str> = offered.iter().map(|f| f.media_types()[0]).collect();
+				Err(SandboxError::NotAcceptable(format!(
+					"this endpoint answers in {}",
+					types.join(", ")
+				)))
+			}
+		}
+	}
+
+	/// `value` in this format; NDJSON ends it with a newline. Event streams
+	/// are framed by their producer, so they get plain JSON.
+	pub fn encode(self, value: &Value) -> Vec<u8> {
+		match self {
+			Self::Json | Self::EventStream => serde_json::to_vec(value).unwrap_or_default(),
+			Self::Ndjson => {
+				let mut line = serde_json::to_vec(value).unwrap_or_default();
+				line.push(b'\n');
+				line
+			}
+			Self::MessagePack => {
+				let mut out = Vec::new();
+				write_msgpack(&mut out, value);
+				out
+			}
+		}
+	}
+}
+
+/// Re-encode the JSON answer of the execution route it wraps in the format
+/// the request's `Accept` asks for: JSON as is, NDJSON as the same document
+/// on one line, MessagePack as the same document in MessagePack. A request
+/// accepting none of them gets `406`.
+pub async fn negotiate(req: Request, next: Next) -> Response {
+	let format = match Format::choose(req.headers(), &[Format::Json, Format::Ndjson, Format::MessagePack]) {
+		Ok(format) => format,
+		Err(e) => return with_vary(e.into_response()),
+	};
+	let response = next.run(req).await;
+	let is_json = response
+		.headers()
+		.get(header::CONTENT_TYPE)
+		.and_then(|v| v.to_str().ok())
+		.is_some_and(|v| v.starts_with("application/json"));
+	if format == Format::Json || !is_json {
+		return with_vary(response);
+	}
+	let (mut parts, body) = response.into_parts();
+	let bytes = match to_bytes(body, usize::MAX).await {
+		Ok(bytes) => bytes,
+		Err(e) => return SandboxError::Internal(format!("reading the response: {e}")).into_response(),
+	};
+	let Ok(value) = serde_json::from_slice::<Value>(&bytes) else {
+		return with_vary(Response::from_parts(parts, Body::from(bytes)));
+	};
+	parts.headers.insert(header::CONTENT_TYPE, format.content_type());
+	parts.headers.remove(header::CONTENT_LENGTH);
+	with_vary(Response::from_parts(parts, Body::from(format.encode(&value))))
+}
+
+/// Mark a negotiated answer as depending on `Accept`, for caches.
+pub fn with_vary(mut response: Response) -> Response {
+	response
+		.headers_mut()
+		.append(header::VARY, HeaderValue::from_static("accept"));
+	response
+}
+
+/// Append `value` to `out` as MessagePack, in the smallest encoding of each
+/// value. JSON numbers that fit an integer stay integers.
+fn write_msgpack(out: &mut Vec<u8>, value: &Value) {
+	match value {
+		Value::Null => out.push(0xc0),
+		Value::Bool(false) => out.push(0xc2),
+		Value::Bool(true) => out.push(0xc3),
+		Value::Number(n) => {
+			if let Some(u) = n.as_u64() {
+				write_uint(out, u);
+			} else if let Some(i) = n.as_i64() {
+				write_int(out, i);
+			} else {
+				out.push(0xcb);
+				out.extend_from_slice(&n.as_f64().unwrap_or_default().to_be_bytes());
+			}
+		}
+		Value::String(s) => write_str(out, s),
+		Value::Array(items) => {
+			write_len(out, items.len(), 0x90, 0xdc);
+			for item in items {
+				write_msgpack(out, item);
+			}
+		}
+		Value::Object(fields) => {
+			write_len(out, fields.len(), 0x80, 0xde);
+			for (key, item) in fields {
+				write_str(out, key);
+				write_msgpack(out, item);
+			}
+		}
+	}
+}
+
+fn write_uint(out: &mut Vec<u8>, u: u64) {
+	match u {
+		0..=0x7f => out.push(u as u8),
+		0x80..=0xff => out.extend_from_slice(&[0xcc, u as u8]),
+		0x100..=0xffff => {
+			out.push(0xcd);
+			out.extend_from_slice(&(u as u16).to_be_bytes());
+		}
+		0x1_0000..=0xffff_ffff => {
+			out.push(0xce);
+			out.extend_from_slice(&(u as u32).to_be_bytes());
+		}
+		_ => {
+			out.push(0xcf);
+			out.extend_from_slice(&u.to_be_bytes());
+		}
+	}
+}
+
+/// A negative integer; non-negative ones go through [`write_uint`].
+fn write_int(out: &mut Vec<u8>, i: i64) {
+	match i {
+		-32..=-1 => out.push(i as u8),
+		-128..=-33 => out.extend_from_slice(&[0xd0, i as u8]),
+		-32_768..=-129 => {
+			out.push(0xd1);
+			out.extend_from_slice(&(i as i16).to_be_bytes());
+		}
+		-2_147_483_648..=-32_769 => {
+			out.push(0xd2);
+			out.extend_from_slice(&(i as i32).to_be_bytes());
+		}
+		_ => {
+			out.push(0xd3);
+			out.extend_from_slice(&i.to_be_bytes());
+		}
+	}
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+	let len = s.len();
+	match len {
+		0..=31 => out.push(0xa0 | len as u8),
+		32..=0xff => out.extend_from_slice(&[0xd9, len as u8]),
+		0x100..=0xffff => {
+			out.push(0xda);
+			out.extend_from_slice(&(len as u16).to_be_bytes());
+		}
+		_ => {
+			out.push(0xdb);
+			out.extend_from_slice(&(len as u32).to_be_bytes());
+		}
+	}
+	out.extend_from_slice(s.as_bytes());
+}
+
+/// The header of an array (`fix` 0x90, `wide` 0xdc) or map (0x80, 0xde):
+/// the fix form up to 15 entries, then 16-bit and 32-bit lengths.
+fn write_len(out: &mut Vec<u8>, len: usize, fix: u8, wide: u8) {
+	match len {
+		0..=15 => out.push(fix | len as u8),
+		16..=0xffff => {
+			out.push(wide);
+			out.extend_from_slice(&(len as u16).to_be_bytes());
+		}
+		_ => {
+			out.push(wide + 1);
+			out.extend_from_slice(&(len as u32).to_be_bytes());
+		}
+	}
+}
@@ -0,0 +1,1595 @@
+use axum::extract::Path;
+use axum::http::header;
+use axum::response::{IntoResponse, Redirect, Response};
+use serde_json::{json, Map, Value};
+use std::sync::{Arc, LazyLock};
+
+use crate::error::SandboxError;
+use crate::registry::ToolManifest;
+use crate::tool_executor::Executor;
+
+/// Swagger UI pointed at the document. Its assets are built into the binary,
+/// so the page works without internet access.
+static SWAGGER_UI: LazyLock<Arc<utoipa_swagger_ui::Config<'static>>> =
+	LazyLock::new(|| Arc::new(utoipa_swagger_ui::Config::from("/api/v1/openapi.json")));
+
+/// `GET /api/v1/docs`: to the UI's index, under `/api/v1/docs/` like the
+/// assets it links to relatively.
+pub async fn handle_swagger_ui_index() -> Redirect {
+	Redirect::permanent("/api/v1/docs/index.html")
+}
+
+/// `GET /api/v1/docs/{file}`: a Swagger UI asset.
+pub async fn handle_swagger_ui(Path(file): Path<String>) -> Response {
+	match utoipa_swagger_ui::serve(&file, SWAGGER_UI.clone()) {
+		Ok(Some(asset)) => ([(header::CONTENT_TYPE, asset.content_type)], asset.bytes.into_owned()).into_response(),
+		Ok(None) => SandboxError::NotFound(format!("no Swagger UI asset {file:?}")).into_response(),
+		Err(e) => SandboxError::Internal(format!("Swagger UI: {e}")).into_response(),
+	}
+}
+
+/// The OpenAPI 3.1 description of the HTTP API.
+///
+/// Built per request so that the registered tools' args schemas are part of
+/// it: `ToolExecutionRequest` is one of a request per tool, with that tool's
+/// schema for `args`, or the generic shape for tools without one.
+pub fn document(executor: &Executor) -> Value {
+	let mut schemas = components();
+	let registry = executor.registry();
+	let mut variants = Vec::new();
+	let mut tools: Vec<_> = registry.tools().filter(|t| t.args_schema.is_some()).collect();
+	tools.sort_by(|a, b| a.name.cmp(&b.name));
+	for tool in tools {
+		let name = format!("ToolRequest.{}", component_name(&tool.name));
+		schemas.insert(
+			name.clone(),
+			json!({
+				"description": tool.description,
+				"allOf": [
+					schema_ref("ToolRequestFields"),
+					{
+						"type": "object",
+						"properties": {
+							"tool_name": tool_name_schema(tool),
+							"args": tool.args_schema,
+						},
+					},
+				],
+			}),
+		);
+		variants.push(schema_ref(&name));
+	}
+	variants.push(schema_ref("ToolRequestFields"));
+	schemas.insert("ToolExecutionRequest".to_string(), json!({ "anyOf": variants }));
+
+	json!({
+		"openapi": "3.1.0",
+		"info": {
+			"title": "PAGI Rust sandbox",
+			"version": crate::VERSION,
+			"description": "Tool execution in isolated subprocesses, containers and WASM.",
+		},
+		"security": [{ "apiKey": [] }, { "bearer": [] }],
+		"paths": paths(),
+		"components": {
+			"schemas": schemas,
+			"securitySchemes": {
+				"apiKey": { "type": "apiKey", "in": "header", "name": "x-api-key" },
+				"bearer": { "type": "http", "scheme": "bearer", "description": "An API key or a JWT." },
+			},
+		},
+	})
+}
+
+/// Component names may only hold `[A-Za-z0-9._-]`.
+fn component_name(tool_name: &str) -> String {
+	tool_name
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '_' })
+		.collect()
+}
+
+fn schema_ref(name: &str) -> Value {
+	json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+fn json_body(schema: Value) -> Value {
+	json!({ "content": { "application/json": { "schema": schema } } })
+}
+
+fn reply(description: &str, schema: Value) -> Value {
+	let mut response = json_body(schema);
+	response["description"] = json!(description);
+	response
+}
+
+fn error(description: &str) -> Value {
+	reply(description, schema_ref("Error"))
+}
+
+/// A rejected tool request: the usual response with a failure status.
+fn rejected(description: &str) -> Value {
+	reply(description, schema_ref("ToolExecutionResponse"))
+}
+
+fn path_id(description: &str) -> Value {
+	json!([{ "name": "id", "in": "path", "required": true, "description": description, "schema": { "type": "string" } }])
+}
+
+fn query(params: &[(&str, &str, &str)]) -> Value {
+	params
+		.iter()
+		.map(|(name, kind, description)| {
+			json!({ "name": name, "in": "query", "description": description, "schema": { "type": kind } })
+		})
+		.collect()
+}
+
+fn operation(tag: &str, summary: &str, request: Option<Value>, responses: Value) -> Value {
+	let mut op = json!({ "tags": [tag], "summary": summary, "responses": responses });
+	if let Some(request) = request {
+		op["requestBody"] = request;
+		op["requestBody"]["required"] = json!(true);
+	}
+	op
+}
+
+/// The responses every admitted tool request can end in.
+fn tool_responses(ok: &str, ok_schema: Value) -> Value {
+	json!({
+		"200": reply(ok, ok_schema),
+		"403": rejected("The caller may not run the tool (`forbidden`)."),
+		"422": rejected("The args or input fail validation (`invalid_args`)."),
+		"429": rejected("Rate limited, over a tenant quota or budget, or the queue is full; see `Retry-After`."),
+	})
+}
+
+fn tool_request_body() -> Value {
+	json!({
+		"content": {
+			"application/json": { "schema": schema_ref("ToolExecutionRequest") },
+			"multipart/form-data": {
+				"schema": {
+					"type": "object",
+					"properties": {
+						"request": { "type": "string", "description": "The JSON request." },
+						"stdin": { "type": "string", "format": "binary" },
+						"input_file": {
+							"type": "array",
+							"items": { "type": "string", "format": "binary" },
+							"description": "Input files; each part's filename is its workspace path.",
+						},
+					},
+					"required": ["request"],
+				},
+			},
+		},
+	})
+}
+
+fn paths() -> Value {
+	let execute = operation(
+		"tools",
+		"Execute a tool, call a session with `session_id`, or plan an execution with `dry_run`",
+		Some(tool_request_body()),
+		tool_responses("The execution's result; `status` is `ok` or the failure class, or `planned` for a dry run.", schema_ref("ToolExecutionResponse")),
+	);
+	let mut legacy = execute.clone();
+	legacy["summary"] = json!("Execute a tool (older route)");
+	let mut stream_responses = tool_responses("Server-sent `stdout`/`stderr` events, then one `result` event.", json!({}));
+	stream_responses["200"] = json!({
+		"description": "Server-sent `stdout`/`stderr` events, then one `result` event.",
+		"content": { "text/event-stream": { "schema": { "type": "string" } } },
+	});
+
+	let mut paths = json!({
+		"/api/v1/execute_tool": { "post": execute },
+		"/execute-tool": { "post": legacy },
+		"/api/v1/execute_tool/stream": {
+			"post": operation("tools", "Execute a tool, streaming its output", Some(tool_request_body()), stream_responses),
+		},
+		"/api/v1/execute_batch": {
+			"post": operation(
+				"tools",
+				"Execute several tools",
+				Some(json_body(schema_ref("BatchRequest"))),
+				json!({
+					"200": reply("Every item's result, in request order.", schema_ref("BatchResponse")),
+					"422": error("The batch is empty or too long."),
+				}),
+			),
+		},
+		"/api/v1/execute_pipeline": {
+			"post": operation(
+				"tools",
+				"Execute a DAG of templated tool steps",
+				Some(json_body(schema_ref("PipelineRequest"))),
+				json!({
+					"200": reply("Every step's result, in request order.", schema_ref("PipelineResponse")),
+					"422": error("The pipeline is empty, too long, or not a valid DAG."),
+				}),
+			),
+		},
+		"/api/v1/tools": {
+			"get": operation(
+				"tools",
+				"List registered tools",
+				None,
+				json!({ "200": reply("The registered tools.", json!({
+					"type": "object",
+					"properties": { "tools": { "type": "array", "items": schema_ref("ToolSummary") } },
+				})) }),
+			),
+		},
+		"/api/v1/artifacts/{id}": {
+			"parameters": path_id("The artifact's SHA-256."),
+			"get": operation(
+				"tools",
+				"Download an artifact",
+				None,
+				json!({
+					"200": {
+						"description": "The artifact's contents.",
+						"content": { "application/octet-stream": { "schema": { "type": "string", "format": "binary" } } },
+					},
+					"404": error("No such artifact."),
+				}),
+			),
+		},
+		"/api/v1/usage": {
+			"get": {
+				"tags": ["tools"],
+				"summary": "Get the caller's tenant usage and quotas",
+				"parameters": query(&[
+					("tenant", "string", "Another tenant than the caller's; admins only. Admins get every tenant without it."),
+				]),
+				"responses": {
+					"200": reply("Today's usage, for one tenant or all of them.", json!({
+						"anyOf": [
+							schema_ref("TenantUsage"),
+							{
+								"type": "object",
+								"properties": { "tenants": { "type": "array", "items": schema_ref("TenantUsage") } },
+							},
+						],
+					})),
+					"403": error("Not an admin, and the tenant is not the caller's."),
+				},
+			},
+		},
+		"/api/v1/sessions": {
+			"get": operation(
+				"sessions",
+				"List the caller's sessions",
+				None,
+				json!({ "200": reply("The caller's sessions.", json!({
+					"type": "object",
+					"properties": { "sessions": { "type": "array", "items": schema_ref("Session") } },
+				})) }),
+			),
+			"post": operation(
+				"sessions",
+				"Start a stateful session",
+				Some(tool_request_body()),
+				json!({
+					"201": reply("The new session.", schema_ref("Session")),
+					"403": rejected("The caller may not run the tool."),
+					"422": error("The tool cannot hold a session, or the request is invalid."),
+					"429": error("A session limit, the rate limit, a tenant quota or budget, or the queue is full."),
+				}),
+			),
+		},
+		"/api/v1/sessions/{id}": {
+			"parameters": path_id("The session id."),
+			"get": operation(
+				"sessions",
+				"Get a session",
+				None,
+				json!({
+					"200": reply("The session.", schema_ref("Session")),
+					"307": { "description": "The session is held by the instance in `Location` (session routing `redirect`)." },
+					"404": error("No such session of the caller's."),
+					"502": error("The instance holding the session is unreachable."),
+				}),
+			),
+			"delete": operation(
+				"sessions",
+				"Tear a session down",
+				None,
+				json!({
+					"200": reply("The session and the result of its whole run.", json!({
+						"type": "object",
+						"properties": { "session": schema_ref("Session"), "response": schema_ref("ToolExecutionResponse") },
+					})),
+					"307": { "description": "The session is held by the instance in `Location` (session routing `redirect`)." },
+					"404": error("No such session of the caller's."),
+					"502": error("The instance holding the session is unreachable."),
+				}),
+			),
+		},
+		"/api/v1/sessions/ws": {
+			"get": operation(
+				"sessions",
+				"Upgrade to a WebSocket for one interactive tool session",
+				None,
+				json!({ "101": { "description": "Switching protocols; JSON frames as described in the README." } }),
+			),
+		},
+		"/api/v1/mcp": {
+			"post": operation(
+				"tools",
+				"MCP streamable HTTP transport",
+				Some(json_body(json!({ "description": "A JSON-RPC 2.0 message or batch." }))),
+				json!({
+					"200": reply("The JSON-RPC answer.", json!({})),
+					"202": { "description": "A notification; nothing to answer." },
+					"400": reply("Not JSON (a JSON-RPC parse error).", json!({})),
+					"403": error("The request's Origin is not allowed."),
+				}),
+			),
+		},
+		"/api/v1/jobs": {
+			"get": {
+				"tags": ["jobs"],
+				"summary": "Query past executions from the job history",
+				"parameters": query(&[
+					("tool", "string", "Only this tool's executions."),
+					("status", "string", "Only executions with this status."),
+					("since", "integer", "Only executions finished at or after this time, in ms since the epoch."),
+					("limit", "integer", "At most this many, newest first."),
+				]),
+				"responses": {
+					"200": reply("Matching executions.", json!({
+						"type": "object",
+						"properties": { "executions": { "type": "array", "items": { "type": "object" } } },
+					})),
+					"404": error("Job history is disabled."),
+					"503": error("Job history is unavailable."),
+				},
+			},
+			"post": operation(
+				"jobs",
+				"Submit an async job",
+				Some(tool_request_body()),
+				{
+					let mut responses = tool_responses("", json!({}));
+					responses.as_object_mut().expect("responses are an object").remove("200");
+					responses["202"] = reply("The queued job, or one pending approval.", schema_ref("Job"));
+					responses
+				},
+			),
+		},
+		"/api/v1/jobs/{id}": {
+			"parameters": path_id("The job id."),
+			"get": operation("jobs", "Get a job", None, json!({ "200": reply("The job.", schema_ref("Job")), "404": error("No such job.") })),
+			"delete": operation(
+				"jobs",
+				"Cancel a job",
+				None,
+				json!({
+					"200": reply("The cancelled job.", schema_ref("Job")),
+					"404": error("No such job."),
+					"409": reply("The job had already finished.", schema_ref("Job")),
+				}),
+			),
+		},
+		"/api/v1/schedules": {
+			"get": operation(
+				"schedules",
+				"List schedules with their recent runs",
+				None,
+				json!({
+					"200": reply("Every schedule.", json!({
+						"type": "object",
+						"properties": { "schedules": { "type": "array", "items": schema_ref("Schedule") } },
+					})),
+					"403": error("Not an admin."),
+				}),
+			),
+			"post": operation(
+				"schedules",
+				"Run a tool on a cron schedule",
+				Some(json_body(schema_ref("ScheduleSpec"))),
+				json!({
+					"201": reply("The new schedule.", schema_ref("Schedule")),
+					"403": error("Not an admin, or not allowed to run the tool."),
+					"409": error("The id is taken."),
+					"422": error("The cron expression, tool or args are invalid."),
+					"429": error("The server holds its maximum of schedules."),
+				}),
+			),
+		},
+		"/api/v1/schedules/{id}": {
+			"parameters": path_id("The schedule id."),
+			"get": operation("schedules", "Get a schedule", None, json!({ "200": reply("The schedule.", schema_ref("Schedule")), "404": error("No such schedule.") })),
+			"patch": operation(
+				"schedules",
+				"Enable or disable a schedule",
+				Some(json_body(json!({ "type": "object", "properties": { "enabled": { "type": "boolean" } }, "required": ["enabled"] }))),
+				json!({ "200": reply("The schedule.", schema_ref("Schedule")), "404": error("No such schedule.") }),
+			),
+			"delete": operation(
+				"schedules",
+				"Delete a schedule; a run in progress finishes",
+				None,
+				json!({ "200": reply("The deleted schedule.", schema_ref("Schedule")), "404": error("No such schedule.") }),
+			),
+		},
+		"/api/v1/admin/rbac": {
+			"get": operation("admin", "Get the RBAC policy", None, json!({ "200": reply("The policy, or null without one.", json!({ "type": ["object", "null"] })), "403": error("Not an admin.") })),
+			"put": operation(
+				"admin",
+				"Replace the RBAC policy",
+				Some(json_body(json!({ "type": "object" }))),
+				json!({ "200": reply("The new policy.", json!({ "type": "object" })), "403": error("Not an admin."), "422": error("The policy is invalid.") }),
+			),
+		},
+		"/api/v1/admin/policy/evaluate": {
+			"post": operation(
+				"admin",
+				"Evaluate the execution policy for a request without running it",
+				Some(json_body(json!({
+					"type": "object",
+					"required": ["tool_name"],
+					"properties": {
+						"principal": {
+							"type": "object",
+							"properties": {
+								"subject": { "type": ["string", "null"], "description": "Null for an anonymous caller." },
+								"roles": { "type": "array", "items": { "type": "string" } },
+								"tenant": { "type": ["string", "null"] },
+							},
+						},
+						"tool_name": { "type": "string" },
+						"args": { "type": "object" },
+						"transport": { "type": "string", "default": "http" },
+					},
+				}))),
+				json!({
+					"200": reply("The policy's decision, never from the cache.", json!({
+						"type": "object",
+						"properties": {
+							"allow": { "type": "boolean" },
+							"reason": { "type": ["string", "null"] },
+							"engine": { "type": "string", "enum": ["rego", "cedar"] },
+							"input": { "type": "object", "description": "What the policy was given." },
+						},
+					})),
+					"403": error("Not an admin."),
+					"404": error("No execution policy is configured."),
+					"422": error("The policy failed to evaluate."),
+				}),
+			),
+		},
+		"/api/v1/admin/audit": {
+			"get": {
+				"tags": ["admin"],
+				"summary": "Query recent audit entries",
+				"parameters": query(&[
+					("subject", "string", "Only this caller's entries."),
+					("tenant", "string", "Only this tenant's entries."),
+					("tool_name", "string", "Only this tool's entries."),
+					("status", "string", "Only entries with this status."),
+					("since_ms", "integer", "Only entries at or after this time."),
+					("limit", "integer", "At most this many."),
+				]),
+				"responses": {
+					"200": reply("Matching entries.", json!({
+						"type": "object",
+						"properties": { "entries": { "type": "array", "items": schema_ref("AuditEntry") } },
+					})),
+					"403": error("Not an admin."),
+				},
+			},
+		},
+		"/api/v1/admin/config": {
+			"get": operation("admin", "Get the effective configuration, secrets redacted", None, json!({ "200": reply("The settings.", json!({ "type": "object" })), "403": error("Not an admin.") })),
+		},
+		"/api/v1/admin/queue": {
+			"get": operation("admin", "Get the work queue's depth and workers", None, json!({ "200": reply("The queue.", schema_ref("QueueStatus")), "403": error("Not an admin."), "404": error("The work queue is disabled."), "503": error("Redis is unreachable.") })),
+		},
+		"/api/v1/admin/log_level": {
+			"get": operation("admin", "Get each log sink's filter", None, json!({ "200": reply("Directives by sink.", log_levels_schema()), "403": error("Not an admin.") })),
+			"put": operation(
+				"admin",
+				"Change the log filter of every sink, or of one, until restart",
+				Some(json_body(json!({
+					"type": "object",
+					"required": ["directives"],
+					"properties": {
+						"directives": { "type": "string", "description": "EnvFilter directives, e.g. `info,tool_executor=debug`." },
+						"sink": { "type": "string", "enum": ["console", "file", "syslog", "otlp"] },
+					},
+				}))),
+				json!({ "200": reply("The new directives by sink.", log_levels_schema()), "403": error("Not an admin."), "422": error("The directives do not parse, or no such sink is running.") }),
+			),
+			"delete": operation("admin", "Put every log sink back at its configured level", None, json!({ "200": reply("Directives by sink.", log_levels_schema()), "403": error("Not an admin.") })),
+		},
+		"/api/v1/admin/reload": {
+			"get": operation("admin", "Get the last reload", None, json!({ "200": reply("The current configuration version.", schema_ref("ReloadStatus")), "403": error("Not an admin.") })),
+			"post": operation(
+				"admin",
+				"Reload manifests, RBAC and execution policies and rate limits",
+				None,
+				json!({
+					"200": reply("The new configuration version.", schema_ref("ReloadStatus")),
+					"403": error("Not an admin."),
+					"422": error("Nothing was changed; `details.errors` lists the problems."),
+				}),
+			),
+		},
+		"/healthz": {
+			"get": {
+				"tags": ["probes"],
+				"summary": "Liveness",
+				"security": [],
+				"responses": { "200": reply("The process is up.", json!({ "type": "object" })) },
+			},
+		},
+		"/readyz": {
+			"get": {
+				"tags": ["probes"],
+				"summary": "Readiness",
+				"security": [],
+				"responses": {
+					"200": reply("Every check passes.", json!({ "type": "object" })),
+					"503": reply("A check fails, or shutdown has begun.", json!({ "type": "object" })),
+				},
+			},
+		},
+		"/metrics": {
+			"get": {
+				"tags": ["probes"],
+				"summary": "Prometheus metrics",
+				"security": [],
+				"responses": { "200": { "description": "Text exposition format.", "content": { "text/plain": { "schema": { "type": "string" } } } } },
+			},
+		},
+	});
+	// Outside the literal above, which is at the macro recursion limit.
+	for more in [tool_paths(), job_paths(), admin_control_paths(), cluster_paths(), docs_paths()] {
+		if let (Value::Object(paths), Value::Object(more)) = (&mut paths, more) {
+			paths.extend(more);
+		}
+	}
+	for path in ["/api/v1/execute_tool", "/execute-tool", "/api/v1/jobs"] {
+		idempotent(&mut paths[path]["post"]);
+	}
+	paths["/health"] = paths["/healthz"].clone();
+	paths["/health"]["get"]["summary"] = json!("Liveness (older route)");
+	paths["/api/v1/scaling"] = json!({
+		"get": {
+			"tags": ["probes"],
+			"summary": "How much work this replica has, for external autoscalers",
+			"security": [],
+			"responses": { "200": reply("The signal.", schema_ref("ScalingSignal")) },
+		},
+	});
+	for path in ["/api/v1/execute_tool", "/execute-tool"] {
+		paths[path]["post"]["responses"]["502"] = error("The cluster peer the request was forwarded to did not answer.");
+	}
+	for path in [
+		"/api/v1/execute_tool",
+		"/execute-tool",
+		"/api/v1/execute_tool/stream",
+		"/api/v1/execute_batch",
+		"/api/v1/execute_pipeline",
+		"/api/v1/mcp",
+	] {
+		with_deadline(&mut paths[path]["post"]);
+	}
+	for path in [
+		"/api/v1/execute_tool",
+		"/execute-tool",
+		"/api/v1/execute_batch",
+		"/api/v1/execute_pipeline",
+		"/api/v1/jobs",
+		"/api/v1/jobs/{id}",
+		"/api/v1/jobs/{id}/replay",
+		"/api/v1/jobs/{id}/approve",
+		"/api/v1/jobs/{id}/reject",
+	] {
+		if let Some(Value::Object(methods)) = paths.get_mut(path) {
+			methods.values_mut().for_each(negotiated);
+		}
+	}
+	let stream = &mut paths["/api/v1/execute_tool/stream"]["post"];
+	negotiated(stream);
+	let event = json!({
+		"type": "object",
+		"description": "One event: `{\"event\", \"data\"}`, `data` being what the event-stream event carries.",
+		"properties": { "event": { "type": "string" }, "data": {} },
+	});
+	stream["responses"]["200"]["content"] = json!({
+		"text/event-stream": { "schema": { "type": "string" } },
+		"application/x-ndjson": { "schema": event },
+		"application/msgpack": { "schema": event },
+	});
+	paths
+}
+
+/// Accept an `Idempotency-Key` on the operation `op`.
+fn idempotent(op: &mut Value) {
+	let header = json!({
+		"name": "Idempotency-Key",
+		"in": "header",
+		"description": "Run the request once: a retry with the same key gets the first response, marked `Idempotent-Replayed: true`.",
+		"schema": { "type": "string", "maxLength": 255 },
+	});
+	match op.get_mut("parameters").and_then(Value::as_array_mut) {
+		Some(parameters) => parameters.push(header),
+		None => op["parameters"] = json!([header]),
+	}
+	op["responses"]["409"] = error("A request with this key is still running on another instance, or its response was too large to keep.");
+	if op["responses"].get("422").is_none() {
+		op["responses"]["422"] = error("The key was used for a different request.");
+	}
+}
+
+/// Accept an `X-Deadline-Ms` on the operation `op`.
+fn with_deadline(op: &mut Value) {
+	let header = json!({
+		"name": "X-Deadline-Ms",
+		"in": "header",
+		"description": "How long the caller waits, in milliseconds. Executions end before then, or with status `deadline_exceeded` once it has passed.",
+		"schema": { "type": "integer", "minimum": 0 },
+	});
+	match op.get_mut("parameters").and_then(Value::as_array_mut) {
+		Some(parameters) => parameters.push(header),
+		None => op["parameters"] = json!([header]),
+	}
+	if op["responses"].get("400").is_none() {
+		op["responses"]["400"] = error("Malformed request.");
+	}
+}
+
+/// Offer the NDJSON and MessagePack encodings of each JSON response of the
+/// operation `op`, as `Accept` selects, and the `406` of accepting none.
+fn negotiated(op: &mut Value) {
+	let Some(Value::Object(responses)) = op.get_mut("responses") else {
+		return;
+	};
+	for response in responses.values_mut() {
+		if let Some(content) = response.get_mut("content") {
+			if let Some(json) = content.get("application/json").cloned() {
+				content["application/x-ndjson"] = json.clone();
+				content["application/msgpack"] = json;
+			}
+		}
+	}
+	responses.insert("406".to_string(), error("The request accepts none of the formats offered (`not_acceptable`)."));
+}
+
+fn tool_paths() -> Value {
+	json!({
+		"/api/v1/tools/{name}": {
+			"parameters": [{
+				"name": "name",
+				"in": "path",
+				"required": true,
+				"description": "The tool name, optionally with a version or range: `wordcount`, `wordcount@1.2.0`, `wordcount@^1.2`.",
+				"schema": { "type": "string" },
+			}],
+			"get": operation(
+				"tools",
+				"Get a tool and its versions",
+				None,
+				json!({ "200": reply("The version the name resolves to, with every version's changelog.", schema_ref("ToolDetail")), "404": error("No tool, or no version of it, matches.") }),
+			),
+		},
+		"/api/v1/tools/{name}/stats": {
+			"parameters": [{
+				"name": "name",
+				"in": "path",
+				"required": true,
+				"description": "The tool name, for every version of it, or `name@<version>` for one.",
+				"schema": { "type": "string" },
+			}],
+			"get": {
+				"tags": ["tools"],
+				"summary": "Summarize a tool's recent executions from the job history",
+				"parameters": query(&[("hours", "integer", "How far back to look; 24 by default.")]),
+				"responses": {
+					"200": reply("Counts by status and failure class, the cache hit ratio, and percentiles of wait, duration and resource usage.", tool_stats_schema()),
+					"404": error("Job history is disabled."),
+					"503": error("Job history is unavailable."),
+				},
+			},
+		},
+	})
+}
+
+fn log_levels_schema() -> Value {
+	json!({
+		"type": "object",
+		"properties": { "sinks": { "type": "object", "additionalProperties": { "type": "string" } } },
+	})
+}
+
+fn tool_stats_schema() -> Value {
+	let distribution = json!({
+		"type": ["object", "null"],
+		"properties": {
+			"count": { "type": "integer" },
+			"mean": { "type": "number" },
+			"p50": { "type": "integer" },
+			"p95": { "type": "integer" },
+			"p99": { "type": "integer" },
+			"max": { "type": "integer" },
+		},
+	});
+	let counts = json!({ "type": "object", "additionalProperties": { "type": "integer" } });
+	json!({
+		"type": "object",
+		"properties": {
+			"tool_name": { "type": "string" },
+			"since_ms": { "type": "integer" },
+			"executions": { "type": "integer" },
+			"sampled": { "type": "boolean", "description": "Only the 50000 most recent executions were looked at." },
+			"by_status": counts,
+			"failures": counts,
+			"cache_hits": { "type": "integer" },
+			"cache_hit_ratio": { "type": ["number", "null"] },
+			"wait_ms": distribution,
+			"duration_ms": distribution,
+			"cpu_ms": distribution,
+			"peak_rss_bytes": distribution,
+			"output_bytes": distribution,
+		},
+	})
+}
+
+fn job_paths() -> Value {
+	let decision = |summary: &str, done: &str| {
+		operation(
+			"jobs",
+			summary,
+			None,
+			json!({
+				"200": reply(done, schema_ref("Job")),
+				"403": error("The caller may not decide on this tool's jobs, or submitted the job."),
+				"404": error("No such job."),
+				"409": error("The job is not pending approval."),
+			}),
+		)
+	};
+	let mut paths = json!({
+		"/api/v1/jobs/{id}/approve": {
+			"parameters": path_id("The job id."),
+			"post": decision("Approve a job pending approval", "The approved job, now queued."),
+		},
+		"/api/v1/jobs/{id}/reject": {
+			"parameters": path_id("The job id."),
+			"post": decision("Reject a job pending approval", "The rejected job."),
+		},
+	});
+	paths["/api/v1/jobs/{id}/approve"]["post"]["responses"]["429"] = error("The queue is full; the job stays pending.");
+	paths["/api/v1/jobs/{id}/reject"]["post"]["requestBody"] = json!({
+		"required": false,
+		"content": { "application/json": { "schema": {
+			"type": "object",
+			"properties": { "reason": { "type": "string", "description": "Why, for the submitter." } },
+		} } },
+	});
+	paths["/api/v1/jobs/{id}/replay"] = json!({
+		"parameters": path_id("The execution id, or a job id for the job's execution."),
+		"post": {
+			"tags": ["jobs"],
+			"summary": "Run a recorded execution again as a new job",
+			"parameters": query(&[(
+				"allow_env_changes",
+				"boolean",
+				"Replay even if host variables the tool inherits have changed since.",
+			)]),
+			"responses": {
+				"202": reply("The queued job, with `replay_of` naming the execution.", schema_ref("Job")),
+				"404": error("Replay is disabled, or there is no such execution."),
+				"409": error("The execution has no recorded request, its tool version is gone, or its environment has changed."),
+				"422": error("The recorded request fails validation now."),
+				"429": error("Rate limited, over quota, or the queue is full."),
+				"503": error("Job history is unavailable."),
+			},
+		},
+	});
+	paths
+}
+
+/// The admin API's runtime controls.
+fn admin_control_paths() -> Value {
+	json!({
+		"/api/v1/admin/executions": {
+			"get": operation(
+				"admin",
+				"List the executions running or queued here",
+				None,
+				json!({
+					"200": reply("The executions.", json!({
+						"type": "object",
+						"properties": {
+							"draining": { "type": "boolean" },
+							"in_flight": { "type": "integer" },
+							"queued": { "type": "integer" },
+							"executions": { "type": "array", "items": schema_ref("RunningExecution") },
+						},
+					})),
+					"403": error("Not an admin."),
+				}),
+			),
+		},
+		"/api/v1/admin/executions/{id}": {
+			"parameters": path_id("The execution id."),
+			"delete": operation("admin", "Kill an execution", None, json!({ "202": reply("The execution is being cancelled.", json!({ "type": "object" })), "403": error("Not an admin."), "404": error("No such execution here.") })),
+		},
+		"/api/v1/admin/quarantine": {
+			"parameters": query(&[("state", "string", "Only entries in this state: `pending`, `approved` or `rejected`.")]),
+			"get": operation(
+				"admin",
+				"List quarantined executions, oldest first",
+				None,
+				json!({ "200": reply("The entries.", json!({ "type": "object", "properties": { "entries": { "type": "array", "items": schema_ref("QuarantineEntry") } } })), "403": error("Not an admin."), "404": error("Quarantine is off.") }),
+			),
+		},
+		"/api/v1/admin/quarantine/{id}": {
+			"parameters": path_id("The execution id."),
+			"get": operation("admin", "Get a quarantined execution", None, json!({ "200": reply("The entry.", schema_ref("QuarantineEntry")), "403": error("Not an admin."), "404": error("No such entry, or quarantine is off.") })),
+		},
+		"/api/v1/admin/quarantine/{id}/approve": {
+			"parameters": path_id("The execution id."),
+			"post": operation("admin", "Release a quarantined execution's artifacts", None, json!({ "200": reply("The entry; its artifacts can now be fetched.", schema_ref("QuarantineEntry")), "403": error("Not an admin."), "404": error("No such entry, or quarantine is off."), "409": error("Approved or rejected already.") })),
+		},
+		"/api/v1/admin/quarantine/{id}/reject": {
+			"parameters": path_id("The execution id."),
+			"post": operation("admin", "Delete a quarantined execution's artifacts", None, json!({ "200": reply("The entry.", schema_ref("QuarantineEntry")), "403": error("Not an admin."), "404": error("No such entry, or quarantine is off."), "409": error("Approved or rejected already.") })),
+		},
+		"/api/v1/admin/drain": {
+			"get": operation("admin", "Get whether the instance is draining", None, json!({ "200": reply("The drain state.", schema_ref("DrainStatus")), "403": error("Not an admin.") })),
+			"post": operation("admin", "Start draining: take no new executions", None, json!({ "200": reply("The drain state.", schema_ref("DrainStatus")), "403": error("Not an admin.") })),
+			"delete": operation("admin", "Stop draining", None, json!({ "200": reply("The drain state.", schema_ref("DrainStatus")), "403": error("Not an admin.") })),
+		},
+		"/api/v1/admin/tools": {
+			"get": operation("admin", "List every loaded tool and whether it is on", None, json!({ "200": reply("The tools.", json!({ "type": "object" })), "403": error("Not an admin.") })),
+		},
+		"/api/v1/admin/tools/{name}": {
+			"parameters": [{ "name": "name", "in": "path", "required": true, "description": "The tool name.", "schema": { "type": "string" } }],
+			"put": operation(
+				"admin",
+				"Turn a tool off or on",
+				Some(json_body(json!({ "type": "object", "required": ["enabled"], "properties": { "enabled": { "type": "boolean" } } }))),
+				json!({ "200": reply("The tool's new state.", json!({ "type": "object" })), "403": error("Not an admin."), "404": error("No tool by that name is loaded.") }),
+			),
+		},
+	})
+}
+
+fn cluster_paths() -> Value {
+	let disabled = "Clustering is disabled.";
+	json!({
+		"/api/v1/cluster/node": {
+			"get": operation("cluster", "Get what this node advertises to its peers", None, json!({ "200": reply("The node.", schema_ref("NodeInfo")), "404": error(disabled) })),
+		},
+		"/api/v1/admin/cluster": {
+			"get": operation(
+				"admin",
+				"Get this node and the cluster peers it knows of",
+				None,
+				json!({
+					"200": reply("The cluster.", json!({
+						"type": "object",
+						"properties": {
+							"node": schema_ref("NodeInfo"),
+							"discovery": { "type": "string", "enum": ["static", "etcd", "consul"] },
+							"forward": { "type": "boolean" },
+							"peers": { "type": "array", "items": schema_ref("NodeInfo") },
+						},
+					})),
+					"403": error("Not an admin."),
+					"404": error(disabled),
+				}),
+			),
+		},
+	})
+}
+
+/// The document itself and the Swagger UI reading it.
+fn docs_paths() -> Value {
+	json!({
+		"/api/v1/openapi.json": {
+			"get": {
+				"tags": ["docs"],
+				"summary": "This document, with the registered tools' args schemas",
+				"security": [],
+				"responses": { "200": reply("The OpenAPI 3.1 document.", json!({ "type": "object" })) },
+			},
+		},
+		"/api/v1/docs": {
+			"get": {
+				"tags": ["docs"],
+				"summary": "Swagger UI, with `server.swagger_ui` on",
+				"security": [],
+				"responses": {
+					"308": { "description": "To `/api/v1/docs/index.html`." },
+					"404": error("Swagger UI is off."),
+				},
+			},
+		},
+		"/api/v1/docs/{file}": {
+			"get": {
+				"tags": ["docs"],
+				"summary": "A Swagger UI asset",
+				"security": [],
+				"parameters": [{ "name": "file", "in": "path", "required": true, "schema": { "type": "string" } }],
+				"responses": {
+					"200": { "description": "The asset.", "content": { "*/*": { "schema": { "type": "string" } } } },
+					"404": error("No such asset, or Swagger UI is off."),
+				},
+			},
+		},
+	})
+}
+
+fn components() -> Map<String, Value> {
+	// Outside the literal below, which is at the macro recursion limit.
+	let tenant_usage = json!({
+		"type": "object",
+		"properties": {
+			"tenant": { "type": "string" },
+			"day": { "type": "string", "description": "The UTC date of the daily figures." },
+			"executions": { "type": "integer" },
+			"cpu_seconds": { "type": "number" },
+			"storage_bytes": { "type": "integer" },
+			"month": { "type": "string", "description": "The UTC month (`YYYY-MM`) of the spend." },
+			"spend": { "type": "number", "description": "What the month's executions cost at `quotas.rates`." },
+			"currency": { "type": "string" },
+			"quota": {
+				"type": "object",
+				"description": "`null` quotas are unlimited.",
+				"properties": {
+					"executions_per_day": { "type": ["integer", "null"] },
+					"cpu_seconds_per_day": { "type": ["integer", "null"] },
+					"storage_bytes": { "type": ["integer", "null"] },
+					"monthly_budget": { "type": ["number", "null"] },
+					"monthly_budget_soft": { "type": ["number", "null"], "description": "Past it, a warning is logged." },
+				},
+			},
+		},
+	});
+	let schemas = json!({
+		"ToolRequestFields": {
+			"type": "object",
+			"properties": {
+				"tool_name": { "type": "string" },
+				"args": { "type": "object" },
+				"timeout_ms": { "type": "integer", "minimum": 0 },
+				"retain_workspace": { "type": "boolean", "default": false },
+				"artifacts": { "type": "array", "items": { "type": "string" }, "description": "Workspace globs to collect." },
+				"stdin": { "type": "string", "contentEncoding": "base64" },
+				"input_files": {
+					"type": "object",
+					"additionalProperties": { "type": "string", "contentEncoding": "base64" },
+					"description": "Files to write into the workspace, by relative path.",
+				},
+				"session_id": { "type": "string", "description": "Call this running session instead of starting the tool." },
+				"retry": schema_ref("RetryOverride"),
+				"callback_url": {
+					"type": "string",
+					"format": "uri",
+					"description": "Jobs only: `POST` a signed notice here when the job finishes.",
+				},
+				"priority": {
+					"type": "string",
+					"enum": ["interactive", "batch", "background"],
+					"description": "Run at this priority when it is below the route's own: interactive for single calls, batch for batches and pipelines, background for jobs.",
+				},
+				"dry_run": {
+					"type": "boolean",
+					"description": "`execute_tool` only: check the request and answer with status `planned` and the execution plan (resolved tool, timeout, limits, network, inherited variable names, secret names, retry policy, caching, artifacts, estimated cost) as `result`, without running it.",
+				},
+			},
+			"required": ["tool_name", "args"],
+		},
+		"RetryOverride": {
+			"type": "object",
+			"description": "Changes to the tool's retry policy for this call; attempts are capped by the server.",
+			"properties": {
+				"max_attempts": { "type": "integer", "minimum": 1 },
+				"initial_backoff_ms": { "type": "integer", "minimum": 0 },
+				"max_backoff_ms": { "type": "integer", "minimum": 0 },
+			},
+			"additionalProperties": false,
+		},
+		"ToolExecutionResponse": {
+			"type": "object",
+			"properties": {
+				"status": { "type": "string", "examples": ["ok", "non_zero_exit", "timed_out", "invalid_args"] },
+				"tool_name": { "type": "string" },
+				"result": schema_ref("ToolResult"),
+			},
+			"required": ["status", "tool_name", "result"],
+		},
+		"ToolResult": {
+			"type": "object",
+			"description": "What the tool produced; rejections carry `error` or `errors` instead.",
+			"properties": {
+				"stdout": { "description": "Parsed JSON, or `{\"stdout\": text}` when it is not JSON." },
+				"stderr": { "type": "string" },
+				"exit_code": { "type": ["integer", "null"] },
+				"workspace_id": { "type": "string" },
+				"artifacts": { "type": "array", "items": schema_ref("Artifact") },
+				"truncated": {
+					"type": "boolean",
+					"description": "Present when output went past the sandbox's limit; stdout and stderr then hold only its beginning.",
+				},
+				"stdout_bytes": { "type": "integer", "description": "Bytes the tool wrote to stdout, when truncated." },
+				"stderr_bytes": { "type": "integer", "description": "Bytes the tool wrote to stderr, when truncated." },
+				"attempts": { "type": "integer", "description": "Runs it took, when a retry policy applied." },
+				"error": { "type": "string" },
+				"errors": { "type": "array", "items": schema_ref("ArgError") },
+			},
+		},
+		"ArgError": {
+			"type": "object",
+			"properties": { "path": { "type": "string" }, "message": { "type": "string" } },
+			"required": ["path", "message"],
+		},
+		"Artifact": {
+			"type": "object",
+			"properties": {
+				"artifact_id": { "type": "string", "description": "SHA-256 of the contents." },
+				"path": { "type": "string" },
+				"size_bytes": { "type": "integer" },
+			},
+			"required": ["artifact_id", "path", "size_bytes"],
+		},
+		"BatchRequest": {
+			"type": "object",
+			"properties": {
+				"mode": { "enum": ["parallel", "sequential", "fail_fast"], "default": "parallel" },
+				"requests": { "type": "array", "items": schema_ref("ToolExecutionRequest"), "minItems": 1 },
+			},
+			"required": ["requests"],
+		},
+		"BatchResponse": {
+			"type": "object",
+			"properties": {
+				"mode": { "enum": ["parallel", "sequential", "fail_fast"] },
+				"results": { "type": "array", "items": timed(&[]) },
+				"succeeded": { "type": "integer" },
+				"failed": { "type": "integer" },
+				"skipped": { "type": "integer" },
+				"elapsed_ms": { "type": "integer" },
+			},
+		},
+		"PipelineRequest": {
+			"type": "object",
+			"properties": {
+				"steps": {
+					"type": "array",
+					"minItems": 1,
+					"items": {
+						"allOf": [
+							schema_ref("ToolRequestFields"),
+							{
+								"type": "object",
+								"properties": {
+									"id": { "type": "string" },
+									"depends_on": { "type": "array", "items": { "type": "string" } },
+								},
+								"required": ["id"],
+							},
+						],
+					},
+				},
+			},
+			"required": ["steps"],
+		},
+		"PipelineResponse": {
+			"type": "object",
+			"properties": {
+				"status": { "enum": ["ok", "failed"] },
+				"steps": { "type": "array", "items": timed(&[("id", json!({ "type": "string" })), ("started_ms", json!({ "type": "integer" }))]) },
+				"succeeded": { "type": "integer" },
+				"failed": { "type": "integer" },
+				"skipped": { "type": "integer" },
+				"elapsed_ms": { "type": "integer" },
+			},
+		},
+		"Session": {
+			"type": "object",
+			"properties": {
+				"session_id": { "type": "string" },
+				"tool_name": { "type": "string" },
+				"state": { "enum": ["running", "evicted", "exited"] },
+				"created_at_ms": { "type": "integer" },
+				"last_used_at_ms": { "type": "integer" },
+				"calls": { "type": "integer" },
+				"expires_at_ms": { "type": "integer" },
+			},
+		},
+		"Job": {
+			"type": "object",
+			"properties": {
+				"job_id": { "type": "string" },
+				"tool_name": { "type": "string" },
+				"state": { "enum": ["queued", "running", "completed", "cancelled", "preempted", "pending_approval", "rejected", "expired"] },
+				"created_at_ms": { "type": "integer" },
+				"started_at_ms": { "type": ["integer", "null"] },
+				"finished_at_ms": { "type": ["integer", "null"] },
+				"response": { "anyOf": [schema_ref("ToolExecutionResponse"), { "type": "null" }] },
+				"replay_of": { "type": "string", "description": "The execution this job replays." },
+			},
+		},
+		"ScheduleSpec": {
+			"type": "object",
+			"properties": {
+				"id": { "type": "string" },
+				"cron": { "type": "string", "description": "Five fields in UTC (minute hour day-of-month month day-of-week), or @hourly, @daily, @weekly, @monthly, @yearly." },
+				"tool_name": { "type": "string" },
+				"args": { "type": "object" },
+				"timeout_ms": { "type": "integer" },
+				"overlap": { "enum": ["skip", "queue"] },
+				"enabled": { "type": "boolean" },
+			},
+			"required": ["id", "cron", "tool_name"],
+		},
+		"Schedule": {
+			"allOf": [schema_ref("ScheduleSpec"), {
+				"type": "object",
+				"properties": {
+					"source": { "enum": ["config", "api"] },
+					"created_by": { "type": ["string", "null"] },
+					"created_at_ms": { "type": "integer" },
+					"next_run_at_ms": { "type": ["integer", "null"] },
+					"running": { "type": "boolean" },
+					"queued": { "type": "boolean" },
+					"runs": { "type": "array", "items": {
+						"type": "object",
+						"properties": {
+							"scheduled_at_ms": { "type": "integer" },
+							"started_at_ms": { "type": ["integer", "null"] },
+							"finished_at_ms": { "type": ["integer", "null"] },
+							"status": { "type": "string" },
+							"exit_code": { "type": ["integer", "null"] },
+							"execution_id": { "type": ["string", "null"] },
+						},
+					} },
+				},
+			}],
+		},
+		"ToolSummary": {
+			"type": "object",
+			"properties": {
+				"name": { "type": "string" },
+				"version": { "type": "string" },
+				"deprecated": { "type": "string" },
+				"versions": { "type": "array", "items": { "type": "string" } },
+				"description": { "type": "string" },
+				"backend": { "enum": ["native", "container", "firecracker", "wasm", "component", "builtin"] },
+				"args_schema": { "type": ["object", "null"] },
+				"limits": { "type": "object" },
+				"network": {},
+				"idempotent": { "type": "boolean" },
+			},
+		},
+		"AuditEntry": {
+			"type": "object",
+			"properties": {
+				"timestamp_ms": { "type": "integer" },
+				"subject": { "type": ["string", "null"] },
+				"tenant": { "type": ["string", "null"] },
+				"client_ip": { "type": ["string", "null"] },
+				"transport": { "type": "string" },
+				"request_id": { "type": ["string", "null"] },
+				"tool_name": { "type": "string" },
+				"args_sha256": { "type": "string" },
+				"status": { "type": "string" },
+			},
+		},
+		"ReloadStatus": {
+			"type": "object",
+			"properties": {
+				"version": { "type": "integer" },
+				"loaded_at_ms": { "type": "integer" },
+				"tools": { "type": "integer" },
+				"rbac_roles": { "type": ["integer", "null"] },
+				"execution_policy": { "type": ["string", "null"], "enum": ["rego", "cedar", null] },
+				"rate_limited_tools": { "type": "integer" },
+			},
+		},
+	});
+	let running_execution = json!({
+		"type": "object",
+		"properties": {
+			"execution_id": { "type": "string" },
+			"tool_name": { "type": "string" },
+			"backend": { "type": "string" },
+			"state": { "enum": ["queued", "running"] },
+			"priority": { "enum": ["interactive", "batch", "background"] },
+			"subject": { "type": ["string", "null"] },
+			"tenant": { "type": ["string", "null"] },
+			"transport": { "type": "string" },
+			"request_id": { "type": ["string", "null"] },
+			"job_id": { "type": ["string", "null"] },
+			"admitted_at_ms": { "type": "integer" },
+			"started_at_ms": { "type": ["integer", "null"] },
+			"timeout_ms": { "type": "integer" },
+			"max_output_bytes": { "type": "integer" },
+			"limits": { "type": "object", "description": "The tool's manifest limits." },
+			"pids": { "type": "array", "items": { "type": "integer" }, "description": "Leaders of its process groups." },
+		},
+	});
+	let queue_status = json!({
+		"type": "object",
+		"properties": {
+			"waiting": { "type": "integer", "description": "Executions no worker has taken yet." },
+			"pending": { "type": "integer", "description": "Executions taken but not yet finished." },
+			"workers": {
+				"type": "array",
+				"items": {
+					"type": "object",
+					"properties": {
+						"worker_id": { "type": "string" },
+						"host": { "type": "string" },
+						"started_at_ms": { "type": "integer" },
+						"last_seen_ms": { "type": "integer" },
+						"running": { "type": "integer" },
+						"capacity": { "type": "integer" },
+						"alive": { "type": "boolean" },
+					},
+				},
+			},
+		},
+	});
+	let scaling_signal = json!({
+		"type": "object",
+		"description": "The answering replica's own work, for KEDA's metrics-api scaler or an HPA external metric: scale out while pending_queue_depth or avg_wait_ms per replica is above target, in once both stay at 0. Draining replicas take no work. Fields are only ever added.",
+		"properties": {
+			"pending_queue_depth": { "type": "integer", "description": "Executions admitted but not yet running, GPU waits included." },
+			"avg_wait_ms": { "type": "integer", "description": "Mean wait for a slot over the last `window_secs`, those still waiting included; 0 without any." },
+			"running": { "type": "integer" },
+			"max_concurrent": { "type": "integer" },
+			"max_queued": { "type": "integer", "description": "Pending executions beyond this are turned away with `429`." },
+			"utilization": { "type": "number", "description": "`(running + pending_queue_depth) / max_concurrent`." },
+			"draining": { "type": "boolean" },
+			"window_secs": { "type": "integer" },
+			"shared_queue_depth": { "type": "integer", "description": "Executions waiting in the work queue for any worker; the same on every replica." },
+		},
+		"required": ["pending_queue_depth", "avg_wait_ms", "running", "max_concurrent", "max_queued", "utilization", "draining", "window_secs"],
+	});
+	let node_info = json!({
+		"type": "object",
+		"description": "A sandbox node as its cluster peers see it.",
+		"properties": {
+			"node_id": { "type": "string" },
+			"url": { "type": "string", "description": "Where peers reach it." },
+			"region": { "type": "string" },
+			"backends": { "type": "array", "items": { "type": "string" } },
+			"tools": { "type": "array", "items": { "type": "string" }, "description": "Names of its loaded tools." },
+			"gpus": { "type": "integer" },
+			"gpus_free": { "type": "integer" },
+			"capacity": {
+				"type": "object",
+				"properties": {
+					"max_concurrent": { "type": "integer" },
+					"max_queued": { "type": "integer" },
+					"running": { "type": "integer" },
+					"queued": { "type": "integer" },
+				},
+			},
+			"draining": { "type": "boolean" },
+			"updated_at_ms": { "type": "integer", "description": "When it was last heard from." },
+		},
+		"required": ["node_id", "url", "backends", "tools", "gpus", "gpus_free", "capacity", "draining", "updated_at_ms"],
+	});
+	let error = json!({
+		"type": "object",
+		"description": "Every error the sandbox returns, rather than a tool's outcome.",
+		"properties": {
+			"code": {
+				"type": "string",
+				"description": "What went wrong: `invalid_request`, `invalid_args`, `invalid`, `unsupported`, `unauthorized`, `forbidden`, `not_found`, `disabled`, `conflict`, `payload_too_large`, `rate_limited`, `quota_exceeded`, `budget_exceeded`, `queue_full`, `session_limit`, `schedule_limit`, `draining`, `bad_gateway`, `unavailable` or `internal`.",
+			},
+			"message": { "type": "string" },
+			"details": { "type": ["object", "null"], "description": "More about some errors, e.g. `errors` or `retry_after_secs`." },
+			"request_id": { "type": ["string", "null"], "description": "The request's `X-Request-Id`." },
+		},
+		"required": ["code", "message", "details", "request_id"],
+	});
+	match schemas {
+		Value::Object(mut map) => {
+			map.insert("Error".to_string(), error);
+			map.insert(
+				"ToolDetail".to_string(),
+				json!({
+					"allOf": [schema_ref("ToolSummary"), {
+						"type": "object",
+						"properties": {
+							"changelog": { "type": ["string", "null"] },
+							"versions": { "type": "array", "items": {
+								"type": "object",
+								"properties": {
+									"version": { "type": "string" },
+									"deprecated": { "type": ["string", "null"] },
+									"changelog": { "type": ["string", "null"] },
+								},
+							} },
+						},
+					}],
+				}),
+			);
+			map.insert(
+				"ExecutionTrace".to_string(),
+				json!({
+					"type": "object",
+					"description": "What an execution took from outside, as recorded with `record_trace`.",
+					"properties": {
+						"time_ms": { "type": "integer", "description": "The execution's clock at the start, in ms since the epoch." },
+						"seed": { "type": "integer" },
+						"http": { "type": "array", "items": {
+							"type": "object",
+							"properties": {
+								"method": { "type": "string" },
+								"url": { "type": "string" },
+								"body_sha256": { "type": "string" },
+								"status": { "type": "string" },
+								"stdout": { "type": "string" },
+								"stderr": { "type": "string" },
+							},
+							"required": ["method", "url", "status"],
+						} },
+					},
+					"required": ["time_ms", "seed"],
+				}),
+			);
+			let fields = &mut map["ToolRequestFields"]["properties"];
+			fields["record_trace"] = json!({
+				"type": "boolean",
+				"description": "Record the execution's clock, seed and `http_request` answers and return them as `result.trace`.",
+			});
+			fields["replay_trace"] = json!({
+				"allOf": [schema_ref("ExecutionTrace")],
+				"description": "Serve the execution a recorded trace in place of the outside world; a call other than the one recorded next fails with `trace_mismatch`.",
+			});
+			let result = &mut map["ToolResult"]["properties"];
+			result["trace"] = schema_ref("ExecutionTrace");
+			result["trace_unused"] = json!({ "type": "integer", "description": "Recorded HTTP exchanges a replay did not ask for." });
+			result["usage"] = schema_ref("ResourceUsage");
+			result["environment"] = schema_ref("ExecutionEnvironment");
+			result["policy_violations"] = json!({
+				"type": "array",
+				"items": schema_ref("PolicyViolation"),
+				"description": "What the code scan found in submitted code: why a `policy_violation` did not run, or what flagged code ran with.",
+			});
+			result["quarantined"] = json!({
+				"type": "object",
+				"description": "Set when the execution was quarantined; its artifacts are held until an operator approves them.",
+				"properties": {
+					"id": { "type": "string", "description": "The execution id, as in `/api/v1/admin/quarantine/{id}`." },
+					"reasons": { "type": "array", "items": schema_ref("QuarantineReason") },
+					"artifacts_held": { "type": "integer" },
+				},
+				"required": ["id", "reasons", "artifacts_held"],
+			});
+			result["redactions"] = json!({
+				"type": "array",
+				"items": schema_ref("Redaction"),
+				"description": "What the output filters took out of `stdout` and `stderr`.",
+			});
+			map.insert(
+				"QuarantineReason".to_string(),
+				json!({
+					"type": "object",
+					"properties": {
+						"trigger": { "type": "string", "enum": ["unknown_caller", "flagged_code", "resource_use"] },
+						"subject": { "type": ["string", "null"], "description": "`unknown_caller`: who called; null when anonymous." },
+						"rules": { "type": "array", "items": { "type": "string" }, "description": "`flagged_code`: the flagging rules." },
+						"measure": { "type": "string", "enum": ["wall_ms", "cpu_ms", "peak_rss_mb", "output_bytes"] },
+						"used": { "type": "integer" },
+						"limit": { "type": "integer" },
+					},
+					"required": ["trigger"],
+				}),
+			);
+			map.insert(
+				"QuarantineEntry".to_string(),
+				json!({
+					"type": "object",
+					"properties": {
+						"id": { "type": "string" },
+						"tool_name": { "type": "string" },
+						"subject": { "type": ["string", "null"] },
+						"tenant": { "type": ["string", "null"] },
+						"transport": { "type": "string" },
+						"reasons": { "type": "array", "items": schema_ref("QuarantineReason") },
+						"status": { "type": "string", "description": "The execution's status." },
+						"artifacts": { "type": "array", "items": schema_ref("Artifact") },
+						"state": { "type": "string", "enum": ["pending", "approved", "rejected"] },
+						"created_at_ms": { "type": "integer" },
+						"decided_at_ms": { "type": "integer" },
+						"decided_by": { "type": "string" },
+					},
+					"required": ["id", "tool_name", "reasons", "status", "artifacts", "state", "created_at_ms"],
+				}),
+			);
+			map.insert(
+				"Redaction".to_string(),
+				json!({
+					"type": "object",
+					"properties": {
+						"stream": { "type": "string", "enum": ["stdout", "stderr"] },
+						"filter": {
+							"type": "string",
+							"description": "`secret`, `credential`, a PII kind (`email`, `phone`, `credit_card`, `ipv4`, `ssn`), `patterns[<index>]`, or `max_line_length`.",
+						},
+						"count": { "type": "integer", "description": "Matches replaced, or lines cut." },
+					},
+					"required": ["stream", "filter", "count"],
+				}),
+			);
+			map.insert(
+				"PolicyViolation".to_string(),
+				json!({
+					"type": "object",
+					"properties": {
+						"rule": { "type": "string", "description": "The rule's id, or `blocked_import`." },
+						"message": { "type": "string" },
+						"action": { "type": "string", "enum": ["reject", "flag"] },
+						"line": { "type": "integer" },
+						"column": { "type": "integer" },
+						"snippet": { "type": "string", "description": "The first line of the matched code." },
+					},
+					"required": ["rule", "message", "action", "line", "column", "snippet"],
+				}),
+			);
+			map.insert(
+				"ExecutionEnvironment".to_string(),
+				json!({
+					"type": "object",
+					"description": "What ran and under which policy. Variables are named, never valued.",
+					"properties": {
+						"tool": { "type": "string", "description": "The definition that ran, as `name@version`." },
+						"backend": { "type": "string" },
+						"sandbox_version": { "type": "string" },
+						"runtime": {
+							"type": "object",
+							"additionalProperties": { "type": "string" },
+							"description": "By role: `sha256:` digests of the `program`, `module`, `component`, `script`, `kernel` or `rootfs`, a container's `image` and `image_id`, a VM's `vm_class`.",
+						},
+						"timeout_ms": { "type": "integer" },
+						"limits": { "description": "As in a dry-run plan." },
+						"network": {},
+						"seccomp": { "type": "boolean" },
+						"landlock": { "type": "boolean" },
+						"env": { "type": "array", "items": { "type": "string" } },
+						"secrets": { "type": "array", "items": { "type": "string" } },
+					},
+					"required": ["tool", "backend", "sandbox_version", "runtime", "timeout_ms", "limits", "network", "seccomp", "landlock", "env", "secrets"],
+				}),
+			);
+			map.insert(
+				"ResourceUsage".to_string(),
+				json!({
+					"type": "object",
+					"description": "What the execution used. All but `wall_ms` are measured on the processes it spawned, and absent when it spawned none.",
+					"properties": {
+						"wall_ms": { "type": "integer", "description": "Time it held its execution slot, all attempts included." },
+						"user_cpu_ms": { "type": "integer" },
+						"sys_cpu_ms": { "type": "integer" },
+						"peak_rss_bytes": { "type": "integer" },
+						"bytes_written": { "type": "integer", "description": "Written to storage." },
+						"output_bytes": { "type": "integer", "description": "Written to stdout and stderr, whether kept or not." },
+						"cost": { "type": "number", "description": "What it cost at `quotas.rates`, when they price anything." },
+					},
+					"required": ["wall_ms"],
+				}),
+			);
+			map["ToolSummary"]["properties"]["requires_approval"] = json!({
+				"type": "boolean",
+				"description": "The tool runs only as a job an approver has let through.",
+			});
+			map["Job"]["properties"]["approval"] = schema_ref("JobApproval");
+			map.insert(
+				"JobApproval".to_string(),
+				json!({
+					"type": "object",
+					"description": "Set on jobs of tools that require approval.",
+					"properties": {
+						"requested_by": { "type": ["string", "null"] },
+						"expires_at_ms": { "type": "integer", "description": "When the job expires unless it is approved first." },
+						"decided_by": { "type": "string" },
+						"decided_at_ms": { "type": "integer" },
+						"reason": { "type": "string", "description": "Why it was rejected, if the approver said." },
+					},
+					"required": ["expires_at_ms"],
+				}),
+			);
+			map.insert("TenantUsage".to_string(), tenant_usage);
+			map.insert("QueueStatus".to_string(), queue_status);
+			map.insert("NodeInfo".to_string(), node_info);
+			map.insert("ScalingSignal".to_string(), scaling_signal);
+			map.insert("RunningExecution".to_string(), running_execution);
+			map.insert(
+				"DrainStatus".to_string(),
+				json!({
+					"type": "object",
+					"properties": {
+						"draining": { "type": "boolean" },
+						"in_flight": { "type": "integer" },
+						"queued": { "type": "integer" },
+					},
+				}),
+			);
+			map
+		}
+		_ => unreachable!("a JSON object literal"),
+	}
+}
+
+/// The tool's name, or for a versioned tool also `name@<version or range>`.
+fn tool_name_schema(tool: &ToolManifest) -> Value {
+	match tool.version {
+		Some(_) => json!({ "type": "string", "pattern": format!("^{}(@.+)?$", tool.name) }),
+		None => json!({ "const": tool.name }),
+	}
+}
+
+/// A response plus `elapsed_ms` and `extra` fields, as batch and pipeline
+/// items are.
+fn timed(extra: &[(&str, Value)]) -> Value {
+	let mut properties = json!({ "elapsed_ms": { "type": "integer" } });
+	for (name, schema) in extra {
+		properties[*name] = schema.clone();
+	}
+	json!({ "allOf": [schema_ref("ToolExecutionResponse"), { "type": "object", "properties": properties }] })
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeSet;
+
+	use regex::Regex;
+
+	use super::*;
+
+	/// (path, method) for every route `main` mounts, in OpenAPI path syntax.
+	fn routes() -> BTreeSet<(String, String)> {
+		let main = include_str!("main.rs");
+		let route = Regex::new(r#"\.route\(\s*"([^"]+)""#).unwrap();
+		let method = Regex::new(r"\b(get|post|put|patch|delete)\(").unwrap();
+		let param = Regex::new(r"[:*](\w+)").unwrap();
+		let starts: Vec<_> = route.captures_iter(main).collect();
+		let mut routes = BTreeSet::new();
+		for (i, captures) in starts.iter().enumerate() {
+			let from = captures.get(0).unwrap().end();
+			let to = starts.get(i + 1).map_or(main.len(), |next| next.get(0).unwrap().start());
+			let args = &main[from..to];
+			let args = &args[..args.find(";").unwrap_or(args.len())];
+			let path = param.replace_all(&captures[1], "{$1}").into_owned();
+			for m in method.captures_iter(args) {
+				routes.insert((path.clone(), m[1].to_string()));
+			}
+		}
+		routes
+	}
+
+	#[test]
+	fn paths_describe_every_route() {
+		let mut described = BTreeSet::new();
+		for (path, methods) in paths().as_object().unwrap() {
+			// Besides the operations, a path item may hold shared `parameters`.
+			for method in methods.as_object().unwrap().keys().filter(|key| *key != "parameters") {
+				described.insert((path.clone(), method.clone()));
+			}
+		}
+		let routes = routes();
+		let undocumented: Vec<_> = routes.difference(&described).collect();
+		let unrouted: Vec<_> = described.difference(&routes).collect();
+		assert!(undocumented.is_empty(), "routes missing from the document: {undocumented:?}");
+		assert!(unrouted.is_empty(), "described but not routed: {unrouted:?}");
+	}
+
+	#[test]
+	fn component_names_are_sanitized() {
+		assert_eq!(component_name("git/clone@2"), "git_clone_2");
+		assert_eq!(component_name("a.b-c_d"), "a.b-c_d");
+	}
+}
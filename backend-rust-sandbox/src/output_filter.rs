@@ -0,0 +1,527 @@
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::encoding::{Encoding, OutputEncoding};
+use crate::redact::{REDACTED, SECRET_PATTERNS};
+use crate::registry::is_valid_tool_name;
+use crate::subprocess::{OutputChunk, StreamKind};
+use crate::tool_executor::{ExecutionContext, ToolResult};
+
+/// Secret values shorter than this are left alone, or every `1` in the
+/// output of a tool given the secret `1` would go.
+const MIN_SECRET_CHARS: usize = 4;
+/// How long a partial line of live output waits for the rest of it before
+/// it is sent as it is.
+const HOLD_PARTIAL_LINE: Duration = Duration::from_millis(100);
+/// Bytes of a line of live output held at most while it is incomplete.
+const MAX_HELD_BYTES: usize = 64 * 1024;
+/// How long the end of live output may take to go through once the tool is
+/// done.
+const TAP_DRAIN: Duration = Duration::from_secs(1);
+/// Put where a line was cut at `max_line_length`.
+const LINE_CUT: &str = "…";
+
+/// Personal data recognized by its shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiKind {
+	Email,
+	/// North American and `+`-prefixed international numbers, with
+	/// separators.
+	Phone,
+	/// Card numbers that pass the Luhn check.
+	CreditCard,
+	Ipv4,
+	/// US social security numbers, as `123-45-6789`.
+	Ssn,
+}
+
+impl PiiKind {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::Email => "email",
+			Self::Phone => "phone",
+			Self::CreditCard => "credit_card",
+			Self::Ipv4 => "ipv4",
+			Self::Ssn => "ssn",
+		}
+	}
+
+	fn pattern(self) -> &'static str {
+		match self {
+			Self::Email => r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b",
+			Self::Phone => r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{3}\)|\b\d{3})[ .-]\d{3}[ .-]\d{4}\b",
+			Self::CreditCard => r"\b\d(?:[ -]?\d){12,18}\b",
+			Self::Ipv4 => r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b",
+			Self::Ssn => r"\b\d{3}-\d{2}-\d{4}\b",
+		}
+	}
+}
+
+impl FromStr for PiiKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"email" => Ok(Self::Email),
+			"phone" => Ok(Self::Phone),
+			"credit_card" => Ok(Self::CreditCard),
+			"ipv4" => Ok(Self::Ipv4),
+			"ssn" => Ok(Self::Ssn),
+			_ => Err(format!("expected email, phone, credit_card, ipv4 or ssn (got {s:?})")),
+		}
+	}
+}
+
+/// What is done to a tool's stdout and stderr before anyone sees them, in
+/// this order. Nothing by default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputFilterConfig {
+	/// Replace the values of the secrets the execution was given, and text
+	/// shaped like credentials (the patterns `logging.redact_secrets` uses).
+	pub secrets: bool,
+	pub pii: Vec<PiiKind>,
+	/// Regular expressions whose matches are replaced.
+	pub patterns: Vec<String>,
+	/// Characters kept of each line; the rest is cut.
+	pub max_line_length: Option<usize>,
+}
+
+impl OutputFilterConfig {
+	fn is_empty(&self) -> bool {
+		!self.secrets && self.pii.is_empty() && self.patterns.is_empty() && self.max_line_length.is_none()
+	}
+}
+
+/// The filters for tools without their own, and tools' own by name. A
+/// tool's own replace the default ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputFiltersConfig {
+	pub default: OutputFilterConfig,
+	pub tools: BTreeMap<String, OutputFilterConfig>,
+}
+
+impl OutputFiltersConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		let named = std::iter::once(("default".to_string(), &self.default))
+			.chain(self.tools.iter().map(|(name, config)| (format!("tools.{name}"), config)));
+		for (at, config) in named {
+			for pattern in &config.patterns {
+				if let Err(e) = Regex::new(pattern) {
+					errors.push(format!("executor.output_filters.{at}.patterns: {pattern:?}: {e}"));
+				}
+			}
+			if config.max_line_length == Some(0) {
+				errors.push(format!("executor.output_filters.{at}.max_line_length must be positive"));
+			}
+		}
+		for name in self.tools.keys() {
+			if !is_valid_tool_name(name) {
+				errors.push(format!("executor.output_filters.tools: {name:?} is not a tool name"));
+			}
+		}
+		errors
+	}
+}
+
+/// How much one filter took out of one stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redaction {
+	pub stream: StreamKind,
+	/// `secret` for a secret's value, `credential` for text shaped like
+	/// one, a PII kind, `patterns[<index>]`, or `max_line_length`.
+	pub filter: String,
+	/// Matches replaced, or lines cut.
+	pub count: usize,
+}
+
+struct Rule {
+	name: String,
+	regex: Regex,
+	/// `$1`-style references to the match's groups are expanded.
+	replacement: String,
+	/// Matches it turns down are kept.
+	check: Option<fn(&str) -> bool>,
+}
+
+/// One compiled filter chain.
+pub struct OutputFilter {
+	secrets: bool,
+	rules: Vec<Rule>,
+	max_line_length: Option<usize>,
+}
+
+impl OutputFilter {
+	fn new(config: &OutputFilterConfig) -> Result<Self, String> {
+		let mut rules = Vec::new();
+		if config.secrets {
+			for (pattern, replacement) in SECRET_PATTERNS {
+				rules.push(Rule {
+					name: "credential".to_string(),
+					regex: Regex::new(pattern).expect("built-in secret patterns compile"),
+					replacement: replacement.to_string(),
+					check: None,
+				});
+			}
+		}
+		for kind in &config.pii {
+			rules.push(Rule {
+				name: kind.as_str().to_string(),
+				regex: Regex::new(kind.pattern()).expect("built-in PII patterns compile"),
+				replacement: REDACTED.to_string(),
+				check: (*kind == PiiKind::CreditCard).then_some(passes_luhn as fn(&str) -> bool),
+			});
+		}
+		for (i, pattern) in config.patterns.iter().enumerate() {
+			rules.push(Rule {
+				name: format!("patterns[{i}]"),
+				regex: Regex::new(pattern).map_err(|e| format!("output filter pattern {pattern:?}: {e}"))?,
+				replacement: REDACTED.to_string(),
+				check: None,
+			});
+		}
+		Ok(Self {
+			secrets: config.secrets,
+			rules,
+			max_line_length: config.max_line_length,
+		})
+	}
+
+	/// `text` with every filter applied, counting what each took out.
+	fn apply(&self, text: &str, secrets: &[String], counts: &mut BTreeMap<String, usize>) -> String {
+		let mut text = text.to_string();
+		if self.secrets {
+			for secret in secrets.iter().filter(|s| s.chars().count() >= MIN_SECRET_CHARS) {
+				let found = text.matches(secret.as_str()).count();
+				if found > 0 {
+					text = text.replace(secret.as_str(), REDACTED);
+					*counts.entry("secret".to_string()).or_default() += found;
+				}
+			}
+		}
+		for rule in &self.rules {
+			let mut found = 0;
+			let replaced = rule.regex.replace_all(&text, |caps: &Captures<'_>| {
+				if rule.check.is_some_and(|check| !check(&caps[0])) {
+					return caps[0].to_string();
+				}
+				found += 1;
+				let mut replacement = String::new();
+				caps.expand(&rule.replacement, &mut replacement);
+				replacement
+			});
+			if found > 0 {
+				text = replaced.into_owned();
+				*counts.entry(rule.name.clone()).or_default() += found;
+			}
+		}
+		if let Some(max) = self.max_line_length {
+			let mut cut = 0;
+			let lines: String = text
+				.split_inclusive('\n')
+				.map(|line| {
+					let (body, end) = line.strip_suffix('\n').map_or((line, ""), |body| (body, "\n"));
+					if body.chars().count() <= max {
+						return line.to_string();
+					}
+					cut += 1;
+					format!("{}{LINE_CUT}{end}", body.chars().take(max).collect::<String>())
+				})
+				.collect();
+			if cut > 0 {
+				text = lines;
+				*counts.entry("max_line_length".to_string()).or_default() += cut;
+			}
+		}
+		text
+	}
+
+	/// Filter `result`'s text output and report what was taken out.
+	/// Output carried in base64 or as an artifact is left as it is.
+	pub fn filter_result(&self, result: &mut ToolResult, secrets: &[String]) {
+		let encoding = result.encoding;
+		for (stream, text, carried) in [
+			(StreamKind::Stdout, &mut result.stdout, OutputEncoding::stdout(encoding)),
+			(StreamKind::Stderr, &mut result.stderr, OutputEncoding::stderr(encoding)),
+		] {
+			if carried != Encoding::Utf8 {
+				continue;
+			}
+			let mut counts = BTreeMap::new();
+			*text = self.apply(text, secrets, &mut counts);
+			result.redactions.extend(counts.into_iter().map(|(filter, count)| Redaction { stream, filter, count }));
+		}
+	}
+
+	/// `ctx` with its live output filtered on the way to the caller, a line
+	/// at a time so that matches are seen whole; `None` without live output.
+	/// A partial line is sent as it is once nothing more arrives for a
+	/// moment, so that prompts still show.
+	pub fn tap(self: &Arc<Self>, ctx: &ExecutionContext, secrets: &[String]) -> Option<FilterTap> {
+		let downstream = ctx.output.clone()?;
+		let (tx, mut rx) = mpsc::channel::<OutputChunk>(16);
+		let (filter, secrets) = (self.clone(), secrets.to_vec());
+		let forwarder = tokio::spawn(async move {
+			let mut held: [(StreamKind, Vec<u8>); 2] = [(StreamKind::Stdout, Vec::new()), (StreamKind::Stderr, Vec::new())];
+			loop {
+				let next = tokio::time::timeout(HOLD_PARTIAL_LINE, rx.recv()).await;
+				let closed = matches!(next, Ok(None));
+				let ready: Vec<(StreamKind, Vec<u8>)> = match next {
+					Ok(Some(chunk)) => {
+						let (stream, bytes) = &mut held[(chunk.stream == StreamKind::Stderr) as usize];
+						bytes.extend_from_slice(&chunk.data);
+						vec![(*stream, take_ready(bytes, false))]
+					}
+					Err(_) => held.iter_mut().map(|(stream, bytes)| (*stream, take_ready(bytes, true))).collect(),
+					Ok(None) => held.iter_mut().map(|(stream, bytes)| (*stream, std::mem::take(bytes))).collect(),
+				};
+				for (stream, data) in ready.into_iter().filter(|(_, data)| !data.is_empty()) {
+					let data = match String::from_utf8(data) {
+						Ok(text) => filter.apply(&text, &secrets, &mut BTreeMap::new()).into_bytes(),
+						Err(e) => e.into_bytes(),
+					};
+					if downstream.send(OutputChunk { stream, data }).await.is_err() {
+						return;
+					}
+				}
+				if closed {
+					return;
+				}
+			}
+		});
+		Some(FilterTap {
+			ctx: ctx.clone().with_output(tx),
+			forwarder,
+		})
+	}
+}
+
+/// An execution context whose live output goes through an output filter.
+pub struct FilterTap {
+	pub ctx: ExecutionContext,
+	forwarder: JoinHandle<()>,
+}
+
+impl FilterTap {
+	/// Wait for the output read so far to go through.
+	pub async fn close(self) {
+		drop(self.ctx);
+		let _ = tokio::time::timeout(TAP_DRAIN, self.forwarder).await;
+	}
+}
+
+/// The filter chains, compiled once; tools with nothing to filter have
+/// none.
+pub struct OutputFilters {
+	default: Option<Arc<OutputFilter>>,
+	tools: BTreeMap<String, Option<Arc<OutputFilter>>>,
+}
+
+impl OutputFilters {
+	pub fn new(config: &OutputFiltersConfig) -> Result<Self, String> {
+		let compile = |config: &OutputFilterConfig| -> Result<Option<Arc<OutputFilter>>, String> {
+			if config.is_empty() {
+				return Ok(None);
+			}
+			OutputFilter::new(config).map(|filter| Some(Arc::new(filter)))
+		};
+		Ok(Self {
+			default: compile(&config.default)?,
+			tools: config
+				.tools
+				.iter()
+				.map(|(name, config)| Ok((name.clone(), compile(config)?)))
+				.collect::<Result<_, String>>()?,
+		})
+	}
+
+	/// The chain for the tool `name`, if it has anything to filter.
+	pub fn for_tool(&self, name: &str) -> Option<&Arc<OutputFilter>> {
+		match self.tools.get(name) {
+			Some(own) => own.as_ref(),
+			None => self.default.as_ref(),
+		}
+	}
+}
+
+/// Take from `held` the live output that can be filtered now: its whole
+/// lines, or with `flush` everything. Partial lines stop short of a
+/// character cut off at the end, which waits for the rest of it; cut in
+/// two, it would be no text and go out unfiltered.
+fn take_ready(held: &mut Vec<u8>, flush: bool) -> Vec<u8> {
+	let end = match held.iter().rposition(|&b| b == b'\n') {
+		_ if flush => whole_chars(held),
+		Some(newline) => newline + 1,
+		None if held.len() >= MAX_HELD_BYTES => whole_chars(held),
+		None => 0,
+	};
+	let rest = held.split_off(end);
+	std::mem::replace(held, rest)
+}
+
+/// The length of `bytes` less a UTF-8 character cut off at the end.
+fn whole_chars(bytes: &[u8]) -> usize {
+	match std::str::from_utf8(bytes) {
+		Err(e) if e.error_len().is_none() => e.valid_up_to(),
+		_ => bytes.len(),
+	}
+}
+
+fn passes_luhn(number: &str) -> bool {
+	let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+	let sum: u32 = digits
+		.iter()
+		.rev()
+		.enumerate()
+		.map(|(i, &d)| match i % 2 {
+			0 => d,
+			_ if d * 2 > 9 => d * 2 - 9,
+			_ => d * 2,
+		})
+		.sum();
+	sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn filter(config: OutputFilterConfig) -> OutputFilter {
+		OutputFilter::new(&config).unwrap()
+	}
+
+	/// `text` filtered, with what each filter took out.
+	fn apply(filter: &OutputFilter, text: &str, secrets: &[&str]) -> (String, Vec<(String, usize)>) {
+		let secrets: Vec<String> = secrets.iter().map(|s| s.to_string()).collect();
+		let mut counts = BTreeMap::new();
+		let text = filter.apply(text, &secrets, &mut counts);
+		(text, counts.into_iter().collect())
+	}
+
+	fn counts(counts: &[(&str, usize)]) -> Vec<(String, usize)> {
+		counts.iter().map(|(name, n)| (name.to_string(), *n)).collect()
+	}
+
+	#[test]
+	fn overlapping_matches() {
+		let filter = filter(OutputFilterConfig {
+			secrets: true,
+			pii: vec![PiiKind::Email, PiiKind::Ipv4],
+			patterns: vec!["abc".to_string(), "bcd".to_string()],
+			..Default::default()
+		});
+		// Secret values go first, then each filter sees what the ones
+		// before it left.
+		let (text, found) = apply(&filter, "key=s3cr3t-value; again s3cr3t-value", &["s3cr3t-value", "cr3t"]);
+		assert_eq!(text, "key=<redacted>; again <redacted>");
+		assert_eq!(found, counts(&[("secret", 2)]));
+		let (text, found) = apply(&filter, "xabcdx", &[]);
+		assert_eq!(text, "x<redacted>dx");
+		assert_eq!(found, counts(&[("patterns[0]", 1)]));
+		let (text, found) = apply(&filter, "ops@10.0.0.1.example.com from 192.168.1.1", &[]);
+		assert_eq!(text, "<redacted> from <redacted>");
+		assert_eq!(found, counts(&[("email", 1), ("ipv4", 1)]));
+		let (text, found) = apply(&filter, "Authorization: Bearer abcdefghijklmnop", &[]);
+		assert_eq!(text, "Authorization: Bearer <redacted>");
+		assert_eq!(found, counts(&[("credential", 1)]));
+	}
+
+	#[test]
+	fn card_numbers_must_pass_luhn() {
+		let filter = filter(OutputFilterConfig {
+			pii: vec![PiiKind::CreditCard, PiiKind::Ssn, PiiKind::Phone],
+			..Default::default()
+		});
+		let (text, found) = apply(&filter, "card 4111 1111 1111 1111, order 4111 1111 1111 1112", &[]);
+		assert_eq!(text, "card <redacted>, order 4111 1111 1111 1112");
+		assert_eq!(found, counts(&[("credit_card", 1)]));
+		let (text, _) = apply(&filter, "ssn 123-45-6789, call (555) 123-4567", &[]);
+		assert_eq!(text, "ssn <redacted>, call <redacted>");
+	}
+
+	#[test]
+	fn multibyte_text() {
+		let cut = filter(OutputFilterConfig {
+			max_line_length: Some(3),
+			..Default::default()
+		});
+		let (text, found) = apply(&cut, "ééééé\nab\n日本語です\n", &[]);
+		assert_eq!(text, "ééé…\nab\n日本語…\n");
+		assert_eq!(found, counts(&[("max_line_length", 2)]));
+		let scrub = filter(OutputFilterConfig {
+			secrets: true,
+			patterns: vec!["пароль=\\S+".to_string()],
+			..Default::default()
+		});
+		let (text, found) = apply(&scrub, "ключ «ñ-sécret» и пароль=42 ✓", &["ñ-sécret"]);
+		assert_eq!(text, "ключ «<redacted>» и <redacted> ✓");
+		assert_eq!(found, counts(&[("patterns[0]", 1), ("secret", 1)]));
+	}
+
+	#[test]
+	fn live_output_is_not_cut_inside_a_character() {
+		// `é` is two bytes; the first arrived without the second.
+		let mut held = "line one\nsecond é".as_bytes().to_vec();
+		held.pop();
+		assert_eq!(take_ready(&mut held, false), b"line one\n");
+		assert_eq!(take_ready(&mut held, true), b"second ");
+		assert_eq!(held, [0xc3]);
+		held.push(0xa9);
+		assert_eq!(String::from_utf8(take_ready(&mut held, true)).unwrap(), "é");
+		assert!(held.is_empty());
+		// A long line without a newline goes out in whole characters.
+		let mut held = "€".repeat(MAX_HELD_BYTES / 3 + 1).into_bytes();
+		held.truncate(MAX_HELD_BYTES + 1);
+		let ready = take_ready(&mut held, false);
+		assert!(String::from_utf8(ready).is_ok());
+		assert_eq!(held.len(), (MAX_HELD_BYTES + 1) % 3);
+		// Bytes that are no text anyway are not held back.
+		let mut held = vec![0xff, 0xfe, b'x'];
+		assert_eq!(take_ready(&mut held, true), [0xff, 0xfe, b'x']);
+	}
+
+	#[test]
+	fn clean_output_is_left_as_is() {
+		let filter = filter(OutputFilterConfig {
+			secrets: true,
+			pii: vec![PiiKind::Email, PiiKind::Phone, PiiKind::CreditCard, PiiKind::Ipv4, PiiKind::Ssn],
+			patterns: vec!["forbidden".to_string()],
+			max_line_length: Some(80),
+		});
+		for text in ["", "\n", "all good\n", "version 1.2.3, 42 files, ünïcode ✓\n", "tiny secret: 123"] {
+			assert_eq!(apply(&filter, text, &["123"]), (text.to_string(), Vec::new()));
+		}
+		let mut result = ToolResult {
+			stdout: "ok\n".to_string(),
+			stderr: String::new(),
+			..Default::default()
+		};
+		filter.filter_result(&mut result, &[]);
+		assert_eq!(result.stdout, "ok\n");
+		assert!(result.redactions.is_empty());
+	}
+
+	#[test]
+	fn tools_replace_the_default_filters() {
+		let config = OutputFiltersConfig {
+			default: OutputFilterConfig {
+				secrets: true,
+				..Default::default()
+			},
+			tools: BTreeMap::from([("quiet".to_string(), OutputFilterConfig::default())]),
+		};
+		let filters = OutputFilters::new(&config).unwrap();
+		assert!(filters.for_tool("python").is_some());
+		assert!(filters.for_tool("quiet").is_none());
+		assert!(OutputFilters::new(&OutputFiltersConfig::default()).unwrap().for_tool("python").is_none());
+	}
+}
@@ -0,0 +1,517 @@
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::tool::{execute_tool_request, ToolExecutionRequest, ToolExecutionResponse};
+use crate::tool_executor::{ExecutionContext, Executor};
+
+#[derive(Debug, Deserialize)]
+pub struct PipelineRequest {
+	pub steps: Vec<PipelineStep>,
+}
+
+/// One tool invocation in a pipeline. String values in `args` may refer to
+/// earlier steps' responses with `{{steps.<id>.<path>}}`, e.g.
+/// `{{steps.search.result.stdout.items[0].url}}`; a referenced step is an
+/// implicit dependency. `\{{` is a literal `{{`.
+#[derive(Debug, Deserialize)]
+pub struct PipelineStep {
+	pub id: String,
+	/// Steps that must finish `ok` before this one starts.
+	#[serde(default)]
+	pub depends_on: Vec<String>,
+	#[serde(flatten)]
+	pub request: ToolExecutionRequest,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StepResult {
+	pub id: String,
+	#[serde(flatten)]
+	pub response: ToolExecutionResponse,
+	/// Offset of the step's start from the start of the pipeline.
+	pub started_ms: u64,
+	pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PipelineResponse {
+	/// `ok` when every step is, `failed` otherwise.
+	pub status: &'static str,
+	/// One per step, in request order.
+	pub steps: Vec<StepResult>,
+	pub succeeded: usize,
+	pub failed: usize,
+	pub skipped: usize,
+	pub elapsed_ms: u64,
+}
+
+/// A `{{...}}` reference into another step's response.
+#[derive(Debug)]
+struct Reference {
+	step: String,
+	path: Vec<Segment>,
+}
+
+#[derive(Debug)]
+enum Segment {
+	Key(String),
+	Index(usize),
+}
+
+/// A piece of a templated string.
+#[derive(Debug, PartialEq)]
+enum Part<'a> {
+	Text(&'a str),
+	/// What is between `{{` and `}}`, trimmed.
+	Expr(&'a str),
+}
+
+/// A pipeline whose shape has been checked: unique ids, known dependencies,
+/// well-formed references and no cycles.
+pub struct Plan {
+	steps: Vec<PipelineStep>,
+	deps: Vec<Vec<usize>>,
+}
+
+impl Plan {
+	/// Every problem with the pipeline's shape, or its steps with their
+	/// dependencies resolved to indices.
+	pub fn new(request: PipelineRequest) -> Result<Self, Vec<String>> {
+		let steps = request.steps;
+		let mut errors = Vec::new();
+		let mut index = HashMap::new();
+		for (i, step) in steps.iter().enumerate() {
+			if step.id.is_empty() {
+				errors.push(format!("steps[{i}]: id must not be empty"));
+			} else if index.insert(step.id.as_str(), i).is_some() {
+				errors.push(format!("steps[{i}]: duplicate id {:?}", step.id));
+			}
+		}
+
+		let mut deps = Vec::with_capacity(steps.len());
+		for step in &steps {
+			let mut names: Vec<String> = step.depends_on.clone();
+			match references(&step.request.args) {
+				Ok(refs) => names.extend(refs.into_iter().map(|r| r.step)),
+				Err(e) => errors.push(format!("step {:?}: {e}", step.id)),
+			}
+			let mut resolved = Vec::new();
+			for name in names {
+				match index.get(name.as_str()) {
+					Some(_) if name == step.id => errors.push(format!("step {:?} depends on itself", step.id)),
+					Some(&i) if !resolved.contains(&i) => resolved.push(i),
+					Some(_) => {}
+					None => errors.push(format!("step {:?} depends on unknown step {name:?}", step.id)),
+				}
+			}
+			deps.push(resolved);
+		}
+		if errors.is_empty() {
+			if let Some(cycle) = find_cycle(&steps, &deps) {
+				errors.push(format!("steps {} depend on each other", cycle.join(", ")));
+			}
+		}
+		if errors.is_empty() {
+			Ok(Self { steps, deps })
+		} else {
+			Err(errors)
+		}
+	}
+}
+
+/// Run `plan`, starting each step as soon as its dependencies have finished.
+///
+/// A step whose dependency did not finish `ok` is `skipped`, and so are its
+/// dependents; steps on other branches carry on. Args are templated, then
+/// the step goes through `admit` (authorization, validation, limits) like a
+/// single request would.
+pub async fn execute_pipeline<F, A>(executor: Arc<Executor>, plan: Plan, admit: F) -> PipelineResponse
+where
+	F: Fn(&ToolExecutionRequest) -> A,
+	A: Future<Output = Result<ExecutionContext, ToolExecutionResponse>>,
+{
+	let started = Instant::now();
+	let Plan { steps, deps } = plan;
+	let mut requests: Vec<Option<PipelineStep>> = steps.into_iter().map(Some).collect();
+	let mut results: Vec<Option<StepResult>> = requests.iter().map(|_| None).collect();
+	// Responses of the steps that ran, as JSON, for templating the steps that
+	// refer to them. Only these can have finished `ok`.
+	let mut outputs: HashMap<String, Value> = HashMap::new();
+	let mut running = FuturesUnordered::new();
+
+	loop {
+		// Settle everything that can be settled without waiting: steps
+		// whose dependencies are all done either start or fail right here.
+		let mut progressed = true;
+		while progressed {
+			progressed = false;
+			for i in 0..requests.len() {
+				if requests[i].is_none() || deps[i].iter().any(|&d| results[d].is_none()) {
+					continue;
+				}
+				let step = requests[i].take().expect("pending steps are present");
+				progressed = true;
+				let offset = started.elapsed().as_millis() as u64;
+				if let Some(&failed) = deps[i].iter().find(|&&d| !is_ok(&results[d])) {
+					let reason = format!("not run: step {:?} did not finish ok", steps_id(&results, failed));
+					results[i] = Some(settled(step.id, step.request.tool_name, "skipped", reason, offset));
+					continue;
+				}
+				let mut req = step.request;
+				req.args = match render(&req.args, &outputs) {
+					Ok(args) => args,
+					Err(e) => {
+						results[i] = Some(settled(step.id, req.tool_name, "template_error", e, offset));
+						continue;
+					}
+				};
+				match admit(&req).await {
+					Ok(ctx) => {
+						let executor = executor.clone();
+						running.push(async move {
+							let run_started = Instant::now();
+							let response = execute_tool_request(executor, req, ctx).await;
+							(i, step.id, response, offset, run_started.elapsed().as_millis() as u64)
+						});
+					}
+					Err(response) => {
+						results[i] = Some(StepResult {
+							id: step.id,
+							response,
+							started_ms: offset,
+							elapsed_ms: 0,
+						});
+					}
+				}
+			}
+		}
+
+		let Some((i, id, response, started_ms, elapsed_ms)) = running.next().await else {
+			break;
+		};
+		outputs.insert(id.clone(), serde_json::to_value(&response).unwrap_or(Value::Null));
+		results[i] = Some(StepResult {
+			id,
+			response,
+			started_ms,
+			elapsed_ms,
+		});
+	}
+
+	let steps: Vec<StepResult> = results
+		.into_iter()
+		.map(|r| r.expect("every step settles once nothing is running"))
+		.collect();
+	let count = |status: &str| steps.iter().filter(|s| s.response.status == status).count();
+	let (succeeded, skipped) = (count("ok"), count("skipped"));
+	PipelineResponse {
+		status: if succeeded == steps.len() { "ok" } else { "failed" },
+		failed: steps.len() - succeeded - skipped,
+		succeeded,
+		skipped,
+		steps,
+		elapsed_ms: started.elapsed().as_millis() as u64,
+	}
+}
+
+fn is_ok(result: &Option<StepResult>) -> bool {
+	result.as_ref().is_some_and(|r| r.response.status == "ok")
+}
+
+fn steps_id(results: &[Option<StepResult>], i: usize) -> &str {
+	results[i].as_ref().map(|r| r.id.as_str()).unwrap_or_default()
+}
+
+/// The result of a step that was settled without running.
+fn settled(id: String, tool_name: String, status: &str, reason: String, offset: u64) -> StepResult {
+	StepResult {
+		id,
+		response: ToolExecutionResponse {
+			status: status.to_string(),
+			tool_name,
+			result: json!({ "error": reason }),
+		},
+		started_ms: offset,
+		elapsed_ms: 0,
+	}
+}
+
+/// Names of dependency cycles' members, if the steps have one.
+fn find_cycle(steps: &[PipelineStep], deps: &[Vec<usize>]) -> Option<Vec<String>> {
+	// Kahn's algorithm: whatever cannot be ordered is on or behind a cycle.
+	let mut remaining: Vec<usize> = deps.iter().map(Vec::len).collect();
+	let mut ordered = HashSet::new();
+	let mut ready: Vec<usize> = (0..steps.len()).filter(|&i| remaining[i] == 0).collect();
+	while let Some(done) = ready.pop() {
+		ordered.insert(done);
+		for (i, step_deps) in deps.iter().enumerate() {
+			if step_deps.contains(&done) {
+				remaining[i] -= 1;
+				if remaining[i] == 0 {
+					ready.push(i);
+				}
+			}
+		}
+	}
+	let stuck: Vec<String> = (0..steps.len())
+		.filter(|i| !ordered.contains(i))
+		.map(|i| format!("{:?}", steps[i].id))
+		.collect();
+	(!stuck.is_empty()).then_some(stuck)
+}
+
+/// Every `{{...}}` reference in the strings of `args`.
+fn references(args: &Value) -> Result<Vec<Reference>, String> {
+	let mut found = Vec::new();
+	let mut pending = vec![args];
+	while let Some(value) = pending.pop() {
+		match value {
+			Value::String(s) => {
+				for part in template_parts(s)? {
+					if let Part::Expr(expr) = part {
+						found.push(parse_reference(expr)?);
+					}
+				}
+			}
+			Value::Array(items) => pending.extend(items),
+			Value::Object(map) => pending.extend(map.values()),
+			_ => {}
+		}
+	}
+	Ok(found)
+}
+
+/// `s` split into its text and its `{{...}}` expressions, with each `\{{`
+/// as the text `{{`.
+fn template_parts(s: &str) -> Result<Vec<Part<'_>>, String> {
+	let mut parts = Vec::new();
+	let mut rest = s;
+	while let Some(start) = rest.find("{{") {
+		if let Some(text) = rest[..start].strip_suffix('\\') {
+			parts.extend([Part::Text(text), Part::Text("{{")]);
+			rest = &rest[start + 2..];
+			continue;
+		}
+		let Some(len) = rest[start + 2..].find("}}") else {
+			return Err(format!("unterminated {{{{ in {s:?}"));
+		};
+		parts.extend([Part::Text(&rest[..start]), Part::Expr(rest[start + 2..start + 2 + len].trim())]);
+		rest = &rest[start + 2 + len + 2..];
+	}
+	parts.push(Part::Text(rest));
+	parts.retain(|part| *part != Part::Text(""));
+	Ok(parts)
+}
+
+/// Parse `steps.<id>.key[0].key` into a reference.
+fn parse_reference(expr: &str) -> Result<Reference, String> {
+	let invalid = || format!("{{{{{expr}}}}} is not of the form steps.<id>.<path>");
+	let rest = expr.strip_prefix("steps.").ok_or_else(invalid)?;
+	let (step, mut rest) = match rest.find(['.', '[']) {
+		Some(end) => (&rest[..end], &rest[end..]),
+		None => (rest, ""),
+	};
+	if step.is_empty() {
+		return Err(invalid());
+	}
+	let mut path = Vec::new();
+	while !rest.is_empty() {
+		if let Some(after) = rest.strip_prefix('.') {
+			let end = after.find(['.', '[']).unwrap_or(after.len());
+			if end == 0 {
+				return Err(invalid());
+			}
+			path.push(Segment::Key(after[..end].to_string()));
+			rest = &after[end..];
+		} else if let Some(after) = rest.strip_prefix('[') {
+			let end = after.find(']').ok_or_else(invalid)?;
+			let index = after[..end].trim().parse().map_err(|_| invalid())?;
+			path.push(Segment::Index(index));
+			rest = &after[end + 1..];
+		} else {
+			return Err(invalid());
+		}
+	}
+	Ok(Reference {
+		step: step.to_string(),
+		path,
+	})
+}
+
+/// `args` with every reference replaced. A string that is nothing but one
+/// reference takes the referenced value as is, keeping its JSON type; in
+/// longer strings references are spliced in as text.
+fn render(args: &Value, outputs: &HashMap<String, Value>) -> Result<Value, String> {
+	Ok(match args {
+		Value::String(s) => {
+			let parts = template_parts(s)?;
+			let exprs: Vec<&str> = parts
+				.iter()
+				.filter_map(|part| match part {
+					Part::Expr(expr) => Some(*expr),
+					Part::Text(_) => None,
+				})
+				.collect();
+			let padded = parts.iter().all(|part| match part {
+				Part::Text(text) => text.trim().is_empty(),
+				Part::Expr(_) => true,
+			});
+			if let ([expr], true) = (&exprs[..], padded) {
+				return lookup(expr, outputs).cloned();
+			}
+			let mut rendered = String::with_capacity(s.len());
+			for part in parts {
+				match part {
+					Part::Text(text) => rendered.push_str(text),
+					Part::Expr(expr) => match lookup(expr, outputs)? {
+						Value::String(text) => rendered.push_str(text),
+						other => rendered.push_str(&other.to_string()),
+					},
+				}
+			}
+			Value::String(rendered)
+		}
+		Value::Array(items) => Value::Array(items.iter().map(|v| render(v, outputs)).collect::<Result<_, _>>()?),
+		Value::Object(map) => Value::Object(
+			map.iter()
+				.map(|(k, v)| Ok((k.clone(), render(v, outputs)?)))
+				.collect::<Result<_, String>>()?,
+		),
+		other => other.clone(),
+	})
+}
+
+fn lookup<'a>(expr: &str, outputs: &'a HashMap<String, Value>) -> Result<&'a Value, String> {
+	let reference = parse_reference(expr)?;
+	let mut value = outputs
+		.get(&reference.step)
+		.ok_or_else(|| format!("{{{{{expr}}}}}: step {:?} has no output", reference.step))?;
+	for segment in &reference.path {
+		let next = match segment {
+			Segment::Key(key) => value.get(key),
+			Segment::Index(i) => value.get(i),
+		};
+		value = next.ok_or_else(|| format!("{{{{{expr}}}}} does not exist in the output of step {:?}", reference.step))?;
+	}
+	Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn outputs() -> HashMap<String, Value> {
+		HashMap::from([
+			(
+				"search".to_string(),
+				json!({ "status": "ok", "result": { "stdout": { "items": [{ "url": "https://a.example", "rank": 1 }] } } }),
+			),
+			("count".to_string(), json!({ "status": "ok", "result": { "words": 42, "text": "héllo wörld" } })),
+		])
+	}
+
+	fn rendered(args: Value) -> Result<Value, String> {
+		render(&args, &outputs())
+	}
+
+	fn step(id: &str, args: Value) -> PipelineStep {
+		PipelineStep {
+			id: id.to_string(),
+			depends_on: Vec::new(),
+			request: serde_json::from_value(json!({ "tool_name": "wordcount", "args": args })).unwrap(),
+		}
+	}
+
+	#[test]
+	fn references_to_earlier_steps_are_filled_in() {
+		let args = json!({
+			"url": "{{steps.search.result.stdout.items[0].url}}",
+			"words": "{{ steps.count.result.words }}",
+			"rank": "  {{steps.search.result.stdout.items[ 0 ].rank}}  ",
+			"item": "{{steps.search.result.stdout.items[0]}}",
+			"line": "fetch {{steps.search.result.stdout.items[0].url}} ({{steps.count.result.words}} words)",
+			"text": "«{{steps.count.result.text}}»",
+			"nested": [{ "status": "{{steps.count.status}}" }, 7, null],
+		});
+		assert_eq!(
+			rendered(args).unwrap(),
+			json!({
+				// A whole reference keeps the JSON type of what it refers to.
+				"url": "https://a.example",
+				"words": 42,
+				"rank": 1,
+				"item": { "url": "https://a.example", "rank": 1 },
+				"line": "fetch https://a.example (42 words)",
+				"text": "«héllo wörld»",
+				"nested": [{ "status": "ok" }, 7, null],
+			})
+		);
+	}
+
+	#[test]
+	fn referenced_steps_are_dependencies() {
+		let plan = Plan::new(PipelineRequest {
+			steps: vec![
+				step("search", json!({})),
+				step("count", json!({ "text": "{{steps.search.result.stdout}} and {{steps.search.status}}" })),
+				step("report", json!({ "n": "{{steps.count.result.words}}", "url": "\\{{steps.nowhere.x}}" })),
+			],
+		})
+		.unwrap();
+		assert_eq!(plan.deps, vec![vec![], vec![0], vec![1]]);
+	}
+
+	#[test]
+	fn missing_references_are_errors() {
+		let missing = [
+			("{{steps.nowhere.result}}", "has no output"),
+			("{{steps.count.result.missing}}", "does not exist"),
+			("{{steps.search.result.stdout.items[1]}}", "does not exist"),
+			("{{steps.count.result.words.deeper}}", "does not exist"),
+			("{{steps.count.result[0]}}", "does not exist"),
+		];
+		for (template, error) in missing {
+			let got = rendered(json!({ "x": format!("in {template} text") })).unwrap_err();
+			assert!(got.contains(error), "{template}: {got}");
+		}
+		let errors = Plan::new(PipelineRequest {
+			steps: vec![step("a", json!({ "x": "{{steps.b.result}}" }))],
+		})
+		.err()
+		.unwrap();
+		assert_eq!(errors, vec!["step \"a\" depends on unknown step \"b\"".to_string()]);
+	}
+
+	#[test]
+	fn malformed_references_are_errors() {
+		for template in ["{{steps.count.result", "{{result.words}}", "{{steps.}}", "{{steps.count..x}}", "{{steps.count[x]}}"] {
+			assert!(references(&json!(template)).is_err(), "{template}");
+			assert!(rendered(json!(template)).is_err(), "{template}");
+		}
+	}
+
+	#[test]
+	fn escaped_braces_are_text() {
+		let args = json!({
+			"code": "print(f\"\\{{x}}\")",
+			"jinja": "\\{{ name }} is {{steps.count.result.words}}",
+			"closing": "}} and } are text",
+			"plain": "no braces, ünïcode",
+		});
+		assert_eq!(references(&args).unwrap().len(), 1);
+		assert_eq!(
+			rendered(args).unwrap(),
+			json!({
+				"code": "print(f\"{{x}}\")",
+				"jinja": "{{ name }} is 42",
+				"closing": "}} and } are text",
+				"plain": "no braces, ünïcode",
+			})
+		);
+	}
+}
@@ -0,0 +1,76 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::audit::Caller;
+use crate::code_scan::PolicyViolation;
+use crate::retry::{RetryOverride, RetryPolicy};
+use crate::scheduler::Priority;
+use crate::tool_executor::ExecutionBackend;
+use crate::workspace::ToolInput;
+
+/// What a request asks of an execution besides the tool and its args, as
+/// far as it changes the plan.
+pub struct PlanOptions<'a> {
+	/// Whose cache entries a cached result would come from.
+	pub caller: &'a Caller,
+	pub timeout_ms: Option<u64>,
+	pub retry: Option<&'a RetryOverride>,
+	pub artifacts: &'a [String],
+	pub input: &'a ToolInput,
+	pub retain_workspace: bool,
+	pub priority: Option<Priority>,
+}
+
+/// What a request would run, and under which sandbox policy, without
+/// running it: the answer to a `dry_run`. Secret values and host paths are
+/// never part of it.
+#[derive(Debug, Serialize)]
+pub struct ExecutionPlan {
+	/// The definition the request resolves to, pinned as `name@version`.
+	pub tool: String,
+	pub backend: ExecutionBackend,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub deprecated: Option<String>,
+	pub timeout_ms: u64,
+	pub priority: Priority,
+	/// Limits after the manifest and server defaults, as the backend applies
+	/// them: cgroup limits for subprocesses (`null` when they run
+	/// unconfined), memory and fuel for WASM, the `docker run` flags for
+	/// containers.
+	pub limits: Value,
+	/// `none`, `full` or `{allowlist}`, or a container's `--network`;
+	/// `null` for built-ins, which have their own policies.
+	pub network: Value,
+	/// Whether a seccomp filter is installed.
+	pub seccomp: bool,
+	/// Whether Landlock keeps the tool's file access to its workspace and
+	/// the readable paths.
+	pub landlock: bool,
+	/// Host variables the tool would inherit, by name.
+	pub env: Vec<String>,
+	/// Variables set from secrets, with the secret each is read from.
+	pub secrets: BTreeMap<String, String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub retry: Option<RetryPolicy>,
+	/// How long an `ok` result would be cached; unset if it would not be.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub cache_ttl_secs: Option<u64>,
+	/// Globs collected as artifacts, the manifest's and the request's.
+	pub artifacts: Vec<String>,
+	/// Files written into the workspace first.
+	pub input_files: Vec<String>,
+	pub stdin_bytes: usize,
+	pub retain_workspace: bool,
+	/// What the code scan finds in the submitted code; any finding that
+	/// rejects means it would not run.
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub policy_violations: Vec<PolicyViolation>,
+	/// The tool runs only as a job an approver has let through.
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub requires_approval: bool,
+	/// The most one attempt could cost at `quotas.rates`; unset when they
+	/// price nothing.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub estimated_cost: Option<f64>,
+}
@@ -0,0 +1,528 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::os::fd::FromRawFd;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::oneshot;
+use tracing::{error, info, warn};
+
+use crate::registry::is_valid_tool_name;
+use crate::signing::ToolVerifier;
+use crate::tool_executor::{ExecutionContext, ToolResult};
+use crate::tool_sdk::Tool;
+
+/// The plugin ABI versions this build speaks, oldest first. See
+/// `plugins/pagi_plugin.h`.
+const ABI_VERSIONS: std::ops::RangeInclusive<u32> = 1..=1;
+const INIT_SYMBOL: &CStr = c"pagi_plugin_init";
+const LIBRARY_EXTENSIONS: &[&str] = &["so", "dylib"];
+/// Started with this argument and a library's path, the sandbox binary runs
+/// as that library's host.
+const HOST_ARG: &str = "--plugin-host";
+/// How long a host may take to load its library and describe it.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The tools of every plugin loaded at startup.
+static LOADED: OnceLock<Vec<Arc<dyn Tool>>> = OnceLock::new();
+
+/// `pagi_tool_v1`.
+#[repr(C)]
+struct ToolV1 {
+	name: *const c_char,
+	description: *const c_char,
+	args_schema_json: *const c_char,
+	run: Option<unsafe extern "C" fn(args_json: *const c_char, timeout_ms: u64) -> *mut c_char>,
+}
+
+/// `pagi_plugin_v1`.
+#[repr(C)]
+struct PluginV1 {
+	abi_version: u32,
+	name: *const c_char,
+	version: *const c_char,
+	tool_count: usize,
+	tools: *const ToolV1,
+	free_result: Option<unsafe extern "C" fn(result: *mut c_char)>,
+}
+
+type InitFn = unsafe extern "C" fn(min_abi: u32, max_abi: u32) -> *const PluginV1;
+
+/// A host's first line: what its library declares, or why it could not be
+/// loaded.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Hello {
+	Loaded(Described),
+	Failed { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Described {
+	name: String,
+	version: String,
+	abi_version: u32,
+	tools: Vec<ToolSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ToolSpec {
+	name: String,
+	description: String,
+	args_schema_json: Option<String>,
+}
+
+/// A call, sandbox to host.
+#[derive(Serialize, Deserialize)]
+struct CallRequest {
+	id: u64,
+	tool: usize,
+	args: String,
+	timeout_ms: u64,
+}
+
+/// Its answer, host to sandbox: what `run` returned, `None` for NULL.
+#[derive(Serialize, Deserialize)]
+struct CallReply {
+	id: u64,
+	result: Option<String>,
+}
+
+/// What a plugin's `run` returns, as JSON.
+#[derive(Deserialize)]
+struct PluginResult {
+	status: String,
+	#[serde(default)]
+	stdout: String,
+	#[serde(default)]
+	stderr: String,
+	#[serde(default)]
+	exit_code: Option<i32>,
+}
+
+/// A plugin library. It runs in a host process of its own, so a panic,
+/// abort or segfault inside it ends that process and nothing else; the
+/// next call starts a new one.
+struct Plugin {
+	path: PathBuf,
+	/// What the library declared at startup. A new host must declare the
+	/// same, or the tools built from it would no longer match.
+	described: Described,
+	/// SHA-256 of the library as its signature was checked at startup; a
+	/// restarted host may only load that.
+	digest: [u8; 32],
+	host: tokio::sync::Mutex<Option<Arc<Host>>>,
+	next_id: AtomicU64,
+}
+
+/// A running host process.
+struct Host {
+	stdin: tokio::sync::Mutex<ChildStdin>,
+	/// Calls sent and not yet answered. Dropped when the host exits, which
+	/// fails them.
+	pending: Arc<Mutex<HashMap<u64, oneshot::Sender<Option<String>>>>>,
+	alive: Arc<AtomicBool>,
+}
+
+struct PluginTool {
+	plugin: Arc<Plugin>,
+	index: usize,
+	name: &'static str,
+	description: &'static str,
+	schema: Value,
+}
+
+#[tonic::async_trait]
+impl Tool for PluginTool {
+	fn name(&self) -> &'static str {
+		self.name
+	}
+
+	fn description(&self) -> &'static str {
+		self.description
+	}
+
+	fn schema(&self) -> Value {
+		self.schema.clone()
+	}
+
+	/// If the execution times out or is cancelled first, the host still
+	/// finishes the call and its answer is dropped.
+	async fn run(&self, ctx: &ExecutionContext, args: Value) -> ToolResult {
+		self.plugin
+			.call(self.index, self.name, &args, ctx.timeout.as_millis() as u64)
+			.await
+	}
+}
+
+impl Plugin {
+	async fn call(&self, index: usize, tool: &str, args: &Value, timeout_ms: u64) -> ToolResult {
+		let name = &self.described.name;
+		let host = match self.host().await {
+			Ok(host) => host,
+			Err(e) => return failure("plugin_error", format!("plugin {name}: {e}")),
+		};
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		let (tx, rx) = oneshot::channel();
+		host.pending.lock().unwrap().insert(id, tx);
+		let request = CallRequest {
+			id,
+			tool: index,
+			args: args.to_string(),
+			timeout_ms,
+		};
+		let mut line = serde_json::to_string(&request).unwrap_or_default();
+		line.push('\n');
+		// If the write fails the host is gone, and its reader fails the call.
+		let _ = host.stdin.lock().await.write_all(line.as_bytes()).await;
+		let text = match rx.await {
+			Ok(Some(text)) => text,
+			Ok(None) => return failure("plugin_error", format!("{tool} returned no result")),
+			Err(_) => {
+				return failure(
+					"plugin_crashed",
+					format!("plugin {name} exited while running {tool}; it is restarted on the next call"),
+				)
+			}
+		};
+		match serde_json::from_str::<PluginResult>(&text) {
+			Ok(result) => ToolResult {
+				status: result.status,
+				stdout: result.stdout,
+				stderr: result.stderr,
+				exit_code: result.exit_code,
+				..Default::default()
+			},
+			Err(e) => failure("plugin_error", format!("{tool} returned an invalid result: {e}")),
+		}
+	}
+
+	/// The running host, started anew if the last one exited.
+	async fn host(&self) -> Result<Arc<Host>, String> {
+		let mut slot = self.host.lock().await;
+		if let Some(host) = slot.as_ref().filter(|h| h.alive.load(Ordering::Relaxed)) {
+			return Ok(host.clone());
+		}
+		let library = std::fs::read(&self.path).map_err(|e| format!("{}: {e}", self.path.display()))?;
+		if <[u8; 32]>::from(Sha256::digest(&library)) != self.digest {
+			return Err("the library changed since its signature was checked at startup".to_string());
+		}
+		let (host, described) = spawn_host(&self.path).await?;
+		if described != self.described {
+			return Err("the library no longer declares what it did at startup".to_string());
+		}
+		info!(plugin = %described.name, path = %self.path.display(), message = "Restarted plugin host");
+		*slot = Some(host.clone());
+		Ok(host)
+	}
+}
+
+/// Start a host for the library at `path` and read what it declares.
+async fn spawn_host(path: &Path) -> Result<(Arc<Host>, Described), String> {
+	let exe = std::env::current_exe().map_err(|e| format!("cannot find the sandbox binary: {e}"))?;
+	// None of the sandbox's environment, credentials included, reaches the
+	// plugin.
+	let mut child = Command::new(exe)
+		.arg(HOST_ARG)
+		.arg(path)
+		.env_clear()
+		.env("PATH", "/usr/local/bin:/usr/bin:/bin")
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::inherit())
+		.kill_on_drop(true)
+		.spawn()
+		.map_err(|e| format!("cannot start a plugin host: {e}"))?;
+	let stdin = child.stdin.take().expect("stdin is piped");
+	let mut lines = BufReader::new(child.stdout.take().expect("stdout is piped")).lines();
+	let hello = match tokio::time::timeout(HELLO_TIMEOUT, lines.next_line()).await {
+		Ok(Ok(Some(line))) => serde_json::from_str::<Hello>(&line).map_err(|e| format!("bad greeting from plugin host: {e}"))?,
+		Ok(_) => return Err("the plugin host exited while loading the library".to_string()),
+		Err(_) => return Err(format!("the library did not load within {}s", HELLO_TIMEOUT.as_secs())),
+	};
+	let described = match hello {
+		Hello::Loaded(described) => described,
+		Hello::Failed { error } => return Err(error),
+	};
+
+	let pending: Arc<Mutex<HashMap<u64, oneshot::Sender<Option<String>>>>> = Arc::default();
+	let alive = Arc::new(AtomicBool::new(true));
+	let host = Arc::new(Host {
+		stdin: tokio::sync::Mutex::new(stdin),
+		pending: pending.clone(),
+		alive: alive.clone(),
+	});
+	let plugin = described.name.clone();
+	tokio::spawn(async move {
+		while let Ok(Some(line)) = lines.next_line().await {
+			match serde_json::from_str::<CallReply>(&line) {
+				Ok(reply) => {
+					if let Some(tx) = pending.lock().unwrap().remove(&reply.id) {
+						let _ = tx.send(reply.result);
+					}
+				}
+				Err(e) => warn!(plugin = %plugin, error = %e, message = "Unreadable reply from plugin host"),
+			}
+		}
+		alive.store(false, Ordering::Relaxed);
+		let status = match child.wait().await {
+			Ok(status) => status.to_string(),
+			Err(e) => e.to_string(),
+		};
+		let lost = std::mem::take(&mut *pending.lock().unwrap()).len();
+		error!(plugin = %plugin, status = %status, lost_calls = lost, message = "Plugin host exited");
+	});
+	Ok((host, described))
+}
+
+/// Load every plugin library in `dir`, each in a host process of its own.
+/// A library whose signature `verifier` rejects, that fails to load, speaks
+/// no ABI version this build does, or declares a tool that is malformed or
+/// already defined fails the whole load. Tools named in `taken` are the
+/// sandbox's own.
+pub async fn load(dir: &Path, taken: &[String], verifier: &ToolVerifier) -> Result<usize, String> {
+	let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+		.map_err(|e| format!("{}: {e}", dir.display()))?
+		.filter_map(|entry| entry.ok().map(|e| e.path()))
+		.filter(|path| {
+			path.extension()
+				.and_then(|e| e.to_str())
+				.is_some_and(|e| LIBRARY_EXTENSIONS.contains(&e))
+		})
+		.collect();
+	paths.sort();
+
+	let mut tools: Vec<Arc<dyn Tool>> = Vec::new();
+	for path in paths {
+		let at = |e: String| format!("{}: {e}", path.display());
+		let library = std::fs::read(&path).map_err(|e| at(e.to_string()))?;
+		verifier.verify(&path, &library).map_err(at)?;
+		let (host, described) = spawn_host(&path).await.map_err(at)?;
+		let plugin = Arc::new(Plugin {
+			path: path.clone(),
+			described: described.clone(),
+			digest: Sha256::digest(&library).into(),
+			host: tokio::sync::Mutex::new(Some(host)),
+			next_id: AtomicU64::new(0),
+		});
+		for (index, spec) in described.tools.iter().enumerate() {
+			let tool = describe(&plugin, index, spec).map_err(at)?;
+			if taken.iter().any(|t| t == tool.name) || tools.iter().any(|t| t.name() == tool.name) {
+				return Err(at(format!("tool {:?} is already defined", tool.name)));
+			}
+			tools.push(Arc::new(tool));
+		}
+		info!(
+			plugin = %described.name,
+			version = %described.version,
+			abi_version = described.abi_version,
+			path = %path.display(),
+			tools = described.tools.len(),
+			message = "Loaded tool plugin"
+		);
+	}
+	let count = tools.len();
+	LOADED
+		.set(tools)
+		.map_err(|_| "plugins are already loaded".to_string())?;
+	Ok(count)
+}
+
+/// The tools of the plugins loaded at startup.
+pub fn tools() -> Vec<Arc<dyn Tool>> {
+	LOADED.get().cloned().unwrap_or_default()
+}
+
+fn describe(plugin: &Arc<Plugin>, index: usize, spec: &ToolSpec) -> Result<PluginTool, String> {
+	let name = &spec.name;
+	if !is_valid_tool_name(name) {
+		return Err(format!("invalid tool name {name:?}: use [A-Za-z0-9_-]"));
+	}
+	let schema = match &spec.args_schema_json {
+		Some(text) => serde_json::from_str::<Value>(text).map_err(|e| format!("tool {name:?}: args_schema_json: {e}"))?,
+		None => json!({ "type": "object" }),
+	};
+	if !schema.is_object() {
+		return Err(format!("tool {name:?}: args_schema_json must be a JSON Schema object"));
+	}
+	jsonschema::validator_for(&schema).map_err(|e| format!("tool {name:?}: invalid args_schema_json: {e}"))?;
+	Ok(PluginTool {
+		plugin: plugin.clone(),
+		index,
+		// Plugins are loaded once, so what they declare lives as long as the
+		// process.
+		name: Box::leak(name.clone().into_boxed_str()),
+		description: Box::leak(spec.description.clone().into_boxed_str()),
+		schema,
+	})
+}
+
+/// The library to host, when the process was started as a plugin host.
+pub fn host_argument() -> Option<PathBuf> {
+	let mut args = std::env::args_os().skip(1);
+	if args.next()? != HOST_ARG {
+		return None;
+	}
+	args.next().map(PathBuf::from)
+}
+
+/// Run as the host of the library at `path`: load it, describe it on
+/// stdout, then run each call read from stdin on a thread of its own and
+/// write the answers back as they finish, one JSON line each. Exits when
+/// stdin closes.
+pub fn host_main(path: &Path) -> ! {
+	// Keep the real stdout for the protocol; whatever the library prints
+	// goes to stderr.
+	let out = unsafe {
+		let fd = libc::dup(libc::STDOUT_FILENO);
+		libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO);
+		File::from_raw_fd(fd)
+	};
+	let out = Arc::new(Mutex::new(out));
+	let library = match Library::open(path) {
+		Ok(library) => library,
+		Err(error) => {
+			write_line(&out, &Hello::Failed { error });
+			std::process::exit(1);
+		}
+	};
+	write_line(&out, &Hello::Loaded(library.describe()));
+
+	for line in std::io::stdin().lock().lines() {
+		let Ok(line) = line else { break };
+		let Ok(request) = serde_json::from_str::<CallRequest>(&line) else {
+			continue;
+		};
+		let out = out.clone();
+		std::thread::spawn(move || {
+			let result = library.run(request.tool, &request.args, request.timeout_ms);
+			write_line(&out, &CallReply { id: request.id, result });
+		});
+	}
+	std::process::exit(0);
+}
+
+fn write_line<T: Serialize>(out: &Mutex<File>, message: &T) {
+	let mut line = serde_json::to_string(message).unwrap_or_default();
+	line.push('\n');
+	let _ = out.lock().unwrap().write_all(line.as_bytes());
+}
+
+/// A library opened by its host. It is never closed: the host exits
+/// instead.
+#[derive(Clone, Copy)]
+struct Library {
+	descriptor: &'static PluginV1,
+}
+
+// The descriptor is immutable and the ABI requires `run` to be callable
+// from any thread.
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+impl Library {
+	/// Open the library and agree on an ABI version with it.
+	fn open(path: &Path) -> Result<Self, String> {
+		let c_path = CString::new(path.as_os_str().as_encoded_bytes())
+			.map_err(|_| "the path contains a NUL byte".to_string())?;
+		let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+		if handle.is_null() {
+			return Err(c_str(unsafe { libc::dlerror() }).unwrap_or_else(|| "dlopen failed".to_string()));
+		}
+		let symbol = unsafe { libc::dlsym(handle, INIT_SYMBOL.as_ptr()) };
+		if symbol.is_null() {
+			return Err(format!("no {} symbol", INIT_SYMBOL.to_string_lossy()));
+		}
+		let init = unsafe { std::mem::transmute::<*mut c_void, InitFn>(symbol) };
+		let descriptor = unsafe { init(*ABI_VERSIONS.start(), *ABI_VERSIONS.end()) };
+		if descriptor.is_null() {
+			return Err(format!(
+				"the plugin supports none of the ABI versions {}..={}",
+				ABI_VERSIONS.start(),
+				ABI_VERSIONS.end()
+			));
+		}
+		let descriptor: &'static PluginV1 = unsafe { &*descriptor };
+		if !ABI_VERSIONS.contains(&descriptor.abi_version) {
+			return Err(format!("ABI version {} is not supported", descriptor.abi_version));
+		}
+		if descriptor.tool_count > 0 && descriptor.tools.is_null() {
+			return Err("tool_count is set but tools is NULL".to_string());
+		}
+		if c_str(descriptor.name).is_none() {
+			return Err("the plugin has no name".to_string());
+		}
+		let library = Self { descriptor };
+		for (index, tool) in library.tools().iter().enumerate() {
+			if c_str(tool.name).is_none() {
+				return Err(format!("tool {index} has no name"));
+			}
+			if tool.run.is_none() {
+				return Err(format!("tool {index} has no run function"));
+			}
+		}
+		Ok(library)
+	}
+
+	fn tools(&self) -> &'static [ToolV1] {
+		match self.descriptor.tool_count {
+			0 => &[],
+			n => unsafe { std::slice::from_raw_parts(self.descriptor.tools, n) },
+		}
+	}
+
+	fn describe(&self) -> Described {
+		Described {
+			name: c_str(self.descriptor.name).unwrap_or_default(),
+			version: c_str(self.descriptor.version).unwrap_or_default(),
+			abi_version: self.descriptor.abi_version,
+			tools: self
+				.tools()
+				.iter()
+				.map(|tool| ToolSpec {
+					name: c_str(tool.name).unwrap_or_default(),
+					description: c_str(tool.description).unwrap_or_default(),
+					args_schema_json: c_str(tool.args_schema_json),
+				})
+				.collect(),
+		}
+	}
+
+	fn run(&self, index: usize, args: &str, timeout_ms: u64) -> Option<String> {
+		let run = self.tools().get(index)?.run?;
+		let args = CString::new(args).ok()?;
+		let raw = unsafe { run(args.as_ptr(), timeout_ms) };
+		if raw.is_null() {
+			return None;
+		}
+		let text = c_str(raw);
+		if let Some(free) = self.descriptor.free_result {
+			unsafe { free(raw) };
+		}
+		text
+	}
+}
+
+fn c_str(ptr: *const c_char) -> Option<String> {
+	if ptr.is_null() {
+		return None;
+	}
+	Some(unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned())
+}
+
+fn failure(status: &str, message: String) -> ToolResult {
+	ToolResult {
+		status: status.to_string(),
+		stderr: message,
+		..Default::default()
+	}
+}
@@ -0,0 +1,395 @@
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::audit::args_sha256;
+use crate::auth::Principal;
+use crate::registry::base_name;
+
+const DEFAULT_REGO_RULE: &str = "data.sandbox.allow";
+const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+const DEFAULT_CACHE_TTL_SECS: u64 = 60;
+
+/// The language of an execution policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyLanguage {
+	/// Open Policy Agent's Rego, evaluated in-process.
+	Rego,
+	Cedar,
+}
+
+impl FromStr for PolicyLanguage {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"rego" => Ok(Self::Rego),
+			"cedar" => Ok(Self::Cedar),
+			_ => Err(format!("expected rego or cedar (got {s:?})")),
+		}
+	}
+}
+
+impl PolicyLanguage {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::Rego => "rego",
+			Self::Cedar => "cedar",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+	/// Policy every execution request is checked against once RBAC lets it
+	/// through; without one there is no such check.
+	pub policy_file: Option<PathBuf>,
+	/// The file's language; a `.rego` or `.cedar` extension if unset.
+	pub engine: Option<PolicyLanguage>,
+	/// Rego: the rule holding the decision, either a boolean or
+	/// `{"allow": bool, "reason": "..."}`. Undefined denies.
+	pub rego_rule: String,
+	/// Decisions kept for inputs seen before; 0 evaluates every request.
+	pub cache_capacity: usize,
+	pub cache_ttl_secs: u64,
+}
+
+impl Default for PolicyConfig {
+	fn default() -> Self {
+		Self {
+			policy_file: None,
+			engine: None,
+			rego_rule: DEFAULT_REGO_RULE.to_string(),
+			cache_capacity: DEFAULT_CACHE_CAPACITY,
+			cache_ttl_secs: DEFAULT_CACHE_TTL_SECS,
+		}
+	}
+}
+
+impl PolicyConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if let Some(path) = &self.policy_file {
+			if !path.is_file() {
+				errors.push(format!("policy.policy_file {} does not exist", path.display()));
+			}
+			if self.language(path).is_none() {
+				errors.push("policy.engine is required unless policy.policy_file ends in .rego or .cedar".to_string());
+			}
+		}
+		if !self.rego_rule.starts_with("data.") {
+			errors.push(format!("policy.rego_rule must be a data. path (got {:?})", self.rego_rule));
+		}
+		errors
+	}
+
+	fn language(&self, path: &Path) -> Option<PolicyLanguage> {
+		self.engine.or_else(|| match path.extension().and_then(|e| e.to_str()) {
+			Some("rego") => Some(PolicyLanguage::Rego),
+			Some("cedar") => Some(PolicyLanguage::Cedar),
+			_ => None,
+		})
+	}
+}
+
+/// Who is asking, as a policy sees them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyPrincipal {
+	/// `None` for anonymous callers.
+	pub subject: Option<String>,
+	pub roles: Vec<String>,
+	pub tenant: Option<String>,
+}
+
+/// What a policy decides on: who asks to run which tool, with which args,
+/// over which transport.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyInput {
+	#[serde(default)]
+	pub principal: PolicyPrincipal,
+	/// The tool's name, without a version.
+	pub tool: String,
+	/// The name as requested, with any version or range.
+	pub requested: String,
+	#[serde(default)]
+	pub args: Value,
+	#[serde(default)]
+	pub tenant: Option<String>,
+	/// `http`, `grpc`, `batch`, `mcp`, `schedule`, ...
+	#[serde(default)]
+	pub transport: String,
+}
+
+impl PolicyInput {
+	/// `roles` are the caller's as RBAC resolves them, bindings and default
+	/// roles included.
+	pub fn new(principal: Option<&Principal>, roles: Vec<String>, transport: &str, tool_name: &str, args: &Value) -> Self {
+		let tenant = principal.and_then(|p| p.tenant.clone());
+		Self {
+			principal: PolicyPrincipal {
+				subject: principal.map(|p| p.subject.clone()),
+				roles,
+				tenant: tenant.clone(),
+			},
+			tool: base_name(tool_name).to_string(),
+			requested: tool_name.to_string(),
+			args: args.clone(),
+			tenant,
+			transport: transport.to_string(),
+		}
+	}
+}
+
+/// A request to evaluate through `POST /api/v1/admin/policy/evaluate`: a
+/// caller as the policy would see them, with their roles already resolved.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EvaluateRequest {
+	#[serde(default)]
+	pub principal: PolicyPrincipal,
+	pub tool_name: String,
+	#[serde(default)]
+	pub args: Value,
+	#[serde(default = "default_transport")]
+	pub transport: String,
+}
+
+fn default_transport() -> String {
+	"http".to_string()
+}
+
+impl EvaluateRequest {
+	pub fn input(self) -> PolicyInput {
+		PolicyInput {
+			tool: base_name(&self.tool_name).to_string(),
+			requested: self.tool_name,
+			args: self.args,
+			tenant: self.principal.tenant.clone(),
+			principal: self.principal,
+			transport: self.transport,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+	pub allow: bool,
+	/// The policy's reason, or the policies that decided, when there are any.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub reason: Option<String>,
+}
+
+enum Engine {
+	Rego { engine: regorus::Engine, rule: String },
+	Cedar(cedar_policy::PolicySet),
+}
+
+/// A Rego or Cedar policy deciding which execution requests may run, with
+/// the decisions it made lately.
+///
+/// Evaluation is in-process and synchronous, so it can sit on every request
+/// path. A policy that fails to evaluate denies; such decisions are not
+/// cached.
+pub struct PolicyEngine {
+	language: PolicyLanguage,
+	engine: Engine,
+	cache: Option<Mutex<LruCache<String, (Instant, Decision)>>>,
+	ttl: Duration,
+}
+
+impl PolicyEngine {
+	/// The policy `config` names, or `None` without one.
+	pub fn load(config: &PolicyConfig) -> Result<Option<Self>, String> {
+		let Some(path) = &config.policy_file else {
+			return Ok(None);
+		};
+		let language = config
+			.language(path)
+			.ok_or_else(|| format!("{}: unknown policy language", path.display()))?;
+		let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+		let engine = match language {
+			PolicyLanguage::Rego => {
+				let mut engine = regorus::Engine::new();
+				engine
+					.add_policy(path.to_string_lossy().to_string(), text)
+					.map_err(|e| format!("{}: {e}", path.display()))?;
+				Engine::Rego {
+					engine,
+					rule: config.rego_rule.clone(),
+				}
+			}
+			PolicyLanguage::Cedar => {
+				Engine::Cedar(text.parse().map_err(|e| format!("{}: {e}", path.display()))?)
+			}
+		};
+		info!(path = %path.display(), engine = language.as_str(), message = "Loaded execution policy");
+		let cache = NonZeroUsize::new(config.cache_capacity)
+			.filter(|_| config.cache_ttl_secs > 0)
+			.map(|capacity| Mutex::new(LruCache::new(capacity)));
+		Ok(Some(Self {
+			language,
+			engine,
+			cache,
+			ttl: Duration::from_secs(config.cache_ttl_secs),
+		}))
+	}
+
+	pub fn language(&self) -> PolicyLanguage {
+		self.language
+	}
+
+	/// `Ok` when the policy allows `input`, from the cache when it decided
+	/// the same input lately.
+	pub fn check(&self, input: &PolicyInput) -> Result<(), String> {
+		let key = args_sha256(&json!(input));
+		let cached = self.cache.as_ref().and_then(|cache| {
+			let mut cache = cache.lock().unwrap();
+			match cache.get(&key) {
+				Some((at, decision)) if at.elapsed() < self.ttl => Some(decision.clone()),
+				_ => None,
+			}
+		});
+		let decision = match cached {
+			Some(decision) => decision,
+			None => {
+				let decision = self.evaluate(input);
+				if let (Some(cache), Ok(decision)) = (&self.cache, &decision) {
+					cache.lock().unwrap().put(key, (Instant::now(), decision.clone()));
+				}
+				decision.unwrap_or_else(|e| {
+					warn!(tool_name = input.requested, error = %e, message = "Execution policy failed to evaluate; denying");
+					Decision {
+						allow: false,
+						reason: Some(format!("the execution policy failed to evaluate: {e}")),
+					}
+				})
+			}
+		};
+		if decision.allow {
+			return Ok(());
+		}
+		let who = input.principal.subject.as_deref().unwrap_or("anonymous");
+		warn!(
+			subject = who,
+			tool_name = input.requested,
+			reason = ?decision.reason,
+			message = "Execution policy denied tool execution"
+		);
+		Err(match decision.reason {
+			Some(reason) => format!("the execution policy denies {who} executing {:?}: {reason}", input.tool),
+			None => format!("the execution policy denies {who} executing {:?}", input.tool),
+		})
+	}
+
+	/// What the policy decides for `input`, never from the cache.
+	pub fn evaluate(&self, input: &PolicyInput) -> Result<Decision, String> {
+		match &self.engine {
+			Engine::Rego { engine, rule } => evaluate_rego(engine, rule, input),
+			Engine::Cedar(policies) => evaluate_cedar(policies, input),
+		}
+	}
+}
+
+fn evaluate_rego(engine: &regorus::Engine, rule: &str, input: &PolicyInput) -> Result<Decision, String> {
+	// Evaluation takes the engine mutably; a copy leaves the loaded one to
+	// other requests.
+	let mut engine = engine.clone();
+	let input = regorus::Value::from_json_str(&json!(input).to_string()).map_err(|e| e.to_string())?;
+	engine.set_input(input);
+	let value = engine.eval_rule(rule.to_string()).map_err(|e| e.to_string())?;
+	if value == regorus::Value::Undefined {
+		return Ok(Decision {
+			allow: false,
+			reason: Some(format!("{rule} is undefined")),
+		});
+	}
+	let value: Value = serde_json::from_str(&value.to_json_str().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+	match value {
+		Value::Bool(allow) => Ok(Decision { allow, reason: None }),
+		Value::Object(decision) => Ok(Decision {
+			allow: decision.get("allow").and_then(Value::as_bool).unwrap_or(false),
+			reason: decision.get("reason").and_then(Value::as_str).map(str::to_string),
+		}),
+		other => Err(format!("{rule} is {other}, not a boolean or {{allow, reason}}")),
+	}
+}
+
+/// Cedar sees a `Sandbox::User` (or, unauthenticated, `Sandbox::Anonymous`)
+/// in its `Sandbox::Role`s, taking action `Sandbox::Action::"execute"` on a
+/// `Sandbox::Tool`, with the args, tenant and transport as context.
+fn evaluate_cedar(policies: &cedar_policy::PolicySet, input: &PolicyInput) -> Result<Decision, String> {
+	use cedar_policy::{Authorizer, Context, Decision as CedarDecision, Entities, EntityUid, Request};
+
+	let uid = |kind: &str, id: &str| json!({ "type": format!("Sandbox::{kind}"), "id": id });
+	let principal = match &input.principal.subject {
+		Some(subject) => uid("User", subject),
+		None => uid("Anonymous", "anonymous"),
+	};
+	let roles: Vec<Value> = input.principal.roles.iter().map(|role| uid("Role", role)).collect();
+	let mut attrs = json!({ "roles": input.principal.roles });
+	if let Some(tenant) = &input.tenant {
+		attrs["tenant"] = json!(tenant);
+	}
+	let mut entities: Vec<Value> = roles
+		.iter()
+		.map(|role| json!({ "uid": role, "attrs": {}, "parents": [] }))
+		.collect();
+	entities.push(json!({ "uid": principal, "attrs": attrs, "parents": roles }));
+	entities.push(json!({
+		"uid": uid("Tool", &input.tool),
+		"attrs": { "requested": input.requested },
+		"parents": [],
+	}));
+	let args = cedar_value(&input.args).unwrap_or_else(|| json!({}));
+	let mut context = json!({ "args": args, "transport": input.transport });
+	if let Some(tenant) = &input.tenant {
+		context["tenant"] = json!(tenant);
+	}
+
+	let entity = |value: &Value| EntityUid::from_json(value.clone()).map_err(|e| e.to_string());
+	let request = Request::new(
+		entity(&principal)?,
+		entity(&uid("Action", "execute"))?,
+		entity(&uid("Tool", &input.tool))?,
+		Context::from_json_value(context, None).map_err(|e| e.to_string())?,
+		None,
+	)
+	.map_err(|e| e.to_string())?;
+	let entities = Entities::from_json_value(Value::Array(entities), None).map_err(|e| e.to_string())?;
+	let response = Authorizer::new().is_authorized(&request, policies, &entities);
+	let diagnostics = response.diagnostics();
+	if let Some(error) = diagnostics.errors().next() {
+		return Err(error.to_string());
+	}
+	let decided: Vec<String> = diagnostics.reason().map(ToString::to_string).collect();
+	Ok(Decision {
+		allow: response.decision() == CedarDecision::Allow,
+		reason: (!decided.is_empty()).then(|| format!("policies {}", decided.join(", "))),
+	})
+}
+
+/// `value` as Cedar takes it: Cedar has no nulls or fractions, so nulls are
+/// left out and numbers that are not integers become strings.
+fn cedar_value(value: &Value) -> Option<Value> {
+	match value {
+		Value::Null => None,
+		Value::Number(n) if n.as_i64().is_none() => Some(Value::String(n.to_string())),
+		Value::Array(items) => Some(Value::Array(items.iter().filter_map(cedar_value).collect())),
+		Value::Object(fields) => Some(Value::Object(
+			fields
+				.iter()
+				.filter_map(|(k, v)| cedar_value(v).map(|v| (k.clone(), v)))
+				.collect::<Map<_, _>>(),
+		)),
+		other => Some(other.clone()),
+	}
+}
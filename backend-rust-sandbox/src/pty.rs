@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::c_char;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::process::Stdio;
+use std::task::{ready, Context, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::watch;
+
+use crate::namespace::check;
+
+/// What the terminal reads as end of file: ^D, in a terminal left at its
+/// defaults.
+const VEOF: u8 = 0x04;
+
+/// Columns and rows of a terminal window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowSize {
+	pub cols: u16,
+	pub rows: u16,
+}
+
+impl Default for WindowSize {
+	fn default() -> Self {
+		Self { cols: 80, rows: 24 }
+	}
+}
+
+/// The terminal a tool runs on instead of pipes, for programs that behave
+/// differently without one. Its size is the session client's, which may
+/// change it while the tool runs.
+#[derive(Debug, Clone)]
+pub struct Terminal(watch::Receiver<WindowSize>);
+
+impl Terminal {
+	/// A terminal of `size`, and what resizes it.
+	pub fn new(size: WindowSize) -> (Self, watch::Sender<WindowSize>) {
+		let (tx, rx) = watch::channel(size);
+		(Self(rx), tx)
+	}
+
+	pub fn size(&self) -> WindowSize {
+		*self.0.borrow()
+	}
+
+	/// Keep `master`'s window at the client's size until it stops sending
+	/// resizes. The tool gets SIGWINCH on each.
+	async fn follow(mut self, master: OwnedFd) {
+		while self.0.changed().await.is_ok() {
+			let size = *self.0.borrow_and_update();
+			let _ = resize(&master, size);
+		}
+	}
+}
+
+/// One of the default size, which nobody resizes.
+impl Default for Terminal {
+	fn default() -> Self {
+		Self::new(WindowSize::default()).0
+	}
+}
+
+/// A pseudo-terminal pair: the sandbox's end, and the tool's.
+pub struct Pty {
+	master: OwnedFd,
+	slave: OwnedFd,
+}
+
+impl Pty {
+	pub fn open(size: WindowSize) -> io::Result<Self> {
+		// SAFETY: plain syscalls. The name buffer outlives `ptsname_r`, which
+		// NUL-terminates what it writes.
+		let pty = unsafe {
+			let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+			check(master)?;
+			let master = OwnedFd::from_raw_fd(master);
+			check(libc::grantpt(master.as_raw_fd()))?;
+			check(libc::unlockpt(master.as_raw_fd()))?;
+			let mut name = [0 as c_char; 64];
+			let rc = libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len());
+			if rc != 0 {
+				return Err(io::Error::from_raw_os_error(rc));
+			}
+			let slave = libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+			check(slave)?;
+			Self {
+				master,
+				slave: OwnedFd::from_raw_fd(slave),
+			}
+		};
+		resize(&pty.master, size)?;
+		Ok(pty)
+	}
+
+	/// The tool's end, as one of its stdio streams.
+	pub fn slave(&self) -> io::Result<Stdio> {
+		self.slave.try_clone().map(Stdio::from)
+	}
+
+	/// The sandbox's end, once the tool has been spawned: what it writes and
+	/// what it reads, kept at `terminal`'s size until the returned reader is
+	/// done with. Dropping the pty's own copy of the tool's end lets reads
+	/// end once the tool and everything it started have closed theirs.
+	pub fn into_master(self, terminal: Terminal) -> io::Result<(PtyReader, PtyWriter)> {
+		let Self { master, slave } = self;
+		drop(slave);
+		// SAFETY: fcntl(2) on an fd we own.
+		unsafe {
+			let flags = libc::fcntl(master.as_raw_fd(), libc::F_GETFL);
+			check(flags)?;
+			check(libc::fcntl(master.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+		}
+		let resizes = tokio::spawn(terminal.follow(master.try_clone()?));
+		let writer = PtyWriter(AsyncFd::new(File::from(master.try_clone()?))?);
+		let reader = PtyReader {
+			fd: AsyncFd::new(File::from(master))?,
+			resizes,
+		};
+		Ok((reader, writer))
+	}
+}
+
+/// In the child, just before it execs, with the pty's slave as its stdio:
+/// start a session with the terminal as its controlling one. The child then
+/// leads a process group of its own, in the terminal's foreground.
+pub fn take_terminal() -> io::Result<()> {
+	// SAFETY: plain syscalls, safe after fork.
+	unsafe {
+		check(libc::setsid())?;
+		check(libc::ioctl(0, libc::TIOCSCTTY, 0))?;
+	}
+	Ok(())
+}
+
+fn resize(master: &OwnedFd, size: WindowSize) -> io::Result<()> {
+	let window = libc::winsize {
+		ws_row: size.rows,
+		ws_col: size.cols,
+		ws_xpixel: 0,
+		ws_ypixel: 0,
+	};
+	// SAFETY: an ioctl on an fd we own, with a struct that outlives it.
+	check(unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &window) })
+}
+
+/// What the tool writes to its terminal, stdout and stderr alike.
+pub struct PtyReader {
+	fd: AsyncFd<File>,
+	resizes: tokio::task::JoinHandle<()>,
+}
+
+impl AsyncRead for PtyReader {
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+		loop {
+			let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+			let unfilled = buf.initialize_unfilled();
+			match guard.try_io(|fd| (&mut fd.get_ref()).read(unfilled)) {
+				Ok(Ok(n)) => {
+					buf.advance(n);
+					return Poll::Ready(Ok(()));
+				}
+				// Once every slave fd is closed, reads fail with EIO instead
+				// of returning end of file.
+				Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => return Poll::Ready(Ok(())),
+				Ok(Err(e)) => return Poll::Ready(Err(e)),
+				Err(_would_block) => continue,
+			}
+		}
+	}
+}
+
+impl Drop for PtyReader {
+	fn drop(&mut self) {
+		self.resizes.abort();
+	}
+}
+
+/// What the tool reads from its terminal. The terminal echoes it, as it would
+/// a user's typing.
+pub struct PtyWriter(AsyncFd<File>);
+
+impl PtyWriter {
+	/// Make the tool's next read see end of file, `last` being the last byte
+	/// it was sent. A terminal ends input with ^D at the start of a line; a
+	/// partial line takes a first one to be delivered.
+	pub async fn end(&mut self, last: Option<u8>) -> io::Result<()> {
+		match last {
+			None | Some(b'\n') => self.write_all(&[VEOF]).await,
+			Some(_) => self.write_all(&[VEOF, VEOF]).await,
+		}
+	}
+}
+
+impl AsyncWrite for PtyWriter {
+	fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+		loop {
+			let mut guard = ready!(self.0.poll_write_ready(cx))?;
+			match guard.try_io(|fd| (&mut fd.get_ref()).write(data)) {
+				// The tool's side is gone, as a pipe's reader would be.
+				Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => {
+					return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+				}
+				Ok(written) => return Poll::Ready(written),
+				Err(_would_block) => continue,
+			}
+		}
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+}
@@ -0,0 +1,536 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::artifacts::{Artifact, ArtifactStore, ArtifactsConfig, S3Config};
+use crate::audit::Caller;
+use crate::clock::now_ms;
+use crate::code_scan::PolicyViolation;
+use crate::egress::NetworkPolicy;
+use crate::metrics::METRICS;
+use crate::registry::{ToolLimits, ToolManifest};
+use crate::tool_executor::ToolResult;
+use crate::usage::ResourceUsage;
+
+const DEFAULT_DIR: &str = "sandbox_quarantine";
+const DEFAULT_S3_PREFIX: &str = "quarantine";
+const DEFAULT_TTL_SECS: u64 = 7 * 24 * 3600;
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// Which executions are quarantined, and how they are held. Off by default.
+///
+/// An execution that looks suspicious before it runs (an unknown caller,
+/// code the code scan flagged) runs under `limits`, with no network and
+/// without its tool's secrets. One that used more than the `max_*` below is
+/// found out once it has run. Either way its artifacts go to the quarantine
+/// store instead of back to the caller, an `execution_quarantined` event is
+/// published, and an operator approves or rejects it through the admin API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuarantineConfig {
+	pub enabled: bool,
+	/// Quarantine callers that are anonymous, or not in `trusted_subjects`
+	/// when that is set.
+	pub unknown_callers: bool,
+	pub trusted_subjects: Vec<String>,
+	/// Quarantine code that the code scan flagged.
+	pub flagged_code: bool,
+	pub max_wall_ms: Option<u64>,
+	/// User and system CPU time together.
+	pub max_cpu_ms: Option<u64>,
+	pub max_peak_rss_mb: Option<u64>,
+	/// Written to stdout and stderr together.
+	pub max_output_bytes: Option<u64>,
+	/// Caps on the limits of executions quarantined before they run; a
+	/// tool's own lower limits stay.
+	pub limits: ToolLimits,
+	/// `local` artifact backend: where held artifacts are stored.
+	pub dir: PathBuf,
+	/// `s3` artifact backend: the key prefix, in the artifacts bucket,
+	/// held artifacts are stored under.
+	pub s3_prefix: String,
+	/// How long held artifacts are kept, decided or not.
+	pub ttl_secs: u64,
+	/// Entries kept; past that the oldest go.
+	pub max_entries: usize,
+}
+
+impl Default for QuarantineConfig {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			unknown_callers: true,
+			trusted_subjects: Vec::new(),
+			flagged_code: true,
+			max_wall_ms: None,
+			max_cpu_ms: None,
+			max_peak_rss_mb: None,
+			max_output_bytes: None,
+			limits: ToolLimits {
+				timeout_ms: Some(5_000),
+				memory_mb: Some(128),
+				cpus: Some(0.5),
+				cpu_time_ms: Some(5_000),
+				pids_limit: Some(32),
+				fuel: Some(1_000_000_000),
+				disk_mb: Some(64),
+				..ToolLimits::default()
+			},
+			dir: DEFAULT_DIR.into(),
+			s3_prefix: DEFAULT_S3_PREFIX.to_string(),
+			ttl_secs: DEFAULT_TTL_SECS,
+			max_entries: DEFAULT_MAX_ENTRIES,
+		}
+	}
+}
+
+impl QuarantineConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if !self.enabled {
+			return errors;
+		}
+		if self.ttl_secs == 0 {
+			errors.push("executor.quarantine.ttl_secs must be greater than 0".to_string());
+		}
+		if self.max_entries == 0 {
+			errors.push("executor.quarantine.max_entries must be greater than 0".to_string());
+		}
+		if self.limits.timeout_ms == Some(0) {
+			errors.push("executor.quarantine.limits.timeout_ms must be greater than 0".to_string());
+		}
+		if self.limits.cpus.is_some_and(|cpus| cpus <= 0.0) {
+			errors.push("executor.quarantine.limits.cpus must be greater than 0".to_string());
+		}
+		errors
+	}
+}
+
+/// Why an execution was quarantined.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "trigger", rename_all = "snake_case")]
+pub enum QuarantineReason {
+	UnknownCaller { subject: Option<String> },
+	/// The ids of the flagging rules.
+	FlaggedCode { rules: Vec<String> },
+	/// `measure` is one of `wall_ms`, `cpu_ms`, `peak_rss_mb` and
+	/// `output_bytes`.
+	ResourceUse { measure: String, used: u64, limit: u64 },
+}
+
+impl QuarantineReason {
+	pub fn trigger(&self) -> &'static str {
+		match self {
+			Self::UnknownCaller { .. } => "unknown_caller",
+			Self::FlaggedCode { .. } => "flagged_code",
+			Self::ResourceUse { .. } => "resource_use",
+		}
+	}
+}
+
+impl fmt::Display for QuarantineReason {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::UnknownCaller { subject: Some(subject) } => write!(f, "unknown caller {subject:?}"),
+			Self::UnknownCaller { subject: None } => write!(f, "anonymous caller"),
+			Self::FlaggedCode { rules } => write!(f, "flagged code ({})", rules.join(", ")),
+			Self::ResourceUse { measure, used, limit } => write!(f, "{measure} {used} over {limit}"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuarantineState {
+	Pending,
+	/// The artifacts were released to the artifact store.
+	Approved,
+	/// The artifacts were deleted.
+	Rejected,
+}
+
+impl QuarantineState {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Pending => "pending",
+			Self::Approved => "approved",
+			Self::Rejected => "rejected",
+		}
+	}
+}
+
+impl std::str::FromStr for QuarantineState {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"pending" => Ok(Self::Pending),
+			"approved" => Ok(Self::Approved),
+			"rejected" => Ok(Self::Rejected),
+			_ => Err(format!("expected pending, approved or rejected (got {s:?})")),
+		}
+	}
+}
+
+/// What a quarantined execution's result says about it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quarantined {
+	/// The execution id; the entry's id in the admin API.
+	pub id: String,
+	pub reasons: Vec<QuarantineReason>,
+	/// Artifacts held until an operator approves them.
+	pub artifacts_held: usize,
+}
+
+/// A quarantined execution, as the admin API shows it.
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantineEntry {
+	pub id: String,
+	pub tool_name: String,
+	pub subject: Option<String>,
+	pub tenant: Option<String>,
+	pub transport: &'static str,
+	pub reasons: Vec<QuarantineReason>,
+	/// The execution's status.
+	pub status: String,
+	pub artifacts: Vec<Artifact>,
+	pub state: QuarantineState,
+	pub created_at_ms: u64,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub decided_at_ms: Option<u64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub decided_by: Option<String>,
+}
+
+pub enum QuarantineError {
+	NotFound,
+	/// Approved or rejected already.
+	Decided(QuarantineState),
+	Store(String),
+}
+
+impl QuarantineError {
+	pub fn message(&self) -> String {
+		match self {
+			Self::NotFound => "no such quarantined execution".to_string(),
+			Self::Decided(state) => format!("the execution was {} already", state.as_str()),
+			Self::Store(e) => format!("quarantine store: {e}"),
+		}
+	}
+}
+
+/// Suspicious executions and the artifacts held from them. Entries are held
+/// in memory and are gone after a restart; their artifacts stay in the
+/// quarantine store until its TTL runs out.
+pub struct Quarantine {
+	config: QuarantineConfig,
+	/// `None` while quarantine is off.
+	store: Option<ArtifactStore>,
+	entries: Mutex<BTreeMap<String, QuarantineEntry>>,
+}
+
+impl Quarantine {
+	pub fn new(config: &QuarantineConfig, artifacts: &ArtifactsConfig) -> Result<Self, String> {
+		let store = config
+			.enabled
+			.then(|| {
+				ArtifactStore::new(&ArtifactsConfig {
+					dir: config.dir.clone(),
+					s3: S3Config {
+						prefix: config.s3_prefix.clone(),
+						..artifacts.s3.clone()
+					},
+					ttl_secs: config.ttl_secs,
+					..artifacts.clone()
+				})
+			})
+			.transpose()?;
+		Ok(Self {
+			config: config.clone(),
+			store,
+			entries: Mutex::new(BTreeMap::new()),
+		})
+	}
+
+	pub fn enabled(&self) -> bool {
+		self.config.enabled
+	}
+
+	/// Why an execution should be quarantined before it runs, if it should.
+	pub fn reasons_before(&self, caller: &Caller, findings: &[PolicyViolation]) -> Vec<QuarantineReason> {
+		let mut reasons = Vec::new();
+		if !self.config.enabled {
+			return reasons;
+		}
+		let trusted = match &caller.subject {
+			Some(subject) => self.config.trusted_subjects.is_empty() || self.config.trusted_subjects.contains(subject),
+			None => false,
+		};
+		if self.config.unknown_callers && !trusted {
+			reasons.push(QuarantineReason::UnknownCaller {
+				subject: caller.subject.clone(),
+			});
+		}
+		if self.config.flagged_code && !findings.is_empty() {
+			let mut rules: Vec<String> = findings.iter().map(|v| v.rule.clone()).collect();
+			rules.sort();
+			rules.dedup();
+			reasons.push(QuarantineReason::FlaggedCode { rules });
+		}
+		reasons
+	}
+
+	/// The resources an execution used past the configured maximums.
+	pub fn reasons_after(&self, usage: Option<&ResourceUsage>) -> Vec<QuarantineReason> {
+		let (true, Some(usage)) = (self.config.enabled, usage) else {
+			return Vec::new();
+		};
+		let cpu_ms = match (usage.user_cpu_ms, usage.sys_cpu_ms) {
+			(None, None) => None,
+			(user, sys) => Some(user.unwrap_or(0) + sys.unwrap_or(0)),
+		};
+		[
+			("wall_ms", Some(usage.wall_ms), self.config.max_wall_ms),
+			("cpu_ms", cpu_ms, self.config.max_cpu_ms),
+			("peak_rss_mb", usage.peak_rss_bytes.map(|b| b / (1024 * 1024)), self.config.max_peak_rss_mb),
+			("output_bytes", usage.output_bytes, self.config.max_output_bytes),
+		]
+		.into_iter()
+		.filter_map(|(measure, used, limit)| match (used, limit) {
+			(Some(used), Some(limit)) if used > limit => Some(QuarantineReason::ResourceUse {
+				measure: measure.to_string(),
+				used,
+				limit,
+			}),
+			_ => None,
+		})
+		.collect()
+	}
+
+	/// `tool` as a quarantined execution runs it: under the quarantine
+	/// limits, with no network and without secrets.
+	pub fn restrict(&self, tool: &ToolManifest) -> ToolManifest {
+		let mut tool = tool.clone();
+		let caps = &self.config.limits;
+		let limits = &mut tool.limits;
+		limits.timeout_ms = lower(limits.timeout_ms, caps.timeout_ms);
+		limits.memory_mb = lower(limits.memory_mb, caps.memory_mb);
+		limits.cpu_weight = lower(limits.cpu_weight, caps.cpu_weight);
+		limits.cpu_time_ms = lower(limits.cpu_time_ms, caps.cpu_time_ms);
+		limits.pids_limit = lower(limits.pids_limit, caps.pids_limit);
+		limits.fuel = lower(limits.fuel, caps.fuel);
+		limits.disk_mb = lower(limits.disk_mb, caps.disk_mb);
+		limits.cpus = match (limits.cpus, caps.cpus) {
+			(Some(own), Some(cap)) => Some(own.min(cap)),
+			(own, cap) => cap.or(own),
+		};
+		tool.network = Some(NetworkPolicy::None);
+		tool.secrets.clear();
+		tool
+	}
+
+	/// Move `result`'s artifacts from `artifacts` to the quarantine store, so
+	/// that they cannot be fetched by their hash until approved, and record
+	/// the execution, pending a decision.
+	pub async fn hold(
+		&self,
+		id: &str,
+		tool_name: &str,
+		caller: &Caller,
+		result: &mut ToolResult,
+		reasons: Vec<QuarantineReason>,
+		artifacts: &ArtifactStore,
+	) -> QuarantineEntry {
+		let store = self.store.as_ref().expect("quarantine is enabled when it holds anything");
+		let mut held = Vec::with_capacity(result.artifacts.len());
+		for artifact in std::mem::take(&mut result.artifacts) {
+			let moved = match store.copy_from(artifacts, &artifact.artifact_id).await {
+				Ok(()) => artifacts.delete(&artifact.artifact_id).await,
+				Err(e) => Err(e),
+			};
+			match moved {
+				Ok(()) => held.push(artifact),
+				Err(e) => {
+					warn!(artifact = artifact.path, error = %e, message = "Failed to quarantine artifact");
+					result.note(&format!("artifact not collected: {}: could not be quarantined\n", artifact.path));
+				}
+			}
+		}
+		result.quarantined = Some(Box::new(Quarantined {
+			id: id.to_string(),
+			reasons: reasons.clone(),
+			artifacts_held: held.len(),
+		}));
+		let entry = QuarantineEntry {
+			id: id.to_string(),
+			tool_name: tool_name.to_string(),
+			subject: caller.subject.clone(),
+			tenant: caller.tenant.clone(),
+			transport: caller.transport,
+			reasons,
+			status: result.status.clone(),
+			artifacts: held,
+			state: QuarantineState::Pending,
+			created_at_ms: now_ms(),
+			decided_at_ms: None,
+			decided_by: None,
+		};
+		for reason in &entry.reasons {
+			METRICS.quarantined.with_label_values(&[tool_name, reason.trigger()]).inc();
+		}
+		let reasons: Vec<String> = entry.reasons.iter().map(ToString::to_string).collect();
+		warn!(
+			execution_id = id,
+			tool_name = tool_name,
+			reasons = %reasons.join("; "),
+			artifacts = entry.artifacts.len(),
+			message = "Execution quarantined"
+		);
+		let mut entries = self.entries.lock().unwrap();
+		if entries.len() >= self.config.max_entries {
+			let oldest = entries.values().min_by_key(|e| e.created_at_ms).map(|e| e.id.clone());
+			if let Some(oldest) = oldest {
+				entries.remove(&oldest);
+			}
+		}
+		entries.insert(id.to_string(), entry.clone());
+		entry
+	}
+
+	/// Entries, oldest first; only those in `state` if given.
+	pub fn list(&self, state: Option<QuarantineState>) -> Vec<QuarantineEntry> {
+		let mut entries: Vec<QuarantineEntry> = self
+			.entries
+			.lock()
+			.unwrap()
+			.values()
+			.filter(|e| state.is_none_or(|state| e.state == state))
+			.cloned()
+			.collect();
+		entries.sort_by_key(|e| e.created_at_ms);
+		entries
+	}
+
+	pub fn get(&self, id: &str) -> Option<QuarantineEntry> {
+		self.entries.lock().unwrap().get(id).cloned()
+	}
+
+	/// Release the entry's artifacts to `artifacts`, where the ids it lists
+	/// can then be fetched.
+	pub async fn approve(
+		&self,
+		id: &str,
+		by: Option<&str>,
+		artifacts: &ArtifactStore,
+	) -> Result<QuarantineEntry, QuarantineError> {
+		let entry = self.pending(id)?;
+		let store = self.store.as_ref().ok_or(QuarantineError::NotFound)?;
+		for artifact in &entry.artifacts {
+			artifacts
+				.copy_from(store, &artifact.artifact_id)
+				.await
+				.map_err(QuarantineError::Store)?;
+		}
+		self.decide(id, QuarantineState::Approved, by)
+	}
+
+	/// Delete the entry's held artifacts.
+	pub async fn reject(&self, id: &str, by: Option<&str>) -> Result<QuarantineEntry, QuarantineError> {
+		let entry = self.pending(id)?;
+		let store = self.store.as_ref().ok_or(QuarantineError::NotFound)?;
+		for artifact in &entry.artifacts {
+			// Another entry may hold the same contents.
+			let shared = self.entries.lock().unwrap().values().any(|other| {
+				other.id != id
+					&& other.state == QuarantineState::Pending
+					&& other.artifacts.iter().any(|a| a.artifact_id == artifact.artifact_id)
+			});
+			if !shared {
+				store.delete(&artifact.artifact_id).await.map_err(QuarantineError::Store)?;
+			}
+		}
+		self.decide(id, QuarantineState::Rejected, by)
+	}
+
+	fn pending(&self, id: &str) -> Result<QuarantineEntry, QuarantineError> {
+		let entry = self.get(id).ok_or(QuarantineError::NotFound)?;
+		match entry.state {
+			QuarantineState::Pending => Ok(entry),
+			state => Err(QuarantineError::Decided(state)),
+		}
+	}
+
+	fn decide(&self, id: &str, state: QuarantineState, by: Option<&str>) -> Result<QuarantineEntry, QuarantineError> {
+		let mut entries = self.entries.lock().unwrap();
+		let entry = entries.get_mut(id).ok_or(QuarantineError::NotFound)?;
+		if entry.state != QuarantineState::Pending {
+			return Err(QuarantineError::Decided(entry.state));
+		}
+		entry.state = state;
+		entry.decided_at_ms = Some(now_ms());
+		entry.decided_by = by.map(str::to_string);
+		info!(
+			execution_id = id,
+			state = state.as_str(),
+			by = by.unwrap_or("anonymous"),
+			message = "Quarantine decided"
+		);
+		Ok(entry.clone())
+	}
+}
+
+/// The lower of a tool's own limit and a cap, either of which may be unset.
+fn lower<T: Ord + Copy>(own: Option<T>, cap: Option<T>) -> Option<T> {
+	match (own, cap) {
+		(Some(own), Some(cap)) => Some(own.min(cap)),
+		(own, cap) => cap.or(own),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn held_artifacts_cannot_be_fetched_until_approved() {
+		let dir = std::env::temp_dir().join(format!("quarantine-{}", uuid::Uuid::new_v4()));
+		let artifacts_config = ArtifactsConfig {
+			dir: dir.join("artifacts"),
+			..Default::default()
+		};
+		let artifacts = ArtifactStore::new(&artifacts_config).unwrap();
+		let quarantine = Quarantine::new(
+			&QuarantineConfig {
+				enabled: true,
+				dir: dir.join("quarantine"),
+				..Default::default()
+			},
+			&artifacts_config,
+		)
+		.unwrap();
+		let caller = Caller::new(None, None, "http");
+		let artifact_id = artifacts.store_bytes(b"exfiltrated".to_vec(), &caller).await.unwrap();
+		let mut result = ToolResult {
+			status: "success".to_string(),
+			artifacts: vec![Artifact {
+				artifact_id: artifact_id.clone(),
+				path: "out.txt".to_string(),
+				size_bytes: 11,
+			}],
+			..Default::default()
+		};
+		let reasons = vec![QuarantineReason::UnknownCaller { subject: None }];
+
+		let entry = quarantine.hold("exec", "python", &caller, &mut result, reasons, &artifacts).await;
+		assert_eq!(entry.artifacts.len(), 1);
+		assert!(result.artifacts.is_empty());
+		assert!(artifacts.get(&artifact_id).await.unwrap().is_none());
+
+		assert!(quarantine.approve("exec", Some("admin"), &artifacts).await.is_ok());
+		assert!(artifacts.get(&artifact_id).await.unwrap().is_some());
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
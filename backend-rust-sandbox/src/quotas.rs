@@ -0,0 +1,531 @@
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::artifacts::Artifact;
+use crate::audit::Caller;
+use crate::clock::now_ms;
+use crate::cost::{Consumption, CostRates};
+use crate::history::{DailyUsage, History, TenantArtifact};
+use crate::metrics::METRICS;
+use crate::registry::ToolLimits;
+use crate::shared_limits::{Recorded, SharedLimits};
+
+const SECS_PER_DAY: u64 = 24 * 3600;
+
+/// What one tenant may use; `None` leaves that resource unlimited. Daily
+/// quotas start over at midnight UTC, budgets on the first of the month.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaLimits {
+	/// Executions started per day.
+	pub executions_per_day: Option<u64>,
+	/// CPU time per day, in seconds.
+	pub cpu_seconds_per_day: Option<u64>,
+	/// Bytes of artifacts the tenant's executions keep in the artifact store.
+	pub storage_bytes: Option<u64>,
+	/// Spend per month at `quotas.rates`. Executions are turned away once
+	/// the spend so far and their estimated cost would go past it.
+	pub monthly_budget: Option<f64>,
+	/// Spend per month past which a warning is logged and counted, once a
+	/// month.
+	pub monthly_budget_soft: Option<f64>,
+}
+
+impl QuotaLimits {
+	fn is_unlimited(&self) -> bool {
+		*self == Self::default()
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+	/// Quotas for tenants without their own entry.
+	pub default: QuotaLimits,
+	/// Per-tenant quotas, replacing the default as a whole.
+	pub tenants: BTreeMap<String, QuotaLimits>,
+	/// What executions cost, for budgets and spend.
+	pub rates: CostRates,
+}
+
+impl QuotaConfig {
+	fn limits_for(&self, tenant: &str) -> QuotaLimits {
+		self.tenants.get(tenant).copied().unwrap_or(self.default)
+	}
+
+	fn enabled(&self) -> bool {
+		!self.default.is_unlimited() || self.tenants.values().any(|l| !l.is_unlimited()) || !self.rates.is_free()
+	}
+
+	/// Problems with the quotas, named by their config key.
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		let limits = std::iter::once(("quotas.default".to_string(), &self.default))
+			.chain(self.tenants.iter().map(|(tenant, l)| (format!("quotas.tenants.{tenant}"), l)));
+		for (key, limits) in limits {
+			let values = [
+				("executions_per_day", limits.executions_per_day),
+				("cpu_seconds_per_day", limits.cpu_seconds_per_day),
+				("storage_bytes", limits.storage_bytes),
+			];
+			for (name, value) in values {
+				if value == Some(0) {
+					errors.push(format!("{key}.{name} must be positive; leave it out for no limit"));
+				}
+			}
+			let budgets = [
+				("monthly_budget", limits.monthly_budget),
+				("monthly_budget_soft", limits.monthly_budget_soft),
+			];
+			for (name, value) in budgets {
+				if value.is_some_and(|v| !v.is_finite() || v <= 0.0) {
+					errors.push(format!("{key}.{name} must be positive; leave it out for no limit"));
+				}
+			}
+			if let (Some(soft), Some(hard)) = (limits.monthly_budget_soft, limits.monthly_budget) {
+				if soft >= hard {
+					errors.push(format!("{key}.monthly_budget_soft must be below monthly_budget"));
+				}
+			}
+			if (limits.monthly_budget.is_some() || limits.monthly_budget_soft.is_some()) && self.rates.is_free() {
+				errors.push(format!("{key} sets a budget but quotas.rates prices nothing"));
+			}
+		}
+		errors.extend(self.rates.validate());
+		errors
+	}
+}
+
+/// Returned when a tenant has used up one of its quotas.
+#[derive(Debug, Clone)]
+pub struct QuotaExceeded {
+	pub tenant: String,
+	/// `executions_per_day`, `cpu_seconds_per_day` or `storage_bytes`.
+	pub quota: &'static str,
+	pub limit: u64,
+	pub used: u64,
+	/// Until the daily quotas start over; `None` for storage, which frees up
+	/// as artifacts expire.
+	pub retry_after_secs: Option<u64>,
+}
+
+impl QuotaExceeded {
+	pub fn message(&self) -> String {
+		let mut message = format!(
+			"tenant {:?} has used its {} quota ({} of {})",
+			self.tenant, self.quota, self.used, self.limit
+		);
+		if let Some(secs) = self.retry_after_secs {
+			message.push_str(&format!("; it resets in {secs}s"));
+		}
+		message
+	}
+}
+
+/// Returned when an execution would take a tenant past its monthly budget.
+#[derive(Debug, Clone)]
+pub struct BudgetExceeded {
+	pub tenant: String,
+	pub budget: f64,
+	pub spent: f64,
+	/// The most the execution could cost.
+	pub estimate: f64,
+	pub currency: String,
+	/// Until the budget starts over.
+	pub retry_after_secs: u64,
+}
+
+impl BudgetExceeded {
+	pub fn message(&self) -> String {
+		let spent = format!(
+			"tenant {:?} has spent {:.2} of its monthly budget of {:.2} {}",
+			self.tenant, self.spent, self.budget, self.currency
+		);
+		let why = match self.spent >= self.budget {
+			true => String::new(),
+			false => format!(", and the execution could cost up to {:.2}", self.estimate),
+		};
+		format!("{spent}{why}; it resets in {}s", self.retry_after_secs)
+	}
+}
+
+/// One tenant's usage against its quotas, as `GET /api/v1/usage` returns it.
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantUsage {
+	pub tenant: String,
+	/// The UTC date the daily figures are for.
+	pub day: String,
+	pub executions: u64,
+	pub cpu_seconds: f64,
+	pub storage_bytes: u64,
+	/// The UTC month (`YYYY-MM`) the spend is for.
+	pub month: String,
+	/// What the month's executions cost at `quotas.rates`.
+	pub spend: f64,
+	pub currency: String,
+	pub quota: QuotaLimits,
+}
+
+/// Filter of `GET /api/v1/usage`.
+#[derive(Debug, Default, Deserialize)]
+pub struct UsageQuery {
+	/// Another tenant than the caller's own; admins only.
+	pub tenant: Option<String>,
+}
+
+#[derive(Default)]
+struct Account {
+	day: String,
+	executions: u64,
+	cpu_ms: u64,
+	month: String,
+	spend: f64,
+	/// Whether the month's spend has been warned about: past the soft
+	/// budget, and past the budget.
+	warned: (bool, bool),
+	/// artifact id -> (size in bytes, when it was last stored in ms)
+	artifacts: HashMap<String, (u64, u64)>,
+}
+
+impl Account {
+	/// Start the daily figures over once `day` has moved on, and the monthly
+	/// ones once its month has.
+	fn roll_over(&mut self, day: &str) {
+		if self.day != day {
+			self.day = day.to_string();
+			self.executions = 0;
+			self.cpu_ms = 0;
+		}
+		let month = month_of(day);
+		if self.month != month {
+			self.month = month.to_string();
+			self.spend = 0.0;
+			self.warned = (false, false);
+		}
+	}
+
+	/// Bytes of the artifacts that have not expired by `cutoff_ms`.
+	fn storage_bytes(&mut self, cutoff_ms: u64) -> u64 {
+		self.artifacts.retain(|_, (_, stored_at_ms)| *stored_at_ms >= cutoff_ms);
+		self.artifacts.values().map(|(size, _)| size).sum()
+	}
+}
+
+/// Per-tenant usage accounting and quota enforcement, shared by every front
+/// end.
+///
+/// Usage is accounted to the caller's tenant (see `auth.tenants` and the JWT
+/// `tenant` claim), or to its subject when it has none; unauthenticated
+/// callers are neither accounted nor limited. Counters live in memory and are
+/// written through to the history database, from which they are loaded at
+/// startup. With `[shared_limits]` configured, checks go by the usage every
+/// replica has recorded in Redis, and by this node's own while Redis does not
+/// answer.
+pub struct Quotas {
+	config: RwLock<QuotaConfig>,
+	/// How long the artifact store keeps an artifact.
+	artifact_ttl: Duration,
+	history: Option<Arc<History>>,
+	shared: Option<Arc<SharedLimits>>,
+	accounts: Mutex<HashMap<String, Account>>,
+}
+
+impl Quotas {
+	/// Load today's usage and the unexpired artifacts from the history, if
+	/// one is configured.
+	pub async fn load(
+		config: QuotaConfig,
+		artifact_ttl: Duration,
+		history: Option<Arc<History>>,
+		shared: Option<Arc<SharedLimits>>,
+	) -> Result<Arc<Self>, String> {
+		let mut accounts: HashMap<String, Account> = HashMap::new();
+		let day = today();
+		match &history {
+			Some(history) => {
+				for usage in history.usage_on(&day).await? {
+					let account = accounts.entry(usage.tenant).or_default();
+					account.day = usage.day;
+					account.executions = usage.executions;
+					account.cpu_ms = usage.cpu_ms;
+				}
+				for (tenant, spend) in history.spend_since(&format!("{}-01", month_of(&day))).await? {
+					let limits = config.limits_for(&tenant);
+					let account = accounts.entry(tenant).or_default();
+					account.month = month_of(&day).to_string();
+					account.spend = spend;
+					// Warned about before the restart, or about to be.
+					account.warned = (
+						limits.monthly_budget_soft.is_some_and(|soft| spend >= soft),
+						limits.monthly_budget.is_some_and(|hard| spend >= hard),
+					);
+				}
+				let cutoff_ms = now_ms().saturating_sub(artifact_ttl.as_millis() as u64);
+				for artifact in history.tenant_artifacts(cutoff_ms).await? {
+					accounts
+						.entry(artifact.tenant)
+						.or_default()
+						.artifacts
+						.insert(artifact.artifact_id, (artifact.size_bytes, artifact.stored_at_ms));
+				}
+			}
+			None if config.enabled() && shared.is_none() => {
+				warn!(message = "Quotas are set but history.database_url is not; usage starts over on every restart");
+			}
+			None => {}
+		}
+		Ok(Arc::new(Self {
+			config: RwLock::new(config),
+			artifact_ttl,
+			history,
+			shared,
+			accounts: Mutex::new(accounts),
+		}))
+	}
+
+	/// Apply new quotas; usage so far counts against them.
+	pub fn set_config(&self, config: QuotaConfig) {
+		*self.config.write().unwrap() = config;
+	}
+
+	/// Turn the caller away if its tenant has used up any of its quotas.
+	/// Executions already running are not counted until they finish, so
+	/// concurrent ones can take a tenant past its daily quotas.
+	pub async fn check(&self, caller: &Caller) -> Result<(), QuotaExceeded> {
+		let Some(tenant) = tenant_of(caller) else {
+			return Ok(());
+		};
+		let limits = self.config.read().unwrap().limits_for(tenant);
+		if limits.is_unlimited() {
+			return Ok(());
+		}
+		let usage = self.usage(tenant).await;
+		let retry_after_secs = Some(SECS_PER_DAY - now_ms() / 1000 % SECS_PER_DAY);
+		let checks = [
+			("executions_per_day", limits.executions_per_day, usage.executions, retry_after_secs),
+			("cpu_seconds_per_day", limits.cpu_seconds_per_day, usage.cpu_seconds as u64, retry_after_secs),
+			("storage_bytes", limits.storage_bytes, usage.storage_bytes, None),
+		];
+		for (quota, limit, used, retry_after_secs) in checks {
+			if let Some(limit) = limit.filter(|limit| used >= *limit) {
+				METRICS.quota_exceeded.with_label_values(&[quota]).inc();
+				return Err(QuotaExceeded {
+					tenant: tenant.to_string(),
+					quota,
+					limit,
+					used,
+					retry_after_secs,
+				});
+			}
+		}
+		Ok(())
+	}
+
+	/// Turn the caller away if an execution that could cost up to
+	/// `estimate` would take its tenant past its monthly budget.
+	pub async fn check_budget(&self, caller: &Caller, estimate: f64) -> Result<(), BudgetExceeded> {
+		let Some(tenant) = tenant_of(caller) else {
+			return Ok(());
+		};
+		let (budget, currency) = {
+			let config = self.config.read().unwrap();
+			(config.limits_for(tenant).monthly_budget, config.rates.currency.clone())
+		};
+		let Some(budget) = budget else {
+			return Ok(());
+		};
+		let spent = self.usage(tenant).await.spend;
+		if spent < budget && spent + estimate <= budget {
+			return Ok(());
+		}
+		METRICS.quota_exceeded.with_label_values(&["monthly_budget"]).inc();
+		Err(BudgetExceeded {
+			tenant: tenant.to_string(),
+			budget,
+			spent,
+			estimate,
+			currency,
+			retry_after_secs: secs_to_next_month(),
+		})
+	}
+
+	/// At most what an attempt of a tool with `limits` and `timeout` could
+	/// cost; `None` when nothing is priced.
+	pub fn estimate(&self, limits: &ToolLimits, timeout: Duration) -> Option<f64> {
+		let config = self.config.read().unwrap();
+		(!config.rates.is_free()).then(|| config.rates.estimate(limits, timeout))
+	}
+
+	/// Account one finished execution: its CPU time, its cost and the
+	/// artifacts it stored. Returns the cost, `None` when nothing is priced.
+	pub fn record(&self, caller: &Caller, used: &Consumption, artifacts: &[Artifact]) -> Option<f64> {
+		let (cost, limits) = {
+			let config = self.config.read().unwrap();
+			let cost = (!config.rates.is_free()).then(|| config.rates.price(used));
+			(cost, tenant_of(caller).map(|tenant| config.limits_for(tenant)))
+		};
+		let (Some(tenant), Some(limits)) = (tenant_of(caller), limits) else {
+			return cost;
+		};
+		let day = today();
+		let now_ms = now_ms();
+		let cpu_ms = used.cpu.as_millis() as u64;
+		{
+			let mut accounts = self.accounts.lock().unwrap();
+			let account = accounts.entry(tenant.to_string()).or_default();
+			account.roll_over(&day);
+			account.executions += 1;
+			account.cpu_ms += cpu_ms;
+			account.spend += cost.unwrap_or_default();
+			for artifact in artifacts {
+				account
+					.artifacts
+					.insert(artifact.artifact_id.clone(), (artifact.size_bytes, now_ms));
+			}
+			if self.shared.is_none() {
+				warn_of_spend(tenant, account, &limits);
+			}
+		}
+		if let Some(shared) = &self.shared {
+			shared.record(Recorded {
+				tenant: tenant.to_string(),
+				month: month_of(&day).to_string(),
+				day: day.clone(),
+				cpu_ms,
+				cost: cost.unwrap_or_default(),
+				artifacts: artifacts.iter().map(|a| (a.artifact_id.clone(), a.size_bytes)).collect(),
+				stored_at_ms: now_ms,
+				artifact_ttl: self.artifact_ttl,
+				budgets: [("soft", limits.monthly_budget_soft), ("hard", limits.monthly_budget)],
+			});
+		}
+		let Some(history) = &self.history else {
+			return cost;
+		};
+		history.record_usage(DailyUsage {
+			tenant: tenant.to_string(),
+			day,
+			executions: 1,
+			cpu_ms,
+			cost: cost.unwrap_or_default(),
+		});
+		for artifact in artifacts {
+			history.record_tenant_artifact(TenantArtifact {
+				tenant: tenant.to_string(),
+				artifact_id: artifact.artifact_id.clone(),
+				size_bytes: artifact.size_bytes,
+				stored_at_ms: now_ms,
+			});
+		}
+		cost
+	}
+
+	/// What `tenant` has used today, and its quotas.
+	pub async fn usage(&self, tenant: &str) -> TenantUsage {
+		let day = today();
+		let cutoff_ms = now_ms().saturating_sub(self.artifact_ttl.as_millis() as u64);
+		let (quota, currency) = {
+			let config = self.config.read().unwrap();
+			(config.limits_for(tenant), config.rates.currency.clone())
+		};
+		let local = {
+			let mut accounts = self.accounts.lock().unwrap();
+			match accounts.get_mut(tenant) {
+				Some(account) => {
+					account.roll_over(&day);
+					(account.executions, account.cpu_ms, account.storage_bytes(cutoff_ms), account.spend)
+				}
+				None => (0, 0, 0, 0.0),
+			}
+		};
+		let shared = match &self.shared {
+			Some(shared) => shared.usage(tenant, &day, month_of(&day), cutoff_ms).await,
+			None => None,
+		};
+		let (executions, cpu_ms, storage_bytes, spend) = match shared {
+			Some(usage) => (usage.executions, usage.cpu_ms, usage.storage_bytes, usage.spend),
+			None => local,
+		};
+		TenantUsage {
+			tenant: tenant.to_string(),
+			month: month_of(&day).to_string(),
+			day,
+			executions,
+			cpu_seconds: cpu_ms as f64 / 1000.0,
+			storage_bytes,
+			spend,
+			currency,
+			quota,
+		}
+	}
+
+	/// Usage of every tenant that has used anything or has quotas of its own,
+	/// by name.
+	pub async fn usage_all(&self) -> Vec<TenantUsage> {
+		let mut tenants: Vec<String> = self.accounts.lock().unwrap().keys().cloned().collect();
+		tenants.extend(self.config.read().unwrap().tenants.keys().cloned());
+		tenants.sort();
+		tenants.dedup();
+		futures::future::join_all(tenants.iter().map(|tenant| self.usage(tenant))).await
+	}
+}
+
+/// The name a caller's usage is accounted under.
+pub fn tenant_of(caller: &Caller) -> Option<&str> {
+	caller.tenant.as_deref().or(caller.subject.as_deref())
+}
+
+/// Log and count the first time in a month that `account` passes its soft
+/// budget and its budget.
+fn warn_of_spend(tenant: &str, account: &mut Account, limits: &QuotaLimits) {
+	let thresholds = [
+		("soft", limits.monthly_budget_soft, &mut account.warned.0),
+		("hard", limits.monthly_budget, &mut account.warned.1),
+	];
+	for (threshold, budget, warned) in thresholds {
+		let Some(budget) = budget.filter(|budget| account.spend >= *budget && !*warned) else {
+			continue;
+		};
+		*warned = true;
+		budget_crossed(tenant, threshold, budget, account.spend, &account.month);
+	}
+}
+
+/// Log and count a tenant passing one of its monthly budgets.
+pub(crate) fn budget_crossed(tenant: &str, threshold: &str, budget: f64, spend: f64, month: &str) {
+	METRICS.budget_thresholds.with_label_values(&[threshold]).inc();
+	warn!(
+		tenant = tenant,
+		threshold = threshold,
+		budget = budget,
+		spend = spend,
+		month = month,
+		message = "Tenant passed a monthly budget"
+	);
+}
+
+fn today() -> String {
+	chrono::Utc::now().format("%Y-%m-%d").to_string()
+}
+
+/// The `YYYY-MM` of a `YYYY-MM-DD` day.
+fn month_of(day: &str) -> &str {
+	day.get(..7).unwrap_or(day)
+}
+
+fn secs_to_next_month() -> u64 {
+	let now = chrono::Utc::now();
+	let (year, month) = match now.month() {
+		12 => (now.year() + 1, 1),
+		month => (now.year(), month + 1),
+	};
+	let next = chrono::NaiveDate::from_ymd_opt(year, month, 1)
+		.and_then(|date| date.and_hms_opt(0, 0, 0))
+		.expect("the first of a month is a valid date")
+		.and_utc();
+	(next - now).num_seconds().max(1) as u64
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use crate::audit::Caller;
+use crate::metrics::METRICS;
+use crate::registry::base_name;
+use crate::shared_limits::SharedLimits;
+
+/// Buckets are swept for idle ones once the table grows past this.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Token-bucket parameters: `capacity` requests in a burst, refilled at
+/// `refill_per_sec`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketConfig {
+	pub capacity: u32,
+	pub refill_per_sec: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+	/// Limit for tools without their own entry; `None` leaves them unlimited.
+	pub default: Option<BucketConfig>,
+	/// Per-tool limits, e.g. lower ones for expensive tools.
+	pub tools: BTreeMap<String, BucketConfig>,
+}
+
+impl RateLimitConfig {
+	fn limit_for(&self, tool_name: &str) -> Option<BucketConfig> {
+		self.tools.get(tool_name).copied().or(self.default)
+	}
+
+	/// Problems with the limits, named by their config key.
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		let limits = self
+			.default
+			.iter()
+			.map(|b| ("rate_limit.default".to_string(), b))
+			.chain(self.tools.iter().map(|(tool, b)| (format!("rate_limit.tools.{tool}"), b)));
+		for (key, bucket) in limits {
+			if bucket.capacity == 0 {
+				errors.push(format!("{key}.capacity must be positive"));
+			}
+			if !(bucket.refill_per_sec > 0.0 && bucket.refill_per_sec.is_finite()) {
+				errors.push(format!("{key}.refill_per_sec must be a positive number"));
+			}
+		}
+		errors
+	}
+}
+
+/// Returned when a caller has used up its bucket for a tool.
+#[derive(Debug, Clone)]
+pub struct RateLimited {
+	pub limit: BucketConfig,
+	pub retry_after_secs: u64,
+}
+
+impl RateLimited {
+	pub fn message(&self, tool_name: &str) -> String {
+		format!(
+			"rate limit for {tool_name:?} exceeded ({} requests, refilled at {}/s); retry in {}s",
+			self.limit.capacity, self.limit.refill_per_sec, self.retry_after_secs
+		)
+	}
+}
+
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+impl Bucket {
+	fn refill(&mut self, limit: BucketConfig, now: Instant) {
+		let elapsed = now.duration_since(self.updated).as_secs_f64();
+		self.tokens = (self.tokens + elapsed * limit.refill_per_sec).min(limit.capacity as f64);
+		self.updated = now;
+	}
+}
+
+/// Per-client, per-tool token buckets shared by the HTTP and gRPC front ends.
+///
+/// Clients are told apart by their authenticated subject (the API key's label
+/// or the JWT `sub`), or by IP address when authentication is off. With
+/// `[shared_limits]` configured the buckets live in Redis and hold across
+/// replicas; the local ones only decide while Redis does not answer.
+pub struct RateLimiter {
+	config: RwLock<RateLimitConfig>,
+	/// (client, tool) -> bucket
+	buckets: Mutex<HashMap<(String, String), Bucket>>,
+	shared: Option<Arc<SharedLimits>>,
+}
+
+impl RateLimiter {
+	pub fn new(config: RateLimitConfig, shared: Option<Arc<SharedLimits>>) -> Self {
+		Self {
+			config: RwLock::new(config),
+			buckets: Mutex::new(HashMap::new()),
+			shared,
+		}
+	}
+
+	/// Apply new limits. Existing buckets are dropped, so every client starts
+	/// again from a full bucket.
+	pub fn set_config(&self, config: RateLimitConfig) {
+		*self.config.write().unwrap() = config;
+		self.buckets.lock().unwrap().clear();
+		METRICS.rate_limit_buckets.set(0);
+	}
+
+	/// Take one token from the caller's bucket for `tool_name`, which all
+	/// versions of the tool share.
+	pub async fn check(&self, caller: &Caller, tool_name: &str) -> Result<(), RateLimited> {
+		let tool_name = base_name(tool_name);
+		let Some(limit) = self.config.read().unwrap().limit_for(tool_name) else {
+			return Ok(());
+		};
+		let client = client_key(caller);
+		let shared = match &self.shared {
+			Some(shared) => shared.take(&client, tool_name, limit).await,
+			None => None,
+		};
+		let taken = match shared {
+			Some(taken) => taken,
+			None => self.take_local(client, tool_name, limit),
+		};
+		taken.map_err(|retry_after_secs| {
+			METRICS.rate_limited.with_label_values(&[tool_name]).inc();
+			RateLimited {
+				limit,
+				retry_after_secs,
+			}
+		})
+	}
+
+	/// Take a token from this node's own bucket, or say how many seconds until
+	/// one is back.
+	fn take_local(&self, client: String, tool_name: &str, limit: BucketConfig) -> Result<(), u64> {
+		let now = Instant::now();
+		let mut buckets = self.buckets.lock().unwrap();
+		if buckets.len() >= SWEEP_THRESHOLD {
+			self.sweep(&mut buckets, now);
+		}
+		let bucket = buckets
+			.entry((client, tool_name.to_string()))
+			.or_insert_with(|| Bucket {
+				tokens: limit.capacity as f64,
+				updated: now,
+			});
+		bucket.refill(limit, now);
+
+		let allowed = bucket.tokens >= 1.0;
+		if allowed {
+			bucket.tokens -= 1.0;
+		}
+		let retry_after_secs = ((1.0 - bucket.tokens) / limit.refill_per_sec).ceil().max(1.0) as u64;
+		METRICS.rate_limit_buckets.set(buckets.len() as i64);
+		if allowed {
+			return Ok(());
+		}
+		Err(retry_after_secs)
+	}
+
+	/// Forget buckets that have refilled completely; they are recreated full.
+	fn sweep(&self, buckets: &mut HashMap<(String, String), Bucket>, now: Instant) {
+		let config = self.config.read().unwrap();
+		buckets.retain(|(_, tool), bucket| {
+			let Some(limit) = config.limit_for(tool) else {
+				return false;
+			};
+			bucket.refill(limit, now);
+			bucket.tokens < limit.capacity as f64
+		});
+	}
+}
+
+/// The identity a caller's buckets are kept under.
+pub fn client_key(caller: &Caller) -> String {
+	match (&caller.subject, caller.client_ip) {
+		(Some(subject), _) => subject.clone(),
+		(None, Some(ip)) => format!("ip:{}", ip.to_canonical()),
+		(None, None) => "unknown".to_string(),
+	}
+}
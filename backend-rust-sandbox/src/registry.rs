@@ -0,0 +1,798 @@
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use wasmtime::component::Component;
+
+use crate::artifacts::is_valid_artifact_pattern;
+use crate::egress::NetworkPolicy;
+use crate::retry::RetryPolicy;
+use crate::seccomp::{SeccompFilter, SeccompProfile};
+use crate::secrets::{is_valid_env_name, is_valid_env_pattern, is_valid_secret_name};
+use crate::signing::ToolVerifier;
+use crate::tool_executor::ExecutionBackend;
+use crate::tool_sdk;
+
+/// Per-tool resource limits. Unset fields fall back to the backend defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolLimits {
+	/// Default timeout for this tool; requests may ask for less or more, up
+	/// to the server maximum.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub timeout_ms: Option<u64>,
+	/// Container or cgroup memory limit, or WASM linear-memory cap.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub memory_mb: Option<u64>,
+	/// Container or cgroup CPU quota.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub cpus: Option<f64>,
+	/// Relative CPU share of a subprocess tool's cgroup (`cpu.weight`).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub cpu_weight: Option<u32>,
+	/// CPU time a subprocess tool may use before it is killed.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub cpu_time_ms: Option<u64>,
+	/// Container or cgroup process limit.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub pids_limit: Option<u64>,
+	/// WASM instruction budget.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub fuel: Option<u64>,
+	/// Disk space the tool's workspace may take up.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub disk_mb: Option<u64>,
+	/// GPUs a native or container tool needs; executions wait for that many
+	/// to be free and see only those.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub gpu: Option<u32>,
+}
+
+/// A declarative tool definition, one per file in the manifest directory.
+///
+/// ```toml
+/// name = "wordcount"
+/// description = "Count words in a string"
+/// backend = "wasm"
+/// command = ["wordcount.wasm"]
+///
+/// [limits]
+/// timeout_ms = 5000
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolManifest {
+	pub name: String,
+	#[serde(default)]
+	pub description: String,
+	/// Several manifests may define one tool, each with its own version;
+	/// requests then name `tool@1.2.0` or a range such as `tool@^1.2`. A
+	/// tool defined without one has a single, unversioned definition.
+	#[serde(default)]
+	pub version: Option<Version>,
+	/// Marks a version (or an unversioned tool) agents should move off,
+	/// saying why or what to use instead. Bare names and ranges prefer
+	/// versions without it.
+	#[serde(default)]
+	pub deprecated: Option<String>,
+	/// What changed in this version.
+	#[serde(default)]
+	pub changelog: Option<String>,
+	pub backend: ExecutionBackend,
+	/// native: program and leading arguments. wasm: the module path.
+	/// component: the component's path, filled in when it is loaded.
+	/// container: argv run inside the image (its entrypoint if empty).
+	/// firecracker: argv run inside the guest.
+	/// The JSON args object is always appended as the final argument.
+	#[serde(default)]
+	pub command: Vec<String>,
+	/// container: image to run; the configured default image if unset.
+	#[serde(default)]
+	pub image: Option<String>,
+	/// firecracker: the `executor.firecracker.classes` entry the VM boots;
+	/// `default` if unset.
+	pub vm_class: Option<String>,
+	/// container: host script mounted read-only and used as the entrypoint.
+	#[serde(default)]
+	pub script: Option<PathBuf>,
+	/// wasm: host directory exposed read-only to the guest as `/data`.
+	/// native: a directory the tool may read under Landlock.
+	#[serde(default)]
+	pub data_dir: Option<PathBuf>,
+	/// JSON Schema the args object must satisfy before the tool is run.
+	#[serde(default)]
+	pub args_schema: Option<Value>,
+	#[serde(default)]
+	pub limits: ToolLimits,
+	/// native: syscall filter applied to the tool's process; unfiltered if
+	/// unset.
+	#[serde(default)]
+	pub seccomp: Option<SeccompProfile>,
+	/// Outbound network access. Unset keeps the backend's default: full for
+	/// native tools, none for WASM, the configured network for containers.
+	#[serde(default)]
+	pub network: Option<NetworkPolicy>,
+	/// native, container: run the tool on a pseudo-terminal rather than
+	/// pipes, for programs that behave differently without one. Its stderr
+	/// then comes out with its stdout.
+	#[serde(default)]
+	pub pty: bool,
+	/// Host environment variables the tool may see, as names or `PREFIX*`.
+	/// Native tools also get `executor.env_allow`; nothing else is inherited.
+	#[serde(default)]
+	pub env_allow: Vec<String>,
+	/// Secrets injected into the tool's environment: variable name to secret
+	/// name, resolved by the configured provider for every execution.
+	#[serde(default)]
+	pub secrets: BTreeMap<String, String>,
+	/// Same args, same result: `ok` results may be served from the result
+	/// cache instead of running the tool again.
+	#[serde(default)]
+	pub idempotent: bool,
+	/// How long cached results stay valid; `cache.default_ttl_secs` if unset.
+	#[serde(default)]
+	pub cache_ttl_secs: Option<u64>,
+	/// Files the tool produces, as globs relative to its workspace, collected
+	/// into the artifact store after every run.
+	#[serde(default)]
+	pub artifacts: Vec<String>,
+	/// Run the tool again after transient failures; each run is final
+	/// without it, unless the request asks for retries.
+	#[serde(default)]
+	pub retry: Option<RetryPolicy>,
+	/// Destructive or otherwise dangerous: the tool runs only as a job that
+	/// an approver has let through, never straight from a request.
+	#[serde(default)]
+	pub requires_approval: bool,
+	/// File the manifest was loaded from; `None` for built-ins.
+	#[serde(skip)]
+	pub source: Option<PathBuf>,
+	/// `args_schema`, compiled once at load time.
+	#[serde(skip)]
+	validator: Option<Arc<jsonschema::Validator>>,
+	/// `seccomp`, compiled once at load time.
+	#[serde(skip)]
+	pub seccomp_filter: Option<SeccompFilter>,
+	/// component: the compiled component, shared by every execution of the
+	/// tools it declares.
+	#[serde(skip)]
+	pub component: Option<Component>,
+}
+
+/// One way in which a request's args violate the tool's `args_schema`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ArgError {
+	/// JSON pointer to the offending value (`""` for the args object itself).
+	pub path: String,
+	pub message: String,
+}
+
+/// What `GET /api/v1/tools` exposes about a tool. Host paths stay private.
+#[derive(Debug, Serialize)]
+pub struct ToolSummary<'a> {
+	pub name: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub version: Option<&'a Version>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub deprecated: Option<&'a str>,
+	pub description: &'a str,
+	pub backend: ExecutionBackend,
+	pub args_schema: Option<&'a Value>,
+	pub limits: &'a ToolLimits,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub network: Option<&'a NetworkPolicy>,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub idempotent: bool,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub retry: Option<&'a RetryPolicy>,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub requires_approval: bool,
+}
+
+impl ToolManifest {
+	pub fn summary(&self) -> ToolSummary<'_> {
+		ToolSummary {
+			name: &self.name,
+			version: self.version.as_ref(),
+			deprecated: self.deprecated.as_deref(),
+			description: &self.description,
+			backend: self.backend,
+			args_schema: self.args_schema.as_ref(),
+			limits: &self.limits,
+			network: self.network.as_ref(),
+			idempotent: self.idempotent,
+			retry: self.retry.as_ref(),
+			requires_approval: self.requires_approval,
+		}
+	}
+
+	/// The name that pins this definition: `name@version`, or the bare name
+	/// of an unversioned tool.
+	pub fn id(&self) -> String {
+		match &self.version {
+			Some(version) => format!("{}@{version}", self.name),
+			None => self.name.clone(),
+		}
+	}
+
+	/// Check `args` against the tool's schema. Tools without one accept anything.
+	pub fn validate_args(&self, args: &Value) -> Result<(), Vec<ArgError>> {
+		let Some(validator) = &self.validator else {
+			return Ok(());
+		};
+		let errors: Vec<ArgError> = validator
+			.iter_errors(args)
+			.map(|e| ArgError {
+				path: e.instance_path().to_string(),
+				message: e.to_string(),
+			})
+			.collect();
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(errors)
+		}
+	}
+
+	fn compile_schema(&mut self) -> Result<(), String> {
+		self.validator = match &self.args_schema {
+			Some(schema) if !schema.is_object() => {
+				return Err("args_schema must be a JSON Schema object".to_string())
+			}
+			Some(schema) => Some(Arc::new(
+				jsonschema::validator_for(schema).map_err(|e| format!("invalid args_schema: {e}"))?,
+			)),
+			None => None,
+		};
+		Ok(())
+	}
+
+	fn builtin(name: &str, description: &str, args_schema: Value) -> Self {
+		let mut manifest = Self {
+			name: name.to_string(),
+			description: description.to_string(),
+			version: None,
+			deprecated: None,
+			changelog: None,
+			backend: ExecutionBackend::Builtin,
+			command: Vec::new(),
+			image: None,
+			vm_class: None,
+			script: None,
+			data_dir: None,
+			args_schema: Some(args_schema),
+			limits: ToolLimits::default(),
+			seccomp: None,
+			network: None,
+			pty: false,
+			env_allow: Vec::new(),
+			secrets: BTreeMap::new(),
+			idempotent: false,
+			cache_ttl_secs: None,
+			artifacts: Vec::new(),
+			retry: None,
+			requires_approval: false,
+			source: None,
+			validator: None,
+			seccomp_filter: None,
+			component: None,
+		};
+		manifest
+			.compile_schema()
+			.expect("built-in args schemas are valid");
+		manifest
+	}
+
+	/// A tool declared by the component at `path`.
+	pub fn component(
+		name: &str,
+		description: &str,
+		args_schema: Option<Value>,
+		path: &Path,
+		component: Component,
+	) -> Result<Self, String> {
+		if !is_valid_tool_name(name) {
+			return Err(format!("invalid tool name {name:?}: use [A-Za-z0-9_-]"));
+		}
+		let mut manifest = Self::builtin(name, description, json!({}));
+		manifest.backend = ExecutionBackend::Component;
+		manifest.command = vec![path.to_string_lossy().to_string()];
+		manifest.args_schema = args_schema;
+		manifest.source = Some(path.to_path_buf());
+		manifest.component = Some(component);
+		manifest.compile_schema().map_err(|e| format!("tool {name:?}: {e}"))?;
+		Ok(manifest)
+	}
+
+	/// Resolve relative paths against the manifest's directory and check that
+	/// the definition is runnable on its backend.
+	fn resolve(&mut self, base: &Path) -> Result<(), String> {
+		if !is_valid_tool_name(&self.name) {
+			return Err(format!("invalid tool name {:?}: use [A-Za-z0-9_-]", self.name));
+		}
+		self.compile_schema()?;
+		if let Some(weight) = self.limits.cpu_weight {
+			if !(1..=10_000).contains(&weight) {
+				return Err(format!("limits.cpu_weight must be between 1 and 10000 (got {weight})"));
+			}
+		}
+		if self.limits.disk_mb == Some(0) {
+			return Err("limits.disk_mb must be positive".to_string());
+		}
+		if self.limits.gpu.is_some()
+			&& !matches!(self.backend, ExecutionBackend::Native | ExecutionBackend::Container)
+		{
+			return Err("limits.gpu applies to native and container tools only".to_string());
+		}
+		if self.pty && !self.backend.runs_on_terminal() {
+			return Err("`pty` applies to native and container tools only".to_string());
+		}
+		if let Some(profile) = &self.seccomp {
+			if self.backend != ExecutionBackend::Native {
+				return Err("`seccomp` applies to native tools only".to_string());
+			}
+			self.seccomp_filter = Some(profile.compile()?);
+		}
+		if let Some(pattern) = self.env_allow.iter().find(|p| !is_valid_env_pattern(p)) {
+			return Err(format!("env_allow: {pattern:?} is not a variable name or PREFIX* pattern"));
+		}
+		for (var, secret) in &self.secrets {
+			if !is_valid_env_name(var) {
+				return Err(format!("secrets: {var:?} is not a valid environment variable name"));
+			}
+			if !is_valid_secret_name(secret) {
+				return Err(format!("secrets.{var}: {secret:?} is not a valid secret name"));
+			}
+		}
+		if let Some(pattern) = self.artifacts.iter().find(|p| !is_valid_artifact_pattern(p)) {
+			return Err(format!("artifacts: {pattern:?} is not a glob relative to the workspace"));
+		}
+		if let Some(retry) = &self.retry {
+			retry.validate()?;
+		}
+		if self.cache_ttl_secs.is_some() && !self.idempotent {
+			return Err("cache_ttl_secs only applies to tools marked idempotent".to_string());
+		}
+		if let Some(network) = &mut self.network {
+			network.validate()?;
+			if self.backend == ExecutionBackend::Container && matches!(network, NetworkPolicy::Allowlist(_)) {
+				return Err("container tools take network = \"none\" or \"full\", not an allowlist".to_string());
+			}
+			if self.backend == ExecutionBackend::Firecracker && !matches!(network, NetworkPolicy::None) {
+				return Err("firecracker tools have no network; leave `network` unset or \"none\"".to_string());
+			}
+		}
+		if self.vm_class.is_some() && self.backend != ExecutionBackend::Firecracker {
+			return Err("`vm_class` applies to firecracker tools only".to_string());
+		}
+
+		match self.backend {
+			ExecutionBackend::Builtin => {
+				return Err("backend \"builtin\" is reserved for tools compiled into the sandbox".to_string())
+			}
+			ExecutionBackend::Component => {
+				return Err("backend \"component\" is for tools loaded from executor.component_dir".to_string())
+			}
+			ExecutionBackend::Native => {
+				let Some(program) = self.command.first_mut() else {
+					return Err("native tools need a non-empty `command`".to_string());
+				};
+				// Bare names are looked up on PATH; anything path-like is a file.
+				if program.contains('/') {
+					*program = resolve_existing(base, program)?;
+				}
+				if let Some(dir) = &self.data_dir {
+					self.data_dir = Some(resolve_existing(base, &dir.to_string_lossy())?.into());
+				}
+			}
+			ExecutionBackend::Wasm => {
+				let [module] = self.command.as_mut_slice() else {
+					return Err("wasm tools need `command = [\"<module>.wasm\"]`".to_string());
+				};
+				*module = resolve_existing(base, module)?;
+				if let Some(dir) = &self.data_dir {
+					self.data_dir = Some(resolve_existing(base, &dir.to_string_lossy())?.into());
+				}
+			}
+			ExecutionBackend::Container => {
+				if let Some(script) = &self.script {
+					self.script = Some(resolve_existing(base, &script.to_string_lossy())?.into());
+				}
+			}
+			// The program is a path inside the guest image, not on the host.
+			ExecutionBackend::Firecracker => {
+				if self.command.is_empty() {
+					return Err("firecracker tools need a non-empty `command`".to_string());
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+fn resolve_existing(base: &Path, path: &str) -> Result<String, String> {
+	let resolved = base.join(path);
+	if !resolved.exists() {
+		return Err(format!("{} does not exist", resolved.display()));
+	}
+	Ok(resolved.to_string_lossy().to_string())
+}
+
+/// Tool names end up in paths and container names, so keep them boring.
+pub fn is_valid_tool_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Every manifest problem found during a load, reported together.
+#[derive(Debug)]
+pub struct RegistryError {
+	pub errors: Vec<String>,
+}
+
+impl fmt::Display for RegistryError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} invalid tool manifest(s): {}", self.errors.len(), self.errors.join("; "))
+	}
+}
+
+impl std::error::Error for RegistryError {}
+
+/// The bare tool name of a requested `name@version` or `name@range`.
+pub fn base_name(requested: &str) -> &str {
+	requested.split_once('@').map_or(requested, |(name, _)| name)
+}
+
+/// All tools the sandbox can execute.
+#[derive(Debug, Clone)]
+pub struct ToolRegistry {
+	/// Each tool's definitions, oldest version first; an unversioned tool
+	/// has exactly one.
+	tools: BTreeMap<String, Vec<ToolManifest>>,
+}
+
+impl ToolRegistry {
+	/// Load the built-ins plus every `*.toml`, `*.yaml` and `*.yml` manifest in
+	/// `dir`. A missing directory is not an error; a single bad manifest, or
+	/// one whose signature `verifier` rejects, fails the whole load.
+	pub fn load(dir: &Path, verifier: &ToolVerifier) -> Result<Self, RegistryError> {
+		let mut tools: BTreeMap<String, Vec<ToolManifest>> = builtin_tools()
+			.into_iter()
+			.map(|t| (t.name.clone(), vec![t]))
+			.collect();
+		let mut errors = Vec::new();
+
+		let entries = match std::fs::read_dir(dir) {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self { tools }),
+			Err(e) => {
+				return Err(RegistryError {
+					errors: vec![format!("{}: {e}", dir.display())],
+				})
+			}
+		};
+
+		let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+		paths.sort();
+		for path in paths {
+			let format = match path.extension().and_then(|e| e.to_str()) {
+				Some("toml") => ManifestFormat::Toml,
+				Some("yaml" | "yml") => ManifestFormat::Yaml,
+				_ => continue,
+			};
+			let manifest = match load_manifest(&path, format, verifier) {
+				Ok(manifest) => manifest,
+				Err(e) => {
+					errors.push(format!("{}: {e}", path.display()));
+					continue;
+				}
+			};
+			let versions = tools.entry(manifest.name.clone()).or_default();
+			// Versions of one tool may coexist; an unversioned definition
+			// stands alone.
+			let clash = versions
+				.iter()
+				.find(|t| t.version.is_none() || manifest.version.is_none() || t.version == manifest.version);
+			match clash {
+				Some(existing) => errors.push(format!(
+					"{}: tool {:?} is already defined by {}",
+					path.display(),
+					manifest.id(),
+					existing
+						.source
+						.as_ref()
+						.map(|p| p.display().to_string())
+						.unwrap_or_else(|| "the sandbox (built-in)".to_string())
+				)),
+				None => {
+					versions.push(manifest);
+					versions.sort_by(|a, b| a.version.cmp(&b.version));
+				}
+			}
+		}
+
+		if errors.is_empty() {
+			Ok(Self { tools })
+		} else {
+			Err(RegistryError { errors })
+		}
+	}
+
+	/// This registry plus the tools declared by components. A component tool
+	/// cannot share a name with any other tool.
+	pub fn with_components(mut self, components: Vec<ToolManifest>) -> Result<Self, RegistryError> {
+		let mut errors = Vec::new();
+		for tool in components {
+			let source = tool.source.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+			match self.tools.get(&tool.name).and_then(|versions| versions.first()) {
+				Some(existing) => errors.push(format!(
+					"{source}: tool {:?} is already defined by {}",
+					tool.name,
+					existing
+						.source
+						.as_ref()
+						.map(|p| p.display().to_string())
+						.unwrap_or_else(|| "the sandbox (built-in)".to_string())
+				)),
+				None => {
+					self.tools.insert(tool.name.clone(), vec![tool]);
+				}
+			}
+		}
+		if errors.is_empty() {
+			Ok(self)
+		} else {
+			Err(RegistryError { errors })
+		}
+	}
+
+	/// The definition a request for `requested` runs: `name` is the tool's
+	/// latest release, `name@1.2.0` exactly that version and `name@^1.2` the
+	/// latest release in the range, passing over deprecated versions while
+	/// others qualify. Pre-releases run when named exactly, through a range
+	/// that names one of theirs (`^2.0.0-beta`), or by bare name while there
+	/// is no release. Unversioned tools answer to their bare name only.
+	pub fn get(&self, requested: &str) -> Option<&ToolManifest> {
+		let (name, wanted) = match requested.split_once('@') {
+			Some((name, wanted)) => (name, Some(wanted.trim())),
+			None => (requested, None),
+		};
+		let versions = self.tools.get(name)?;
+		let candidates: Vec<&ToolManifest> = match wanted {
+			None => {
+				let released = |t: &&ToolManifest| t.version.as_ref().is_none_or(|v| v.pre.is_empty());
+				match versions.iter().any(|t| released(&t)) {
+					true => versions.iter().filter(released).collect(),
+					false => versions.iter().collect(),
+				}
+			}
+			Some(wanted) => match Version::parse(wanted) {
+				Ok(exact) => return versions.iter().find(|t| t.version.as_ref() == Some(&exact)),
+				Err(_) => {
+					let range = VersionReq::parse(wanted).ok()?;
+					versions
+						.iter()
+						.filter(|t| t.version.as_ref().is_some_and(|v| range.matches(v)))
+						.collect()
+				}
+			},
+		};
+		candidates
+			.iter()
+			.rev()
+			.find(|t| t.deprecated.is_none())
+			.or(candidates.last())
+			.copied()
+	}
+
+	/// Every definition of the tool `name`, oldest version first.
+	pub fn versions(&self, name: &str) -> &[ToolManifest] {
+		self.tools.get(name).map_or(&[], Vec::as_slice)
+	}
+
+	/// Each tool once, as its bare name resolves.
+	pub fn tools(&self) -> impl Iterator<Item = &ToolManifest> {
+		self.tools.keys().filter_map(|name| self.get(name))
+	}
+
+	/// Every definition of every tool.
+	pub fn all_versions(&self) -> impl Iterator<Item = &ToolManifest> {
+		self.tools.values().flatten()
+	}
+
+	pub fn len(&self) -> usize {
+		self.tools.len()
+	}
+
+	/// This registry less the tools named in `names`, every version of them.
+	pub fn without(&self, names: &BTreeSet<String>) -> Self {
+		let mut tools = self.tools.clone();
+		tools.retain(|name, _| !names.contains(name));
+		Self { tools }
+	}
+}
+
+enum ManifestFormat {
+	Toml,
+	Yaml,
+}
+
+fn load_manifest(path: &Path, format: ManifestFormat, verifier: &ToolVerifier) -> Result<ToolManifest, String> {
+	let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+	verifier.verify(path, &bytes)?;
+	let text = String::from_utf8(bytes).map_err(|e| e.to_string())?;
+	let mut manifest: ToolManifest = match format {
+		ManifestFormat::Toml => toml::from_str(&text).map_err(|e| e.to_string())?,
+		ManifestFormat::Yaml => serde_yaml::from_str(&text).map_err(|e| e.to_string())?,
+	};
+	let base = path.parent().unwrap_or_else(|| Path::new("."));
+	manifest.resolve(base)?;
+	manifest.source = Some(path.to_path_buf());
+	Ok(manifest)
+}
+
+/// The names of the tools compiled into the sandbox.
+pub fn builtin_names() -> Vec<String> {
+	builtin_tools().into_iter().map(|tool| tool.name).collect()
+}
+
+/// The tools compiled into the sandbox: those running code or queries on
+/// the executor's own runtimes, plus the [`Tool`](crate::tool_sdk::Tool)
+/// implementations.
+fn builtin_tools() -> Vec<ToolManifest> {
+	let compiled = tool_sdk::tools()
+		.into_values()
+		.map(|tool| ToolManifest::builtin(tool.name(), tool.description(), tool.schema()));
+	let builtins = vec![
+		ToolManifest::builtin(
+			"execute_code",
+			"Compile and run source code in the sandbox.",
+			json!({
+				"type": "object",
+				"properties": {
+					"language": { "enum": ["rust", "go", "python", "java", "javascript"] },
+					"code": { "type": "string" },
+				},
+				"required": ["code"],
+			}),
+		),
+		ToolManifest::builtin(
+			"python",
+			"Run a Python program; an uncaught exception is returned with its type, message, line and traceback.",
+			json!({
+				"type": "object",
+				"properties": {
+					"code": { "type": "string" },
+					"argv": { "type": "array", "items": { "type": "string" } },
+				},
+				"required": ["code"],
+			}),
+		),
+		ToolManifest::builtin(
+			"javascript",
+			"Run a JavaScript module; an uncaught exception is returned with its type, message, line and stack.",
+			json!({
+				"type": "object",
+				"properties": {
+					"code": { "type": "string" },
+					"argv": { "type": "array", "items": { "type": "string" } },
+				},
+				"required": ["code"],
+			}),
+		),
+		ToolManifest::builtin(
+			"shell",
+			"Run a command line; only commands allowed by the sandbox's shell policy may run.",
+			json!({
+				"type": "object",
+				"properties": { "command": { "type": "string" } },
+				"required": ["command"],
+			}),
+		),
+		ToolManifest::builtin(
+			"sql_query",
+			"Run a parameterized SQL query against a configured database; read-only unless the connection allows writes.",
+			json!({
+				"type": "object",
+				"properties": {
+					"connection": { "type": "string" },
+					"query": { "type": "string" },
+					"params": { "type": "array" },
+					"format": { "enum": ["json", "csv"] },
+					"max_rows": { "type": "integer", "minimum": 1 },
+				},
+				"required": ["query"],
+			}),
+		),
+		ToolManifest::builtin(
+			"http_request",
+			"Call an HTTP API on a host the sandbox allows; internal addresses are refused.",
+			json!({
+				"type": "object",
+				"properties": {
+					"method": { "type": "string" },
+					"url": { "type": "string" },
+					"headers": { "type": "object", "additionalProperties": { "type": "string" } },
+					"body": { "type": "string" },
+					"json": {},
+				},
+				"required": ["url"],
+			}),
+		),
+	];
+	builtins.into_iter().chain(compiled).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A registry with one tool, `wordcount`, in `versions`; a version ending
+	/// in `!` is deprecated.
+	fn wordcount(versions: &[&str]) -> ToolRegistry {
+		let versions = versions
+			.iter()
+			.map(|version| {
+				let mut tool = ToolManifest::builtin("wordcount", "", json!({}));
+				tool.version = Some(Version::parse(version.trim_end_matches('!')).unwrap());
+				tool.deprecated = version.ends_with('!').then(|| "use another".to_string());
+				tool
+			})
+			.collect();
+		ToolRegistry {
+			tools: BTreeMap::from([("wordcount".to_string(), versions)]),
+		}
+	}
+
+	fn resolve(registry: &ToolRegistry, requested: &str) -> Option<String> {
+		registry.get(requested).map(|tool| tool.version.as_ref().unwrap().to_string())
+	}
+
+	#[test]
+	fn highest_matching_version_wins() {
+		let registry = wordcount(&["1.0.0", "1.2.0", "1.10.0", "2.0.0", "2.1.3"]);
+		assert_eq!(resolve(&registry, "wordcount").as_deref(), Some("2.1.3"));
+		assert_eq!(resolve(&registry, "wordcount@^1").as_deref(), Some("1.10.0"));
+		assert_eq!(resolve(&registry, "wordcount@~1.2").as_deref(), Some("1.2.0"));
+		assert_eq!(resolve(&registry, "wordcount@>=1.1, <2").as_deref(), Some("1.10.0"));
+		assert_eq!(resolve(&registry, "wordcount@ ^2.0 ").as_deref(), Some("2.1.3"));
+		assert_eq!(resolve(&registry, "wordcount@1.2.0").as_deref(), Some("1.2.0"));
+	}
+
+	#[test]
+	fn deprecated_versions_only_when_nothing_else_matches() {
+		let registry = wordcount(&["1.0.0", "1.1.0!", "2.0.0!"]);
+		assert_eq!(resolve(&registry, "wordcount").as_deref(), Some("1.0.0"));
+		assert_eq!(resolve(&registry, "wordcount@^1").as_deref(), Some("1.0.0"));
+		assert_eq!(resolve(&registry, "wordcount@^2").as_deref(), Some("2.0.0"));
+		assert_eq!(resolve(&registry, "wordcount@1.1.0").as_deref(), Some("1.1.0"));
+	}
+
+	#[test]
+	fn pre_releases_are_excluded() {
+		let registry = wordcount(&["1.0.0", "1.5.0", "2.0.0-beta.1", "2.0.0-rc.1"]);
+		assert_eq!(resolve(&registry, "wordcount").as_deref(), Some("1.5.0"));
+		assert_eq!(resolve(&registry, "wordcount@*").as_deref(), Some("1.5.0"));
+		assert_eq!(resolve(&registry, "wordcount@>=1").as_deref(), Some("1.5.0"));
+		assert_eq!(resolve(&registry, "wordcount@^2"), None);
+		// Unless asked for.
+		assert_eq!(resolve(&registry, "wordcount@2.0.0-beta.1").as_deref(), Some("2.0.0-beta.1"));
+		assert_eq!(resolve(&registry, "wordcount@^2.0.0-beta").as_deref(), Some("2.0.0-rc.1"));
+		// Or there is nothing else.
+		let registry = wordcount(&["0.1.0-alpha", "0.1.0-beta"]);
+		assert_eq!(resolve(&registry, "wordcount").as_deref(), Some("0.1.0-beta"));
+	}
+
+	#[test]
+	fn unsatisfiable_requirements_fail() {
+		let registry = wordcount(&["1.0.0", "1.2.0"]);
+		for requested in ["wordcount@^3", "wordcount@1.1.0", "wordcount@>1.2.0", "wordcount@not-a-range", "other"] {
+			assert_eq!(resolve(&registry, requested), None, "{requested}");
+		}
+	}
+}
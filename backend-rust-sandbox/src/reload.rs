@@ -0,0 +1,163 @@
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::authz::{load_policy, Authorizer};
+use crate::clock::now_ms;
+use crate::config::Settings;
+use crate::policy::{PolicyConfig, PolicyEngine, PolicyLanguage};
+use crate::ratelimit::RateLimiter;
+use crate::registry::ToolRegistry;
+use crate::tool_executor::Executor;
+
+/// The configuration snapshot currently in effect.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadStatus {
+	/// 1 at startup, bumped by every successful reload.
+	pub version: u64,
+	pub loaded_at_ms: u64,
+	pub tools: usize,
+	/// `None` when no RBAC policy file is configured.
+	pub rbac_roles: Option<usize>,
+	/// `None` when no execution policy is configured.
+	pub execution_policy: Option<PolicyLanguage>,
+	/// Tools with a rate limit of their own.
+	pub rate_limited_tools: usize,
+}
+
+#[derive(Debug)]
+pub struct ReloadError {
+	pub errors: Vec<String>,
+}
+
+impl fmt::Display for ReloadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "reload rejected with {} error(s): {}", self.errors.len(), self.errors.join("; "))
+	}
+}
+
+impl std::error::Error for ReloadError {}
+
+/// Re-reads the tool manifests and components, the RBAC and execution policy
+/// files and the rate limits and quotas in the settings on SIGHUP or
+/// `POST /api/v1/admin/reload`. Other settings (ports, backends, the file
+/// paths themselves) need a restart.
+///
+/// Everything is loaded and validated before anything is swapped, so a reload
+/// with any error leaves the running snapshot untouched. Executions already
+/// running keep the tool definitions they started with.
+pub struct Reloader {
+	executor: Arc<Executor>,
+	authz: Arc<Authorizer>,
+	ratelimit: Arc<RateLimiter>,
+	manifest_dir: PathBuf,
+	policy_file: Option<PathBuf>,
+	execution_policy: PolicyConfig,
+	/// Held for the whole reload so concurrent requests apply in order.
+	current: Mutex<Snapshot>,
+}
+
+struct Snapshot {
+	status: ReloadStatus,
+	/// The startup settings with reloaded sections replaced.
+	settings: Arc<Settings>,
+}
+
+impl Reloader {
+	pub fn new(
+		settings: Arc<Settings>,
+		executor: Arc<Executor>,
+		authz: Arc<Authorizer>,
+		ratelimit: Arc<RateLimiter>,
+	) -> Self {
+		let status = ReloadStatus {
+			version: 1,
+			loaded_at_ms: now_ms(),
+			tools: executor.registry().len(),
+			rbac_roles: authz.policy().map(|p| p.roles.len()),
+			execution_policy: authz.engine().map(|e| e.language()),
+			rate_limited_tools: settings.rate_limit.tools.len(),
+		};
+		Self {
+			executor,
+			authz,
+			ratelimit,
+			manifest_dir: settings.executor.manifest_dir.clone(),
+			policy_file: settings.rbac.policy_file.clone(),
+			execution_policy: settings.policy.clone(),
+			current: Mutex::new(Snapshot { status, settings }),
+		}
+	}
+
+	pub fn status(&self) -> ReloadStatus {
+		self.current.lock().unwrap().status.clone()
+	}
+
+	/// The settings in effect.
+	pub fn settings(&self) -> Arc<Settings> {
+		self.current.lock().unwrap().settings.clone()
+	}
+
+	pub fn reload(&self) -> Result<ReloadStatus, ReloadError> {
+		let mut current = self.current.lock().unwrap();
+		let mut errors = Vec::new();
+
+		let limits = Settings::load()
+			.map(|s| (s.rate_limit, s.quotas))
+			.map_err(|e| errors.extend(e.errors))
+			.ok();
+
+		let registry = ToolRegistry::load(&self.manifest_dir, self.executor.verifier())
+			.and_then(|registry| self.executor.with_components(registry))
+			.map_err(|e| errors.extend(e.errors))
+			.ok();
+		let policy = match &self.policy_file {
+			Some(path) => load_policy(path).map(Some).map_err(|e| errors.push(e)).ok(),
+			None => Some(None),
+		};
+		let engine = PolicyEngine::load(&self.execution_policy).map_err(|e| errors.push(e)).ok();
+
+		let (Some(registry), Some(policy), Some(engine), Some((rate_limit, quotas))) = (registry, policy, engine, limits)
+		else {
+			warn!(version = current.status.version, errors = ?errors, message = "Reload rejected; keeping current configuration");
+			return Err(ReloadError { errors });
+		};
+
+		let next = ReloadStatus {
+			version: current.status.version + 1,
+			loaded_at_ms: now_ms(),
+			tools: registry.len(),
+			rbac_roles: policy.as_ref().map(|p| p.roles.len()),
+			execution_policy: engine.as_ref().map(|e| e.language()),
+			rate_limited_tools: rate_limit.tools.len(),
+		};
+		self.executor.set_registry(registry);
+		if let Some(policy) = policy {
+			self.authz
+				.set_policy(policy)
+				.expect("policy was validated when loaded");
+		}
+		// Decisions the old policy cached go with it.
+		self.authz.set_engine(engine);
+		self.ratelimit.set_config(rate_limit.clone());
+		self.executor.quotas().set_config(quotas.clone());
+		let mut settings = (*current.settings).clone();
+		settings.rate_limit = rate_limit;
+		settings.quotas = quotas;
+		info!(
+			version = next.version,
+			tools = next.tools,
+			rbac_roles = next.rbac_roles,
+			execution_policy = ?next.execution_policy,
+			rate_limited_tools = next.rate_limited_tools,
+			message = "Reloaded configuration"
+		);
+		*current = Snapshot {
+			status: next.clone(),
+			settings: Arc::new(settings),
+		};
+		Ok(next)
+	}
+}
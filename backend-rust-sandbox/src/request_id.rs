@@ -0,0 +1,100 @@
+use axum::http::{self, HeaderMap, HeaderValue};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::Span;
+
+/// Names the request id in HTTP headers and gRPC metadata, both ways.
+pub const HEADER: &str = "x-request-id";
+/// Longest client-chosen id kept; anything longer is replaced.
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+	static CURRENT: String;
+}
+
+/// The id of the request being handled, if this task is handling one.
+pub fn current() -> Option<String> {
+	CURRENT.try_with(Clone::clone).ok()
+}
+
+/// Run `future` as the handling of the request `request_id`.
+pub async fn scope<F: Future>(request_id: String, future: F) -> F::Output {
+	CURRENT.scope(request_id, future).await
+}
+
+/// The request's id: the client's `X-Request-Id`, if it sent a usable one,
+/// else a new UUID, which is put in `headers` for the handlers to find.
+/// Ids that could garble a log line are replaced.
+pub fn ensure(headers: &mut HeaderMap) -> String {
+	let sent = headers
+		.get(HEADER)
+		.and_then(|v| v.to_str().ok())
+		.filter(|id| is_usable(id));
+	if let Some(id) = sent {
+		return id.to_string();
+	}
+	let id = uuid::Uuid::new_v4().to_string();
+	headers.insert(HEADER, HeaderValue::from_str(&id).expect("a UUID is a header value"));
+	id
+}
+
+fn is_usable(id: &str) -> bool {
+	!id.is_empty()
+		&& id.len() <= MAX_LEN
+		&& id.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.:/".contains(&b))
+}
+
+/// Echo the id in a response.
+pub fn set(headers: &mut HeaderMap, request_id: &str) {
+	if let Ok(value) = HeaderValue::from_str(request_id) {
+		headers.insert(HEADER, value);
+	}
+}
+
+/// tonic layer doing for gRPC calls what [`crate::telemetry::trace_http`]
+/// does for HTTP requests: settle the call's id, record it on its span, and
+/// return it in the response metadata.
+#[derive(Debug, Clone, Copy)]
+pub struct GrpcRequestIdLayer;
+
+impl<S> Layer<S> for GrpcRequestIdLayer {
+	type Service = GrpcRequestId<S>;
+
+	fn layer(&self, inner: S) -> Self::Service {
+		GrpcRequestId { inner }
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcRequestId<S> {
+	inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for GrpcRequestId<S>
+where
+	S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+	S::Future: Send + 'static,
+{
+	type Response = S::Response;
+	type Error = S::Error;
+	type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+	fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+		self.inner.poll_ready(cx)
+	}
+
+	fn call(&mut self, mut request: http::Request<ReqBody>) -> Self::Future {
+		let request_id = ensure(request.headers_mut());
+		// Interceptors run in `call`, so it too is in the scope.
+		let future = CURRENT.sync_scope(request_id.clone(), || self.inner.call(request));
+		Box::pin(CURRENT.scope(request_id.clone(), async move {
+			// The call's span is entered while its future is polled.
+			Span::current().record("request_id", request_id.as_str());
+			let mut response = future.await?;
+			set(response.headers_mut(), &request_id);
+			Ok(response)
+		}))
+	}
+}
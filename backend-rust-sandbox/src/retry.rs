@@ -0,0 +1,121 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::tool_executor::ToolResult;
+
+pub use pagi_sandbox_client::wire::RetryOverride;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INITIAL_BACKOFF_MS: u64 = 200;
+const DEFAULT_MAX_BACKOFF_MS: u64 = 10_000;
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+const DEFAULT_JITTER: f64 = 0.2;
+const DEFAULT_RETRY_ON: &[&str] = &["spawn_error", "container_error", "vm_error"];
+
+/// Outcomes that retrying cannot change, or that the caller asked for.
+const NEVER_RETRIED: &[&str] = &["ok", "cancelled", "aborted", "preempted", "deadline_exceeded", "unknown_tool", "invalid_args"];
+
+/// A manifest's `[retry]` table: which failures are transient, and how often
+/// and how far apart the tool is run again after one.
+///
+/// ```toml
+/// [retry]
+/// max_attempts = 4
+/// retryable_exit_codes = [75]  # EX_TEMPFAIL
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+	/// Runs in all, the first one included.
+	pub max_attempts: u32,
+	/// Wait before the second attempt.
+	pub initial_backoff_ms: u64,
+	/// Longest wait between attempts, however many there were.
+	pub max_backoff_ms: u64,
+	/// Each wait is this many times the one before.
+	pub multiplier: f64,
+	/// Up to this fraction of each wait is added or taken off at random, so
+	/// that tools failing together do not retry in lockstep.
+	pub jitter: f64,
+	/// Result statuses worth another attempt.
+	pub retry_on: Vec<String>,
+	/// Exit codes that make a `non_zero_exit` worth another attempt; other
+	/// non-zero exits are final.
+	pub retryable_exit_codes: Vec<i32>,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: DEFAULT_MAX_ATTEMPTS,
+			initial_backoff_ms: DEFAULT_INITIAL_BACKOFF_MS,
+			max_backoff_ms: DEFAULT_MAX_BACKOFF_MS,
+			multiplier: DEFAULT_MULTIPLIER,
+			jitter: DEFAULT_JITTER,
+			retry_on: DEFAULT_RETRY_ON.iter().map(|s| s.to_string()).collect(),
+			retryable_exit_codes: Vec::new(),
+		}
+	}
+}
+
+impl RetryPolicy {
+	pub fn validate(&self) -> Result<(), String> {
+		if self.max_attempts == 0 {
+			return Err("retry.max_attempts must be at least 1".to_string());
+		}
+		if self.initial_backoff_ms > self.max_backoff_ms {
+			return Err(format!(
+				"retry.initial_backoff_ms ({}) is above retry.max_backoff_ms ({})",
+				self.initial_backoff_ms, self.max_backoff_ms
+			));
+		}
+		if !(self.multiplier >= 1.0 && self.multiplier.is_finite()) {
+			return Err(format!("retry.multiplier must be at least 1 (got {})", self.multiplier));
+		}
+		if !(0.0..=1.0).contains(&self.jitter) {
+			return Err(format!("retry.jitter must be between 0 and 1 (got {})", self.jitter));
+		}
+		if let Some(status) = self.retry_on.iter().find(|s| NEVER_RETRIED.contains(&s.as_str())) {
+			return Err(format!("retry.retry_on: {status:?} is never retried"));
+		}
+		if self.retry_on.iter().any(|s| s == "non_zero_exit") {
+			return Err("retry.retry_on: use retryable_exit_codes for non_zero_exit".to_string());
+		}
+		Ok(())
+	}
+
+	/// This policy with a request's changes, attempts capped at `max_attempts`.
+	pub fn with_override(&self, changes: Option<&RetryOverride>, max_attempts: u32) -> Self {
+		let mut policy = self.clone();
+		if let Some(changes) = changes {
+			policy.max_attempts = changes.max_attempts.unwrap_or(policy.max_attempts);
+			policy.initial_backoff_ms = changes.initial_backoff_ms.unwrap_or(policy.initial_backoff_ms);
+			policy.max_backoff_ms = changes.max_backoff_ms.unwrap_or(policy.max_backoff_ms);
+		}
+		policy.max_attempts = policy.max_attempts.clamp(1, max_attempts.max(1));
+		policy.initial_backoff_ms = policy.initial_backoff_ms.min(policy.max_backoff_ms);
+		policy
+	}
+
+	/// Whether `result` is a transient failure under this policy.
+	pub fn retryable(&self, result: &ToolResult) -> bool {
+		if result.status == "non_zero_exit" {
+			return result.exit_code.is_some_and(|code| self.retryable_exit_codes.contains(&code));
+		}
+		self.retry_on.contains(&result.status)
+	}
+
+	/// How long to wait after failed attempt number `attempt` (from 1).
+	pub fn backoff(&self, attempt: u32) -> Duration {
+		let exponent = attempt.saturating_sub(1).min(63) as i32;
+		let base = (self.initial_backoff_ms as f64 * self.multiplier.powi(exponent)).min(self.max_backoff_ms as f64);
+		let spread = base * self.jitter;
+		let jittered = if spread > 0.0 {
+			base + rand::thread_rng().gen_range(-spread..=spread)
+		} else {
+			base
+		};
+		Duration::from_millis(jittered.clamp(0.0, self.max_backoff_ms as f64) as u64)
+	}
+}
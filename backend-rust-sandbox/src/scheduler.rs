@@ -0,0 +1,444 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, OnceCell};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::metrics::METRICS;
+
+pub use pagi_sandbox_client::wire::{Priority, ScalingSignal};
+
+/// How long rejected callers are told to wait before retrying.
+const RETRY_AFTER_SECS: u64 = 1;
+const DEFAULT_PREEMPT_MIN_RUNTIME_MS: u64 = 10_000;
+/// How far back [`Scheduler::scaling`] averages slot waits over.
+const WAIT_WINDOW_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreemptionConfig {
+	/// Cancel a lower-priority execution to make room for a request that
+	/// finds every slot taken.
+	pub enabled: bool,
+	/// Executions that have run for less than this are left alone.
+	pub min_runtime_ms: u64,
+}
+
+impl Default for PreemptionConfig {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			min_runtime_ms: DEFAULT_PREEMPT_MIN_RUNTIME_MS,
+		}
+	}
+}
+
+/// Returned instead of an execution context when every slot is taken and the
+/// queue is full.
+#[derive(Debug, Clone)]
+pub struct QueueFull {
+	pub max_concurrent: usize,
+	pub max_queued: usize,
+	pub retry_after_secs: u64,
+	/// Turned away because the instance is draining rather than busy.
+	pub draining: bool,
+}
+
+impl QueueFull {
+	/// `queue_full`, or `draining`.
+	pub fn status(&self) -> &'static str {
+		match self.draining {
+			true => "draining",
+			false => "queue_full",
+		}
+	}
+
+	pub fn message(&self) -> String {
+		if self.draining {
+			return "this instance is draining and takes no new executions; retry on another".to_string();
+		}
+		format!(
+			"all {} execution slots are busy and {} executions are already queued; retry later",
+			self.max_concurrent, self.max_queued
+		)
+	}
+}
+
+struct Waiter {
+	slot: oneshot::Sender<Slot>,
+	cancel: CancellationToken,
+	preempted: Arc<AtomicBool>,
+	since: Instant,
+}
+
+struct Running {
+	priority: Priority,
+	started: Instant,
+	cancel: CancellationToken,
+	preempted: Arc<AtomicBool>,
+}
+
+struct Slots {
+	free: usize,
+	next_id: u64,
+	/// Keyed so that the last entry is the one to serve next: highest
+	/// priority, then longest waiting.
+	waiting: BTreeMap<(Priority, Reverse<u64>), Waiter>,
+	running: HashMap<u64, Running>,
+	waits: Waits,
+}
+
+impl Slots {
+	/// Take a free slot for the caller described by `waiter`.
+	fn take(&mut self, shared: &Arc<Mutex<Slots>>, priority: Priority, waiter: &Waiter) -> Slot {
+		self.free -= 1;
+		let id = self.next_id;
+		self.next_id += 1;
+		self.running.insert(
+			id,
+			Running {
+				priority,
+				started: Instant::now(),
+				cancel: waiter.cancel.clone(),
+				preempted: waiter.preempted.clone(),
+			},
+		);
+		Slot {
+			slots: Some(shared.clone()),
+			id,
+		}
+	}
+
+	/// Hand a freed slot to the next caller still waiting, if any.
+	fn hand_off(&mut self, shared: &Arc<Mutex<Slots>>) {
+		while let Some(((priority, _), waiter)) = self.waiting.pop_last() {
+			let slot = self.take(shared, priority, &waiter);
+			let waited = waiter.since.elapsed();
+			if let Err(mut slot) = waiter.slot.send(slot) {
+				// The caller gave up; the slot is still ours to give.
+				slot.slots = None;
+				self.running.remove(&slot.id);
+				self.free += 1;
+			} else {
+				self.waits.record(waited);
+				return;
+			}
+		}
+	}
+
+	/// Cancel the lowest-priority execution below `priority` that has run
+	/// for at least `min_runtime`, the longest-running one among equals.
+	fn preempt(&mut self, priority: Priority, min_runtime: Duration) {
+		let victim = self
+			.running
+			.values()
+			.filter(|r| r.priority < priority && !r.preempted.load(Ordering::Acquire))
+			.filter(|r| r.started.elapsed() >= min_runtime)
+			.min_by_key(|r| (r.priority, r.started));
+		if let Some(victim) = victim {
+			info!(
+				priority = %victim.priority,
+				for_priority = %priority,
+				runtime_ms = victim.started.elapsed().as_millis() as u64,
+				message = "Preempting an execution for a higher-priority request"
+			);
+			METRICS.preemptions.with_label_values(&[victim.priority.as_str()]).inc();
+			victim.preempted.store(true, Ordering::Release);
+			victim.cancel.cancel();
+		}
+	}
+}
+
+/// Waits for a slot that ended in the last [`WAIT_WINDOW_SECS`], summed by
+/// the second.
+struct Waits {
+	epoch: Instant,
+	/// The second since `epoch`, how many waits ended in it and their total,
+	/// oldest first.
+	buckets: VecDeque<(u64, u64, Duration)>,
+}
+
+impl Waits {
+	fn new() -> Self {
+		Self {
+			epoch: Instant::now(),
+			buckets: VecDeque::new(),
+		}
+	}
+
+	fn record(&mut self, waited: Duration) {
+		let second = self.prune();
+		match self.buckets.back_mut() {
+			Some((at, count, total)) if *at == second => {
+				*count += 1;
+				*total += waited;
+			}
+			_ => self.buckets.push_back((second, 1, waited)),
+		}
+	}
+
+	/// How many waits ended within the window, and their total.
+	fn recent(&mut self) -> (u64, Duration) {
+		self.prune();
+		self.buckets
+			.iter()
+			.fold((0, Duration::ZERO), |(count, total), (_, n, waited)| (count + n, total + *waited))
+	}
+
+	/// Drop the buckets that fell out of the window; returns the current
+	/// second.
+	fn prune(&mut self) -> u64 {
+		let second = self.epoch.elapsed().as_secs();
+		while self.buckets.front().is_some_and(|(at, ..)| at + WAIT_WINDOW_SECS <= second) {
+			self.buckets.pop_front();
+		}
+		second
+	}
+}
+
+/// Bounds how many executions run at once and how many may wait for a slot.
+///
+/// Admission is decided up front, so a caller is either rejected immediately
+/// or guaranteed a place in line. Free slots go to the highest-priority
+/// waiting execution, and with preemption enabled a waiting execution may
+/// cancel a long-running one of a lower priority to get one.
+pub struct Scheduler {
+	slots: Arc<Mutex<Slots>>,
+	/// Running plus waiting executions.
+	admitted: Arc<AtomicUsize>,
+	max_concurrent: usize,
+	max_queued: usize,
+	preemption: PreemptionConfig,
+	draining: AtomicBool,
+}
+
+impl Scheduler {
+	pub fn new(max_concurrent: usize, max_queued: usize, preemption: PreemptionConfig) -> Self {
+		Self {
+			slots: Arc::new(Mutex::new(Slots {
+				free: max_concurrent,
+				next_id: 0,
+				waiting: BTreeMap::new(),
+				running: HashMap::new(),
+				waits: Waits::new(),
+			})),
+			admitted: Arc::new(AtomicUsize::new(0)),
+			max_concurrent,
+			max_queued,
+			preemption,
+			draining: AtomicBool::new(false),
+		}
+	}
+
+	/// Reserve a place for one execution, or reject it when the queue is full.
+	pub fn admit(&self) -> Result<Admission, QueueFull> {
+		if self.draining() {
+			return Err(QueueFull {
+				max_concurrent: self.max_concurrent,
+				max_queued: self.max_queued,
+				retry_after_secs: RETRY_AFTER_SECS,
+				draining: true,
+			});
+		}
+		let capacity = self.max_concurrent + self.max_queued;
+		let admitted = self
+			.admitted
+			.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < capacity).then_some(n + 1));
+		if admitted.is_err() {
+			METRICS.rejected_executions.inc();
+			return Err(QueueFull {
+				max_concurrent: self.max_concurrent,
+				max_queued: self.max_queued,
+				retry_after_secs: RETRY_AFTER_SECS,
+				draining: false,
+			});
+		}
+		Ok(Admission {
+			slots: self.slots.clone(),
+			admitted: self.admitted.clone(),
+			preemption: self.preemption.enabled.then(|| Duration::from_millis(self.preemption.min_runtime_ms)),
+			preempted: Arc::new(AtomicBool::new(false)),
+			slot: OnceCell::new(),
+		})
+	}
+
+	/// Turn every new execution away while `draining`.
+	pub fn set_draining(&self, draining: bool) {
+		self.draining.store(draining, Ordering::Release);
+	}
+
+	pub fn draining(&self) -> bool {
+		self.draining.load(Ordering::Acquire)
+	}
+
+	/// Admitted executions still waiting for a slot.
+	pub fn queued(&self) -> usize {
+		let running = self.slots.lock().unwrap().running.len();
+		self.admitted.load(Ordering::Acquire).saturating_sub(running)
+	}
+
+	pub fn capacity(&self) -> Capacity {
+		let running = self.slots.lock().unwrap().running.len();
+		Capacity {
+			max_concurrent: self.max_concurrent,
+			max_queued: self.max_queued,
+			running,
+			queued: self.admitted.load(Ordering::Acquire).saturating_sub(running),
+		}
+	}
+
+	/// What an external autoscaler scales this replica's deployment by.
+	pub fn scaling(&self) -> ScalingSignal {
+		let mut slots = self.slots.lock().unwrap();
+		let running = slots.running.len();
+		let pending = self.admitted.load(Ordering::Acquire).saturating_sub(running);
+		// Callers still in line count with how long they have waited so far,
+		// so that a stalled queue does not read as no wait at all.
+		let (mut waits, mut waited) = slots.waits.recent();
+		for waiter in slots.waiting.values().filter(|w| !w.slot.is_closed()) {
+			waits += 1;
+			waited += waiter.since.elapsed();
+		}
+		let avg_wait_ms = match waits {
+			0 => 0,
+			n => (waited.as_millis() / n as u128) as u64,
+		};
+		ScalingSignal {
+			pending_queue_depth: pending as u64,
+			avg_wait_ms,
+			running: running as u64,
+			max_concurrent: self.max_concurrent as u64,
+			max_queued: self.max_queued as u64,
+			utilization: (running + pending) as f64 / self.max_concurrent.max(1) as f64,
+			draining: self.draining(),
+			window_secs: WAIT_WINDOW_SECS,
+			shared_queue_depth: None,
+		}
+	}
+}
+
+/// How busy the scheduler is, as cluster nodes advertise it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capacity {
+	pub max_concurrent: usize,
+	pub max_queued: usize,
+	pub running: usize,
+	pub queued: usize,
+}
+
+impl Capacity {
+	/// Executions that would still be admitted.
+	pub fn free(&self) -> usize {
+		(self.max_concurrent + self.max_queued).saturating_sub(self.running + self.queued)
+	}
+}
+
+/// An execution slot; the next waiting execution gets it when dropped.
+struct Slot {
+	/// `None` once the slot has been given back.
+	slots: Option<Arc<Mutex<Slots>>>,
+	id: u64,
+}
+
+impl Drop for Slot {
+	fn drop(&mut self) {
+		if let Some(shared) = self.slots.take() {
+			let mut slots = shared.lock().unwrap();
+			slots.running.remove(&self.id);
+			slots.free += 1;
+			slots.hand_off(&shared);
+		}
+	}
+}
+
+/// A place in line; holds an execution slot once [`Admission::wait_turn`]
+/// returns, and gives both back when dropped.
+pub struct Admission {
+	slots: Arc<Mutex<Slots>>,
+	admitted: Arc<AtomicUsize>,
+	/// Minimum runtime of the executions this one may preempt, when
+	/// preemption is enabled.
+	preemption: Option<Duration>,
+	preempted: Arc<AtomicBool>,
+	slot: OnceCell<Slot>,
+}
+
+impl fmt::Debug for Admission {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Admission")
+			.field("has_slot", &self.slot.initialized())
+			.field("preempted", &self.preempted())
+			.finish()
+	}
+}
+
+impl Admission {
+	/// Wait for a free slot at `priority`. Returns at once if this admission
+	/// already has one. `cancel` is what preemption cancels once the
+	/// execution runs.
+	pub async fn wait_turn(&self, priority: Priority, cancel: &CancellationToken) {
+		if self.slot.initialized() {
+			return;
+		}
+		let _waiting = Waiting::start();
+		self.slot
+			.get_or_init(|| async {
+				let receiver = {
+					let (sender, receiver) = oneshot::channel();
+					let waiter = Waiter {
+						slot: sender,
+						cancel: cancel.clone(),
+						preempted: self.preempted.clone(),
+						since: Instant::now(),
+					};
+					let mut slots = self.slots.lock().unwrap();
+					// Entries of callers that gave up are cleared on the way.
+					slots.waiting.retain(|_, w| !w.slot.is_closed());
+					let id = slots.next_id;
+					slots.next_id += 1;
+					slots.waiting.insert((priority, Reverse(id)), waiter);
+					if slots.free > 0 {
+						slots.hand_off(&self.slots);
+					} else if let Some(min_runtime) = self.preemption {
+						slots.preempt(priority, min_runtime);
+					}
+					receiver
+				};
+				receiver.await.expect("waiters are only dropped after being served")
+			})
+			.await;
+	}
+
+	/// Whether the execution was cancelled to make room for a higher-priority
+	/// one.
+	pub fn preempted(&self) -> bool {
+		self.preempted.load(Ordering::Acquire)
+	}
+}
+
+impl Drop for Admission {
+	fn drop(&mut self) {
+		self.admitted.fetch_sub(1, Ordering::AcqRel);
+	}
+}
+
+/// Counts a caller in the queue gauge until it gets a slot or gives up.
+struct Waiting;
+
+impl Waiting {
+	fn start() -> Self {
+		METRICS.queued_executions.inc();
+		Self
+	}
+}
+
+impl Drop for Waiting {
+	fn drop(&mut self) {
+		METRICS.queued_executions.dec();
+	}
+}
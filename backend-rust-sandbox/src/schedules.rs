@@ -0,0 +1,571 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, info_span, Instrument};
+
+use crate::audit::Caller;
+use crate::auth::Principal;
+use crate::authz::Authorizer;
+use crate::clock::now_ms;
+use crate::cron::Cron;
+use crate::metrics::METRICS;
+use crate::registry::is_valid_tool_name;
+use crate::scheduler::Priority;
+use crate::shared_limits::SharedLimits;
+use crate::tool::{execute_tool_request_with_context, ToolExecutionRequest};
+use crate::tool_executor::Executor;
+
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+const DEFAULT_MAX_SCHEDULES: usize = 100;
+/// Longest a schedule sleeps before looking at the clock again, so that a
+/// wall clock set forward is noticed within this long.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+/// How long a replica's claim on a configured run holds; longer than the
+/// replicas' clocks drift apart.
+const CLAIM_TTL: Duration = Duration::from_secs(600);
+
+/// What happens to a run that falls due while the previous one is still
+/// going.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overlap {
+	/// It is recorded as `skipped` and does not run.
+	#[default]
+	Skip,
+	/// It runs as soon as the previous one finishes. Only one run waits;
+	/// runs falling due while one is waiting are skipped.
+	Queue,
+}
+
+/// A tool to run periodically, from `[[schedules.entries]]` or
+/// `POST /api/v1/schedules`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleSpec {
+	/// Names the schedule in the API, the logs and the metrics.
+	pub id: String,
+	/// When to run, as a cron expression in UTC (see [`Cron`]).
+	pub cron: String,
+	pub tool_name: String,
+	#[serde(default = "empty_args")]
+	pub args: Value,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub timeout_ms: Option<u64>,
+	#[serde(default)]
+	pub overlap: Overlap,
+	#[serde(default = "enabled")]
+	pub enabled: bool,
+}
+
+fn empty_args() -> Value {
+	json!({})
+}
+
+fn enabled() -> bool {
+	true
+}
+
+impl ScheduleSpec {
+	fn validate(&self) -> Result<Cron, String> {
+		if !is_valid_tool_name(&self.id) {
+			return Err(format!("schedule id {:?} must be letters, digits, '_' and '-'", self.id));
+		}
+		if self.timeout_ms == Some(0) {
+			return Err(format!("schedule {}: timeout_ms must be positive", self.id));
+		}
+		Cron::parse(&self.cron).map_err(|e| format!("schedule {}: cron {e}", self.id))
+	}
+
+	/// The request each run makes.
+	pub fn request(&self) -> ToolExecutionRequest {
+		ToolExecutionRequest {
+			tool_name: self.tool_name.clone(),
+			args: self.args.clone(),
+			timeout_ms: self.timeout_ms,
+			retain_workspace: false,
+			artifacts: Vec::new(),
+			stdin: None,
+			input_files: Default::default(),
+			session_id: None,
+			retry: None,
+			callback_url: None,
+			priority: None,
+			dry_run: false,
+			record_trace: false,
+			replay_trace: None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulesConfig {
+	/// Finished runs kept per schedule, for `GET /api/v1/schedules/:id`.
+	pub history_limit: usize,
+	/// Schedules held at once, the configured ones included.
+	pub max_schedules: usize,
+	/// Schedules set up at startup. They run with the server's authority,
+	/// unlike those created through the API.
+	pub entries: Vec<ScheduleSpec>,
+}
+
+impl Default for SchedulesConfig {
+	fn default() -> Self {
+		Self {
+			history_limit: DEFAULT_HISTORY_LIMIT,
+			max_schedules: DEFAULT_MAX_SCHEDULES,
+			entries: Vec::new(),
+		}
+	}
+}
+
+impl SchedulesConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if self.history_limit == 0 {
+			errors.push("schedules.history_limit must be positive".to_string());
+		}
+		if self.entries.len() > self.max_schedules {
+			errors.push(format!(
+				"schedules.entries holds {} schedules, above schedules.max_schedules ({})",
+				self.entries.len(),
+				self.max_schedules
+			));
+		}
+		let mut ids = HashSet::new();
+		for spec in &self.entries {
+			if let Err(e) = spec.validate() {
+				errors.push(format!("schedules.entries: {e}"));
+			}
+			if !ids.insert(spec.id.as_str()) {
+				errors.push(format!("schedules.entries: schedule id {:?} is used twice", spec.id));
+			}
+		}
+		errors
+	}
+}
+
+/// The body of `PATCH /api/v1/schedules/:id`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleUpdate {
+	pub enabled: bool,
+}
+
+/// One run of a schedule, or one it skipped.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleRun {
+	/// When the run fell due.
+	pub scheduled_at_ms: u64,
+	pub started_at_ms: Option<u64>,
+	pub finished_at_ms: Option<u64>,
+	/// The execution's status; `skipped` when the previous run was still
+	/// going, `claimed` when another replica ran it, and `forbidden`,
+	/// `queue_full` or `draining` when it was turned away.
+	pub status: String,
+	pub exit_code: Option<i64>,
+	/// The execution's ID in the job history and the audit log.
+	pub execution_id: Option<String>,
+}
+
+impl ScheduleRun {
+	fn not_run(scheduled_at_ms: u64, status: &str) -> Self {
+		Self {
+			scheduled_at_ms,
+			started_at_ms: None,
+			finished_at_ms: None,
+			status: status.to_string(),
+			exit_code: None,
+			execution_id: None,
+		}
+	}
+}
+
+/// Snapshot of a schedule as returned by the schedules API.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleView {
+	#[serde(flatten)]
+	pub spec: ScheduleSpec,
+	/// `config` or `api`.
+	pub source: &'static str,
+	/// The subject that created the schedule through the API.
+	pub created_by: Option<String>,
+	pub created_at_ms: u64,
+	/// `None` while the schedule is disabled.
+	pub next_run_at_ms: Option<u64>,
+	pub running: bool,
+	/// A run is waiting for the current one to finish.
+	pub queued: bool,
+	/// Recent runs, newest first.
+	pub runs: Vec<ScheduleRun>,
+}
+
+/// Why a schedule could not be created or found.
+#[derive(Debug)]
+pub enum ScheduleError {
+	NotFound,
+	Exists,
+	Invalid(String),
+	LimitReached(String),
+}
+
+impl ScheduleError {
+	pub fn message(&self) -> String {
+		match self {
+			Self::NotFound => "schedule not found".to_string(),
+			Self::Exists => "a schedule with this id already exists".to_string(),
+			Self::Invalid(m) | Self::LimitReached(m) => m.clone(),
+		}
+	}
+}
+
+struct ScheduleState {
+	enabled: bool,
+	next_run_at_ms: Option<u64>,
+	running: bool,
+	/// When the waiting run fell due, under [`Overlap::Queue`].
+	queued: Option<u64>,
+	runs: VecDeque<ScheduleRun>,
+}
+
+/// Where a schedule came from.
+enum Origin {
+	Config,
+	/// Created through the API, by this principal when authentication is
+	/// on. Every run is authorized for them again, so revoking their access
+	/// stops the schedule; configured schedules are not checked.
+	Api(Option<Principal>),
+}
+
+struct Schedule {
+	spec: ScheduleSpec,
+	cron: Cron,
+	origin: Origin,
+	created_at_ms: u64,
+	state: Mutex<ScheduleState>,
+	/// Wakes the timer when the schedule is enabled or disabled.
+	changed: Notify,
+	/// Stops the timer when the schedule is deleted or the server shuts
+	/// down; a run in progress finishes.
+	removed: CancellationToken,
+}
+
+impl Schedule {
+	fn view(&self) -> ScheduleView {
+		let state = self.state.lock().unwrap();
+		let mut spec = self.spec.clone();
+		spec.enabled = state.enabled;
+		ScheduleView {
+			spec,
+			source: match self.origin {
+				Origin::Config => "config",
+				Origin::Api(_) => "api",
+			},
+			created_by: self.owner().map(|p| p.subject.clone()),
+			created_at_ms: self.created_at_ms,
+			next_run_at_ms: state.next_run_at_ms,
+			running: state.running,
+			queued: state.queued.is_some(),
+			runs: state.runs.iter().rev().cloned().collect(),
+		}
+	}
+
+	/// The next time the schedule fires, `None` while it is disabled.
+	fn next_due(&self) -> Option<DateTime<Utc>> {
+		let mut state = self.state.lock().unwrap();
+		let due = state.enabled.then(|| self.cron.next_after(Utc::now())).flatten();
+		state.next_run_at_ms = due.map(|t| t.timestamp_millis() as u64);
+		due
+	}
+
+	fn owner(&self) -> Option<&Principal> {
+		match &self.origin {
+			Origin::Api(owner) => owner.as_ref(),
+			Origin::Config => None,
+		}
+	}
+}
+
+/// The schedules and their timers.
+///
+/// Each schedule has a task that sleeps until its next fire time; runs go
+/// through the executor's queue like any request and land in the audit log
+/// and job history with the `schedule` transport. Schedules created through
+/// the API are held in memory only and are gone after a restart; those in
+/// the configuration file come back, enabled as configured.
+///
+/// Every replica loads the configured schedules. With shared limits, each
+/// run is claimed in Redis first and only the replica whose claim lands
+/// runs it; while Redis does not answer, every replica runs it. Without
+/// them, configure schedules on exactly one replica.
+pub struct Schedules {
+	executor: Arc<Executor>,
+	authz: Arc<Authorizer>,
+	shared: Option<Arc<SharedLimits>>,
+	schedules: Mutex<BTreeMap<String, Arc<Schedule>>>,
+	history_limit: usize,
+	max_schedules: usize,
+	shutdown: CancellationToken,
+}
+
+impl Schedules {
+	/// Set up the configured schedules and start their timers. Fails when
+	/// one names an unknown tool or args its schema rejects.
+	pub fn start(
+		executor: Arc<Executor>,
+		authz: Arc<Authorizer>,
+		shared: Option<Arc<SharedLimits>>,
+		config: &SchedulesConfig,
+		shutdown: CancellationToken,
+	) -> Result<Arc<Self>, String> {
+		let schedules = Arc::new(Self {
+			executor,
+			authz,
+			shared,
+			schedules: Mutex::new(BTreeMap::new()),
+			history_limit: config.history_limit,
+			max_schedules: config.max_schedules,
+			shutdown,
+		});
+		for spec in &config.entries {
+			if let Err(errors) = schedules.executor.validate_args(&spec.tool_name, &spec.args) {
+				let errors: Vec<String> = errors
+					.iter()
+					.map(|e| if e.path.is_empty() { e.message.clone() } else { format!("{}: {}", e.path, e.message) })
+					.collect();
+				return Err(format!("schedule {}: invalid args: {}", spec.id, errors.join("; ")));
+			}
+			schedules.insert(spec.clone(), Origin::Config).map_err(|e| e.message())?;
+		}
+		Ok(schedules)
+	}
+
+	/// Add a schedule for `owner`, who has been authorized for its tool.
+	pub fn create(self: &Arc<Self>, spec: ScheduleSpec, owner: Option<Principal>) -> Result<ScheduleView, ScheduleError> {
+		self.insert(spec, Origin::Api(owner))
+	}
+
+	fn insert(self: &Arc<Self>, spec: ScheduleSpec, origin: Origin) -> Result<ScheduleView, ScheduleError> {
+		let cron = spec.validate().map_err(ScheduleError::Invalid)?;
+		if self.executor.registry().get(&spec.tool_name).is_none() {
+			return Err(ScheduleError::Invalid(format!(
+				"schedule {}: unknown tool {:?}",
+				spec.id, spec.tool_name
+			)));
+		}
+		let mut schedules = self.schedules.lock().unwrap();
+		if schedules.contains_key(&spec.id) {
+			return Err(ScheduleError::Exists);
+		}
+		if schedules.len() >= self.max_schedules {
+			return Err(ScheduleError::LimitReached(format!(
+				"the server already holds its maximum of {} schedules",
+				self.max_schedules
+			)));
+		}
+		let schedule = Arc::new(Schedule {
+			state: Mutex::new(ScheduleState {
+				enabled: spec.enabled,
+				next_run_at_ms: None,
+				running: false,
+				queued: None,
+				runs: VecDeque::new(),
+			}),
+			spec,
+			cron,
+			origin,
+			created_at_ms: now_ms(),
+			changed: Notify::new(),
+			removed: self.shutdown.child_token(),
+		});
+		// Filled in before the view is taken, so it shows the first fire time.
+		schedule.next_due();
+		schedules.insert(schedule.spec.id.clone(), schedule.clone());
+		drop(schedules);
+
+		let span = info_span!("schedule", schedule_id = %schedule.spec.id);
+		let this = self.clone();
+		let timer = schedule.clone();
+		tokio::spawn(async move { this.tick(timer).await }.instrument(span));
+		info!(
+			schedule_id = schedule.spec.id,
+			tool_name = schedule.spec.tool_name,
+			cron = %schedule.cron,
+			message = "Schedule added"
+		);
+		Ok(schedule.view())
+	}
+
+	pub fn list(&self) -> Vec<ScheduleView> {
+		self.schedules.lock().unwrap().values().map(|s| s.view()).collect()
+	}
+
+	pub fn get(&self, id: &str) -> Result<ScheduleView, ScheduleError> {
+		Ok(self.lookup(id)?.view())
+	}
+
+	/// Enable or disable a schedule. Disabling drops a waiting run; one in
+	/// progress finishes.
+	pub fn set_enabled(&self, id: &str, enabled: bool) -> Result<ScheduleView, ScheduleError> {
+		let schedule = self.lookup(id)?;
+		{
+			let mut state = schedule.state.lock().unwrap();
+			state.enabled = enabled;
+			if !enabled {
+				state.queued = None;
+			}
+		}
+		schedule.next_due();
+		schedule.changed.notify_one();
+		info!(schedule_id = id, enabled = enabled, message = "Schedule updated");
+		Ok(schedule.view())
+	}
+
+	/// Delete a schedule. A run in progress finishes.
+	pub fn remove(&self, id: &str) -> Result<ScheduleView, ScheduleError> {
+		let schedule = self.schedules.lock().unwrap().remove(id).ok_or(ScheduleError::NotFound)?;
+		schedule.removed.cancel();
+		schedule.state.lock().unwrap().queued = None;
+		info!(schedule_id = id, message = "Schedule deleted");
+		Ok(schedule.view())
+	}
+
+	fn lookup(&self, id: &str) -> Result<Arc<Schedule>, ScheduleError> {
+		self.schedules.lock().unwrap().get(id).cloned().ok_or(ScheduleError::NotFound)
+	}
+
+	/// The schedule's timer: fire at each due time until the schedule is
+	/// removed.
+	async fn tick(self: Arc<Self>, schedule: Arc<Schedule>) {
+		let mut due = schedule.next_due();
+		loop {
+			let wait = due
+				.and_then(|t| (t - Utc::now()).to_std().ok())
+				.map_or(MAX_SLEEP, |d| d.min(MAX_SLEEP));
+			tokio::select! {
+				_ = tokio::time::sleep(wait) => {}
+				_ = schedule.changed.notified() => {
+					due = schedule.next_due();
+					continue;
+				}
+				_ = schedule.removed.cancelled() => return,
+			}
+			match due {
+				Some(t) if Utc::now() >= t => {
+					self.fire(&schedule, t.timestamp_millis() as u64).await;
+					due = schedule.next_due();
+				}
+				Some(_) => {}
+				None => due = schedule.next_due(),
+			}
+		}
+	}
+
+	/// Start the run due at `due_ms`, or apply the overlap policy if the
+	/// previous one is still going.
+	async fn fire(self: &Arc<Self>, schedule: &Arc<Schedule>, due_ms: u64) {
+		if !self.claim(schedule, due_ms).await {
+			let mut state = schedule.state.lock().unwrap();
+			self.record(&schedule.spec.id, &mut state, ScheduleRun::not_run(due_ms, "claimed"));
+			return;
+		}
+		{
+			let mut state = schedule.state.lock().unwrap();
+			if state.running {
+				if schedule.spec.overlap == Overlap::Queue && state.queued.is_none() {
+					state.queued = Some(due_ms);
+					return;
+				}
+				self.record(&schedule.spec.id, &mut state, ScheduleRun::not_run(due_ms, "skipped"));
+				info!(
+					schedule_id = schedule.spec.id,
+					message = "Scheduled run skipped; the previous run is still going"
+				);
+				return;
+			}
+			state.running = true;
+		}
+
+		let this = self.clone();
+		let schedule = schedule.clone();
+		tokio::spawn(
+			async move {
+				let mut due_ms = due_ms;
+				loop {
+					let run = this.run(&schedule, due_ms).await;
+					let mut state = schedule.state.lock().unwrap();
+					this.record(&schedule.spec.id, &mut state, run);
+					match state.queued.take() {
+						Some(queued) if !schedule.removed.is_cancelled() => due_ms = queued,
+						_ => {
+							state.running = false;
+							return;
+						}
+					}
+				}
+			}
+			.in_current_span(),
+		);
+	}
+
+	/// Whether this replica runs the configured run due at `due_ms`. Those
+	/// created through the API exist on this replica only.
+	async fn claim(&self, schedule: &Schedule, due_ms: u64) -> bool {
+		let (Origin::Config, Some(shared)) = (&schedule.origin, &self.shared) else {
+			return true;
+		};
+		let claimed = shared.claim(&format!("schedule:{}:{due_ms}", schedule.spec.id), CLAIM_TTL).await;
+		if claimed == Some(false) {
+			info!(schedule_id = schedule.spec.id, message = "Scheduled run claimed by another replica");
+		}
+		claimed != Some(false)
+	}
+
+	async fn run(&self, schedule: &Schedule, due_ms: u64) -> ScheduleRun {
+		let spec = &schedule.spec;
+		let caller = Caller::new(schedule.owner(), None, "schedule");
+		let audit = self.executor.audit();
+		if let Origin::Api(owner) = &schedule.origin {
+			let authorized = self
+				.authz
+				.authorize_execution(owner.as_ref(), caller.transport, &spec.tool_name, &spec.args);
+			if authorized.is_err() {
+				audit.record(&caller, &spec.tool_name, &spec.args, "forbidden");
+				return ScheduleRun::not_run(due_ms, "forbidden");
+			}
+		}
+		let ctx = match self.executor.context(&spec.tool_name, spec.timeout_ms) {
+			Ok(ctx) => ctx.with_caller(caller).with_priority_at_most(Priority::Background),
+			Err(e) => {
+				audit.record(&caller, &spec.tool_name, &spec.args, e.status());
+				return ScheduleRun::not_run(due_ms, e.status());
+			}
+		};
+		let started_at_ms = now_ms();
+		let response = execute_tool_request_with_context(&self.executor, spec.request(), &ctx).await;
+		ScheduleRun {
+			scheduled_at_ms: due_ms,
+			started_at_ms: Some(started_at_ms),
+			finished_at_ms: Some(now_ms()),
+			exit_code: response.result["exit_code"].as_i64(),
+			status: response.status,
+			execution_id: Some(ctx.workspace_id.clone()),
+		}
+	}
+
+	fn record(&self, id: &str, state: &mut ScheduleState, run: ScheduleRun) {
+		METRICS.schedule_runs.with_label_values(&[id, &run.status]).inc();
+		if run.execution_id.is_some() {
+			info!(schedule_id = id, status = run.status, message = "Scheduled run finished");
+		}
+		state.runs.push_back(run);
+		while state.runs.len() > self.history_limit {
+			state.runs.pop_front();
+		}
+	}
+}
@@ -0,0 +1,278 @@
+use seccompiler::{BpfProgram, TargetArch};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// Syscalls an ordinary program needs to start, use files and pipes, fork
+/// and exec helpers, and exit. No sockets, mounts, tracing or privilege
+/// changes.
+const BASE_SYSCALLS: &[&str] = &[
+	"read",
+	"write",
+	"readv",
+	"writev",
+	"pread64",
+	"pwrite64",
+	"openat",
+	"openat2",
+	"close",
+	"close_range",
+	"newfstatat",
+	"fstat",
+	"statx",
+	"statfs",
+	"fstatfs",
+	"lseek",
+	"getdents64",
+	"getcwd",
+	"chdir",
+	"fchdir",
+	"faccessat",
+	"faccessat2",
+	"readlinkat",
+	"mkdirat",
+	"unlinkat",
+	"renameat",
+	"renameat2",
+	"linkat",
+	"symlinkat",
+	"fchmod",
+	"fchmodat",
+	"umask",
+	"utimensat",
+	"truncate",
+	"ftruncate",
+	"fsync",
+	"fdatasync",
+	"fadvise64",
+	"flock",
+	"fcntl",
+	"ioctl",
+	"dup",
+	"dup3",
+	"pipe2",
+	"copy_file_range",
+	"sendfile",
+	"splice",
+	"ppoll",
+	"pselect6",
+	"epoll_create1",
+	"epoll_ctl",
+	"epoll_pwait",
+	"eventfd2",
+	"timerfd_create",
+	"timerfd_settime",
+	"timerfd_gettime",
+	"mmap",
+	"munmap",
+	"mprotect",
+	"mremap",
+	"madvise",
+	"mincore",
+	"msync",
+	"brk",
+	"rt_sigaction",
+	"rt_sigprocmask",
+	"rt_sigreturn",
+	"rt_sigsuspend",
+	"rt_sigtimedwait",
+	"sigaltstack",
+	"kill",
+	"tgkill",
+	"tkill",
+	"clone",
+	"clone3",
+	"execve",
+	"execveat",
+	"wait4",
+	"waitid",
+	"exit",
+	"exit_group",
+	"restart_syscall",
+	"futex",
+	"set_robust_list",
+	"get_robust_list",
+	"set_tid_address",
+	"rseq",
+	"sched_yield",
+	"sched_getaffinity",
+	"nanosleep",
+	"clock_nanosleep",
+	"clock_gettime",
+	"clock_getres",
+	"gettimeofday",
+	"getitimer",
+	"setitimer",
+	"getrandom",
+	"uname",
+	"sysinfo",
+	"times",
+	"getrusage",
+	"getrlimit",
+	"prlimit64",
+	"prctl",
+	"getpid",
+	"getppid",
+	"gettid",
+	"getuid",
+	"geteuid",
+	"getgid",
+	"getegid",
+	"getresuid",
+	"getresgid",
+	"getgroups",
+	"getpgid",
+	"setpgid",
+	"getsid",
+	"setsid",
+];
+
+/// Older entry points that x86_64 libcs and binaries still call directly.
+#[cfg(target_arch = "x86_64")]
+const LEGACY_SYSCALLS: &[&str] = &[
+	"open",
+	"stat",
+	"lstat",
+	"access",
+	"readlink",
+	"mkdir",
+	"rmdir",
+	"unlink",
+	"rename",
+	"link",
+	"symlink",
+	"chmod",
+	"creat",
+	"getdents",
+	"dup2",
+	"pipe",
+	"poll",
+	"select",
+	"epoll_create",
+	"epoll_wait",
+	"eventfd",
+	"fork",
+	"vfork",
+	"alarm",
+	"pause",
+	"time",
+	"getpgrp",
+	"arch_prctl",
+];
+#[cfg(not(target_arch = "x86_64"))]
+const LEGACY_SYSCALLS: &[&str] = &[];
+
+/// Added by the `network` profile: outbound and local sockets.
+const NETWORK_SYSCALLS: &[&str] = &[
+	"socket",
+	"socketpair",
+	"connect",
+	"bind",
+	"listen",
+	"accept",
+	"accept4",
+	"getsockname",
+	"getpeername",
+	"getsockopt",
+	"setsockopt",
+	"sendto",
+	"recvfrom",
+	"sendmsg",
+	"recvmsg",
+	"sendmmsg",
+	"recvmmsg",
+	"shutdown",
+];
+
+/// Allowlist a native tool starts from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BaseProfile {
+	/// Files, pipes and processes; no network.
+	Base,
+	/// `base` plus sockets, for fetchers and API clients.
+	Network,
+}
+
+/// A tool manifest's `[seccomp]` section: a default-deny syscall filter.
+///
+/// ```toml
+/// [seccomp]
+/// profile = "network"
+/// allow = ["mlock"]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeccompProfile {
+	pub profile: BaseProfile,
+	/// Syscalls this tool needs on top of the profile.
+	#[serde(default)]
+	pub allow: Vec<String>,
+}
+
+impl SeccompProfile {
+	/// Compile the profile for the architecture the sandbox runs on.
+	pub fn compile(&self) -> Result<SeccompFilter, String> {
+		let arch = TargetArch::try_from(std::env::consts::ARCH).map_err(|e| format!("seccomp: {e}"))?;
+		let mut syscalls: Vec<&str> = BASE_SYSCALLS.iter().chain(LEGACY_SYSCALLS).copied().collect();
+		if self.profile == BaseProfile::Network {
+			syscalls.extend(NETWORK_SYSCALLS);
+		}
+		syscalls.extend(self.allow.iter().map(String::as_str));
+		syscalls.sort_unstable();
+		syscalls.dedup();
+
+		let rules: Vec<_> = syscalls.iter().map(|name| json!({ "syscall": name })).collect();
+		let definition = json!({
+			"tool": {
+				"mismatch_action": "kill_process",
+				"match_action": "allow",
+				"filter": rules,
+			}
+		});
+		let mut programs = seccompiler::compile_from_json(definition.to_string().as_bytes(), arch)
+			.map_err(|e| format!("invalid seccomp profile: {e}"))?;
+		let program = programs.remove("tool").expect("the filter was defined as \"tool\"");
+		Ok(SeccompFilter(Arc::new(program)))
+	}
+}
+
+/// A compiled filter, shared by every execution of the tool.
+#[derive(Clone)]
+pub struct SeccompFilter(Arc<BpfProgram>);
+
+impl fmt::Debug for SeccompFilter {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "SeccompFilter({} instructions)", self.0.len())
+	}
+}
+
+impl SeccompFilter {
+	/// Confine the calling process. Runs in the child between fork and exec,
+	/// so it only makes syscalls; the filter is inherited across exec.
+	pub fn install(&self) -> io::Result<()> {
+		let program = libc::sock_fprog {
+			len: self.0.len() as libc::c_ushort,
+			filter: self.0.as_ptr() as *mut libc::sock_filter,
+		};
+		// SAFETY: prctl with integer arguments; the kernel copies the program
+		// before seccomp returns, and `self.0` outlives the call.
+		unsafe {
+			if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+				return Err(io::Error::last_os_error());
+			}
+			if libc::syscall(
+				libc::SYS_seccomp,
+				libc::SECCOMP_SET_MODE_FILTER,
+				0,
+				&program as *const libc::sock_fprog,
+			) != 0
+			{
+				return Err(io::Error::last_os_error());
+			}
+		}
+		Ok(())
+	}
+}
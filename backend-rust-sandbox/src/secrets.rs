@@ -0,0 +1,326 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_ENV_PREFIX: &str = "SANDBOX_SECRET_";
+const DEFAULT_DIR: &str = "/run/secrets";
+const DEFAULT_VAULT_MOUNT: &str = "secret";
+const DEFAULT_VAULT_CACHE_SECS: u64 = 300;
+const VAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Field read from a Vault secret when the name does not pick one.
+const DEFAULT_VAULT_FIELD: &str = "value";
+
+/// Host variables that no env allowlist passes on to a tool, by prefix: the
+/// sandbox's own settings, which hold its keys and the URLs of the stores
+/// (Redis, the history database) through which tools can be run elsewhere.
+const NEVER_INHERITED_PREFIXES: &[&str] = &["SANDBOX_", "PAGI_SANDBOX_"];
+
+/// Other credentials the sandbox reads from its environment: Vault's, those
+/// the S3 artifact store picks up, and the OTLP exporter's headers.
+const NEVER_INHERITED: &[&str] = &[
+	"VAULT_ADDR",
+	"VAULT_TOKEN",
+	"AWS_ACCESS_KEY_ID",
+	"AWS_SECRET_ACCESS_KEY",
+	"AWS_SESSION_TOKEN",
+	"AWS_SECURITY_TOKEN",
+	"AWS_WEB_IDENTITY_TOKEN_FILE",
+	"AWS_CONTAINER_CREDENTIALS_RELATIVE_URI",
+	"AWS_CONTAINER_CREDENTIALS_FULL_URI",
+	"AWS_CONTAINER_AUTHORIZATION_TOKEN",
+	"OTEL_EXPORTER_OTLP_HEADERS",
+];
+
+/// Where named secrets are looked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretsProvider {
+	/// The sandbox's own environment: `github-token` is read from
+	/// `SANDBOX_SECRET_GITHUB_TOKEN`.
+	Env,
+	/// One file per secret in a directory, as mounted by Docker or Kubernetes.
+	File,
+	/// A HashiCorp Vault KV v2 engine; `ci/github#token` reads field `token`
+	/// of secret `ci/github`.
+	Vault,
+}
+
+impl FromStr for SecretsProvider {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"env" => Ok(Self::Env),
+			"file" => Ok(Self::File),
+			"vault" => Ok(Self::Vault),
+			_ => Err(format!("expected env, file or vault (got {s:?})")),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VaultConfig {
+	pub addr: Option<String>,
+	pub token: Option<String>,
+	/// Read on every lookup, so a rotated token is picked up.
+	pub token_file: Option<PathBuf>,
+	/// Mount point of the KV v2 engine.
+	pub mount: String,
+	/// How long a fetched secret is reused before Vault is asked again.
+	pub cache_secs: u64,
+}
+
+impl Default for VaultConfig {
+	fn default() -> Self {
+		Self {
+			addr: None,
+			token: None,
+			token_file: None,
+			mount: DEFAULT_VAULT_MOUNT.to_string(),
+			cache_secs: DEFAULT_VAULT_CACHE_SECS,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretsConfig {
+	pub provider: SecretsProvider,
+	/// `env` provider: prefix of the variables holding secrets.
+	pub env_prefix: String,
+	/// `file` provider: directory holding one file per secret.
+	pub dir: PathBuf,
+	pub vault: VaultConfig,
+}
+
+impl Default for SecretsConfig {
+	fn default() -> Self {
+		Self {
+			provider: SecretsProvider::Env,
+			env_prefix: DEFAULT_ENV_PREFIX.to_string(),
+			dir: DEFAULT_DIR.into(),
+			vault: VaultConfig::default(),
+		}
+	}
+}
+
+impl SecretsConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		match self.provider {
+			SecretsProvider::Env if self.env_prefix.is_empty() => {
+				errors.push("executor.secrets.env_prefix must not be empty".to_string())
+			}
+			SecretsProvider::File if !self.dir.is_dir() => errors.push(format!(
+				"executor.secrets.dir {} is not a directory",
+				self.dir.display()
+			)),
+			SecretsProvider::Vault => {
+				let vault = &self.vault;
+				match &vault.addr {
+					Some(addr) if addr.starts_with("http://") || addr.starts_with("https://") => {}
+					Some(addr) => errors.push(format!("executor.secrets.vault.addr must be an http(s) URL (got {addr:?})")),
+					None => errors.push("executor.secrets.vault.addr is required by the vault provider".to_string()),
+				}
+				if vault.token.is_none() && vault.token_file.is_none() {
+					errors.push("executor.secrets.vault needs a token or token_file".to_string());
+				}
+				if let Some(path) = &vault.token_file {
+					if !path.is_file() {
+						errors.push(format!("executor.secrets.vault.token_file {} does not exist", path.display()));
+					}
+				}
+			}
+			_ => {}
+		}
+		errors
+	}
+
+	/// Whether host variable `name` belongs to the sandbox's own credentials.
+	fn is_sandbox_secret(&self, name: &str) -> bool {
+		NEVER_INHERITED.contains(&name)
+			|| NEVER_INHERITED_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+			|| (!self.env_prefix.is_empty() && name.starts_with(&self.env_prefix))
+	}
+}
+
+/// A secret value. Its `Debug` output is redacted, so it can sit in structs
+/// that are logged without leaking.
+#[derive(Clone)]
+pub struct Secret(Arc<str>);
+
+impl Secret {
+	pub fn new(value: &str) -> Self {
+		Self(value.into())
+	}
+
+	pub fn expose(&self) -> &str {
+		&self.0
+	}
+}
+
+impl fmt::Debug for Secret {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Secret(<redacted>)")
+	}
+}
+
+/// What a tool's environment is made of, beyond the variables the sandbox
+/// sets itself.
+#[derive(Debug, Clone, Default)]
+pub struct ToolEnv {
+	/// Host variables that passed the allowlist.
+	pub vars: Vec<(String, String)>,
+	/// Secrets, by the variable name they are injected as.
+	pub secrets: Vec<(String, Secret)>,
+	/// UUIDs of the GPUs the execution holds.
+	pub gpus: Vec<String>,
+}
+
+/// Whether `name` matches an allowlist entry: an exact name, or a prefix
+/// ending in `*`.
+fn env_allowed(name: &str, patterns: &[&str]) -> bool {
+	patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+		Some(prefix) => name.starts_with(prefix),
+		None => name == *pattern,
+	})
+}
+
+/// Whether `pattern` is a valid env allowlist entry.
+pub fn is_valid_env_pattern(pattern: &str) -> bool {
+	let name = pattern.strip_suffix('*').unwrap_or(pattern);
+	(!name.is_empty() || pattern == "*") && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `name` can be used as an environment variable name.
+pub fn is_valid_env_name(name: &str) -> bool {
+	name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+		&& name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `name` can name a secret: path-like segments for Vault, with an
+/// optional `#field`.
+pub fn is_valid_secret_name(name: &str) -> bool {
+	let path = name.split_once('#').map_or(name, |(path, _)| path);
+	!path.is_empty()
+		&& path
+			.split('/')
+			.all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+		&& name
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | '#'))
+}
+
+/// Resolves the secrets tool manifests ask for. Values are never logged;
+/// errors name the secret, not its contents.
+pub struct SecretStore {
+	config: SecretsConfig,
+	http: reqwest::Client,
+	/// Vault only: secrets fetched recently, by name.
+	cache: Mutex<HashMap<String, (Instant, Secret)>>,
+}
+
+impl SecretStore {
+	pub fn new(config: SecretsConfig) -> Self {
+		let http = reqwest::Client::builder()
+			.timeout(VAULT_TIMEOUT)
+			.build()
+			.expect("a default reqwest client builds");
+		Self {
+			config,
+			http,
+			cache: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// The host variables matching `patterns`, minus the sandbox's own
+	/// credentials.
+	pub fn inherited_env(&self, patterns: &[&str]) -> Vec<(String, String)> {
+		if patterns.is_empty() {
+			return Vec::new();
+		}
+		std::env::vars()
+			.filter(|(name, _)| env_allowed(name, patterns) && !self.config.is_sandbox_secret(name))
+			.collect()
+	}
+
+	pub async fn get(&self, name: &str) -> Result<Secret, String> {
+		match self.config.provider {
+			SecretsProvider::Env => {
+				let var = format!(
+					"{}{}",
+					self.config.env_prefix,
+					name.to_ascii_uppercase().replace(['-', '.', '/', '#'], "_")
+				);
+				std::env::var(&var)
+					.map(|value| Secret(value.into()))
+					.map_err(|_| format!("{var} is not set"))
+			}
+			SecretsProvider::File => {
+				if name.contains(['/', '#']) {
+					return Err("file secrets are plain file names".to_string());
+				}
+				let path = self.config.dir.join(name);
+				let value = tokio::fs::read_to_string(&path)
+					.await
+					.map_err(|e| format!("{}: {e}", path.display()))?;
+				Ok(Secret(value.trim_end_matches(['\n', '\r']).into()))
+			}
+			SecretsProvider::Vault => self.get_vault(name).await,
+		}
+	}
+
+	async fn get_vault(&self, name: &str) -> Result<Secret, String> {
+		let ttl = Duration::from_secs(self.config.vault.cache_secs);
+		if let Some((fetched, secret)) = self.cache.lock().unwrap().get(name) {
+			if fetched.elapsed() < ttl {
+				return Ok(secret.clone());
+			}
+		}
+
+		let vault = &self.config.vault;
+		let addr = vault.addr.as_deref().expect("validated: vault provider has an addr");
+		let token = match (&vault.token_file, &vault.token) {
+			(Some(path), _) => std::fs::read_to_string(path)
+				.map_err(|e| format!("vault token_file {}: {e}", path.display()))?
+				.trim()
+				.to_string(),
+			(None, Some(token)) => token.clone(),
+			(None, None) => unreachable!("validated: vault provider has a token"),
+		};
+		let (path, field) = name.split_once('#').unwrap_or((name, DEFAULT_VAULT_FIELD));
+		let url = format!("{}/v1/{}/data/{path}", addr.trim_end_matches('/'), vault.mount);
+		let response = self
+			.http
+			.get(&url)
+			.header("X-Vault-Token", token)
+			.send()
+			.await
+			.map_err(|e| format!("vault request failed: {e}"))?;
+		let status = response.status();
+		if !status.is_success() {
+			return Err(format!("vault returned {status} for {path}"));
+		}
+		let body: Value = response
+			.json()
+			.await
+			.map_err(|e| format!("vault response for {path} is not JSON: {e}"))?;
+		let value = body
+			.pointer("/data/data")
+			.and_then(|data| data.get(field))
+			.and_then(Value::as_str)
+			.ok_or_else(|| format!("vault secret {path} has no string field {field:?}"))?;
+		let secret = Secret(value.into());
+		self.cache
+			.lock()
+			.unwrap()
+			.insert(name.to_string(), (Instant::now(), secret.clone()));
+		Ok(secret)
+	}
+}
@@ -0,0 +1,239 @@
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderName, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::error::SandboxError;
+use crate::metrics::METRICS;
+
+pub const DEFAULT_REGISTRY_KEY_PREFIX: &str = "pagi-sandbox:sessions";
+/// Set on requests an instance sends on, so that the receiving one answers
+/// them itself even if its registry entry is stale.
+pub const FORWARDED_HEADER: &str = "x-sandbox-forwarded-by";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Headers that describe one connection rather than the request.
+const HOP_BY_HOP: &[&str] = &[
+	"connection",
+	"keep-alive",
+	"proxy-authenticate",
+	"proxy-authorization",
+	"te",
+	"trailer",
+	"transfer-encoding",
+	"upgrade",
+	"host",
+	"content-length",
+];
+
+/// How a request for a session held by another instance gets there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionRouting {
+	/// Send the request on and relay the response.
+	#[default]
+	Proxy,
+	/// Answer `307 Temporary Redirect` to the instance's advertised URL.
+	Redirect,
+}
+
+impl fmt::Display for SessionRouting {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Proxy => "proxy",
+			Self::Redirect => "redirect",
+		})
+	}
+}
+
+impl FromStr for SessionRouting {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"proxy" => Ok(Self::Proxy),
+			"redirect" => Ok(Self::Redirect),
+			_ => Err(format!("unknown session routing {s:?} (proxy or redirect)")),
+		}
+	}
+}
+
+/// Where a session lives, as the registry stores it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+	/// Advertised URL of the instance holding the session.
+	url: String,
+	/// The client the session belongs to.
+	owner: String,
+}
+
+/// Which instance holds which session, shared through Redis by every
+/// instance behind one load balancer.
+///
+/// An instance registers the sessions it starts under its advertised URL and
+/// renews each entry as the session is called, so that an entry outlives its
+/// session by no more than the idle timeout. A request for a session another
+/// instance holds is proxied or redirected there; the receiving instance
+/// checks it again like any request.
+pub struct SessionRegistry {
+	connection: ConnectionManager,
+	key_prefix: String,
+	advertise_url: String,
+	routing: SessionRouting,
+	/// How long an entry lasts without being renewed.
+	ttl: Duration,
+	http: reqwest::Client,
+}
+
+impl SessionRegistry {
+	pub async fn connect(
+		redis_url: &str,
+		key_prefix: &str,
+		advertise_url: &str,
+		routing: SessionRouting,
+		ttl: Duration,
+	) -> Result<Self, String> {
+		let client = redis::Client::open(redis_url).map_err(|e| format!("sessions.registry_url: {e}"))?;
+		let connection = client
+			.get_connection_manager()
+			.await
+			.map_err(|e| format!("sessions.registry_url: {e}"))?;
+		let http = reqwest::Client::builder()
+			.connect_timeout(CONNECT_TIMEOUT)
+			.redirect(reqwest::redirect::Policy::none())
+			.build()
+			.map_err(|e| format!("session routing client: {e}"))?;
+		let advertise_url = advertise_url.trim_end_matches('/').to_string();
+		info!(advertise_url = %advertise_url, routing = %routing, message = "Session registry ready");
+		Ok(Self {
+			connection,
+			key_prefix: key_prefix.to_string(),
+			advertise_url,
+			routing,
+			ttl,
+			http,
+		})
+	}
+
+	fn key(&self, session_id: &str) -> String {
+		format!("{}:{session_id}", self.key_prefix)
+	}
+
+	/// Record that this instance holds `session_id`. Failures are logged: the
+	/// session still works through this instance.
+	pub async fn register(&self, session_id: &str, owner: &str) {
+		let entry = Entry {
+			url: self.advertise_url.clone(),
+			owner: owner.to_string(),
+		};
+		let entry = serde_json::to_string(&entry).expect("a registry entry serializes");
+		let ttl_ms = self.ttl.as_millis() as u64;
+		let stored: redis::RedisResult<()> = self.connection.clone().pset_ex(self.key(session_id), entry, ttl_ms).await;
+		if let Err(e) = stored {
+			warn!(session_id = session_id, error = %e, message = "Unable to register session; other instances will not find it");
+		}
+	}
+
+	/// Keep the entry of a session that was just called.
+	pub async fn renew(&self, session_id: &str) {
+		let ttl_ms = self.ttl.as_millis() as i64;
+		let renewed: redis::RedisResult<()> = self.connection.clone().pexpire(self.key(session_id), ttl_ms).await;
+		if let Err(e) = renewed {
+			warn!(session_id = session_id, error = %e, message = "Unable to renew session registration");
+		}
+	}
+
+	pub async fn remove(&self, session_id: &str) {
+		let removed: redis::RedisResult<()> = self.connection.clone().del(self.key(session_id)).await;
+		if let Err(e) = removed {
+			warn!(session_id = session_id, error = %e, message = "Unable to remove session registration");
+		}
+	}
+
+	/// The advertised URL of the other instance holding `owner`'s session
+	/// `session_id`, unless `headers` show the request was already sent on.
+	pub async fn locate(&self, session_id: &str, owner: &str, headers: &HeaderMap) -> Option<String> {
+		if headers.contains_key(FORWARDED_HEADER) {
+			return None;
+		}
+		let entry: redis::RedisResult<Option<String>> = self.connection.clone().get(self.key(session_id)).await;
+		let entry = match entry {
+			Ok(entry) => entry?,
+			Err(e) => {
+				warn!(session_id = session_id, error = %e, message = "Unable to look up session registration");
+				return None;
+			}
+		};
+		let entry: Entry = serde_json::from_str(&entry).ok()?;
+		// Sessions of other clients stay not found, wherever they are.
+		(entry.owner == owner && entry.url != self.advertise_url).then_some(entry.url)
+	}
+
+	/// Send a request on to the instance at `url`, or redirect the client
+	/// there. `path` includes the query, if any.
+	pub async fn route(&self, url: &str, method: Method, path: &str, headers: &HeaderMap, body: Option<Value>) -> Response {
+		let target = format!("{url}{path}");
+		if self.routing == SessionRouting::Redirect {
+			METRICS.session_routes.with_label_values(&["redirected"]).inc();
+			return (StatusCode::TEMPORARY_REDIRECT, [(header::LOCATION, target)]).into_response();
+		}
+
+		match relay(&self.http, &target, method, headers, body, &self.advertise_url).await {
+			Ok(response) => {
+				METRICS.session_routes.with_label_values(&["proxied"]).inc();
+				response
+			}
+			Err(e) => unreachable(url, &e.to_string()),
+		}
+	}
+}
+
+/// Send a request on to `target` on behalf of the instance advertised at
+/// `forwarded_by`, and relay the response. The body is sent on as JSON,
+/// whatever it arrived as.
+pub async fn relay(
+	http: &reqwest::Client,
+	target: &str,
+	method: Method,
+	headers: &HeaderMap,
+	body: Option<Value>,
+	forwarded_by: &str,
+) -> Result<Response, reqwest::Error> {
+	let mut forwarded = HeaderMap::new();
+	for (name, value) in headers {
+		if !HOP_BY_HOP.contains(&name.as_str()) && name != header::CONTENT_TYPE && name != header::CONTENT_ENCODING {
+			forwarded.append(name.clone(), value.clone());
+		}
+	}
+	forwarded.insert(
+		HeaderName::from_static(FORWARDED_HEADER),
+		forwarded_by.parse().expect("validated as a URL"),
+	);
+	let mut request = http.request(method, target).headers(forwarded);
+	if let Some(body) = body {
+		request = request.json(&body);
+	}
+	let reply = request.send().await?;
+	let status = reply.status();
+	let mut relayed = HeaderMap::new();
+	for (name, value) in reply.headers() {
+		if !HOP_BY_HOP.contains(&name.as_str()) {
+			relayed.append(name.clone(), value.clone());
+		}
+	}
+	let bytes = reply.bytes().await?;
+	Ok((status, relayed, Body::from(bytes)).into_response())
+}
+
+/// The error is logged rather than returned, as it names the instance.
+fn unreachable(url: &str, error: &str) -> Response {
+	METRICS.session_routes.with_label_values(&["failed"]).inc();
+	warn!(instance = url, error = error, message = "Unable to reach the instance holding a session");
+	SandboxError::BadGateway("the instance holding the session is unreachable".to_string()).into_response()
+}
@@ -0,0 +1,112 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tracing::warn;
+
+use crate::subprocess::{self, CommandSpec};
+
+/// Longest a checkpoint may take; a process that big is better left
+/// running.
+const DUMP_TIMEOUT: Duration = Duration::from_secs(60);
+/// Log lines quoted in the error when CRIU fails.
+const LOG_TAIL_LINES: usize = 3;
+
+/// A session process checkpointed to disk with CRIU, and stopped.
+///
+/// The images hold its memory, registers and open files; restored, it keeps
+/// its PID and carries on where it was, in the workspace it left, which must
+/// still be there. Its stdin, stdout and stderr were pipes to the sandbox,
+/// and the restore hands it new ones in their place.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+	criu: String,
+	images: PathBuf,
+	/// The pipes it had as stdin, stdout and stderr, as CRIU names them
+	/// (`pipe:[inode]`).
+	stdio: Vec<String>,
+}
+
+impl Checkpoint {
+	/// Checkpoint the process tree led by `pid` into `images`, replacing
+	/// whatever is there, and kill it.
+	pub async fn dump(criu: &str, pid: u32, images: PathBuf) -> Result<Self, String> {
+		let mut stdio = Vec::new();
+		for fd in 0..3 {
+			let link = fs::read_link(format!("/proc/{pid}/fd/{fd}"))
+				.await
+				.map_err(|e| format!("reading fd {fd} of process {pid}: {e}"))?;
+			let link = link.to_string_lossy().to_string();
+			if !link.starts_with("pipe:[") {
+				return Err(format!("fd {fd} of process {pid} is {link}, not a pipe from the sandbox"));
+			}
+			stdio.push(link);
+		}
+		remove_images(&images).await;
+		fs::create_dir_all(&images)
+			.await
+			.map_err(|e| format!("{}: {e}", images.display()))?;
+		let checkpoint = Self {
+			criu: criu.to_string(),
+			images,
+			stdio,
+		};
+		let spec = checkpoint
+			.command("dump")
+			.arg("--tree")
+			.arg(pid.to_string())
+			.timeout(DUMP_TIMEOUT);
+		let out = subprocess::run(&spec)
+			.await
+			.map_err(|e| format!("failed to run {criu}: {e}"))?;
+		if !out.success() {
+			let error = format!("{criu} dump: {}: {}", out.status(), checkpoint.log_tail("dump.log").await);
+			remove_images(&checkpoint.images).await;
+			return Err(error);
+		}
+		Ok(checkpoint)
+	}
+
+	/// What restores the process: `criu restore` in the foreground, which
+	/// waits for the restored tree and exits as its leader does, giving it
+	/// its own stdin, stdout and stderr for the old pipes. Cgroups are left
+	/// as `criu` finds them, so the process joins the restoring execution's.
+	pub fn restore_command(&self) -> CommandSpec {
+		let mut spec = self.command("restore");
+		for (fd, pipe) in self.stdio.iter().enumerate() {
+			spec = spec.arg("--inherit-fd").arg(format!("fd[{fd}]:{pipe}"));
+		}
+		spec
+	}
+
+	fn command(&self, action: &str) -> CommandSpec {
+		CommandSpec::new(&self.criu)
+			.arg(action)
+			.arg("--images-dir")
+			.arg(self.images.to_string_lossy())
+			.arg("--log-file")
+			.arg(format!("{action}.log"))
+			.arg("--shell-job")
+			.arg("--file-locks")
+			.arg("--manage-cgroups=ignore")
+	}
+
+	/// The last errors CRIU logged, which is where it explains a failure.
+	async fn log_tail(&self, log: &str) -> String {
+		let Ok(text) = fs::read_to_string(self.images.join(log)).await else {
+			return "no log".to_string();
+		};
+		let errors: Vec<&str> = text.lines().filter(|line| line.contains("Error")).collect();
+		let tail = if errors.is_empty() { text.lines().collect() } else { errors };
+		tail[tail.len().saturating_sub(LOG_TAIL_LINES)..].join("; ")
+	}
+}
+
+/// Delete a checkpoint's images, if there are any.
+pub async fn remove_images(images: &Path) {
+	if let Err(e) = fs::remove_dir_all(images).await {
+		if e.kind() != io::ErrorKind::NotFound {
+			warn!(images = %images.display(), error = %e, message = "Failed to remove a session checkpoint");
+		}
+	}
+}
@@ -0,0 +1,821 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn, Instrument};
+
+use crate::audit::Caller;
+use crate::clock::now_ms;
+use crate::executions::ProcessList;
+use crate::metrics::METRICS;
+use crate::ratelimit::client_key;
+use crate::scheduler::Priority;
+use crate::session_registry::{SessionRegistry, SessionRouting, DEFAULT_REGISTRY_KEY_PREFIX};
+use crate::session_snapshot::{remove_images, Checkpoint};
+use crate::subprocess::{OutputChunk, StdinStream, StreamKind};
+use crate::tool::{
+	execute_tool_request_with_context, queue_full_response, result_body, ToolExecutionRequest, ToolExecutionResponse,
+};
+use crate::tool_executor::{ExecutionBackend, ExecutionContext, Executor, ToolResult};
+
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 300;
+const DEFAULT_MAX_LIFETIME_SECS: u64 = 3600;
+const DEFAULT_MAX_PER_CLIENT: usize = 4;
+const DEFAULT_MAX_SESSIONS: usize = 64;
+/// How often sessions are checked for having been idle too long.
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a tool whose stdin was closed gets to exit before it is killed.
+const STOP_GRACE: Duration = Duration::from_secs(2);
+const STDIN_BUFFER: usize = 16;
+const CHUNK_BUFFER: usize = 256;
+const REPLY_BUFFER: usize = 16;
+/// Stderr held for the next call; older output is dropped first.
+const MAX_PENDING_STDERR: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionsConfig {
+	/// A session nobody has called for this long is torn down.
+	pub idle_timeout_secs: u64,
+	/// Longest a session's process may run, however busy it is; a create
+	/// request's `timeout_ms` can only shorten it.
+	pub max_lifetime_secs: u64,
+	/// Running sessions one client (API key subject, or address) may hold.
+	pub max_per_client: usize,
+	/// Running sessions across all clients.
+	pub max_sessions: usize,
+	/// `redis://` or `rediss://` server where instances record which of them
+	/// holds which session; unset, every instance only knows its own.
+	pub registry_url: Option<String>,
+	/// Prefix of the registry's Redis keys.
+	pub registry_key_prefix: String,
+	/// Base URL at which the other instances reach this one, e.g.
+	/// `http://10.0.0.5:8080`; required with `registry_url`.
+	pub advertise_url: Option<String>,
+	/// How requests for a session on another instance get there.
+	pub routing: SessionRouting,
+	/// A native tool's session idle this long is checkpointed to disk with
+	/// CRIU and its process stopped, until the next call restores it; unset,
+	/// sessions stay in memory until they idle out.
+	pub evict_after_secs: Option<u64>,
+	/// Where the checkpoints of evicted sessions are kept.
+	pub snapshot_dir: PathBuf,
+	/// The CRIU binary, run as a user allowed to checkpoint the tools
+	/// (root, or with `CAP_CHECKPOINT_RESTORE`).
+	pub criu: String,
+}
+
+impl Default for SessionsConfig {
+	fn default() -> Self {
+		Self {
+			idle_timeout_secs: DEFAULT_IDLE_TIMEOUT_SECS,
+			max_lifetime_secs: DEFAULT_MAX_LIFETIME_SECS,
+			max_per_client: DEFAULT_MAX_PER_CLIENT,
+			max_sessions: DEFAULT_MAX_SESSIONS,
+			registry_url: None,
+			registry_key_prefix: DEFAULT_REGISTRY_KEY_PREFIX.to_string(),
+			advertise_url: None,
+			routing: SessionRouting::default(),
+			evict_after_secs: None,
+			snapshot_dir: PathBuf::from("sandbox_snapshots"),
+			criu: "criu".to_string(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionState {
+	Running,
+	/// The process is checkpointed to disk; the next call restores it.
+	Evicted,
+	/// The process is gone; the session is kept until it is deleted or
+	/// idles out so that its final result can still be fetched.
+	Exited,
+}
+
+/// Snapshot of a session as returned by the sessions API.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionView {
+	pub session_id: String,
+	pub tool_name: String,
+	pub state: SessionState,
+	pub created_at_ms: u64,
+	pub last_used_at_ms: u64,
+	pub calls: u64,
+	/// When the session is torn down unless it is called again first.
+	pub expires_at_ms: u64,
+}
+
+/// Why a session could not be started or called.
+#[derive(Debug)]
+pub enum SessionError {
+	NotFound,
+	/// The tool cannot hold a session (it does not read a live stdin).
+	Unsupported(String),
+	/// The call names a different tool than the session runs.
+	WrongTool(String),
+	LimitReached(String),
+}
+
+impl SessionError {
+	pub fn message(&self) -> String {
+		match self {
+			Self::NotFound => "session not found".to_string(),
+			Self::Unsupported(m) | Self::WrongTool(m) | Self::LimitReached(m) => m.clone(),
+		}
+	}
+}
+
+struct Activity {
+	last_used: Instant,
+	last_used_at_ms: u64,
+	calls: u64,
+}
+
+struct Session {
+	id: String,
+	tool_name: String,
+	/// The startup args, which the restores of its process are recorded with.
+	args: Value,
+	owner: String,
+	caller: Caller,
+	priority: Priority,
+	artifacts: Vec<String>,
+	created_at_ms: u64,
+	lifetime: Duration,
+	activity: Mutex<Activity>,
+	/// Dropped to close the tool's stdin.
+	stdin: Mutex<Option<mpsc::Sender<Vec<u8>>>>,
+	/// Stdout lines, one per call. Holding the lock is holding the session:
+	/// calls take turns.
+	replies: tokio::sync::Mutex<mpsc::Receiver<String>>,
+	stderr: Arc<Mutex<Vec<u8>>>,
+	/// The response for the whole run, once the process has exited.
+	ended: watch::Sender<Option<ToolExecutionResponse>>,
+	/// Cancels the process's current run.
+	cancel: Mutex<CancellationToken>,
+	/// The current run, awaited when its process is checkpointed.
+	run: Mutex<Option<JoinHandle<ToolExecutionResponse>>>,
+	/// Checkpointed when idle; its workspace is then kept for the restores.
+	evictable: bool,
+	/// The caller asked for the workspace, rather than only the restores.
+	retain_workspace: bool,
+	/// The first run's processes, where the tool's PID is found; a restored
+	/// process keeps its PID.
+	processes: ProcessList,
+	pid: Mutex<Option<u32>>,
+	/// Set while the process is checkpointed, so that its run ending does not
+	/// end the session.
+	evicting: AtomicBool,
+	/// While evicted: the checkpoint, and the response of the run it stopped.
+	evicted: Mutex<Option<(Checkpoint, ToolExecutionResponse)>>,
+}
+
+impl Session {
+	fn view(&self, idle_timeout: Duration) -> SessionView {
+		let activity = self.activity.lock().unwrap();
+		let state = if self.ended.borrow().is_some() {
+			SessionState::Exited
+		} else if self.evicted.lock().unwrap().is_some() {
+			SessionState::Evicted
+		} else {
+			SessionState::Running
+		};
+		let mut expires_at_ms = activity.last_used_at_ms + idle_timeout.as_millis() as u64;
+		if state != SessionState::Exited {
+			expires_at_ms = expires_at_ms.min(self.created_at_ms + self.lifetime.as_millis() as u64);
+		}
+		SessionView {
+			session_id: self.id.clone(),
+			tool_name: self.tool_name.clone(),
+			state,
+			created_at_ms: self.created_at_ms,
+			last_used_at_ms: activity.last_used_at_ms,
+			calls: activity.calls,
+			expires_at_ms,
+		}
+	}
+
+	fn touch(&self) {
+		let mut activity = self.activity.lock().unwrap();
+		activity.last_used = Instant::now();
+		activity.last_used_at_ms = now_ms();
+		activity.calls += 1;
+	}
+
+	fn idle_for(&self) -> Duration {
+		self.activity.lock().unwrap().last_used.elapsed()
+	}
+
+	fn take_stderr(&self) -> String {
+		let bytes = std::mem::take(&mut *self.stderr.lock().unwrap());
+		String::from_utf8_lossy(&bytes).to_string()
+	}
+
+	async fn wait_ended(&self) -> ToolExecutionResponse {
+		let mut ended = self.ended.subscribe();
+		let response = ended
+			.wait_for(Option::is_some)
+			.await
+			.expect("the session holds the sender");
+		response.clone().expect("waited for a response")
+	}
+
+	/// Close the tool's stdin so that it can exit on its own, kill it if it
+	/// has not after a grace period, and return the run's response.
+	async fn stop(&self) -> ToolExecutionResponse {
+		self.stdin.lock().unwrap().take();
+		match tokio::time::timeout(STOP_GRACE, self.wait_ended()).await {
+			Ok(response) => response,
+			Err(_) => {
+				self.cancel();
+				self.wait_ended().await
+			}
+		}
+	}
+
+	fn cancel(&self) {
+		self.cancel.lock().unwrap().cancel();
+	}
+}
+
+/// Long-lived tool processes that several `execute_tool` calls talk to.
+///
+/// A session runs its tool once, like any execution, in its own workspace
+/// and holding one execution slot for as long as it lives. Each call writes
+/// its args to the tool's stdin as one line of JSON and takes the next line
+/// the tool prints as its result, so state (an interpreter's variables, files
+/// in the workspace) carries over from call to call. Sessions belong to the
+/// client that created them and end when deleted, when idle for too long,
+/// when their lifetime runs out, or when the tool exits. With a registry,
+/// other instances can find the sessions held here.
+///
+/// With `evict_after_secs`, a native session left idle that long is
+/// checkpointed to disk and its process stopped, giving back its memory and
+/// slot; the next call restores it, as an execution of its own, before
+/// sending its args. Other backends stay resident: WASM components cannot be
+/// serialized mid-run, and containers and microVMs are not checkpointed.
+pub struct SessionManager {
+	executor: Arc<Executor>,
+	config: SessionsConfig,
+	sessions: Mutex<HashMap<String, Arc<Session>>>,
+	registry: Option<SessionRegistry>,
+}
+
+impl SessionManager {
+	/// Connects to the session registry when `config.registry_url` is set.
+	pub async fn new(executor: Arc<Executor>, config: SessionsConfig) -> Result<Self, String> {
+		let registry = match (&config.registry_url, &config.advertise_url) {
+			(Some(url), Some(advertise_url)) => {
+				// An exited session is kept until it idles out; the entry
+				// lasts until the reaper has had a chance to remove it.
+				let ttl = Duration::from_secs(config.idle_timeout_secs) + 2 * REAP_INTERVAL;
+				let registry =
+					SessionRegistry::connect(url, &config.registry_key_prefix, advertise_url, config.routing, ttl).await?;
+				Some(registry)
+			}
+			_ => None,
+		};
+		Ok(Self {
+			executor,
+			config,
+			sessions: Mutex::new(HashMap::new()),
+			registry,
+		})
+	}
+
+	/// Where requests for sessions this instance does not hold are looked up.
+	pub fn registry(&self) -> Option<&SessionRegistry> {
+		self.registry.as_ref()
+	}
+
+	/// Whether the session is held here, whoever it belongs to.
+	pub fn holds(&self, session_id: &str) -> bool {
+		self.sessions.lock().unwrap().contains_key(session_id)
+	}
+
+	fn idle_timeout(&self) -> Duration {
+		Duration::from_secs(self.config.idle_timeout_secs)
+	}
+
+	/// Start a session running `req`, admitted as `ctx`. `req.args` are the
+	/// tool's startup args; `req.timeout_ms` may shorten the lifetime.
+	pub async fn start(
+		self: &Arc<Self>,
+		req: ToolExecutionRequest,
+		ctx: ExecutionContext,
+	) -> Result<SessionView, SessionError> {
+		let backend = self.executor.registry().get(&req.tool_name).map(|tool| tool.backend);
+		if !backend.is_some_and(ExecutionBackend::streams_live) {
+			return Err(SessionError::Unsupported(format!(
+				"{} does not read a live stdin and cannot hold a session; only native and container tools can",
+				req.tool_name
+			)));
+		}
+
+		let owner = client_key(&ctx.caller);
+		let max_lifetime = Duration::from_secs(self.config.max_lifetime_secs);
+		let lifetime = req
+			.timeout_ms
+			.filter(|ms| *ms > 0)
+			.map(Duration::from_millis)
+			.map_or(max_lifetime, |requested| requested.min(max_lifetime));
+		let evictable = self.config.evict_after_secs.is_some() && backend == Some(ExecutionBackend::Native);
+		let retain_workspace = ctx.retain_workspace;
+		let stderr = Arc::new(Mutex::new(Vec::new()));
+		let (mut ctx, stdin, replies) = wire(ctx, &stderr);
+		ctx.timeout = lifetime;
+		// A restored process needs the workspace it was checkpointed in.
+		ctx.retain_workspace |= evictable;
+		let created_at_ms = now_ms();
+		let session = Arc::new(Session {
+			id: ctx.workspace_id.clone(),
+			tool_name: req.tool_name.clone(),
+			args: req.args.clone(),
+			owner: owner.clone(),
+			caller: ctx.caller.clone(),
+			priority: ctx.priority,
+			artifacts: ctx.artifacts.clone(),
+			created_at_ms,
+			lifetime,
+			activity: Mutex::new(Activity {
+				last_used: Instant::now(),
+				last_used_at_ms: created_at_ms,
+				calls: 0,
+			}),
+			stdin: Mutex::new(Some(stdin)),
+			replies: tokio::sync::Mutex::new(replies),
+			stderr,
+			ended: watch::Sender::new(None),
+			cancel: Mutex::new(ctx.cancel.clone()),
+			run: Mutex::new(None),
+			evictable,
+			retain_workspace,
+			processes: ctx.processes.clone(),
+			pid: Mutex::new(None),
+			evicting: AtomicBool::new(false),
+			evicted: Mutex::new(None),
+		});
+
+		{
+			let mut sessions = self.sessions.lock().unwrap();
+			let running = |s: &&Arc<Session>| s.ended.borrow().is_none();
+			if sessions.values().filter(running).count() >= self.config.max_sessions {
+				return Err(SessionError::LimitReached(format!(
+					"the server is holding its maximum of {} sessions",
+					self.config.max_sessions
+				)));
+			}
+			let held = sessions.values().filter(running).filter(|s| s.owner == owner).count();
+			if held >= self.config.max_per_client {
+				return Err(SessionError::LimitReached(format!(
+					"a client may hold {} sessions; delete one first",
+					self.config.max_per_client
+				)));
+			}
+			sessions.insert(session.id.clone(), session.clone());
+		}
+		METRICS.live_sessions.inc();
+		self.spawn_run(&session, req, ctx);
+
+		if let Some(registry) = &self.registry {
+			registry.register(&session.id, &owner).await;
+		}
+		info!(session_id = session.id, tool_name = session.tool_name, owner = owner, message = "Session started");
+		Ok(session.view(self.idle_timeout()))
+	}
+
+	/// Run the session's process: its start, or the restore of its
+	/// checkpoint. Unless the process is being checkpointed, the run ending
+	/// ends the session.
+	fn spawn_run(self: &Arc<Self>, session: &Arc<Session>, req: ToolExecutionRequest, ctx: ExecutionContext) {
+		let manager = self.clone();
+		let run = session.clone();
+		let handle = tokio::spawn(
+			async move {
+				let tool_name = req.tool_name.clone();
+				let executor = manager.executor.clone();
+				let task = tokio::spawn(
+					async move { execute_tool_request_with_context(&executor, req, &ctx).await }.in_current_span(),
+				);
+				let response = task.await.unwrap_or_else(|e| ToolExecutionResponse {
+					status: "internal_error".to_string(),
+					tool_name,
+					result: json!({ "stdout": "", "stderr": e.to_string(), "exit_code": null }),
+				});
+				if !run.evicting.load(Ordering::SeqCst) {
+					info!(
+						session_id = run.id,
+						tool_name = run.tool_name,
+						status = response.status,
+						message = "Session process exited"
+					);
+					METRICS.live_sessions.dec();
+					manager.finish(&run, response.clone()).await;
+				}
+				response
+			}
+			.in_current_span(),
+		);
+		*session.run.lock().unwrap() = Some(handle);
+	}
+
+	/// The session's process is gone for good: drop what was kept only to
+	/// restore it, and record the response for its whole run.
+	async fn finish(&self, session: &Session, mut response: ToolExecutionResponse) {
+		if session.evictable {
+			remove_images(&self.images(session)).await;
+			if !session.retain_workspace {
+				self.executor.remove_workspace(&session.id).await;
+				if let Some(result) = response.result.as_object_mut() {
+					result.remove("workspace_id");
+				}
+			}
+		}
+		session.ended.send_replace(Some(response));
+	}
+
+	fn images(&self, session: &Session) -> PathBuf {
+		self.config.snapshot_dir.join(&session.id)
+	}
+
+	/// Checkpoint an idle session's process to disk and stop it. A session
+	/// in the middle of a call, or whose process cannot be checkpointed, is
+	/// left running.
+	async fn evict(self: &Arc<Self>, session: &Arc<Session>) {
+		let Ok(_turn) = session.replies.try_lock() else {
+			return;
+		};
+		if session.ended.borrow().is_some() || session.evicted.lock().unwrap().is_some() {
+			return;
+		}
+		let pid = {
+			let mut pid = session.pid.lock().unwrap();
+			if pid.is_none() {
+				*pid = session.processes.pids().first().copied();
+			}
+			*pid
+		};
+		let Some(pid) = pid else {
+			return;
+		};
+		session.evicting.store(true, Ordering::SeqCst);
+		let checkpoint = match Checkpoint::dump(&self.config.criu, pid, self.images(session)).await {
+			Ok(checkpoint) => checkpoint,
+			Err(e) => {
+				session.evicting.store(false, Ordering::SeqCst);
+				METRICS.session_snapshots.with_label_values(&["failed"]).inc();
+				warn!(session_id = session.id, error = %e, message = "Failed to checkpoint an idle session; keeping it running");
+				// Its process may have exited meanwhile, unnoticed.
+				let run = {
+					let mut run = session.run.lock().unwrap();
+					match run.as_ref().is_some_and(JoinHandle::is_finished) {
+						true => run.take(),
+						false => None,
+					}
+				};
+				if let Some(run) = run {
+					METRICS.live_sessions.dec();
+					self.finish(session, run.await.expect("session runs do not panic")).await;
+				}
+				return;
+			}
+		};
+		let run = session.run.lock().unwrap().take();
+		let response = match run {
+			Some(run) => run.await.expect("session runs do not panic"),
+			None => return,
+		};
+		*session.evicted.lock().unwrap() = Some((checkpoint, response));
+		session.evicting.store(false, Ordering::SeqCst);
+		METRICS.live_sessions.dec();
+		METRICS.evicted_sessions.inc();
+		METRICS.session_snapshots.with_label_values(&["evicted"]).inc();
+		info!(session_id = session.id, tool_name = session.tool_name, pid = pid, message = "Session idle; checkpointed it to disk");
+		// Deleted while it was being checkpointed.
+		if !self.holds(&session.id) {
+			self.stop(session).await;
+		}
+	}
+
+	/// Bring an evicted session's process back from `checkpoint`, admitted
+	/// like a new execution, its replies going to `replies`. Fails when
+	/// there is no room for it.
+	fn restore(
+		self: &Arc<Self>,
+		session: &Arc<Session>,
+		checkpoint: Checkpoint,
+		replies: &mut mpsc::Receiver<String>,
+	) -> Result<(), ToolExecutionResponse> {
+		let ctx = self
+			.executor
+			.context(&session.tool_name, None)
+			.map_err(|e| queue_full_response(&session.tool_name, &e))?
+			.with_caller(session.caller.clone())
+			.with_retained_workspace(true)
+			.with_artifacts(session.artifacts.clone())
+			.with_priority_at_most(session.priority);
+		let (mut ctx, stdin, restored_replies) = wire(ctx, &session.stderr);
+		let lived = Duration::from_millis(now_ms().saturating_sub(session.created_at_ms));
+		ctx.timeout = session.lifetime.saturating_sub(lived);
+		ctx.workspace_id = session.id.clone();
+		ctx.restore = Some(checkpoint);
+		*session.stdin.lock().unwrap() = Some(stdin);
+		*session.cancel.lock().unwrap() = ctx.cancel.clone();
+		*replies = restored_replies;
+		METRICS.evicted_sessions.dec();
+		METRICS.live_sessions.inc();
+		METRICS.session_snapshots.with_label_values(&["restored"]).inc();
+		info!(session_id = session.id, tool_name = session.tool_name, message = "Restoring a checkpointed session");
+		self.spawn_run(session, ToolExecutionRequest::new(&session.tool_name, session.args.clone()), ctx);
+		Ok(())
+	}
+
+	/// Send `req.args` to the session and wait up to `timeout` for its reply.
+	///
+	/// A session that does not reply in time is stopped, since a late reply
+	/// would be taken for the next call's. The call runs on its own task, so
+	/// a caller that goes away does not leave its reply behind either.
+	pub async fn call(
+		self: &Arc<Self>,
+		session_id: &str,
+		caller: &Caller,
+		req: ToolExecutionRequest,
+		timeout: Duration,
+	) -> Result<ToolExecutionResponse, SessionError> {
+		let session = self.lookup(session_id, caller)?;
+		if session.tool_name != req.tool_name {
+			return Err(SessionError::WrongTool(format!(
+				"session {session_id} runs {}, not {}",
+				session.tool_name, req.tool_name
+			)));
+		}
+		let tool_name = req.tool_name.clone();
+		let manager = self.clone();
+		let caller = caller.clone();
+		let task = tokio::spawn(
+			async move {
+				let response = manager.exchange(&session, &req, timeout).await;
+				if let Some(registry) = &manager.registry {
+					registry.renew(&session.id).await;
+				}
+				manager
+					.executor
+					.audit()
+					.record(&caller, &req.tool_name, &req.args, &response.status);
+				response
+			}
+			.in_current_span(),
+		);
+		Ok(task.await.unwrap_or_else(|e| ToolExecutionResponse {
+			status: "internal_error".to_string(),
+			tool_name,
+			result: json!({ "error": e.to_string() }),
+		}))
+	}
+
+	async fn exchange(
+		self: &Arc<Self>,
+		session: &Arc<Session>,
+		req: &ToolExecutionRequest,
+		timeout: Duration,
+	) -> ToolExecutionResponse {
+		let mut replies = session.replies.lock().await;
+		let evicted = session.evicted.lock().unwrap().take();
+		if let Some((checkpoint, response)) = evicted {
+			if let Err(refused) = self.restore(session, checkpoint.clone(), &mut replies) {
+				*session.evicted.lock().unwrap() = Some((checkpoint, response));
+				return refused;
+			}
+		}
+		session.touch();
+		let mut line = req.args.to_string().into_bytes();
+		line.push(b'\n');
+		let stdin = session.stdin.lock().unwrap().clone();
+		let reply = tokio::time::timeout(timeout, async {
+			// A tool that has closed its stdin may still print what it owes.
+			if let Some(stdin) = stdin {
+				let _ = stdin.send(line).await;
+			}
+			replies.recv().await
+		})
+		.await;
+		drop(replies);
+
+		match reply {
+			Ok(Some(stdout)) => {
+				let mut result = result_body(&ToolResult {
+					status: "ok".to_string(),
+					stdout,
+					stderr: session.take_stderr(),
+					..Default::default()
+				});
+				result["session_id"] = json!(session.id);
+				ToolExecutionResponse {
+					status: "ok".to_string(),
+					tool_name: session.tool_name.clone(),
+					result,
+				}
+			}
+			Ok(None) => {
+				let ended = session.wait_ended().await;
+				let mut result = ended.result;
+				result["error"] = json!("the session's process exited");
+				result["session_id"] = json!(session.id);
+				result["session_status"] = json!(ended.status);
+				ToolExecutionResponse {
+					status: "session_ended".to_string(),
+					tool_name: session.tool_name.clone(),
+					result,
+				}
+			}
+			Err(_) => {
+				info!(session_id = session.id, timeout_ms = timeout.as_millis() as u64, message = "Session call timed out; stopping the session");
+				session.cancel();
+				ToolExecutionResponse {
+					status: "timed_out".to_string(),
+					tool_name: session.tool_name.clone(),
+					result: json!({
+						"error": format!("no reply within {} ms; the session was stopped", timeout.as_millis()),
+						"stderr": session.take_stderr(),
+						"session_id": session.id,
+					}),
+				}
+			}
+		}
+	}
+
+	pub fn get(&self, session_id: &str, caller: &Caller) -> Result<SessionView, SessionError> {
+		Ok(self.lookup(session_id, caller)?.view(self.idle_timeout()))
+	}
+
+	/// The caller's sessions, oldest first.
+	pub fn list(&self, caller: &Caller) -> Vec<SessionView> {
+		let owner = client_key(caller);
+		let mut views: Vec<_> = self
+			.sessions
+			.lock()
+			.unwrap()
+			.values()
+			.filter(|s| s.owner == owner)
+			.map(|s| s.view(self.idle_timeout()))
+			.collect();
+		views.sort_by_key(|v| v.created_at_ms);
+		views
+	}
+
+	/// Tear a session down, returning its last state and the response for
+	/// its whole run (with its workspace and artifacts, if requested).
+	pub async fn close(&self, session_id: &str, caller: &Caller) -> Result<(SessionView, ToolExecutionResponse), SessionError> {
+		let session = self.lookup(session_id, caller)?;
+		self.sessions.lock().unwrap().remove(session_id);
+		if let Some(registry) = &self.registry {
+			registry.remove(session_id).await;
+		}
+		let response = self.stop(&session).await;
+		info!(session_id = session_id, message = "Session deleted");
+		Ok((session.view(self.idle_timeout()), response))
+	}
+
+	/// Stop every session, for shutdown.
+	pub async fn close_all(&self) {
+		let sessions: Vec<_> = self.sessions.lock().unwrap().drain().map(|(_, s)| s).collect();
+		futures::future::join_all(sessions.iter().map(|s| async {
+			if let Some(registry) = &self.registry {
+				registry.remove(&s.id).await;
+			}
+			self.stop(s).await
+		}))
+		.await;
+	}
+
+	/// End a session: an evicted one at once, a running one as
+	/// [`Session::stop`] does.
+	async fn stop(&self, session: &Session) -> ToolExecutionResponse {
+		let evicted = session.evicted.lock().unwrap().take();
+		if let Some((_, response)) = evicted {
+			METRICS.evicted_sessions.dec();
+			self.finish(session, response).await;
+		}
+		session.stop().await
+	}
+
+	/// Tear down sessions that have been idle longer than the idle timeout,
+	/// and evict those idle long enough for that, until `shutdown` is
+	/// cancelled.
+	pub fn spawn_reaper(self: &Arc<Self>, shutdown: CancellationToken) {
+		let manager = self.clone();
+		tokio::spawn(async move {
+			let mut tick = tokio::time::interval(REAP_INTERVAL);
+			loop {
+				tokio::select! {
+					_ = tick.tick() => {}
+					_ = shutdown.cancelled() => return,
+				}
+				let idle_timeout = manager.idle_timeout();
+				let expired: Vec<_> = {
+					let mut sessions = manager.sessions.lock().unwrap();
+					let ids: Vec<_> = sessions
+						.values()
+						.filter(|s| s.idle_for() >= idle_timeout)
+						.map(|s| s.id.clone())
+						.collect();
+					ids.iter().filter_map(|id| sessions.remove(id)).collect()
+				};
+				for session in expired {
+					info!(session_id = session.id, tool_name = session.tool_name, message = "Session idle; tearing it down");
+					let manager = manager.clone();
+					tokio::spawn(async move {
+						if let Some(registry) = &manager.registry {
+							registry.remove(&session.id).await;
+						}
+						manager.stop(&session).await
+					});
+				}
+				let Some(evict_after) = manager.config.evict_after_secs.map(Duration::from_secs) else {
+					continue;
+				};
+				let idle: Vec<_> = manager
+					.sessions
+					.lock()
+					.unwrap()
+					.values()
+					.filter(|s| s.evictable && s.idle_for() >= evict_after)
+					.filter(|s| s.ended.borrow().is_none() && s.evicted.lock().unwrap().is_none())
+					.cloned()
+					.collect();
+				for session in idle {
+					let manager = manager.clone();
+					tokio::spawn(async move { manager.evict(&session).await });
+				}
+			}
+		});
+	}
+
+	/// Sessions of other clients are reported as not found.
+	fn lookup(&self, session_id: &str, caller: &Caller) -> Result<Arc<Session>, SessionError> {
+		let sessions = self.sessions.lock().unwrap();
+		match sessions.get(session_id) {
+			Some(session) if session.owner == client_key(caller) => Ok(session.clone()),
+			_ => Err(SessionError::NotFound),
+		}
+	}
+}
+
+/// `ctx` with a live stdin and its output going to replies and `stderr`;
+/// the session's ends of both.
+fn wire(
+	ctx: ExecutionContext,
+	stderr: &Arc<Mutex<Vec<u8>>>,
+) -> (ExecutionContext, mpsc::Sender<Vec<u8>>, mpsc::Receiver<String>) {
+	let (stdin_tx, stdin_rx) = mpsc::channel(STDIN_BUFFER);
+	let (output_tx, output) = mpsc::channel(CHUNK_BUFFER);
+	let (replies_tx, replies) = mpsc::channel(REPLY_BUFFER);
+	tokio::spawn(split_output(output, replies_tx, stderr.clone(), ctx.max_output_bytes));
+	let ctx = ctx.with_output(output_tx).with_stdin_stream(StdinStream::new(stdin_rx));
+	(ctx, stdin_tx, replies)
+}
+
+/// Turn the tool's output into replies, one per stdout line, and pending
+/// stderr. Lines longer than `max_line` bytes are cut short.
+async fn split_output(
+	mut output: mpsc::Receiver<OutputChunk>,
+	replies: mpsc::Sender<String>,
+	stderr: Arc<Mutex<Vec<u8>>>,
+	max_line: usize,
+) {
+	let keep = |line: &mut Vec<u8>, bytes: &[u8]| {
+		let room = max_line.saturating_sub(line.len());
+		line.extend_from_slice(&bytes[..bytes.len().min(room)]);
+	};
+	let mut line = Vec::new();
+	while let Some(chunk) = output.recv().await {
+		match chunk.stream {
+			StreamKind::Stderr => {
+				let mut pending = stderr.lock().unwrap();
+				pending.extend_from_slice(&chunk.data);
+				let excess = pending.len().saturating_sub(MAX_PENDING_STDERR);
+				pending.drain(..excess);
+			}
+			StreamKind::Stdout => {
+				let mut rest = chunk.data.as_slice();
+				while let Some(end) = rest.iter().position(|b| *b == b'\n') {
+					keep(&mut line, &rest[..end]);
+					rest = &rest[end + 1..];
+					let reply = String::from_utf8_lossy(&std::mem::take(&mut line)).to_string();
+					if replies.send(reply).await.is_err() {
+						return;
+					}
+				}
+				keep(&mut line, rest);
+			}
+		}
+	}
+}
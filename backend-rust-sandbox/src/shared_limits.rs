@@ -0,0 +1,367 @@
+use redis::aio::ConnectionManager;
+use redis::{RedisResult, Script};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::metrics::METRICS;
+use crate::quotas::budget_crossed;
+use crate::ratelimit::BucketConfig;
+
+const DEFAULT_KEY_PREFIX: &str = "pagi-sandbox:limits";
+const DEFAULT_TIMEOUT_MS: u64 = 50;
+const DEFAULT_RETRY_MS: u64 = 5_000;
+/// Usage is written after the execution, off the request path, so it may
+/// take longer than a decision.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+const SECS_PER_DAY: i64 = 24 * 3600;
+/// Monthly keys outlive the longest month.
+const MONTH_KEY_SECS: i64 = 32 * SECS_PER_DAY;
+
+/// GCRA over one key holding the bucket's theoretical arrival time, in
+/// microseconds of the server's clock so that every node agrees on it.
+/// Returns whether the request may go ahead and, when not, how many
+/// microseconds until it would.
+const GCRA: &str = r"
+local time = redis.call('TIME')
+local now = time[1] * 1000000 + time[2]
+local interval = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local tat = tonumber(redis.call('GET', KEYS[1]) or now)
+if tat < now then tat = now end
+local next_tat = tat + interval
+local allow_at = next_tat - burst
+if allow_at > now then
+	return {0, math.ceil(allow_at - now)}
+end
+redis.call('SET', KEYS[1], string.format('%.0f', next_tat), 'PX', math.ceil((next_tat - now) / 1000))
+return {1, 0}
+";
+
+/// A tenant's executions and CPU time today, spend this month and the bytes
+/// of its artifacts stored since `ARGV[1]` (ms), dropping older ones.
+const USAGE: &str = r"
+local today = redis.call('HMGET', KEYS[1], 'executions', 'cpu_ms')
+local spend = redis.call('GET', KEYS[2])
+local expired = redis.call('ZRANGEBYSCORE', KEYS[3], '-inf', '(' .. ARGV[1])
+for _, id in ipairs(expired) do
+	redis.call('HDEL', KEYS[4], id)
+end
+redis.call('ZREMRANGEBYSCORE', KEYS[3], '-inf', '(' .. ARGV[1])
+local bytes = 0
+for _, size in ipairs(redis.call('HVALS', KEYS[4])) do
+	bytes = bytes + tonumber(size)
+end
+return {today[1] or '0', today[2] or '0', spend or '0', string.format('%.0f', bytes)}
+";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SharedLimitsConfig {
+	/// Keep rate limit buckets and tenant usage in this Redis server, shared
+	/// by every replica, instead of in each one's memory.
+	pub redis_url: Option<String>,
+	pub key_prefix: String,
+	/// How long a request waits for Redis before it is decided locally.
+	pub timeout_ms: u64,
+	/// After Redis fails, how long requests are decided locally before it
+	/// is tried again.
+	pub retry_ms: u64,
+}
+
+impl Default for SharedLimitsConfig {
+	fn default() -> Self {
+		Self {
+			redis_url: None,
+			key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+			timeout_ms: DEFAULT_TIMEOUT_MS,
+			retry_ms: DEFAULT_RETRY_MS,
+		}
+	}
+}
+
+impl SharedLimitsConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if let Some(url) = &self.redis_url {
+			if !url.starts_with("redis://") && !url.starts_with("rediss://") {
+				errors.push("shared_limits.redis_url must be a redis:// or rediss:// URL".to_string());
+			}
+		}
+		if self.key_prefix.is_empty() {
+			errors.push("shared_limits.key_prefix must not be empty".to_string());
+		}
+		if self.timeout_ms == 0 {
+			errors.push("shared_limits.timeout_ms must be positive".to_string());
+		}
+		if self.retry_ms == 0 {
+			errors.push("shared_limits.retry_ms must be positive".to_string());
+		}
+		errors
+	}
+}
+
+/// A tenant's usage as every replica together has recorded it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SharedUsage {
+	pub executions: u64,
+	pub cpu_ms: u64,
+	pub spend: f64,
+	pub storage_bytes: u64,
+}
+
+/// One finished execution, to be added to its tenant's shared usage.
+#[derive(Debug, Clone)]
+pub struct Recorded {
+	pub tenant: String,
+	pub day: String,
+	pub month: String,
+	pub cpu_ms: u64,
+	pub cost: f64,
+	/// (artifact id, size in bytes)
+	pub artifacts: Vec<(String, u64)>,
+	pub stored_at_ms: u64,
+	/// How long the artifact store keeps them.
+	pub artifact_ttl: Duration,
+	/// The tenant's soft budget and budget, each warned about once a month
+	/// by whichever replica passes it first.
+	pub budgets: [(&'static str, Option<f64>); 2],
+}
+
+/// Rate limit buckets and tenant usage kept in Redis, so that limits hold
+/// across every replica rather than per node.
+///
+/// Rate limits use GCRA, which keeps one timestamp per bucket and allows the
+/// same bursts as the local token buckets. Decisions are made on the request
+/// path, so they wait at most `timeout_ms`; when Redis does not answer in
+/// time, or has not been reached yet, the caller decides with its local
+/// buckets and counters instead, and keeps doing so for `retry_ms` before
+/// asking Redis again. Usage recorded while Redis was away stays local.
+pub struct SharedLimits {
+	/// Set once the first connection is made; reconnecting after that is the
+	/// manager's job.
+	connection: Arc<OnceLock<ConnectionManager>>,
+	prefix: String,
+	timeout: Duration,
+	retry: Duration,
+	/// Until when decisions are made locally after Redis failed.
+	down_until: Mutex<Option<Instant>>,
+	gcra: Script,
+	usage: Script,
+}
+
+impl SharedLimits {
+	/// Connect to the configured server, if any, in the background: the
+	/// sandbox starts with local limits and shares them once Redis answers.
+	pub fn start(config: &SharedLimitsConfig) -> Result<Option<Arc<Self>>, String> {
+		let Some(url) = &config.redis_url else {
+			return Ok(None);
+		};
+		let client = redis::Client::open(url.as_str()).map_err(|e| format!("shared_limits.redis_url: {e}"))?;
+		let connection = Arc::new(OnceLock::new());
+		let retry = Duration::from_millis(config.retry_ms);
+		let connecting = connection.clone();
+		tokio::spawn(async move {
+			loop {
+				match client.get_connection_manager().await {
+					Ok(manager) => {
+						let _ = connecting.set(manager);
+						info!(message = "Rate limits and quotas are shared through Redis");
+						return;
+					}
+					Err(e) => {
+						warn!(error = %e, message = "Unable to reach the shared limits store; limiting locally");
+						tokio::time::sleep(retry).await;
+					}
+				}
+			}
+		});
+		Ok(Some(Arc::new(Self {
+			connection,
+			prefix: config.key_prefix.clone(),
+			timeout: Duration::from_millis(config.timeout_ms),
+			retry,
+			down_until: Mutex::new(None),
+			gcra: Script::new(GCRA),
+			usage: Script::new(USAGE),
+		})))
+	}
+
+	/// Take a request from `client`'s shared bucket for `tool_name`: `Ok` to
+	/// let it through, or the seconds until it would be let through. `None`
+	/// when Redis did not answer.
+	pub async fn take(&self, client: &str, tool_name: &str, limit: BucketConfig) -> Option<Result<(), u64>> {
+		let key = format!("{}:rate:{client}:{tool_name}", self.prefix);
+		let interval_us = 1_000_000.0 / limit.refill_per_sec;
+		let burst_us = interval_us * limit.capacity as f64;
+		let (allowed, wait_us): (u8, u64) = self.decide("rate_limit", |mut connection| async move {
+			let mut invocation = self.gcra.prepare_invoke();
+			invocation
+				.key(&key)
+				.arg(interval_us.round().max(1.0) as u64)
+				.arg(burst_us.round() as u64);
+			invocation.invoke_async(&mut connection).await
+		})
+		.await?;
+		Some(match allowed {
+			1 => Ok(()),
+			_ => Err(wait_us.div_ceil(1_000_000).max(1)),
+		})
+	}
+
+	/// What `tenant` has used on `day` and in its month, and the artifacts it
+	/// stored since `cutoff_ms`. `None` when Redis did not answer.
+	pub async fn usage(&self, tenant: &str, day: &str, month: &str, cutoff_ms: u64) -> Option<SharedUsage> {
+		let keys = self.usage_keys(tenant, day, month);
+		let figures: Vec<String> = self.decide("quota", |mut connection| async move {
+			let mut invocation = self.usage.prepare_invoke();
+			invocation.key(&keys[..]).arg(cutoff_ms);
+			invocation.invoke_async(&mut connection).await
+		})
+		.await?;
+		let figure = |i: usize| figures.get(i).and_then(|v| v.parse::<f64>().ok()).unwrap_or_default();
+		Some(SharedUsage {
+			executions: figure(0) as u64,
+			cpu_ms: figure(1) as u64,
+			spend: figure(2),
+			storage_bytes: figure(3) as u64,
+		})
+	}
+
+	/// Claim `name` for this replica for `ttl`: `Some(true)` for the first
+	/// replica to ask, `Some(false)` for every other, `None` when Redis did
+	/// not answer.
+	pub async fn claim(&self, name: &str, ttl: Duration) -> Option<bool> {
+		let key = format!("{}:claim:{name}", self.prefix);
+		let claimed: Option<String> = self.decide("claim", |mut connection| async move {
+			redis::cmd("SET")
+				.arg(&key)
+				.arg(1)
+				.arg("NX")
+				.arg("PX")
+				.arg(ttl.as_millis() as u64)
+				.query_async(&mut connection)
+				.await
+		})
+		.await?;
+		Some(claimed.is_some())
+	}
+
+	/// Add a finished execution to its tenant's shared usage, on a task of
+	/// its own.
+	pub fn record(self: &Arc<Self>, recorded: Recorded) {
+		let Some(connection) = self.connection.get().cloned() else {
+			METRICS.shared_limit_fallbacks.with_label_values(&["record"]).inc();
+			return;
+		};
+		let shared = self.clone();
+		tokio::spawn(async move {
+			let written = tokio::time::timeout(WRITE_TIMEOUT, shared.write(connection, &recorded)).await;
+			match written {
+				Ok(Ok(())) => {}
+				Ok(Err(e)) => shared.failed("record", &e.to_string()),
+				Err(_) => shared.failed("record", "timed out"),
+			}
+		});
+	}
+
+	async fn write(&self, mut connection: ConnectionManager, recorded: &Recorded) -> RedisResult<()> {
+		let [day_key, spend_key, stored_key, sizes_key] =
+			self.usage_keys(&recorded.tenant, &recorded.day, &recorded.month);
+		let mut pipe = redis::pipe();
+		pipe.atomic();
+		pipe.hincr(&day_key, "executions", 1).ignore();
+		pipe.hincr(&day_key, "cpu_ms", recorded.cpu_ms).ignore();
+		pipe.expire(&day_key, 2 * SECS_PER_DAY).ignore();
+		pipe.cmd("INCRBYFLOAT").arg(&spend_key).arg(recorded.cost);
+		pipe.expire(&spend_key, MONTH_KEY_SECS).ignore();
+		if !recorded.artifacts.is_empty() {
+			let ttl_secs = recorded.artifact_ttl.as_secs().max(1) as i64;
+			for (artifact_id, size_bytes) in &recorded.artifacts {
+				pipe.zadd(&stored_key, artifact_id, recorded.stored_at_ms).ignore();
+				pipe.hset(&sizes_key, artifact_id, *size_bytes).ignore();
+			}
+			pipe.expire(&stored_key, ttl_secs).ignore();
+			pipe.expire(&sizes_key, ttl_secs).ignore();
+		}
+		let (spend,): (f64,) = pipe.query_async(&mut connection).await?;
+		for (threshold, budget) in recorded.budgets {
+			let Some(budget) = budget.filter(|budget| spend >= *budget) else {
+				continue;
+			};
+			let key = format!("{}:warned:{}:{}:{threshold}", self.prefix, recorded.tenant, recorded.month);
+			let first: Option<String> = redis::cmd("SET")
+				.arg(&key)
+				.arg(1)
+				.arg("NX")
+				.arg("EX")
+				.arg(MONTH_KEY_SECS)
+				.query_async(&mut connection)
+				.await?;
+			if first.is_some() {
+				budget_crossed(&recorded.tenant, threshold, budget, spend, &recorded.month);
+			}
+		}
+		Ok(())
+	}
+
+	/// The day's counters, the month's spend, and the tenant's stored
+	/// artifacts by time and by size.
+	fn usage_keys(&self, tenant: &str, day: &str, month: &str) -> [String; 4] {
+		let prefix = &self.prefix;
+		[
+			format!("{prefix}:usage:{tenant}:{day}"),
+			format!("{prefix}:spend:{tenant}:{month}"),
+			format!("{prefix}:artifacts:{tenant}"),
+			format!("{prefix}:artifact_bytes:{tenant}"),
+		]
+	}
+
+	/// Run `query` against Redis within the timeout; `None` means the caller
+	/// decides locally.
+	async fn decide<T, F>(&self, kind: &str, query: impl FnOnce(ConnectionManager) -> F) -> Option<T>
+	where
+		F: Future<Output = RedisResult<T>>,
+	{
+		let Some(connection) = self.connection.get().cloned() else {
+			METRICS.shared_limit_fallbacks.with_label_values(&[kind]).inc();
+			return None;
+		};
+		if self.down_until.lock().unwrap().is_some_and(|until| Instant::now() < until) {
+			METRICS.shared_limit_fallbacks.with_label_values(&[kind]).inc();
+			return None;
+		}
+		match tokio::time::timeout(self.timeout, query(connection)).await {
+			Ok(Ok(value)) => {
+				if self.down_until.lock().unwrap().take().is_some() {
+					info!(message = "The shared limits store answers again; limits are shared");
+				}
+				Some(value)
+			}
+			Ok(Err(e)) => {
+				self.failed(kind, &e.to_string());
+				None
+			}
+			Err(_) => {
+				self.failed(kind, "timed out");
+				None
+			}
+		}
+	}
+
+	/// Decide locally for the next `retry`, saying so when Redis was fine
+	/// until now.
+	fn failed(&self, kind: &str, error: &str) {
+		METRICS.shared_limit_fallbacks.with_label_values(&[kind]).inc();
+		let was_down = self.down_until.lock().unwrap().replace(Instant::now() + self.retry).is_some();
+		if !was_down {
+			warn!(
+				error = error,
+				retry_ms = self.retry.as_millis() as u64,
+				message = "The shared limits store failed; limiting locally for now"
+			);
+		}
+	}
+}
@@ -0,0 +1,145 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use p256::ecdsa::signature::Verifier as _;
+use p256::ecdsa::{Signature, VerifyingKey};
+use p256::pkcs8::DecodePublicKey;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// What minisign names a file's signature: `<file>.minisig`.
+const MINISIGN_SUFFIX: &str = "minisig";
+/// What `cosign sign-blob --output-signature` is usually given: `<file>.sig`.
+const COSIGN_SUFFIX: &str = "sig";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+	/// Refuse tool manifests, plugin libraries and components that carry no
+	/// signature from one of the keys below. A signature that does not
+	/// verify is refused either way.
+	pub require_signed_tools: bool,
+	/// minisign public keys, as `minisign -G` writes them.
+	pub minisign_public_keys: Vec<PathBuf>,
+	/// cosign public keys (PEM), as `cosign generate-key-pair` writes them.
+	pub cosign_public_keys: Vec<PathBuf>,
+}
+
+impl SigningConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		for path in self.minisign_public_keys.iter().chain(&self.cosign_public_keys) {
+			if !path.is_file() {
+				errors.push(format!("executor.signing: public key {} does not exist", path.display()));
+			}
+		}
+		if self.require_signed_tools && self.minisign_public_keys.is_empty() && self.cosign_public_keys.is_empty() {
+			errors.push(
+				"executor.signing.require_signed_tools needs minisign_public_keys or cosign_public_keys".to_string(),
+			);
+		}
+		errors
+	}
+}
+
+/// Checks the files tools are defined by against the signatures beside them,
+/// made with minisign or with `cosign sign-blob` and a key pair. Keyless
+/// cosign signatures, which need a transparency log, are not supported.
+pub struct ToolVerifier {
+	required: bool,
+	minisign: Vec<(String, minisign_verify::PublicKey)>,
+	cosign: Vec<(String, VerifyingKey)>,
+}
+
+impl ToolVerifier {
+	pub fn new(config: &SigningConfig) -> Result<Self, String> {
+		let read = |path: &PathBuf| std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()));
+		let minisign = config
+			.minisign_public_keys
+			.iter()
+			.map(|path| {
+				let key = minisign_verify::PublicKey::decode(read(path)?.trim())
+					.map_err(|e| format!("{}: {e}", path.display()))?;
+				Ok((path.display().to_string(), key))
+			})
+			.collect::<Result<_, String>>()?;
+		let cosign = config
+			.cosign_public_keys
+			.iter()
+			.map(|path| {
+				let key = VerifyingKey::from_public_key_pem(&read(path)?)
+					.map_err(|e| format!("{}: not a P-256 public key: {e}", path.display()))?;
+				Ok((path.display().to_string(), key))
+			})
+			.collect::<Result<_, String>>()?;
+		Ok(Self {
+			required: config.require_signed_tools,
+			minisign,
+			cosign,
+		})
+	}
+
+	/// Check `contents`, read from `path`, against its signature, and return
+	/// the key that made it. An unsigned file passes unless signatures are
+	/// required; the contents should be the very bytes then used, so that
+	/// the file cannot change in between.
+	pub fn verify(&self, path: &Path, contents: &[u8]) -> Result<Option<String>, String> {
+		let minisig = signature_path(path, MINISIGN_SUFFIX);
+		let sig = signature_path(path, COSIGN_SUFFIX);
+		let signer = if minisig.is_file() {
+			Some(self.verify_minisign(&minisig, contents)?)
+		} else if sig.is_file() {
+			Some(self.verify_cosign(&sig, contents)?)
+		} else if self.required {
+			return Err(format!(
+				"unsigned, and executor.signing.require_signed_tools is set; sign it into {} or {}",
+				minisig.display(),
+				sig.display()
+			));
+		} else {
+			debug!(path = %path.display(), message = "Loading unsigned tool definition");
+			None
+		};
+		if let Some(signer) = &signer {
+			info!(path = %path.display(), key = signer, message = "Verified tool signature");
+		}
+		Ok(signer)
+	}
+
+	fn verify_minisign(&self, minisig: &Path, contents: &[u8]) -> Result<String, String> {
+		if self.minisign.is_empty() {
+			return Err(format!("signed ({}), but no minisign public key is configured", minisig.display()));
+		}
+		let text = std::fs::read_to_string(minisig).map_err(|e| format!("{}: {e}", minisig.display()))?;
+		let signature = minisign_verify::Signature::decode(&text).map_err(|e| format!("{}: {e}", minisig.display()))?;
+		self.minisign
+			.iter()
+			.find(|(_, key)| key.verify(contents, &signature, false).is_ok())
+			.map(|(name, _)| name.clone())
+			.ok_or_else(|| format!("{} does not verify with any minisign public key", minisig.display()))
+	}
+
+	fn verify_cosign(&self, sig: &Path, contents: &[u8]) -> Result<String, String> {
+		if self.cosign.is_empty() {
+			return Err(format!("signed ({}), but no cosign public key is configured", sig.display()));
+		}
+		let text = std::fs::read_to_string(sig).map_err(|e| format!("{}: {e}", sig.display()))?;
+		let der = STANDARD
+			.decode(text.trim())
+			.map_err(|e| format!("{}: not base64: {e}", sig.display()))?;
+		let signature = Signature::from_der(&der).map_err(|e| format!("{}: {e}", sig.display()))?;
+		self.cosign
+			.iter()
+			.find(|(_, key)| key.verify(contents, &signature).is_ok())
+			.map(|(name, _)| name.clone())
+			.ok_or_else(|| format!("{} does not verify with any cosign public key", sig.display()))
+	}
+}
+
+/// `path` with `suffix` added to its file name.
+fn signature_path(path: &Path, suffix: &str) -> PathBuf {
+	let mut name = path.file_name().unwrap_or_default().to_os_string();
+	name.push(".");
+	name.push(suffix);
+	path.with_file_name(name)
+}
@@ -0,0 +1,690 @@
+use std::future::Future;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{info_span, warn};
+
+use crate::cgroup::{self, CgroupSpec};
+use crate::egress::{NetworkJail, NetworkMonitor};
+use crate::encoding::{self, Encoding, OutputEncoding};
+use crate::executions::ProcessList;
+use crate::landlock::Landlock;
+use crate::metrics::METRICS;
+use crate::namespace::{self, Unshare};
+use crate::pty::{self, Pty, Terminal};
+use crate::seccomp::SeccompFilter;
+use crate::secrets::{Secret, ToolEnv};
+use crate::usage::{self, UsageMeter};
+use crate::workspace::Jail;
+
+const READ_CHUNK_BYTES: usize = 8 * 1024;
+/// How long to keep reading pipes after the child exits. A descendant that
+/// escaped the process group may hold them open indefinitely.
+const DRAIN_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamKind {
+	Stdout,
+	Stderr,
+}
+
+/// A piece of tool output, forwarded as soon as it is read.
+#[derive(Debug, Clone)]
+pub struct OutputChunk {
+	pub stream: StreamKind,
+	pub data: Vec<u8>,
+}
+
+/// Receives live output. Sends never fail the execution: if the receiver has
+/// gone away the chunk is dropped and capture continues.
+pub type OutputSink = mpsc::Sender<OutputChunk>;
+
+/// What a child process reads on stdin.
+#[derive(Debug, Clone)]
+pub enum StdinSource {
+	/// These bytes, then end of file.
+	Bytes(Arc<[u8]>),
+	/// Chunks as they arrive, then end of file once every sender is gone.
+	Stream(StdinStream),
+}
+
+/// Live stdin of an interactive execution. Only the first process spawned
+/// with it gets the data.
+#[derive(Debug, Clone)]
+pub struct StdinStream(Arc<Mutex<Option<mpsc::Receiver<Vec<u8>>>>>);
+
+impl StdinStream {
+	pub fn new(chunks: mpsc::Receiver<Vec<u8>>) -> Self {
+		Self(Arc::new(Mutex::new(Some(chunks))))
+	}
+
+	pub fn take(&self) -> Option<mpsc::Receiver<Vec<u8>>> {
+		self.0.lock().unwrap().take()
+	}
+}
+
+/// Description of a child process to spawn for a tool execution.
+#[derive(Debug, Clone, Default)]
+pub struct CommandSpec {
+	pub program: String,
+	pub args: Vec<String>,
+	pub cwd: Option<PathBuf>,
+	/// Start the child from an empty environment instead of the sandbox's.
+	pub clear_env: bool,
+	pub env: Vec<(String, String)>,
+	/// Like `env`, but kept out of logs. `env` wins where both set a name.
+	pub secrets: Vec<(String, Secret)>,
+	/// What the child reads on stdin; `/dev/null` without it.
+	pub stdin: Option<StdinSource>,
+	/// Runs the child on a pseudo-terminal: stdin is typed into it, and
+	/// what the child writes to it is its stdout, stderr included.
+	pub terminal: Option<Terminal>,
+	/// Wall-clock limit; the whole process group is killed when it expires.
+	pub timeout: Option<Duration>,
+	/// Where to forward output chunks while the process runs.
+	pub output: Option<OutputSink>,
+	/// Bytes of each stream to keep; the rest is counted and dropped, though
+	/// `output` still gets all of it. Unlimited without it.
+	pub max_output: Option<usize>,
+	/// Kills the whole process group when cancelled.
+	pub cancel: Option<CancellationToken>,
+	/// Lists the child while it runs, for the admin API.
+	pub processes: Option<ProcessList>,
+	/// Adds up what the child used, once it has exited.
+	pub usage: Option<UsageMeter>,
+	/// Resource limits enforced through a cgroup created for this process.
+	pub cgroup: Option<CgroupSpec>,
+	/// Confines the child's writes to its workspace.
+	pub jail: Option<Jail>,
+	/// Runs the child in a PID namespace of its own, so that nothing it
+	/// starts outlives it.
+	pub pid_namespace: bool,
+	/// Cuts the child off from the network, save for an allowlist.
+	pub network: Option<NetworkJail>,
+	/// Confines the child's file access to the paths its rules allow.
+	pub landlock: Option<Landlock>,
+	/// Syscall filter installed in the child just before it execs.
+	pub seccomp: Option<SeccompFilter>,
+}
+
+impl CommandSpec {
+	pub fn new(program: impl Into<String>) -> Self {
+		Self {
+			program: program.into(),
+			..Default::default()
+		}
+	}
+
+	pub fn arg(mut self, arg: impl Into<String>) -> Self {
+		self.args.push(arg.into());
+		self
+	}
+
+	pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+		self.cwd = Some(cwd.into());
+		self
+	}
+
+	pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+		self.env.push((key.into(), value.into()));
+		self
+	}
+
+	pub fn secret(mut self, key: impl Into<String>, value: Secret) -> Self {
+		self.secrets.push((key.into(), value));
+		self
+	}
+
+	/// Give the child only `env` (allowlisted variables and secrets) plus
+	/// whatever `env` adds afterwards.
+	pub fn tool_env(mut self, env: &ToolEnv) -> Self {
+		self.clear_env = true;
+		self.env.extend(env.vars.iter().cloned());
+		self.secrets.extend(env.secrets.iter().cloned());
+		self
+	}
+
+	pub fn stdin(mut self, source: Option<StdinSource>) -> Self {
+		self.stdin = source;
+		self
+	}
+
+	pub fn terminal(mut self, terminal: Option<Terminal>) -> Self {
+		self.terminal = terminal;
+		self
+	}
+
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = Some(timeout);
+		self
+	}
+
+	pub fn output(mut self, sink: Option<OutputSink>) -> Self {
+		self.output = sink;
+		self
+	}
+
+	pub fn max_output(mut self, bytes: usize) -> Self {
+		self.max_output = Some(bytes);
+		self
+	}
+
+	pub fn cancel(mut self, token: CancellationToken) -> Self {
+		self.cancel = Some(token);
+		self
+	}
+
+	pub fn processes(mut self, list: ProcessList) -> Self {
+		self.processes = Some(list);
+		self
+	}
+
+	pub fn usage(mut self, meter: UsageMeter) -> Self {
+		self.usage = Some(meter);
+		self
+	}
+
+	pub fn cgroup(mut self, cgroup: Option<CgroupSpec>) -> Self {
+		self.cgroup = cgroup;
+		self
+	}
+
+	pub fn jail(mut self, jail: Option<Jail>) -> Self {
+		self.jail = jail;
+		self
+	}
+
+	pub fn pid_namespace(mut self, enabled: bool) -> Self {
+		self.pid_namespace = enabled;
+		self
+	}
+
+	pub fn network(mut self, network: Option<NetworkJail>) -> Self {
+		self.network = network;
+		self
+	}
+
+	pub fn landlock(mut self, landlock: Option<Landlock>) -> Self {
+		self.landlock = landlock;
+		self
+	}
+
+	pub fn seccomp(mut self, filter: Option<SeccompFilter>) -> Self {
+		self.seccomp = filter;
+		self
+	}
+}
+
+/// Captured result of a finished child process.
+#[derive(Debug)]
+pub struct ProcessOutput {
+	/// What the process wrote, as text, or in base64 where it was not
+	/// UTF-8, as `encoding` says.
+	pub stdout: String,
+	pub stderr: String,
+	pub encoding: Option<OutputEncoding>,
+	pub exit_code: Option<i32>,
+	pub signal: Option<i32>,
+	/// The timeout expired and the process group was killed; stdout/stderr
+	/// hold whatever was produced up to that point.
+	pub timed_out: bool,
+	/// Like `timed_out`, but the execution was cancelled by the caller.
+	pub cancelled: bool,
+	/// The kernel killed a process in the cgroup for exceeding its memory
+	/// limit.
+	pub oom_killed: bool,
+	/// The cgroup used up its CPU time limit and was killed.
+	pub cpu_exceeded: bool,
+	/// The seccomp filter killed the process for a syscall outside its
+	/// allowlist.
+	pub policy_violation: bool,
+	/// Destinations the network policy kept the process from reaching.
+	pub network_denied: Vec<String>,
+	/// Set when a stream went past `max_output`; `stdout`/`stderr` then hold
+	/// only its beginning.
+	pub truncated: Option<Truncation>,
+}
+
+/// How much a process wrote to each stream, reported when some of it had to
+/// be dropped.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Truncation {
+	pub stdout_bytes: u64,
+	pub stderr_bytes: u64,
+}
+
+impl ProcessOutput {
+	pub fn success(&self) -> bool {
+		self.exit_code == Some(0)
+	}
+
+	/// Add a line of the sandbox's to `stderr`.
+	pub fn note(&mut self, note: &str) {
+		encoding::push_note(&mut self.stderr, OutputEncoding::stderr(self.encoding), note);
+	}
+
+	/// Whether the process was stopped by the sandbox or the kernel rather
+	/// than exiting on its own.
+	pub fn interrupted(&self) -> bool {
+		self.timed_out || self.cancelled || self.oom_killed || self.cpu_exceeded || self.policy_violation
+	}
+
+	/// Map the way the process ended onto a tool status string.
+	pub fn status(&self) -> &'static str {
+		if self.policy_violation {
+			return "policy_violation";
+		}
+		if self.oom_killed {
+			return "oom_killed";
+		}
+		if self.cpu_exceeded {
+			return "cpu_exceeded";
+		}
+		if self.timed_out {
+			return "timed_out";
+		}
+		if self.cancelled {
+			return "cancelled";
+		}
+		if !self.network_denied.is_empty() {
+			return "network_denied";
+		}
+		match (self.exit_code, self.signal) {
+			(Some(0), _) => "ok",
+			(Some(_), _) => "non_zero_exit",
+			(None, Some(_)) => "killed",
+			(None, None) => "unknown_exit",
+		}
+	}
+}
+
+/// Map a spawn failure onto a tool status string.
+pub fn spawn_error_status(err: &io::Error) -> &'static str {
+	match err.kind() {
+		io::ErrorKind::NotFound => "not_found",
+		io::ErrorKind::PermissionDenied => "permission_denied",
+		_ => "spawn_error",
+	}
+}
+
+/// Spawn `spec` and wait for it to exit.
+///
+/// The child leads its own process group so that a timeout, a cancellation,
+/// or dropping the returned future can take down everything it forked. stdout and stderr are drained concurrently, chunk by
+/// chunk, so a child that fills one pipe while we are blocked on the other
+/// cannot deadlock.
+///
+/// With `spec.cgroup`, the child joins a fresh cgroup before it execs, so its
+/// limits cover everything it forks; whatever is still in the cgroup when the
+/// child exits is killed. So is whatever is left in its process group, and,
+/// with `spec.pid_namespace`, in its PID namespace.
+///
+/// With `spec.terminal`, the child leads a session of its own instead, on a
+/// fresh pty; its process group is the session's.
+pub async fn run(spec: &CommandSpec) -> io::Result<ProcessOutput> {
+	let cgroup = spec.cgroup.as_ref().map(CgroupSpec::create).transpose()?;
+	let mut cmd = Command::new(&spec.program);
+	if spec.clear_env {
+		cmd.env_clear();
+	}
+	cmd.args(&spec.args)
+		.envs(spec.secrets.iter().map(|(k, v)| (k, v.expose())))
+		.envs(spec.env.iter().map(|(k, v)| (k, v)))
+		.kill_on_drop(true);
+	let pty = spec.terminal.as_ref().map(|t| Pty::open(t.size())).transpose()?;
+	match &pty {
+		Some(pty) => {
+			cmd.stdin(pty.slave()?).stdout(pty.slave()?).stderr(pty.slave()?);
+			// SAFETY: `take_terminal` only makes syscalls. A process group
+			// leader cannot start a session, so the child does not get one
+			// of its own first.
+			unsafe {
+				cmd.pre_exec(pty::take_terminal);
+			}
+		}
+		None => {
+			cmd.stdin(if spec.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+				.stdout(Stdio::piped())
+				.stderr(Stdio::piped())
+				.process_group(0);
+		}
+	}
+	if let Some(cwd) = &spec.cwd {
+		cmd.current_dir(cwd);
+	}
+	if let Some(cgroup) = &cgroup {
+		let procs = cgroup.procs_fd();
+		// SAFETY: `join` only makes a write(2) call, which is safe after fork.
+		unsafe {
+			cmd.pre_exec(move || cgroup::join(procs));
+		}
+	}
+	let mut namespaces = 0;
+	if spec.jail.is_some() {
+		namespaces |= libc::CLONE_NEWNS;
+	}
+	if spec.network.is_some() {
+		namespaces |= libc::CLONE_NEWNET;
+	}
+	if spec.pid_namespace {
+		namespaces |= libc::CLONE_NEWPID;
+	}
+	if namespaces != 0 {
+		let unshare = Unshare::new(namespaces);
+		// SAFETY: `enter` only makes syscalls. It runs after the cgroup join,
+		// which a user namespace would no longer be allowed to do.
+		unsafe {
+			cmd.pre_exec(move || unshare.enter());
+		}
+	}
+	if let Some(jail) = spec.jail.clone() {
+		// SAFETY: `enter` only makes syscalls.
+		unsafe {
+			cmd.pre_exec(move || jail.enter());
+		}
+	}
+	let channel = spec.network.as_ref().map(|_| NetworkJail::channel()).transpose()?;
+	if let (Some(network), Some((_, child_end))) = (spec.network.clone(), &channel) {
+		cmd.envs(network.proxy_env());
+		let fd = child_end.as_raw_fd();
+		// SAFETY: `enter` only makes syscalls; `child_end` outlives the spawn.
+		unsafe {
+			cmd.pre_exec(move || network.enter(fd));
+		}
+	}
+	if spec.pid_namespace {
+		// SAFETY: `enter_pid_namespace` only makes async-signal-safe calls.
+		// It forks once the namespaces are set up, and before the tool's own
+		// confinement, which its init does without.
+		unsafe {
+			cmd.pre_exec(namespace::enter_pid_namespace);
+		}
+	}
+	if let Some(landlock) = spec.landlock.clone() {
+		// SAFETY: `restrict` only makes syscalls. It runs once the mounts are
+		// in place, and before seccomp, which does not allow its syscalls.
+		unsafe {
+			cmd.pre_exec(move || landlock.restrict());
+		}
+	}
+	if let Some(filter) = spec.seccomp.clone() {
+		// SAFETY: `install` only makes prctl(2) and seccomp(2) calls. It runs
+		// last, so the filter only has to allow exec itself.
+		unsafe {
+			cmd.pre_exec(move || filter.install());
+		}
+	}
+
+	let spawn_span = info_span!("sandbox.spawn", program = %spec.program, pid = tracing::field::Empty);
+	let mut child = spawn_span.in_scope(|| cmd.spawn())?;
+	// The command holds copies of the child's end of the pty, which must
+	// all be closed for reads of ours to end.
+	drop(cmd);
+	let network = match (&spec.network, channel) {
+		(Some(jail), Some((ours, _))) => Some(NetworkMonitor::receive(jail, &ours)?),
+		_ => None,
+	};
+	let pid = child.id();
+	if let Some(pid) = pid {
+		spawn_span.record("pid", pid);
+	}
+	drop(spawn_span);
+	let mut group = GroupGuard { pgid: pid };
+	let _tracked = pid.map(|pgid| METRICS.track_process_group(pgid));
+	let _listed = pid.zip(spec.processes.as_ref()).map(|(pid, list)| list.add(pid));
+	let master = pty
+		.zip(spec.terminal.clone())
+		.map(|(pty, terminal)| pty.into_master(terminal))
+		.transpose()?;
+	let limit = spec.max_output.unwrap_or(usize::MAX);
+	let stdout_buf = Arc::new(Mutex::new(Capture::default()));
+	let stderr_buf = Arc::new(Mutex::new(Capture::default()));
+	let source = spec.stdin.clone();
+	let (mut stdout_task, mut stderr_task) = match master {
+		// The terminal is all the child has, for stdin and output alike. Its
+		// stdin ends as typed input would, so that a child reading to the end
+		// of it is not left waiting.
+		Some((reader, mut writer)) => {
+			feed_stdin(async move {
+				let last = match source {
+					Some(source) => write_stdin(&mut writer, source).await?,
+					None => None,
+				};
+				writer.end(last).await
+			});
+			(
+				tokio::spawn(drain(reader, stdout_buf.clone(), limit, StreamKind::Stdout, spec.output.clone())),
+				tokio::spawn(async { Ok::<_, io::Error>(()) }),
+			)
+		}
+		None => {
+			if let (Some(mut pipe), Some(source)) = (child.stdin.take(), source) {
+				feed_stdin(async move { write_stdin(&mut pipe, source).await.map(drop) });
+			}
+			let stdout = child.stdout.take().expect("stdout is piped");
+			let stderr = child.stderr.take().expect("stderr is piped");
+			(
+				tokio::spawn(drain(stdout, stdout_buf.clone(), limit, StreamKind::Stdout, spec.output.clone())),
+				tokio::spawn(drain(stderr, stderr_buf.clone(), limit, StreamKind::Stderr, spec.output.clone())),
+			)
+		}
+	};
+
+	let deadline = async {
+		match spec.timeout {
+			Some(limit) => tokio::time::sleep(limit).await,
+			None => std::future::pending().await,
+		}
+	};
+	let cpu_exhausted = async {
+		match &cgroup {
+			Some(cgroup) => cgroup.cpu_exhausted().await,
+			None => std::future::pending().await,
+		}
+	};
+	let cancel = spec.cancel.clone().unwrap_or_default();
+	// Resolves once the child has exited, leaving it for `wait` to reap.
+	let mut exited = tokio::task::spawn_blocking(move || pid.map(usage::wait_exited));
+	let (mut timed_out, mut cancelled, mut cpu_exceeded) = (false, false, false);
+	let exited_usage = tokio::select! {
+		outcome = &mut exited => Some(outcome),
+		_ = deadline => {
+			timed_out = true;
+			None
+		}
+		_ = cancel.cancelled() => {
+			cancelled = true;
+			None
+		}
+		_ = cpu_exhausted => {
+			cpu_exceeded = true;
+			None
+		}
+	};
+	let exited_usage = match exited_usage {
+		Some(outcome) => outcome,
+		None => {
+			group.kill();
+			if let Some(cgroup) = &cgroup {
+				cgroup.kill();
+			}
+			exited.await
+		}
+	};
+	// Descendants left in the group go with the leader. It is not reaped
+	// yet, so the group's id cannot have been reused.
+	group.kill();
+	let status = child.wait().await?;
+	let mut usage = match exited_usage {
+		Ok(Some(Ok(usage))) => Some(usage),
+		Ok(Some(Err(e))) => {
+			warn!(error = %e, message = "Failed to read a tool process's resource usage");
+			None
+		}
+		_ => None,
+	};
+	let mut oom_killed = false;
+	if let (Some(cgroup), Some(cgroup_spec)) = (&cgroup, &spec.cgroup) {
+		oom_killed = cgroup.oom_killed();
+		cgroup_spec.record_cpu_usage(cgroup);
+		if let Some(usage) = &mut usage {
+			cgroup.refine_usage(usage);
+		}
+		cgroup.kill();
+		cgroup.wait_empty().await;
+	}
+
+	for task in [&mut stdout_task, &mut stderr_task] {
+		if tokio::time::timeout(DRAIN_GRACE, &mut *task).await.is_err() {
+			task.abort();
+		}
+	}
+
+	let (stdout, stdout_encoding, stdout_bytes) = take_encoded(&stdout_buf);
+	let (stderr, stderr_encoding, stderr_bytes) = take_encoded(&stderr_buf);
+	if let Some(usage) = &mut usage {
+		usage.output_bytes = stdout_bytes + stderr_bytes;
+		if let Some(meter) = &spec.usage {
+			meter.add(*usage);
+		}
+	}
+	let limit = limit as u64;
+	Ok(ProcessOutput {
+		stdout,
+		stderr,
+		encoding: OutputEncoding::of(stdout_encoding, stderr_encoding),
+		exit_code: status.code(),
+		signal: status.signal(),
+		timed_out,
+		cancelled,
+		oom_killed,
+		cpu_exceeded,
+		policy_violation: spec.seccomp.is_some() && status.signal() == Some(libc::SIGSYS),
+		network_denied: network.map(NetworkMonitor::finish).unwrap_or_default(),
+		truncated: (stdout_bytes > limit || stderr_bytes > limit).then_some(Truncation {
+			stdout_bytes,
+			stderr_bytes,
+		}),
+	})
+}
+
+/// Write a child's stdin with `write`. A child that exits without reading
+/// everything closes it; that is its business, not an error.
+fn feed_stdin(write: impl Future<Output = io::Result<()>> + Send + 'static) {
+	tokio::spawn(async move {
+		if let Err(e) = write.await {
+			if e.kind() != io::ErrorKind::BrokenPipe {
+				warn!(error = %e, message = "Failed to write tool stdin");
+			}
+		}
+	});
+}
+
+/// Write `source` to `pipe`; the last byte written, if there was any.
+async fn write_stdin(pipe: &mut (impl AsyncWrite + Unpin), source: StdinSource) -> io::Result<Option<u8>> {
+	let mut last = None;
+	match source {
+		StdinSource::Bytes(data) => {
+			pipe.write_all(&data).await?;
+			last = data.last().copied();
+		}
+		StdinSource::Stream(stream) => {
+			let Some(mut chunks) = stream.take() else {
+				return Ok(None);
+			};
+			while let Some(chunk) = chunks.recv().await {
+				pipe.write_all(&chunk).await?;
+				last = chunk.last().copied().or(last);
+			}
+		}
+	}
+	Ok(last)
+}
+
+/// Kills the child's process group if `run` stops waiting for it early,
+/// including when the `run` future itself is dropped.
+struct GroupGuard {
+	pgid: Option<u32>,
+}
+
+impl GroupGuard {
+	fn kill(&mut self) {
+		if let Some(pgid) = self.pgid.take() {
+			kill_process_group(pgid);
+		}
+	}
+}
+
+impl Drop for GroupGuard {
+	fn drop(&mut self) {
+		self.kill();
+	}
+}
+
+/// SIGKILL every process in the group led by `pgid`.
+pub fn kill_process_group(pgid: u32) {
+	// SAFETY: killpg has no memory-safety preconditions.
+	let rc = unsafe { libc::killpg(pgid as libc::pid_t, libc::SIGKILL) };
+	if rc != 0 {
+		let err = io::Error::last_os_error();
+		if err.raw_os_error() != Some(libc::ESRCH) {
+			warn!(pgid = pgid, error = %err, message = "Failed to kill process group");
+		}
+	}
+}
+
+/// What was kept of one stream, and how much of it there was.
+#[derive(Default)]
+struct Capture {
+	bytes: Vec<u8>,
+	total: u64,
+}
+
+fn take_encoded(buf: &Mutex<Capture>) -> (String, Encoding, u64) {
+	let capture = std::mem::take(&mut *buf.lock().unwrap());
+	let cut = capture.total > capture.bytes.len() as u64;
+	let (text, encoding) = encoding::encode(capture.bytes, cut);
+	(text, encoding, capture.total)
+}
+
+async fn drain<R: AsyncRead + Unpin>(
+	mut reader: R,
+	captured: Arc<Mutex<Capture>>,
+	limit: usize,
+	stream: StreamKind,
+	mut sink: Option<OutputSink>,
+) -> io::Result<()> {
+	let mut chunk = vec![0u8; READ_CHUNK_BYTES];
+	loop {
+		let n = reader.read(&mut chunk).await?;
+		if n == 0 {
+			return Ok(());
+		}
+		{
+			let mut capture = captured.lock().unwrap();
+			let keep = n.min(limit.saturating_sub(capture.bytes.len()));
+			capture.bytes.extend_from_slice(&chunk[..keep]);
+			capture.total += n as u64;
+		}
+		if let Some(tx) = &sink {
+			let out = OutputChunk {
+				stream,
+				data: chunk[..n].to_vec(),
+			};
+			if tx.send(out).await.is_err() {
+				sink = None;
+			}
+		}
+	}
+}
@@ -0,0 +1,120 @@
+use axum::{
+	extract::{MatchedPath, Request},
+	http::{self, HeaderMap},
+	middleware::Next,
+	response::Response,
+};
+use opentelemetry::propagation::{Extractor, TextMapCompositePropagator};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::logs::LoggerProvider;
+use opentelemetry_sdk::propagation::{BaggagePropagator, TraceContextPropagator};
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::{field, info_span, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::request_id;
+
+/// Build the OTLP/gRPC span exporter when an endpoint is configured, and
+/// install the W3C trace-context propagator either way so incoming
+/// `traceparent` headers still parent our spans.
+///
+/// The other standard `OTEL_EXPORTER_OTLP_*` variables (headers, timeout) are
+/// read by the exporter itself.
+pub fn init_tracer(service_name: &'static str, endpoint: Option<&str>) -> Option<(TracerProvider, Tracer)> {
+	global::set_text_map_propagator(TextMapCompositePropagator::new(vec![
+		Box::new(TraceContextPropagator::new()),
+		Box::new(BaggagePropagator::new()),
+	]));
+
+	let exporter = opentelemetry_otlp::SpanExporter::builder()
+		.with_tonic()
+		.with_endpoint(endpoint?)
+		.build()
+		.unwrap_or_else(|e| panic!("Unable to create OTLP exporter: {e}"));
+	let provider = TracerProvider::builder()
+		.with_batch_exporter(exporter, runtime::Tokio)
+		.with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)]))
+		.build();
+	let tracer = provider.tracer(service_name);
+	global::set_tracer_provider(provider.clone());
+	Some((provider, tracer))
+}
+
+/// Build the OTLP/gRPC log exporter for the `logging.otlp` sink, sending to
+/// the same collector as the spans.
+pub fn init_logger(service_name: &'static str, endpoint: &str) -> LoggerProvider {
+	let exporter = opentelemetry_otlp::LogExporter::builder()
+		.with_tonic()
+		.with_endpoint(endpoint)
+		.build()
+		.unwrap_or_else(|e| panic!("Unable to create OTLP log exporter: {e}"));
+	LoggerProvider::builder()
+		.with_batch_exporter(exporter, runtime::Tokio)
+		.with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)]))
+		.build()
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+	fn get(&self, key: &str) -> Option<&str> {
+		self.0.get(key).and_then(|v| v.to_str().ok())
+	}
+
+	fn keys(&self) -> Vec<&str> {
+		self.0.keys().map(|k| k.as_str()).collect()
+	}
+}
+
+/// Make `span` a child of the remote span described by `headers`, if any.
+fn set_remote_parent(span: &Span, headers: &HeaderMap) {
+	let parent = global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(headers)));
+	span.set_parent(parent);
+}
+
+/// axum middleware opening a server span per request, continuing the caller's
+/// trace when it sent a `traceparent` header.
+pub async fn trace_http(mut request: Request, next: Next) -> Response {
+	let route = request
+		.extensions()
+		.get::<MatchedPath>()
+		.map(|p| p.as_str().to_string())
+		.unwrap_or_else(|| request.uri().path().to_string());
+	let method = request.method().to_string();
+	let request_id = request_id::ensure(request.headers_mut());
+	let span = info_span!(
+		"http_request",
+		otel.name = format!("{method} {route}"),
+		otel.kind = "server",
+		http.request.method = %method,
+		http.route = %route,
+		http.response.status_code = field::Empty,
+		request_id = request_id,
+	);
+	set_remote_parent(&span, request.headers());
+
+	let future = next.run(request).instrument(span.clone());
+	let mut response = request_id::scope(request_id.clone(), future).await;
+	span.record("http.response.status_code", response.status().as_u16());
+	request_id::set(response.headers_mut(), &request_id);
+	response
+}
+
+/// `trace_fn` for the tonic server: the gRPC counterpart of [`trace_http`],
+/// reading `traceparent` from the call metadata.
+pub fn grpc_span<B>(request: &http::Request<B>) -> Span {
+	let method = request.uri().path();
+	let span = info_span!(
+		"grpc_request",
+		otel.name = method.trim_start_matches('/'),
+		otel.kind = "server",
+		rpc.system = "grpc",
+		rpc.method = method,
+		request_id = field::Empty,
+	);
+	set_remote_parent(&span, request.headers());
+	span
+}
@@ -0,0 +1,165 @@
+use axum::extract::connect_info::ConnectInfo;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+use tracing::{debug, warn};
+
+/// A client that connects and never finishes the handshake is dropped after
+/// this long.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Back-off after a failed `accept` (e.g. out of file descriptors).
+const ACCEPT_RETRY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+	/// PEM certificate chain, leaf first. Both listeners serve TLS when this
+	/// and `key_path` are set.
+	pub cert_path: Option<PathBuf>,
+	/// PEM private key (PKCS#8, PKCS#1 or SEC1).
+	pub key_path: Option<PathBuf>,
+	/// PEM CA certificates that client certificates are verified against.
+	/// Clients presenting one are identified by its SAN.
+	pub client_ca_path: Option<PathBuf>,
+	/// Refuse connections without a valid client certificate, instead of
+	/// falling back to API keys and JWTs.
+	pub require_client_cert: bool,
+}
+
+impl TlsConfig {
+	pub fn enabled(&self) -> bool {
+		self.cert_path.is_some()
+	}
+}
+
+/// The identity in a verified client certificate: its first URI SAN (a
+/// SPIFFE ID, say), else its first DNS SAN. Added to the request extensions
+/// of every request on the connection.
+#[derive(Debug, Clone)]
+pub struct PeerIdentity(pub String);
+
+/// Build the acceptor for `config`, reading the certificate, key and client
+/// CAs. Errors name the file at fault.
+pub fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor, String> {
+	let (Some(cert_path), Some(key_path)) = (&config.cert_path, &config.key_path) else {
+		return Err("tls.cert_path and tls.key_path must be set together".to_string());
+	};
+	let certs = read_certs(cert_path)?;
+	let key = PrivateKeyDer::from_pem_file(key_path)
+		.map_err(|e| format!("tls.key_path {}: {e}", key_path.display()))?;
+	let provider = Arc::new(rustls::crypto::ring::default_provider());
+	let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+		.with_safe_default_protocol_versions()
+		.map_err(|e| e.to_string())?;
+	let builder = match &config.client_ca_path {
+		Some(ca_path) => {
+			let mut roots = RootCertStore::empty();
+			for cert in read_certs(ca_path)? {
+				roots
+					.add(cert)
+					.map_err(|e| format!("tls.client_ca_path {}: {e}", ca_path.display()))?;
+			}
+			let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+			let verifier = if config.require_client_cert {
+				verifier
+			} else {
+				verifier.allow_unauthenticated()
+			};
+			builder.with_client_cert_verifier(verifier.build().map_err(|e| e.to_string())?)
+		}
+		None => builder.with_no_client_auth(),
+	};
+	let mut server_config = builder
+		.with_single_cert(certs, key)
+		.map_err(|e| format!("tls.cert_path {}: {e}", cert_path.display()))?;
+	// gRPC needs HTTP/2; browsers and curl may still speak HTTP/1.1.
+	server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+	Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+pub fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+	let certs = CertificateDer::pem_file_iter(path)
+		.and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+		.map_err(|e| format!("{}: {e}", path.display()))?;
+	if certs.is_empty() {
+		return Err(format!("{}: no certificates found", path.display()));
+	}
+	Ok(certs)
+}
+
+fn peer_identity(stream: &TlsStream<tokio::net::TcpStream>) -> Option<PeerIdentity> {
+	let leaf = stream.get_ref().1.peer_certificates()?.first()?;
+	let cert = webpki::EndEntityCert::try_from(leaf).ok()?;
+	let identity = cert
+		.valid_uri_names()
+		.next()
+		.or_else(|| cert.valid_dns_names().next())?;
+	Some(PeerIdentity(identity.to_string()))
+}
+
+/// Serve `app` over TLS on `listener` until `shutdown`, then wait for open
+/// connections to finish their requests. Requests carry the peer address as
+/// [`ConnectInfo`], and the client certificate's identity if there was one.
+pub async fn serve(listener: TcpListener, acceptor: TlsAcceptor, app: Router, shutdown: CancellationToken) {
+	let graceful = GracefulShutdown::new();
+	loop {
+		let (tcp, remote_addr) = tokio::select! {
+			accepted = listener.accept() => match accepted {
+				Ok(accepted) => accepted,
+				Err(e) => {
+					warn!(error = %e, message = "Failed to accept a connection");
+					tokio::time::sleep(ACCEPT_RETRY).await;
+					continue;
+				}
+			},
+			_ = shutdown.cancelled() => break,
+		};
+		let (acceptor, app, watcher) = (acceptor.clone(), app.clone(), graceful.watcher());
+		tokio::spawn(async move {
+			let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+				Ok(Ok(stream)) => stream,
+				Ok(Err(e)) => {
+					debug!(client_ip = %remote_addr.ip(), error = %e, message = "TLS handshake failed");
+					return;
+				}
+				Err(_) => {
+					debug!(client_ip = %remote_addr.ip(), message = "TLS handshake timed out");
+					return;
+				}
+			};
+			let identity = peer_identity(&stream);
+			if let Some(identity) = &identity {
+				debug!(client_ip = %remote_addr.ip(), peer = identity.0, message = "Client certificate accepted");
+			}
+			let service = hyper::service::service_fn(move |mut request: hyper::Request<Incoming>| {
+				request.extensions_mut().insert(ConnectInfo(remote_addr));
+				if let Some(identity) = &identity {
+					request.extensions_mut().insert(identity.clone());
+				}
+				app.clone().oneshot(request)
+			});
+			let builder = auto::Builder::new(TokioExecutor::new());
+			let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+			if let Err(e) = watcher.watch(connection).await {
+				debug!(client_ip = %remote_addr.ip(), error = %e, message = "Connection ended with an error");
+			}
+		});
+	}
+	drop(listener);
+	graceful.shutdown().await;
+}
@@ -1,41 +1,277 @@
-use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::sync::Arc;
 use tracing::info;
 
-use crate::tool_executor::execute_tool;
+use crate::artifacts::is_valid_artifact_pattern;
+use crate::audit::Caller;
+use crate::encoding::{Encoding, OutputEncoding};
+use crate::plan::PlanOptions;
+use crate::quotas::{BudgetExceeded, QuotaExceeded};
+use crate::ratelimit::RateLimited;
+use crate::registry::ArgError;
+use crate::scheduler::QueueFull;
+use crate::tool_executor::{run_detached, ExecutionContext, Executor, ToolResult};
+use crate::trace::Tracer;
+use crate::workspace::{is_valid_input_path, ToolInput};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ToolExecutionRequest {
-    pub tool_name: String,
-    pub args: Value,
+pub use pagi_sandbox_client::wire::{Base64, ToolExecutionRequest, ToolExecutionResponse};
+
+/// The execution's end of the trace `req` records or replays, if any.
+pub fn request_tracer(req: &ToolExecutionRequest) -> Option<Arc<Tracer>> {
+    match (&req.replay_trace, req.record_trace) {
+        (Some(trace), _) => Some(Arc::new(Tracer::replay(trace.clone()))),
+        (None, true) => Some(Arc::new(Tracer::record())),
+        (None, false) => None,
+    }
+}
+
+/// What `req` gives the tool to read: its stdin and input files.
+pub fn request_input(req: &ToolExecutionRequest) -> ToolInput {
+    ToolInput {
+        stdin: req.stdin.as_ref().map(|b| b.0.as_slice().into()),
+        files: req
+            .input_files
+            .iter()
+            .map(|(path, data)| (path.clone(), data.0.clone()))
+            .collect(),
+    }
+}
+
+
+/// Reject a request whose args violate the tool's schema, or whose artifact
+/// globs or input files reach outside the workspace, before anything is
+/// spawned. The response carries every violation under `result.errors`.
+pub fn validate_tool_request(
+    executor: &Executor,
+    req: &ToolExecutionRequest,
+) -> Result<(), ToolExecutionResponse> {
+    let mut errors = executor
+        .validate_args(&req.tool_name, &req.args)
+        .err()
+        .unwrap_or_default();
+    errors.extend(artifact_pattern_errors(&req.artifacts));
+    errors.extend(input_path_errors(req.input_files.keys()));
+    if req.record_trace && req.replay_trace.is_some() {
+        errors.push(ArgError {
+            path: "replay_trace".to_string(),
+            message: "record_trace and replay_trace cannot both be set".to_string(),
+        });
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(ToolExecutionResponse {
+        status: "invalid_args".to_string(),
+        tool_name: req.tool_name.clone(),
+        result: json!({ "errors": errors }),
+    })
 }
 
-#[derive(Debug, Serialize)]
-pub struct ToolExecutionResponse {
-    pub status: String,
-    pub tool_name: String,
-    pub result: Value,
+/// One error per requested artifact glob that is not relative to the
+/// workspace; `path` names the request field rather than a place in args.
+pub fn artifact_pattern_errors(patterns: &[String]) -> Vec<ArgError> {
+    patterns
+        .iter()
+        .enumerate()
+        .filter(|(_, pattern)| !is_valid_artifact_pattern(pattern))
+        .map(|(i, pattern)| ArgError {
+            path: format!("artifacts[{i}]"),
+            message: format!("{pattern:?} is not a glob relative to the workspace"),
+        })
+        .collect()
 }
 
-pub async fn execute_mock_tool(req: ToolExecutionRequest) -> ToolExecutionResponse {
+/// One error per input file whose path is not relative to the workspace.
+pub fn input_path_errors<'a>(paths: impl IntoIterator<Item = &'a String>) -> Vec<ArgError> {
+    paths
+        .into_iter()
+        .filter(|path| !is_valid_input_path(path))
+        .map(|path| ArgError {
+            path: format!("input_files[{path:?}]"),
+            message: format!("{path:?} is not a path relative to the workspace"),
+        })
+        .collect()
+}
+
+/// Body for a request the scheduler turned away because its queue is full,
+/// or the instance is draining.
+pub fn queue_full_response(tool_name: &str, e: &QueueFull) -> ToolExecutionResponse {
+    ToolExecutionResponse {
+        status: e.status().to_string(),
+        tool_name: tool_name.to_string(),
+        result: json!({ "error": e.message(), "retry_after_secs": e.retry_after_secs }),
+    }
+}
+
+/// Body for a request over the caller's rate limit for the tool.
+pub fn rate_limited_response(tool_name: &str, e: &RateLimited) -> ToolExecutionResponse {
+    ToolExecutionResponse {
+        status: "rate_limited".to_string(),
+        tool_name: tool_name.to_string(),
+        result: json!({
+            "error": e.message(tool_name),
+            "retry_after_secs": e.retry_after_secs,
+            "limit": e.limit,
+        }),
+    }
+}
+
+/// Body for a request from a tenant that has used up one of its quotas.
+pub fn quota_exceeded_response(tool_name: &str, e: &QuotaExceeded) -> ToolExecutionResponse {
+    ToolExecutionResponse {
+        status: "quota_exceeded".to_string(),
+        tool_name: tool_name.to_string(),
+        result: json!({
+            "error": e.message(),
+            "tenant": e.tenant,
+            "quota": e.quota,
+            "limit": e.limit,
+            "used": e.used,
+            "retry_after_secs": e.retry_after_secs,
+        }),
+    }
+}
+
+/// Body for a request that would take its tenant past its monthly budget.
+pub fn budget_exceeded_response(tool_name: &str, e: &BudgetExceeded) -> ToolExecutionResponse {
+    ToolExecutionResponse {
+        status: "budget_exceeded".to_string(),
+        tool_name: tool_name.to_string(),
+        result: json!({
+            "error": e.message(),
+            "tenant": e.tenant,
+            "budget": e.budget,
+            "spent": e.spent,
+            "estimated_cost": e.estimate,
+            "currency": e.currency,
+            "retry_after_secs": e.retry_after_secs,
+        }),
+    }
+}
+
+/// Execute `req` to completion. Dropping the returned future (e.g. because the
+/// HTTP client disconnected) cancels the execution instead of abandoning it.
+pub async fn execute_tool_request(
+    executor: Arc<Executor>,
+    req: ToolExecutionRequest,
+    ctx: ExecutionContext,
+) -> ToolExecutionResponse {
+    let cancel = ctx.cancel.clone();
+    run_detached(cancel, async move {
+        execute_tool_request_with_context(&executor, req, &ctx).await
+    })
+    .await
+}
+
+pub async fn execute_tool_request_with_context(
+    executor: &Executor,
+    req: ToolExecutionRequest,
+    ctx: &ExecutionContext,
+) -> ToolExecutionResponse {
     info!(
         tool_name = req.tool_name,
-        args = %req.args,
-        message = "Executing mock tool"
+        args = %executor.loggable_args(&req.tool_name, &req.args),
+        message = "Executing tool"
     );
 
-	let tool_result = execute_tool(req.tool_name.as_str(), req.args.clone()).await;
-	let parsed_stdout: Value = serde_json::from_str(&tool_result.stdout)
-		.unwrap_or_else(|_| json!({"stdout": tool_result.stdout}));
-	let result = json!({
-		"stdout": parsed_stdout,
-		"stderr": tool_result.stderr,
-	});
+	// The response names the version that ran.
+	let tool_name = executor.pin_tool(&req.tool_name);
+	let tool_result = executor
+		.execute_tool(tool_name.as_str(), req.args.clone(), ctx)
+		.await;
+	let mut result = result_body(&tool_result);
+	if ctx.retain_workspace && executor.workspace_exists(&ctx.workspace_id) {
+		result["workspace_id"] = json!(ctx.workspace_id);
+	}
+	if !tool_result.artifacts.is_empty() {
+		result["artifacts"] = json!(tool_result.artifacts);
+	}
+	if let Some(note) = executor.registry().get(&tool_name).and_then(|t| t.deprecated.clone()) {
+		result["deprecated"] = json!(note);
+	}
+	if let Some(trace) = &ctx.trace {
+		match trace.recorded() {
+			Some(recorded) => result["trace"] = json!(recorded),
+			None if trace.unused() > 0 => result["trace_unused"] = json!(trace.unused()),
+			None => {}
+		}
+	}
 
 	ToolExecutionResponse {
 		status: tool_result.status,
-		tool_name: req.tool_name,
+		tool_name,
 		result,
 	}
 }
 
+/// The answer to a `dry_run`: status `planned` with the execution plan as
+/// the result, or `unknown_tool`.
+pub fn plan_tool_request(executor: &Executor, caller: &Caller, req: &ToolExecutionRequest) -> ToolExecutionResponse {
+	let input = request_input(req);
+	let options = PlanOptions {
+		caller,
+		timeout_ms: req.timeout_ms,
+		retry: req.retry.as_ref(),
+		artifacts: &req.artifacts,
+		input: &input,
+		retain_workspace: req.retain_workspace,
+		priority: req.priority,
+	};
+	match executor.plan(&req.tool_name, &req.args, &options) {
+		Some(plan) => ToolExecutionResponse {
+			status: "planned".to_string(),
+			tool_name: plan.tool.clone(),
+			result: json!(plan),
+		},
+		None => ToolExecutionResponse {
+			status: "unknown_tool".to_string(),
+			tool_name: req.tool_name.clone(),
+			result: json!({ "error": format!("no tool matches {:?}", req.tool_name) }),
+		},
+	}
+}
+
+/// The `result` of a response: stdout as JSON when it parses, else wrapped as
+/// `{"stdout": "..."}`, plus stderr and the exit code. Truncated output is
+/// marked with `truncated: true` and the byte counts of both streams,
+/// output that is not text with its `encoding`, retried executions with
+/// their `attempts`, and executions that ran with their `environment`.
+pub fn result_body(tool_result: &ToolResult) -> Value {
+	let parsed_stdout: Value = match OutputEncoding::stdout(tool_result.encoding) {
+		Encoding::Utf8 => serde_json::from_str(&tool_result.stdout).ok(),
+		Encoding::Base64 | Encoding::Artifact => None,
+	}
+	.unwrap_or_else(|| json!({"stdout": tool_result.stdout}));
+	let mut body = json!({
+		"stdout": parsed_stdout,
+		"stderr": tool_result.stderr,
+		"exit_code": tool_result.exit_code,
+	});
+	if let Some(encoding) = &tool_result.encoding {
+		body["encoding"] = json!(encoding);
+	}
+	if let Some(attempts) = tool_result.attempts {
+		body["attempts"] = json!(attempts);
+	}
+	if !tool_result.policy_violations.is_empty() {
+		body["policy_violations"] = json!(tool_result.policy_violations);
+	}
+	if !tool_result.redactions.is_empty() {
+		body["redactions"] = json!(tool_result.redactions);
+	}
+	if let Some(quarantined) = &tool_result.quarantined {
+		body["quarantined"] = json!(quarantined);
+	}
+	if let Some(truncation) = &tool_result.truncated {
+		body["truncated"] = json!(true);
+		body["stdout_bytes"] = json!(truncation.stdout_bytes);
+		body["stderr_bytes"] = json!(truncation.stderr_bytes);
+	}
+	if let Some(usage) = &tool_result.usage {
+		body["usage"] = json!(usage);
+	}
+	if let Some(environment) = &tool_result.environment {
+		body["environment"] = json!(environment);
+	}
+	body
+}
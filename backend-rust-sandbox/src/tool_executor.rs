@@ -1,11 +1,470 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use std::future::Future;
 use tokio::fs;
-use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn, Instrument, Span};
 
-use crate::tool_web_search;
+use crate::artifacts::{Artifact, ArtifactStore, ArtifactsConfig};
+use crate::audit::{AuditLog, Caller};
+use crate::cache::ResultCache;
+use crate::cgroup::{CgroupConfig, CgroupSpec, Cgroups};
+use crate::chaos::Chaos;
+use crate::clock::now_ms;
+use crate::code_scan::{self, CodeScanConfig, CodeScanner, PolicyViolation, ScanAction};
+use crate::cost::Consumption;
+use crate::deadline::DeadlineGuard;
+use crate::egress::{NetworkJail, NetworkPolicy};
+use crate::encoding::{self, Encoding, OutputEncoding};
+use crate::environment::{find_program, ExecutionEnvironment, FileDigests, SANDBOX_VERSION};
+use crate::events::EventBus;
+use crate::executions::{ExecutionView, Executions, ProcessList};
+use crate::executor_container::{ContainerConfig, ContainerExecutor};
+use crate::executor_firecracker::{FirecrackerConfig, FirecrackerExecutor};
+use crate::warm_pool::WarmPoolConfig;
+use crate::executor_javascript::{JavaScriptConfig, JavaScriptRuntime};
+use crate::executor_python::{PythonConfig, PythonRuntime};
+use crate::executor_component::ComponentRuntime;
+use crate::executor_wasm::{WasmConfig, WasmRuntime};
+use crate::gateway_client::{ForwardedAuth, GatewayCall, GatewayClient, GatewayConfig};
+use crate::gpu::{GpuConfig, GpuLease, Gpus};
+use crate::history::{History, RecordedRequest};
+use crate::metrics::METRICS;
+use crate::output_filter::{OutputFilters, OutputFiltersConfig, Redaction};
+use crate::plan::{ExecutionPlan, PlanOptions};
+use crate::pty::Terminal;
+use crate::quarantine::{Quarantine, QuarantineConfig, QuarantineEntry, QuarantineError, Quarantined};
+use crate::quotas::Quotas;
+use crate::registry::{ArgError, RegistryError, ToolManifest, ToolRegistry};
+use crate::retry::{RetryOverride, RetryPolicy};
+use crate::scheduler::{Admission, Capacity, PreemptionConfig, Priority, QueueFull, ScalingSignal, Scheduler};
+use crate::secrets::{SecretStore, SecretsConfig, ToolEnv};
+use crate::session_snapshot::Checkpoint;
+use crate::signing::{SigningConfig, ToolVerifier};
+use crate::subprocess::{self, CommandSpec, OutputChunk, OutputSink, StdinSource, StdinStream, StreamKind, Truncation};
+use crate::tool::Base64;
+use crate::tool_http::{HttpConfig, HttpTool};
+use crate::tool_sdk::{self, Tool};
+use crate::tool_shell::{ShellConfig, ShellTool};
+use crate::tool_sql::{SqlConfig, SqlTool};
+use crate::trace::Tracer;
+use crate::usage::{ResourceUsage, UsageMeter};
+use crate::work_queue::WorkQueue;
+use crate::workspace::{ToolInput, Workspace, WorkspaceConfig, Workspaces};
+
+const DEFAULT_MANIFEST_DIR: &str = "tools.d";
+const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_MAX_TIMEOUT_MS: u64 = 300_000;
+const DEFAULT_DEADLINE_OVERHEAD_MS: u64 = 50;
+const DEFAULT_MAX_CONCURRENT: usize = 16;
+const DEFAULT_MAX_QUEUED: usize = 64;
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+const DEFAULT_ENV_ALLOW: &[&str] = &["PATH", "LANG", "LC_ALL", "TZ"];
+
+/// Where a tool runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionBackend {
+	/// Implemented inside the sandbox binary.
+	Builtin,
+	/// A host subprocess running the manifest's `command`.
+	Native,
+	/// A WASI module in the embedded wasmtime sandbox.
+	Wasm,
+	/// A WebAssembly component from `executor.component_dir`, implementing
+	/// the `pagi:tool` world.
+	Component,
+	/// An ephemeral container.
+	Container,
+	/// A Firecracker microVM of its own.
+	Firecracker,
+}
+
+impl FromStr for ExecutionBackend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.into_iter()
+			.find(|backend| backend.as_str() == s)
+			.ok_or_else(|| format!("unknown execution backend {s:?}"))
+	}
+}
+
+impl ExecutionBackend {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Builtin => "builtin",
+			Self::Native => "native",
+			Self::Wasm => "wasm",
+			Self::Component => "component",
+			Self::Container => "container",
+			Self::Firecracker => "firecracker",
+		}
+	}
+
+	pub const ALL: [Self; 6] = [
+		Self::Builtin,
+		Self::Native,
+		Self::Wasm,
+		Self::Component,
+		Self::Container,
+		Self::Firecracker,
+	];
+
+	/// Whether output reaches `ExecutionContext::output` while the tool runs.
+	pub fn streams_live(self) -> bool {
+		matches!(self, Self::Native | Self::Container | Self::Component | Self::Firecracker)
+	}
+
+	/// Whether its tools can run on a terminal.
+	pub fn runs_on_terminal(self) -> bool {
+		matches!(self, Self::Native | Self::Container)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutorConfig {
+	/// Directory scanned for tool manifests at startup.
+	pub manifest_dir: PathBuf,
+	/// Directory of tool plugin libraries (`*.so`, `*.dylib`), loaded once
+	/// at startup; none by default.
+	pub plugin_dir: Option<PathBuf>,
+	/// Directory of tool components (`*.wasm`), loaded at startup and again
+	/// on every reload; none by default.
+	pub component_dir: Option<PathBuf>,
+	/// Checks on the code `execute_code`, `python` and `javascript` are
+	/// given, before it runs.
+	pub code_scan: CodeScanConfig,
+	/// What is scrubbed from tools' stdout and stderr before anyone sees
+	/// them.
+	pub output_filters: OutputFiltersConfig,
+	/// What becomes of executions that look suspicious.
+	pub quarantine: QuarantineConfig,
+	/// Signatures the manifests, plugins and components must carry.
+	pub signing: SigningConfig,
+	/// Timeout applied when neither the request nor the manifest sets one.
+	pub default_timeout_ms: u64,
+	/// Upper bound on any requested timeout.
+	pub max_timeout_ms: u64,
+	/// Taken off a caller's deadline for the time its answer needs to
+	/// reach it: an execution must end this long before the deadline.
+	pub deadline_overhead_ms: u64,
+	/// Executions allowed to run at the same time.
+	pub max_concurrent_executions: usize,
+	/// Executions allowed to wait for a slot; beyond that requests are
+	/// rejected.
+	pub max_queued_executions: usize,
+	pub preemption: PreemptionConfig,
+	pub wasm: WasmConfig,
+	pub container: ContainerConfig,
+	pub firecracker: FirecrackerConfig,
+	/// What becomes of warm containers and VMs after an execution.
+	pub warm_pool: WarmPoolConfig,
+	pub python: PythonConfig,
+	pub javascript: JavaScriptConfig,
+	pub shell: ShellConfig,
+	pub sql: SqlConfig,
+	pub http: HttpConfig,
+	/// Limits for native tools and `execute_code` subprocesses.
+	pub cgroup: CgroupConfig,
+	/// GPUs for the native and container tools that request them.
+	pub gpu: GpuConfig,
+	pub workspace: WorkspaceConfig,
+	/// Host environment variables native tools and `execute_code` inherit,
+	/// as names or `PREFIX*`; manifests can allow more for their tool.
+	pub env_allow: Vec<String>,
+	/// Cap on the attempts of any retry policy, the manifest's or a
+	/// request's.
+	pub max_attempts: u32,
+	/// Bytes of stdout and of stderr kept from each execution. Tools may
+	/// write more; the excess is counted, streamed to live callers, and
+	/// dropped.
+	pub max_output_bytes: usize,
+	pub secrets: SecretsConfig,
+	pub artifacts: ArtifactsConfig,
+	/// The model gateway client built-in tools call back into.
+	pub gateway: GatewayConfig,
+}
+
+impl Default for ExecutorConfig {
+	fn default() -> Self {
+		Self {
+			manifest_dir: DEFAULT_MANIFEST_DIR.into(),
+			plugin_dir: None,
+			component_dir: None,
+			code_scan: CodeScanConfig::default(),
+			output_filters: OutputFiltersConfig::default(),
+			quarantine: QuarantineConfig::default(),
+			signing: SigningConfig::default(),
+			default_timeout_ms: DEFAULT_TIMEOUT_MS,
+			max_timeout_ms: DEFAULT_MAX_TIMEOUT_MS,
+			deadline_overhead_ms: DEFAULT_DEADLINE_OVERHEAD_MS,
+			max_concurrent_executions: DEFAULT_MAX_CONCURRENT,
+			max_queued_executions: DEFAULT_MAX_QUEUED,
+			preemption: PreemptionConfig::default(),
+			wasm: WasmConfig::default(),
+			container: ContainerConfig::default(),
+			firecracker: FirecrackerConfig::default(),
+			warm_pool: WarmPoolConfig::default(),
+			python: PythonConfig::default(),
+			javascript: JavaScriptConfig::default(),
+			shell: ShellConfig::default(),
+			sql: SqlConfig::default(),
+			http: HttpConfig::default(),
+			cgroup: CgroupConfig::default(),
+			gpu: GpuConfig::default(),
+			workspace: WorkspaceConfig::default(),
+			env_allow: DEFAULT_ENV_ALLOW.iter().map(|v| v.to_string()).collect(),
+			max_attempts: DEFAULT_MAX_ATTEMPTS,
+			max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+			secrets: SecretsConfig::default(),
+			artifacts: ArtifactsConfig::default(),
+			gateway: GatewayConfig::default(),
+		}
+	}
+}
+
+/// Per-call settings for a single tool execution.
+#[derive(Debug, Clone)]
+pub struct ExecutionContext {
+	pub timeout: Duration,
+	/// Live output destination for streaming callers.
+	pub output: Option<OutputSink>,
+	/// Cancelling this stops the execution and releases what it holds
+	/// (processes, WASM instances, run dirs); the result is `cancelled`.
+	pub cancel: CancellationToken,
+	/// Recorded in the audit log with the result.
+	pub caller: Caller,
+	/// This execution's place in the scheduler queue.
+	pub admission: Arc<Admission>,
+	/// Where it goes in that queue.
+	pub priority: Priority,
+	/// Limits for the subprocesses the execution spawns, when cgroups are in
+	/// use.
+	pub cgroup: Option<CgroupSpec>,
+	/// Bytes kept of each output stream.
+	pub max_output_bytes: usize,
+	/// Names the execution's workspace directory.
+	pub workspace_id: String,
+	/// Keep the workspace after the run instead of deleting it.
+	pub retain_workspace: bool,
+	/// The async job the execution belongs to, if any.
+	pub job_id: Option<String>,
+	/// When the request was admitted, in ms since the epoch.
+	pub created_at_ms: u64,
+	/// Run the tool even if a cached result exists; the new result is still
+	/// cached.
+	pub bypass_cache: bool,
+	/// Workspace globs to collect as artifacts, on top of the manifest's.
+	pub artifacts: Vec<String>,
+	/// Stdin and input files from the request.
+	pub input: ToolInput,
+	/// Live stdin of an interactive session; takes the place of
+	/// `input.stdin`.
+	pub stdin_stream: Option<StdinStream>,
+	/// The session's terminal, which native and container tools run on in
+	/// place of pipes.
+	pub terminal: Option<Terminal>,
+	/// The request's changes to the tool's retry policy.
+	pub retry: Option<RetryOverride>,
+	/// Taken from the work queue by this worker; runs here rather than going
+	/// back to the queue.
+	pub from_queue: bool,
+	/// The processes it is running, as the admin API lists them.
+	pub processes: ProcessList,
+	/// What those processes have used.
+	pub usage: UsageMeter,
+	/// The recorded execution it replays.
+	pub replay_of: Option<String>,
+	/// The trace it records its external effects in, or serves them from.
+	pub trace: Option<Arc<Tracer>>,
+	/// The model gateway client, when one is configured.
+	pub gateway: Option<Arc<GatewayClient>>,
+	/// The credentials the caller presented, for the gateway calls the
+	/// execution makes. Never queued, recorded or logged.
+	pub forwarded_auth: Option<ForwardedAuth>,
+	/// When the caller stops waiting for the result, overhead deducted; the
+	/// execution is cancelled then, with status `deadline_exceeded`.
+	pub deadline: Option<Instant>,
+	/// A checkpointed session process to bring back, in the workspace it
+	/// left, instead of starting the tool.
+	pub restore: Option<Checkpoint>,
+	/// Who let a job of a tool that requires approval run (`anonymous`
+	/// without authentication); such tools run only with it.
+	pub approved_by: Option<String>,
+}
+
+impl ExecutionContext {
+	pub fn with_output(mut self, sink: OutputSink) -> Self {
+		self.output = Some(sink);
+		self
+	}
+
+	pub fn with_caller(mut self, caller: Caller) -> Self {
+		self.caller = caller;
+		self
+	}
+
+	pub fn with_retained_workspace(mut self, retain: bool) -> Self {
+		self.retain_workspace = retain;
+		self
+	}
+
+	pub fn with_cache_bypass(mut self, bypass: bool) -> Self {
+		self.bypass_cache = bypass;
+		self
+	}
+
+	pub fn with_artifacts(mut self, patterns: Vec<String>) -> Self {
+		self.artifacts = patterns;
+		self
+	}
+
+	pub fn with_input(mut self, input: ToolInput) -> Self {
+		self.input = input;
+		self
+	}
+
+	pub fn with_stdin_stream(mut self, stream: StdinStream) -> Self {
+		self.stdin_stream = Some(stream);
+		self
+	}
+
+	pub fn with_terminal(mut self, terminal: Option<Terminal>) -> Self {
+		self.terminal = terminal;
+		self
+	}
+
+	pub fn with_retry(mut self, retry: Option<RetryOverride>) -> Self {
+		self.retry = retry;
+		self
+	}
+
+	/// What the tool's process reads on stdin.
+	pub fn stdin(&self) -> Option<StdinSource> {
+		match &self.stdin_stream {
+			Some(stream) => Some(StdinSource::Stream(stream.clone())),
+			None => self.input.stdin.clone().map(StdinSource::Bytes),
+		}
+	}
+
+	/// The terminal `tool`'s process runs on: the session's, or one of the
+	/// default size for a tool whose manifest asks for one.
+	pub fn terminal(&self, tool: &ToolManifest) -> Option<Terminal> {
+		self.terminal.clone().or_else(|| tool.pty.then(Terminal::default))
+	}
+
+	/// Lower the priority to `max` if it is above it.
+	pub fn with_priority_at_most(mut self, max: Priority) -> Self {
+		self.priority = self.priority.min(max);
+		self
+	}
+
+	pub fn with_job(mut self, job_id: String) -> Self {
+		self.job_id = Some(job_id);
+		self
+	}
+
+	pub fn with_replay_of(mut self, execution_id: String) -> Self {
+		self.replay_of = Some(execution_id);
+		self
+	}
+
+	pub fn with_approval(mut self, approved_by: String) -> Self {
+		self.approved_by = Some(approved_by);
+		self
+	}
+
+	pub fn with_trace(mut self, trace: Option<Arc<Tracer>>) -> Self {
+		self.trace = trace;
+		self
+	}
+
+	pub fn with_forwarded_auth(mut self, auth: Option<ForwardedAuth>) -> Self {
+		self.forwarded_auth = auth;
+		self
+	}
+
+	/// Hold the execution to the caller's `deadline`, shortening its timeout
+	/// to the time left until then.
+	pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+		if let Some(deadline) = deadline {
+			self.timeout = self.timeout.min(deadline.saturating_duration_since(Instant::now()));
+			self.deadline = Some(deadline);
+		}
+		self
+	}
+
+	pub fn taken_from_queue(mut self) -> Self {
+		self.from_queue = true;
+		self
+	}
+
+	/// The model gateway, for calls made on this execution's behalf: they
+	/// must finish within its timeout, counted from now, and carry its
+	/// request id and, where `executor.gateway.forward_auth` is on, its
+	/// caller's credentials. `None` when no gateway is configured.
+	pub fn gateway(&self) -> Option<GatewayCall<'_>> {
+		let client = self.gateway.as_deref()?;
+		Some(client.scoped(
+			Instant::now() + self.timeout,
+			self.caller.request_id.as_deref(),
+			self.forwarded_auth.as_ref(),
+		))
+	}
+
+	/// Wait for an execution slot; `Err` with a `cancelled` result if the
+	/// execution is cancelled first.
+	pub async fn wait_turn(&self) -> Result<(), ToolResult> {
+		tokio::select! {
+			_ = self.admission.wait_turn(self.priority, &self.cancel) => self.check_cancelled(),
+			_ = self.cancel.cancelled() => self.check_cancelled(),
+		}
+	}
+
+	/// `Err` with a `cancelled` result once the execution has been cancelled.
+	#[allow(clippy::result_large_err)]
+	pub fn check_cancelled(&self) -> Result<(), ToolResult> {
+		if !self.cancel.is_cancelled() {
+			return Ok(());
+		}
+		Err(ToolResult {
+			status: "cancelled".to_string(),
+			stderr: "execution cancelled".to_string(),
+			..Default::default()
+		})
+	}
+
+	/// Forward a finished result's output for backends that cannot stream.
+	async fn emit_buffered(&self, result: &ToolResult) {
+		let Some(tx) = &self.output else {
+			return;
+		};
+		for (stream, text, encoding) in [
+			(StreamKind::Stdout, &result.stdout, OutputEncoding::stdout(result.encoding)),
+			(StreamKind::Stderr, &result.stderr, OutputEncoding::stderr(result.encoding)),
+		] {
+			// Output moved to an artifact is there to fetch, not to stream.
+			if !text.is_empty() && encoding != Encoding::Artifact {
+				let chunk = OutputChunk {
+					stream,
+					data: encoding::decode(text, encoding),
+				};
+				let _ = tx.send(chunk).await;
+			}
+		}
+	}
+}
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -14,6 +473,7 @@ pub enum ToolLanguage {
 	Go,
 	Python,
 	Java,
+	Javascript,
 }
 
 #[derive(Debug, Serialize)]
@@ -24,53 +484,1223 @@ pub struct ToolExecutionResult {
 	pub stderr: String,
 	pub compile_stdout: String,
 	pub compile_stderr: String,
+	/// How the program's `stdout` and `stderr` are carried, when either is
+	/// not plain UTF-8.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub encoding: Option<OutputEncoding>,
 	pub exit_code: Option<i32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub truncated: Option<Truncation>,
+	/// The uncaught exception that ended the program, where the language
+	/// reports one.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub exception: Option<CodeException>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CodeException {
+	/// The exception's class, e.g. `ZeroDivisionError`.
+	#[serde(rename = "type")]
+	pub kind: String,
+	pub message: String,
+	/// Line of the submitted code it was raised at.
+	pub line: Option<u32>,
+	#[serde(skip_serializing)]
+	pub syntax_error: bool,
+	/// As the language prints it, without the sandbox's own frames.
+	pub traceback: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ToolResult {
 	pub status: String,
 	pub stdout: String,
 	pub stderr: String,
+	/// How `stdout` and `stderr` are carried, when either is not plain
+	/// UTF-8.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub encoding: Option<OutputEncoding>,
+	pub exit_code: Option<i32>,
+	/// Files collected from the workspace, as declared by the manifest and the
+	/// request.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub artifacts: Vec<Artifact>,
+	/// Set when output past `executor.max_output_bytes` was dropped.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub truncated: Option<Truncation>,
+	/// Runs it took, when a retry policy applied.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub attempts: Option<u32>,
+	/// What the code scan found in the submitted code: the reasons it did
+	/// not run, or the findings it ran with.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub policy_violations: Vec<PolicyViolation>,
+	/// What the output filters took out of `stdout` and `stderr`.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub redactions: Vec<Redaction>,
+	/// Set when the execution was quarantined: its artifacts are held
+	/// until an operator approves them.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub quarantined: Option<Box<Quarantined>>,
+	/// What ran and under which policy, once it has run.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub environment: Option<Box<ExecutionEnvironment>>,
+	/// What the execution used, once it has run.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub usage: Option<ResourceUsage>,
 }
 
-#[derive(Debug)]
-struct CmdOutput {
-	stdout: String,
-	stderr: String,
-	exit_code: Option<i32>,
-	success: bool,
+impl ToolResult {
+	/// Add a line of the sandbox's to `stderr`.
+	pub fn note(&mut self, note: &str) {
+		encoding::push_note(&mut self.stderr, OutputEncoding::stderr(self.encoding), note);
+	}
 }
 
-async fn run_cmd(program: &str, args: &[&str], cwd: &Path) -> std::io::Result<CmdOutput> {
-	let out = Command::new(program).args(args).current_dir(cwd).output().await?;
-	Ok(CmdOutput {
-		stdout: String::from_utf8_lossy(&out.stdout).to_string(),
-		stderr: String::from_utf8_lossy(&out.stderr).to_string(),
-		exit_code: out.status.code(),
-		success: out.status.success(),
-	})
+/// Executes tool requests against the registered tools.
+pub struct Executor {
+	config: ExecutorConfig,
+	/// Swapped whole on reload or when a tool is turned off or on; executions
+	/// keep the snapshot they started with. `loaded` less `disabled`.
+	registry: RwLock<Arc<ToolRegistry>>,
+	/// The tool set as last loaded.
+	loaded: RwLock<Arc<ToolRegistry>>,
+	/// Tools turned off through the admin API; kept across reloads.
+	disabled: Mutex<BTreeSet<String>>,
+	/// What is running or queued here, for the admin API.
+	executions: Executions,
+	wasm: Arc<WasmRuntime>,
+	components: ComponentRuntime,
+	verifier: Arc<ToolVerifier>,
+	scanner: CodeScanner,
+	output_filters: OutputFilters,
+	quarantine: Quarantine,
+	container: ContainerExecutor,
+	firecracker: FirecrackerExecutor,
+	builtins: Builtins,
+	cgroups: Option<Arc<Cgroups>>,
+	gpus: Option<Arc<Gpus>>,
+	workspaces: Workspaces,
+	artifacts: ArtifactStore,
+	secrets: SecretStore,
+	gateway: Option<Arc<GatewayClient>>,
+	scheduler: Scheduler,
+	audit: Arc<AuditLog>,
+	history: Option<Arc<History>>,
+	quotas: Arc<Quotas>,
+	cache: ResultCache,
+	events: Arc<EventBus>,
+	/// Of the programs, modules and images tools run from.
+	digests: FileDigests,
+	/// Faults injected for resilience testing; inert unless turned on.
+	chaos: Arc<Chaos>,
+	/// Where executions go instead of running here, when dispatching is on.
+	queue: OnceLock<Arc<WorkQueue>>,
+	/// Parent of every execution's cancel token; cancelled by `abort_all`.
+	shutdown: CancellationToken,
+	/// Counts running executions so shutdown can wait for them.
+	in_flight: TaskTracker,
 }
 
-fn make_run_dir() -> PathBuf {
-	let nanos = SystemTime::now()
-		.duration_since(UNIX_EPOCH)
-		.unwrap_or_default()
-		.as_nanos();
-	PathBuf::from("sandbox_runs").join(format!("run-{nanos}"))
+impl Executor {
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		config: ExecutorConfig,
+		registry: ToolRegistry,
+		verifier: Arc<ToolVerifier>,
+		audit: Arc<AuditLog>,
+		history: Option<Arc<History>>,
+		quotas: Arc<Quotas>,
+		cache: ResultCache,
+		events: Arc<EventBus>,
+		chaos: Arc<Chaos>,
+	) -> wasmtime::Result<Self> {
+		let wasm = Arc::new(WasmRuntime::new(config.wasm.clone())?);
+		let components = ComponentRuntime::new(wasm.clone())?;
+		let registry = with_components(&components, &config, &verifier, registry).map_err(wasmtime::Error::msg)?;
+		let scanner = CodeScanner::new(&config.code_scan).map_err(wasmtime::Error::msg)?;
+		let output_filters = OutputFilters::new(&config.output_filters).map_err(wasmtime::Error::msg)?;
+		let container = ContainerExecutor::new(config.container.clone(), config.warm_pool.clone());
+		let firecracker = FirecrackerExecutor::new(config.firecracker.clone(), config.warm_pool.clone());
+		let builtins = Builtins {
+			python: PythonRuntime::new(config.python.clone()).map_err(wasmtime::Error::msg)?,
+			javascript: JavaScriptRuntime::new(config.javascript.clone(), wasm.clone())
+				.map_err(wasmtime::Error::msg)?,
+			shell: ShellTool::new(config.shell.clone()).map_err(wasmtime::Error::msg)?,
+			sql: SqlTool::new(&config.sql).map_err(wasmtime::Error::msg)?,
+			http: HttpTool::new(config.http.clone()).map_err(wasmtime::Error::msg)?,
+			compiled: tool_sdk::tools(),
+		};
+		let cgroups = Cgroups::init(&config.cgroup).map_err(wasmtime::Error::msg)?;
+		let gpus = Gpus::init(&config.gpu).map_err(wasmtime::Error::msg)?;
+		let workspaces = Workspaces::new(&config.workspace).map_err(wasmtime::Error::msg)?;
+		let artifacts = ArtifactStore::new(&config.artifacts).map_err(wasmtime::Error::msg)?;
+		let quarantine = Quarantine::new(&config.quarantine, &config.artifacts).map_err(wasmtime::Error::msg)?;
+		let secrets = SecretStore::new(config.secrets.clone());
+		let gateway = GatewayClient::new(&config.gateway).map_err(wasmtime::Error::msg)?.map(Arc::new);
+		let scheduler = Scheduler::new(
+			config.max_concurrent_executions,
+			config.max_queued_executions,
+			config.preemption.clone(),
+		);
+		Ok(Self {
+			config,
+			loaded: RwLock::new(Arc::new(registry.clone())),
+			registry: RwLock::new(Arc::new(registry)),
+			disabled: Mutex::new(BTreeSet::new()),
+			executions: Executions::default(),
+			wasm,
+			components,
+			verifier,
+			scanner,
+			output_filters,
+			quarantine,
+			container,
+			firecracker,
+			builtins,
+			cgroups,
+			gpus,
+			workspaces,
+			artifacts,
+			secrets,
+			gateway,
+			scheduler,
+			audit,
+			history,
+			quotas,
+			cache,
+			events,
+			digests: FileDigests::default(),
+			chaos,
+			queue: OnceLock::new(),
+			shutdown: CancellationToken::new(),
+			in_flight: TaskTracker::new(),
+		})
+	}
+
+	/// `registry` plus the tools of the components in
+	/// `executor.component_dir`, as they are on disk now.
+	pub fn with_components(&self, registry: ToolRegistry) -> Result<ToolRegistry, RegistryError> {
+		with_components(&self.components, &self.config, &self.verifier, registry)
+	}
+
+	/// What checks the signatures of tool definitions as they are loaded.
+	pub fn verifier(&self) -> &ToolVerifier {
+		&self.verifier
+	}
+
+	/// The quarantined executions.
+	pub fn quarantine(&self) -> &Quarantine {
+		&self.quarantine
+	}
+
+	/// Release a quarantined execution's artifacts, to be fetched by the ids
+	/// its entry lists.
+	pub async fn approve_quarantined(&self, id: &str, by: Option<&str>) -> Result<QuarantineEntry, QuarantineError> {
+		self.quarantine.approve(id, by, &self.artifacts).await
+	}
+
+	/// The current tool set.
+	pub fn registry(&self) -> Arc<ToolRegistry> {
+		self.registry.read().unwrap().clone()
+	}
+
+	/// Make `registry` the tool set for executions starting from now on,
+	/// less the tools turned off.
+	pub fn set_registry(&self, registry: ToolRegistry) {
+		let disabled = self.disabled.lock().unwrap();
+		*self.registry.write().unwrap() = Arc::new(registry.without(&disabled));
+		*self.loaded.write().unwrap() = Arc::new(registry);
+		self.cache.clear();
+		// Setups that no tool uses any more expire with their TTL.
+		self.prewarm();
+	}
+
+	/// Every loaded tool, by name, with whether it is turned on.
+	pub fn tool_states(&self) -> Vec<(String, &'static str, bool)> {
+		let disabled = self.disabled.lock().unwrap();
+		self.loaded
+			.read()
+			.unwrap()
+			.tools()
+			.map(|t| (t.name.clone(), t.backend.as_str(), !disabled.contains(&t.name)))
+			.collect()
+	}
+
+	/// Turn a loaded tool off or on, every version of it, for requests from
+	/// now on; a tool that is off is treated as unknown. `false` if no tool
+	/// by that name is loaded.
+	pub fn set_tool_enabled(&self, name: &str, enabled: bool) -> bool {
+		let mut disabled = self.disabled.lock().unwrap();
+		let loaded = self.loaded.read().unwrap().clone();
+		if loaded.versions(name).is_empty() {
+			return false;
+		}
+		let changed = match enabled {
+			true => disabled.remove(name),
+			false => disabled.insert(name.to_string()),
+		};
+		if changed {
+			*self.registry.write().unwrap() = Arc::new(loaded.without(&disabled));
+			info!(tool_name = name, enabled = enabled, message = "Tool turned on or off by an admin");
+		}
+		true
+	}
+
+	/// Executions running or waiting for a slot here.
+	pub fn executions(&self) -> Vec<ExecutionView> {
+		self.executions.list()
+	}
+
+	/// Cancel the execution with that id, if it runs here.
+	pub fn kill_execution(&self, execution_id: &str) -> bool {
+		self.executions.kill(execution_id)
+	}
+
+	/// Turn new executions away, or take them again; running and queued ones
+	/// carry on.
+	pub fn set_draining(&self, draining: bool) {
+		self.scheduler.set_draining(draining);
+	}
+
+	pub fn draining(&self) -> bool {
+		self.scheduler.draining()
+	}
+
+	/// Whether the execution identified by `workspace_id` left its workspace
+	/// behind, i.e. it was retained and has not expired.
+	pub fn workspace_exists(&self, workspace_id: &str) -> bool {
+		self.workspaces.exists(workspace_id)
+	}
+
+	/// Delete the workspace of execution `workspace_id`, if it was left.
+	pub async fn remove_workspace(&self, workspace_id: &str) {
+		self.workspaces.remove(workspace_id).await;
+	}
+
+	/// Where collected artifacts are served from.
+	pub fn artifacts(&self) -> &ArtifactStore {
+		&self.artifacts
+	}
+
+	/// Front ends record requests they reject before execution here too.
+	pub fn audit(&self) -> &AuditLog {
+		&self.audit
+	}
+
+	pub fn quotas(&self) -> &Quotas {
+		&self.quotas
+	}
+
+	/// The front ends inject their own faults with it too.
+	pub fn chaos(&self) -> &Chaos {
+		&self.chaos
+	}
+
+	/// Send the executions the queue accepts there from now on.
+	pub fn set_work_queue(&self, queue: Arc<WorkQueue>) {
+		let _ = self.queue.set(queue);
+	}
+
+	/// Probe the container runtime, if any registered tool needs it.
+	pub async fn check_container_runtime(&self) -> Option<Result<String, String>> {
+		let needed = self
+			.registry()
+			.all_versions()
+			.any(|t| t.backend == ExecutionBackend::Container);
+		if !needed {
+			return None;
+		}
+		Some(self.container.check_runtime().await)
+	}
+
+	/// Probe KVM and the Firecracker binary, if any registered tool needs
+	/// them.
+	pub async fn check_firecracker(&self) -> Option<Result<Value, String>> {
+		let needed = self
+			.registry()
+			.all_versions()
+			.any(|t| t.backend == ExecutionBackend::Firecracker);
+		if !needed {
+			return None;
+		}
+		Some(self.firecracker.check().await)
+	}
+
+	/// Start the warm containers and VMs the registered tools run in.
+	pub fn prewarm(&self) {
+		let registry = self.registry();
+		self.container
+			.prewarm(registry.all_versions().filter(|t| t.backend == ExecutionBackend::Container));
+		if registry.all_versions().any(|t| t.backend == ExecutionBackend::Firecracker) {
+			self.firecracker.prewarm();
+		}
+	}
+
+	/// Wait until no execution is running. Meant for shutdown: executions that
+	/// start afterwards are still counted, but the wait may already be over.
+	pub async fn wait_idle(&self) {
+		self.in_flight.close();
+		self.in_flight.wait().await;
+	}
+
+	/// Number of executions currently running or waiting for a slot.
+	pub fn in_flight(&self) -> usize {
+		self.in_flight.len()
+	}
+
+	/// Number of executions waiting for a slot.
+	pub fn queued(&self) -> usize {
+		self.scheduler.queued()
+	}
+
+	pub fn capacity(&self) -> Capacity {
+		self.scheduler.capacity()
+	}
+
+	pub fn scaling(&self) -> ScalingSignal {
+		self.scheduler.scaling()
+	}
+
+	/// GPUs handed out to tools, and how many of them are free.
+	pub fn gpus(&self) -> (usize, usize) {
+		self.gpus.as_ref().map_or((0, 0), |gpus| (gpus.count(), gpus.free()))
+	}
+
+	/// Cancel every running and future execution; their results report
+	/// `aborted`.
+	pub fn abort_all(&self) {
+		self.shutdown.cancel();
+	}
+
+	/// `args` as they may appear in the server log.
+	pub fn loggable_args(&self, tool_name: &str, args: &Value) -> Value {
+		match tool_name {
+			"http_request" => self.builtins.http.redact_args(args),
+			_ => args.clone(),
+		}
+	}
+
+	/// Tell a cancellation by shutdown or preemption from one by the caller.
+	fn mark_aborted(&self, ctx: &ExecutionContext, mut result: ToolResult) -> ToolResult {
+		if result.status != "cancelled" {
+			return result;
+		}
+		if self.shutdown.is_cancelled() {
+			result.status = "aborted".to_string();
+		} else if ctx.admission.preempted() {
+			result.status = "preempted".to_string();
+			result.note("preempted by a higher-priority execution\n");
+		} else if ctx.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+			result.status = "deadline_exceeded".to_string();
+			result.note("the caller's deadline passed\n");
+		}
+		result
+	}
+
+	/// The name pinning the definition a request for `requested` runs, as
+	/// [`ToolRegistry::get`] resolves it; `requested` itself if nothing does.
+	pub fn pin_tool(&self, requested: &str) -> String {
+		self.registry().get(requested).map_or_else(|| requested.to_string(), ToolManifest::id)
+	}
+
+	/// What running `tool_name` with `args` would do, resolved the way an
+	/// execution is but without running anything or taking a slot. `None`
+	/// for an unknown tool.
+	pub fn plan(&self, tool_name: &str, args: &Value, options: &PlanOptions) -> Option<ExecutionPlan> {
+		let registry = self.registry();
+		let tool = registry.get(tool_name)?;
+		let (limits, network) = self.policy(tool);
+		let mut env: Vec<String> = self.inherited_env(tool).into_iter().map(|(name, _)| name).collect();
+		env.sort();
+		let cache_ttl_secs = self
+			.cache
+			.key(options.caller, tool, args, options.input)
+			.filter(|_| !options.retain_workspace && options.artifacts.is_empty())
+			.map(|key| key.ttl().as_secs());
+		Some(ExecutionPlan {
+			tool: tool.id(),
+			backend: tool.backend,
+			deprecated: tool.deprecated.clone(),
+			timeout_ms: self.timeout_for(tool_name, options.timeout_ms).as_millis() as u64,
+			priority: Priority::default().min(options.priority.unwrap_or_default()),
+			limits,
+			network,
+			seccomp: tool.seccomp_filter.is_some(),
+			landlock: self.landlocked(tool),
+			env,
+			secrets: tool.secrets.clone(),
+			retry: self.retry_policy(tool, options.retry),
+			cache_ttl_secs,
+			artifacts: tool.artifacts.iter().chain(options.artifacts).cloned().collect(),
+			input_files: options.input.files.keys().cloned().collect(),
+			stdin_bytes: options.input.stdin.as_ref().map_or(0, |stdin| stdin.len()),
+			retain_workspace: options.retain_workspace,
+			policy_violations: match tool.backend {
+				ExecutionBackend::Builtin => self.scanner.scan_request(&tool.name, args),
+				_ => Vec::new(),
+			},
+			requires_approval: tool.requires_approval,
+			estimated_cost: self.quotas.estimate(&tool.limits, self.timeout_for(tool_name, options.timeout_ms)),
+		})
+	}
+
+	/// At most what an attempt of `tool_name` could cost, as budgets are
+	/// checked against; `None` for unknown tools or when nothing is priced.
+	pub fn estimate_cost(&self, tool_name: &str, timeout_ms: Option<u64>) -> Option<f64> {
+		let registry = self.registry();
+		let tool = registry.get(tool_name)?;
+		self.quotas.estimate(&tool.limits, self.timeout_for(tool_name, timeout_ms))
+	}
+
+	/// `tool`'s limits and network policy, as its backend applies them.
+	fn policy(&self, tool: &ToolManifest) -> (Value, Value) {
+		let cgroup_limits = || match &self.cgroups {
+			Some(cgroups) => {
+				let limits = cgroups.spec(&tool.limits).limits;
+				json!({
+					"cpu_weight": limits.cpu_weight,
+					"cpus": limits.cpus,
+					"memory_max_mb": limits.memory_max_mb,
+					"pids_max": limits.pids_max,
+					"cpu_time_limit_ms": limits.cpu_time_limit.map(|d| d.as_millis() as u64),
+				})
+			}
+			None => Value::Null,
+		};
+		match tool.backend {
+			ExecutionBackend::Native => (cgroup_limits(), json!(tool.network.as_ref().unwrap_or(&NetworkPolicy::Full))),
+			ExecutionBackend::Builtin => (cgroup_limits(), Value::Null),
+			ExecutionBackend::Wasm | ExecutionBackend::Component => (
+				json!({ "memory_mb": self.wasm.max_memory_mb(tool), "fuel": self.wasm.fuel(tool) }),
+				json!(tool.network.as_ref().unwrap_or(&NetworkPolicy::None)),
+			),
+			ExecutionBackend::Container => {
+				let limits = self.container.limits(tool);
+				(json!(limits), json!(limits.network))
+			}
+			ExecutionBackend::Firecracker => (self.firecracker.limits(tool), json!(NetworkPolicy::None)),
+		}
+	}
+
+	fn landlocked(&self, tool: &ToolManifest) -> bool {
+		tool.backend == ExecutionBackend::Native && self.workspaces.landlocked()
+	}
+
+	/// What `tool` runs as, and under which policy, in the execution `ctx`.
+	async fn environment(&self, tool: &ToolManifest, ctx: &ExecutionContext) -> ExecutionEnvironment {
+		let mut runtime = BTreeMap::new();
+		let mut files: Vec<(&str, PathBuf)> = Vec::new();
+		match tool.backend {
+			ExecutionBackend::Builtin => {}
+			ExecutionBackend::Native => {
+				files.extend(tool.command.first().and_then(|p| find_program(p)).map(|p| ("program", p)));
+			}
+			ExecutionBackend::Wasm => files.extend(tool.command.first().map(|p| ("module", p.into()))),
+			ExecutionBackend::Component => files.extend(tool.command.first().map(|p| ("component", p.into()))),
+			ExecutionBackend::Container => {
+				let image = self.container.limits(tool).image;
+				runtime.insert("image".to_string(), image.to_string());
+				if let Ok(id) = self.container.image_id(image).await {
+					runtime.insert("image_id".to_string(), id);
+				}
+				files.extend(tool.script.clone().map(|p| ("script", p)));
+			}
+			ExecutionBackend::Firecracker => {
+				let (class, images) = self.firecracker.images(tool);
+				runtime.insert("vm_class".to_string(), class.to_string());
+				if let Some((kernel, rootfs)) = images {
+					files.push(("kernel", kernel.to_path_buf()));
+					files.push(("rootfs", rootfs.to_path_buf()));
+				}
+			}
+		}
+		for (role, path) in files {
+			if let Ok(digest) = self.digests.digest(&path).await {
+				runtime.insert(role.to_string(), digest);
+			}
+		}
+		let (limits, network) = self.policy(tool);
+		let mut env: Vec<String> = self.inherited_env(tool).into_iter().map(|(name, _)| name).collect();
+		env.sort();
+		ExecutionEnvironment {
+			tool: tool.id(),
+			backend: tool.backend,
+			sandbox_version: SANDBOX_VERSION.to_string(),
+			runtime,
+			timeout_ms: ctx.timeout.as_millis() as u64,
+			limits,
+			network,
+			seccomp: tool.seccomp_filter.is_some(),
+			landlock: self.landlocked(tool),
+			env,
+			secrets: tool.secrets.keys().cloned().collect(),
+		}
+	}
+
+	/// Check `args` against the tool's `args_schema`. Unknown tools pass so
+	/// that execution can report them as `unknown_tool`.
+	pub fn validate_args(&self, tool_name: &str, args: &Value) -> Result<(), Vec<ArgError>> {
+		match self.registry().get(tool_name) {
+			Some(tool) => tool.validate_args(args),
+			None => Ok(()),
+		}
+	}
+
+	/// Build the context for one execution of `tool_name`.
+	///
+	/// The requested timeout wins over the manifest's `limits.timeout_ms`,
+	/// which wins over the server default; all are capped at the maximum.
+	/// Fails when the execution queue is full.
+	pub fn context(&self, tool_name: &str, timeout_ms: Option<u64>) -> Result<ExecutionContext, QueueFull> {
+		let admission = self.scheduler.admit()?;
+		let registry = self.registry();
+		let limits = registry.get(tool_name).map(|t| &t.limits);
+		Ok(ExecutionContext {
+			timeout: self.timeout_for(tool_name, timeout_ms),
+			output: None,
+			cancel: self.shutdown.child_token(),
+			caller: Caller::default(),
+			admission: Arc::new(admission),
+			priority: Priority::default(),
+			cgroup: self
+				.cgroups
+				.as_ref()
+				.zip(limits)
+				.map(|(cgroups, limits)| cgroups.spec(limits)),
+			max_output_bytes: self.config.max_output_bytes,
+			workspace_id: uuid::Uuid::new_v4().to_string(),
+			retain_workspace: false,
+			job_id: None,
+			created_at_ms: now_ms(),
+			bypass_cache: false,
+			artifacts: Vec::new(),
+			input: ToolInput::default(),
+			stdin_stream: None,
+			terminal: None,
+			retry: None,
+			from_queue: false,
+			processes: ProcessList::default(),
+			usage: UsageMeter::default(),
+			replay_of: None,
+			trace: None,
+			gateway: self.gateway.clone(),
+			forwarded_auth: None,
+			deadline: None,
+			restore: None,
+			approved_by: None,
+		})
+	}
+
+	/// Whether `tool_name` runs only as an approved job.
+	pub fn requires_approval(&self, tool_name: &str) -> bool {
+		self.registry().get(tool_name).is_some_and(|t| t.requires_approval)
+	}
+
+	/// The wall-clock limit for one execution of `tool_name`, resolved as
+	/// described on [`Executor::context`].
+	pub fn timeout_for(&self, tool_name: &str, timeout_ms: Option<u64>) -> Duration {
+		let manifest_ms = self.registry().get(tool_name).and_then(|t| t.limits.timeout_ms);
+		let timeout_ms = timeout_ms
+			.or(manifest_ms)
+			.filter(|ms| *ms > 0)
+			.unwrap_or(self.config.default_timeout_ms)
+			.min(self.config.max_timeout_ms);
+		Duration::from_millis(timeout_ms)
+	}
+
+	/// How long before a caller's deadline its executions must end.
+	pub fn deadline_overhead(&self) -> Duration {
+		Duration::from_millis(self.config.deadline_overhead_ms)
+	}
+
+	/// Execute a tool request on the backend its manifest names.
+	///
+	/// When `ctx.output` is set, output is forwarded live by the native,
+	/// container and component backends and in one piece at exit by the
+	/// others. With
+	/// dispatching on, executions the work queue accepts run on a worker
+	/// instead.
+	#[tracing::instrument(name = "execute_tool", skip_all, fields(tool_name = name, backend, status))]
+	pub async fn execute_tool(&self, name: &str, args: Value, ctx: &ExecutionContext) -> ToolResult {
+		let _in_flight = self.in_flight.token();
+		let _deadline = ctx.deadline.map(|deadline| DeadlineGuard::arm(deadline, ctx.cancel.clone()));
+		// From here on, a range names the version it resolved to, on a worker too.
+		let pinned = self.pin_tool(name);
+		let name = pinned.as_str();
+		// The worker that runs it records, accounts and announces it.
+		if let Some(queue) = self.queue.get().filter(|queue| queue.accepts(ctx)) {
+			let result = match queue.dispatch(name, &args, ctx).await {
+				Ok(result) => result,
+				Err(result) => {
+					self.audit.record(&ctx.caller, name, &args, &result.status);
+					result
+				}
+			};
+			return self.mark_aborted(ctx, result);
+		}
+		let (result, started_at_ms) = match self.events.tap_output(name, ctx) {
+			Some(tap) => {
+				let outcome = self.run_execution(name, args, &tap.ctx).await;
+				// The output events go out before the one that ends them.
+				tap.close().await;
+				outcome
+			}
+			None => self.run_execution(name, args, ctx).await,
+		};
+		self.events.finished(name, ctx, started_at_ms, &result);
+		result
+	}
+
+	/// `execute_tool` short of the final event; also returns when the tool
+	/// started, if it did.
+	async fn run_execution(&self, name: &str, args: Value, ctx: &ExecutionContext) -> (ToolResult, Option<u64>) {
+		let span = Span::current();
+		let registry = self.registry();
+		let Some(tool) = registry.get(name) else {
+			let result = unknown_tool(name, &args);
+			// Unregistered names are caller input; keep them out of the labels.
+			METRICS.start_execution("unknown").finish(&result);
+			self.audit.record(&ctx.caller, name, &args, &result.status);
+			self.record_history(name, &args, ctx, None, &result);
+			ctx.emit_buffered(&result).await;
+			return (result, None);
+		};
+
+		span.record("backend", tool.backend.as_str());
+		if tool.requires_approval && ctx.approved_by.is_none() {
+			let result = approval_required(name);
+			METRICS.start_execution(name).finish(&result);
+			span.record("status", result.status.as_str());
+			self.audit.record(&ctx.caller, name, &args, &result.status);
+			self.record_history(name, &args, ctx, None, &result);
+			ctx.emit_buffered(&result).await;
+			return (result, None);
+		}
+		if let Some(note) = &tool.deprecated {
+			warn!(tool_name = name, deprecated = note.as_str(), message = "Running a deprecated tool version");
+		}
+		// A manifest tool may share a built-in's name, but not the code it runs.
+		let findings = match tool.backend {
+			ExecutionBackend::Builtin => self.scanner.scan_request(&tool.name, &args),
+			_ => Vec::new(),
+		};
+		if code_scan::rejects(&findings) {
+			let result = policy_violation(findings);
+			METRICS.start_execution(name).finish(&result);
+			span.record("status", result.status.as_str());
+			self.audit.record(&ctx.caller, name, &args, &result.status);
+			self.record_history(name, &args, ctx, None, &result);
+			ctx.emit_buffered(&result).await;
+			return (result, None);
+		}
+		if !findings.is_empty() {
+			warn!(tool_name = name, findings = findings.len(), message = "Running code the code scan flagged");
+		}
+		// A suspicious execution runs cut down, and is held once it has run.
+		let mut suspicions = self.quarantine.reasons_before(&ctx.caller, &findings);
+		let restricted = (!suspicions.is_empty()).then(|| self.restricted(tool, ctx));
+		let (tool, ctx) = match &restricted {
+			Some((tool, ctx)) => (tool, ctx),
+			None => (tool, ctx),
+		};
+		// A retained workspace, the artifacts, a live session or a trace is
+		// what the caller is after, so those runs neither use nor fill the
+		// cache.
+		let cache_key = self.cache.key(&ctx.caller, tool, &args, &ctx.input).filter(|_| {
+			!ctx.retain_workspace
+				&& ctx.artifacts.is_empty()
+				&& ctx.stdin_stream.is_none()
+				&& ctx.trace.is_none()
+				&& restricted.is_none()
+		});
+		if let Some(key) = &cache_key {
+			let cached = if ctx.bypass_cache {
+				METRICS.cache_lookups.with_label_values(&[name, "bypass"]).inc();
+				None
+			} else {
+				self.cache.get(key).await
+			};
+			if let Some(mut result) = cached {
+				result.policy_violations = findings;
+				METRICS.cache_lookups.with_label_values(&[name, "hit"]).inc();
+				span.record("status", result.status.as_str());
+				self.audit.record(&ctx.caller, name, &args, &result.status);
+				self.record_history(name, &args, ctx, None, &result);
+				ctx.emit_buffered(&result).await;
+				return (result, None);
+			}
+			if !ctx.bypass_cache {
+				METRICS.cache_lookups.with_label_values(&[name, "miss"]).inc();
+			}
+		}
+
+		let tracked = self.executions.track(name, tool.backend.as_str(), &tool.limits, ctx);
+		// GPUs come before a slot, so that executions waiting for one leave
+		// the slots to those that can run.
+		let (gpus, turn) = match self.gpu_lease(tool, ctx).await {
+			Ok(gpus) => (gpus, ctx.wait_turn().await),
+			Err(result) => (None, Err(result)),
+		};
+		let timer = METRICS.start_execution(name);
+		if let Err(result) = turn {
+			let result = self.mark_aborted(ctx, result);
+			timer.finish(&result);
+			self.audit.record(&ctx.caller, name, &args, &result.status);
+			self.record_history(name, &args, ctx, None, &result);
+			return (result, None);
+		}
+		let started_at_ms = now_ms();
+		let started = Instant::now();
+		tracked.started(started_at_ms);
+		self.events.started(name, ctx);
+		let devices = gpus.as_ref().map(GpuLease::devices).unwrap_or_default();
+		let (mut result, environment) =
+			tokio::join!(self.run_with_retries(tool, &args, ctx, &devices), self.environment(tool, ctx));
+		drop(gpus);
+		let wall = started.elapsed();
+		let mut usage = ctx.usage.total(wall);
+		result.environment = Some(Box::new(environment));
+		result.policy_violations = findings;
+		// Backends without a cgroup are charged the time they held the slot.
+		let cpu = ctx.cgroup.as_ref().and_then(CgroupSpec::cpu_used).unwrap_or(wall);
+		let used = Consumption {
+			cpu,
+			memory_bytes: tool.limits.memory_mb.map(|mb| mb << 20).or(usage.peak_rss_bytes).unwrap_or(0),
+			gpus: devices.len() as u32,
+			wall,
+		};
+		usage.cost = self.quotas.record(&ctx.caller, &used, &result.artifacts);
+		result.usage = Some(usage);
+		suspicions.extend(self.quarantine.reasons_after(result.usage.as_ref()));
+		if !suspicions.is_empty() {
+			let entry = self
+				.quarantine
+				.hold(&ctx.workspace_id, name, &ctx.caller, &mut result, suspicions, &self.artifacts)
+				.await;
+			self.events.quarantined(name, ctx, &entry);
+		}
+		let result = self.mark_aborted(ctx, result);
+		timer.finish(&result);
+		span.record("status", result.status.as_str());
+		self.audit.record(&ctx.caller, name, &args, &result.status);
+		self.record_history(name, &args, ctx, Some(started_at_ms), &result);
+		if let Some(key) = cache_key.filter(|_| result.quarantined.is_none()) {
+			self.cache.put(key, &result);
+		}
+		if !tool.backend.streams_live() {
+			ctx.emit_buffered(&result).await;
+		}
+		(result, Some(started_at_ms))
+	}
+
+	/// `tool` and `ctx` as a quarantined execution runs them.
+	fn restricted(&self, tool: &ToolManifest, ctx: &ExecutionContext) -> (ToolManifest, ExecutionContext) {
+		let tool = self.quarantine.restrict(tool);
+		let mut ctx = ctx.clone();
+		if let Some(cap) = tool.limits.timeout_ms {
+			ctx.timeout = ctx.timeout.min(Duration::from_millis(cap));
+		}
+		ctx.cgroup = self.cgroups.as_ref().map(|cgroups| cgroups.spec(&tool.limits));
+		(tool, ctx)
+	}
+
+	/// Run the tool until it succeeds, fails for good or runs out of
+	/// attempts, waiting out the policy's backoff in between. Interactive
+	/// executions run once: their stdin cannot be replayed.
+	async fn run_with_retries(
+		&self,
+		tool: &ToolManifest,
+		args: &Value,
+		ctx: &ExecutionContext,
+		gpus: &[String],
+	) -> ToolResult {
+		let policy = match self.retry_policy(tool, ctx.retry.as_ref()) {
+			Some(policy) if ctx.stdin_stream.is_none() => policy,
+			_ => return self.run_in_workspace(tool, args, ctx, gpus).await,
+		};
+		let mut attempt = 1;
+		loop {
+			let mut result = self.run_in_workspace(tool, args, ctx, gpus).await;
+			if attempt >= policy.max_attempts || !policy.retryable(&result) || ctx.cancel.is_cancelled() {
+				result.attempts = Some(attempt);
+				return result;
+			}
+			let backoff = policy.backoff(attempt);
+			info!(
+				tool_name = %tool.name,
+				attempt = attempt,
+				status = %result.status,
+				backoff_ms = backoff.as_millis() as u64,
+				message = "Retrying tool after a transient failure"
+			);
+			METRICS.retries.with_label_values(&[&tool.name]).inc();
+			if ctx.retain_workspace {
+				self.workspaces.remove(&ctx.workspace_id).await;
+			}
+			tokio::select! {
+				_ = tokio::time::sleep(backoff) => {}
+				_ = ctx.cancel.cancelled() => {
+					result.attempts = Some(attempt);
+					return result;
+				}
+			}
+			attempt += 1;
+		}
+	}
+
+	/// The manifest's retry policy with the request's `changes`, or the
+	/// default one if only the request asks for retries.
+	fn retry_policy(&self, tool: &ToolManifest, changes: Option<&RetryOverride>) -> Option<RetryPolicy> {
+		match (&tool.retry, changes) {
+			(Some(policy), changes) => Some(policy.with_override(changes, self.config.max_attempts)),
+			(None, Some(changes)) => Some(RetryPolicy::default().with_override(Some(changes), self.config.max_attempts)),
+			(None, None) => None,
+		}
+	}
+
+	fn record_history(
+		&self,
+		name: &str,
+		args: &Value,
+		ctx: &ExecutionContext,
+		started_at_ms: Option<u64>,
+		result: &ToolResult,
+	) {
+		if let Some(history) = &self.history {
+			let kept = ctx.retain_workspace && self.workspaces.exists(&ctx.workspace_id);
+			// Live stdin is gone once read, so such executions cannot be replayed.
+			let request = (history.records_requests() && ctx.stdin_stream.is_none()).then(|| RecordedRequest {
+				tool_name: name.to_string(),
+				args: args.clone(),
+				timeout_ms: ctx.timeout.as_millis() as u64,
+				retain_workspace: ctx.retain_workspace,
+				artifacts: ctx.artifacts.clone(),
+				stdin: ctx.input.stdin.as_ref().map(|b| Base64(b.to_vec())),
+				input_files: ctx
+					.input
+					.files
+					.iter()
+					.map(|(path, data)| (path.clone(), Base64(data.clone())))
+					.collect(),
+				retry: ctx.retry.clone(),
+				env: self.registry().get(name).map(|tool| self.env_fingerprint(tool)).unwrap_or_default(),
+			});
+			history.record_execution(name, args, ctx, started_at_ms, result, kept, request.as_ref());
+		}
+	}
+
+	/// Resolve the tool's environment, then run it on its backend in a fresh
+	/// workspace, with the `gpus` it holds.
+	async fn run_in_workspace(
+		&self,
+		tool: &ToolManifest,
+		args: &Value,
+		ctx: &ExecutionContext,
+		gpus: &[String],
+	) -> ToolResult {
+		if self.chaos.crash() {
+			return simulated_crash();
+		}
+		let mut env = match self.tool_env(tool).await {
+			Ok(env) => env,
+			Err(result) => return result,
+		};
+		// With GPUs scheduled, tools see the ones they hold and no others.
+		if self.gpus.is_some() && matches!(tool.backend, ExecutionBackend::Native | ExecutionBackend::Container) {
+			env.vars.retain(|(name, _)| name != "CUDA_VISIBLE_DEVICES");
+			env.vars.push(("CUDA_VISIBLE_DEVICES".to_string(), gpus.join(",")));
+			env.gpus = gpus.to_vec();
+		}
+		// A traced execution's clock and seed win over the host's.
+		if let Some(trace) = &ctx.trace {
+			let traced = trace.env();
+			env.vars.retain(|(name, _)| !traced.iter().any(|(var, _)| var == name));
+			env.vars.extend(traced);
+		}
+		let workspace = match &ctx.restore {
+			Some(_) => self.workspaces.open(&ctx.workspace_id),
+			None => self.workspaces.create(&ctx.workspace_id, tool.limits.disk_mb).await,
+		};
+		let workspace = match workspace {
+			Ok(workspace) => workspace,
+			Err(e) => return workspace_error(e),
+		};
+		if let Err(e) = workspace.write_inputs(&ctx.input).await {
+			workspace.finish(false).await;
+			return ToolResult {
+				status: "io_error".to_string(),
+				stderr: format!("failed to write input file {e}"),
+				..Default::default()
+			};
+		}
+		// A workspace that fills up stops the execution the way a cancel does.
+		let watch = workspace
+			.quota
+			.clone()
+			.map(|quota| tokio::spawn(quota.watch(ctx.cancel.clone())));
+		// Live output is filtered on its way, the result once it is done;
+		// everything after (the cache, history, audit log) sees it filtered.
+		let filter = self.output_filters.for_tool(&tool.name).cloned();
+		let secrets: Vec<String> = match &filter {
+			Some(_) => env.secrets.iter().map(|(_, secret)| secret.expose().to_string()).collect(),
+			None => Vec::new(),
+		};
+		let tap = filter.as_ref().and_then(|filter| filter.tap(ctx, &secrets));
+		let live = tap.as_ref().map_or(ctx, |tap| &tap.ctx);
+		let result = match tool.backend {
+			// A checkpointed process comes back as it ran, whatever the
+			// manifest has become since.
+			_ if live.restore.is_some() => execute_native_tool(tool, args, live, &workspace, &env).await,
+			ExecutionBackend::Builtin => {
+				execute_builtin_tool(&tool.name, args.clone(), live, &workspace, &env, &self.builtins).await
+			}
+			ExecutionBackend::Native => execute_native_tool(tool, args, live, &workspace, &env).await,
+			ExecutionBackend::Wasm => {
+				self.wasm
+					.run(tool, args, &workspace.path, &env, live)
+					.await
+			}
+			ExecutionBackend::Component => self.components.run(tool, args, &workspace.path, &env, live).await,
+			ExecutionBackend::Container => self.container.run(tool, args, &workspace.path, &env, live).await,
+			ExecutionBackend::Firecracker => self.firecracker.run(tool, args, &workspace.path, &env, live).await,
+		};
+		let mut result = result;
+		if let Some(tap) = tap {
+			tap.close().await;
+		}
+		if let Some(watch) = watch {
+			watch.abort();
+		}
+		if let Some(filter) = &filter {
+			filter.filter_result(&mut result, &secrets);
+		}
+		if let Some(quota) = &workspace.quota {
+			if quota.exceeded().await {
+				result.status = "disk_quota_exceeded".to_string();
+				result.note(&format!("the workspace went over its {} MB disk quota\n", quota.limit_mb()));
+			}
+		}
+		let patterns: Vec<&str> = tool.artifacts.iter().chain(&ctx.artifacts).map(String::as_str).collect();
+		if !patterns.is_empty() {
+			let collected = self.artifacts.collect(&workspace.path, &patterns, &ctx.caller).await;
+			for note in &collected.skipped {
+				result.note(&format!("artifact not collected: {note}\n"));
+			}
+			result.artifacts = collected.artifacts;
+		}
+		self.divert_binary_output(&mut result, &ctx.caller).await;
+		workspace.finish(ctx.retain_workspace).await;
+		result
+	}
+
+	/// Store stdout or stderr that is not text, and over the configured
+	/// size, as an artifact for `caller`, leaving its artifact id in its place.
+	async fn divert_binary_output(&self, result: &mut ToolResult, caller: &Caller) {
+		let Some(mut carried) = result.encoding else {
+			return;
+		};
+		for (stream, text, kept) in [
+			("stdout", &mut result.stdout, &mut carried.stdout),
+			("stderr", &mut result.stderr, &mut carried.stderr),
+		] {
+			if *kept != Encoding::Base64 {
+				continue;
+			}
+			let bytes = encoding::decode(text, *kept);
+			if !self.artifacts.diverts_output(bytes.len()) {
+				continue;
+			}
+			match self.artifacts.store_bytes(bytes, caller).await {
+				Ok(artifact_id) => {
+					*text = artifact_id;
+					*kept = Encoding::Artifact;
+				}
+				Err(e) => warn!(stream = stream, error = %e, message = "Failed to store binary output; it stays inline"),
+			}
+		}
+		result.encoding = Some(carried);
+	}
+
+	/// The GPUs `tool` asks for, once they are free; `None` if it asks for
+	/// none.
+	async fn gpu_lease(&self, tool: &ToolManifest, ctx: &ExecutionContext) -> Result<Option<GpuLease>, ToolResult> {
+		let count = match tool.limits.gpu {
+			None | Some(0) => return Ok(None),
+			Some(count) => count,
+		};
+		let gpus = match &self.gpus {
+			Some(gpus) if count as usize <= gpus.count() => gpus,
+			Some(gpus) => {
+				let message = format!("tool needs {count} GPUs; this host has {}", gpus.count());
+				return Err(gpu_unavailable(message));
+			}
+			None => return Err(gpu_unavailable("tool needs a GPU; GPU scheduling is off".to_string())),
+		};
+		tokio::select! {
+			lease = gpus.acquire(count) => Ok(Some(lease)),
+			_ = ctx.cancel.cancelled() => ctx.check_cancelled().map(|()| None),
+		}
+	}
+
+	/// The host variables `tool` inherits.
+	fn inherited_env(&self, tool: &ToolManifest) -> Vec<(String, String)> {
+		let mut patterns: Vec<&str> = tool.env_allow.iter().map(String::as_str).collect();
+		if matches!(tool.backend, ExecutionBackend::Native | ExecutionBackend::Builtin) {
+			patterns.extend(self.config.env_allow.iter().map(String::as_str));
+		}
+		self.secrets.inherited_env(&patterns)
+	}
+
+	/// The host variables `tool` inherits, each with the SHA-256 of its
+	/// value: what a replay compares, without holding the values.
+	pub fn env_fingerprint(&self, tool: &ToolManifest) -> BTreeMap<String, String> {
+		self.inherited_env(tool)
+			.into_iter()
+			.map(|(name, value)| {
+				let digest = Sha256::digest(value.as_bytes());
+				(name, digest.iter().map(|b| format!("{b:02x}")).collect())
+			})
+			.collect()
+	}
+
+	/// The allowlisted host variables and resolved secrets for one execution.
+	async fn tool_env(&self, tool: &ToolManifest) -> Result<ToolEnv, ToolResult> {
+		let mut env = ToolEnv {
+			vars: self.inherited_env(tool),
+			secrets: Vec::with_capacity(tool.secrets.len()),
+			gpus: Vec::new(),
+		};
+		for (var, name) in &tool.secrets {
+			match self.secrets.get(name).await {
+				Ok(secret) => env.secrets.push((var.clone(), secret)),
+				Err(e) => {
+					warn!(tool_name = %tool.name, secret = %name, error = %e, message = "Secret unavailable");
+					return Err(ToolResult {
+						status: "secret_unavailable".to_string(),
+						stderr: format!("secret {name:?} for {var} is unavailable: {e}"),
+						..Default::default()
+					});
+				}
+			}
+		}
+		Ok(env)
+	}
 }
 
-/// Execute a tool request.
-///
-/// Dispatch order:
-/// 1) Internal tools (e.g. web_search)
-/// 2) Source-code execution tools (e.g. execute_code)
-pub async fn execute_tool(name: &str, args: Value) -> ToolResult {
-	let internal = execute_internal_tool(name, args.clone()).await;
-	if internal.status != "not_internal" {
-		return internal;
+/// Drive an execution on its own task, cancelling `cancel` if the caller is
+/// dropped first (e.g. the client disconnected). The execution then still winds
+/// down normally and cleans up after itself instead of being torn down midway.
+pub async fn run_detached<T: Send + 'static>(
+	cancel: CancellationToken,
+	execution: impl Future<Output = T> + Send + 'static,
+) -> T {
+	let _cancel_on_drop = cancel.drop_guard();
+	match tokio::spawn(execution.in_current_span()).await {
+		Ok(output) => output,
+		Err(e) => std::panic::resume_unwind(e.into_panic()),
 	}
+}
+
+fn workspace_error(e: std::io::Error) -> ToolResult {
+	ToolResult {
+		status: "io_error".to_string(),
+		stderr: format!("failed to create workspace: {e}"),
+		..Default::default()
+	}
+}
+
+fn gpu_unavailable(message: String) -> ToolResult {
+	ToolResult {
+		status: "gpu_unavailable".to_string(),
+		stderr: message,
+		..Default::default()
+	}
+}
+
+fn simulated_crash() -> ToolResult {
+	ToolResult {
+		status: "internal_error".to_string(),
+		stderr: "chaos: simulated executor crash".to_string(),
+		..Default::default()
+	}
+}
+
+fn unknown_tool(name: &str, args: &Value) -> ToolResult {
+	ToolResult {
+		status: "unknown_tool".to_string(),
+		stdout: serde_json::to_string_pretty(&json!({
+			"message": "Unknown tool",
+			"tool_name": name,
+			"echo": args,
+		}))
+		.unwrap_or_else(|_| "Unknown tool".to_string()),
+		..Default::default()
+	}
+}
+
+/// The code scan's refusal to run submitted code, with what it found.
+fn approval_required(name: &str) -> ToolResult {
+	ToolResult {
+		status: "approval_required".to_string(),
+		stderr: format!("{name} requires approval; submit it to /api/v1/jobs and have an approver approve the job\n"),
+		..Default::default()
+	}
+}
 
+fn policy_violation(findings: Vec<PolicyViolation>) -> ToolResult {
+	let stderr: String = findings
+		.iter()
+		.filter(|v| v.action == ScanAction::Reject)
+		.map(|v| format!("blocked by the sandbox's code scan: {v}\n"))
+		.collect();
+	ToolResult {
+		status: "policy_violation".to_string(),
+		stderr,
+		policy_violations: findings,
+		..Default::default()
+	}
+}
+
+/// What the built-in tools that run code or commands run them with, and
+/// the tools registered through the SDK.
+struct Builtins {
+	python: PythonRuntime,
+	javascript: JavaScriptRuntime,
+	shell: ShellTool,
+	sql: SqlTool,
+	http: HttpTool,
+	compiled: BTreeMap<&'static str, Arc<dyn Tool>>,
+}
+
+fn with_components(
+	components: &ComponentRuntime,
+	config: &ExecutorConfig,
+	verifier: &ToolVerifier,
+	registry: ToolRegistry,
+) -> Result<ToolRegistry, RegistryError> {
+	match &config.component_dir {
+		Some(dir) => registry.with_components(
+			components
+				.load(dir, verifier)
+				.map_err(|errors| RegistryError { errors })?,
+		),
+		None => Ok(registry),
+	}
+}
+
+/// Run a tool compiled into the sandbox.
+async fn execute_builtin_tool(
+	name: &str,
+	args: Value,
+	ctx: &ExecutionContext,
+	workspace: &Workspace,
+	env: &ToolEnv,
+	builtins: &Builtins,
+) -> ToolResult {
+	if let Some(tool) = builtins.compiled.get(name) {
+		return run_compiled(tool.as_ref(), args, ctx).await;
+	}
 	match name {
 		"execute_code" => {
 			let lang_value = args.get("language").cloned().unwrap_or(json!("python"));
@@ -78,64 +1708,170 @@ pub async fn execute_tool(name: &str, args: Value) -> ToolResult {
 				serde_json::from_value(lang_value).unwrap_or(ToolLanguage::Python);
 
 			let code = args.get("code").and_then(|v| v.as_str()).unwrap_or("");
-			let exec = execute_code(language, code).await;
-
-			let stdout = serde_json::to_string_pretty(&exec).unwrap_or_else(|_| exec.stdout.clone());
-			let stderr = format!(
-				"{}{}{}",
-				exec.compile_stderr,
-				if exec.compile_stderr.is_empty() { "" } else { "\n" },
-				exec.stderr
-			);
+			code_result(execute_code(language, code, ctx, workspace, env, builtins).await)
+		}
+		"python" => {
+			let code = args.get("code").and_then(|v| v.as_str()).unwrap_or("");
+			let argv: Vec<String> = args
+				.get("argv")
+				.and_then(|v| v.as_array())
+				.map(|a| a.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+				.unwrap_or_default();
+			code_result(builtins.python.run(code, &argv, ctx, workspace, env).await)
+		}
+		"javascript" => {
+			let code = args.get("code").and_then(|v| v.as_str()).unwrap_or("");
+			let argv: Vec<String> = args
+				.get("argv")
+				.and_then(|v| v.as_array())
+				.map(|a| a.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+				.unwrap_or_default();
+			code_result(builtins.javascript.run(code, &argv, ctx, workspace, env).await)
+		}
+		"shell" => builtins.shell.run(&args, ctx, workspace, env).await,
+		"sql_query" => builtins.sql.run(&args, ctx).await,
+		"http_request" => builtins.http.run(&args, ctx).await,
+		_ => unknown_tool(name, &args),
+	}
+}
 
-			ToolResult {
-				status: exec.status,
-				stdout,
-				stderr,
+/// Run an SDK tool, within the execution's timeout and until it is
+/// cancelled.
+async fn run_compiled(tool: &dyn Tool, args: Value, ctx: &ExecutionContext) -> ToolResult {
+	let run = tokio::time::timeout(ctx.timeout, tool.run(ctx, args));
+	tokio::select! {
+		outcome = run => match outcome {
+			Ok(result) => result,
+			Err(_) => ToolResult {
+				status: "timed_out".to_string(),
+				stderr: format!("{} did not finish within {}ms", tool.name(), ctx.timeout.as_millis()),
+				..Default::default()
+			},
+		},
+		_ = ctx.cancel.cancelled() => ctx.check_cancelled().unwrap_err(),
+	}
+}
+
+/// Run a native tool as a subprocess.
+///
+/// The manifest's `command` is spawned with the JSON args object appended as
+/// the last argument, and the tool name exposed as `PAGI_TOOL_NAME`. It runs
+/// in its workspace, which `HOME` and `TMPDIR` also point to. Under Landlock
+/// it may also read its program's directory and its `data_dir`. A session
+/// process restored from a checkpoint runs as `criu restore` instead.
+async fn execute_native_tool(
+	tool: &ToolManifest,
+	args: &Value,
+	ctx: &ExecutionContext,
+	workspace: &Workspace,
+	env: &ToolEnv,
+) -> ToolResult {
+	// A restored process brings its own command, environment and
+	// confinement back from its checkpoint.
+	let spec = match &ctx.restore {
+		Some(checkpoint) => checkpoint.restore_command(),
+		None => native_command(tool, args, workspace, env),
+	};
+	let spec = spec
+		.stdin(ctx.stdin())
+		.terminal(ctx.terminal(tool))
+		.timeout(ctx.timeout)
+		.output(ctx.output.clone())
+		.max_output(ctx.max_output_bytes)
+		.cancel(ctx.cancel.clone())
+		.processes(ctx.processes.clone())
+		.usage(ctx.usage.clone())
+		.cgroup(ctx.cgroup.clone());
+
+	match subprocess::run(&spec).await {
+		Ok(mut out) => {
+			if out.policy_violation {
+				warn!(tool_name = %tool.name, message = "Native tool killed by its seccomp profile");
+				out.note("killed by the sandbox: syscall not allowed by the tool's seccomp profile\n");
+			}
+			if !out.network_denied.is_empty() {
+				warn!(tool_name = %tool.name, denied = ?out.network_denied, message = "Native tool tried to reach the network outside its policy");
+				out.note(&format!(
+					"blocked by the sandbox: not allowed by the tool's network policy: {}\n",
+					out.network_denied.join(", ")
+				));
 			}
-		}
-		"weather_tool" => {
-			let city = args.get("city").and_then(|v| v.as_str()).unwrap_or("unknown");
 			ToolResult {
-				status: "ok".to_string(),
-				stdout: serde_json::to_string_pretty(&json!({
-					"city": city,
-					"temperature": "22C",
-					"conditions": "Sunny",
-				}))
-				.unwrap_or_else(|_| format!("{{\"city\":\"{}\"}}", city)),
-				stderr: "".to_string(),
+				status: out.status().to_string(),
+				stdout: out.stdout,
+				stderr: out.stderr,
+				encoding: out.encoding,
+				exit_code: out.exit_code,
+				truncated: out.truncated,
+				..Default::default()
 			}
 		}
-		_ => ToolResult {
-			status: "unknown_tool".to_string(),
-			stdout: serde_json::to_string_pretty(&json!({
-				"message": "Unknown tool",
-				"tool_name": name,
-				"echo": args,
-			}))
-			.unwrap_or_else(|_| "Unknown tool".to_string()),
-			stderr: "".to_string(),
+		Err(e) => ToolResult {
+			status: subprocess::spawn_error_status(&e).to_string(),
+			stderr: format!("failed to spawn {}: {e}", spec.program),
+			..Default::default()
 		},
 	}
 }
 
-pub async fn execute_internal_tool(name: &str, args: Value) -> ToolResult {
-	if name == "web_search" {
-		return tool_web_search::execute_web_search(args).await;
+/// The manifest's command with `args`, confined to `workspace`.
+fn native_command(tool: &ToolManifest, args: &Value, workspace: &Workspace, env: &ToolEnv) -> CommandSpec {
+	let (program, leading) = tool
+		.command
+		.split_first()
+		.expect("native manifests are validated to have a command");
+	let mut spec = CommandSpec::new(program);
+	for arg in leading {
+		spec = spec.arg(arg);
 	}
+	let program_dir = program.contains('/').then(|| Path::new(program).parent()).flatten();
+	let landlock = workspace
+		.landlock
+		.as_ref()
+		.map(|landlock| landlock.with_readable(program_dir.into_iter().chain(tool.data_dir.as_deref())));
+	spec.arg(args.to_string())
+		.tool_env(env)
+		.env("PAGI_TOOL_NAME", &tool.name)
+		.env("HOME", workspace.path.to_string_lossy())
+		.env("TMPDIR", workspace.path.to_string_lossy())
+		.cwd(&workspace.path)
+		.jail(workspace.jail.clone())
+		.pid_namespace(workspace.pid_namespace)
+		.network(tool.network.as_ref().and_then(NetworkJail::new))
+		.landlock(landlock)
+		.seccomp(tool.seccomp_filter.clone())
+}
 
+/// The result of running submitted code, with the structured outcome as the
+/// JSON stdout and the compiler's and program's stderr together.
+fn code_result(exec: ToolExecutionResult) -> ToolResult {
+	let stdout = serde_json::to_string_pretty(&exec).unwrap_or_else(|_| exec.stdout.clone());
+	let stderr_encoding = OutputEncoding::stderr(exec.encoding);
+	let stderr = encoding::prefixed(&exec.compile_stderr, &exec.stderr, stderr_encoding);
 	ToolResult {
-		status: "not_internal".to_string(),
-		stdout: "".to_string(),
-		stderr: "".to_string(),
+		status: exec.status,
+		stdout,
+		stderr,
+		encoding: OutputEncoding::of(Encoding::Utf8, stderr_encoding),
+		exit_code: exec.exit_code,
+		truncated: exec.truncated,
+		..Default::default()
 	}
 }
 
 /// Execute source code in the requested language.
-async fn execute_code(language: ToolLanguage, source_code: &str) -> ToolExecutionResult {
+async fn execute_code(
+	language: ToolLanguage,
+	source_code: &str,
+	ctx: &ExecutionContext,
+	workspace: &Workspace,
+	env: &ToolEnv,
+	builtins: &Builtins,
+) -> ToolExecutionResult {
 	match language {
-		ToolLanguage::Java => execute_java_tool(source_code).await,
+		ToolLanguage::Java => execute_java_tool(source_code, ctx, workspace, env).await,
+		ToolLanguage::Python => builtins.python.run(source_code, &[], ctx, workspace, env).await,
+		ToolLanguage::Javascript => builtins.javascript.run(source_code, &[], ctx, workspace, env).await,
 		_ => ToolExecutionResult {
 			status: "unsupported_language".to_string(),
 			language,
@@ -143,25 +1879,23 @@ async fn execute_code(language: ToolLanguage, source_code: &str) -> ToolExecutio
 			stderr: "".to_string(),
 			compile_stdout: "".to_string(),
 			compile_stderr: format!("Language {language:?} not implemented in sandbox yet"),
+			encoding: None,
 			exit_code: None,
+			truncated: None,
+			exception: None,
 		},
 	}
 }
 
-async fn execute_java_tool(source_code: &str) -> ToolExecutionResult {
-	let run_dir = make_run_dir();
-	if let Err(e) = fs::create_dir_all(&run_dir).await {
-		return ToolExecutionResult {
-			status: "io_error".to_string(),
-			language: ToolLanguage::Java,
-			stdout: "".to_string(),
-			stderr: "".to_string(),
-			compile_stdout: "".to_string(),
-			compile_stderr: format!("failed to create run dir: {e}"),
-			exit_code: None,
-		};
-	}
-
+/// Compile and run a Java tool. The context timeout bounds compilation and
+/// execution separately.
+async fn execute_java_tool(
+	source_code: &str,
+	ctx: &ExecutionContext,
+	workspace: &Workspace,
+	env: &ToolEnv,
+) -> ToolExecutionResult {
+	let run_dir = &workspace.path;
 	let java_path = run_dir.join("Tool.java");
 	if let Err(e) = fs::write(&java_path, source_code).await {
 		return ToolExecutionResult {
@@ -171,11 +1905,26 @@ async fn execute_java_tool(source_code: &str) -> ToolExecutionResult {
 			stderr: "".to_string(),
 			compile_stdout: "".to_string(),
 			compile_stderr: format!("failed to write Tool.java: {e}"),
+			encoding: None,
 			exit_code: None,
+			truncated: None,
+			exception: None,
 		};
 	}
 
-	let compile = match run_cmd("javac", &["Tool.java"], &run_dir).await {
+	let javac = CommandSpec::new("javac")
+		.arg("Tool.java")
+		.cwd(run_dir)
+		.tool_env(env)
+		.jail(workspace.jail.clone())
+		.pid_namespace(workspace.pid_namespace)
+		.timeout(ctx.timeout)
+		.max_output(ctx.max_output_bytes)
+		.cancel(ctx.cancel.clone())
+		.processes(ctx.processes.clone())
+		.usage(ctx.usage.clone())
+		.cgroup(ctx.cgroup.clone());
+	let compile = match subprocess::run(&javac).await {
 		Ok(o) => o,
 		Err(e) => {
 			return ToolExecutionResult {
@@ -185,24 +1934,47 @@ async fn execute_java_tool(source_code: &str) -> ToolExecutionResult {
 				stderr: "".to_string(),
 				compile_stdout: "".to_string(),
 				compile_stderr: format!("failed to run javac: {e}"),
+				encoding: None,
 				exit_code: None,
+				truncated: None,
+				exception: None,
 			}
 		}
 	};
 
-	if !compile.success {
+	if !compile.success() {
 		return ToolExecutionResult {
-			status: "compile_error".to_string(),
+			status: if compile.interrupted() {
+				compile.status().to_string()
+			} else {
+				"compile_error".to_string()
+			},
 			language: ToolLanguage::Java,
 			stdout: "".to_string(),
 			stderr: "".to_string(),
 			compile_stdout: compile.stdout,
 			compile_stderr: compile.stderr,
+			encoding: None,
 			exit_code: compile.exit_code,
+			truncated: compile.truncated,
+			exception: None,
 		};
 	}
 
-	let run = match run_cmd("java", &["Tool"], &run_dir).await {
+	let java = CommandSpec::new("java")
+		.arg("Tool")
+		.stdin(ctx.stdin())
+		.cwd(run_dir)
+		.tool_env(env)
+		.jail(workspace.jail.clone())
+		.pid_namespace(workspace.pid_namespace)
+		.timeout(ctx.timeout)
+		.max_output(ctx.max_output_bytes)
+		.cancel(ctx.cancel.clone())
+		.processes(ctx.processes.clone())
+		.usage(ctx.usage.clone())
+		.cgroup(ctx.cgroup.clone());
+	let run = match subprocess::run(&java).await {
 		Ok(o) => o,
 		Err(e) => {
 			return ToolExecutionResult {
@@ -212,14 +1984,19 @@ async fn execute_java_tool(source_code: &str) -> ToolExecutionResult {
 				stderr: format!("failed to run java: {e}"),
 				compile_stdout: compile.stdout,
 				compile_stderr: compile.stderr,
+				encoding: None,
 				exit_code: None,
+				truncated: None,
+				exception: None,
 			};
 		}
 	};
 
 	ToolExecutionResult {
-		status: if run.success {
+		status: if run.success() {
 			"ok".to_string()
+		} else if run.interrupted() {
+			run.status().to_string()
 		} else {
 			"runtime_error".to_string()
 		},
@@ -228,7 +2005,9 @@ async fn execute_java_tool(source_code: &str) -> ToolExecutionResult {
 		stderr: run.stderr,
 		compile_stdout: compile.stdout,
 		compile_stderr: compile.stderr,
+		encoding: run.encoding,
 		exit_code: run.exit_code,
+		truncated: run.truncated,
+		exception: None,
 	}
 }
-
@@ -0,0 +1,541 @@
+use base64::Engine as _;
+use futures::StreamExt;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Method, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::error::Error as StdError;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::egress::NetworkPolicy;
+use crate::request_id;
+use crate::tool_executor::{ExecutionContext, ToolResult};
+use crate::trace::{self, HttpExchange};
+
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 5 * 1024 * 1024;
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+/// Credential headers, masked in `http_request` args and request logs.
+pub(crate) const DEFAULT_REDACT_HEADERS: &[&str] = &[
+	"authorization",
+	"proxy-authorization",
+	"cookie",
+	"set-cookie",
+	"x-api-key",
+];
+/// Cloud metadata services, refused even where private networks are
+/// allowed.
+const METADATA_ADDRS: &[IpAddr] = &[
+	IpAddr::V4(Ipv4Addr::new(169, 254, 169, 254)),
+	IpAddr::V4(Ipv4Addr::new(169, 254, 170, 2)),
+	IpAddr::V4(Ipv4Addr::new(100, 100, 100, 200)),
+	IpAddr::V6(Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254)),
+];
+const METADATA_HOSTS: &[&str] = &["metadata.google.internal", "metadata.goog"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+	/// Hosts the `http_request` tool may call: none by default, an
+	/// allowlist, or `full` for any public host.
+	pub network: NetworkPolicy,
+	/// Let requests reach loopback, private, link-local and other
+	/// non-public addresses. Metadata endpoints stay blocked.
+	pub allow_private_networks: bool,
+	/// Body bytes kept from a response; the rest is dropped.
+	pub max_response_bytes: usize,
+	pub max_redirects: usize,
+	/// Headers whose values are masked in the server log.
+	pub redact_headers: Vec<String>,
+}
+
+impl Default for HttpConfig {
+	fn default() -> Self {
+		Self {
+			network: NetworkPolicy::None,
+			allow_private_networks: false,
+			max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+			max_redirects: DEFAULT_MAX_REDIRECTS,
+			redact_headers: DEFAULT_REDACT_HEADERS.iter().map(|h| h.to_string()).collect(),
+		}
+	}
+}
+
+impl HttpConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if let Err(e) = self.network.clone().validate() {
+			errors.push(e.replace("network.allowlist", "executor.http.network.allowlist"));
+		}
+		if self.max_response_bytes == 0 {
+			errors.push("executor.http.max_response_bytes must be positive".to_string());
+		}
+		errors
+	}
+}
+
+/// Why a destination was refused.
+#[derive(Debug)]
+pub(crate) struct Blocked(String);
+
+impl fmt::Display for Blocked {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+impl StdError for Blocked {}
+
+/// Which hosts and addresses requests may reach. Job callbacks go through
+/// the same checks as the `http_request` tool.
+pub(crate) struct Destinations {
+	network: NetworkPolicy,
+	allow_private: bool,
+	/// The setting `network` comes from, for error messages.
+	setting: &'static str,
+}
+
+impl Destinations {
+	pub(crate) fn new(network: NetworkPolicy, allow_private: bool, setting: &'static str) -> Self {
+		Self {
+			network,
+			allow_private,
+			setting,
+		}
+	}
+
+	pub(crate) fn check_url(&self, url: &Url) -> Result<(), Blocked> {
+		if !matches!(url.scheme(), "http" | "https") {
+			return Err(Blocked(format!("{} URLs are not allowed", url.scheme())));
+		}
+		let host = url.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+		self.check_host(host)?;
+		if let Ok(ip) = host.parse::<IpAddr>() {
+			self.check_ip(host, ip)?;
+		}
+		Ok(())
+	}
+
+	fn check_host(&self, host: &str) -> Result<(), Blocked> {
+		let name = host.trim_end_matches('.').to_ascii_lowercase();
+		if METADATA_HOSTS.contains(&name.as_str()) {
+			return Err(Blocked(format!("{host} is a metadata endpoint")));
+		}
+		if !self.network.allows(host) {
+			return Err(Blocked(format!("{host} is not allowed by {}", self.setting)));
+		}
+		Ok(())
+	}
+
+	fn check_ip(&self, host: &str, ip: IpAddr) -> Result<(), Blocked> {
+		let ip = canonical(ip);
+		if METADATA_ADDRS.contains(&ip) {
+			return Err(Blocked(format!("{host} is a metadata endpoint")));
+		}
+		if !self.allow_private && !is_public(ip) {
+			return Err(Blocked(format!("{host} resolves to non-public address {ip}")));
+		}
+		Ok(())
+	}
+}
+
+/// Resolves names for the client, keeping only addresses requests may
+/// reach, so a name cannot be pointed at internal hosts between the check
+/// and the connection.
+pub(crate) struct CheckedResolver(pub(crate) Arc<Destinations>);
+
+impl Resolve for CheckedResolver {
+	fn resolve(&self, name: Name) -> Resolving {
+		let destinations = self.0.clone();
+		Box::pin(async move {
+			let host = name.as_str().to_string();
+			destinations.check_host(&host)?;
+			let resolved: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+			let mut refused = None;
+			let allowed: Vec<SocketAddr> = resolved
+				.into_iter()
+				.filter(|addr| match destinations.check_ip(&host, addr.ip()) {
+					Ok(()) => true,
+					Err(e) => {
+						refused = Some(e);
+						false
+					}
+				})
+				.collect();
+			if allowed.is_empty() {
+				let e = refused.unwrap_or_else(|| Blocked(format!("{host} did not resolve")));
+				return Err(Box::new(e) as Box<dyn StdError + Send + Sync>);
+			}
+			Ok(Box::new(allowed.into_iter()) as Addrs)
+		})
+	}
+}
+
+/// Implements the `http_request` tool.
+///
+/// Args: `{"method": "GET", "url": "https://api.example.com/items",
+/// "headers": {"accept": "application/json"}, "body": "..."}`, or `json`
+/// in place of `body` to send a JSON document. The result is the response's
+/// `status`, `headers` and `body`, as text or, for binary bodies, base64
+/// with `"body_encoding": "base64"`, plus `url` after redirects and
+/// `truncated` when the body ran past the cap.
+pub struct HttpTool {
+	config: HttpConfig,
+	destinations: Arc<Destinations>,
+	client: reqwest::Client,
+}
+
+impl HttpTool {
+	pub fn new(config: HttpConfig) -> Result<Self, String> {
+		let mut network = config.network.clone();
+		network.validate()?;
+		let destinations = Arc::new(Destinations::new(
+			network,
+			config.allow_private_networks,
+			"executor.http.network",
+		));
+		let max_redirects = config.max_redirects;
+		let redirects = destinations.clone();
+		let policy = redirect::Policy::custom(move |attempt| {
+			if attempt.previous().len() > max_redirects {
+				return attempt.error(Blocked(format!("more than {max_redirects} redirects")));
+			}
+			match redirects.check_url(attempt.url()) {
+				Ok(()) => attempt.follow(),
+				Err(e) => attempt.error(e),
+			}
+		});
+		// No proxy: it would resolve names where the checks cannot see.
+		let client = reqwest::Client::builder()
+			.no_proxy()
+			.redirect(policy)
+			.dns_resolver(Arc::new(CheckedResolver(destinations.clone())))
+			.build()
+			.map_err(|e| e.to_string())?;
+		Ok(Self {
+			config,
+			destinations,
+			client,
+		})
+	}
+
+	/// `args` with the values of headers in `redact_headers` masked.
+	pub fn redact_args(&self, args: &Value) -> Value {
+		let mut args = args.clone();
+		if let Some(headers) = args.get_mut("headers").and_then(|h| h.as_object_mut()) {
+			for (name, value) in headers.iter_mut() {
+				if self.redacts(name) {
+					*value = json!("<redacted>");
+				}
+			}
+		}
+		args
+	}
+
+	fn redacts(&self, header: &str) -> bool {
+		self.config.redact_headers.iter().any(|h| h.eq_ignore_ascii_case(header))
+	}
+
+	/// Make the request, or under a trace replay answer it from the trace: a
+	/// call other than the one recorded next fails with `trace_mismatch`.
+	pub async fn run(&self, args: &Value, ctx: &ExecutionContext) -> ToolResult {
+		let result = |status: String, stdout: String, stderr: String| ToolResult {
+			status,
+			stdout,
+			stderr,
+			..Default::default()
+		};
+		if let Some(replayed) = ctx.trace.as_ref().and_then(|trace| trace.next_http(args)) {
+			return match replayed {
+				Ok(exchange) => result(exchange.status, exchange.stdout, exchange.stderr),
+				Err(e) => result("trace_mismatch".to_string(), String::new(), e),
+			};
+		}
+		let request = tokio::time::timeout(ctx.timeout, self.request(args, ctx.caller.request_id.as_deref()));
+		let outcome = tokio::select! {
+			outcome = request => match outcome {
+				Ok(outcome) => outcome,
+				Err(_) => Err(("timed_out", format!("http_request did not finish within {}ms", ctx.timeout.as_millis()))),
+			},
+			_ = ctx.cancel.cancelled() => return ctx.check_cancelled().unwrap_err(),
+		};
+		let (status, stdout, stderr) = match outcome {
+			Ok(stdout) => ("ok", stdout, String::new()),
+			Err((status, e)) => (status, String::new(), e),
+		};
+		if let Some(trace) = &ctx.trace {
+			let (method, url, body_sha256) = trace::http_request_of(args);
+			trace.record_http(HttpExchange {
+				method,
+				url,
+				body_sha256,
+				status: status.to_string(),
+				stdout: stdout.clone(),
+				stderr: stderr.clone(),
+			});
+		}
+		result(status.to_string(), stdout, stderr)
+	}
+
+	/// `request_id` is sent on unless the args set the header themselves.
+	async fn request(&self, args: &Value, request_id: Option<&str>) -> Result<String, (&'static str, String)> {
+		let invalid = |e: String| ("invalid_args", e);
+		let url = args
+			.get("url")
+			.and_then(|v| v.as_str())
+			.ok_or_else(|| invalid("url must be a string".to_string()))?;
+		let url = Url::parse(url).map_err(|e| invalid(format!("url: {e}")))?;
+		let method = args.get("method").and_then(|v| v.as_str()).unwrap_or("GET");
+		let method = Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+			.map_err(|_| invalid(format!("{method:?} is not an HTTP method")))?;
+		if let Err(e) = self.destinations.check_url(&url) {
+			return Err(("network_denied", format!("blocked by the sandbox: {e}\n")));
+		}
+
+		let mut request = self.client.request(method.clone(), url.clone());
+		let mut logged = Map::new();
+		let sets_request_id = args
+			.get("headers")
+			.and_then(Value::as_object)
+			.is_some_and(|headers| headers.keys().any(|name| name.eq_ignore_ascii_case(request_id::HEADER)));
+		if let Some(id) = request_id.filter(|_| !sets_request_id) {
+			request = request.header(request_id::HEADER, id);
+		}
+		if let Some(headers) = args.get("headers") {
+			let headers = headers
+				.as_object()
+				.ok_or_else(|| invalid("headers must be an object".to_string()))?;
+			for (name, value) in headers {
+				let value = value
+					.as_str()
+					.ok_or_else(|| invalid(format!("header {name:?} must be a string")))?;
+				if name.eq_ignore_ascii_case("host") {
+					return Err(invalid("the host header cannot be set".to_string()));
+				}
+				logged.insert(name.clone(), json!(if self.redacts(name) { "<redacted>" } else { value }));
+				request = request.header(name, value);
+			}
+		}
+		match (args.get("body"), args.get("json")) {
+			(Some(_), Some(_)) => return Err(invalid("give body or json, not both".to_string())),
+			(Some(Value::String(body)), None) => request = request.body(body.clone()),
+			(Some(_), None) => return Err(invalid("body must be a string".to_string())),
+			(None, Some(document)) => request = request.json(document),
+			(None, None) => {}
+		}
+
+		let response = request.send().await.map_err(|e| match blocked(&e) {
+			Some(reason) => ("network_denied", format!("blocked by the sandbox: {reason}\n")),
+			None => ("network_error", e.without_url().to_string()),
+		})?;
+		let status = response.status();
+		let final_url = response.url().clone();
+		let headers: Map<String, Value> = response
+			.headers()
+			.iter()
+			.map(|(name, value)| (name.to_string(), json!(String::from_utf8_lossy(value.as_bytes()))))
+			.collect();
+		let mut body = Vec::new();
+		let mut truncated = false;
+		let mut stream = response.bytes_stream();
+		while let Some(chunk) = stream.next().await {
+			let chunk = chunk.map_err(|e| ("network_error", e.without_url().to_string()))?;
+			let room = self.config.max_response_bytes - body.len();
+			if chunk.len() > room {
+				body.extend_from_slice(&chunk[..room]);
+				truncated = true;
+				break;
+			}
+			body.extend_from_slice(&chunk);
+		}
+		// The path only: query strings often carry tokens.
+		let logged = Value::Object(logged);
+		info!(
+			method = %method,
+			host = final_url.host_str().unwrap_or_default(),
+			path = final_url.path(),
+			status = status.as_u16(),
+			bytes = body.len(),
+			headers = %logged,
+			message = "HTTP request made for a tool"
+		);
+
+		let mut result = json!({
+			"status": status.as_u16(),
+			"url": final_url.as_str(),
+			"headers": headers,
+			"truncated": truncated,
+		});
+		match String::from_utf8(body) {
+			Ok(text) => result["body"] = json!(text),
+			Err(e) => {
+				result["body"] = json!(base64::engine::general_purpose::STANDARD.encode(e.as_bytes()));
+				result["body_encoding"] = json!("base64");
+			}
+		}
+		Ok(serde_json::to_string_pretty(&result).unwrap_or_default())
+	}
+}
+
+/// The sandbox's reason, when `e` comes from a refused destination.
+pub(crate) fn blocked(e: &reqwest::Error) -> Option<String> {
+	let mut source: Option<&(dyn StdError + 'static)> = Some(e);
+	while let Some(err) = source {
+		if let Some(blocked) = err.downcast_ref::<Blocked>() {
+			return Some(blocked.0.clone());
+		}
+		source = err.source();
+	}
+	None
+}
+
+/// IPv4 addresses carried in IPv6 (mapped, IPv4-compatible, NAT64 or 6to4)
+/// as plain IPv4.
+fn canonical(ip: IpAddr) -> IpAddr {
+	match ip {
+		IpAddr::V6(v6) => {
+			if let Some(v4) = v6.to_ipv4_mapped() {
+				return IpAddr::V4(v4);
+			}
+			let s = v6.segments();
+			let v4 = |high: u16, low: u16| {
+				IpAddr::V4(Ipv4Addr::new((high >> 8) as u8, high as u8, (low >> 8) as u8, low as u8))
+			};
+			match s {
+				// `::a.b.c.d`, but not `::` and `::1`.
+				[0, 0, 0, 0, 0, 0, high, low] if high != 0 || low > 1 => v4(high, low),
+				[0x64, 0xff9b, 0, 0, 0, 0, high, low] => v4(high, low),
+				[0x2002, high, low, ..] => v4(high, low),
+				_ => ip,
+			}
+		}
+		v4 => v4,
+	}
+}
+
+fn is_public(ip: IpAddr) -> bool {
+	match canonical(ip) {
+		IpAddr::V4(v4) => {
+			let [a, b, ..] = v4.octets();
+			!(v4.is_unspecified()
+				|| v4.is_loopback()
+				|| v4.is_private()
+				|| v4.is_link_local()
+				|| v4.is_broadcast()
+				|| v4.is_multicast()
+				|| v4.is_documentation()
+				|| a == 0
+				|| a >= 240
+				|| (a == 100 && (64..128).contains(&b))
+				|| (a == 192 && b == 0 && v4.octets()[2] == 0)
+				|| (a == 198 && (18..20).contains(&b)))
+		}
+		IpAddr::V6(v6) => {
+			let first = v6.segments()[0];
+			!(v6.is_unspecified()
+				|| v6.is_loopback()
+				|| v6.is_multicast()
+				|| (first & 0xfe00) == 0xfc00
+				|| (first & 0xffc0) == 0xfe80
+				|| (first == 0x2001 && v6.segments()[1] == 0xdb8))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ip(text: &str) -> IpAddr {
+		text.parse().unwrap()
+	}
+
+	#[test]
+	fn canonical_unwraps_ipv4_in_ipv6() {
+		assert_eq!(canonical(ip("::ffff:127.0.0.1")), ip("127.0.0.1"));
+		assert_eq!(canonical(ip("64:ff9b::a9fe:a9fe")), ip("169.254.169.254"));
+		assert_eq!(canonical(ip("::10.0.0.1")), ip("10.0.0.1"));
+		assert_eq!(canonical(ip("2002:7f00:1::1")), ip("127.0.0.1"));
+		assert_eq!(canonical(ip("::")), ip("::"));
+		assert_eq!(canonical(ip("::1")), ip("::1"));
+		assert_eq!(canonical(ip("2001:db8::1")), ip("2001:db8::1"));
+		assert_eq!(canonical(ip("10.0.0.1")), ip("10.0.0.1"));
+	}
+
+	#[test]
+	fn is_public_refuses_special_ranges() {
+		for public in ["8.8.8.8", "1.1.1.1", "100.63.255.255", "100.128.0.1", "172.32.0.1", "2606:4700::1111", "::8.8.8.8", "2002:808:808::1"] {
+			assert!(is_public(ip(public)), "{public}");
+		}
+		for special in [
+			"0.0.0.0",
+			"0.1.2.3",
+			"127.0.0.1",
+			"10.1.2.3",
+			"172.16.0.1",
+			"192.168.1.1",
+			"169.254.169.254",
+			"100.64.0.1",
+			"192.0.0.8",
+			"192.0.2.1",
+			"198.18.0.1",
+			"224.0.0.1",
+			"240.0.0.1",
+			"255.255.255.255",
+			"::",
+			"::1",
+			"fc00::1",
+			"fd12:3456::1",
+			"fe80::1",
+			"ff02::1",
+			"2001:db8::1",
+			"::ffff:10.0.0.1",
+			"::127.0.0.1",
+			"::a9fe:a9fe",
+			"64:ff9b::c0a8:101",
+			"2002:7f00:1::1",
+			"2002:a9fe:a9fe::",
+			"2002:c0a8:101:1::1",
+		] {
+			assert!(!is_public(ip(special)), "{special}");
+		}
+	}
+
+	#[test]
+	fn check_url_sees_through_address_spellings() {
+		let public = Destinations::new(NetworkPolicy::Full, false, "executor.http.network");
+		let private = Destinations::new(NetworkPolicy::Full, true, "executor.http.network");
+		let check = |destinations: &Destinations, url: &str| destinations.check_url(&Url::parse(url).unwrap());
+		assert!(check(&public, "https://8.8.8.8/").is_ok());
+		assert!(check(&public, "https://example.com/").is_ok());
+		for url in [
+			"http://127.0.0.1/",
+			"http://2130706433/",
+			"http://0x7f.1/",
+			"http://[::ffff:127.0.0.1]/",
+			"http://[64:ff9b::a00:1]/",
+			"http://[::127.0.0.1]/",
+			"http://[::7f00:1]/",
+			"http://[2002:7f00:1::]/",
+			"http://[2002:a00:1:1::1]/",
+			"file:///etc/passwd",
+		] {
+			assert!(check(&public, url).is_err(), "{url}");
+		}
+		assert!(check(&private, "http://10.0.0.1/").is_ok());
+		for url in [
+			"http://169.254.169.254/latest/meta-data/",
+			"http://[::ffff:169.254.169.254]/",
+			"http://[64:ff9b::a9fe:a9fe]/",
+			"http://[::169.254.169.254]/",
+			"http://[2002:a9fe:a9fe::1]/",
+			"http://[fd00:ec2::254]/",
+			"http://metadata.google.internal./",
+			"http://METADATA.goog/",
+		] {
+			assert!(check(&private, url).is_err(), "{url}");
+		}
+	}
+}
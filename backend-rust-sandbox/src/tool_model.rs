@@ -0,0 +1,187 @@
+use serde_json::{json, Value};
+use std::future::Future;
+use tonic::{Code, Status};
+
+use crate::gateway_client::GatewayCall;
+use crate::tool_executor::{ExecutionContext, ToolResult};
+use crate::tool_sdk::Tool;
+use crate::tool_service::proto::{PlanRequest, RagContextRequest, Resource};
+
+const DEFAULT_TOP_K: i32 = 3;
+
+/// The `ask_model` tool: the prompt goes to the model gateway's `GetPlan`,
+/// and the model's answer comes back on stdout.
+///
+/// Args:
+///   {"prompt": "...", "resources": [{"type": "image", "uri": "s3://..."}]}
+pub struct AskModel;
+
+#[tonic::async_trait]
+impl Tool for AskModel {
+	fn name(&self) -> &'static str {
+		"ask_model"
+	}
+
+	fn description(&self) -> &'static str {
+		"Ask the model behind the model gateway a question and get its answer."
+	}
+
+	fn schema(&self) -> Value {
+		json!({
+			"type": "object",
+			"properties": {
+				"prompt": { "type": "string" },
+				"resources": {
+					"type": "array",
+					"items": {
+						"type": "object",
+						"properties": { "type": { "type": "string" }, "uri": { "type": "string" } },
+						"required": ["uri"],
+					},
+				},
+			},
+			"required": ["prompt"],
+		})
+	}
+
+	async fn run(&self, ctx: &ExecutionContext, args: Value) -> ToolResult {
+		ask_model(&args, ctx).await
+	}
+}
+
+/// The `rag_search` tool: the knowledge-base passages the model gateway's
+/// `GetRAGContext` finds for a query.
+///
+/// Args:
+///   {"query": "...", "top_k": 3, "knowledge_bases": ["kb-docs"]}
+pub struct RagSearch;
+
+#[tonic::async_trait]
+impl Tool for RagSearch {
+	fn name(&self) -> &'static str {
+		"rag_search"
+	}
+
+	fn description(&self) -> &'static str {
+		"Find knowledge-base passages matching a query, through the model gateway."
+	}
+
+	fn schema(&self) -> Value {
+		json!({
+			"type": "object",
+			"properties": {
+				"query": { "type": "string" },
+				"top_k": { "type": "integer", "minimum": 1 },
+				"knowledge_bases": { "type": "array", "items": { "type": "string" } },
+			},
+			"required": ["query"],
+		})
+	}
+
+	async fn run(&self, ctx: &ExecutionContext, args: Value) -> ToolResult {
+		rag_search(&args, ctx).await
+	}
+}
+
+async fn ask_model(args: &Value, ctx: &ExecutionContext) -> ToolResult {
+	let resources = args
+		.get("resources")
+		.and_then(Value::as_array)
+		.map(|resources| {
+			resources
+				.iter()
+				.map(|r| Resource {
+					r#type: r.get("type").and_then(Value::as_str).unwrap_or_default().to_string(),
+					uri: r.get("uri").and_then(Value::as_str).unwrap_or_default().to_string(),
+				})
+				.collect()
+		})
+		.unwrap_or_default();
+	let request = PlanRequest {
+		prompt: args.get("prompt").and_then(Value::as_str).unwrap_or_default().to_string(),
+		resources,
+	};
+	run(ctx, "ask_model", |gateway| async move {
+		let response = gateway.plan(request).await?;
+		Ok(json!({
+			"answer": response.plan,
+			"model": response.model_name,
+			"latency_ms": response.latency_ms,
+		}))
+	})
+	.await
+}
+
+async fn rag_search(args: &Value, ctx: &ExecutionContext) -> ToolResult {
+	let request = RagContextRequest {
+		query: args.get("query").and_then(Value::as_str).unwrap_or_default().to_string(),
+		top_k: args
+			.get("top_k")
+			.and_then(Value::as_i64)
+			.and_then(|k| i32::try_from(k).ok())
+			.unwrap_or(DEFAULT_TOP_K),
+		knowledge_bases: args
+			.get("knowledge_bases")
+			.and_then(Value::as_array)
+			.map(|kbs| kbs.iter().filter_map(|kb| kb.as_str().map(str::to_string)).collect())
+			.unwrap_or_default(),
+	};
+	run(ctx, "rag_search", |gateway| async move {
+		let response = gateway.rag_context(request).await?;
+		let matches: Vec<Value> = response
+			.matches
+			.into_iter()
+			.map(|m| {
+				json!({
+					"id": m.id,
+					"text": m.text,
+					"distance": m.distance,
+					"knowledge_base": m.knowledge_base,
+					"source": m.source,
+				})
+			})
+			.collect();
+		Ok(json!({ "matches": matches }))
+	})
+	.await
+}
+
+/// Make the call through the execution's gateway handle, stopping early if
+/// the execution is cancelled.
+async fn run<'a, F, Fut>(ctx: &'a ExecutionContext, name: &str, call: F) -> ToolResult
+where
+	F: FnOnce(GatewayCall<'a>) -> Fut,
+	Fut: Future<Output = Result<Value, Status>>,
+{
+	let Some(gateway) = ctx.gateway() else {
+		return failure("disabled", format!("{name} needs a model gateway; set executor.gateway.url"));
+	};
+	let outcome = tokio::select! {
+		outcome = call(gateway) => outcome,
+		_ = ctx.cancel.cancelled() => return ctx.check_cancelled().unwrap_err(),
+	};
+	match outcome {
+		Ok(output) => ToolResult {
+			status: "ok".to_string(),
+			stdout: serde_json::to_string_pretty(&output).unwrap_or_default(),
+			..Default::default()
+		},
+		Err(status) => {
+			let kind = match status.code() {
+				Code::DeadlineExceeded => "timed_out",
+				Code::Unauthenticated | Code::PermissionDenied => "gateway_denied",
+				Code::InvalidArgument => "invalid_args",
+				_ => "gateway_error",
+			};
+			failure(kind, format!("model gateway: {:?}: {}", status.code(), status.message()))
+		}
+	}
+}
+
+fn failure(status: &str, message: String) -> ToolResult {
+	ToolResult {
+		status: status.to_string(),
+		stderr: message,
+		..Default::default()
+	}
+}
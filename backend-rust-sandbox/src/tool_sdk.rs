@@ -0,0 +1,50 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::plugins;
+use crate::tool_executor::{ExecutionContext, ToolResult};
+use crate::tool_model::{AskModel, RagSearch};
+use crate::tool_weather::Weather;
+use crate::tool_web_search::WebSearch;
+
+/// A tool written in Rust and compiled into the sandbox, or loaded from a
+/// plugin library. Registered with [`register_tools!`], it is listed, validated, authorized, audited and
+/// metered like a manifest tool; the executor calls [`Tool::run`] where it
+/// would spawn a process.
+///
+/// `run` gets args that passed [`Tool::schema`]. The executor bounds it by
+/// the execution's timeout and cancellation, so a tool only has to watch
+/// `ctx.cancel` to stop early on its own terms.
+#[tonic::async_trait]
+pub trait Tool: Send + Sync {
+	fn name(&self) -> &'static str;
+	fn description(&self) -> &'static str;
+	/// JSON Schema for the args object.
+	fn schema(&self) -> Value;
+	async fn run(&self, ctx: &ExecutionContext, args: Value) -> ToolResult;
+}
+
+/// Define [`compiled_tools`] as the listed [`Tool`] values. A tool is added
+/// to the sandbox by implementing the trait and naming it here.
+macro_rules! register_tools {
+	($($tool:expr),* $(,)?) => {
+		/// Every tool compiled into the sandbox through the SDK, by name.
+		pub fn compiled_tools() -> BTreeMap<&'static str, Arc<dyn Tool>> {
+			let tools: Vec<Arc<dyn Tool>> = vec![$(Arc::new($tool)),*];
+			let count = tools.len();
+			let tools: BTreeMap<_, _> = tools.into_iter().map(|tool| (tool.name(), tool)).collect();
+			assert_eq!(tools.len(), count, "two compiled tools share a name");
+			tools
+		}
+	};
+}
+
+register_tools![WebSearch, Weather, AskModel, RagSearch];
+
+/// The compiled tools and those of the plugins loaded at startup, by name.
+pub fn tools() -> BTreeMap<&'static str, Arc<dyn Tool>> {
+	let mut tools = compiled_tools();
+	tools.extend(plugins::tools().into_iter().map(|tool| (tool.name(), tool)));
+	tools
+}
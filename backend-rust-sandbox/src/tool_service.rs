@@ -1,57 +1,697 @@
 use serde_json::{json, Value};
-use tonic::{Request, Response, Status};
-use tracing::info;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+use tokio_stream::Stream;
+use tonic::codec::CompressionEncoding;
+use prost::Message;
+use tonic::metadata::MetadataValue;
+use tonic::server::NamedService;
+use tonic::service::interceptor::InterceptedService;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{info, Instrument};
 
-use crate::tool_executor;
+use crate::audit::Caller;
+use crate::auth::{tenant_of, Authenticator, GrpcAuthInterceptor, Principal};
+use crate::authz::Authorizer;
+use crate::cache::bypass_requested;
+use crate::chaos;
+use crate::deadline;
+use crate::encoding::{Encoding, OutputEncoding};
+use crate::error::SandboxError;
+use crate::gateway_client::ForwardedAuth;
+use crate::idempotency::{self, Claim, Idempotency};
+use crate::plan::PlanOptions;
+use crate::pty::{Terminal, WindowSize};
+use crate::ratelimit::{client_key, RateLimiter};
+use crate::registry::ArgError;
+use crate::retry::RetryOverride;
+use crate::scheduler::Priority;
+use crate::subprocess::{OutputChunk, StdinStream, StreamKind};
+use crate::tool::{artifact_pattern_errors, input_path_errors};
+use crate::tool_executor::{run_detached, ExecutionBackend, ExecutionContext, Executor, ToolResult};
+use crate::trace::{ExecutionTrace, Tracer};
+use crate::workspace::ToolInput;
 
+#[allow(clippy::enum_variant_names, clippy::large_enum_variant)]
 pub mod proto {
 	tonic::include_proto!("modelgateway");
 }
 
 use proto::tool_service_server::{ToolService, ToolServiceServer};
-use proto::{ToolRequest, ToolResponse};
+use proto::interactive_client_message::Payload as ClientPayload;
+use proto::interactive_server_message::Payload as ServerPayload;
+use proto::tool_stream_message::Payload;
+use proto::{
+	InteractiveClientMessage, InteractiveControl, InteractiveServerMessage, InteractiveStarted, OutputStreamType,
+	ToolOutputChunk, ToolRequest, ToolResponse, ToolStreamMessage,
+};
 
-#[derive(Debug, Default)]
-pub struct SandboxToolService;
+pub const SERVICE_NAME: &str = <ToolServiceServer<SandboxToolService> as NamedService>::NAME;
 
-#[tonic::async_trait]
-impl ToolService for SandboxToolService {
-	async fn execute_tool(
-		&self,
-		request: Request<ToolRequest>,
-	) -> Result<Response<ToolResponse>, Status> {
-		let req = request.into_inner();
+/// The descriptors of `model.proto`, as build.rs writes them, for reflection.
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("modelgateway_descriptor");
+
+/// Chunks buffered between the tool and a slow gRPC client.
+const CHUNK_BUFFER: usize = 256;
+/// Stdin messages buffered while the tool is not reading; past this, flow
+/// control holds the client back.
+const STDIN_BUFFER: usize = 16;
+
+pub struct SandboxToolService {
+	executor: Arc<Executor>,
+	authz: Arc<Authorizer>,
+	ratelimit: Arc<RateLimiter>,
+	idempotency: Arc<Idempotency>,
+}
+
+/// A request's parsed parts, once authorized.
+struct Authorized {
+	args: Value,
+	priority: Option<Priority>,
+	trace: Option<Arc<Tracer>>,
+}
 
+impl SandboxToolService {
+	/// Log the request contract, check the caller may run the tool with
+	/// these args, and parse them, the priority and any trace to replay.
+	#[allow(clippy::result_large_err)]
+	fn authorize(
+		&self,
+		caller: &Caller,
+		principal: Option<&Principal>,
+		req: &ToolRequest,
+	) -> Result<Authorized, SandboxError> {
 		// Log the (future) isolation/resource contract fields. The current sandbox
 		// implementation may ignore these, but they are required for a hardened
 		// micro-VM execution architecture (gVisor/Firecracker).
 		info!(
+			tenant = tenant_of(principal),
 			tool_name = %req.tool_name,
 			execution_environment = %req.execution_environment,
 			cpu_limit_mhz = req.cpu_limit_mhz,
 			memory_limit_mb = req.memory_limit_mb,
 			timeout_seconds = req.timeout_seconds,
+			timeout_ms = req.timeout_ms,
 			message = "Received ToolRequest (isolation contract)"
 		);
-
-		let args: Value = if req.args_json.trim().is_empty() {
-			json!({})
+		// Unparseable args are audited as the raw string.
+		let parsed: Result<Value, _> = if req.args_json.trim().is_empty() {
+			Ok(json!({}))
 		} else {
 			serde_json::from_str(&req.args_json)
-				.map_err(|e| Status::invalid_argument(format!("invalid args_json: {e}")))?
 		};
+		let audited_args = parsed
+			.as_ref()
+			.cloned()
+			.unwrap_or_else(|_| Value::String(req.args_json.clone()));
+		let reject = |status: &str| {
+			self.executor
+				.audit()
+				.record(caller, &req.tool_name, &audited_args, status);
+		};
+
+		if let Err(reason) = self.authz.authorize_tool(principal, &req.tool_name) {
+			reject("forbidden");
+			return Err(SandboxError::Forbidden(reason));
+		}
+		let args = parsed.map_err(|e| {
+			reject("invalid_args");
+			SandboxError::InvalidRequest(format!("invalid args_json: {e}"))
+		})?;
+		if let Err(reason) = self.authz.check_policy(principal, caller.transport, &req.tool_name, &args) {
+			reject("forbidden");
+			return Err(SandboxError::Forbidden(reason));
+		}
+		let priority = req
+			.priority
+			.as_deref()
+			.map(str::parse::<Priority>)
+			.transpose()
+			.map_err(|e| {
+				reject("invalid_args");
+				SandboxError::InvalidRequest(e)
+			})?;
+		let replay = req
+			.replay_trace_json
+			.as_deref()
+			.map(serde_json::from_str::<ExecutionTrace>)
+			.transpose()
+			.map_err(|e| {
+				reject("invalid_args");
+				SandboxError::InvalidRequest(format!("invalid replay_trace_json: {e}"))
+			})?;
+		let mut errors = self.executor.validate_args(&req.tool_name, &args).err().unwrap_or_default();
+		errors.extend(artifact_pattern_errors(&req.artifacts));
+		errors.extend(input_path_errors(req.input_files.keys()));
+		if req.record_trace && replay.is_some() {
+			errors.push(ArgError {
+				path: "replay_trace_json".to_string(),
+				message: "record_trace and replay_trace_json cannot both be set".to_string(),
+			});
+		}
+		if !errors.is_empty() {
+			reject("invalid_args");
+			return Err(SandboxError::invalid_args(&req.tool_name, errors));
+		}
+		let trace = match replay {
+			Some(trace) => Some(Arc::new(Tracer::replay(trace))),
+			None => req.record_trace.then(|| Arc::new(Tracer::record())),
+		};
+		Ok(Authorized { args, priority, trace })
+	}
+
+	/// [`Self::authorize`] the request, then admit it and turn it into
+	/// executor inputs.
+	#[allow(clippy::result_large_err)]
+	async fn prepare(
+		&self,
+		caller: Caller,
+		principal: Option<&Principal>,
+		req: &ToolRequest,
+	) -> Result<(Value, ExecutionContext), SandboxError> {
+		if req.dry_run {
+			return Err(SandboxError::InvalidRequest("dry_run is only accepted by ExecuteTool".to_string()));
+		}
+		if req.pty.is_some() {
+			return Err(SandboxError::InvalidRequest("pty is only accepted by RunInteractive".to_string()));
+		}
+		let Authorized { args, priority, trace } = self.authorize(&caller, principal, req)?;
+		let reject = |status: &str| {
+			self.executor.audit().record(&caller, &req.tool_name, &args, status);
+		};
+
+		if self.executor.registry().get(&req.tool_name).is_some() {
+			self.ratelimit.check(&caller, &req.tool_name).await.map_err(|e| {
+				reject("rate_limited");
+				SandboxError::Limited {
+					code: "rate_limited",
+					message: e.message(&req.tool_name),
+					retry_after_secs: Some(e.retry_after_secs),
+					subject: Some(client_key(&caller)),
+				}
+			})?;
+			self.executor.quotas().check(&caller).await.map_err(|e| {
+				reject("quota_exceeded");
+				SandboxError::Limited {
+					code: "quota_exceeded",
+					message: e.message(),
+					retry_after_secs: e.retry_after_secs,
+					subject: Some(e.tenant.clone()),
+				}
+			})?;
+			let estimate = self.executor.estimate_cost(&req.tool_name, timeout_ms(req));
+			self.executor.quotas().check_budget(&caller, estimate.unwrap_or_default()).await.map_err(|e| {
+				reject("budget_exceeded");
+				SandboxError::Limited {
+					code: "budget_exceeded",
+					message: e.message(),
+					retry_after_secs: Some(e.retry_after_secs),
+					subject: Some(e.tenant.clone()),
+				}
+			})?;
+		}
+
+		let ctx = self.executor.context(&req.tool_name, timeout_ms(req)).map_err(|e| {
+			reject(e.status());
+			SandboxError::Limited {
+				code: e.status(),
+				message: e.message(),
+				retry_after_secs: Some(e.retry_after_secs),
+				subject: None,
+			}
+		})?;
+		let ctx = ctx
+			.with_caller(caller)
+			.with_retained_workspace(req.retain_workspace)
+			.with_artifacts(req.artifacts.clone())
+			.with_input(tool_input(req))
+			.with_retry(req.retry.as_ref().map(retry_override))
+			.with_trace(trace)
+			.with_priority_at_most(priority.unwrap_or_default());
+		Ok((args, ctx))
+	}
+
+	/// A dry run: checked like any request, then answered with its plan,
+	/// taking no slot and counting against no limit.
+	#[allow(clippy::result_large_err)]
+	fn plan(&self, caller: &Caller, principal: Option<&Principal>, req: &ToolRequest) -> Result<ToolResponse, SandboxError> {
+		let Authorized { args, priority, .. } = self.authorize(caller, principal, req)?;
+		let input = tool_input(req);
+		let retry = req.retry.as_ref().map(retry_override);
+		let options = PlanOptions {
+			caller,
+			timeout_ms: timeout_ms(req),
+			retry: retry.as_ref(),
+			artifacts: &req.artifacts,
+			input: &input,
+			retain_workspace: req.retain_workspace,
+			priority,
+		};
+		let (status, stdout) = match self.executor.plan(&req.tool_name, &args, &options) {
+			Some(plan) => ("planned", json!(plan).to_string()),
+			None => ("unknown_tool", json!({ "error": format!("no tool matches {:?}", req.tool_name) }).to_string()),
+		};
+		Ok(ToolResponse {
+			status: status.to_string(),
+			stdout,
+			..Default::default()
+		})
+	}
+}
 
-		let result = tool_executor::execute_tool(req.tool_name.as_str(), args).await;
+/// `timeout_ms`, which wins over the coarser legacy `timeout_seconds`.
+fn timeout_ms(req: &ToolRequest) -> Option<u64> {
+	req.timeout_ms
+		.filter(|ms| *ms > 0)
+		.or_else(|| (req.timeout_seconds > 0).then(|| req.timeout_seconds as u64 * 1000))
+}
+
+fn tool_input(req: &ToolRequest) -> ToolInput {
+	ToolInput {
+		stdin: req.stdin.as_deref().map(Into::into),
+		files: req.input_files.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+	}
+}
 
-		Ok(Response::new(ToolResponse {
-			status: result.status,
-			stdout: result.stdout,
-			stderr: result.stderr,
-		}))
+fn retry_override(retry: &proto::RetryOverride) -> RetryOverride {
+	RetryOverride {
+		max_attempts: retry.max_attempts,
+		initial_backoff_ms: retry.initial_backoff_ms,
+		max_backoff_ms: retry.max_backoff_ms,
 	}
 }
 
-pub fn tool_service_server() -> ToolServiceServer<SandboxToolService> {
-	ToolServiceServer::new(SandboxToolService::default())
+#[allow(clippy::result_large_err)]
+fn window_size(size: &proto::TerminalSize) -> Result<WindowSize, SandboxError> {
+	match (u16::try_from(size.cols), u16::try_from(size.rows)) {
+		(Ok(cols), Ok(rows)) => Ok(WindowSize { cols, rows }),
+		_ => Err(SandboxError::InvalidRequest(format!("{}x{} is not a terminal size", size.cols, size.rows))),
+	}
 }
 
+fn to_stream(stream: StreamKind) -> OutputStreamType {
+	match stream {
+		StreamKind::Stdout => OutputStreamType::OutputStreamStdout,
+		StreamKind::Stderr => OutputStreamType::OutputStreamStderr,
+	}
+}
+
+fn to_chunk(chunk: OutputChunk, sequence: u64) -> ToolOutputChunk {
+	ToolOutputChunk {
+		stream: to_stream(chunk.stream) as i32,
+		data: chunk.data,
+		sequence,
+	}
+}
+
+fn to_encoding(encoding: Encoding) -> i32 {
+	let encoding = match encoding {
+		Encoding::Utf8 => proto::OutputEncoding::Utf8,
+		Encoding::Base64 => proto::OutputEncoding::Base64,
+		Encoding::Artifact => proto::OutputEncoding::Artifact,
+	};
+	encoding as i32
+}
+
+fn to_response(result: ToolResult, workspace_id: Option<String>, trace: Option<&Tracer>) -> ToolResponse {
+	let truncation = result.truncated;
+	ToolResponse {
+		status: result.status,
+		stdout: result.stdout,
+		stderr: result.stderr,
+		exit_code: result.exit_code,
+		workspace_id,
+		artifacts: result
+			.artifacts
+			.into_iter()
+			.map(|a| proto::Artifact {
+				artifact_id: a.artifact_id,
+				path: a.path,
+				size_bytes: a.size_bytes,
+			})
+			.collect(),
+		truncated: truncation.is_some(),
+		stdout_bytes: truncation.map_or(0, |t| t.stdout_bytes),
+		stderr_bytes: truncation.map_or(0, |t| t.stderr_bytes),
+		attempts: result.attempts,
+		trace_json: trace.and_then(Tracer::recorded).map(|trace| json!(trace).to_string()),
+		usage: result.usage.map(|u| proto::ResourceUsage {
+			wall_ms: u.wall_ms,
+			user_cpu_ms: u.user_cpu_ms,
+			sys_cpu_ms: u.sys_cpu_ms,
+			peak_rss_bytes: u.peak_rss_bytes,
+			bytes_written: u.bytes_written,
+			output_bytes: u.output_bytes,
+			cost: u.cost,
+		}),
+		environment_json: result.environment.map(|environment| json!(environment).to_string()),
+		policy_violations: result
+			.policy_violations
+			.into_iter()
+			.map(|v| proto::PolicyViolation {
+				rule: v.rule,
+				message: v.message,
+				action: v.action.as_str().to_string(),
+				line: v.line as u32,
+				column: v.column as u32,
+				snippet: v.snippet,
+			})
+			.collect(),
+		redactions: result
+			.redactions
+			.into_iter()
+			.map(|r| proto::Redaction {
+				stream: to_stream(r.stream) as i32,
+				filter: r.filter,
+				count: r.count as u32,
+			})
+			.collect(),
+		quarantine: result.quarantined.map(|q| proto::Quarantine {
+			id: q.id,
+			reasons: q.reasons.iter().map(ToString::to_string).collect(),
+			artifacts_held: q.artifacts_held as u32,
+		}),
+		stdout_encoding: to_encoding(OutputEncoding::stdout(result.encoding)),
+		stderr_encoding: to_encoding(OutputEncoding::stderr(result.encoding)),
+	}
+}
+
+impl SandboxToolService {
+	/// The execution's workspace id, if it was asked to be kept and still is.
+	fn retained_workspace(&self, ctx: &ExecutionContext) -> impl FnOnce() -> Option<String> {
+		let executor = self.executor.clone();
+		let id = ctx.retain_workspace.then(|| ctx.workspace_id.clone());
+		move || id.filter(|id| executor.workspace_exists(id))
+	}
+}
+
+#[tonic::async_trait]
+impl ToolService for SandboxToolService {
+	async fn execute_tool(
+		&self,
+		request: Request<ToolRequest>,
+	) -> Result<Response<ToolResponse>, Status> {
+		let principal = request.extensions().get::<Principal>().cloned();
+		let caller = Caller::new(principal.as_ref(), request.remote_addr().map(|a| a.ip()), "grpc");
+		let bypass_cache = bypass_requested(request.metadata().get("cache-control").and_then(|v| v.to_str().ok()));
+		let auth = ForwardedAuth::from_metadata(request.metadata());
+		let key = idempotency::key_from(request.metadata().get("idempotency-key").map(|v| v.as_bytes()))?;
+		let deadline = deadline::from_metadata(request.metadata(), self.executor.deadline_overhead());
+		let req = request.into_inner();
+		self.executor.chaos().before_call().await?;
+		if req.dry_run {
+			return Ok(Response::new(self.plan(&caller, principal.as_ref(), &req)?));
+		}
+		let pending = match key {
+			Some(key) => {
+				let args = serde_json::from_str(&req.args_json).unwrap_or_else(|_| Value::String(req.args_json.clone()));
+				let fingerprint = idempotency::fingerprint(&req.tool_name, &args, &tool_input(&req));
+				match self.idempotency.claim("grpc", &caller, &key, fingerprint).await? {
+					Some(Claim::New(pending)) => Some(pending),
+					Some(Claim::Replay(stored)) => {
+						let response = ToolResponse::decode(stored.body.as_slice())
+							.map_err(|e| Status::internal(format!("stored response: {e}")))?;
+						let mut response = Response::new(response);
+						response.metadata_mut().insert("idempotent-replayed", MetadataValue::from_static("true"));
+						return Ok(response);
+					}
+					None => None,
+				}
+			}
+			None => None,
+		};
+		let (args, ctx) = self.prepare(caller, principal.as_ref(), &req).await?;
+		let ctx = ctx
+			.with_cache_bypass(bypass_cache)
+			.with_forwarded_auth(auth)
+			.with_deadline(deadline);
+		let workspace_id = self.retained_workspace(&ctx);
+		let trace = ctx.trace.clone();
+
+		// tonic drops this future when the client cancels the call.
+		let executor = self.executor.clone();
+		let result = run_detached(ctx.cancel.clone(), async move {
+			executor
+				.execute_tool(req.tool_name.as_str(), args, &ctx)
+				.await
+		})
+		.await;
+
+		let response = to_response(result, workspace_id(), trace.as_deref());
+		if let Some(pending) = pending {
+			pending.complete(0, response.encode_to_vec());
+		}
+		Ok(Response::new(response))
+	}
+
+	type ExecuteToolStreamStream =
+		Pin<Box<dyn Stream<Item = Result<ToolStreamMessage, Status>> + Send + 'static>>;
+
+	async fn execute_tool_stream(
+		&self,
+		request: Request<ToolRequest>,
+	) -> Result<Response<Self::ExecuteToolStreamStream>, Status> {
+		let principal = request.extensions().get::<Principal>().cloned();
+		let caller = Caller::new(principal.as_ref(), request.remote_addr().map(|a| a.ip()), "grpc");
+		let bypass_cache = bypass_requested(request.metadata().get("cache-control").and_then(|v| v.to_str().ok()));
+		let auth = ForwardedAuth::from_metadata(request.metadata());
+		let deadline = deadline::from_metadata(request.metadata(), self.executor.deadline_overhead());
+		let req = request.into_inner();
+		self.executor.chaos().before_call().await?;
+		let (args, ctx) = self.prepare(caller, principal.as_ref(), &req).await?;
+		let ctx = ctx
+			.with_cache_bypass(bypass_cache)
+			.with_forwarded_auth(auth)
+			.with_deadline(deadline);
+
+		let workspace_id = self.retained_workspace(&ctx);
+		let trace = ctx.trace.clone();
+		let (tx, mut rx) = mpsc::channel(CHUNK_BUFFER);
+		let ctx = ctx.with_output(tx);
+		let cancel = ctx.cancel.clone();
+		let cut = self.executor.chaos().stream_cut();
+		let executor = self.executor.clone();
+		let run = tokio::spawn(
+			async move {
+				executor
+					.execute_tool(req.tool_name.as_str(), args, &ctx)
+					.await
+			}
+			.in_current_span(),
+		);
+
+		let stream = async_stream::stream! {
+			// Dropped with the response stream when the client cancels the call.
+			let _cancel_on_drop = cancel.drop_guard();
+			// The channel closes once the execution (and with it every sender) is done.
+			let mut sequence: u64 = 0;
+			while let Some(chunk) = rx.recv().await {
+				if cut == Some(sequence) {
+					yield Err(chaos::stream_dropped());
+					return;
+				}
+				yield Ok(ToolStreamMessage {
+					payload: Some(Payload::Chunk(to_chunk(chunk, sequence))),
+				});
+				sequence += 1;
+			}
+			if cut.is_some() {
+				yield Err(chaos::stream_dropped());
+				return;
+			}
+
+			match run.await {
+				Ok(result) => yield Ok(ToolStreamMessage {
+					payload: Some(Payload::Final(to_response(result, workspace_id(), trace.as_deref()))),
+				}),
+				Err(e) => yield Err(Status::internal(format!("execution task failed: {e}"))),
+			}
+		};
+
+		Ok(Response::new(Box::pin(stream)))
+	}
+
+	type RunInteractiveStream =
+		Pin<Box<dyn Stream<Item = Result<InteractiveServerMessage, Status>> + Send + 'static>>;
+
+	async fn run_interactive(
+		&self,
+		request: Request<Streaming<InteractiveClientMessage>>,
+	) -> Result<Response<Self::RunInteractiveStream>, Status> {
+		let principal = request.extensions().get::<Principal>().cloned();
+		let caller = Caller::new(principal.as_ref(), request.remote_addr().map(|a| a.ip()), "grpc");
+		let auth = ForwardedAuth::from_metadata(request.metadata());
+		let deadline = deadline::from_metadata(request.metadata(), self.executor.deadline_overhead());
+		let mut inbound = request.into_inner();
+		self.executor.chaos().before_call().await?;
+		let mut req = match inbound.message().await? {
+			Some(InteractiveClientMessage {
+				payload: Some(ClientPayload::Start(req)),
+			}) => req,
+			Some(_) => return Err(SandboxError::InvalidRequest("the first message must be start".to_string()).into()),
+			None => {
+				return Err(SandboxError::InvalidRequest("the call ended before a start message".to_string()).into())
+			}
+		};
+		let (backend, tool_pty) = self
+			.executor
+			.registry()
+			.get(&req.tool_name)
+			.map(|tool| (tool.backend, tool.pty))
+			.unzip();
+		if matches!(backend, Some(ExecutionBackend::Wasm | ExecutionBackend::Component)) {
+			let error = SandboxError::Unsupported(format!("{} is a WASM tool and cannot read a live stdin", req.tool_name));
+			return Err(error.into());
+		}
+		let pty = req.pty.take().map(|size| window_size(&size)).transpose()?;
+		if let Some(backend) = backend.filter(|backend| pty.is_some() && !backend.runs_on_terminal()) {
+			let error = format!("{} is a {} tool and cannot run on a terminal", req.tool_name, backend.as_str());
+			return Err(SandboxError::Unsupported(error).into());
+		}
+		// A tool whose manifest asks for a terminal gets one the client can
+		// resize.
+		let pty = pty.or_else(|| (tool_pty == Some(true)).then(WindowSize::default));
+		let (terminal, resize) = pty.map(Terminal::new).unzip();
+		let (args, ctx) = self.prepare(caller, principal.as_ref(), &req).await?;
+		let ctx = ctx
+			.with_forwarded_auth(auth)
+			.with_deadline(deadline)
+			.with_terminal(terminal);
+
+		let workspace_id = self.retained_workspace(&ctx);
+		let trace = ctx.trace.clone();
+		let (stdin_tx, stdin_rx) = mpsc::channel(STDIN_BUFFER);
+		let (tx, mut rx) = mpsc::channel(CHUNK_BUFFER);
+		let ctx = ctx.with_output(tx).with_stdin_stream(StdinStream::new(stdin_rx));
+		let cancel = ctx.cancel.clone();
+		let session_id = ctx.workspace_id.clone();
+		let tool_name = req.tool_name.clone();
+		info!(tool_name = tool_name, session_id = session_id, message = "Interactive session started");
+		tokio::spawn(forward_client_messages(inbound, stdin_tx, resize, cancel.clone()).in_current_span());
+		let cut = self.executor.chaos().stream_cut();
+		let executor = self.executor.clone();
+		let run = tokio::spawn(
+			async move {
+				executor
+					.execute_tool(req.tool_name.as_str(), args, &ctx)
+					.await
+			}
+			.in_current_span(),
+		);
+
+		let stream = async_stream::stream! {
+			// Dropped with the response stream when the client cancels the call,
+			// and at the end, which also stops reading the client's messages.
+			let _cancel_on_drop = cancel.drop_guard();
+			yield Ok(InteractiveServerMessage {
+				payload: Some(ServerPayload::Started(InteractiveStarted { tool_name, session_id })),
+			});
+			let mut sequence: u64 = 0;
+			while let Some(chunk) = rx.recv().await {
+				if cut == Some(sequence) {
+					yield Err(chaos::stream_dropped());
+					return;
+				}
+				yield Ok(InteractiveServerMessage {
+					payload: Some(ServerPayload::Chunk(to_chunk(chunk, sequence))),
+				});
+				sequence += 1;
+			}
+			if cut.is_some() {
+				yield Err(chaos::stream_dropped());
+				return;
+			}
+
+			match run.await {
+				Ok(result) => yield Ok(InteractiveServerMessage {
+					payload: Some(ServerPayload::Exited(to_response(result, workspace_id(), trace.as_deref()))),
+				}),
+				Err(e) => yield Err(Status::internal(format!("execution task failed: {e}"))),
+			}
+		};
+
+		Ok(Response::new(Box::pin(stream)))
+	}
+}
+
+/// Feed an interactive call's stdin messages to the tool and act on its
+/// control and resize messages until the execution ends (`cancel`) or the
+/// client stops sending. A half-close is an EOF; a broken call kills the
+/// tool.
+async fn forward_client_messages(
+	mut inbound: Streaming<InteractiveClientMessage>,
+	stdin: mpsc::Sender<Vec<u8>>,
+	resize: Option<watch::Sender<WindowSize>>,
+	cancel: CancellationToken,
+) {
+	let mut stdin = Some(stdin);
+	loop {
+		let message = tokio::select! {
+			_ = cancel.cancelled() => return,
+			message = inbound.message() => message,
+		};
+		let payload = match message {
+			Ok(Some(message)) => message.payload,
+			Ok(None) => return,
+			Err(_) => {
+				cancel.cancel();
+				return;
+			}
+		};
+		match payload {
+			Some(ClientPayload::Stdin(data)) => {
+				if let Some(tx) = &stdin {
+					tokio::select! {
+						_ = cancel.cancelled() => return,
+						sent = tx.send(data) => {
+							// The tool closed its stdin; nothing more will be read.
+							if sent.is_err() {
+								stdin = None;
+							}
+						}
+					}
+				}
+			}
+			Some(ClientPayload::Control(control)) => match InteractiveControl::try_from(control) {
+				Ok(InteractiveControl::Eof) => stdin = None,
+				Ok(InteractiveControl::Terminate) => cancel.cancel(),
+				_ => {}
+			},
+			Some(ClientPayload::Resize(size)) => {
+				if let (Some(resize), Ok(size)) = (&resize, window_size(&size)) {
+					resize.send_replace(size);
+				}
+			}
+			Some(ClientPayload::Start(_)) | None => {}
+		}
+	}
+}
+
+pub fn tool_service_server(
+	executor: Arc<Executor>,
+	auth: Arc<Authenticator>,
+	authz: Arc<Authorizer>,
+	ratelimit: Arc<RateLimiter>,
+	max_message_bytes: usize,
+	idempotency: Arc<Idempotency>,
+	compress: bool,
+) -> InterceptedService<ToolServiceServer<SandboxToolService>, GrpcAuthInterceptor> {
+	let mut server = ToolServiceServer::new(SandboxToolService {
+		executor,
+		authz,
+		ratelimit,
+		idempotency,
+	})
+	.max_decoding_message_size(max_message_bytes)
+	.accept_compressed(CompressionEncoding::Gzip)
+	.accept_compressed(CompressionEncoding::Zstd);
+	if compress {
+		// Answers use the first of gzip and zstd the client lists as accepted.
+		server = server
+			.send_compressed(CompressionEncoding::Gzip)
+			.send_compressed(CompressionEncoding::Zstd);
+	}
+	InterceptedService::new(server, GrpcAuthInterceptor::new(auth))
+}
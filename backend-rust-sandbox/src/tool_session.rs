@@ -0,0 +1,235 @@
+use axum::extract::ws::{Message, WebSocket};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{info, warn, Instrument};
+
+use crate::encoding::ChunkEncoder;
+use crate::pty::{Terminal, WindowSize};
+use crate::subprocess::StdinStream;
+use crate::tool::{execute_tool_request_with_context, ToolExecutionRequest, ToolExecutionResponse};
+use crate::tool_executor::{ExecutionBackend, ExecutionContext, Executor};
+
+/// Chunks buffered between the tool and a slow client before the tool's pipe
+/// reads start waiting.
+const CHUNK_BUFFER: usize = 256;
+const STDIN_BUFFER: usize = 16;
+/// Stdin messages held while the tool is not reading before the socket
+/// stops being read too.
+const MAX_PENDING_STDIN: usize = 64;
+
+/// Messages a client sends, as JSON text frames. Binary frames are stdin.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+	/// The first message: which tool to run, as for `execute_tool`.
+	Start {
+		#[serde(flatten)]
+		request: Box<ToolExecutionRequest>,
+		/// Run the tool on a terminal of this size, `{cols, rows}`.
+		#[serde(default)]
+		pty: Option<WindowSize>,
+	},
+	Stdin {
+		data: String,
+	},
+	/// Close the tool's stdin.
+	Eof,
+	/// Resize the tool's terminal; without one, it has no effect.
+	Resize {
+		cols: u16,
+		rows: u16,
+	},
+	/// Stop the tool; the session ends with status `cancelled`.
+	Terminate,
+}
+
+/// Run one interactive session over `socket`.
+///
+/// The client starts a tool with a `start` message, then streams stdin as
+/// `stdin` messages or binary frames until `eof`. The server answers with
+/// `started` (`{tool_name, session_id}`), `stdout`/`stderr` frames as output
+/// arrives (with `"encoding": "base64"` when it is not text), and a final `exited` frame holding what `/api/v1/execute_tool`
+/// would have returned, then closes the socket. Closing the socket or sending
+/// `terminate` kills the tool.
+///
+/// A tool run on a terminal, because `start` has a `pty` size or its
+/// manifest asks for one, has its stdout and stderr together as `stdout`
+/// frames; `resize` changes the terminal's size.
+///
+/// `admit` authorizes and admits the start request like any other tool
+/// request; a rejection is sent as the `exited` frame.
+pub async fn run_session<F, A>(mut socket: WebSocket, executor: Arc<Executor>, admit: F)
+where
+	F: FnOnce(&ToolExecutionRequest) -> A,
+	A: Future<Output = Result<ExecutionContext, ToolExecutionResponse>>,
+{
+	let (req, pty) = loop {
+		match socket.recv().await {
+			Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+				Ok(ClientMessage::Start { request, pty }) => break (*request, pty),
+				Ok(_) => send_error(&mut socket, "the first message must be a start message").await,
+				Err(e) => send_error(&mut socket, &format!("invalid message: {e}")).await,
+			},
+			Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+			Some(Ok(Message::Binary(_))) => send_error(&mut socket, "the first message must be a start message").await,
+			_ => return,
+		}
+	};
+	let (backend, tool_pty) = executor
+		.registry()
+		.get(&req.tool_name)
+		.map(|tool| (tool.backend, tool.pty))
+		.unzip();
+	let interactive = backend.is_none_or(|backend| !matches!(backend, ExecutionBackend::Wasm | ExecutionBackend::Component));
+	if !interactive {
+		send_error(&mut socket, &format!("{} is a WASM tool and cannot read a live stdin", req.tool_name)).await;
+		let _ = socket.send(Message::Close(None)).await;
+		return;
+	}
+	if let Some(backend) = backend.filter(|backend| pty.is_some() && !backend.runs_on_terminal()) {
+		let error = format!("{} is a {} tool and cannot run on a terminal", req.tool_name, backend.as_str());
+		send_error(&mut socket, &error).await;
+		let _ = socket.send(Message::Close(None)).await;
+		return;
+	}
+	// A tool whose manifest asks for a terminal gets one the client can
+	// resize.
+	let pty = pty.or_else(|| (tool_pty == Some(true)).then(WindowSize::default));
+	let (terminal, resize) = pty.map(Terminal::new).unzip();
+	let ctx = match admit(&req).await {
+		Ok(ctx) => ctx,
+		Err(rejection) => {
+			send_frame(&mut socket, "exited", json!(rejection)).await;
+			let _ = socket.send(Message::Close(None)).await;
+			return;
+		}
+	};
+
+	let (stdin_tx, stdin_rx) = mpsc::channel(STDIN_BUFFER);
+	let (output_tx, mut output) = mpsc::channel(CHUNK_BUFFER);
+	let ctx = ctx
+		.with_output(output_tx)
+		.with_stdin_stream(StdinStream::new(stdin_rx))
+		.with_terminal(terminal);
+	let cancel = ctx.cancel.clone();
+	// Whichever way this function ends, the tool does not outlive it.
+	let _cancel_on_drop = cancel.clone().drop_guard();
+	let session_id = ctx.workspace_id.clone();
+	let tool_name = req.tool_name.clone();
+	info!(tool_name = tool_name, session_id = session_id, message = "Interactive session started");
+	send_frame(&mut socket, "started", json!({ "tool_name": tool_name, "session_id": session_id })).await;
+	let run = tokio::spawn(
+		async move { execute_tool_request_with_context(&executor, req, &ctx).await }.in_current_span(),
+	);
+
+	let mut stdin = Some(stdin_tx);
+	let mut pending: VecDeque<Vec<u8>> = VecDeque::new();
+	let mut eof = false;
+	let mut seq: u64 = 0;
+	let mut encoder = ChunkEncoder::default();
+	loop {
+		if eof && pending.is_empty() {
+			stdin = None;
+		}
+		let writer = stdin.clone().filter(|_| !pending.is_empty());
+		tokio::select! {
+			chunk = output.recv() => match chunk {
+				Some(chunk) => {
+					let Some((data, encoding)) = encoder.encode(&chunk) else {
+						continue;
+					};
+					let mut frame = json!({
+						"type": chunk.stream,
+						"seq": seq,
+						"data": data,
+					});
+					if !encoding.is_utf8() {
+						frame["encoding"] = json!(encoding);
+					}
+					seq += 1;
+					if socket.send(Message::Text(frame.to_string())).await.is_err() {
+						return;
+					}
+				}
+				// Every sender is gone once the execution is over.
+				None => break,
+			},
+			permit = async { writer.clone().expect("checked by the guard").reserve_owned().await }, if writer.is_some() => {
+				match permit {
+					Ok(permit) => {
+						permit.send(pending.pop_front().expect("writers wait for pending stdin"));
+					}
+					// The tool closed its stdin; nothing it is sent will be read.
+					Err(_) => {
+						pending.clear();
+						stdin = None;
+					}
+				}
+			}
+			message = socket.recv(), if pending.len() < MAX_PENDING_STDIN => match message {
+				Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+					Ok(ClientMessage::Stdin { data }) if !eof => pending.push_back(data.into_bytes()),
+					Ok(ClientMessage::Stdin { .. }) => send_error(&mut socket, "stdin is already closed").await,
+					Ok(ClientMessage::Eof) => eof = true,
+					Ok(ClientMessage::Resize { cols, rows }) => {
+						if let Some(resize) = &resize {
+							resize.send_replace(WindowSize { cols, rows });
+						}
+					}
+					Ok(ClientMessage::Terminate) => cancel.cancel(),
+					Ok(ClientMessage::Start { .. }) => send_error(&mut socket, "the session has already started").await,
+					Err(e) => send_error(&mut socket, &format!("invalid message: {e}")).await,
+				},
+				Some(Ok(Message::Binary(data))) if !eof => pending.push_back(data),
+				Some(Ok(Message::Binary(_))) => send_error(&mut socket, "stdin is already closed").await,
+				Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+				// The client went away: stop the tool.
+				Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+					info!(tool_name = tool_name, session_id = session_id, message = "Interactive session closed by the client");
+					return;
+				}
+			},
+		}
+	}
+
+	let response = match run.await {
+		Ok(response) => json!(response),
+		Err(e) => {
+			warn!(tool_name = tool_name, error = %e, message = "Interactive session task failed");
+			json!({
+				"status": "internal_error",
+				"tool_name": tool_name,
+				"result": { "stdout": "", "stderr": e.to_string(), "exit_code": null },
+			})
+		}
+	};
+	info!(
+		tool_name = tool_name,
+		session_id = session_id,
+		status = response["status"].as_str().unwrap_or_default(),
+		message = "Interactive session finished"
+	);
+	send_frame(&mut socket, "exited", response).await;
+	let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Send `body` as a frame of type `kind`; object bodies get a `type` field,
+/// anything else is wrapped.
+async fn send_frame(socket: &mut WebSocket, kind: &str, body: Value) {
+	let frame = match body {
+		Value::Object(mut map) => {
+			map.insert("type".to_string(), json!(kind));
+			Value::Object(map)
+		}
+		other => json!({ "type": kind, "data": other }),
+	};
+	let _ = socket.send(Message::Text(frame.to_string())).await;
+}
+
+async fn send_error(socket: &mut WebSocket, error: &str) {
+	send_frame(socket, "error", json!({ "error": error })).await;
+}
@@ -0,0 +1,487 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Component, Path, PathBuf};
+
+use crate::egress::{NetworkJail, NetworkPolicy};
+use crate::secrets::ToolEnv;
+use crate::subprocess::{self, CommandSpec};
+use crate::tool_executor::{ExecutionContext, ToolResult};
+use crate::workspace::Workspace;
+
+const DEFAULT_SHELL: &str = "/bin/sh";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShellConfig {
+	/// Programs the `shell` tool may run, by the name the command gives,
+	/// e.g. `ls` or `/usr/bin/git`. Nothing may run while this and
+	/// `allowed_patterns` are both empty.
+	pub allowed_commands: Vec<String>,
+	/// Regexes a command is also allowed by when one matches it whole, e.g.
+	/// `git (status|log|diff)( .*)?`. The command is matched as its words,
+	/// quoted where a shell would need it, joined by spaces.
+	pub allowed_patterns: Vec<String>,
+	/// Let commands be joined with `|`.
+	pub allow_pipelines: bool,
+	/// Let commands redirect to and from files in the workspace with `<`,
+	/// `>`, `>>`, `2>`, `2>>` and `2>&1`.
+	pub allow_redirects: bool,
+	/// Runs pipelines and redirections; plain commands are spawned directly.
+	pub shell: PathBuf,
+	/// Where commands may connect to; offline by default.
+	pub network: NetworkPolicy,
+}
+
+impl Default for ShellConfig {
+	fn default() -> Self {
+		Self {
+			allowed_commands: Vec::new(),
+			allowed_patterns: Vec::new(),
+			allow_pipelines: false,
+			allow_redirects: false,
+			shell: DEFAULT_SHELL.into(),
+			network: NetworkPolicy::None,
+		}
+	}
+}
+
+impl ShellConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		for pattern in &self.allowed_patterns {
+			if let Err(e) = Regex::new(pattern) {
+				errors.push(format!("executor.shell.allowed_patterns: {pattern:?}: {e}"));
+			}
+		}
+		if let Err(e) = self.network.clone().validate() {
+			errors.push(e.replace("network.allowlist", "executor.shell.network.allowlist"));
+		}
+		errors
+	}
+}
+
+/// Implements the `shell` tool: runs a command line, checked command by
+/// command against `executor.shell`.
+///
+/// Args: `{"command": "grep -n TODO src/main.rs"}`. The line is split into
+/// words like a POSIX shell does, quotes and backslashes included, but
+/// nothing is expanded: variables, globs and `~` reach the program as
+/// written. `;`, `&&`, `||` and `&` are refused, as are `|` and
+/// redirections unless enabled. A plain command is spawned without a shell;
+/// a pipeline or redirection goes to `shell` with every word quoted again,
+/// so only the operators that passed the check mean anything to it.
+pub struct ShellTool {
+	config: ShellConfig,
+	patterns: Vec<Regex>,
+	network: Option<NetworkJail>,
+}
+
+impl ShellTool {
+	pub fn new(config: ShellConfig) -> Result<Self, String> {
+		let patterns = config
+			.allowed_patterns
+			.iter()
+			.map(|p| Regex::new(&format!("^(?:{p})$")).map_err(|e| format!("{p:?}: {e}")))
+			.collect::<Result<_, _>>()?;
+		let mut network = config.network.clone();
+		network.validate()?;
+		Ok(Self {
+			network: NetworkJail::new(&network),
+			patterns,
+			config,
+		})
+	}
+
+	pub async fn run(&self, args: &Value, ctx: &ExecutionContext, workspace: &Workspace, env: &ToolEnv) -> ToolResult {
+		let result = |status: &str, stderr: String| ToolResult {
+			status: status.to_string(),
+			stderr,
+			..Default::default()
+		};
+		let Some(line) = args.get("command").and_then(|v| v.as_str()) else {
+			return result("invalid_args", "command must be a string".to_string());
+		};
+		let pipeline = match parse(line).and_then(|p| self.check(&p).map(|()| p)) {
+			Ok(pipeline) => pipeline,
+			Err(e) => return result("command_denied", format!("blocked by the sandbox: {e}\n")),
+		};
+
+		let spec = match pipeline.as_slice() {
+			[stage] if stage.redirects.is_empty() => {
+				let mut spec = CommandSpec::new(&stage.words[0]);
+				for word in &stage.words[1..] {
+					spec = spec.arg(word);
+				}
+				spec
+			}
+			_ => CommandSpec::new(self.config.shell.to_string_lossy())
+				.arg("-c")
+				.arg(render(&pipeline)),
+		};
+		let spec = spec
+			.stdin(ctx.stdin())
+			.tool_env(env)
+			.env("PAGI_TOOL_NAME", "shell")
+			.env("HOME", workspace.path.to_string_lossy())
+			.env("TMPDIR", workspace.path.to_string_lossy())
+			.cwd(&workspace.path)
+			.jail(workspace.jail.clone())
+			.pid_namespace(workspace.pid_namespace)
+			.network(self.network.clone())
+			.timeout(ctx.timeout)
+			.output(ctx.output.clone())
+			.max_output(ctx.max_output_bytes)
+			.cancel(ctx.cancel.clone())
+			.processes(ctx.processes.clone())
+			.usage(ctx.usage.clone())
+			.cgroup(ctx.cgroup.clone());
+		match subprocess::run(&spec).await {
+			Ok(mut out) => {
+				if !out.network_denied.is_empty() {
+					out.note(&format!(
+						"blocked by the sandbox: not allowed by executor.shell.network: {}\n",
+						out.network_denied.join(", ")
+					));
+				}
+				ToolResult {
+					status: out.status().to_string(),
+					stdout: out.stdout,
+					stderr: out.stderr,
+					encoding: out.encoding,
+					exit_code: out.exit_code,
+					truncated: out.truncated,
+					..Default::default()
+				}
+			}
+			Err(e) => result(
+				subprocess::spawn_error_status(&e),
+				format!("failed to spawn {}: {e}", spec.program),
+			),
+		}
+	}
+
+	fn check(&self, pipeline: &[Stage]) -> Result<(), String> {
+		if pipeline.len() > 1 && !self.config.allow_pipelines {
+			return Err("pipelines are not allowed by executor.shell.allow_pipelines".to_string());
+		}
+		for stage in pipeline {
+			if !stage.redirects.is_empty() && !self.config.allow_redirects {
+				return Err("redirections are not allowed by executor.shell.allow_redirects".to_string());
+			}
+			for redirect in &stage.redirects {
+				if let Some(path) = &redirect.target {
+					let inside = Path::new(path)
+						.components()
+						.all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+					if !inside {
+						return Err(format!("redirection target {path:?} is outside the workspace"));
+					}
+				}
+			}
+			let program = &stage.words[0];
+			let command = stage.words.iter().map(|w| quote(w)).collect::<Vec<_>>().join(" ");
+			let allowed = self.config.allowed_commands.contains(program)
+				|| self.patterns.iter().any(|p| p.is_match(&command));
+			if !allowed {
+				return Err(format!("command {command:?} is not allowed by executor.shell"));
+			}
+		}
+		Ok(())
+	}
+}
+
+/// One command of a pipeline.
+#[derive(Debug)]
+struct Stage {
+	words: Vec<String>,
+	redirects: Vec<Redirect>,
+}
+
+#[derive(Debug)]
+struct Redirect {
+	/// `<`, `>`, `>>`, `2>`, `2>>` or `2>&1`.
+	op: &'static str,
+	/// The file, for all but `2>&1`.
+	target: Option<String>,
+}
+
+enum Token {
+	Word(String),
+	Pipe,
+	Redirect(&'static str),
+}
+
+/// Split `line` into a pipeline of commands.
+fn parse(line: &str) -> Result<Vec<Stage>, String> {
+	let mut pipeline = Vec::new();
+	let mut stage = Stage {
+		words: Vec::new(),
+		redirects: Vec::new(),
+	};
+	let mut tokens = tokenize(line)?.into_iter();
+	while let Some(token) = tokens.next() {
+		match token {
+			Token::Word(word) => stage.words.push(word),
+			Token::Redirect("2>&1") => stage.redirects.push(Redirect {
+				op: "2>&1",
+				target: None,
+			}),
+			Token::Redirect(op) => match tokens.next() {
+				Some(Token::Word(target)) => stage.redirects.push(Redirect {
+					op,
+					target: Some(target),
+				}),
+				_ => return Err(format!("{op} needs a file name")),
+			},
+			Token::Pipe => {
+				if stage.words.is_empty() {
+					return Err("empty command in pipeline".to_string());
+				}
+				pipeline.push(std::mem::replace(
+					&mut stage,
+					Stage {
+						words: Vec::new(),
+						redirects: Vec::new(),
+					},
+				));
+			}
+		}
+	}
+	if stage.words.is_empty() {
+		return Err(if pipeline.is_empty() { "empty command" } else { "empty command in pipeline" }.to_string());
+	}
+	pipeline.push(stage);
+	Ok(pipeline)
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+	let mut tokens = Vec::new();
+	let mut chars = line.chars().peekable();
+	let mut word = String::new();
+	// Whether `word` is underway, so that `''` still makes an empty word.
+	let mut in_word = false;
+	while let Some(c) = chars.next() {
+		match c {
+			'\'' => {
+				in_word = true;
+				loop {
+					match chars.next() {
+						Some('\'') => break,
+						Some(q) => word.push(q),
+						None => return Err("unterminated single quote".to_string()),
+					}
+				}
+			}
+			'"' => {
+				in_word = true;
+				loop {
+					match chars.next() {
+						Some('"') => break,
+						Some('\\') if matches!(chars.peek(), Some('"' | '\\' | '$' | '`')) => {
+							word.push(chars.next().unwrap_or_default())
+						}
+						Some(q) => word.push(q),
+						None => return Err("unterminated double quote".to_string()),
+					}
+				}
+			}
+			'\\' => {
+				in_word = true;
+				match chars.next() {
+					Some('\n') | None => {}
+					Some(escaped) => word.push(escaped),
+				}
+			}
+			c if c.is_whitespace() || matches!(c, '|' | '&' | ';' | '<' | '>') => {
+				// `2>` is a redirection only when the 2 stands alone.
+				let stderr = c == '>' && in_word && word == "2";
+				if in_word && !stderr {
+					tokens.push(Token::Word(std::mem::take(&mut word)));
+				}
+				in_word = false;
+				word.clear();
+				match c {
+					'|' if chars.peek() == Some(&'|') => return Err("|| is not allowed".to_string()),
+					'|' => tokens.push(Token::Pipe),
+					'&' if chars.peek() == Some(&'&') => return Err("&& is not allowed".to_string()),
+					'&' => return Err("background jobs (&) are not allowed".to_string()),
+					';' => return Err("; is not allowed".to_string()),
+					'<' => tokens.push(Token::Redirect("<")),
+					'>' => {
+						let append = chars.peek() == Some(&'>');
+						if append {
+							chars.next();
+						}
+						let op = match (stderr, append) {
+							(true, _) if !append && chars.peek() == Some(&'&') => {
+								chars.next();
+								if chars.next() != Some('1') {
+									return Err("only 2>&1 may duplicate a descriptor".to_string());
+								}
+								"2>&1"
+							}
+							(true, true) => "2>>",
+							(true, false) => "2>",
+							(false, true) => ">>",
+							(false, false) => ">",
+						};
+						if chars.peek() == Some(&'&') || chars.peek() == Some(&'|') {
+							return Err(format!("{op}{} is not allowed", chars.peek().unwrap_or(&' ')));
+						}
+						tokens.push(Token::Redirect(op));
+					}
+					_ => {}
+				}
+			}
+			c => {
+				in_word = true;
+				word.push(c);
+			}
+		}
+	}
+	if in_word {
+		tokens.push(Token::Word(word));
+	}
+	Ok(tokens)
+}
+
+/// The pipeline as a line for `sh -c`, every word and file name quoted.
+fn render(pipeline: &[Stage]) -> String {
+	pipeline
+		.iter()
+		.map(|stage| {
+			let mut parts: Vec<String> = stage.words.iter().map(|w| quote(w)).collect();
+			for redirect in &stage.redirects {
+				match &redirect.target {
+					Some(target) => parts.push(format!("{} {}", redirect.op, quote(target))),
+					None => parts.push(redirect.op.to_string()),
+				}
+			}
+			parts.join(" ")
+		})
+		.collect::<Vec<_>>()
+		.join(" | ")
+}
+
+/// `word` as a shell would need it written to stay one literal word.
+fn quote(word: &str) -> String {
+	let plain = !word.is_empty()
+		&& word
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/' | ',' | ':' | '=' | '+' | '@' | '%'));
+	if plain {
+		word.to_string()
+	} else {
+		format!("'{}'", word.replace('\'', r"'\''"))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Each stage's words and (operator, target) redirections.
+	type Shape = Vec<(Vec<String>, Vec<(&'static str, Option<String>)>)>;
+
+	fn shape(line: &str) -> Shape {
+		parse(line)
+			.unwrap()
+			.into_iter()
+			.map(|stage| {
+				let redirects = stage.redirects.into_iter().map(|r| (r.op, r.target)).collect();
+				(stage.words, redirects)
+			})
+			.collect()
+	}
+
+	fn words(words: &[&str]) -> Vec<String> {
+		words.iter().map(|w| w.to_string()).collect()
+	}
+
+	#[test]
+	fn stderr_joins_stdout() {
+		assert_eq!(
+			shape("make 2>&1 | tail -n 5"),
+			vec![(words(&["make"]), vec![("2>&1", None)]), (words(&["tail", "-n", "5"]), vec![])]
+		);
+	}
+
+	#[test]
+	fn two_redirects_stderr_only_standing_alone() {
+		assert_eq!(
+			shape("cmd 2> err a2> out 2>>log"),
+			vec![(
+				words(&["cmd", "a2"]),
+				vec![
+					("2>", Some("err".to_string())),
+					(">", Some("out".to_string())),
+					("2>>", Some("log".to_string())),
+				]
+			)]
+		);
+		assert_eq!(shape("echo 2 > out"), vec![(words(&["echo", "2"]), vec![(">", Some("out".to_string()))])]);
+	}
+
+	#[test]
+	fn empty_quotes_are_words() {
+		assert_eq!(shape(r#"printf '' x """#), vec![(words(&["printf", "", "x", ""]), vec![])]);
+	}
+
+	#[test]
+	fn quoting_and_escapes() {
+		assert_eq!(
+			shape(r#"grep -n 'a b' "it's \"so\"" x\ y $HOME *.rs"#),
+			vec![(words(&["grep", "-n", "a b", r#"it's "so""#, "x y", "$HOME", "*.rs"]), vec![])]
+		);
+	}
+
+	#[test]
+	fn render_round_trips() {
+		for line in [
+			"ls -la",
+			r#"grep -n 'a b' "it's" x\ y '' 2>>log < in | wc -l"#,
+			"make 2>&1 | tail -n 5",
+			r#"echo '$(rm -rf /)' '`id`' '|' ';' > 'out file'"#,
+		] {
+			let rendered = render(&parse(line).unwrap());
+			assert_eq!(shape(&rendered), shape(line), "{line} rendered as {rendered}");
+		}
+	}
+
+	#[test]
+	fn quote_leaves_plain_words_alone() {
+		assert_eq!(quote("src/main.rs"), "src/main.rs");
+		assert_eq!(quote(""), "''");
+		assert_eq!(quote("a b"), "'a b'");
+		assert_eq!(quote("it's"), r"'it'\''s'");
+	}
+
+	#[test]
+	fn control_operators_are_refused() {
+		for line in ["a; b", "a && b", "a || b", "a &", "a & b", "a >&2", "a |& b", "a 2>&2", "a >", "| a", "a |", ""] {
+			assert!(parse(line).is_err(), "{line:?} parsed");
+		}
+	}
+
+	#[test]
+	fn check_applies_the_config() {
+		let tool = ShellTool::new(ShellConfig {
+			allowed_commands: words(&["ls", "wc"]),
+			allowed_patterns: words(&["git (status|log)( .*)?"]),
+			allow_redirects: true,
+			..Default::default()
+		})
+		.unwrap();
+		let check = |line: &str| tool.check(&parse(line).unwrap());
+		assert!(check("ls -la").is_ok());
+		assert!(check("git log --oneline").is_ok());
+		assert!(check("git push").is_err());
+		assert!(check("gitx status").is_err());
+		assert!(check("rm -rf x").is_err());
+		assert!(check("ls | wc -l").is_err());
+		assert!(check("ls > out/list").is_ok());
+		assert!(check("ls > ../list").is_err());
+		assert!(check("ls > /tmp/list").is_err());
+	}
+}
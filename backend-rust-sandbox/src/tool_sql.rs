@@ -0,0 +1,526 @@
+use futures::TryStreamExt;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sqlx::any::{AnyArguments, AnyPoolOptions, AnyRow, AnyTypeInfoKind};
+use sqlx::query::Query;
+use sqlx::{Any, AnyConnection, AnyPool, Column, Connection, Row};
+use std::collections::{BTreeMap, HashSet};
+use std::os::raw::{c_char, c_int};
+use std::sync::{Once, OnceLock};
+
+use base64::Engine as _;
+
+use crate::history::redact_url;
+use crate::tool_executor::{ExecutionContext, ToolResult};
+
+const DEFAULT_MAX_ROWS: usize = 1000;
+const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+const DEFAULT_MAX_CONNECTIONS: u32 = 4;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqlConfig {
+	/// Databases the `sql_query` tool can reach, by the name requests use.
+	pub connections: BTreeMap<String, SqlConnectionConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqlConnectionConfig {
+	/// `postgres://`, `mysql://` or `sqlite:` URL.
+	pub url: String,
+	/// Run every query in a read-only transaction (a read-only connection
+	/// for SQLite).
+	pub read_only: bool,
+	/// Schemas queries may read, checked against the query plan before the
+	/// query runs: schema names for Postgres, database names (`main` or
+	/// `temp`) for SQLite. Any schema when empty. Not supported for
+	/// MySQL, where the user's grants have to do it.
+	pub schemas: Vec<String>,
+	/// Rows returned at most; requests may ask for fewer.
+	pub max_rows: usize,
+	/// Size the rows returned may take, as JSON.
+	pub max_bytes: usize,
+	pub max_connections: u32,
+}
+
+impl Default for SqlConnectionConfig {
+	fn default() -> Self {
+		Self {
+			url: String::new(),
+			read_only: true,
+			schemas: Vec::new(),
+			max_rows: DEFAULT_MAX_ROWS,
+			max_bytes: DEFAULT_MAX_BYTES,
+			max_connections: DEFAULT_MAX_CONNECTIONS,
+		}
+	}
+}
+
+impl SqlConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		for (name, connection) in &self.connections {
+			let prefix = format!("executor.sql.connections.{name}");
+			match Backend::of(&connection.url) {
+				None => errors.push(format!(
+					"{prefix}.url must be a postgres:, mysql: or sqlite: URL (got {:?})",
+					redact_url(&connection.url)
+				)),
+				Some(Backend::MySql) if !connection.schemas.is_empty() => errors.push(format!(
+					"{prefix}.schemas cannot be enforced for MySQL; limit the user's grants instead"
+				)),
+				Some(Backend::Sqlite) if connection.read_only && sets_mode(&connection.url) => errors.push(format!(
+					"{prefix}.url must not set mode=, which read_only sets to ro"
+				)),
+				Some(_) => {}
+			}
+			if connection.max_rows == 0 {
+				errors.push(format!("{prefix}.max_rows must be at least 1"));
+			}
+			if connection.max_bytes == 0 {
+				errors.push(format!("{prefix}.max_bytes must be positive"));
+			}
+			if connection.max_connections == 0 {
+				errors.push(format!("{prefix}.max_connections must be at least 1"));
+			}
+		}
+		errors
+	}
+
+	pub fn redact(&mut self) {
+		for connection in self.connections.values_mut() {
+			connection.url = redact_url(&connection.url);
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+	Postgres,
+	MySql,
+	Sqlite,
+}
+
+impl Backend {
+	fn of(url: &str) -> Option<Self> {
+		let scheme = url.split_once(':')?.0;
+		match scheme {
+			"postgres" | "postgresql" => Some(Self::Postgres),
+			"mysql" | "mariadb" => Some(Self::MySql),
+			"sqlite" => Some(Self::Sqlite),
+			_ => None,
+		}
+	}
+}
+
+struct Database {
+	config: SqlConnectionConfig,
+	backend: Backend,
+	pool: AnyPool,
+}
+
+/// Implements the `sql_query` tool.
+///
+/// Args: `{"connection": "analytics", "query": "SELECT * FROM t WHERE id = $1",
+/// "params": [42], "format": "json", "max_rows": 10}`. `connection` may be
+/// left out when only one is configured. The query is one statement with
+/// its parameters bound, never spliced in; placeholders are the database's
+/// own (`$1` for Postgres and SQLite, `?` for MySQL). The result is
+/// `{"columns", "rows", "row_count", "truncated"}` with each row an object,
+/// or CSV with a header line.
+pub struct SqlTool {
+	databases: BTreeMap<String, Database>,
+}
+
+impl SqlTool {
+	/// Pools connect on first use, so an unreachable database only fails
+	/// the queries against it.
+	pub fn new(config: &SqlConfig) -> Result<Self, String> {
+		sqlx::any::install_default_drivers();
+		let mut databases = BTreeMap::new();
+		for (name, connection) in &config.connections {
+			let backend = Backend::of(&connection.url).ok_or_else(|| format!("{name}: unsupported database URL"))?;
+			if backend == Backend::Sqlite {
+				forbid_attach();
+			}
+			let mut url = connection.url.clone();
+			if backend == Backend::Sqlite && connection.read_only {
+				url.push(if url.contains('?') { '&' } else { '?' });
+				url.push_str("mode=ro");
+			}
+			let pool = AnyPoolOptions::new()
+				.max_connections(connection.max_connections)
+				.connect_lazy(&url)
+				.map_err(|e| format!("{name}: {}: {e}", redact_url(&connection.url)))?;
+			databases.insert(
+				name.clone(),
+				Database {
+					config: connection.clone(),
+					backend,
+					pool,
+				},
+			);
+		}
+		Ok(Self { databases })
+	}
+
+	pub async fn run(&self, args: &Value, ctx: &ExecutionContext) -> ToolResult {
+		let query = tokio::time::timeout(ctx.timeout, self.query(args));
+		let outcome = tokio::select! {
+			outcome = query => match outcome {
+				Ok(outcome) => outcome,
+				Err(_) => Err(Failure::new(
+					"timed_out",
+					format!("sql_query did not finish within {}ms", ctx.timeout.as_millis()),
+				)),
+			},
+			_ = ctx.cancel.cancelled() => return ctx.check_cancelled().unwrap_err(),
+		};
+		match outcome {
+			Ok((stdout, stderr)) => ToolResult {
+				status: "ok".to_string(),
+				stdout,
+				stderr,
+				..Default::default()
+			},
+			Err(failure) => ToolResult {
+				status: failure.status.to_string(),
+				stderr: failure.message,
+				..Default::default()
+			},
+		}
+	}
+
+	async fn query(&self, args: &Value) -> Result<(String, String), Failure> {
+		let database = match args.get("connection").and_then(|v| v.as_str()) {
+			Some(name) => self
+				.databases
+				.get(name)
+				.ok_or_else(|| Failure::new("invalid_args", format!("no connection named {name:?}")))?,
+			None if self.databases.len() == 1 => self.databases.values().next().expect("one connection"),
+			None if self.databases.is_empty() => {
+				return Err(Failure::new("invalid_args", "no SQL connections are configured".to_string()))
+			}
+			None => return Err(Failure::new("invalid_args", "connection is required".to_string())),
+		};
+		let Some(sql) = args.get("query").and_then(|v| v.as_str()) else {
+			return Err(Failure::new("invalid_args", "query must be a string".to_string()));
+		};
+		let params = match args.get("params") {
+			None | Some(Value::Null) => Vec::new(),
+			Some(Value::Array(params)) => params.clone(),
+			Some(_) => return Err(Failure::new("invalid_args", "params must be an array".to_string())),
+		};
+		let csv = match args.get("format").and_then(|v| v.as_str()).unwrap_or("json") {
+			"json" => false,
+			"csv" => true,
+			other => return Err(Failure::new("invalid_args", format!("format must be json or csv (got {other:?})"))),
+		};
+		let max_rows = args
+			.get("max_rows")
+			.and_then(|v| v.as_u64())
+			.map_or(database.config.max_rows, |n| (n as usize).min(database.config.max_rows));
+		let mut conn = database.pool.acquire().await.map_err(Failure::database)?;
+		let begin = match (database.config.read_only, database.backend) {
+			(true, Backend::Postgres) => Some("BEGIN READ ONLY"),
+			(true, Backend::MySql) => Some("START TRANSACTION READ ONLY"),
+			_ => None,
+		};
+		let (columns, out, truncated) = match begin {
+			Some(begin) => {
+				let mut tx = conn.begin_with(begin).await.map_err(Failure::database)?;
+				let fetched = fetch(&mut tx, database, sql, &params, max_rows).await;
+				tx.rollback().await.map_err(Failure::database)?;
+				fetched?
+			}
+			None => fetch(&mut conn, database, sql, &params, max_rows).await?,
+		};
+
+		let row_count = out.len();
+		let note = if truncated {
+			format!("result truncated to {row_count} rows by the row or byte limit\n")
+		} else {
+			String::new()
+		};
+		if csv {
+			let mut text = csv_line(columns.iter().map(String::as_str));
+			for values in &out {
+				let fields: Vec<String> = values
+					.iter()
+					.map(|v| match v {
+						Value::Null => String::new(),
+						Value::String(s) => s.clone(),
+						other => other.to_string(),
+					})
+					.collect();
+				text.push_str(&csv_line(fields.iter().map(String::as_str)));
+			}
+			return Ok((text, note));
+		}
+		let rows: Vec<Value> = out
+			.into_iter()
+			.map(|values| Value::Object(columns.iter().cloned().zip(values).collect::<Map<_, _>>()))
+			.collect();
+		let result = json!({
+			"columns": columns,
+			"rows": rows,
+			"row_count": row_count,
+			"truncated": truncated,
+		});
+		Ok((serde_json::to_string_pretty(&result).unwrap_or_default(), note))
+	}
+}
+
+/// Run `sql` and collect its rows up to the limits: the columns, the rows
+/// and whether any were left out.
+async fn fetch(
+	conn: &mut AnyConnection,
+	database: &Database,
+	sql: &str,
+	params: &[Value],
+	max_rows: usize,
+) -> Result<(Vec<String>, Vec<Vec<Value>>, bool), Failure> {
+	if !database.config.schemas.is_empty() {
+		check_schemas(conn, database, sql, params).await?;
+	}
+	let mut rows = bind(sqlx::query(sql), params).fetch(&mut *conn);
+	let mut columns: Vec<String> = Vec::new();
+	let mut out = Vec::new();
+	let mut bytes = 0;
+	while let Some(row) = rows.try_next().await.map_err(Failure::database)? {
+		if columns.is_empty() {
+			columns = row.columns().iter().map(|c| c.name().to_string()).collect();
+		}
+		let values = decode_row(&row)?;
+		bytes += values.iter().map(|v| v.to_string().len()).sum::<usize>();
+		if out.len() == max_rows || bytes > database.config.max_bytes {
+			return Ok((columns, out, true));
+		}
+		out.push(values);
+	}
+	Ok((columns, out, false))
+}
+
+fn bind<'q>(mut query: Query<'q, Any, AnyArguments<'q>>, params: &[Value]) -> Query<'q, Any, AnyArguments<'q>> {
+	for param in params {
+		query = match param {
+			Value::Null => query.bind(None::<String>),
+			Value::Bool(b) => query.bind(*b),
+			Value::Number(n) => match n.as_i64() {
+				Some(i) => query.bind(i),
+				None => query.bind(n.as_f64().unwrap_or_default()),
+			},
+			Value::String(s) => query.bind(s.clone()),
+			other => query.bind(other.to_string()),
+		};
+	}
+	query
+}
+
+/// A row's values as JSON; blobs as base64.
+fn decode_row(row: &AnyRow) -> Result<Vec<Value>, Failure> {
+	let mut values = Vec::with_capacity(row.columns().len());
+	for (i, column) in row.columns().iter().enumerate() {
+		let value = match column.type_info().kind {
+			AnyTypeInfoKind::Null => Value::Null,
+			AnyTypeInfoKind::Bool => json!(row.try_get::<Option<bool>, _>(i).map_err(Failure::database)?),
+			AnyTypeInfoKind::SmallInt | AnyTypeInfoKind::Integer | AnyTypeInfoKind::BigInt => {
+				json!(row.try_get::<Option<i64>, _>(i).map_err(Failure::database)?)
+			}
+			AnyTypeInfoKind::Real | AnyTypeInfoKind::Double => {
+				json!(row.try_get::<Option<f64>, _>(i).map_err(Failure::database)?)
+			}
+			AnyTypeInfoKind::Text => json!(row.try_get::<Option<String>, _>(i).map_err(Failure::database)?),
+			AnyTypeInfoKind::Blob => json!(row
+				.try_get::<Option<Vec<u8>>, _>(i)
+				.map_err(Failure::database)?
+				.map(|b| base64::engine::general_purpose::STANDARD.encode(b))),
+		};
+		values.push(value);
+	}
+	Ok(values)
+}
+
+/// Refuse `sql` if its plan reads or writes a relation outside the
+/// connection's schemas.
+async fn check_schemas(conn: &mut AnyConnection, database: &Database, sql: &str, params: &[Value]) -> Result<(), Failure> {
+	let allowed: HashSet<&str> = database.config.schemas.iter().map(String::as_str).collect();
+	let touched: Vec<String> = match database.backend {
+		Backend::Postgres => {
+			let explain = format!("EXPLAIN (VERBOSE, FORMAT TEXT) {sql}");
+			let plan: Vec<String> = bind(sqlx::query(&explain), params)
+				.fetch_all(&mut *conn)
+				.await
+				.map_err(Failure::database)?
+				.iter()
+				.map(|row| row.try_get::<String, _>(0).unwrap_or_default())
+				.collect();
+			plan_schemas(&plan)
+		}
+		Backend::Sqlite => {
+			// `temp` is only listed once something has created it.
+			let mut names = BTreeMap::from([(1, "temp".to_string())]);
+			names.extend(sqlx::query("PRAGMA database_list")
+				.fetch_all(&mut *conn)
+				.await
+				.map_err(Failure::database)?
+				.iter()
+				.filter_map(|row| Some((row.try_get::<i64, _>(0).ok()?, row.try_get::<String, _>(1).ok()?))),
+			);
+			let explain = format!("EXPLAIN {sql}");
+			bind(sqlx::query(&explain), params)
+				.fetch_all(&mut *conn)
+				.await
+				.map_err(Failure::database)?
+				.iter()
+				.filter(|row| {
+					matches!(row.try_get::<String, _>(1).as_deref(), Ok("OpenRead" | "OpenWrite" | "ReopenIdx"))
+				})
+				.filter_map(|row| row.try_get::<i64, _>(4).ok())
+				.map(|db| names.get(&db).cloned().unwrap_or_else(|| db.to_string()))
+				.collect()
+		}
+		Backend::MySql => Vec::new(),
+	};
+	match touched.iter().find(|schema| !allowed.contains(schema.as_str())) {
+		Some(schema) => Err(Failure::new(
+			"query_denied",
+			format!("schema {schema:?} is not among the connection's schemas"),
+		)),
+		None => Ok(()),
+	}
+}
+
+/// The schemas of the relations a Postgres `EXPLAIN (VERBOSE)` plan scans
+/// or writes, one per plan line that does.
+fn plan_schemas(plan: &[String]) -> Vec<String> {
+	static RELATION: OnceLock<Regex> = OnceLock::new();
+	let relation = RELATION.get_or_init(|| {
+		Regex::new(
+			r"^\s*(?:->\s+)?(?:(?:Seq|Index|Index Only|Bitmap Heap|Tid|Tid Range|Sample|Foreign) Scan(?: Backward)?(?: using \S+)?|Insert|Update|Delete|Merge) on ([^\s.]+)\.",
+		)
+		.expect("valid regex")
+	});
+	plan.iter()
+		.filter_map(|line| relation.captures(line).map(|c| c[1].trim_matches('"').to_string()))
+		.collect()
+}
+
+/// Whether a SQLite URL's query sets the open `mode`.
+fn sets_mode(url: &str) -> bool {
+	url.split_once('?')
+		.is_some_and(|(_, query)| query.split('&').any(|pair| pair.split('=').next() == Some("mode")))
+}
+
+/// Open every SQLite connection from here on with no room for attached
+/// databases, since `ATTACH` can open any file the process may read and
+/// reach past `read_only` and `schemas`. The limit is the engine's, so no
+/// statement, however written, gets around it. It applies to the whole
+/// process, the history database included, which attaches nothing.
+fn forbid_attach() {
+	static REGISTERED: Once = Once::new();
+	REGISTERED.call_once(|| {
+		// SAFETY: registers a function with the signature SQLite expects,
+		// before the connections it applies to are opened.
+		unsafe { libsqlite3_sys::sqlite3_auto_extension(Some(no_attached_databases)) };
+	});
+}
+
+unsafe extern "C" fn no_attached_databases(
+	db: *mut libsqlite3_sys::sqlite3,
+	_error: *mut *mut c_char,
+	_api: *const libsqlite3_sys::sqlite3_api_routines,
+) -> c_int {
+	// SAFETY: SQLite hands over the connection it is opening.
+	unsafe { libsqlite3_sys::sqlite3_limit(db, libsqlite3_sys::SQLITE_LIMIT_ATTACHED, 0) };
+	libsqlite3_sys::SQLITE_OK
+}
+
+fn csv_line<'a>(fields: impl Iterator<Item = &'a str>) -> String {
+	let mut line = fields
+		.map(|f| {
+			if f.contains([',', '"', '\n', '\r']) {
+				format!("\"{}\"", f.replace('"', "\"\""))
+			} else {
+				f.to_string()
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(",");
+	line.push('\n');
+	line
+}
+
+struct Failure {
+	status: &'static str,
+	message: String,
+}
+
+impl Failure {
+	fn new(status: &'static str, message: String) -> Self {
+		Self { status, message }
+	}
+
+	fn database(e: sqlx::Error) -> Self {
+		Self::new("query_error", e.to_string())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sqlite(url: &str, read_only: bool) -> SqlConfig {
+		SqlConfig {
+			connections: BTreeMap::from([(
+				"db".to_string(),
+				SqlConnectionConfig {
+					url: url.to_string(),
+					read_only,
+					..Default::default()
+				},
+			)]),
+		}
+	}
+
+	#[tokio::test]
+	async fn attach_is_refused_however_it_is_written() {
+		let tool = SqlTool::new(&sqlite("sqlite::memory:", false)).unwrap();
+		assert!(tool.query(&json!({ "query": "SELECT 1" })).await.is_ok());
+		for query in [
+			"ATTACH DATABASE ':memory:' AS other",
+			"/* */ attach ':memory:' as other",
+			"SELECT 1; ATTACH ':memory:' AS other",
+		] {
+			let failure = tool.query(&json!({ "query": query })).await.unwrap_err();
+			assert!(failure.message.contains("attached"), "{query}: {}", failure.message);
+		}
+	}
+
+	#[test]
+	fn read_only_urls_may_not_set_mode() {
+		assert!(sqlite("sqlite:data.db?mode=rwc", true).validate()[0].contains("mode="));
+		assert!(sqlite("sqlite:data.db?cache=shared", true).validate().is_empty());
+		assert!(sqlite("sqlite:data.db?mode=rwc", false).validate().is_empty());
+	}
+
+	#[test]
+	fn plan_schemas_names_every_scanned_and_written_relation() {
+		let plan = [
+			"Insert on audit.log  (cost=0.00..1.01 rows=0 width=0)",
+			"  ->  Nested Loop  (cost=0.29..8.31 rows=1 width=36)",
+			"        Output: u.id, o.total",
+			"        ->  Index Only Scan Backward using users_pkey on public.users u  (cost=0.29..4.30 rows=1 width=4)",
+			"        ->  Seq Scan on \"Billing\".orders o  (cost=0.00..4.00 rows=1 width=32)",
+			"              Filter: (o.user_id = public.users.id)",
+			"  ->  Bitmap Heap Scan on private.secrets  (cost=4.18..12.64 rows=4 width=36)",
+			"  ->  Foreign Scan on remote.items  (cost=100.00..150.00 rows=10 width=4)",
+			"Update on public.users  (cost=0.00..1.01 rows=0 width=0)",
+			"  ->  CTE Scan on recent  (cost=0.00..0.02 rows=1 width=4)",
+		]
+		.map(String::from);
+		assert_eq!(plan_schemas(&plan), ["audit", "public", "Billing", "private", "remote", "public"]);
+	}
+}
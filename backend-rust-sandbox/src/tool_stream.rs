@@ -0,0 +1,103 @@
+use axum::body::Body;
+use axum::http::header;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+use tracing::{warn, Instrument};
+
+use crate::compression::Unbuffered;
+use crate::encoding::ChunkEncoder;
+use crate::negotiate::{self, Format};
+use crate::tool::{execute_tool_request_with_context, ToolExecutionRequest};
+use crate::tool_executor::{ExecutionContext, Executor};
+
+/// Chunks buffered between the tool and a slow SSE client before the tool's
+/// pipe reads start waiting.
+const CHUNK_BUFFER: usize = 256;
+
+/// Run a tool and stream its lifecycle, as Server-Sent Events or, in the
+/// other `format`s, as one `{"event", "data"}` document per event.
+///
+/// Events, in order:
+/// - `started`: `{"tool_name"}`
+/// - `chunk` (repeated): `{"seq", "stream": "stdout"|"stderr", "data"}`, plus
+///   `"encoding": "base64"` when the data is not text
+/// - `exited`: the same body `/api/v1/execute_tool` would have returned
+pub fn execute_tool_stream(
+	executor: Arc<Executor>,
+	req: ToolExecutionRequest,
+	ctx: ExecutionContext,
+	format: Format,
+) -> Response {
+	let events = execute_tool_events(executor, req, ctx);
+	let mut response = match format {
+		Format::EventStream => Sse::new(events.map(|(name, data)| {
+			Ok::<_, Infallible>(Event::default().event(name).data(data.to_string()))
+		}))
+		.keep_alive(KeepAlive::default())
+		.into_response(),
+		Format::Json | Format::Ndjson | Format::MessagePack => {
+			let body = Body::from_stream(events.map(move |(name, data)| {
+				Ok::<_, Infallible>(format.encode(&json!({ "event": name, "data": data })))
+			}));
+			([(header::CONTENT_TYPE, format.content_type())], body).into_response()
+		}
+	};
+	response.extensions_mut().insert(Unbuffered);
+	negotiate::with_vary(response)
+}
+
+fn execute_tool_events(
+	executor: Arc<Executor>,
+	req: ToolExecutionRequest,
+	ctx: ExecutionContext,
+) -> impl Stream<Item = (&'static str, Value)> {
+	let (tx, mut rx) = mpsc::channel(CHUNK_BUFFER);
+	let tool_name = req.tool_name.clone();
+	let ctx = ctx.with_output(tx);
+	let cancel = ctx.cancel.clone();
+	let run = tokio::spawn(
+		async move { execute_tool_request_with_context(&executor, req, &ctx).await }.in_current_span(),
+	);
+
+	async_stream::stream! {
+		// A client that goes away drops this stream, which cancels the run.
+		let _cancel_on_drop = cancel.drop_guard();
+		yield ("started", json!({ "tool_name": tool_name }));
+
+		// The channel closes once the execution (and with it every sender) is done.
+		let mut seq: u64 = 0;
+		let mut encoder = ChunkEncoder::default();
+		while let Some(chunk) = rx.recv().await {
+			let Some((data, encoding)) = encoder.encode(&chunk) else {
+				continue;
+			};
+			let mut frame = json!({
+				"seq": seq,
+				"stream": chunk.stream,
+				"data": data,
+			});
+			if !encoding.is_utf8() {
+				frame["encoding"] = json!(encoding);
+			}
+			yield ("chunk", frame);
+			seq += 1;
+		}
+
+		match run.await {
+			Ok(response) => yield ("exited", json!(response)),
+			Err(e) => {
+				warn!(tool_name = tool_name, error = %e, message = "Streaming execution task failed");
+				yield ("exited", json!({
+					"status": "internal_error",
+					"tool_name": tool_name,
+					"result": { "stdout": "", "stderr": e.to_string(), "exit_code": null },
+				}));
+			}
+		}
+	}
+}
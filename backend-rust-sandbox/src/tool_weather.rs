@@ -0,0 +1,42 @@
+use serde_json::{json, Value};
+
+use crate::tool_executor::{ExecutionContext, ToolResult};
+use crate::tool_sdk::Tool;
+
+/// The `weather_tool` tool: canned weather for any city.
+///
+/// Args (optional):
+///   {"city": "..."}
+pub struct Weather;
+
+#[tonic::async_trait]
+impl Tool for Weather {
+	fn name(&self) -> &'static str {
+		"weather_tool"
+	}
+
+	fn description(&self) -> &'static str {
+		"Return the current weather for a city (mock data)."
+	}
+
+	fn schema(&self) -> Value {
+		json!({
+			"type": "object",
+			"properties": { "city": { "type": "string" } },
+		})
+	}
+
+	async fn run(&self, _ctx: &ExecutionContext, args: Value) -> ToolResult {
+		let city = args.get("city").and_then(|v| v.as_str()).unwrap_or("unknown");
+		ToolResult {
+			status: "ok".to_string(),
+			stdout: serde_json::to_string_pretty(&json!({
+				"city": city,
+				"temperature": "22C",
+				"conditions": "Sunny",
+			}))
+			.unwrap_or_else(|_| format!("{{\"city\":\"{}\"}}", city)),
+			..Default::default()
+		}
+	}
+}
@@ -1,14 +1,39 @@
-use crate::tool_executor::ToolResult;
-use serde_json::Value;
+use crate::tool_executor::{ExecutionContext, ToolResult};
+use crate::tool_sdk::Tool;
+use serde_json::{json, Value};
 
-/// Implements the `web_search` tool.
+/// The `web_search` tool.
 ///
 /// This is a deterministic "mock" backed by a public JSON endpoint so we can
 /// exercise real HTTP I/O without requiring a search API key.
 ///
 /// Args (optional):
 ///   {"query": "..."}
-pub async fn execute_web_search(args: Value) -> ToolResult {
+pub struct WebSearch;
+
+#[tonic::async_trait]
+impl Tool for WebSearch {
+	fn name(&self) -> &'static str {
+		"web_search"
+	}
+
+	fn description(&self) -> &'static str {
+		"Search the web (demo: fetches a fixed public JSON endpoint)."
+	}
+
+	fn schema(&self) -> Value {
+		json!({
+			"type": "object",
+			"properties": { "query": { "type": "string" } },
+		})
+	}
+
+	async fn run(&self, _ctx: &ExecutionContext, args: Value) -> ToolResult {
+		execute_web_search(args).await
+	}
+}
+
+async fn execute_web_search(args: Value) -> ToolResult {
 	let _query = args
 		.get("query")
 		.and_then(|v| v.as_str())
@@ -22,8 +47,8 @@ pub async fn execute_web_search(args: Value) -> ToolResult {
 		Err(e) => {
 			return ToolResult {
 				status: "network_error".to_string(),
-				stdout: "".to_string(),
 				stderr: format!("reqwest error: {e}"),
+				..Default::default()
 			};
 		}
 	};
@@ -34,8 +59,8 @@ pub async fn execute_web_search(args: Value) -> ToolResult {
 		Err(e) => {
 			return ToolResult {
 				status: "read_error".to_string(),
-				stdout: "".to_string(),
 				stderr: format!("failed reading response body: {e}"),
+				..Default::default()
 			};
 		}
 	};
@@ -52,7 +77,7 @@ pub async fn execute_web_search(args: Value) -> ToolResult {
 			format!("http_{}", status.as_u16())
 		},
 		stdout,
-		stderr: "".to_string(),
+		..Default::default()
 	}
 }
 
@@ -0,0 +1,138 @@
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::clock::now_ms;
+
+pub use pagi_sandbox_client::wire::{ExecutionTrace, HttpExchange};
+
+/// Bytes of seeded randomness a WASM guest cycles through.
+const RANDOM_POOL_BYTES: usize = 64 * 1024;
+
+/// The method, URL and body hash of the `http_request` call `args` asks
+/// for, as far as they can be read; the tool itself rejects malformed args.
+pub fn http_request_of(args: &Value) -> (String, String, Option<String>) {
+	let method = args.get("method").and_then(Value::as_str).unwrap_or("GET").to_ascii_uppercase();
+	let url = args.get("url").and_then(Value::as_str).unwrap_or_default().to_string();
+	let body = match (args.get("body"), args.get("json")) {
+		(Some(Value::String(body)), _) => Some(body.clone()),
+		(None, Some(document)) => Some(document.to_string()),
+		_ => None,
+	};
+	let body_sha256 = body.map(|body| Sha256::digest(body.as_bytes()).iter().map(|b| format!("{b:02x}")).collect());
+	(method, url, body_sha256)
+}
+
+#[derive(Debug)]
+enum Mode {
+	Recording(Vec<HttpExchange>),
+	/// The exchanges not served yet.
+	Replaying(VecDeque<HttpExchange>),
+}
+
+/// An execution's end of a trace: it either records effects as they happen
+/// or serves recorded ones back in the order they were recorded.
+#[derive(Debug)]
+pub struct Tracer {
+	time_ms: u64,
+	seed: u64,
+	mode: Mutex<Mode>,
+}
+
+impl Tracer {
+	/// Start recording, at the current time and with a fresh seed.
+	pub fn record() -> Self {
+		Self {
+			time_ms: now_ms(),
+			seed: rand::thread_rng().gen(),
+			mode: Mutex::new(Mode::Recording(Vec::new())),
+		}
+	}
+
+	pub fn replay(trace: ExecutionTrace) -> Self {
+		Self {
+			time_ms: trace.time_ms,
+			seed: trace.seed,
+			mode: Mutex::new(Mode::Replaying(trace.http.into())),
+		}
+	}
+
+	pub fn time_ms(&self) -> u64 {
+		self.time_ms
+	}
+
+	pub fn seed(&self) -> u64 {
+		self.seed
+	}
+
+	/// Variables a subprocess reads its clock and seed from. Tools that use
+	/// them in place of the system's are reproducible under replay.
+	pub fn env(&self) -> Vec<(String, String)> {
+		vec![
+			("PAGI_TRACE_TIME_MS".to_string(), self.time_ms.to_string()),
+			("PAGI_TRACE_SEED".to_string(), self.seed.to_string()),
+			("SOURCE_DATE_EPOCH".to_string(), (self.time_ms / 1000).to_string()),
+			("PYTHONHASHSEED".to_string(), (self.seed % (1 << 32)).to_string()),
+		]
+	}
+
+	/// Randomness for a WASM guest, the same for every run with this seed.
+	pub fn random_bytes(&self) -> Vec<u8> {
+		let mut bytes = vec![0; RANDOM_POOL_BYTES];
+		StdRng::seed_from_u64(self.seed).fill_bytes(&mut bytes);
+		bytes
+	}
+
+	/// In replay, the recorded answer to the next `http_request` call, or an
+	/// error when the call is not the one recorded next; `None` when
+	/// recording.
+	pub fn next_http(&self, args: &Value) -> Option<Result<HttpExchange, String>> {
+		let Mode::Replaying(exchanges) = &mut *self.mode.lock().unwrap() else {
+			return None;
+		};
+		let (method, url, body_sha256) = http_request_of(args);
+		let Some(next) = exchanges.pop_front() else {
+			return Some(Err(format!("the trace has no more HTTP exchanges for {method} {url}")));
+		};
+		if next.method != method || next.url != url {
+			return Some(Err(format!(
+				"the trace's next HTTP exchange is {} {}, not {method} {url}",
+				next.method, next.url
+			)));
+		}
+		if next.body_sha256 != body_sha256 {
+			return Some(Err(format!("{method} {url} was recorded with a different body")));
+		}
+		Some(Ok(next))
+	}
+
+	/// Add an exchange when recording.
+	pub fn record_http(&self, exchange: HttpExchange) {
+		if let Mode::Recording(exchanges) = &mut *self.mode.lock().unwrap() {
+			exchanges.push(exchange);
+		}
+	}
+
+	/// The trace recorded so far; `None` in replay.
+	pub fn recorded(&self) -> Option<ExecutionTrace> {
+		match &*self.mode.lock().unwrap() {
+			Mode::Recording(exchanges) => Some(ExecutionTrace {
+				time_ms: self.time_ms,
+				seed: self.seed,
+				http: exchanges.clone(),
+			}),
+			Mode::Replaying(_) => None,
+		}
+	}
+
+	/// Recorded exchanges a replay never asked for.
+	pub fn unused(&self) -> usize {
+		match &*self.mode.lock().unwrap() {
+			Mode::Replaying(exchanges) => exchanges.len(),
+			Mode::Recording(_) => 0,
+		}
+	}
+}
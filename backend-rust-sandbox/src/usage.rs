@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// What an execution used, as its result reports it. Wall time is the time
+/// it held its execution slot; the rest is measured on the processes it
+/// spawned, across every attempt, and is absent when it spawned none, as
+/// with WASM and other in-process backends or containers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+	pub wall_ms: u64,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub user_cpu_ms: Option<u64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub sys_cpu_ms: Option<u64>,
+	/// The most memory any one process (or, in cgroups, all of them
+	/// together) held at once.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub peak_rss_bytes: Option<u64>,
+	/// Written to storage.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub bytes_written: Option<u64>,
+	/// Written to stdout and stderr, whether kept or not.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub output_bytes: Option<u64>,
+	/// What it cost at `quotas.rates`, when they price anything.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub cost: Option<f64>,
+}
+
+/// One process's usage, from its `rusage` and, when it ran in one, its
+/// cgroup.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessUsage {
+	pub user_cpu: Duration,
+	pub sys_cpu: Duration,
+	pub peak_rss_bytes: u64,
+	pub bytes_written: u64,
+	pub output_bytes: u64,
+}
+
+/// Adds up the usage of an execution's processes. Clones share the total.
+#[derive(Debug, Clone, Default)]
+pub struct UsageMeter(Arc<Mutex<Option<ProcessUsage>>>);
+
+impl UsageMeter {
+	pub fn add(&self, usage: ProcessUsage) {
+		let mut total = self.0.lock().unwrap();
+		let sum = total.get_or_insert_with(ProcessUsage::default);
+		sum.user_cpu += usage.user_cpu;
+		sum.sys_cpu += usage.sys_cpu;
+		sum.peak_rss_bytes = sum.peak_rss_bytes.max(usage.peak_rss_bytes);
+		sum.bytes_written += usage.bytes_written;
+		sum.output_bytes += usage.output_bytes;
+	}
+
+	/// The total so far, for an execution that ran for `wall`.
+	pub fn total(&self, wall: Duration) -> ResourceUsage {
+		let total = *self.0.lock().unwrap();
+		ResourceUsage {
+			wall_ms: wall.as_millis() as u64,
+			user_cpu_ms: total.map(|u| u.user_cpu.as_millis() as u64),
+			sys_cpu_ms: total.map(|u| u.sys_cpu.as_millis() as u64),
+			peak_rss_bytes: total.map(|u| u.peak_rss_bytes),
+			bytes_written: total.map(|u| u.bytes_written),
+			output_bytes: total.map(|u| u.output_bytes),
+			cost: None,
+		}
+	}
+}
+
+/// Block until the child `pid` has exited, without reaping it, and return
+/// what it and the children it waited for used. The child stays a zombie
+/// for `Child::wait` to reap; calling this after that fails with `ECHILD`.
+pub fn wait_exited(pid: u32) -> io::Result<ProcessUsage> {
+	loop {
+		// SAFETY: both structs are plain C data, and zeroed is valid for either.
+		let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+		let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+		// The glibc wrapper has no rusage argument; the system call does,
+		// and fills it in for WNOWAIT too.
+		// SAFETY: the pointers are valid for the duration of the call.
+		let rc = unsafe {
+			libc::syscall(
+				libc::SYS_waitid,
+				libc::P_PID,
+				pid as libc::id_t,
+				&mut info as *mut libc::siginfo_t,
+				libc::WEXITED | libc::WNOWAIT,
+				&mut rusage as *mut libc::rusage,
+			)
+		};
+		if rc == 0 {
+			return Ok(from_rusage(&rusage));
+		}
+		let err = io::Error::last_os_error();
+		if err.kind() != io::ErrorKind::Interrupted {
+			return Err(err);
+		}
+	}
+}
+
+fn from_rusage(rusage: &libc::rusage) -> ProcessUsage {
+	let time = |t: libc::timeval| Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64);
+	ProcessUsage {
+		user_cpu: time(rusage.ru_utime),
+		sys_cpu: time(rusage.ru_stime),
+		// Reported in KiB on Linux.
+		peak_rss_bytes: rusage.ru_maxrss as u64 * 1024,
+		// In 512-byte blocks.
+		bytes_written: rusage.ru_oublock as u64 * 512,
+		output_bytes: 0,
+	}
+}
@@ -0,0 +1,47 @@
+use pagi_sandbox_client::Base64;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Port the agent listens on inside the guest.
+pub const DEFAULT_GUEST_PORT: u32 = 52;
+
+/// Sandbox to `pagi-vm-agent`, the guest's init, over vsock: one JSON frame
+/// per line, as in the other direction.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostFrame {
+	/// Run `argv` in the work directory, holding `files`, with exactly `env`.
+	Start {
+		argv: Vec<String>,
+		env: BTreeMap<String, String>,
+		files: BTreeMap<String, Base64>,
+	},
+	Stdin {
+		data: Base64,
+	},
+	/// Nothing more for the tool's stdin.
+	StdinEof,
+}
+
+/// `pagi-vm-agent` to sandbox.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GuestFrame {
+	Stdout {
+		data: Base64,
+	},
+	Stderr {
+		data: Base64,
+	},
+	/// The tool has exited; `files` is the work directory as it left it.
+	Exit {
+		exit_code: Option<i32>,
+		signal: Option<i32>,
+		files: BTreeMap<String, Base64>,
+	},
+	/// The agent could not run the tool: a bad start frame, or a program
+	/// that would not spawn.
+	Error {
+		message: String,
+	},
+}
@@ -0,0 +1,378 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Once, Weak};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::metrics::METRICS;
+
+const DEFAULT_TTL_SECS: u64 = 600;
+const DEFAULT_MAX_USES: u32 = 10;
+/// Idle instances are checked for expiry at a quarter of the TTL, but no
+/// more often than this.
+const MIN_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What becomes of an instance once an execution has had it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaintPolicy {
+	/// Every instance serves one execution and is then destroyed.
+	Destroy,
+	/// An instance whose execution exited on its own is reset and put back,
+	/// until it has served `max_uses`; timeouts, cancellations, kills and
+	/// backend errors still destroy it.
+	Recycle,
+}
+
+impl FromStr for TaintPolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"destroy" => Ok(Self::Destroy),
+			"recycle" => Ok(Self::Recycle),
+			_ => Err("expected destroy or recycle".to_string()),
+		}
+	}
+}
+
+/// How pre-started sandboxes (containers, microVMs) are kept; how many
+/// each backend keeps is part of its own configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarmPoolConfig {
+	/// Instances older than this are destroyed and replaced, idle or not,
+	/// so that none runs for long on a stale image or a leaked state.
+	pub ttl_secs: u64,
+	pub taint: TaintPolicy,
+	/// Executions a recycled instance serves before it is destroyed.
+	pub max_uses: u32,
+}
+
+impl Default for WarmPoolConfig {
+	fn default() -> Self {
+		Self {
+			ttl_secs: DEFAULT_TTL_SECS,
+			taint: TaintPolicy::Destroy,
+			max_uses: DEFAULT_MAX_USES,
+		}
+	}
+}
+
+impl WarmPoolConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if self.ttl_secs == 0 {
+			errors.push("executor.warm_pool.ttl_secs must be positive".to_string());
+		}
+		if self.max_uses == 0 {
+			errors.push("executor.warm_pool.max_uses must be positive".to_string());
+		}
+		errors
+	}
+}
+
+/// Starts and stops the instances of one backend.
+#[tonic::async_trait]
+pub trait Launcher: Send + Sync + 'static {
+	/// What an instance is started from, e.g. a VM class; executions only
+	/// get instances of their own class.
+	type Class: Clone + Eq + Hash + Display + Send + Sync + 'static;
+	type Instance: Send + 'static;
+
+	/// Instances of `class` to keep ready.
+	fn pool_size(&self, class: &Self::Class) -> usize;
+
+	async fn launch(&self, class: &Self::Class) -> Result<Self::Instance, String>;
+
+	/// Whether an idle instance can still be handed out.
+	fn alive(&self, instance: &mut Self::Instance) -> bool;
+
+	/// Clear what an execution left in `instance`, so that the next one gets
+	/// it as if new.
+	async fn reset(&self, instance: &mut Self::Instance) -> Result<(), String>;
+
+	async fn destroy(&self, instance: Self::Instance);
+}
+
+struct Entry<T> {
+	instance: T,
+	launched: Instant,
+	uses: u32,
+}
+
+struct Slots<T> {
+	ready: Vec<Entry<T>>,
+	launching: usize,
+	/// Handed out and expected back, when instances are recycled.
+	leased: usize,
+}
+
+// Not derived, which would ask for `T: Default`.
+impl<T> Default for Slots<T> {
+	fn default() -> Self {
+		Self {
+			ready: Vec::new(),
+			launching: 0,
+			leased: 0,
+		}
+	}
+}
+
+impl<T> Slots<T> {
+	fn filled(&self) -> usize {
+		self.ready.len() + self.launching + self.leased
+	}
+}
+
+/// Instances of one backend started ahead of executions, by class, so that
+/// executions skip the start-up.
+pub struct WarmPool<L: Launcher> {
+	/// Labels the pool's metrics.
+	backend: &'static str,
+	launcher: L,
+	config: WarmPoolConfig,
+	classes: Mutex<HashMap<L::Class, Slots<L::Instance>>>,
+	sweeping: Once,
+}
+
+impl<L: Launcher> WarmPool<L> {
+	pub fn new(backend: &'static str, launcher: L, config: WarmPoolConfig) -> Arc<Self> {
+		Arc::new(Self {
+			backend,
+			launcher,
+			config,
+			classes: Mutex::new(HashMap::new()),
+			sweeping: Once::new(),
+		})
+	}
+
+	pub fn launcher(&self) -> &L {
+		&self.launcher
+	}
+
+	fn ttl(&self) -> Duration {
+		Duration::from_secs(self.config.ttl_secs)
+	}
+
+	/// A ready instance of `class`, or one started now if there is none.
+	pub async fn take(self: &Arc<Self>, class: &L::Class) -> Result<Lease<L>, String> {
+		let recycling = self.config.taint == TaintPolicy::Recycle;
+		let mut discarded = Vec::new();
+		let ready = {
+			let mut classes = self.classes.lock().unwrap();
+			let slots = classes.entry(class.clone()).or_default();
+			let mut ready = None;
+			while let Some(mut entry) = slots.ready.pop() {
+				// An instance may have died or gone stale while it waited.
+				if entry.launched.elapsed() >= self.ttl() {
+					discarded.push((entry, "expired"));
+				} else if !self.launcher.alive(&mut entry.instance) {
+					discarded.push((entry, "dead"));
+				} else {
+					ready = Some(entry);
+					break;
+				}
+			}
+			if recycling {
+				slots.leased += 1;
+			}
+			ready
+		};
+		for (entry, reason) in discarded {
+			self.discard(entry, reason);
+		}
+		self.refill(class);
+		if self.launcher.pool_size(class) > 0 {
+			let result = if ready.is_some() { "hit" } else { "miss" };
+			METRICS.warm_pool_takes.with_label_values(&[self.backend, result]).inc();
+		}
+		let entry = match ready {
+			Some(entry) => entry,
+			None => match self.launcher.launch(class).await {
+				Ok(instance) => Entry {
+					instance,
+					launched: Instant::now(),
+					uses: 0,
+				},
+				Err(e) => {
+					if recycling {
+						self.returned(class);
+					}
+					return Err(e);
+				}
+			},
+		};
+		Ok(Lease {
+			pool: self.clone(),
+			class: class.clone(),
+			entry: Some(entry),
+		})
+	}
+
+	/// Start instances until `class` has its pool size ready or on the way.
+	pub fn refill(self: &Arc<Self>, class: &L::Class) {
+		self.sweeping.call_once(|| {
+			tokio::spawn(sweep(Arc::downgrade(self)));
+		});
+		let size = self.launcher.pool_size(class);
+		let mut classes = self.classes.lock().unwrap();
+		let slots = classes.entry(class.clone()).or_default();
+		while slots.filled() < size {
+			slots.launching += 1;
+			let pool = self.clone();
+			let class = class.clone();
+			tokio::spawn(async move {
+				let launched = pool.launcher.launch(&class).await;
+				let mut classes = pool.classes.lock().unwrap();
+				let slots = classes.entry(class.clone()).or_default();
+				slots.launching -= 1;
+				match launched {
+					Ok(instance) => slots.ready.push(Entry {
+						instance,
+						launched: Instant::now(),
+						uses: 0,
+					}),
+					Err(e) => warn!(
+						backend = pool.backend,
+						class = %class,
+						error = %e,
+						message = "Failed to start a warm instance"
+					),
+				}
+			});
+		}
+	}
+
+	/// Ready instances by class.
+	pub fn ready(&self) -> BTreeMap<String, usize> {
+		self.classes
+			.lock()
+			.unwrap()
+			.iter()
+			.map(|(class, slots)| (class.to_string(), slots.ready.len()))
+			.collect()
+	}
+
+	/// An instance is back from its execution; `clean` if the execution
+	/// exited on its own.
+	fn release(self: &Arc<Self>, class: L::Class, mut entry: Entry<L::Instance>, clean: bool) {
+		entry.uses += 1;
+		let discard = match self.config.taint {
+			TaintPolicy::Destroy => Some("tainted"),
+			TaintPolicy::Recycle if !clean => Some("tainted"),
+			TaintPolicy::Recycle if entry.uses >= self.config.max_uses => Some("used_up"),
+			TaintPolicy::Recycle if entry.launched.elapsed() >= self.ttl() => Some("expired"),
+			TaintPolicy::Recycle => None,
+		};
+		if let Some(reason) = discard {
+			if self.config.taint == TaintPolicy::Recycle {
+				self.returned(&class);
+			}
+			self.discard(entry, reason);
+			self.refill(&class);
+			return;
+		}
+		let pool = self.clone();
+		tokio::spawn(async move {
+			let reset = pool.launcher.reset(&mut entry.instance).await;
+			pool.returned(&class);
+			if let Err(e) = reset {
+				warn!(backend = pool.backend, class = %class, error = %e, message = "Failed to reset a warm instance");
+				pool.discard(entry, "reset_failed");
+			} else {
+				let mut classes = pool.classes.lock().unwrap();
+				let slots = classes.entry(class.clone()).or_default();
+				// Instances started because none was ready come back too.
+				if slots.filled() < pool.launcher.pool_size(&class) {
+					slots.ready.push(entry);
+				} else {
+					drop(classes);
+					pool.discard(entry, "surplus");
+				}
+			}
+			pool.refill(&class);
+		});
+	}
+
+	fn returned(&self, class: &L::Class) {
+		if let Some(slots) = self.classes.lock().unwrap().get_mut(class) {
+			slots.leased = slots.leased.saturating_sub(1);
+		}
+	}
+
+	fn discard(self: &Arc<Self>, entry: Entry<L::Instance>, reason: &'static str) {
+		METRICS.warm_pool_discards.with_label_values(&[self.backend, reason]).inc();
+		let pool = self.clone();
+		tokio::spawn(async move { pool.launcher.destroy(entry.instance).await });
+	}
+}
+
+/// Replace idle instances past their TTL, for as long as the pool exists.
+async fn sweep<L: Launcher>(pool: Weak<WarmPool<L>>) {
+	loop {
+		let interval = match pool.upgrade() {
+			Some(pool) => (pool.ttl() / 4).max(MIN_SWEEP_INTERVAL),
+			None => return,
+		};
+		tokio::time::sleep(interval).await;
+		let Some(pool) = pool.upgrade() else {
+			return;
+		};
+		let ttl = pool.ttl();
+		let mut expired = Vec::new();
+		let classes: Vec<L::Class> = {
+			let mut classes = pool.classes.lock().unwrap();
+			for slots in classes.values_mut() {
+				let (stale, fresh): (Vec<_>, Vec<_>) = std::mem::take(&mut slots.ready)
+					.into_iter()
+					.partition(|entry| entry.launched.elapsed() >= ttl);
+				slots.ready = fresh;
+				expired.extend(stale);
+			}
+			classes.keys().cloned().collect()
+		};
+		for entry in expired {
+			pool.discard(entry, "expired");
+		}
+		for class in &classes {
+			pool.refill(class);
+		}
+	}
+}
+
+/// An instance handed to one execution. Dropping it without
+/// [`release`](Self::release) destroys the instance.
+pub struct Lease<L: Launcher> {
+	pool: Arc<WarmPool<L>>,
+	class: L::Class,
+	entry: Option<Entry<L::Instance>>,
+}
+
+impl<L: Launcher> Lease<L> {
+	pub fn instance(&mut self) -> &mut L::Instance {
+		&mut self.entry.as_mut().expect("held until released").instance
+	}
+
+	/// Hand the instance back. `clean` says the execution exited on its own,
+	/// which is what lets the taint policy recycle it.
+	pub fn release(mut self, clean: bool) {
+		let entry = self.entry.take().expect("released once");
+		self.pool.release(self.class.clone(), entry, clean);
+	}
+}
+
+impl<L: Launcher> Drop for Lease<L> {
+	fn drop(&mut self) {
+		if let Some(entry) = self.entry.take() {
+			if self.pool.config.taint == TaintPolicy::Recycle {
+				self.pool.returned(&self.class);
+			}
+			self.pool.discard(entry, "tainted");
+			self.pool.refill(&self.class);
+		}
+	}
+}
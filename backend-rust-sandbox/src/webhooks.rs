@@ -0,0 +1,389 @@
+use hmac::{Hmac, Mac};
+use reqwest::redirect;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+use crate::clock::now_ms;
+use crate::egress::NetworkPolicy;
+use crate::jobs::JobView;
+use crate::metrics::METRICS;
+use crate::request_id;
+use crate::retry::RetryPolicy;
+use crate::tool_http::{blocked, CheckedResolver, Destinations};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_INITIAL_BACKOFF_MS: u64 = 1000;
+const DEFAULT_MAX_BACKOFF_MS: u64 = 60_000;
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+const SIGNATURE_HEADER: &str = "x-sandbox-signature";
+const TIMESTAMP_HEADER: &str = "x-sandbox-timestamp";
+const DELIVERY_HEADER: &str = "x-sandbox-delivery";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+	/// HMAC-SHA256 key every delivery is signed with. Jobs are refused a
+	/// `callback_url` without one.
+	pub secret: Option<String>,
+	/// Hosts callback URLs may point at; `*.example.com` matches every
+	/// subdomain of `example.com`. Any public host when empty.
+	pub allowed_hosts: Vec<String>,
+	/// Let callbacks and approval webhooks reach loopback, private,
+	/// link-local and other non-public addresses. Metadata endpoints stay
+	/// blocked.
+	pub allow_private_networks: bool,
+	/// Put in front of the job's path in a payload's `job_url`, e.g.
+	/// `https://sandbox.internal:8001`.
+	pub base_url: Option<String>,
+	/// Delivery attempts in all, the first one included.
+	pub max_attempts: u32,
+	pub initial_backoff_ms: u64,
+	pub max_backoff_ms: u64,
+	/// Limit for each attempt, connecting included.
+	pub timeout_ms: u64,
+	/// JSON-lines file undeliverable payloads are appended to; they are only
+	/// logged without one.
+	pub dead_letter_file: Option<PathBuf>,
+}
+
+impl Default for WebhooksConfig {
+	fn default() -> Self {
+		Self {
+			secret: None,
+			allowed_hosts: Vec::new(),
+			allow_private_networks: false,
+			base_url: None,
+			max_attempts: DEFAULT_MAX_ATTEMPTS,
+			initial_backoff_ms: DEFAULT_INITIAL_BACKOFF_MS,
+			max_backoff_ms: DEFAULT_MAX_BACKOFF_MS,
+			timeout_ms: DEFAULT_TIMEOUT_MS,
+			dead_letter_file: None,
+		}
+	}
+}
+
+impl WebhooksConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		if self.secret.as_ref().is_some_and(|s| s.is_empty()) {
+			errors.push("webhooks.secret must not be empty".to_string());
+		}
+		if let Err(e) = self.hosts() {
+			errors.push(e);
+		}
+		if let Some(url) = &self.base_url {
+			if !(url.starts_with("http://") || url.starts_with("https://")) {
+				errors.push(format!("webhooks.base_url must be an http(s) URL (got {url:?})"));
+			}
+		}
+		if let Err(e) = self.retry_policy().validate() {
+			errors.push(format!("webhooks: {e}"));
+		}
+		if self.timeout_ms == 0 {
+			errors.push("webhooks.timeout_ms must be positive".to_string());
+		}
+		errors
+	}
+
+	fn hosts(&self) -> Result<NetworkPolicy, String> {
+		if self.allowed_hosts.is_empty() {
+			return Ok(NetworkPolicy::Full);
+		}
+		let mut hosts = NetworkPolicy::Allowlist(self.allowed_hosts.clone());
+		hosts
+			.validate()
+			.map_err(|e| e.replace("network.allowlist", "webhooks.allowed_hosts"))?;
+		Ok(hosts)
+	}
+
+	fn retry_policy(&self) -> RetryPolicy {
+		RetryPolicy {
+			max_attempts: self.max_attempts,
+			initial_backoff_ms: self.initial_backoff_ms,
+			max_backoff_ms: self.max_backoff_ms,
+			..RetryPolicy::default()
+		}
+	}
+}
+
+/// Delivers job completion callbacks, and the notifications of jobs that
+/// wait for approval.
+///
+/// Each delivery is a `POST` of a JSON payload naming the job, its outcome
+/// and where to fetch its result, signed with
+/// `X-Sandbox-Signature: sha256=<hex HMAC of "<timestamp>.<body>">` and
+/// `X-Sandbox-Timestamp` (Unix seconds) so receivers can reject replays.
+/// Network errors, timeouts, `408`, `429` and `5xx` answers are retried with
+/// backoff under the same `X-Sandbox-Delivery` ID; other answers, and the
+/// last failed attempt, send the payload to the dead-letter log.
+pub struct Webhooks {
+	config: WebhooksConfig,
+	/// Where job callbacks may go.
+	callbacks: Destinations,
+	/// Where any delivery may go, the approval webhooks included: public
+	/// addresses only unless `allow_private_networks`, never a metadata
+	/// endpoint.
+	destinations: Arc<Destinations>,
+	retry: RetryPolicy,
+	http: reqwest::Client,
+	dead_letters: Option<Mutex<File>>,
+	/// Cuts retry waits short at shutdown; the payloads are dead-lettered.
+	shutdown: CancellationToken,
+	deliveries: TaskTracker,
+}
+
+impl Webhooks {
+	pub fn new(config: &WebhooksConfig) -> Result<Self, String> {
+		let dead_letters = match &config.dead_letter_file {
+			Some(path) => Some(Mutex::new(
+				OpenOptions::new()
+					.create(true)
+					.append(true)
+					.open(path)
+					.map_err(|e| format!("{}: {e}", path.display()))?,
+			)),
+			None => None,
+		};
+		let allow_private = config.allow_private_networks;
+		let destinations = Arc::new(Destinations::new(NetworkPolicy::Full, allow_private, "webhooks"));
+		// Redirects are not followed: they could lead past `allowed_hosts`.
+		// No proxy: it would resolve names where the checks cannot see.
+		let http = reqwest::Client::builder()
+			.timeout(Duration::from_millis(config.timeout_ms))
+			.redirect(redirect::Policy::none())
+			.no_proxy()
+			.dns_resolver(Arc::new(CheckedResolver(destinations.clone())))
+			.build()
+			.map_err(|e| e.to_string())?;
+		Ok(Self {
+			callbacks: Destinations::new(config.hosts()?, allow_private, "webhooks.allowed_hosts"),
+			destinations,
+			retry: config.retry_policy(),
+			config: config.clone(),
+			http,
+			dead_letters,
+			shutdown: CancellationToken::new(),
+			deliveries: TaskTracker::new(),
+		})
+	}
+
+	/// Check a job's `callback_url` before the job is accepted.
+	pub fn check_url(&self, url: &str) -> Result<(), String> {
+		if self.config.secret.is_none() {
+			return Err("callbacks are disabled; set webhooks.secret to enable them".to_string());
+		}
+		let parsed = reqwest::Url::parse(url).map_err(|e| format!("callback_url {url:?}: {e}"))?;
+		if !matches!(parsed.scheme(), "http" | "https") {
+			return Err(format!("callback_url must be an http(s) URL (got {url:?})"));
+		}
+		self.callbacks
+			.check_url(&parsed)
+			.map_err(|e| format!("callback_url {url:?} is refused: {e}"))
+	}
+
+	/// Deliver the callback for `job`, which has finished, in the background.
+	pub fn notify(self: &Arc<Self>, url: String, job: &JobView, request_id: Option<String>) {
+		let payload = json!({
+			"event": "job.finished",
+			"job_id": job.job_id,
+			"tool_name": job.tool_name,
+			"state": job.state,
+			"status": job.response.as_ref().map_or("cancelled", |r| r.status.as_str()),
+			"created_at_ms": job.created_at_ms,
+			"finished_at_ms": job.finished_at_ms,
+			"job_url": self.job_url(&job.job_id),
+		});
+		self.send(url, &job.job_id, request_id, payload);
+	}
+
+	/// Tell approvers that `job` waits for one: a signed
+	/// `job.approval_requested` payload to `url`.
+	pub fn request_approval(self: &Arc<Self>, url: String, job: &JobView, request_id: Option<String>) {
+		let approval = job.approval.as_ref();
+		let payload = json!({
+			"event": "job.approval_requested",
+			"job_id": job.job_id,
+			"tool_name": job.tool_name,
+			"state": job.state,
+			"requested_by": approval.and_then(|a| a.requested_by.as_deref()),
+			"created_at_ms": job.created_at_ms,
+			"expires_at_ms": approval.map(|a| a.expires_at_ms),
+			"job_url": self.job_url(&job.job_id),
+			"approve_url": format!("{}/approve", self.job_url(&job.job_id)),
+		});
+		self.send(url, &job.job_id, request_id, payload);
+	}
+
+	/// The same, as a message for a Slack incoming webhook at `url`.
+	pub fn request_approval_on_slack(self: &Arc<Self>, url: String, job: &JobView, request_id: Option<String>) {
+		let who = job
+			.approval
+			.as_ref()
+			.and_then(|a| a.requested_by.as_deref())
+			.unwrap_or("an anonymous caller");
+		let minutes = job
+			.approval
+			.as_ref()
+			.map_or(0, |a| a.expires_at_ms.saturating_sub(job.created_at_ms) / 60_000);
+		let text = format!(
+			"{who} wants to run `{}` (job `{}`), which needs approval. Approve it within {minutes} min with \
+			`POST {}/approve`, or reject it with `POST {}/reject`.",
+			job.tool_name,
+			job.job_id,
+			self.job_url(&job.job_id),
+			self.job_url(&job.job_id),
+		);
+		self.send(url, &job.job_id, request_id, json!({ "text": text }));
+	}
+
+	fn job_url(&self, job_id: &str) -> String {
+		let base_url = self.config.base_url.as_deref().unwrap_or_default().trim_end_matches('/');
+		format!("{base_url}/api/v1/jobs/{job_id}")
+	}
+
+	fn send(self: &Arc<Self>, url: String, job_id: &str, request_id: Option<String>, payload: Value) {
+		let delivery = Delivery {
+			id: uuid::Uuid::new_v4().to_string(),
+			url,
+			job_id: job_id.to_string(),
+			request_id,
+			body: payload.to_string(),
+		};
+		let webhooks = self.clone();
+		self.deliveries.spawn(async move { webhooks.deliver(delivery).await });
+	}
+
+	/// Stop retrying, dead-letter what is still pending and wait for the
+	/// attempts in progress.
+	pub async fn close(&self) {
+		self.shutdown.cancel();
+		self.deliveries.close();
+		self.deliveries.wait().await;
+	}
+
+	async fn deliver(&self, delivery: Delivery) {
+		let mut attempt = 1;
+		loop {
+			let error = match self.attempt(&delivery).await {
+				Ok(()) => {
+					METRICS.webhook_deliveries.with_label_values(&["delivered"]).inc();
+					info!(
+						job_id = delivery.job_id,
+						delivery_id = delivery.id,
+						attempts = attempt,
+						message = "Job callback delivered"
+					);
+					return;
+				}
+				Err(Failure::Final(e)) => e,
+				Err(Failure::Transient(e)) if attempt < self.retry.max_attempts => {
+					METRICS.webhook_deliveries.with_label_values(&["retried"]).inc();
+					tokio::select! {
+						_ = tokio::time::sleep(self.retry.backoff(attempt)) => {}
+						_ = self.shutdown.cancelled() => {
+							self.dead_letter(&delivery, attempt, &format!("{e}; not retried, the server shut down"));
+							return;
+						}
+					}
+					attempt += 1;
+					continue;
+				}
+				Err(Failure::Transient(e)) => e,
+			};
+			self.dead_letter(&delivery, attempt, &error);
+			return;
+		}
+	}
+
+	async fn attempt(&self, delivery: &Delivery) -> Result<(), Failure> {
+		// Names are checked as they resolve; addresses in the URL here.
+		let url = reqwest::Url::parse(&delivery.url).map_err(|e| Failure::Final(e.to_string()))?;
+		self.destinations
+			.check_url(&url)
+			.map_err(|e| Failure::Final(format!("blocked by the sandbox: {e}")))?;
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		let secret = self.config.secret.as_deref().unwrap_or_default();
+		let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+		mac.update(format!("{timestamp}.{}", delivery.body).as_bytes());
+		let signature: String = mac.finalize().into_bytes().iter().map(|b| format!("{b:02x}")).collect();
+		let mut request = self
+			.http
+			.post(url)
+			.header(reqwest::header::CONTENT_TYPE, "application/json")
+			.header(SIGNATURE_HEADER, format!("sha256={signature}"))
+			.header(TIMESTAMP_HEADER, timestamp.to_string())
+			.header(DELIVERY_HEADER, &delivery.id);
+		if let Some(request_id) = &delivery.request_id {
+			request = request.header(request_id::HEADER, request_id);
+		}
+		let response = request
+			.body(delivery.body.clone())
+			.send()
+			.await
+			.map_err(|e| match blocked(&e) {
+				Some(reason) => Failure::Final(format!("blocked by the sandbox: {reason}")),
+				None => Failure::Transient(e.without_url().to_string()),
+			})?;
+		let status = response.status();
+		if status.is_success() {
+			Ok(())
+		} else if status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429 {
+			Err(Failure::Transient(format!("answered {status}")))
+		} else {
+			Err(Failure::Final(format!("answered {status}")))
+		}
+	}
+
+	/// Record a delivery that has been given up on. The URL's query is kept
+	/// out of the server log, since it may carry a token.
+	fn dead_letter(&self, delivery: &Delivery, attempts: u32, error: &str) {
+		METRICS.webhook_deliveries.with_label_values(&["failed"]).inc();
+		warn!(
+			job_id = delivery.job_id,
+			delivery_id = delivery.id,
+			host = reqwest::Url::parse(&delivery.url).ok().and_then(|u| u.host_str().map(str::to_string)),
+			attempts = attempts,
+			error = error,
+			message = "Job callback could not be delivered"
+		);
+		let Some(file) = &self.dead_letters else {
+			return;
+		};
+		let entry = json!({
+			"timestamp_ms": now_ms(),
+			"delivery_id": delivery.id,
+			"job_id": delivery.job_id,
+			"url": delivery.url,
+			"attempts": attempts,
+			"error": error,
+			"payload": serde_json::from_str::<Value>(&delivery.body).unwrap_or_default(),
+		});
+		let mut file = file.lock().unwrap();
+		if let Err(e) = writeln!(file, "{entry}") {
+			warn!(error = %e, message = "Failed to write to the webhook dead-letter file");
+		}
+	}
+}
+
+struct Delivery {
+	id: String,
+	url: String,
+	job_id: String,
+	request_id: Option<String>,
+	body: String,
+}
+
+enum Failure {
+	/// Worth another attempt.
+	Transient(String),
+	Final(String),
+}
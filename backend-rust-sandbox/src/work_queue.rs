@@ -0,0 +1,779 @@
+use redis::aio::{ConnectionManager, MultiplexedConnection};
+use redis::streams::{
+	StreamClaimOptions, StreamClaimReply, StreamId, StreamMaxlen, StreamPendingCountReply, StreamPendingReply,
+	StreamReadOptions, StreamReadReply,
+};
+use redis::{AsyncCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::audit::Caller;
+use crate::clock::now_ms;
+use crate::metrics::METRICS;
+use crate::retry::RetryOverride;
+use crate::scheduler::Priority;
+use crate::tool::Base64;
+use crate::tool_executor::{ExecutionContext, Executor, ToolResult};
+use crate::workspace::ToolInput;
+
+const DEFAULT_KEY_PREFIX: &str = "pagi-sandbox:queue";
+const DEFAULT_VISIBILITY_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_HEARTBEAT_MS: u64 = 5_000;
+const DEFAULT_MAX_DELIVERIES: u32 = 3;
+const DEFAULT_MAX_WAIT_MS: u64 = 60_000;
+/// The consumer group every worker reads in.
+const GROUP: &str = "workers";
+/// Dead-lettered executions kept, roughly.
+const DEAD_LETTER_MAXLEN: usize = 1000;
+/// Pending entries looked at per heartbeat for ones to take over.
+const RECLAIM_BATCH: usize = 100;
+/// Heartbeats a worker may miss before it is listed as not alive.
+const MISSED_HEARTBEATS: u64 = 3;
+/// Workers silent for longer are dropped from the list.
+const FORGET_WORKER_AFTER: Duration = Duration::from_secs(3600);
+/// Pause after a Redis error before the worker tries again.
+const RETRY_PAUSE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+	/// `redis://` or `rediss://` server holding the queue.
+	pub redis_url: Option<String>,
+	/// Send executions received here to the queue instead of running them.
+	pub dispatch: bool,
+	/// Take executions from the queue and run them here.
+	pub worker: bool,
+	/// Prefix of the queue's Redis keys; instances with the same prefix share
+	/// one queue.
+	pub key_prefix: String,
+	/// How long an execution a worker took may go unrenewed before another
+	/// worker takes it over.
+	pub visibility_timeout_ms: u64,
+	/// How often workers report in and renew the executions they run.
+	pub heartbeat_ms: u64,
+	/// Runs an execution gets, counting those cut short by a lost worker,
+	/// before it is dead-lettered.
+	pub max_deliveries: u32,
+	/// How long a dispatched execution may wait for a worker's result beyond
+	/// its own timeout.
+	pub max_wait_ms: u64,
+}
+
+impl Default for QueueConfig {
+	fn default() -> Self {
+		Self {
+			redis_url: None,
+			dispatch: false,
+			worker: false,
+			key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+			visibility_timeout_ms: DEFAULT_VISIBILITY_TIMEOUT_MS,
+			heartbeat_ms: DEFAULT_HEARTBEAT_MS,
+			max_deliveries: DEFAULT_MAX_DELIVERIES,
+			max_wait_ms: DEFAULT_MAX_WAIT_MS,
+		}
+	}
+}
+
+impl QueueConfig {
+	pub fn validate(&self) -> Vec<String> {
+		let mut errors = Vec::new();
+		match &self.redis_url {
+			Some(url) if !url.starts_with("redis://") && !url.starts_with("rediss://") => {
+				errors.push("queue.redis_url must be a redis:// or rediss:// URL".to_string());
+			}
+			None if self.dispatch || self.worker => {
+				errors.push("queue.dispatch and queue.worker need queue.redis_url".to_string());
+			}
+			_ => {}
+		}
+		if self.key_prefix.is_empty() {
+			errors.push("queue.key_prefix must not be empty".to_string());
+		}
+		if self.heartbeat_ms == 0 {
+			errors.push("queue.heartbeat_ms must be positive".to_string());
+		}
+		// Otherwise executions still running would be taken over.
+		if self.visibility_timeout_ms <= self.heartbeat_ms {
+			errors.push(format!(
+				"queue.visibility_timeout_ms ({}) must exceed queue.heartbeat_ms ({})",
+				self.visibility_timeout_ms, self.heartbeat_ms
+			));
+		}
+		if self.max_deliveries == 0 {
+			errors.push("queue.max_deliveries must be positive".to_string());
+		}
+		if self.max_wait_ms == 0 {
+			errors.push("queue.max_wait_ms must be positive".to_string());
+		}
+		errors
+	}
+}
+
+/// One worker as it last reported in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerStatus {
+	pub worker_id: String,
+	pub host: String,
+	pub started_at_ms: u64,
+	pub last_seen_ms: u64,
+	/// Executions from the queue running on it.
+	pub running: usize,
+	/// Executions from the queue it runs at once.
+	pub capacity: usize,
+	/// Whether it reported in within the last few heartbeats.
+	#[serde(default)]
+	pub alive: bool,
+}
+
+/// The queue as `GET /api/v1/admin/queue` shows it.
+#[derive(Debug, Serialize)]
+pub struct QueueStatus {
+	/// Executions no worker has taken yet.
+	pub waiting: u64,
+	/// Executions taken by a worker and not finished.
+	pub pending: u64,
+	/// By worker ID.
+	pub workers: Vec<WorkerStatus>,
+}
+
+/// One execution as it travels through the queue: what the dispatching
+/// instance admitted, minus what only makes sense there.
+#[derive(Debug, Serialize, Deserialize)]
+struct QueuedExecution {
+	execution_id: String,
+	tool_name: String,
+	args: Value,
+	timeout_ms: u64,
+	priority: Priority,
+	subject: Option<String>,
+	tenant: Option<String>,
+	client_ip: Option<IpAddr>,
+	#[serde(default)]
+	request_id: Option<String>,
+	job_id: Option<String>,
+	bypass_cache: bool,
+	artifacts: Vec<String>,
+	stdin: Option<Base64>,
+	input_files: BTreeMap<String, Base64>,
+	retry: Option<RetryOverride>,
+	#[serde(default)]
+	replay_of: Option<String>,
+	#[serde(default)]
+	approved_by: Option<String>,
+	enqueued_at_ms: u64,
+}
+
+impl QueuedExecution {
+	fn new(tool_name: &str, args: &Value, ctx: &ExecutionContext) -> Self {
+		Self {
+			execution_id: ctx.workspace_id.clone(),
+			tool_name: tool_name.to_string(),
+			args: args.clone(),
+			timeout_ms: ctx.timeout.as_millis() as u64,
+			priority: ctx.priority,
+			subject: ctx.caller.subject.clone(),
+			tenant: ctx.caller.tenant.clone(),
+			client_ip: ctx.caller.client_ip,
+			request_id: ctx.caller.request_id.clone(),
+			job_id: ctx.job_id.clone(),
+			bypass_cache: ctx.bypass_cache,
+			artifacts: ctx.artifacts.clone(),
+			stdin: ctx.input.stdin.as_ref().map(|b| Base64(b.to_vec())),
+			input_files: ctx
+				.input
+				.files
+				.iter()
+				.map(|(path, data)| (path.clone(), Base64(data.clone())))
+				.collect(),
+			retry: ctx.retry.clone(),
+			replay_of: ctx.replay_of.clone(),
+			approved_by: ctx.approved_by.clone(),
+			enqueued_at_ms: now_ms(),
+		}
+	}
+
+	/// `ctx`, fresh from this worker's executor, set up as the dispatching
+	/// instance had it.
+	fn context(&self, ctx: ExecutionContext) -> ExecutionContext {
+		let caller = Caller {
+			subject: self.subject.clone(),
+			tenant: self.tenant.clone(),
+			client_ip: self.client_ip,
+			transport: "queue",
+			request_id: self.request_id.clone(),
+		};
+		let input = ToolInput {
+			stdin: self.stdin.as_ref().map(|b| b.0.as_slice().into()),
+			files: self
+				.input_files
+				.iter()
+				.map(|(path, data)| (path.clone(), data.0.clone()))
+				.collect(),
+		};
+		let ctx = ctx
+			.with_caller(caller)
+			.with_priority_at_most(self.priority)
+			.with_artifacts(self.artifacts.clone())
+			.with_input(input)
+			.with_retry(self.retry.clone())
+			.with_cache_bypass(self.bypass_cache)
+			.taken_from_queue();
+		let ctx = match &self.replay_of {
+			Some(execution_id) => ctx.with_replay_of(execution_id.clone()),
+			None => ctx,
+		};
+		let ctx = match &self.approved_by {
+			Some(approver) => ctx.with_approval(approver.clone()),
+			None => ctx,
+		};
+		match &self.job_id {
+			Some(job_id) => ctx.with_job(job_id.clone()),
+			None => ctx,
+		}
+	}
+}
+
+/// A work queue on a Redis stream that sandbox instances share.
+///
+/// Dispatching instances add the executions they admit to the stream and
+/// wait for the result on a list of its own; workers read the stream in one
+/// consumer group and run what they read. Delivery is at least once: an
+/// execution is acknowledged only after its result is published, and one
+/// whose worker stops renewing it (crashed, cut off, or shut down mid-run)
+/// is taken over by another worker once the visibility timeout has passed,
+/// until it has been delivered `max_deliveries` times and is dead-lettered.
+pub struct WorkQueue {
+	config: QueueConfig,
+	client: redis::Client,
+	connection: ConnectionManager,
+	worker_id: String,
+	host: String,
+	/// The worker's heartbeat, which signs it off when it ends.
+	heartbeat: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl WorkQueue {
+	/// Connect when this instance dispatches or works; `None` otherwise.
+	pub async fn connect(config: &QueueConfig) -> Result<Option<Arc<Self>>, String> {
+		let Some(url) = config.redis_url.as_ref().filter(|_| config.dispatch || config.worker) else {
+			return Ok(None);
+		};
+		let client = redis::Client::open(url.as_str()).map_err(|e| format!("queue.redis_url: {e}"))?;
+		let mut connection = client
+			.get_connection_manager()
+			.await
+			.map_err(|e| format!("queue.redis_url: {e}"))?;
+		let host = hostname();
+		let queue = Self {
+			config: config.clone(),
+			client,
+			connection: connection.clone(),
+			worker_id: format!("{host}-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]),
+			host,
+			heartbeat: Mutex::new(None),
+		};
+		// Whichever instance comes first creates the group, so nothing
+		// dispatched before the first worker starts is missed.
+		let created: RedisResult<()> = connection.xgroup_create_mkstream(queue.stream(), GROUP, "0").await;
+		if let Err(e) = created {
+			if e.code() != Some("BUSYGROUP") {
+				return Err(format!("queue.redis_url: {e}"));
+			}
+		}
+		info!(
+			dispatch = config.dispatch,
+			worker = config.worker,
+			worker_id = %queue.worker_id,
+			message = "Work queue ready"
+		);
+		Ok(Some(Arc::new(queue)))
+	}
+
+	fn stream(&self) -> String {
+		format!("{}:executions", self.config.key_prefix)
+	}
+
+	fn results_key(&self, execution_id: &str) -> String {
+		format!("{}:results:{execution_id}", self.config.key_prefix)
+	}
+
+	fn cancel_key(&self, execution_id: &str) -> String {
+		format!("{}:cancel:{execution_id}", self.config.key_prefix)
+	}
+
+	fn workers_key(&self) -> String {
+		format!("{}:workers", self.config.key_prefix)
+	}
+
+	fn dead_letter_key(&self) -> String {
+		format!("{}:dead", self.config.key_prefix)
+	}
+
+	/// Whether `ctx` goes to the queue. Live output, live stdin, retained
+	/// workspaces and traces tie an execution to the instance it arrived on,
+	/// and what a worker took from the queue runs where it is.
+	pub fn accepts(&self, ctx: &ExecutionContext) -> bool {
+		self.config.dispatch
+			&& !ctx.from_queue
+			&& ctx.output.is_none()
+			&& ctx.stdin_stream.is_none()
+			&& !ctx.retain_workspace
+			&& ctx.trace.is_none()
+	}
+
+	/// Queue one execution and wait for a worker's result. `Err` holds a
+	/// result made up here because none came back.
+	pub async fn dispatch(&self, tool_name: &str, args: &Value, ctx: &ExecutionContext) -> Result<ToolResult, ToolResult> {
+		let execution = QueuedExecution::new(tool_name, args, ctx);
+		let body = serde_json::to_string(&execution).map_err(|e| queue_error(format!("cannot queue the execution: {e}")))?;
+		// Its own connection: BLPOP would hold up every other command on a
+		// shared one.
+		let mut waiting = self
+			.client
+			.get_multiplexed_tokio_connection()
+			.await
+			.map_err(|e| queue_error(format!("work queue unavailable: {e}")))?;
+		let mut connection = self.connection.clone();
+		let added: RedisResult<String> = connection.xadd(self.stream(), "*", &[("execution", body)]).await;
+		added.map_err(|e| queue_error(format!("work queue unavailable: {e}")))?;
+		METRICS.queue_messages.with_label_values(&["dispatched"]).inc();
+
+		let wait = ctx.timeout + Duration::from_millis(self.config.max_wait_ms);
+		let popped: RedisResult<Option<(String, String)>> = tokio::select! {
+			popped = waiting.blpop(self.results_key(&execution.execution_id), wait.as_secs_f64()) => popped,
+			_ = ctx.cancel.cancelled() => {
+				self.cancel(&execution.execution_id, wait).await;
+				return Ok(ctx.check_cancelled().expect_err("cancelled above"));
+			}
+		};
+		match popped {
+			Ok(Some((_, body))) => {
+				serde_json::from_str(&body).map_err(|e| queue_error(format!("unreadable result from the work queue: {e}")))
+			}
+			Ok(None) => {
+				// Whoever has it, or gets it later, can stop.
+				self.cancel(&execution.execution_id, wait).await;
+				METRICS.queue_messages.with_label_values(&["timed_out"]).inc();
+				Err(ToolResult {
+					status: "queue_timeout".to_string(),
+					stderr: format!("no worker finished the execution within {}s", wait.as_secs()),
+					..Default::default()
+				})
+			}
+			Err(e) => Err(queue_error(format!("lost the work queue while waiting: {e}"))),
+		}
+	}
+
+	/// Tell the worker running execution `execution_id`, or the one that
+	/// takes it later, to give up on it.
+	async fn cancel(&self, execution_id: &str, ttl: Duration) {
+		let mut connection = self.connection.clone();
+		let ttl_ms = (ttl + Duration::from_millis(self.config.visibility_timeout_ms)).as_millis() as u64;
+		let set: RedisResult<()> = connection.pset_ex(self.cancel_key(execution_id), 1, ttl_ms).await;
+		if let Err(e) = set {
+			warn!(execution_id = execution_id, error = %e, message = "Failed to cancel a queued execution");
+		}
+	}
+
+	/// Publish an execution's result, if it has one, and drop its entry from
+	/// the stream. The result goes first: an entry acknowledged without one
+	/// would be lost.
+	async fn finish(&self, entry_id: &str, result: Option<(&str, &ToolResult)>) {
+		let mut pipe = redis::pipe();
+		pipe.atomic();
+		if let Some((execution_id, result)) = result {
+			let key = self.results_key(execution_id);
+			let body = serde_json::to_string(result).expect("results serialize");
+			pipe.lpush(&key, body).ignore();
+			pipe.pexpire(&key, self.config.max_wait_ms as i64).ignore();
+		}
+		pipe.xack(self.stream(), GROUP, &[entry_id]).ignore();
+		pipe.xdel(self.stream(), &[entry_id]).ignore();
+		let mut connection = self.connection.clone();
+		if let Err(e) = pipe.query_async::<()>(&mut connection).await {
+			warn!(entry_id = entry_id, error = %e, message = "Failed to publish a queued execution's result");
+		}
+	}
+
+	/// Executions no worker has taken yet.
+	pub async fn waiting(&self) -> Result<u64, String> {
+		let mut connection = self.connection.clone();
+		let length: u64 = connection.xlen(self.stream()).await.map_err(|e| e.to_string())?;
+		let pending: StreamPendingReply = connection.xpending(self.stream(), GROUP).await.map_err(|e| e.to_string())?;
+		Ok(length.saturating_sub(pending.count() as u64))
+	}
+
+	/// What waits in the queue and which workers serve it.
+	pub async fn status(&self) -> Result<QueueStatus, String> {
+		let mut connection = self.connection.clone();
+		let length: u64 = connection.xlen(self.stream()).await.map_err(|e| e.to_string())?;
+		let pending: StreamPendingReply = connection.xpending(self.stream(), GROUP).await.map_err(|e| e.to_string())?;
+		let reported: HashMap<String, String> = connection.hgetall(self.workers_key()).await.map_err(|e| e.to_string())?;
+		let now_ms = now_ms();
+		let alive_since = now_ms.saturating_sub(MISSED_HEARTBEATS * self.config.heartbeat_ms);
+		let forget_before = now_ms.saturating_sub(FORGET_WORKER_AFTER.as_millis() as u64);
+		let mut workers = Vec::new();
+		let mut forgotten = Vec::new();
+		for (worker_id, body) in reported {
+			match serde_json::from_str::<WorkerStatus>(&body) {
+				Ok(status) if status.last_seen_ms >= forget_before => workers.push(WorkerStatus {
+					alive: status.last_seen_ms >= alive_since,
+					..status
+				}),
+				_ => forgotten.push(worker_id),
+			}
+		}
+		if !forgotten.is_empty() {
+			let removed: RedisResult<()> = connection.hdel(self.workers_key(), &forgotten).await;
+			if let Err(e) = removed {
+				warn!(error = %e, message = "Failed to drop silent workers from the list");
+			}
+		}
+		workers.sort_by(|a, b| a.worker_id.cmp(&b.worker_id));
+		let pending = pending.count() as u64;
+		Ok(QueueStatus {
+			waiting: length.saturating_sub(pending),
+			pending,
+			workers,
+		})
+	}
+
+	/// Run executions from the queue on `executor`, at most `capacity` at a
+	/// time, until `shutdown`. Does nothing unless this instance is a worker.
+	pub fn spawn_worker(self: &Arc<Self>, executor: Arc<Executor>, capacity: usize, shutdown: CancellationToken) {
+		if !self.config.worker {
+			return;
+		}
+		let worker = Arc::new(Worker {
+			queue: self.clone(),
+			executor,
+			capacity,
+			slots: Arc::new(Semaphore::new(capacity)),
+			running: Mutex::new(HashMap::new()),
+			started_at_ms: now_ms(),
+			shutdown,
+		});
+		*self.heartbeat.lock().unwrap() = Some(tokio::spawn(worker.clone().heartbeat()));
+		tokio::spawn(worker.take_new());
+	}
+
+	/// Wait for the worker, once shut down, to finish what it runs and sign
+	/// off.
+	pub async fn close(&self) {
+		let heartbeat = self.heartbeat.lock().unwrap().take();
+		if let Some(heartbeat) = heartbeat {
+			let _ = heartbeat.await;
+		}
+	}
+}
+
+/// An execution running on this worker.
+struct Running {
+	execution_id: String,
+	cancel: CancellationToken,
+}
+
+struct Worker {
+	queue: Arc<WorkQueue>,
+	executor: Arc<Executor>,
+	capacity: usize,
+	/// One permit per execution this worker may run.
+	slots: Arc<Semaphore>,
+	/// By stream entry ID.
+	running: Mutex<HashMap<String, Running>>,
+	started_at_ms: u64,
+	shutdown: CancellationToken,
+}
+
+impl Worker {
+	/// Read new entries from the stream whenever a slot is free.
+	async fn take_new(self: Arc<Self>) {
+		let stream = self.queue.stream();
+		let options = StreamReadOptions::default()
+			.group(GROUP, &self.queue.worker_id)
+			.count(1)
+			.block(self.queue.config.heartbeat_ms as usize);
+		// Its own connection, which XREADGROUP blocks.
+		let mut connection: Option<MultiplexedConnection> = None;
+		loop {
+			let permit = tokio::select! {
+				permit = self.slots.clone().acquire_owned() => permit.expect("the semaphore is never closed"),
+				_ = self.shutdown.cancelled() => break,
+			};
+			// A draining worker leaves new executions to the others.
+			if self.executor.draining() {
+				drop(permit);
+				tokio::select! {
+					_ = tokio::time::sleep(RETRY_PAUSE) => continue,
+					_ = self.shutdown.cancelled() => break,
+				}
+			}
+			let read = async {
+				if connection.is_none() {
+					connection = Some(self.queue.client.get_multiplexed_tokio_connection().await?);
+				}
+				let connection = connection.as_mut().expect("connected above");
+				connection
+					.xread_options::<_, _, Option<StreamReadReply>>(&[&stream], &[">"], &options)
+					.await
+			};
+			// An entry read as shutdown cancels this stays pending, and is
+			// taken over like any other.
+			let read = tokio::select! {
+				read = read => read,
+				_ = self.shutdown.cancelled() => break,
+			};
+			match read {
+				Ok(reply) => {
+					let entry = reply.into_iter().flat_map(|r| r.keys).flat_map(|k| k.ids).next();
+					if let Some(entry) = entry {
+						tokio::spawn(self.clone().run(entry, permit));
+					}
+				}
+				Err(e) => {
+					warn!(error = %e, message = "Failed to read the work queue");
+					connection = None;
+					tokio::select! {
+						_ = tokio::time::sleep(RETRY_PAUSE) => {}
+						_ = self.shutdown.cancelled() => break,
+					}
+				}
+			}
+		}
+		info!(worker_id = %self.queue.worker_id, message = "Stopped taking executions from the work queue");
+	}
+
+	/// Run one entry, holding `_permit` until it is done.
+	async fn run(self: Arc<Self>, entry: StreamId, _permit: OwnedSemaphorePermit) {
+		let execution = entry
+			.get::<String>("execution")
+			.and_then(|body| serde_json::from_str::<QueuedExecution>(&body).ok());
+		let Some(execution) = execution else {
+			warn!(entry_id = %entry.id, message = "Dropping an unreadable work queue entry");
+			self.queue.finish(&entry.id, None).await;
+			return;
+		};
+		let mut connection = self.queue.connection.clone();
+		let cancelled: bool = connection
+			.exists(self.queue.cancel_key(&execution.execution_id))
+			.await
+			.unwrap_or(false);
+		if cancelled {
+			let result = ToolResult {
+				status: "cancelled".to_string(),
+				stderr: "execution cancelled".to_string(),
+				..Default::default()
+			};
+			self.queue.finish(&entry.id, Some((&execution.execution_id, &result))).await;
+			return;
+		}
+		let ctx = match self.executor.context(&execution.tool_name, Some(execution.timeout_ms)) {
+			Ok(ctx) => execution.context(ctx),
+			Err(e) => {
+				// Left pending, for whichever worker has room once the
+				// visibility timeout has passed.
+				warn!(entry_id = %entry.id, error = %e.message(), message = "No room for a queued execution");
+				return;
+			}
+		};
+		self.running.lock().unwrap().insert(
+			entry.id.clone(),
+			Running {
+				execution_id: execution.execution_id.clone(),
+				cancel: ctx.cancel.clone(),
+			},
+		);
+		let result = self.executor.execute_tool(&execution.tool_name, execution.args.clone(), &ctx).await;
+		self.running.lock().unwrap().remove(&entry.id);
+		if result.status == "aborted" {
+			// Cut short by shutdown; another worker runs it again.
+			return;
+		}
+		self.queue.finish(&entry.id, Some((&execution.execution_id, &result))).await;
+		METRICS.queue_messages.with_label_values(&["completed"]).inc();
+	}
+
+	/// Report in and renew the executions running here every heartbeat,
+	/// until shutdown and the last of them has finished.
+	async fn heartbeat(self: Arc<Self>) {
+		let mut interval = tokio::time::interval(Duration::from_millis(self.queue.config.heartbeat_ms));
+		loop {
+			let stopping = self.shutdown.is_cancelled();
+			if stopping && self.running.lock().unwrap().is_empty() {
+				break;
+			}
+			if stopping {
+				interval.tick().await;
+			} else {
+				tokio::select! {
+					_ = interval.tick() => {}
+					_ = self.shutdown.cancelled() => continue,
+				}
+			}
+			if let Err(e) = self.beat().await {
+				warn!(error = %e, message = "Work queue heartbeat failed");
+			}
+		}
+		let mut connection = self.queue.connection.clone();
+		let removed: RedisResult<()> = connection.hdel(self.queue.workers_key(), &self.queue.worker_id).await;
+		if let Err(e) = removed {
+			warn!(error = %e, message = "Failed to sign off from the work queue");
+		}
+	}
+
+	async fn beat(self: &Arc<Self>) -> RedisResult<()> {
+		let mut connection = self.queue.connection.clone();
+		let running: Vec<(String, String)> = self
+			.running
+			.lock()
+			.unwrap()
+			.iter()
+			.map(|(entry_id, r)| (entry_id.clone(), r.execution_id.clone()))
+			.collect();
+		if !running.is_empty() {
+			// Claiming an entry again resets its idle time, which is what
+			// the visibility timeout measures.
+			let entry_ids: Vec<&str> = running.iter().map(|(entry_id, _)| entry_id.as_str()).collect();
+			let _: Vec<String> = connection
+				.xclaim_options(
+					self.queue.stream(),
+					GROUP,
+					&self.queue.worker_id,
+					0,
+					&entry_ids,
+					StreamClaimOptions::default().with_justid(),
+				)
+				.await?;
+			let cancel_keys: Vec<String> = running.iter().map(|(_, id)| self.queue.cancel_key(id)).collect();
+			let cancelled: Vec<Option<String>> = connection.mget(&cancel_keys).await?;
+			let live = self.running.lock().unwrap();
+			for (entry_id, cancelled) in entry_ids.iter().zip(cancelled) {
+				if let (Some(r), Some(_)) = (live.get(*entry_id), cancelled) {
+					r.cancel.cancel();
+				}
+			}
+		}
+		let status = WorkerStatus {
+			worker_id: self.queue.worker_id.clone(),
+			host: self.queue.host.clone(),
+			started_at_ms: self.started_at_ms,
+			last_seen_ms: now_ms(),
+			running: running.len(),
+			capacity: self.capacity,
+			alive: true,
+		};
+		let body = serde_json::to_string(&status).expect("worker status serializes");
+		let _: () = connection.hset(self.queue.workers_key(), &self.queue.worker_id, body).await?;
+		if !self.shutdown.is_cancelled() {
+			self.reclaim(&mut connection).await?;
+		}
+		Ok(())
+	}
+
+	/// Take over entries whose worker stopped renewing them while there are
+	/// free slots, and dead-letter those delivered too often.
+	async fn reclaim(self: &Arc<Self>, connection: &mut ConnectionManager) -> RedisResult<()> {
+		if self.slots.available_permits() == 0 {
+			return Ok(());
+		}
+		let config = &self.queue.config;
+		let pending: StreamPendingCountReply = connection
+			.xpending_count(self.queue.stream(), GROUP, "-", "+", RECLAIM_BATCH)
+			.await?;
+		let stale = pending
+			.ids
+			.into_iter()
+			.filter(|p| p.last_delivered_ms as u64 >= config.visibility_timeout_ms);
+		for stale in stale {
+			let dead = stale.times_delivered as u64 >= config.max_deliveries as u64;
+			let permit = match self.slots.clone().try_acquire_owned() {
+				Ok(permit) => permit,
+				Err(_) if dead => continue,
+				Err(_) => break,
+			};
+			// Claiming again with the same minimum idle time means another
+			// worker that got there first keeps it.
+			let claimed: StreamClaimReply = connection
+				.xclaim(
+					self.queue.stream(),
+					GROUP,
+					&self.queue.worker_id,
+					config.visibility_timeout_ms,
+					&[&stale.id],
+				)
+				.await?;
+			let Some(entry) = claimed.ids.into_iter().next() else {
+				continue;
+			};
+			if dead {
+				self.dead_letter(connection, entry, stale.times_delivered).await?;
+				continue;
+			}
+			warn!(
+				entry_id = %entry.id,
+				previous_worker = %stale.consumer,
+				deliveries = stale.times_delivered,
+				message = "Taking over a queued execution its worker stopped renewing"
+			);
+			METRICS.queue_messages.with_label_values(&["reclaimed"]).inc();
+			tokio::spawn(self.clone().run(entry, permit));
+		}
+		Ok(())
+	}
+
+	/// Move an entry to the dead-letter stream and tell its dispatcher.
+	async fn dead_letter(&self, connection: &mut ConnectionManager, entry: StreamId, deliveries: usize) -> RedisResult<()> {
+		warn!(
+			entry_id = %entry.id,
+			deliveries = deliveries,
+			message = "Dead-lettering a queued execution no worker finished"
+		);
+		METRICS.queue_messages.with_label_values(&["dead_lettered"]).inc();
+		let body = entry.get::<String>("execution").unwrap_or_default();
+		let mut pipe = redis::pipe();
+		pipe.atomic();
+		pipe.xadd_maxlen(
+			self.queue.dead_letter_key(),
+			StreamMaxlen::Approx(DEAD_LETTER_MAXLEN),
+			"*",
+			&[("execution", body.clone()), ("deliveries", deliveries.to_string())],
+		)
+		.ignore();
+		if let Ok(execution) = serde_json::from_str::<QueuedExecution>(&body) {
+			let result = queue_error(format!("gave up on the execution after {deliveries} deliveries"));
+			let key = self.queue.results_key(&execution.execution_id);
+			pipe.lpush(&key, serde_json::to_string(&result).expect("results serialize")).ignore();
+			pipe.pexpire(&key, self.queue.config.max_wait_ms as i64).ignore();
+		}
+		pipe.xack(self.queue.stream(), GROUP, &[&entry.id]).ignore();
+		pipe.xdel(self.queue.stream(), &[&entry.id]).ignore();
+		pipe.query_async(connection).await
+	}
+}
+
+fn queue_error(message: String) -> ToolResult {
+	METRICS.queue_messages.with_label_values(&["failed"]).inc();
+	ToolResult {
+		status: "queue_error".to_string(),
+		stderr: message,
+		..Default::default()
+	}
+}
+
+/// The machine's host name, as replicas are told apart by default.
+pub fn hostname() -> String {
+	std::fs::read_to_string("/proc/sys/kernel/hostname")
+		.ok()
+		.map(|name| name.trim().to_string())
+		.filter(|name| !name.is_empty())
+		.or_else(|| std::env::var("HOSTNAME").ok())
+		.unwrap_or_else(|| "unknown".to_string())
+}
@@ -0,0 +1,464 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::cgroup::IsolationMode;
+use crate::disk_quota::{DiskQuota, DiskQuotas};
+use crate::landlock::{self, Landlock};
+use crate::namespace::{self, check, Unshare};
+
+const DEFAULT_ROOT: &str = "sandbox_runs";
+const DEFAULT_RETAIN_SECS: u64 = 3600;
+const DEFAULT_DISK_QUOTA_MB: u64 = 1024;
+const DEFAULT_DISK_CHECK_INTERVAL_MS: u64 = 1000;
+/// Retained workspaces are looked for expiry at most this often.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceConfig {
+	/// Directory holding one scratch directory per execution.
+	pub root: PathBuf,
+	/// Run subprocess tools in a mount namespace where everything but their
+	/// workspace is read-only.
+	pub jail: IsolationMode,
+	/// Run subprocess tools in a PID namespace of their own, so that the
+	/// processes they start die and are reaped with them.
+	pub pid_namespace: IsolationMode,
+	/// Restrict native tools with Landlock to reading `landlock_read_paths`
+	/// and writing their workspace, with or without the jail.
+	pub landlock: IsolationMode,
+	pub landlock_read_paths: Vec<PathBuf>,
+	/// How long workspaces kept at the caller's request survive the run.
+	pub retain_secs: u64,
+	/// Disk space a workspace may take up, unless the manifest's
+	/// `limits.disk_mb` says otherwise; unlimited when unset.
+	pub disk_quota_mb: Option<u64>,
+	/// Enforce the quota with XFS project quotas on the root's filesystem;
+	/// without them it is only checked every `disk_check_interval_ms`.
+	pub xfs_quota: IsolationMode,
+	pub disk_check_interval_ms: u64,
+}
+
+impl Default for WorkspaceConfig {
+	fn default() -> Self {
+		Self {
+			root: DEFAULT_ROOT.into(),
+			jail: IsolationMode::Auto,
+			pid_namespace: IsolationMode::Auto,
+			landlock: IsolationMode::Auto,
+			landlock_read_paths: landlock::DEFAULT_READ_PATHS.iter().map(PathBuf::from).collect(),
+			retain_secs: DEFAULT_RETAIN_SECS,
+			disk_quota_mb: Some(DEFAULT_DISK_QUOTA_MB),
+			xfs_quota: IsolationMode::Auto,
+			disk_check_interval_ms: DEFAULT_DISK_CHECK_INTERVAL_MS,
+		}
+	}
+}
+
+/// Hands out per-execution scratch directories under one root.
+pub struct Workspaces {
+	root: PathBuf,
+	jail: bool,
+	pid_namespace: bool,
+	/// Rules every native tool starts from, when Landlock is used.
+	landlock: Option<Landlock>,
+	quotas: DiskQuotas,
+	disk_quota_mb: Option<u64>,
+	retain: Duration,
+	last_prune: Mutex<Instant>,
+}
+
+impl Workspaces {
+	pub fn new(config: &WorkspaceConfig) -> Result<Self, String> {
+		std::fs::create_dir_all(&config.root).map_err(|e| format!("{}: {e}", config.root.display()))?;
+		// Bind mounts and child working directories need an absolute path.
+		let root = config
+			.root
+			.canonicalize()
+			.map_err(|e| format!("{}: {e}", config.root.display()))?;
+
+		let jail = match config.jail {
+			IsolationMode::Off => false,
+			mode => match probe_jail(&root) {
+				Ok(()) => true,
+				Err(e) if mode == IsolationMode::Auto => {
+					warn!(error = %e, message = "Filesystem jail unavailable; subprocess tools can write outside their workspace");
+					false
+				}
+				Err(e) => return Err(format!("filesystem jail unavailable: {e}")),
+			},
+		};
+		let pid_namespace = match config.pid_namespace {
+			IsolationMode::Off => false,
+			mode => match probe_pid_namespace() {
+				Ok(()) => true,
+				Err(e) if mode == IsolationMode::Auto => {
+					warn!(error = %e, message = "PID namespaces unavailable; processes that leave a tool's process group outlive it");
+					false
+				}
+				Err(e) => return Err(format!("PID namespaces unavailable: {e}")),
+			},
+		};
+		let landlock = match config.landlock {
+			IsolationMode::Off => None,
+			mode => match landlock::supported_access() {
+				Ok(handled) => Some(Landlock::new(handled, &config.landlock_read_paths)),
+				Err(e) if mode == IsolationMode::Auto => {
+					warn!(error = %e, message = "Landlock unavailable; native tools can read and write outside their workspace");
+					None
+				}
+				Err(e) => return Err(format!("Landlock unavailable: {e}")),
+			},
+		};
+		let quotas = DiskQuotas::init(
+			&root,
+			config.xfs_quota,
+			Duration::from_millis(config.disk_check_interval_ms),
+		)?;
+		info!(
+			root = %root.display(),
+			jail = jail,
+			pid_namespace = pid_namespace,
+			landlock = landlock.is_some(),
+			xfs_quota = quotas.enforced(),
+			message = "Execution workspaces ready"
+		);
+
+		let workspaces = Self {
+			root,
+			jail,
+			pid_namespace,
+			landlock,
+			quotas,
+			disk_quota_mb: config.disk_quota_mb,
+			retain: Duration::from_secs(config.retain_secs),
+			last_prune: Mutex::new(Instant::now()),
+		};
+		workspaces.prune();
+		Ok(workspaces)
+	}
+
+	/// Create the (empty) workspace for execution `id`, capped at `disk_mb`
+	/// or the configured quota.
+	pub async fn create(&self, id: &str, disk_mb: Option<u64>) -> io::Result<Workspace> {
+		let due = {
+			let mut last = self.last_prune.lock().unwrap();
+			let due = last.elapsed() >= PRUNE_INTERVAL;
+			if due {
+				*last = Instant::now();
+			}
+			due
+		};
+		if due {
+			self.prune();
+		}
+
+		let path = self.root.join(id);
+		fs::create_dir(&path).await?;
+		let mut workspace = self.workspace(path);
+		if let Some(limit_mb) = disk_mb.or(self.disk_quota_mb) {
+			match self.quotas.apply(&workspace.path, limit_mb) {
+				Ok(quota) => workspace.quota = Some(quota),
+				Err(e) => {
+					workspace.finish(false).await;
+					return Err(io::Error::new(e.kind(), format!("setting the disk quota: {e}")));
+				}
+			}
+		}
+		Ok(workspace)
+	}
+
+	/// Execution `id`'s workspace as it was left, for a session process
+	/// restored into the one it was checkpointed in. Under XFS it keeps the
+	/// quota it was created with; otherwise it is no longer checked.
+	pub fn open(&self, id: &str) -> io::Result<Workspace> {
+		let path = self.root.join(id);
+		if !path.is_dir() {
+			return Err(io::Error::new(
+				io::ErrorKind::NotFound,
+				format!("{} is gone", path.display()),
+			));
+		}
+		Ok(self.workspace(path))
+	}
+
+	fn workspace(&self, path: PathBuf) -> Workspace {
+		Workspace {
+			jail: self.jail.then(|| Jail::new(&path)),
+			pid_namespace: self.pid_namespace,
+			landlock: self.landlock.as_ref().map(|landlock| landlock.with_writable(&path)),
+			quota: None,
+			path,
+		}
+	}
+
+	/// Delete execution `id`'s workspace, if it has one.
+	pub async fn remove(&self, id: &str) {
+		let path = self.root.join(id);
+		if let Err(e) = fs::remove_dir_all(&path).await {
+			if e.kind() != io::ErrorKind::NotFound {
+				warn!(workspace = %path.display(), error = %e, message = "Failed to remove workspace");
+			}
+		}
+	}
+
+	/// Whether native tools run under Landlock.
+	pub fn landlocked(&self) -> bool {
+		self.landlock.is_some()
+	}
+
+	/// Whether execution `id` left a workspace behind.
+	pub fn exists(&self, id: &str) -> bool {
+		self.root.join(id).is_dir()
+	}
+
+	/// Delete retained workspaces, and any left by a crash, once they expire.
+	fn prune(&self) {
+		let Ok(entries) = std::fs::read_dir(&self.root) else {
+			return;
+		};
+		let now = SystemTime::now();
+		for entry in entries.flatten() {
+			let expired = entry
+				.metadata()
+				.and_then(|m| m.modified())
+				.is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() >= self.retain);
+			if expired {
+				if let Err(e) = std::fs::remove_dir_all(entry.path()) {
+					warn!(workspace = %entry.path().display(), error = %e, message = "Failed to remove expired workspace");
+				}
+			}
+		}
+	}
+}
+
+/// What a request hands the tool besides its args: bytes for its stdin and
+/// files to place in its workspace before it starts.
+#[derive(Debug, Clone, Default)]
+pub struct ToolInput {
+	pub stdin: Option<Arc<[u8]>>,
+	/// Contents by path relative to the workspace.
+	pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl ToolInput {
+	pub fn is_empty(&self) -> bool {
+		self.stdin.is_none() && self.files.is_empty()
+	}
+
+	/// Hash of everything the tool will read, so that cached results of
+	/// different inputs are kept apart. `None` when there is no input.
+	pub fn sha256(&self) -> Option<String> {
+		if self.is_empty() {
+			return None;
+		}
+		let mut hasher = Sha256::new();
+		hasher.update([u8::from(self.stdin.is_some())]);
+		// Length-prefixed, so that no two inputs feed the hasher the same bytes.
+		let mut feed = |data: &[u8]| {
+			hasher.update((data.len() as u64).to_le_bytes());
+			hasher.update(data);
+		};
+		feed(self.stdin.as_deref().unwrap_or_default());
+		for (path, data) in &self.files {
+			feed(path.as_bytes());
+			feed(data);
+		}
+		Some(format!("{:x}", hasher.finalize()))
+	}
+}
+
+/// Whether `path` can name an input file: relative, and without `..` or
+/// anything else that could leave the workspace.
+pub fn is_valid_input_path(path: &str) -> bool {
+	!path.is_empty() && Path::new(path).components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Copy the directories and regular files under `from` into `to`. Symlinks
+/// are left out: a tool may have planted them to point anywhere on the host.
+pub fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+	for entry in std::fs::read_dir(from)? {
+		let entry = entry?;
+		let kind = entry.file_type()?;
+		let target = to.join(entry.file_name());
+		if kind.is_dir() {
+			std::fs::create_dir_all(&target)?;
+			copy_tree(&entry.path(), &target)?;
+		} else if kind.is_file() {
+			std::fs::copy(entry.path(), &target)?;
+		}
+	}
+	Ok(())
+}
+
+/// Remove everything inside `dir`, leaving it empty.
+pub fn empty_dir(dir: &Path) -> io::Result<()> {
+	for entry in std::fs::read_dir(dir)? {
+		let entry = entry?;
+		if entry.file_type()?.is_dir() {
+			std::fs::remove_dir_all(entry.path())?;
+		} else {
+			std::fs::remove_file(entry.path())?;
+		}
+	}
+	Ok(())
+}
+
+/// One execution's scratch directory. Tools run with it as their working
+/// directory, `/work` for WASM and container tools.
+#[derive(Debug)]
+pub struct Workspace {
+	pub path: PathBuf,
+	/// Set when subprocesses must be confined to the workspace.
+	pub jail: Option<Jail>,
+	/// Whether subprocesses run in a PID namespace of their own.
+	pub pid_namespace: bool,
+	/// Set when native tools must be confined to the workspace and the
+	/// readable paths.
+	pub landlock: Option<Landlock>,
+	/// The disk space the workspace may take up, when it is capped.
+	pub quota: Option<DiskQuota>,
+}
+
+impl Workspace {
+	/// Write the request's input files. Runs before the tool does, so nothing
+	/// in the workspace can be a symlink planted to redirect the writes.
+	pub async fn write_inputs(&self, input: &ToolInput) -> io::Result<()> {
+		for (relative, data) in &input.files {
+			let path = self.path.join(relative);
+			if let Some(parent) = path.parent() {
+				fs::create_dir_all(parent).await?;
+			}
+			fs::write(&path, data)
+				.await
+				.map_err(|e| io::Error::new(e.kind(), format!("{relative}: {e}")))?;
+		}
+		Ok(())
+	}
+
+	/// Delete the workspace, unless the caller asked to keep it; kept ones are
+	/// deleted once they expire.
+	pub async fn finish(self, retain: bool) {
+		if retain {
+			return;
+		}
+		if let Err(e) = fs::remove_dir_all(&self.path).await {
+			warn!(workspace = %self.path.display(), error = %e, message = "Failed to remove workspace");
+		}
+	}
+}
+
+/// A private mount namespace in which `/` is read-only and only the workspace
+/// is writable.
+#[derive(Debug, Clone)]
+pub struct Jail {
+	workspace: Arc<CString>,
+}
+
+impl Jail {
+	fn new(workspace: &Path) -> Self {
+		let workspace = CString::new(workspace.as_os_str().as_bytes()).expect("paths have no NUL bytes");
+		Self {
+			workspace: Arc::new(workspace),
+		}
+	}
+
+	/// Set up the mounts. Runs in the child between fork and exec, once it is
+	/// in a mount namespace of its own (`CLONE_NEWNS`).
+	pub fn enter(&self) -> io::Result<()> {
+		let workspace = self.workspace.as_c_str();
+		// SAFETY: plain syscalls on NUL-terminated strings that outlive them.
+		unsafe {
+			// Keep the mounts below from propagating back to the host.
+			check(libc::mount(
+				std::ptr::null(),
+				c"/".as_ptr(),
+				std::ptr::null(),
+				libc::MS_REC | libc::MS_PRIVATE,
+				std::ptr::null(),
+			))?;
+			check(libc::mount(
+				workspace.as_ptr(),
+				workspace.as_ptr(),
+				std::ptr::null(),
+				libc::MS_BIND | libc::MS_REC,
+				std::ptr::null(),
+			))?;
+			set_mount_attr(c"/", libc::MOUNT_ATTR_RDONLY, 0)?;
+			set_mount_attr(workspace, 0, libc::MOUNT_ATTR_RDONLY)?;
+			// The working directory still points into the read-only mount.
+			check(libc::chdir(workspace.as_ptr()))?;
+		}
+		Ok(())
+	}
+}
+
+/// Change the attributes of every mount at and below `path`.
+unsafe fn set_mount_attr(path: &CStr, set: u64, clear: u64) -> io::Result<()> {
+	let mut attr: libc::mount_attr = std::mem::zeroed();
+	attr.attr_set = set;
+	attr.attr_clr = clear;
+	let rc = libc::syscall(
+		libc::SYS_mount_setattr,
+		libc::AT_FDCWD,
+		path.as_ptr(),
+		libc::AT_RECURSIVE,
+		&attr as *const libc::mount_attr,
+		std::mem::size_of::<libc::mount_attr>(),
+	);
+	check(rc as libc::c_int)
+}
+
+/// Run `true` in a jail once, so a host without mount namespaces is found at
+/// startup rather than on the first execution.
+fn probe_jail(root: &Path) -> Result<(), String> {
+	let dir = root.join(".jail-probe");
+	std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+	let unshare = Unshare::new(libc::CLONE_NEWNS);
+	let jail = Jail::new(&dir);
+	let mut cmd = std::process::Command::new("true");
+	cmd.current_dir(&dir);
+	// SAFETY: both only make syscalls.
+	unsafe {
+		cmd.pre_exec(move || {
+			unshare.enter()?;
+			jail.enter()
+		});
+	}
+	let status = cmd.status();
+	let _ = std::fs::remove_dir(&dir);
+	match status {
+		Ok(status) if status.success() => Ok(()),
+		Ok(status) => Err(format!("probe exited with {status}")),
+		Err(e) => Err(e.to_string()),
+	}
+}
+
+/// Run `true` in a PID namespace once, so a host without them is found at
+/// startup rather than on the first execution.
+fn probe_pid_namespace() -> Result<(), String> {
+	let unshare = Unshare::new(libc::CLONE_NEWPID);
+	let mut cmd = std::process::Command::new("true");
+	// SAFETY: both only make async-signal-safe calls.
+	unsafe {
+		cmd.pre_exec(move || {
+			unshare.enter()?;
+			namespace::enter_pid_namespace()
+		});
+	}
+	match cmd.status() {
+		Ok(status) if status.success() => Ok(()),
+		Ok(status) => Err(format!("probe exited with {status}")),
+		Err(e) => Err(e.to_string()),
+	}
+}